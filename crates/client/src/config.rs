@@ -1,8 +1,12 @@
 //! Client configuration management
 
 use anyhow::{Context, Result, anyhow};
+use common::DeviceFilter;
+use protocol::DeviceInfo;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 
 /// Auto-connect mode for servers
@@ -35,7 +39,13 @@ pub struct ServerConfig {
     /// Pattern formats:
     /// - "vid:pid" (e.g., "04f9:0042") - exact vendor:product match
     /// - "vid:*" (e.g., "04f9:*") - all devices from vendor
-    /// - Any other string - case-insensitive product name substring match
+    /// - A stable device ID as listed by the server (e.g., "1050:0407:12345678"
+    ///   for that serial number, "04f9:0042@1-1.4" for that device on port 1-1.4)
+    /// - "port:1-1.4" - whatever is plugged into that port ("port:1-1.*" for
+    ///   anything below the hub on 1-1)
+    /// - Any other word - case-insensitive product name substring match
+    /// - A filter expression such as "vid:1050 and product:*YubiKey*" or
+    ///   "class:03 and not port:1-2" (see [`common::filter`])
     ///
     /// Behavior:
     /// - auto_connect=manual: auto_attach is ignored
@@ -45,6 +55,13 @@ pub struct ServerConfig {
     /// - auto_connect=full + patterns: connect and attach only matching devices
     #[serde(default)]
     pub auto_attach: Vec<String>,
+    /// Interfaces to attach for composite devices, keyed by the same patterns
+    ///
+    /// Matching devices are attached by interface (e.g. `"0bda:5411" = [0]`)
+    /// and appear locally with only those interfaces; other devices are
+    /// attached whole. Requires a server with protocol 1.5 or later.
+    #[serde(default)]
+    pub interfaces: BTreeMap<String, Vec<u8>>,
 }
 
 impl ServerConfig {
@@ -53,50 +70,33 @@ impl ServerConfig {
     /// Returns true if:
     /// - auto_attach is empty and auto_connect is Full (attach all)
     /// - Device matches any pattern in auto_attach
-    pub fn should_auto_attach(
-        &self,
-        vendor_id: u16,
-        product_id: u16,
-        product_name: Option<&str>,
-    ) -> bool {
+    pub fn should_auto_attach(&self, device: &DeviceInfo) -> bool {
         // If auto_attach is empty, behavior depends on auto_connect mode
         if self.auto_attach.is_empty() {
             return self.auto_connect == AutoConnectMode::AutoWithDevices;
         }
 
-        // Check each pattern
-        let vid_str = format!("{:04x}", vendor_id);
-        let pid_str = format!("{:04x}", product_id);
-
-        for pattern in &self.auto_attach {
-            if Self::matches_pattern(pattern, &vid_str, &pid_str, product_name) {
-                return true;
-            }
-        }
+        self.auto_attach
+            .iter()
+            .any(|pattern| Self::matches_pattern(pattern, device))
+    }
 
-        false
+    /// Interfaces to attach for a device, if it is listed in `interfaces`
+    ///
+    /// If several patterns match, the first in sorted order wins.
+    pub fn interfaces_for(&self, device: &DeviceInfo) -> Option<Vec<u8>> {
+        self.interfaces
+            .iter()
+            .find(|(pattern, _)| Self::matches_pattern(pattern, device))
+            .map(|(_, interfaces)| interfaces.clone())
     }
 
     /// Check if a device matches a single pattern
-    fn matches_pattern(pattern: &str, vid: &str, pid: &str, product_name: Option<&str>) -> bool {
-        let pattern_lower = pattern.to_lowercase();
-
-        // Check for vid:pid or vid:* format
-        if let Some((pattern_vid, pattern_pid)) = pattern_lower.split_once(':') {
-            // Exact vid:pid match
-            if pattern_pid != "*" {
-                return vid == pattern_vid && pid == pattern_pid;
-            }
-            // Vendor wildcard match (vid:*)
-            return vid == pattern_vid;
-        }
-
-        // Otherwise, treat as product name substring (case-insensitive)
-        if let Some(name) = product_name {
-            return name.to_lowercase().contains(&pattern_lower);
-        }
-
-        false
+    ///
+    /// Patterns are device filter expressions (see [`common::filter`]);
+    /// invalid ones are rejected at config load and never match.
+    fn matches_pattern(pattern: &str, device: &DeviceInfo) -> bool {
+        DeviceFilter::parse(pattern).is_ok_and(|filter| filter.matches(device))
     }
 }
 
@@ -113,6 +113,10 @@ pub struct ClientSettings {
     #[serde(default)]
     pub global_auto_connect: Option<AutoConnectMode>,
     pub log_level: String,
+    /// Address of the Prometheus `/metrics` endpoint, e.g. "127.0.0.1:9465"
+    /// If None, no metrics endpoint is served
+    #[serde(default)]
+    pub metrics_addr: Option<SocketAddr>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -140,6 +144,7 @@ impl Default for ClientConfig {
             client: ClientSettings {
                 global_auto_connect: None,
                 log_level: "info".to_string(),
+                metrics_addr: None,
             },
             servers: ServersSettings {
                 approved_servers: Vec::new(),
@@ -175,6 +180,7 @@ impl ClientConfig {
                     name: None,
                     auto_connect: AutoConnectMode::Manual,
                     auto_attach: Vec::new(),
+                    interfaces: BTreeMap::new(),
                 });
             }
         }
@@ -330,6 +336,15 @@ impl ClientConfig {
             // Note: Full NodeId validation would require iroh types, done at runtime
         }
 
+        // Validate auto_attach and interfaces patterns
+        for server in &self.servers.configured {
+            for pattern in server.auto_attach.iter().chain(server.interfaces.keys()) {
+                DeviceFilter::parse(pattern).map_err(|e| {
+                    anyhow!("Invalid auto_attach pattern for {}: {}", server.node_id, e)
+                })?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -345,6 +360,13 @@ pub fn load_config(path: &str) -> Result<ClientConfig> {
 mod tests {
     use super::*;
 
+    /// Device with the given IDs and product name
+    fn device(vendor_id: u16, product_id: u16, product: Option<&str>) -> DeviceInfo {
+        let mut device = common::test_utils::create_mock_device_info(1, vendor_id, product_id);
+        device.product = product.map(str::to_string);
+        device
+    }
+
     #[test]
     fn test_default_config() {
         let config = ClientConfig::default();
@@ -402,6 +424,7 @@ mod tests {
             name: Some("pi5-kim".to_string()),
             auto_connect: AutoConnectMode::Auto,
             auto_attach: Vec::new(),
+            interfaces: BTreeMap::new(),
         });
 
         let all = config.all_servers();
@@ -435,6 +458,7 @@ mod tests {
             name: Some("Named Server".to_string()),
             auto_connect: AutoConnectMode::AutoWithDevices,
             auto_attach: Vec::new(),
+            interfaces: BTreeMap::new(),
         });
 
         let all = config.all_servers();
@@ -450,6 +474,7 @@ mod tests {
             name: None,
             auto_connect: AutoConnectMode::Manual,
             auto_attach: Vec::new(),
+            interfaces: BTreeMap::new(),
         });
 
         // Without global override, uses per-server setting
@@ -475,6 +500,7 @@ mod tests {
             name: None,
             auto_connect: AutoConnectMode::Auto,
             auto_attach: Vec::new(),
+            interfaces: BTreeMap::new(),
         });
 
         let toml_str = toml::to_string(&config).unwrap();
@@ -499,6 +525,7 @@ mod tests {
             name: Some("pi5-kim".to_string()),
             auto_connect: AutoConnectMode::Manual,
             auto_attach: Vec::new(),
+            interfaces: BTreeMap::new(),
         });
 
         assert_eq!(
@@ -523,14 +550,15 @@ mod tests {
             name: None,
             auto_connect: AutoConnectMode::Auto,
             auto_attach: vec!["04f9:0042".to_string()],
+            interfaces: BTreeMap::new(),
         };
 
         // Exact match
-        assert!(server.should_auto_attach(0x04f9, 0x0042, Some("Brother Printer")));
+        assert!(server.should_auto_attach(&device(0x04f9, 0x0042, Some("Brother Printer"))));
         // Wrong product ID
-        assert!(!server.should_auto_attach(0x04f9, 0x0043, Some("Brother Scanner")));
+        assert!(!server.should_auto_attach(&device(0x04f9, 0x0043, Some("Brother Scanner"))));
         // Wrong vendor ID
-        assert!(!server.should_auto_attach(0x04f8, 0x0042, Some("Other Device")));
+        assert!(!server.should_auto_attach(&device(0x04f8, 0x0042, Some("Other Device"))));
     }
 
     #[test]
@@ -540,13 +568,14 @@ mod tests {
             name: None,
             auto_connect: AutoConnectMode::Auto,
             auto_attach: vec!["04f9:*".to_string()],
+            interfaces: BTreeMap::new(),
         };
 
         // Any product from vendor matches
-        assert!(server.should_auto_attach(0x04f9, 0x0042, Some("Brother Printer")));
-        assert!(server.should_auto_attach(0x04f9, 0x1234, Some("Brother Scanner")));
+        assert!(server.should_auto_attach(&device(0x04f9, 0x0042, Some("Brother Printer"))));
+        assert!(server.should_auto_attach(&device(0x04f9, 0x1234, Some("Brother Scanner"))));
         // Different vendor doesn't match
-        assert!(!server.should_auto_attach(0x04f8, 0x0042, None));
+        assert!(!server.should_auto_attach(&device(0x04f8, 0x0042, None)));
     }
 
     #[test]
@@ -556,15 +585,20 @@ mod tests {
             name: None,
             auto_connect: AutoConnectMode::Auto,
             auto_attach: vec!["YubiKey".to_string()],
+            interfaces: BTreeMap::new(),
         };
 
         // Case-insensitive substring match
-        assert!(server.should_auto_attach(0x1050, 0x0407, Some("Yubico YubiKey OTP+FIDO")));
-        assert!(server.should_auto_attach(0x1050, 0x0407, Some("yubikey 5")));
+        assert!(server.should_auto_attach(&device(
+            0x1050,
+            0x0407,
+            Some("Yubico YubiKey OTP+FIDO")
+        )));
+        assert!(server.should_auto_attach(&device(0x1050, 0x0407, Some("yubikey 5"))));
         // Doesn't match other devices
-        assert!(!server.should_auto_attach(0x1050, 0x0407, Some("Security Key")));
+        assert!(!server.should_auto_attach(&device(0x1050, 0x0407, Some("Security Key"))));
         // No product name - doesn't match
-        assert!(!server.should_auto_attach(0x1050, 0x0407, None));
+        assert!(!server.should_auto_attach(&device(0x1050, 0x0407, None)));
     }
 
     #[test]
@@ -578,16 +612,17 @@ mod tests {
                 "1050:*".to_string(),
                 "Brother".to_string(),
             ],
+            interfaces: BTreeMap::new(),
         };
 
         // Matches exact vid:pid
-        assert!(server.should_auto_attach(0x04f9, 0x0042, None));
+        assert!(server.should_auto_attach(&device(0x04f9, 0x0042, None)));
         // Matches vendor wildcard
-        assert!(server.should_auto_attach(0x1050, 0x9999, None));
+        assert!(server.should_auto_attach(&device(0x1050, 0x9999, None)));
         // Matches product name
-        assert!(server.should_auto_attach(0x0000, 0x0000, Some("Brother HL-2270DW")));
+        assert!(server.should_auto_attach(&device(0x0000, 0x0000, Some("Brother HL-2270DW"))));
         // Doesn't match anything
-        assert!(!server.should_auto_attach(0x0000, 0x0000, Some("HP Printer")));
+        assert!(!server.should_auto_attach(&device(0x0000, 0x0000, Some("HP Printer"))));
     }
 
     #[test]
@@ -598,10 +633,11 @@ mod tests {
             name: None,
             auto_connect: AutoConnectMode::AutoWithDevices,
             auto_attach: Vec::new(),
+            interfaces: BTreeMap::new(),
         };
 
-        assert!(server.should_auto_attach(0x1234, 0x5678, Some("Any Device")));
-        assert!(server.should_auto_attach(0x0000, 0x0000, None));
+        assert!(server.should_auto_attach(&device(0x1234, 0x5678, Some("Any Device"))));
+        assert!(server.should_auto_attach(&device(0x0000, 0x0000, None)));
     }
 
     #[test]
@@ -612,10 +648,11 @@ mod tests {
             name: None,
             auto_connect: AutoConnectMode::Auto,
             auto_attach: Vec::new(),
+            interfaces: BTreeMap::new(),
         };
 
-        assert!(!server.should_auto_attach(0x1234, 0x5678, Some("Any Device")));
-        assert!(!server.should_auto_attach(0x0000, 0x0000, None));
+        assert!(!server.should_auto_attach(&device(0x1234, 0x5678, Some("Any Device"))));
+        assert!(!server.should_auto_attach(&device(0x0000, 0x0000, None)));
     }
 
     #[test]
@@ -626,9 +663,98 @@ mod tests {
             name: None,
             auto_connect: AutoConnectMode::AutoWithDevices,
             auto_attach: vec!["04f9:*".to_string()],
+            interfaces: BTreeMap::new(),
+        };
+
+        assert!(server.should_auto_attach(&device(0x04f9, 0x0042, None)));
+        assert!(!server.should_auto_attach(&device(0x1234, 0x5678, None)));
+    }
+
+    #[test]
+    fn test_interfaces_for() {
+        let server: ServerConfig = toml::from_str(
+            r#"
+            node_id = "test"
+            auto_attach = ["0bda:5411"]
+
+            [interfaces]
+            "0bda:5411" = [0, 2]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            server.interfaces_for(&device(0x0bda, 0x5411, None)),
+            Some(vec![0, 2])
+        );
+        assert_eq!(server.interfaces_for(&device(0x04f9, 0x0042, None)), None);
+    }
+
+    #[test]
+    fn test_auto_attach_port_path() {
+        let server = ServerConfig {
+            node_id: "test".to_string(),
+            name: None,
+            auto_connect: AutoConnectMode::Auto,
+            auto_attach: vec!["port:1-1.4".to_string()],
+            interfaces: BTreeMap::new(),
         };
 
-        assert!(server.should_auto_attach(0x04f9, 0x0042, None));
-        assert!(!server.should_auto_attach(0x1234, 0x5678, None));
+        let mut on_port = device(0x04f9, 0x0042, None);
+        on_port.port_numbers = vec![1, 4];
+        let mut elsewhere = on_port.clone();
+        elsewhere.port_numbers = vec![1, 3];
+
+        assert!(server.should_auto_attach(&on_port));
+        assert!(!server.should_auto_attach(&elsewhere));
+    }
+
+    #[test]
+    fn test_auto_attach_stable_id() {
+        let server = ServerConfig {
+            node_id: "test".to_string(),
+            name: None,
+            auto_connect: AutoConnectMode::Auto,
+            auto_attach: vec!["1050:0407@1-1.3".to_string()],
+            interfaces: BTreeMap::new(),
+        };
+
+        let mut on_port = device(0x1050, 0x0407, Some("YubiKey"));
+        on_port.stable_id = "1050:0407@1-1.3".to_string();
+        let mut elsewhere = on_port.clone();
+        elsewhere.stable_id = "1050:0407@1-1.2".to_string();
+
+        assert!(server.should_auto_attach(&on_port));
+        assert!(!server.should_auto_attach(&elsewhere));
+    }
+
+    #[test]
+    fn test_auto_attach_expression() {
+        let server = ServerConfig {
+            node_id: "test".to_string(),
+            name: None,
+            auto_connect: AutoConnectMode::Auto,
+            auto_attach: vec!["vid:1050 and (product:*yubikey* or serial:SN1*)".to_string()],
+            interfaces: BTreeMap::new(),
+        };
+
+        assert!(server.should_auto_attach(&device(0x1050, 0x0407, Some("YubiKey 5 NFC"))));
+        assert!(!server.should_auto_attach(&device(0x1050, 0x0407, Some("Security Key"))));
+        assert!(!server.should_auto_attach(&device(0x04f9, 0x0042, Some("YubiKey"))));
+    }
+
+    #[test]
+    fn test_validate_auto_attach_pattern() {
+        let mut config = ClientConfig::default();
+        config.servers.configured.push(ServerConfig {
+            node_id: "test".to_string(),
+            name: None,
+            auto_connect: AutoConnectMode::Auto,
+            auto_attach: vec!["vid:1050 or".to_string()],
+            interfaces: BTreeMap::new(),
+        });
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("'vid:1050 or' at column 12"), "{}", err);
     }
 }
@@ -0,0 +1,6 @@
+//! rust-p2p-usb Client library
+//!
+//! The Iroh network layer of the client, shared by the `p2p-usb-client`
+//! binary and by tests that run a client in-process.
+
+pub mod network;
@@ -0,0 +1,385 @@
+//! Remote `lsusb -v`
+//!
+//! Renders a `DeviceDescriptors` tree fetched with `GetDescriptorsRequest` in
+//! the layout of `lsusb -v`, so a remote device can be inspected before it is
+//! attached. Used by the `--lsusb` CLI flag and the TUI descriptor view.
+
+use protocol::{
+    ConfigurationDescriptor, DeviceDescriptors, DeviceInfo, EndpointDescriptor, InterfaceDescriptor,
+};
+use std::fmt::{Display, Write};
+
+/// Render a device's descriptors like `lsusb -v`
+pub fn format_verbose(device: &DeviceInfo, descriptors: &DeviceDescriptors) -> String {
+    let name = [device.manufacturer.as_deref(), device.product.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut w = Writer::default();
+    w.line(format!(
+        "Bus {:03} Device {:03}: ID {:04x}:{:04x} {}",
+        device.bus_number, device.device_address, device.vendor_id, device.product_id, name
+    ));
+    w.line("Device Descriptor:");
+    w.indent = 2;
+    w.field("bLength", 18);
+    w.field("bDescriptorType", 1);
+    w.field("bcdUSB", bcd(descriptors.usb_version));
+    w.field("bDeviceClass", class(device.class));
+    w.field("bDeviceSubClass", device.subclass);
+    w.field("bDeviceProtocol", device.protocol);
+    w.field("bMaxPacketSize0", descriptors.max_packet_size0);
+    w.field("idVendor", format!("0x{:04x}", device.vendor_id));
+    w.field("idProduct", format!("0x{:04x}", device.product_id));
+    w.field("bcdDevice", bcd(descriptors.device_version));
+    w.field(
+        "iManufacturer",
+        string(descriptors, descriptors.manufacturer_index),
+    );
+    w.field("iProduct", string(descriptors, descriptors.product_index));
+    w.field(
+        "iSerial",
+        string(descriptors, descriptors.serial_number_index),
+    );
+    w.field("bNumConfigurations", descriptors.configurations.len());
+
+    for config in &descriptors.configurations {
+        format_configuration(&mut w, descriptors, config);
+    }
+
+    if let Some(bos) = &descriptors.bos {
+        format_bos(&mut w, bos);
+    }
+
+    w.indent = 0;
+    match descriptors.active_configuration {
+        Some(value) => w.line(format!("Active configuration: {}", value)),
+        None => w.line("Device not configured"),
+    }
+    w.out
+}
+
+fn format_configuration(
+    w: &mut Writer,
+    descriptors: &DeviceDescriptors,
+    config: &ConfigurationDescriptor,
+) {
+    let mut numbers: Vec<u8> = config.interfaces.iter().map(|i| i.number).collect();
+    numbers.dedup();
+
+    w.indent = 2;
+    w.line("Configuration Descriptor:");
+    w.indent = 4;
+    w.field("bLength", 9);
+    w.field("bDescriptorType", 2);
+    w.field("wTotalLength", format!("0x{:04x}", total_length(config)));
+    w.field("bNumInterfaces", numbers.len());
+    w.field("bConfigurationValue", config.value);
+    w.field("iConfiguration", string(descriptors, config.string_index));
+    w.field("bmAttributes", format!("0x{:02x}", config.attributes));
+    if config.attributes & 0x40 != 0 {
+        w.line("  Self Powered");
+    } else {
+        w.line("  (Bus Powered)");
+    }
+    if config.attributes & 0x20 != 0 {
+        w.line("  Remote Wakeup");
+    }
+    w.field("MaxPower", format!("{}mA", config.max_power_ma));
+    w.extra(&config.extra);
+
+    for interface in &config.interfaces {
+        format_interface(w, descriptors, interface);
+    }
+}
+
+fn format_interface(
+    w: &mut Writer,
+    descriptors: &DeviceDescriptors,
+    interface: &InterfaceDescriptor,
+) {
+    w.indent = 4;
+    w.line("Interface Descriptor:");
+    w.indent = 6;
+    w.field("bLength", 9);
+    w.field("bDescriptorType", 4);
+    w.field("bInterfaceNumber", interface.number);
+    w.field("bAlternateSetting", interface.alt_setting);
+    w.field("bNumEndpoints", interface.endpoints.len());
+    w.field("bInterfaceClass", class(interface.class));
+    w.field("bInterfaceSubClass", interface.subclass);
+    w.field("bInterfaceProtocol", interface.protocol);
+    w.field("iInterface", string(descriptors, interface.string_index));
+    w.extra(&interface.extra);
+
+    for endpoint in &interface.endpoints {
+        format_endpoint(w, endpoint);
+    }
+}
+
+fn format_endpoint(w: &mut Writer, endpoint: &EndpointDescriptor) {
+    const TRANSFER: [&str; 4] = ["Control", "Isochronous", "Bulk", "Interrupt"];
+    const SYNC: [&str; 4] = ["None", "Asynchronous", "Adaptive", "Synchronous"];
+    const USAGE: [&str; 4] = ["Data", "Feedback", "Implicit feedback Data", "(reserved)"];
+
+    let number = endpoint.address & 0x0f;
+    let direction = if endpoint.address & 0x80 != 0 {
+        "IN"
+    } else {
+        "OUT"
+    };
+    let size = endpoint.max_packet_size & 0x7ff;
+    let transactions = 1 + ((endpoint.max_packet_size >> 11) & 0x3);
+    let attributes = endpoint.attributes as usize;
+
+    w.indent = 6;
+    w.line("Endpoint Descriptor:");
+    w.indent = 8;
+    w.field("bLength", 7);
+    w.field("bDescriptorType", 5);
+    w.field(
+        "bEndpointAddress",
+        format!("0x{:02x}  EP {} {}", endpoint.address, number, direction),
+    );
+    w.field("bmAttributes", endpoint.attributes);
+    w.line(format!(
+        "  Transfer Type            {}",
+        TRANSFER[attributes & 0x3]
+    ));
+    w.line(format!(
+        "  Synch Type               {}",
+        SYNC[(attributes >> 2) & 0x3]
+    ));
+    w.line(format!(
+        "  Usage Type               {}",
+        USAGE[(attributes >> 4) & 0x3]
+    ));
+    w.field(
+        "wMaxPacketSize",
+        format!(
+            "0x{:04x}  {}x {} bytes",
+            endpoint.max_packet_size, transactions, size
+        ),
+    );
+    w.field("bInterval", endpoint.interval);
+    w.extra(&endpoint.extra);
+}
+
+fn format_bos(w: &mut Writer, bos: &[u8]) {
+    w.indent = 0;
+    w.line("Binary Object Store Descriptor:");
+    w.indent = 2;
+    if bos.len() < 5 {
+        w.extra(bos);
+        return;
+    }
+    w.field("bLength", bos[0]);
+    w.field("bDescriptorType", bos[1]);
+    w.field(
+        "wTotalLength",
+        format!("0x{:04x}", u16::from_le_bytes([bos[2], bos[3]])),
+    );
+    w.field("bNumDeviceCaps", bos[4]);
+
+    let mut offset = bos[0] as usize;
+    while offset + 3 <= bos.len() {
+        let len = bos[offset] as usize;
+        if len < 3 || offset + len > bos.len() {
+            break;
+        }
+        let capability = &bos[offset..offset + len];
+        let name = match capability[2] {
+            0x02 => "USB 2.0 Extension Device Capability",
+            0x03 => "SuperSpeed USB Device Capability",
+            0x04 => "Container ID Device Capability",
+            0x05 => "Platform Device Capability",
+            0x0A => "SuperSpeedPlus USB Device Capability",
+            _ => "Device Capability",
+        };
+        w.indent = 2;
+        w.line(format!("{}:", name));
+        w.indent = 4;
+        w.field("bLength", capability[0]);
+        w.field("bDescriptorType", capability[1]);
+        w.field("bDevCapabilityType", capability[2]);
+        w.extra(&capability[3..]);
+        offset += len;
+    }
+}
+
+/// Output buffer that indents every line
+#[derive(Default)]
+struct Writer {
+    out: String,
+    indent: usize,
+}
+
+impl Writer {
+    /// Append a line at the current indentation
+    fn line(&mut self, text: impl Display) {
+        let _ = writeln!(self.out, "{:indent$}{}", "", text, indent = self.indent);
+    }
+
+    /// Append `name value` aligned like lsusb
+    fn field(&mut self, name: &str, value: impl Display) {
+        // Padding only applies to a rendered string
+        let value = value.to_string();
+        self.line(format_args!("{:<20}{:>6}", name, value));
+    }
+
+    /// Append class-specific bytes as a hex dump
+    fn extra(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(16) {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            self.line(format!("** {}", hex.join(" ")));
+        }
+    }
+}
+
+/// Length of the configuration descriptor set as sent by the device
+fn total_length(config: &ConfigurationDescriptor) -> usize {
+    9 + config.extra.len()
+        + config
+            .interfaces
+            .iter()
+            .map(|interface| {
+                9 + interface.extra.len()
+                    + interface
+                        .endpoints
+                        .iter()
+                        .map(|endpoint| 7 + endpoint.extra.len())
+                        .sum::<usize>()
+            })
+            .sum::<usize>()
+}
+
+/// String index followed by its contents, if known
+fn string(descriptors: &DeviceDescriptors, index: u8) -> String {
+    match descriptors.string(index) {
+        Some(value) if index != 0 => format!("{} {}", index, value),
+        _ => index.to_string(),
+    }
+}
+
+/// Format a BCD version as `major.minor`
+fn bcd(value: u16) -> String {
+    format!("{:x}.{:02x}", value >> 8, value & 0xff)
+}
+
+/// Class code followed by its name
+fn class(code: u8) -> String {
+    let name = match code {
+        0x00 => "(Defined at Interface level)",
+        0x01 => "Audio",
+        0x02 => "Communications",
+        0x03 => "Human Interface Device",
+        0x05 => "Physical Interface Device",
+        0x06 => "Imaging",
+        0x07 => "Printer",
+        0x08 => "Mass Storage",
+        0x09 => "Hub",
+        0x0A => "CDC Data",
+        0x0B => "Chip/SmartCard",
+        0x0D => "Content Security",
+        0x0E => "Video",
+        0x0F => "Personal Healthcare",
+        0x10 => "Audio/Video",
+        0xDC => "Diagnostic",
+        0xE0 => "Wireless",
+        0xEF => "Miscellaneous Device",
+        0xFE => "Application Specific Interface",
+        0xFF => "Vendor Specific Class",
+        _ => "",
+    };
+    format!("{} {}", code, name).trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::StringDescriptor;
+
+    fn keyboard_descriptors() -> DeviceDescriptors {
+        DeviceDescriptors {
+            usb_version: 0x0200,
+            device_version: 0x1211,
+            max_packet_size0: 8,
+            manufacturer_index: 1,
+            product_index: 2,
+            serial_number_index: 0,
+            active_configuration: Some(1),
+            configurations: vec![ConfigurationDescriptor {
+                value: 1,
+                string_index: 0,
+                attributes: 0xa0,
+                max_power_ma: 98,
+                interfaces: vec![InterfaceDescriptor {
+                    number: 0,
+                    alt_setting: 0,
+                    class: 3,
+                    subclass: 1,
+                    protocol: 1,
+                    string_index: 0,
+                    endpoints: vec![EndpointDescriptor {
+                        address: 0x81,
+                        attributes: 3,
+                        max_packet_size: 8,
+                        interval: 8,
+                        extra: Vec::new(),
+                    }],
+                    extra: vec![0x09, 0x21, 0x11, 0x01, 0x00, 0x01, 0x22, 0x3b, 0x00],
+                }],
+                extra: Vec::new(),
+            }],
+            bos: None,
+            strings: vec![
+                StringDescriptor {
+                    index: 1,
+                    language: 0x0409,
+                    value: "Logitech".to_string(),
+                },
+                StringDescriptor {
+                    index: 2,
+                    language: 0x0409,
+                    value: "USB Receiver".to_string(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_format_verbose() {
+        let mut device = common::test_utils::create_mock_device_info(1, 0x046d, 0xc52b);
+        device.bus_number = 1;
+        device.device_address = 4;
+
+        let text = format_verbose(&device, &keyboard_descriptors());
+
+        assert!(text.starts_with("Bus 001 Device 004: ID 046d:c52b"));
+        assert!(text.contains("bcdUSB                2.00"));
+        assert!(text.contains("iProduct            2 USB Receiver"));
+        assert!(text.contains("wTotalLength        0x0022"));
+        assert!(text.contains("bInterfaceClass     3 Human Interface Device"));
+        assert!(text.contains("0x81  EP 1 IN"));
+        assert!(text.contains("Transfer Type            Interrupt"));
+        assert!(text.contains("** 09 21 11 01 00 01 22 3b 00"));
+        assert!(text.contains("      Remote Wakeup"));
+        assert!(text.ends_with("Active configuration: 1\n"));
+    }
+
+    #[test]
+    fn test_format_bos() {
+        let mut w = Writer::default();
+        format_bos(
+            &mut w,
+            &[
+                0x05, 0x0f, 0x0c, 0x00, 0x01, // BOS header
+                0x07, 0x10, 0x02, 0x06, 0x00, 0x00, 0x00, // USB 2.0 extension
+            ],
+        );
+        assert!(w.out.contains("bNumDeviceCaps           1"));
+        assert!(w.out.contains("USB 2.0 Extension Device Capability"));
+    }
+}
@@ -4,24 +4,26 @@
 //! virtual USB devices for remote access.
 
 mod config;
-mod network;
+mod lsusb;
+mod reservations;
 mod tui;
 mod virtual_usb;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use common::setup_logging;
+use client::network;
+use common::{MetricsExporter, setup_logging};
 use iroh::PublicKey as EndpointId;
 use network::{
     ClientConfig as NetworkClientConfig, DeviceNotification, IrohClient, ReconciliationResult,
 };
-use protocol::DeviceId;
+use protocol::{DeviceId, ReservationId};
 use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::signal;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
-use virtual_usb::VirtualUsbManager;
+use virtual_usb::{InterruptFeedback, VirtualUsbManager};
 
 #[derive(Parser, Debug)]
 #[command(name = "p2p-usb-client")]
@@ -37,12 +39,23 @@ EXAMPLES:
     # Connect to specific server immediately
     p2p-usb-client --connect <server-node-id>
 
+    # Enroll with a server using the invite URL it printed
+    p2p-usb-client --connect 'p2p-usb://connect/<server-node-id>?invite=<token>'
+
     # Run with custom config
     p2p-usb-client --config /path/to/config.toml
 
     # Run with debug logging
     p2p-usb-client --log-level debug
 
+    # Show the descriptors of a server's devices (like lsusb -v)
+    p2p-usb-client --connect pi5-home --lsusb
+
+    # Book device 3 for the morning, list bookings, cancel booking 12
+    p2p-usb-client --connect pi5-home --reserve 3 --from 2026-12-24T08:00 --until 2026-12-24T12:00
+    p2p-usb-client --connect pi5-home --reservations
+    p2p-usb-client --connect pi5-home --cancel-reservation 12
+
 CONFIGURATION:
     The client looks for configuration files in the following order:
     1. Path specified with --config
@@ -50,6 +63,9 @@ CONFIGURATION:
     3. /etc/p2p-usb/client.toml
     4. Built-in defaults
 
+    With client.metrics_addr set (e.g. \"127.0.0.1:9465\") the client serves
+    Prometheus metrics on http://<metrics_addr>/metrics.
+
 For more information, visit: https://github.com/kimasplund/rust-p2p-usb
 ")]
 struct Args {
@@ -61,7 +77,7 @@ struct Args {
     #[arg(long)]
     save_config: bool,
 
-    /// Connect to specific server by node ID
+    /// Connect to specific server by node ID, name or connection URL
     #[arg(long, value_name = "NODE_ID")]
     connect: Option<String>,
 
@@ -72,6 +88,38 @@ struct Args {
     /// Run in headless mode (no TUI, stay connected until Ctrl+C)
     #[arg(long)]
     headless: bool,
+
+    /// Print `lsusb -v` for the devices of the --connect server and exit
+    /// (all devices, or only DEVICE_ID)
+    #[arg(long, value_name = "DEVICE_ID", requires = "connect", num_args = 0..=1)]
+    lsusb: Option<Option<u32>>,
+
+    /// Reserve DEVICE_ID on the --connect server from --from until --until
+    /// and exit
+    #[arg(long, value_name = "DEVICE_ID", requires_all = ["connect", "until"])]
+    reserve: Option<u32>,
+
+    /// Start of the --reserve period: local time (e.g. 2026-12-24T08:00), an
+    /// instant with offset, or "now" (default)
+    #[arg(long, value_name = "TIME", requires = "reserve")]
+    from: Option<String>,
+
+    /// End of the --reserve period
+    #[arg(long, value_name = "TIME", requires = "reserve")]
+    until: Option<String>,
+
+    /// Note shown next to the --reserve booking
+    #[arg(long, value_name = "TEXT", requires = "reserve")]
+    note: Option<String>,
+
+    /// List current and upcoming reservations on the --connect server and exit
+    #[arg(long, requires = "connect")]
+    reservations: bool,
+
+    /// Cancel one of this client's reservations on the --connect server and
+    /// exit
+    #[arg(long, value_name = "ID", requires = "connect")]
+    cancel_reservation: Option<u64>,
 }
 
 #[tokio::main]
@@ -115,6 +163,20 @@ async fn main() -> Result<()> {
 
     info!("Client EndpointId: {}", client.endpoint_id());
 
+    // Remote lsusb only reads descriptors, no virtual USB needed
+    if let (Some(device_id), Some(server_id_str)) = (args.lsusb, args.connect.as_deref()) {
+        return print_remote_lsusb(&client, server_id_str, device_id.map(DeviceId), &config).await;
+    }
+
+    // Reservations are managed without attaching anything either
+    if let Some(server_id_str) = args.connect.as_deref()
+        && (args.reserve.is_some() || args.reservations || args.cancel_reservation.is_some())
+    {
+        return manage_reservations(&client, server_id_str, &args, &config).await;
+    }
+
+    let _metrics_handle = spawn_metrics_exporter(&config, &client).await;
+
     // Initialize Virtual USB Manager
     let virtual_usb = Arc::new(
         VirtualUsbManager::new()
@@ -220,11 +282,61 @@ async fn create_iroh_client(config: &config::ClientConfig) -> Result<IrohClient>
         allowed_servers,
         alpn: common::ALPN_PROTOCOL.to_vec(),
         secret_key_path: config.iroh.secret_key_path.clone(),
+        relay_servers: config.iroh.relay_servers.clone(),
     };
 
     IrohClient::new(network_config).await
 }
 
+/// Serve Prometheus metrics in the background if `client.metrics_addr` is set
+///
+/// The client keeps running without them if the address cannot be bound.
+async fn spawn_metrics_exporter(
+    config: &config::ClientConfig,
+    client: &Arc<IrohClient>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let addr = config.client.metrics_addr?;
+    let client = client.clone();
+    match MetricsExporter::bind(addr).await {
+        Ok(exporter) => Some(tokio::spawn(async move {
+            let render = move || {
+                let client = client.clone();
+                async move { network::metrics::render(&client).await }
+            };
+            if let Err(e) = exporter.run(render).await {
+                error!("Metrics endpoint error: {:#}", e);
+            }
+        })),
+        Err(e) => {
+            warn!("Metrics endpoint disabled: failed to bind {}: {}", addr, e);
+            None
+        }
+    }
+}
+
+/// Resolve a `--connect` argument, holding the invite an invite URL carries
+///
+/// Besides what `resolve_server_id` accepts, takes connection URLs such as
+/// `p2p-usb://connect/<endpoint>?invite=<token>`.
+async fn resolve_server(
+    client: &IrohClient,
+    server_str: &str,
+    config: &config::ClientConfig,
+) -> Result<EndpointId> {
+    let Some(endpoint_str) = tui::qr::parse_connection_url(server_str) else {
+        return resolve_server_id(server_str, config);
+    };
+    let server_id = endpoint_str
+        .parse::<EndpointId>()
+        .context("Invalid EndpointId in connection URL")?;
+
+    if let Some(token) = tui::qr::parse_invite_token(server_str) {
+        client.add_allowed_server(server_id).await;
+        client.set_invite(server_id, token.to_string()).await;
+    }
+    Ok(server_id)
+}
+
 /// Resolve a server identifier to an EndpointId
 ///
 /// Accepts either:
@@ -281,6 +393,99 @@ fn resolve_server_id(server_str: &str, config: &config::ClientConfig) -> Result<
     }
 }
 
+/// Connect to a server and print `lsusb -v` for its devices
+async fn print_remote_lsusb(
+    client: &IrohClient,
+    server_id_str: &str,
+    device_id: Option<DeviceId>,
+    config: &config::ClientConfig,
+) -> Result<()> {
+    let server_id = resolve_server(client, server_id_str, config).await?;
+    client
+        .connect_to_server(server_id, None)
+        .await
+        .context("Failed to connect to server")?;
+
+    let devices = client.list_remote_devices(server_id).await?;
+    let devices: Vec<_> = devices
+        .into_iter()
+        .filter(|d| device_id.is_none_or(|id| d.id == id))
+        .collect();
+    if devices.is_empty() {
+        anyhow::bail!("No matching devices on server");
+    }
+
+    for device in devices {
+        match client.get_descriptors(server_id, device.id).await {
+            Ok(descriptors) => println!("{}", lsusb::format_verbose(&device, &descriptors)),
+            Err(e) => eprintln!("Device {}: {:#}\n", device.id.0, e),
+        }
+    }
+
+    let _ = client.disconnect_from_server(server_id).await;
+    Ok(())
+}
+
+/// Connect to a server and create, list or cancel reservations
+async fn manage_reservations(
+    client: &IrohClient,
+    server_id_str: &str,
+    args: &Args,
+    config: &config::ClientConfig,
+) -> Result<()> {
+    let server_id = resolve_server(client, server_id_str, config).await?;
+    client
+        .connect_to_server(server_id, None)
+        .await
+        .context("Failed to connect to server")?;
+
+    let timezone = jiff::tz::TimeZone::system();
+    let result = async {
+        if let Some(device_id) = args.reserve {
+            let starts_at =
+                reservations::parse_time(args.from.as_deref().unwrap_or("now"), &timezone)?;
+            let ends_at =
+                reservations::parse_time(args.until.as_deref().unwrap_or_default(), &timezone)?;
+            let reservation = client
+                .create_reservation(
+                    server_id,
+                    DeviceId(device_id),
+                    starts_at,
+                    ends_at,
+                    args.note.clone(),
+                )
+                .await?;
+            println!(
+                "Reserved: {}",
+                reservations::format_reservation(&reservation, &timezone)
+            );
+        }
+        if let Some(id) = args.cancel_reservation {
+            client
+                .cancel_reservation(server_id, ReservationId(id))
+                .await?;
+            println!("Cancelled reservation {}", id);
+        }
+        if args.reservations {
+            let booked = client.list_reservations(server_id, None).await?;
+            if booked.is_empty() {
+                println!("No current or upcoming reservations");
+            }
+            for reservation in &booked {
+                println!(
+                    "{}",
+                    reservations::format_reservation(reservation, &timezone)
+                );
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    let _ = client.disconnect_from_server(server_id).await;
+    result
+}
+
 /// Connect to specific server and run in connected mode
 async fn connect_and_run(
     client: Arc<IrohClient>,
@@ -289,8 +494,8 @@ async fn connect_and_run(
     config: &config::ClientConfig,
     headless: bool,
 ) -> Result<()> {
-    // Resolve server name or EndpointId
-    let server_id = resolve_server_id(server_id_str, config)?;
+    // Resolve server name, EndpointId or connection URL
+    let server_id = resolve_server(&client, server_id_str, config).await?;
     let display_name = config.server_display_name(&server_id.to_string());
     info!("Connecting to server: {} ({})", display_name, server_id);
 
@@ -323,9 +528,7 @@ async fn connect_and_run(
 
                     // Check if this device should be auto-attached
                     let should_attach = server_config
-                        .map(|s| {
-                            s.should_auto_attach(device.vendor_id, device.product_id, product_name)
-                        })
+                        .map(|s| s.should_auto_attach(device))
                         .unwrap_or(matches!(
                             effective_mode,
                             config::AutoConnectMode::AutoWithDevices
@@ -349,8 +552,14 @@ async fn connect_and_run(
                     }
 
                     // Create device proxy and attach as virtual USB device
-                    match IrohClient::create_device_proxy(client.clone(), server_id, device.clone())
-                        .await
+                    let interfaces = server_config.and_then(|s| s.interfaces_for(device));
+                    match IrohClient::create_device_proxy(
+                        client.clone(),
+                        server_id,
+                        device.clone(),
+                        interfaces,
+                    )
+                    .await
                     {
                         Ok(device_proxy) => match virtual_usb.attach_device(device_proxy).await {
                             Ok(global_id) => {
@@ -459,8 +668,7 @@ async fn auto_attach_devices(
                 let product_name = device.product.as_deref();
 
                 // Check if this device matches auto_attach filter
-                let should_attach =
-                    server_config.should_auto_attach(device.vendor_id, device.product_id, product_name);
+                let should_attach = server_config.should_auto_attach(device);
 
                 if !should_attach {
                     debug!(
@@ -480,7 +688,14 @@ async fn auto_attach_devices(
                 );
 
                 // Create device proxy and attach as virtual USB device
-                match IrohClient::create_device_proxy(client.clone(), server_id, device.clone()).await
+                let interfaces = server_config.interfaces_for(device);
+                match IrohClient::create_device_proxy(
+                    client.clone(),
+                    server_id,
+                    device.clone(),
+                    interfaces,
+                )
+                .await
                 {
                     Ok(device_proxy) => match virtual_usb.attach_device(device_proxy).await {
                         Ok(global_id) => {
@@ -537,13 +752,7 @@ async fn handle_notifications(
                 // Check if this device matches auto_attach filter
                 let matches_filter = server_config
                     .as_ref()
-                    .map(|s| {
-                        s.should_auto_attach(
-                            device.vendor_id,
-                            device.product_id,
-                            device.product.as_deref(),
-                        )
-                    })
+                    .map(|s| s.should_auto_attach(&device))
                     .unwrap_or(false);
 
                 // Auto-attach if previously attached OR matches auto_attach filter
@@ -559,8 +768,14 @@ async fn handle_notifications(
                     );
 
                     // Attempt to attach the device
-                    match IrohClient::create_device_proxy(client.clone(), server_id, device.clone())
-                        .await
+                    let interfaces = server_config.as_ref().and_then(|s| s.interfaces_for(&device));
+                    match IrohClient::create_device_proxy(
+                        client.clone(),
+                        server_id,
+                        device.clone(),
+                        interfaces,
+                    )
+                    .await
                     {
                         Ok(device_proxy) => match virtual_usb.attach_device(device_proxy).await {
                             Ok(global_id) => {
@@ -620,6 +835,23 @@ async fn handle_notifications(
                     device_info.as_ref().map(|d| &d.id)
                 );
             }
+            Ok(DeviceNotification::QuotaStatus { quotas }) => {
+                for quota in quotas.iter().filter(|quota| quota.is_exhausted()) {
+                    warn!("Quota used up on server {}: {}", server_id, quota);
+                }
+            }
+            Ok(DeviceNotification::ReservationChanged { update }) => {
+                info!(
+                    "Reservation {} on server {} {}: {}",
+                    update.reservation.id.0,
+                    server_id,
+                    update.event,
+                    reservations::format_reservation(
+                        &update.reservation,
+                        &jiff::tz::TimeZone::system()
+                    )
+                );
+            }
             Ok(DeviceNotification::InterruptData {
                 handle,
                 endpoint,
@@ -630,11 +862,12 @@ async fn handle_notifications(
             }) => {
                 // Interrupt data is received from server's proactive streaming
                 // Process through InterruptReceiveManager for integrity verification and buffering
-                let (stored, checksum_valid) = virtual_usb.process_interrupt_data(
+                let len = data.len();
+                let (checksum_valid, feedback) = virtual_usb.process_interrupt_data(
                     handle.0,
                     endpoint,
                     sequence,
-                    data.clone(),
+                    data,
                     timestamp_us,
                     checksum,
                 );
@@ -647,9 +880,49 @@ async fn handle_notifications(
                 }
 
                 tracing::trace!(
-                    "Received streamed interrupt data: handle={}, ep=0x{:02x}, seq={}, len={}, stored={}, valid={}",
-                    handle.0, endpoint, sequence, data.len(), stored, checksum_valid
+                    "Received streamed interrupt data: handle={}, ep=0x{:02x}, seq={}, len={}, valid={}",
+                    handle.0, endpoint, sequence, len, checksum_valid
                 );
+
+                // ACK/NACK are fire-and-forget; don't hold up the next report
+                if let Some(feedback) = feedback {
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        let result = match feedback {
+                            InterruptFeedback::Ack {
+                                last_seq,
+                                reports_received,
+                            } => {
+                                client
+                                    .interrupt_ack(
+                                        server_id,
+                                        handle,
+                                        endpoint,
+                                        last_seq,
+                                        reports_received,
+                                    )
+                                    .await
+                            }
+                            InterruptFeedback::Nack {
+                                missing_sequences,
+                                last_contiguous_seq,
+                            } => {
+                                client
+                                    .interrupt_nack(
+                                        server_id,
+                                        handle,
+                                        endpoint,
+                                        missing_sequences,
+                                        last_contiguous_seq,
+                                    )
+                                    .await
+                            }
+                        };
+                        if let Err(e) = result {
+                            debug!("Failed to send interrupt flow control: {:#}", e);
+                        }
+                    });
+                }
             }
             Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
                 warn!("Missed {} device notifications", n);
@@ -3,20 +3,28 @@
 //! Connects to remote servers and manages connections using Iroh P2P networking.
 
 use anyhow::{Context, Result, anyhow};
-use common::{ALPN_PROTOCOL, load_or_generate_secret_key};
+use common::iroh_ext::bind_endpoint;
+use common::{
+    ALPN_PROTOCOL, TransferKind, TransferMetrics, TransferOutcome, load_or_generate_secret_key,
+    request_payload_size, response_payload_size,
+};
 use iroh::{Endpoint, EndpointAddr, PublicKey as EndpointId};
-use protocol::{DeviceId, DeviceInfo};
+use protocol::{
+    CancelResult, DeviceDescriptors, DeviceHandle, DeviceId, DeviceInfo, DeviceOperation,
+    InterruptStreamInfo, RequestId, Reservation, ReservationId, TransferResult,
+};
 use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock, broadcast};
-use tokio::time::{Duration, sleep};
+use tokio::time::{Duration, Instant, sleep};
 use tracing::{debug, error, info, warn};
 
 use super::connection::{DeviceNotification, ServerConnection};
 use super::device_proxy::DeviceProxy;
+use super::metrics::{ClientMetricsRegistry, SharedClientMetrics};
 
 /// Type alias for reconciliation callback
 ///
@@ -51,8 +59,13 @@ pub enum ConnectionState {
     Disconnected,
     /// Attempting to reconnect (attempt #, next retry in)
     Reconnecting(u32, Duration),
+    /// Connected, but the server is waiting for its operator to approve this client
+    AwaitingApproval,
 }
 
+/// Sender for per-server connection state updates
+pub type StateUpdates = broadcast::Sender<(EndpointId, ConnectionState)>;
+
 /// Reconnection policy with exponential backoff
 #[derive(Debug, Clone)]
 struct ReconnectionPolicy {
@@ -110,13 +123,17 @@ pub struct IrohClient {
     /// Active server connections
     connections: Arc<Mutex<HashMap<EndpointId, ServerConnection>>>,
     /// Connection state updates
-    state_updates: broadcast::Sender<(EndpointId, ConnectionState)>,
+    state_updates: StateUpdates,
     /// Device notification updates (aggregated from all servers)
     notification_updates: broadcast::Sender<(EndpointId, DeviceNotification)>,
     /// Target servers we want to maintain connections to
     target_servers: Arc<RwLock<HashSet<EndpointId>>>,
     /// Optional callback for reconciliation after reconnection
     reconciliation_callback: Arc<RwLock<Option<ReconciliationCallback>>>,
+    /// Pairing invites to present on the next connection to each server
+    invites: Arc<Mutex<HashMap<EndpointId, String>>>,
+    /// Per-device and per-transfer-type metrics
+    metrics: SharedClientMetrics,
 }
 
 /// Client configuration
@@ -129,6 +146,8 @@ pub struct ClientConfig {
     /// Path to the secret key file for stable EndpointId
     /// If None, uses default XDG path: ~/.config/p2p-usb/secret_key
     pub secret_key_path: Option<PathBuf>,
+    /// Relay servers (None = Iroh defaults, empty = relays disabled)
+    pub relay_servers: Option<Vec<String>>,
 }
 
 impl Default for ClientConfig {
@@ -137,6 +156,7 @@ impl Default for ClientConfig {
             allowed_servers: HashSet::new(),
             alpn: ALPN_PROTOCOL.to_vec(),
             secret_key_path: None,
+            relay_servers: None,
         }
     }
 }
@@ -165,15 +185,13 @@ impl IrohClient {
             .context("Failed to load or generate secret key")?;
 
         // Create Iroh endpoint with persistent key
-        let endpoint = Endpoint::builder()
-            .secret_key(secret_key)
-            .alpns(vec![config.alpn.clone()])
-            .bind()
-            .await
-            .context("Failed to create Iroh endpoint")?;
-
-        // Wait for endpoint to discover its addresses before accepting connections
-        let _ = endpoint.online().await;
+        let endpoint = bind_endpoint(
+            secret_key,
+            config.alpn.clone(),
+            config.relay_servers.as_deref(),
+        )
+        .await
+        .context("Failed to create Iroh endpoint")?;
 
         let endpoint_id = endpoint.id();
         info!(
@@ -194,6 +212,8 @@ impl IrohClient {
             notification_updates,
             target_servers,
             reconciliation_callback,
+            invites: Arc::new(Mutex::new(HashMap::new())),
+            metrics: Arc::new(ClientMetricsRegistry::new()),
         };
 
         // Start background connection monitor
@@ -421,7 +441,6 @@ impl IrohClient {
     }
 
     /// Add a server to the allowlist
-    #[allow(dead_code)]
     pub async fn add_allowed_server(&self, server_id: EndpointId) {
         let mut allowlist = self.allowed_servers.write().await;
         allowlist.insert(server_id);
@@ -436,6 +455,15 @@ impl IrohClient {
         info!("Removed server from allowlist: {}", server_id);
     }
 
+    /// Present a pairing invite on the next connection to a server
+    ///
+    /// The token comes from a `p2p-usb://connect/<endpoint>?invite=<token>`
+    /// URL. It is dropped once the server has admitted this client.
+    pub async fn set_invite(&self, server_id: EndpointId, token: String) {
+        self.invites.lock().await.insert(server_id, token);
+        info!("Holding pairing invite for server: {}", server_id);
+    }
+
     /// Check if a server is in the allowlist
     async fn is_server_allowed(&self, server_id: &EndpointId) -> bool {
         let allowlist = self.allowed_servers.read().await;
@@ -551,6 +579,77 @@ impl IrohClient {
         connection.list_devices().await
     }
 
+    /// Fetch the full descriptor set of a remote device
+    ///
+    /// # Arguments
+    /// * `server_id` - Server hosting the device
+    /// * `device_id` - Device to describe (need not be attached)
+    pub async fn get_descriptors(
+        &self,
+        server_id: EndpointId,
+        device_id: DeviceId,
+    ) -> Result<DeviceDescriptors> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection.get_descriptors(device_id).await
+    }
+
+    /// Book a remote device for a period
+    ///
+    /// # Arguments
+    /// * `server_id` - Server hosting the device
+    /// * `device_id` - Device to book (need not be attached)
+    /// * `starts_at` / `ends_at` - Period in Unix seconds
+    /// * `note` - Shown to other clients next to the booking
+    pub async fn create_reservation(
+        &self,
+        server_id: EndpointId,
+        device_id: DeviceId,
+        starts_at: u64,
+        ends_at: u64,
+        note: Option<String>,
+    ) -> Result<Reservation> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection
+            .create_reservation(device_id, starts_at, ends_at, note)
+            .await
+    }
+
+    /// List current and upcoming reservations on a server
+    ///
+    /// # Arguments
+    /// * `server_id` - Server holding the reservations
+    /// * `device_id` - Only list reservations of this device
+    pub async fn list_reservations(
+        &self,
+        server_id: EndpointId,
+        device_id: Option<DeviceId>,
+    ) -> Result<Vec<Reservation>> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection.list_reservations(device_id).await
+    }
+
+    /// Cancel one of this client's reservations on a server
+    pub async fn cancel_reservation(&self, server_id: EndpointId, id: ReservationId) -> Result<()> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection.cancel_reservation(id).await
+    }
+
     /// Create a device proxy for a remote USB device
     ///
     /// Note: This method must be called on an Arc<IrohClient>
@@ -566,6 +665,7 @@ impl IrohClient {
         client: Arc<Self>,
         server_id: EndpointId,
         device_info: DeviceInfo,
+        interfaces: Option<Vec<u8>>,
     ) -> Result<Arc<DeviceProxy>> {
         // Verify we're connected to the server
         let connections = client.connections.lock().await;
@@ -575,7 +675,11 @@ impl IrohClient {
         drop(connections); // Release lock
 
         // Create proxy (doesn't attach yet - that's done by the caller)
-        Ok(Arc::new(DeviceProxy::new(client, server_id, device_info)))
+        let proxy = DeviceProxy::new(client, server_id, device_info);
+        Ok(Arc::new(match interfaces {
+            Some(interfaces) => proxy.with_interfaces(interfaces),
+            None => proxy,
+        }))
     }
 
     /// Attach to a remote device
@@ -596,7 +700,28 @@ impl IrohClient {
             .get(&server_id)
             .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
 
-        connection.attach_device(device_id).await
+        let handle = connection.attach_device(device_id).await?;
+        self.metrics.device_attached(server_id, handle, device_id);
+        Ok(handle)
+    }
+
+    /// Attach to some interfaces of a remote composite device
+    ///
+    /// Requires a server with protocol 1.5 or later.
+    pub async fn attach_interfaces(
+        &self,
+        server_id: EndpointId,
+        device_id: DeviceId,
+        interfaces: Vec<u8>,
+    ) -> Result<protocol::DeviceHandle> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        let handle = connection.attach_interfaces(device_id, interfaces).await?;
+        self.metrics.device_attached(server_id, handle, device_id);
+        Ok(handle)
     }
 
     /// Detach from a remote device
@@ -614,7 +739,26 @@ impl IrohClient {
             .get(&server_id)
             .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
 
-        connection.detach_device(handle).await
+        connection.detach_device(handle).await?;
+        self.metrics.device_detached(server_id, handle);
+        Ok(())
+    }
+
+    /// Perform a device operation on a remote device
+    ///
+    /// See `ServerConnection::device_operation` for the nested result.
+    pub async fn device_operation(
+        &self,
+        server_id: EndpointId,
+        handle: DeviceHandle,
+        operation: DeviceOperation,
+    ) -> Result<std::result::Result<(), protocol::UsbError>> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection.device_operation(handle, operation).await
     }
 
     /// Submit a USB transfer request
@@ -635,7 +779,148 @@ impl IrohClient {
             .get(&server_id)
             .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
 
-        connection.submit_transfer(request).await
+        let metrics = connection.transfer_metrics();
+        let out_bytes = request_payload_size(&request.transfer);
+        let handle = request.handle;
+        let kind = TransferKind::of(&request.transfer);
+        let started = Instant::now();
+        metrics.transfer_started();
+        self.metrics.transfer_started(server_id, handle, kind);
+
+        // Release the connections lock before awaiting so transfers to this
+        // and other servers can run concurrently over the transfer channel
+        let result = if let Some(channel) = connection.transfer_channel().await {
+            drop(connections);
+            channel.submit(request).await
+        } else {
+            connection.submit_transfer(request).await
+        };
+
+        let outcome = match &result {
+            Ok(response) if !matches!(response.result, TransferResult::Error { .. }) => {
+                TransferOutcome::Completed {
+                    bytes_sent: out_bytes,
+                    bytes_received: response_payload_size(&response.result),
+                    latency: started.elapsed(),
+                }
+            }
+            _ => TransferOutcome::Failed,
+        };
+        metrics.transfer_finished(outcome);
+        self.metrics
+            .transfer_finished(server_id, handle, kind, outcome);
+
+        result
+    }
+
+    /// Cancel an in-flight USB transfer on a server
+    ///
+    /// Fails if the server does not support cancellation; the caller then
+    /// falls back to abandoning the transfer locally.
+    pub async fn cancel_transfer(
+        &self,
+        server_id: EndpointId,
+        handle: DeviceHandle,
+        request_id: RequestId,
+    ) -> Result<CancelResult> {
+        let channel = {
+            let connections = self.connections.lock().await;
+            let connection = connections
+                .get(&server_id)
+                .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+            connection.transfer_channel().await
+        };
+
+        match channel {
+            Some(channel) => channel.cancel(handle, request_id).await,
+            None => Err(anyhow!("No transfer channel to cancel on")),
+        }
+    }
+
+    /// Ask a server to stream an interrupt IN endpoint
+    ///
+    /// See `ServerConnection::start_interrupt_stream`.
+    pub async fn start_interrupt_stream(
+        &self,
+        server_id: EndpointId,
+        handle: DeviceHandle,
+        endpoint: u8,
+        buffer_hint: u32,
+    ) -> Result<InterruptStreamInfo> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection
+            .start_interrupt_stream(handle, endpoint, buffer_hint)
+            .await
+    }
+
+    /// Acknowledge streamed interrupt reports on a server
+    pub async fn interrupt_ack(
+        &self,
+        server_id: EndpointId,
+        handle: DeviceHandle,
+        endpoint: u8,
+        last_seq: u64,
+        reports_received: u32,
+    ) -> Result<()> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection
+            .interrupt_ack(handle, endpoint, last_seq, reports_received)
+            .await
+    }
+
+    /// Request retransmission of missing interrupt reports from a server
+    pub async fn interrupt_nack(
+        &self,
+        server_id: EndpointId,
+        handle: DeviceHandle,
+        endpoint: u8,
+        missing_sequences: Vec<u64>,
+        last_contiguous_seq: u64,
+    ) -> Result<()> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection
+            .interrupt_nack(handle, endpoint, missing_sequences, last_contiguous_seq)
+            .await
+    }
+
+    /// Get the transfer metrics recorded for a server connection
+    ///
+    /// Returns None if not connected.
+    pub async fn transfer_metrics(&self, server_id: EndpointId) -> Option<Arc<TransferMetrics>> {
+        let connections = self.connections.lock().await;
+        connections
+            .get(&server_id)
+            .map(|conn| conn.transfer_metrics())
+    }
+
+    /// Per-device and per-transfer-type metrics of this client
+    pub fn metrics(&self) -> SharedClientMetrics {
+        self.metrics.clone()
+    }
+
+    /// Fetch the metrics summary from a server
+    pub async fn get_server_metrics(
+        &self,
+        server_id: EndpointId,
+    ) -> Result<protocol::ServerMetricsSummary> {
+        let connections = self.connections.lock().await;
+        let connection = connections
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("Not connected to server: {}", server_id))?;
+
+        connection.get_server_metrics().await
     }
 
     /// Get list of connected servers
@@ -732,8 +1017,17 @@ impl IrohClient {
     ) -> Result<ServerConnection> {
         // ServerConnection::new() includes connection warm-up which does
         // the capability exchange. No need to call send_client_capabilities() again.
-        let connection =
-            ServerConnection::new(self.endpoint.clone(), server_id, server_addr).await?;
+        let invite_token = self.invites.lock().await.get(&server_id).cloned();
+        let connection = ServerConnection::new(
+            self.endpoint.clone(),
+            server_id,
+            server_addr,
+            self.state_updates.clone(),
+            invite_token,
+        )
+        .await?;
+        // Admitted, so the invite (if any) has been used up
+        self.invites.lock().await.remove(&server_id);
 
         // Setup notification forwarding
         let notification_tx_agg = self.notification_updates.clone();
@@ -4,11 +4,13 @@
 //! request/response correlation, heartbeat, and automatic reconnection.
 
 use anyhow::{Context, Result, anyhow};
-use common::ALPN_PROTOCOL;
+use common::{ALPN_PROTOCOL, TransferMetrics};
 use iroh::{Endpoint, EndpointAddr, PublicKey as EndpointId};
 use protocol::{
-    CURRENT_VERSION, DeviceHandle, DeviceId, DeviceInfo, DeviceRemovalReason, Message,
-    MessagePayload, RequestId, UsbRequest, UsbResponse, decode_framed, encode_framed,
+    CURRENT_VERSION, DeviceDescriptors, DeviceHandle, DeviceId, DeviceInfo, DeviceOperation,
+    DeviceRemovalReason, InterruptStreamInfo, Message, MessagePayload, ProtocolError,
+    ProtocolMetrics, ProtocolVersion, RequestId, Reservation, ReservationId, ReservationUpdate,
+    ServerMetricsSummary, UsbError, UsbRequest, UsbResponse, decode_framed, encode_framed,
     validate_version,
 };
 use std::sync::Arc;
@@ -19,9 +21,11 @@ use tokio::task::JoinHandle;
 use tokio::time::{Instant, interval_at, sleep};
 use tracing::{debug, error, info, warn};
 
+use super::client::StateUpdates;
 use super::health::{
     HEARTBEAT_INTERVAL, HEARTBEAT_TIMEOUT, HealthMetrics, HealthMonitor, create_health_monitor,
 };
+use super::transfer_channel::TransferChannel;
 
 /// Device notification received from server via push
 #[derive(Debug, Clone)]
@@ -50,6 +54,10 @@ pub enum DeviceNotification {
         /// CRC32C checksum for integrity verification
         checksum: u32,
     },
+    /// Usage of the quotas the server applies to this client
+    QuotaStatus { quotas: Vec<protocol::QuotaUsage> },
+    /// A reservation of this client, or on a device it uses, started or ended
+    ReservationChanged { update: ReservationUpdate },
 }
 
 /// Connection state
@@ -64,6 +72,8 @@ pub enum ConnectionState {
     Connected,
     /// Connection lost, attempting to reconnect
     Reconnecting,
+    /// Waiting for the server operator to approve this client
+    AwaitingApproval,
     /// Permanently closed
     Closed,
 }
@@ -88,6 +98,18 @@ pub struct ServerConnection {
     notification_tx: broadcast::Sender<DeviceNotification>,
     /// Connection health monitor
     health_monitor: Arc<HealthMonitor>,
+    /// Persistent transfer channel (when negotiated with the server)
+    transfer_channel: Arc<RwLock<Option<TransferChannel>>>,
+    /// Transfers to this server as seen by the client (reported to the server)
+    transfer_metrics: Arc<TransferMetrics>,
+    /// Protocol version the server reported in the capability exchange
+    server_version: Arc<RwLock<Option<ProtocolVersion>>>,
+    /// Set once the server rejects a metrics update (it predates them)
+    metrics_update_rejected: Arc<AtomicBool>,
+    /// Announces when the server parks this client for approval
+    state_updates: StateUpdates,
+    /// Pairing invite presented until the server has admitted this client
+    invite_token: Arc<std::sync::Mutex<Option<String>>>,
 }
 
 impl ServerConnection {
@@ -98,6 +120,8 @@ impl ServerConnection {
         endpoint: Endpoint,
         server_id: EndpointId,
         server_addr: Option<EndpointAddr>,
+        state_updates: StateUpdates,
+        invite_token: Option<String>,
     ) -> Result<Self> {
         let state = Arc::new(RwLock::new(ConnectionState::Connecting));
         let connection = Arc::new(Mutex::new(None));
@@ -105,6 +129,11 @@ impl ServerConnection {
         let shutdown = Arc::new(AtomicBool::new(false));
         let (notification_tx, _) = broadcast::channel(64);
         let health_monitor = create_health_monitor();
+        let transfer_channel = Arc::new(RwLock::new(None));
+        let transfer_metrics = Arc::new(TransferMetrics::new());
+        let server_version = Arc::new(RwLock::new(None));
+        let metrics_update_rejected = Arc::new(AtomicBool::new(false));
+        let invite_token = Arc::new(std::sync::Mutex::new(invite_token));
 
         let conn = Self {
             server_id,
@@ -116,10 +145,17 @@ impl ServerConnection {
             shutdown: shutdown.clone(),
             notification_tx: notification_tx.clone(),
             health_monitor: health_monitor.clone(),
+            transfer_channel: transfer_channel.clone(),
+            transfer_metrics: transfer_metrics.clone(),
+            server_version: server_version.clone(),
+            metrics_update_rejected: metrics_update_rejected.clone(),
+            state_updates: state_updates.clone(),
+            invite_token: invite_token.clone(),
         };
 
         // Establish initial connection
         conn.connect().await?;
+        transfer_metrics.mark_connected();
 
         // Spawn heartbeat task with health monitoring
         let conn_clone = Self {
@@ -132,6 +168,12 @@ impl ServerConnection {
             shutdown: shutdown.clone(),
             notification_tx: notification_tx.clone(),
             health_monitor: health_monitor.clone(),
+            transfer_channel: transfer_channel.clone(),
+            transfer_metrics,
+            server_version,
+            metrics_update_rejected,
+            state_updates,
+            invite_token,
         };
         tokio::spawn(async move {
             conn_clone.heartbeat_loop().await;
@@ -187,7 +229,7 @@ impl ServerConnection {
     /// that for warm-up rather than Ping.
     async fn warm_up_connection(&self) -> Result<()> {
         info!("Warming up QUIC connection...");
-        let start = Instant::now();
+        let mut start = Instant::now();
 
         // Use ClientCapabilities for warm-up since server expects it first
         let message = Message {
@@ -197,37 +239,167 @@ impl ServerConnection {
             },
         };
 
+        // A pairing invite rides along until the server has admitted us
+        let invite_token = self.invite_token.lock().unwrap().clone();
+        let first_message = match invite_token {
+            Some(token) => Message {
+                version: CURRENT_VERSION,
+                payload: MessagePayload::ClientCapabilitiesV2 {
+                    supports_push_notifications: true,
+                    invite_token: Some(token),
+                },
+            },
+            None => message.clone(),
+        };
+
         // Allow generous timeout for warm-up (30 seconds) since this is a one-time cost
-        let response = tokio::time::timeout(Duration::from_secs(30), self.send_message(message))
-            .await
-            .context("Connection warm-up timed out (30s)")?
-            .context("Failed to warm up connection")?;
+        let (mut response, recv) = self.warm_up_request(first_message).await?;
 
+        // Servers from 1.9 hold unknown clients on this stream until their
+        // operator decides, or admit them at once for a valid invite; either
+        // way they expect the capability exchange again
         match response.payload {
-            MessagePayload::ServerCapabilities {
-                will_send_notifications,
-            } => {
-                let elapsed = start.elapsed();
-                info!(
-                    "Connection warm-up complete in {:?} - server will push notifications: {}",
-                    elapsed, will_send_notifications
-                );
-                // Record this as the first RTT measurement
-                if elapsed.as_millis() > 1000 {
-                    warn!(
-                        "Slow connection detected: {:?} warm-up time. USB transfers may be affected.",
-                        elapsed
-                    );
+            MessagePayload::ApprovalPending => {
+                self.await_approval(recv).await?;
+                start = Instant::now();
+                (response, _) = self.warm_up_request(message).await?;
+            }
+            MessagePayload::ApprovalGranted => {
+                info!("Server {} accepted the invite", self.server_id);
+                start = Instant::now();
+                (response, _) = self.warm_up_request(message).await?;
+            }
+            _ => {}
+        }
+
+        // Servers before 1.2 answer with the original ServerCapabilities and
+        // don't offer the channel; transfers then use one stream each
+        let (will_send_notifications, transfer_channel, max_in_flight_transfers) =
+            match response.payload {
+                MessagePayload::ServerCapabilities {
+                    will_send_notifications,
+                } => (will_send_notifications, false, 0),
+                MessagePayload::ServerCapabilitiesV2 {
+                    will_send_notifications,
+                    transfer_channel,
+                    max_in_flight_transfers,
+                } => (
+                    will_send_notifications,
+                    transfer_channel,
+                    max_in_flight_transfers,
+                ),
+                MessagePayload::Error { message } => {
+                    return Err(anyhow!("Server error during warm-up: {}", message));
                 }
+                _ => return Err(anyhow!("Unexpected response during warm-up")),
+            };
+
+        let elapsed = start.elapsed();
+        info!(
+            "Connection warm-up complete in {:?} - server will push notifications: {}",
+            elapsed, will_send_notifications
+        );
+        // Record this as the first RTT measurement
+        if elapsed.as_millis() > 1000 {
+            warn!(
+                "Slow connection detected: {:?} warm-up time. USB transfers may be affected.",
+                elapsed
+            );
+        }
+
+        *self.server_version.write().await = Some(response.version);
+        // The invite is burned (or was not needed); don't present it again
+        self.invite_token.lock().unwrap().take();
+        // A reconnect may reach an upgraded server
+        self.metrics_update_rejected.store(false, Ordering::Relaxed);
+
+        *self.transfer_channel.write().await = None;
+        if transfer_channel {
+            self.open_transfer_channel(
+                max_in_flight_transfers,
+                response.version.supports_cancel_transfer(),
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Send the warm-up capabilities and read the first answer
+    ///
+    /// Returns the receive side too, on which an approval decision follows.
+    async fn warm_up_request(
+        &self,
+        message: Message,
+    ) -> Result<(Message, iroh::endpoint::RecvStream)> {
+        tokio::time::timeout(Duration::from_secs(30), async {
+            let mut recv = self.send_oneway(message).await?;
+            let response = Self::read_response(&mut recv).await?;
+            anyhow::Ok((response, recv))
+        })
+        .await
+        .context("Connection warm-up timed out (30s)")?
+        .context("Failed to warm up connection")
+    }
+
+    /// Wait on the held capability stream until the server operator decides
+    async fn await_approval(&self, mut recv: iroh::endpoint::RecvStream) -> Result<()> {
+        warn!(
+            "Server {} is awaiting operator approval for this client",
+            self.server_id
+        );
+        *self.state.write().await = ConnectionState::AwaitingApproval;
+        let _ = self.state_updates.send((
+            self.server_id,
+            super::client::ConnectionState::AwaitingApproval,
+        ));
+
+        let decision = Self::read_response(&mut recv)
+            .await
+            .context("Connection closed while awaiting approval")?;
+        match decision.payload {
+            MessagePayload::ApprovalGranted => {
+                info!("Server {} approved this client", self.server_id);
+                *self.state.write().await = ConnectionState::Connected;
                 Ok(())
             }
+            MessagePayload::ApprovalDenied => {
+                Err(anyhow!("Server {} denied access", self.server_id))
+            }
             MessagePayload::Error { message } => {
-                Err(anyhow!("Server error during warm-up: {}", message))
+                Err(anyhow!("Server error while awaiting approval: {}", message))
             }
-            _ => Err(anyhow!("Unexpected response during warm-up")),
+            _ => Err(anyhow!("Unexpected response while awaiting approval")),
+        }
+    }
+
+    /// Open the persistent transfer channel on the current connection
+    ///
+    /// Failure is not fatal: transfers fall back to per-request streams.
+    async fn open_transfer_channel(&self, max_in_flight: u32, supports_cancel: bool) {
+        let conn = match self.connection.lock().await.as_ref() {
+            Some(conn) => conn.clone(),
+            None => return,
+        };
+
+        match TransferChannel::open(&conn, max_in_flight, supports_cancel).await {
+            Ok(channel) => *self.transfer_channel.write().await = Some(channel),
+            Err(e) => warn!(
+                "Failed to open transfer channel, using per-request streams: {:#}",
+                e
+            ),
         }
     }
 
+    /// Get the transfer channel if one is open and usable
+    pub async fn transfer_channel(&self) -> Option<TransferChannel> {
+        self.transfer_channel
+            .read()
+            .await
+            .as_ref()
+            .filter(|channel| !channel.is_closed())
+            .cloned()
+    }
+
     /// Reconnect with exponential backoff
     async fn reconnect(&self) -> Result<()> {
         let mut backoff_ms = 1000; // Start at 1 second
@@ -305,21 +477,11 @@ impl ServerConnection {
 
                 // Accept unidirectional stream with timeout
                 match tokio::time::timeout(Duration::from_secs(1), conn.accept_uni()).await {
-                    Ok(Ok(mut recv)) => {
-                        // Read notification message
-                        match protocol::read_framed_async(&mut recv).await {
-                            Ok(bytes) => match decode_framed(&bytes) {
-                                Ok(message) => {
-                                    Self::handle_notification(message.payload, &notification_tx);
-                                }
-                                Err(e) => {
-                                    warn!("Failed to decode notification: {}", e);
-                                }
-                            },
-                            Err(e) => {
-                                debug!("Failed to read notification: {}", e);
-                            }
-                        }
+                    Ok(Ok(recv)) => {
+                        // Notifications use one stream each, interrupt data
+                        // shares a long-lived stream, so read every stream
+                        // to its end without holding up the next one
+                        tokio::spawn(Self::read_notifications(recv, notification_tx.clone()));
                     }
                     Ok(Err(e)) => {
                         // Connection error - may be closing
@@ -334,9 +496,36 @@ impl ServerConnection {
         })
     }
 
+    /// Read notification frames from a unidirectional stream until it ends
+    async fn read_notifications(
+        mut recv: iroh::endpoint::RecvStream,
+        notification_tx: broadcast::Sender<DeviceNotification>,
+    ) {
+        loop {
+            match protocol::read_framed_async(&mut recv).await {
+                Ok(bytes) => match decode_framed(&bytes) {
+                    Ok(message) => {
+                        Self::handle_notification(message.payload, &notification_tx);
+                    }
+                    Err(e) => {
+                        warn!("Failed to decode notification: {}", e);
+                    }
+                },
+                Err(ProtocolError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    break;
+                }
+                Err(e) => {
+                    debug!("Failed to read notification: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
     fn handle_notification(payload: MessagePayload, tx: &broadcast::Sender<DeviceNotification>) {
         match payload {
-            MessagePayload::DeviceArrivedNotification { device } => {
+            MessagePayload::DeviceArrivedNotification { device }
+            | MessagePayload::DeviceArrivedNotificationV2 { device } => {
                 info!("Received device arrived notification: {:?}", device.id);
                 let _ = tx.send(DeviceNotification::DeviceArrived { device });
             }
@@ -356,6 +545,11 @@ impl ServerConnection {
                 device_id,
                 device_info,
                 reason,
+            }
+            | MessagePayload::DeviceStatusChangedNotificationV2 {
+                device_id,
+                device_info,
+                reason,
             } => {
                 info!(
                     "Received device status changed notification: {:?} ({:?})",
@@ -367,7 +561,8 @@ impl ServerConnection {
                     reason,
                 });
             }
-            MessagePayload::AggregatedNotifications { notifications } => {
+            MessagePayload::AggregatedNotifications { notifications }
+            | MessagePayload::AggregatedNotificationsV2 { notifications } => {
                 info!(
                     "Received aggregated notification batch with {} items",
                     notifications.len()
@@ -424,6 +619,19 @@ impl ServerConnection {
                     checksum,
                 });
             }
+            MessagePayload::QuotaStatus { quotas } => {
+                for quota in &quotas {
+                    info!("Quota status: {}", quota);
+                }
+                let _ = tx.send(DeviceNotification::QuotaStatus { quotas });
+            }
+            MessagePayload::ReservationNotification { update } => {
+                info!(
+                    "Reservation {} on device {:?} {}",
+                    update.reservation.id.0, update.reservation.device_id, update.event
+                );
+                let _ = tx.send(DeviceNotification::ReservationChanged { update });
+            }
             _ => {
                 warn!("Unexpected notification payload: {:?}", payload);
             }
@@ -447,6 +655,10 @@ impl ServerConnection {
         match response.payload {
             MessagePayload::ServerCapabilities {
                 will_send_notifications,
+            }
+            | MessagePayload::ServerCapabilitiesV2 {
+                will_send_notifications,
+                ..
             } => {
                 debug!(
                     "Server capabilities received: will_send_notifications={}",
@@ -483,6 +695,11 @@ impl ServerConnection {
                     if let Some(rtt) = rtt_ms {
                         debug!("Heartbeat successful, RTT: {}ms", rtt);
                     }
+
+                    // Share our view of the connection with the server
+                    if let Err(e) = self.send_metrics_update().await {
+                        warn!("Failed to send metrics update: {:#}", e);
+                    }
                 }
                 Err(e) => {
                     warn!("Heartbeat failed: {}. Recording failure.", e);
@@ -580,6 +797,71 @@ impl ServerConnection {
         self.health_monitor.clone()
     }
 
+    /// Get the transfer metrics recorded for this server
+    pub fn transfer_metrics(&self) -> Arc<TransferMetrics> {
+        self.transfer_metrics.clone()
+    }
+
+    /// Send this client's transfer metrics to the server
+    ///
+    /// The server stores the report and finishes the stream without a
+    /// response. Servers that predate metrics exchange answer with an error;
+    /// after the first one no more updates are sent on this connection.
+    async fn send_metrics_update(&self) -> Result<()> {
+        if self.metrics_update_rejected.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::ClientMetricsUpdate {
+                metrics: ProtocolMetrics::from(self.transfer_metrics.as_ref()),
+            },
+        };
+
+        let mut recv = self.send_oneway(message).await?;
+        let reply = match protocol::read_framed_async(&mut recv).await {
+            Ok(reply) => reply,
+            Err(ProtocolError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(());
+            }
+            Err(e) => return Err(e).context("Failed to read metrics update reply"),
+        };
+
+        let reason = match decode_framed(&reply) {
+            Ok(Message {
+                payload: MessagePayload::Error { message },
+                ..
+            }) => message,
+            Ok(message) => format!("unexpected reply {:?}", message.payload),
+            Err(e) => e.to_string(),
+        };
+        self.metrics_update_rejected.store(true, Ordering::Relaxed);
+        warn!(
+            "Server {} rejected metrics update ({}), not sending more",
+            self.server_id, reason
+        );
+        Ok(())
+    }
+
+    /// Send a message the server does not answer
+    ///
+    /// Returns the receive side, which the server finishes without a frame.
+    async fn send_oneway(&self, message: Message) -> Result<iroh::endpoint::RecvStream> {
+        let connection = self.connection.lock().await;
+        let conn = connection
+            .as_ref()
+            .ok_or_else(|| anyhow!("Not connected"))?;
+        let (mut send, recv) = conn.open_bi().await.context("Failed to open QUIC stream")?;
+
+        let encoded = encode_framed(&message).context("Failed to encode message")?;
+        protocol::write_framed_async(&mut send, &encoded)
+            .await
+            .context("Failed to write message")?;
+        send.finish().context("Failed to finish stream")?;
+        Ok(recv)
+    }
+
     /// Send a message and wait for response
     async fn send_message(&self, message: Message) -> Result<Message> {
         let connection = self.connection.lock().await;
@@ -598,13 +880,19 @@ impl ServerConnection {
             .context("Failed to write message")?;
         send.finish().context("Failed to finish stream")?;
 
+        Self::read_response(&mut recv).await
+    }
+
+    /// Read and validate one response frame
+    async fn read_response(recv: &mut iroh::endpoint::RecvStream) -> Result<Message> {
         // Read response
-        let response_bytes = protocol::read_framed_async(&mut recv)
+        let response_bytes = protocol::read_framed_async(recv)
             .await
             .context("Failed to read response")?;
 
         // Decode response
-        let response = decode_framed(&response_bytes).context("Failed to decode response")?;
+        let response: Message =
+            decode_framed(&response_bytes).context("Failed to decode response")?;
 
         // Validate version
         validate_version(&response.version).context("Incompatible protocol version")?;
@@ -622,12 +910,29 @@ impl ServerConnection {
         let response = self.send_message(message).await?;
 
         match response.payload {
-            MessagePayload::ListDevicesResponse { devices } => Ok(devices),
+            MessagePayload::ListDevicesResponse { devices }
+            | MessagePayload::ListDevicesResponseV2 { devices } => Ok(devices),
             MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
             _ => Err(anyhow!("Unexpected response to ListDevicesRequest")),
         }
     }
 
+    /// Get the server's metrics summary
+    pub async fn get_server_metrics(&self) -> Result<ServerMetricsSummary> {
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::GetMetricsRequest,
+        };
+
+        let response = self.send_message(message).await?;
+
+        match response.payload {
+            MessagePayload::GetMetricsResponse { metrics } => Ok(metrics),
+            MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
+            _ => Err(anyhow!("Unexpected response to GetMetricsRequest")),
+        }
+    }
+
     /// Attach to a device
     pub async fn attach_device(&self, device_id: DeviceId) -> Result<DeviceHandle> {
         let message = Message {
@@ -646,6 +951,42 @@ impl ServerConnection {
         }
     }
 
+    /// Attach to some interfaces of a device
+    pub async fn attach_interfaces(
+        &self,
+        device_id: DeviceId,
+        interfaces: Vec<u8>,
+    ) -> Result<DeviceHandle> {
+        let supported = self
+            .server_version
+            .read()
+            .await
+            .is_some_and(|version| version.supports_interface_sharing());
+        if !supported {
+            return Err(anyhow!(
+                "Server does not support attaching single interfaces"
+            ));
+        }
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::AttachInterfacesRequest {
+                device_id,
+                interfaces,
+            },
+        };
+
+        let response = self.send_message(message).await?;
+
+        match response.payload {
+            MessagePayload::AttachDeviceResponse { result } => {
+                result.map_err(|e| anyhow!("Attach failed: {:?}", e))
+            }
+            MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
+            _ => Err(anyhow!("Unexpected response to AttachInterfacesRequest")),
+        }
+    }
+
     /// Detach from a device
     pub async fn detach_device(&self, handle: DeviceHandle) -> Result<()> {
         let message = Message {
@@ -664,8 +1005,229 @@ impl ServerConnection {
         }
     }
 
+    /// Perform a device operation (reset, clear-halt, set-config, set-interface)
+    ///
+    /// The outer error covers transport failures and servers older than
+    /// protocol 1.4; the inner result is the outcome on the device.
+    pub async fn device_operation(
+        &self,
+        handle: DeviceHandle,
+        operation: DeviceOperation,
+    ) -> Result<std::result::Result<(), UsbError>> {
+        let supported = self
+            .server_version
+            .read()
+            .await
+            .is_some_and(|version| version.supports_device_operations());
+        if !supported {
+            return Err(anyhow!("Server does not support device operations"));
+        }
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::DeviceOperationRequest { handle, operation },
+        };
+
+        let response = self.send_message(message).await?;
+
+        match response.payload {
+            MessagePayload::DeviceOperationResponse { result, .. } => Ok(result),
+            MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
+            _ => Err(anyhow!("Unexpected response to DeviceOperationRequest")),
+        }
+    }
+
+    /// Fetch the full descriptor set of a device (no attach needed)
+    pub async fn get_descriptors(&self, device_id: DeviceId) -> Result<DeviceDescriptors> {
+        let supported = self
+            .server_version
+            .read()
+            .await
+            .is_some_and(|version| version.supports_descriptors());
+        if !supported {
+            return Err(anyhow!("Server does not support descriptor queries"));
+        }
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::GetDescriptorsRequest { device_id },
+        };
+
+        let response = self.send_message(message).await?;
+
+        match response.payload {
+            MessagePayload::GetDescriptorsResponse { result, .. } => {
+                result.map_err(|e| anyhow!("Failed to read descriptors: {:?}", e))
+            }
+            MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
+            _ => Err(anyhow!("Unexpected response to GetDescriptorsRequest")),
+        }
+    }
+
+    /// Book a device for a period (Unix seconds)
+    pub async fn create_reservation(
+        &self,
+        device_id: DeviceId,
+        starts_at: u64,
+        ends_at: u64,
+        note: Option<String>,
+    ) -> Result<Reservation> {
+        self.require_reservations().await?;
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::CreateReservationRequest {
+                device_id,
+                starts_at,
+                ends_at,
+                note,
+            },
+        };
+
+        let response = self.send_message(message).await?;
+
+        match response.payload {
+            MessagePayload::CreateReservationResponse { result } => {
+                result.map_err(|e| anyhow!("Reservation refused: {}", e))
+            }
+            MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
+            _ => Err(anyhow!("Unexpected response to CreateReservationRequest")),
+        }
+    }
+
+    /// List current and upcoming reservations, optionally of one device
+    pub async fn list_reservations(&self, device_id: Option<DeviceId>) -> Result<Vec<Reservation>> {
+        self.require_reservations().await?;
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::ListReservationsRequest { device_id },
+        };
+
+        let response = self.send_message(message).await?;
+
+        match response.payload {
+            MessagePayload::ListReservationsResponse { reservations } => Ok(reservations),
+            MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
+            _ => Err(anyhow!("Unexpected response to ListReservationsRequest")),
+        }
+    }
+
+    /// Cancel one of this client's reservations
+    pub async fn cancel_reservation(&self, id: ReservationId) -> Result<()> {
+        self.require_reservations().await?;
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::CancelReservationRequest { id },
+        };
+
+        let response = self.send_message(message).await?;
+
+        match response.payload {
+            MessagePayload::CancelReservationResponse { result } => {
+                result.map_err(|e| anyhow!("Failed to cancel reservation {}: {}", id.0, e))
+            }
+            MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
+            _ => Err(anyhow!("Unexpected response to CancelReservationRequest")),
+        }
+    }
+
+    async fn require_reservations(&self) -> Result<()> {
+        let supported = self
+            .server_version
+            .read()
+            .await
+            .is_some_and(|version| version.supports_reservations());
+        if !supported {
+            return Err(anyhow!("Server does not support device reservations"));
+        }
+        Ok(())
+    }
+
+    /// Ask the server to stream an interrupt IN endpoint
+    ///
+    /// Reports then arrive as `InterruptData` notifications. Fails if the
+    /// server rejects the endpoint or does not stream interrupt data.
+    pub async fn start_interrupt_stream(
+        &self,
+        handle: DeviceHandle,
+        endpoint: u8,
+        buffer_hint: u32,
+    ) -> Result<InterruptStreamInfo> {
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::StartInterruptStreamRequest {
+                handle,
+                endpoint,
+                buffer_hint,
+            },
+        };
+
+        let response = self.send_message(message).await?;
+
+        match response.payload {
+            MessagePayload::StartInterruptStreamResponse { result, .. } => {
+                result.map_err(|e| anyhow!("Interrupt stream refused: {}", e))
+            }
+            MessagePayload::Error { message } => Err(anyhow!("Server error: {}", message)),
+            _ => Err(anyhow!(
+                "Unexpected response to StartInterruptStreamRequest"
+            )),
+        }
+    }
+
+    /// Acknowledge streamed interrupt reports up to `last_seq`
+    pub async fn interrupt_ack(
+        &self,
+        handle: DeviceHandle,
+        endpoint: u8,
+        last_seq: u64,
+        reports_received: u32,
+    ) -> Result<()> {
+        self.send_oneway(Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::InterruptAck {
+                handle,
+                endpoint,
+                last_seq,
+                reports_received,
+            },
+        })
+        .await
+        .map(drop)
+    }
+
+    /// Request retransmission of missing interrupt reports
+    pub async fn interrupt_nack(
+        &self,
+        handle: DeviceHandle,
+        endpoint: u8,
+        missing_sequences: Vec<u64>,
+        last_contiguous_seq: u64,
+    ) -> Result<()> {
+        self.send_oneway(Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::InterruptNack {
+                handle,
+                endpoint,
+                missing_sequences,
+                last_contiguous_seq,
+            },
+        })
+        .await
+        .map(drop)
+    }
+
     /// Submit a USB transfer
+    ///
+    /// Uses the persistent transfer channel when available, otherwise a
+    /// dedicated stream for this transfer.
     pub async fn submit_transfer(&self, request: UsbRequest) -> Result<UsbResponse> {
+        if let Some(channel) = self.transfer_channel().await {
+            return channel.submit(request).await;
+        }
+
         let message = Message {
             version: CURRENT_VERSION,
             payload: MessagePayload::SubmitTransfer { request },
@@ -697,6 +1259,9 @@ impl ServerConnection {
         // Update state
         *self.state.write().await = ConnectionState::Closed;
 
+        // Drop the transfer channel before the connection goes away
+        self.transfer_channel.write().await.take();
+
         // Close QUIC connection
         let mut connection = self.connection.lock().await;
         if let Some(conn) = connection.take() {
@@ -6,8 +6,9 @@
 use anyhow::{Context, Result, anyhow};
 use iroh::PublicKey as EndpointId;
 use protocol::{
-    DeviceHandle, DeviceId, DeviceInfo, RequestId, TransferResult, TransferType, UsbRequest,
-    UsbResponse, integrity::{compute_checksum, verify_checksum}, UsbError,
+    CancelResult, DeviceHandle, DeviceId, DeviceInfo, DeviceOperation, InterruptStreamInfo,
+    RequestId, TransferResult, TransferType, UsbError, UsbRequest, UsbResponse,
+    integrity::{compute_checksum, verify_checksum},
 };
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -29,6 +30,8 @@ pub struct DeviceProxy {
     info: DeviceInfo,
     /// Device handle (if attached)
     handle: Arc<RwLock<Option<DeviceHandle>>>,
+    /// Interfaces to attach (None = whole device)
+    interfaces: Option<Vec<u8>>,
 }
 
 impl DeviceProxy {
@@ -44,9 +47,23 @@ impl DeviceProxy {
             server_id,
             info,
             handle: Arc::new(RwLock::new(None)),
+            interfaces: None,
         }
     }
 
+    /// Attach only the given interfaces of a composite device
+    ///
+    /// The virtual device then exposes just these interfaces.
+    pub fn with_interfaces(mut self, interfaces: Vec<u8>) -> Self {
+        self.interfaces = Some(interfaces);
+        self
+    }
+
+    /// Interfaces this proxy attaches (None = whole device)
+    pub fn interfaces(&self) -> Option<&[u8]> {
+        self.interfaces.as_deref()
+    }
+
     /// Get device information
     pub fn device_info(&self) -> &DeviceInfo {
         &self.info
@@ -83,11 +100,18 @@ impl DeviceProxy {
             self.info.id.0, self.server_id
         );
 
-        let handle = self
-            .client
-            .attach_device(self.server_id, self.info.id)
-            .await
-            .context("Failed to attach to device")?;
+        let handle = match &self.interfaces {
+            Some(interfaces) => self
+                .client
+                .attach_interfaces(self.server_id, self.info.id, interfaces.clone())
+                .await
+                .context("Failed to attach to device interfaces")?,
+            None => self
+                .client
+                .attach_device(self.server_id, self.info.id)
+                .await
+                .context("Failed to attach to device")?,
+        };
 
         *self.handle.write().await = Some(handle);
 
@@ -319,6 +343,46 @@ impl DeviceProxy {
         }
     }
 
+    /// Ask the server to cancel a transfer submitted through this proxy
+    ///
+    /// Fails if the server cannot cancel transfers (pre-1.3 protocol or no
+    /// transfer channel).
+    pub async fn cancel_transfer(&self, request_id: RequestId) -> Result<CancelResult> {
+        let handle = self.get_handle().await?;
+        self.client
+            .cancel_transfer(self.server_id, handle, request_id)
+            .await
+    }
+
+    /// Reset the device, clear a halt, or change configuration/alternate setting
+    ///
+    /// Fails if the server predates protocol 1.4; the inner result is the
+    /// outcome reported by the device.
+    pub async fn device_operation(
+        &self,
+        operation: DeviceOperation,
+    ) -> Result<std::result::Result<(), UsbError>> {
+        let handle = self.get_handle().await?;
+        self.client
+            .device_operation(self.server_id, handle, operation)
+            .await
+    }
+
+    /// Ask the server to stream an interrupt IN endpoint of this device
+    ///
+    /// Fails if the server does not stream interrupt data; the endpoint is
+    /// then polled with regular transfers.
+    pub async fn start_interrupt_stream(
+        &self,
+        endpoint: u8,
+        buffer_hint: u32,
+    ) -> Result<InterruptStreamInfo> {
+        let handle = self.get_handle().await?;
+        self.client
+            .start_interrupt_stream(self.server_id, handle, endpoint, buffer_hint)
+            .await
+    }
+
     /// Check if a USB error is retryable
     fn is_retryable_error(error: &protocol::UsbError) -> bool {
         matches!(
@@ -379,6 +443,10 @@ mod tests {
             protocol: 0x50,
             speed: DeviceSpeed::High,
             num_configurations: 1,
+            interfaces: Vec::new(),
+            stable_id: "1234:5678:ABC123".to_string(),
+            port_numbers: vec![1],
+            parent_hub: None,
         }
     }
 
@@ -0,0 +1,288 @@
+//! Client transfer metrics
+//!
+//! Each ServerConnection counts its own transfers. This registry adds the
+//! per-device and per-transfer-type breakdowns (device handles are mapped
+//! back to the device they were attached from) and renders everything,
+//! together with the health of each connection, for the Prometheus
+//! `/metrics` endpoint configured by `client.metrics_addr`.
+
+use common::{
+    MetricType, MetricsEncoder, TransferKind, TransferKindMetrics, TransferMetrics, TransferOutcome,
+};
+use iroh::PublicKey as EndpointId;
+use protocol::{DeviceHandle, DeviceId};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use super::client::IrohClient;
+use super::health::HealthState;
+
+/// Shared handle to the client metrics registry
+pub type SharedClientMetrics = Arc<ClientMetricsRegistry>;
+
+/// Per-device and per-transfer-type metrics of the client
+#[derive(Debug, Default)]
+pub struct ClientMetricsRegistry {
+    /// Device attached under each handle, per server
+    handles: RwLock<HashMap<(EndpointId, DeviceHandle), DeviceId>>,
+    /// Transfers per server and device
+    devices: RwLock<HashMap<(EndpointId, DeviceId), Arc<TransferMetrics>>>,
+    /// Transfers per transfer type, across all servers
+    transfer_types: TransferKindMetrics,
+}
+
+impl ClientMetricsRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember which device `handle` on `server_id` refers to
+    pub fn device_attached(
+        &self,
+        server_id: EndpointId,
+        handle: DeviceHandle,
+        device_id: DeviceId,
+    ) {
+        self.handles
+            .write()
+            .unwrap()
+            .insert((server_id, handle), device_id);
+    }
+
+    /// Forget a detached handle (the device's counters are kept)
+    pub fn device_detached(&self, server_id: EndpointId, handle: DeviceHandle) {
+        self.handles.write().unwrap().remove(&(server_id, handle));
+    }
+
+    /// Record the start of a transfer on `handle`
+    pub fn transfer_started(
+        &self,
+        server_id: EndpointId,
+        handle: DeviceHandle,
+        kind: TransferKind,
+    ) {
+        if let Some(device) = self.device(server_id, handle) {
+            device.transfer_started();
+        }
+        self.transfer_types.get(kind).transfer_started();
+    }
+
+    /// Record the end of a transfer previously passed to `transfer_started`
+    pub fn transfer_finished(
+        &self,
+        server_id: EndpointId,
+        handle: DeviceHandle,
+        kind: TransferKind,
+        outcome: TransferOutcome,
+    ) {
+        if let Some(device) = self.device(server_id, handle) {
+            device.transfer_finished(outcome);
+        }
+        self.transfer_types.get(kind).transfer_finished(outcome);
+    }
+
+    /// Metrics of the device behind `handle` (None for an unknown handle)
+    fn device(&self, server_id: EndpointId, handle: DeviceHandle) -> Option<Arc<TransferMetrics>> {
+        let device_id = *self.handles.read().unwrap().get(&(server_id, handle))?;
+        Some(
+            self.devices
+                .write()
+                .unwrap()
+                .entry((server_id, device_id))
+                .or_default()
+                .clone(),
+        )
+    }
+
+    /// Write the device and transfer type metrics to `encoder`
+    pub fn encode(&self, encoder: &mut MetricsEncoder) {
+        let mut devices: Vec<_> = self
+            .devices
+            .read()
+            .unwrap()
+            .iter()
+            .map(|((server_id, device_id), metrics)| {
+                (server_id.to_string(), device_id.0, metrics.clone())
+            })
+            .collect();
+        devices.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        let device_series: Vec<_> = devices
+            .iter()
+            .map(|(server, device_id, metrics)| {
+                (
+                    vec![
+                        ("server", server.clone()),
+                        ("device", device_id.to_string()),
+                    ],
+                    metrics.as_ref(),
+                )
+            })
+            .collect();
+        let type_series: Vec<_> = self
+            .transfer_types
+            .iter()
+            .map(|(kind, metrics)| (vec![("type", kind.as_str().to_string())], metrics))
+            .collect();
+
+        encoder.transfer_metrics("p2p_usb_device", &device_series);
+        encoder.transfer_metrics("p2p_usb_transfer_type", &type_series);
+    }
+}
+
+/// Render the client's metrics page in the text exposition format
+///
+/// Covers the transfers of every connected server, the registry's device
+/// and transfer type breakdowns and the health of each connection.
+pub async fn render(client: &IrohClient) -> String {
+    let mut encoder = MetricsEncoder::new();
+
+    let mut servers: Vec<_> = client.get_all_health_metrics().await.into_iter().collect();
+    servers.sort_by_key(|(server_id, _)| server_id.to_string());
+
+    let mut transfers = Vec::new();
+    for (server_id, _) in &servers {
+        if let Some(metrics) = client.transfer_metrics(*server_id).await {
+            transfers.push((server_id.to_string(), metrics));
+        }
+    }
+    let server_series: Vec<_> = transfers
+        .iter()
+        .map(|(server, metrics)| (vec![("server", server.clone())], metrics.as_ref()))
+        .collect();
+    encoder.transfer_metrics("p2p_usb_server", &server_series);
+
+    client.metrics().encode(&mut encoder);
+
+    encoder.family(
+        "p2p_usb_connected_servers",
+        MetricType::Gauge,
+        "Servers currently connected",
+    );
+    encoder.sample("p2p_usb_connected_servers", &[], servers.len() as f64);
+
+    encoder.family(
+        "p2p_usb_server_healthy",
+        MetricType::Gauge,
+        "Whether heartbeats to the server succeed (1) or not (0)",
+    );
+    for (server_id, health) in &servers {
+        let healthy = matches!(health.state, HealthState::Connected | HealthState::Degraded);
+        encoder.sample(
+            "p2p_usb_server_healthy",
+            &[("server", &server_id.to_string())],
+            if healthy { 1.0 } else { 0.0 },
+        );
+    }
+
+    encoder.family(
+        "p2p_usb_server_rtt_seconds",
+        MetricType::Gauge,
+        "Latest heartbeat round-trip time to the server",
+    );
+    for (server_id, health) in &servers {
+        if let Some(rtt_ms) = health.latest_rtt_ms {
+            encoder.sample(
+                "p2p_usb_server_rtt_seconds",
+                &[("server", &server_id.to_string())],
+                rtt_ms as f64 / 1000.0,
+            );
+        }
+    }
+
+    encoder.family(
+        "p2p_usb_server_packet_loss_ratio",
+        MetricType::Gauge,
+        "Share of recent heartbeats that went unanswered",
+    );
+    for (server_id, health) in &servers {
+        encoder.sample(
+            "p2p_usb_server_packet_loss_ratio",
+            &[("server", &server_id.to_string())],
+            health.packet_loss,
+        );
+    }
+
+    encoder.family(
+        "p2p_usb_server_heartbeats_sent_total",
+        MetricType::Counter,
+        "Heartbeats sent to the server",
+    );
+    for (server_id, health) in &servers {
+        encoder.sample(
+            "p2p_usb_server_heartbeats_sent_total",
+            &[("server", &server_id.to_string())],
+            health.heartbeats_sent as f64,
+        );
+    }
+
+    encoder.family(
+        "p2p_usb_server_heartbeats_received_total",
+        MetricType::Counter,
+        "Heartbeats the server answered",
+    );
+    for (server_id, health) in &servers {
+        encoder.sample(
+            "p2p_usb_server_heartbeats_received_total",
+            &[("server", &server_id.to_string())],
+            health.heartbeats_received as f64,
+        );
+    }
+
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_transfers_follow_attached_handles() {
+        let server_id = iroh::SecretKey::generate(&mut rand::rng()).public();
+        let metrics = ClientMetricsRegistry::new();
+        metrics.device_attached(server_id, DeviceHandle(7), DeviceId(3));
+
+        metrics.transfer_started(server_id, DeviceHandle(7), TransferKind::Bulk);
+        metrics.transfer_finished(
+            server_id,
+            DeviceHandle(7),
+            TransferKind::Bulk,
+            TransferOutcome::Completed {
+                bytes_sent: 31,
+                bytes_received: 512,
+                latency: Duration::from_millis(3),
+            },
+        );
+        metrics.device_detached(server_id, DeviceHandle(7));
+
+        // Unknown handles still count towards their transfer type
+        metrics.transfer_started(server_id, DeviceHandle(7), TransferKind::Control);
+        metrics.transfer_finished(
+            server_id,
+            DeviceHandle(7),
+            TransferKind::Control,
+            TransferOutcome::Failed,
+        );
+
+        let mut encoder = MetricsEncoder::new();
+        metrics.encode(&mut encoder);
+        let page = encoder.finish();
+
+        let device = format!("server=\"{}\",device=\"3\"", server_id);
+        assert!(page.contains(&format!(
+            "p2p_usb_device_transfers_total{{{},result=\"completed\"}} 1\n",
+            device
+        )));
+        assert!(page.contains(&format!(
+            "p2p_usb_device_bytes_received_total{{{}}} 512\n",
+            device
+        )));
+        assert!(page.contains(
+            "p2p_usb_transfer_type_transfers_total{type=\"bulk\",result=\"completed\"} 1\n"
+        ));
+        assert!(page.contains(
+            "p2p_usb_transfer_type_transfers_total{type=\"control\",result=\"failed\"} 1\n"
+        ));
+    }
+}
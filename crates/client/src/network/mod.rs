@@ -6,13 +6,16 @@ pub mod client;
 pub mod connection;
 pub mod device_proxy;
 pub mod health;
+pub mod metrics;
 pub mod session;
+pub mod transfer_channel;
 
 // Re-export public types
 pub use client::{
     ClientConfig, ConnectionState, IrohClient, ReconciliationCallback, ReconciliationResult,
 };
 pub use connection::DeviceNotification;
+pub use metrics::{ClientMetricsRegistry, SharedClientMetrics};
 pub use health::{
     ConnectionQuality, HealthMetrics,
     HealthState,
@@ -0,0 +1,307 @@
+//! Persistent transfer channel
+//!
+//! Client side of the long-lived, request-ID-multiplexed transfer stream.
+//! Once negotiated, every `SubmitTransfer` is written to the same QUIC
+//! bidirectional stream instead of opening a new stream per transfer, and
+//! `TransferComplete` frames are matched back to their callers by request ID.
+//! Many transfers can be in flight at once, so a slow bulk-IN no longer blocks
+//! every other transfer queued behind it.
+//!
+//! Request IDs generated by virtual devices (USB/IP sequence numbers) are only
+//! unique per device, so the channel assigns its own wire IDs and restores the
+//! caller's ID on the response. `cancel` translates the caller's ID the same
+//! way before sending `CancelTransfer` (protocol 1.3+).
+
+use anyhow::{Context, Result, anyhow};
+use iroh::endpoint::{Connection, RecvStream, SendStream};
+use protocol::{
+    CURRENT_VERSION, CancelResult, DeviceHandle, Message, MessagePayload, RequestId, UsbRequest,
+    UsbResponse, decode_framed, encode_framed, validate_version,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::{Mutex, Semaphore, mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+/// How long to wait for the server to answer a `CancelTransfer`
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transfers waiting for a `TransferComplete`, keyed by wire request ID
+type PendingResponses = Arc<Mutex<HashMap<RequestId, oneshot::Sender<UsbResponse>>>>;
+
+/// Cancellations waiting for a `CancelTransferResponse`, keyed by wire request ID
+type PendingCancels = Arc<Mutex<HashMap<RequestId, oneshot::Sender<CancelResult>>>>;
+
+/// Handle to an open transfer channel
+///
+/// Cheap to clone; all clones share the same underlying stream.
+#[derive(Clone)]
+pub struct TransferChannel {
+    /// Encoded frames queued for the writer task
+    frame_tx: mpsc::Sender<Vec<u8>>,
+    /// Callers awaiting responses
+    pending: PendingResponses,
+    /// Callers awaiting cancellation results
+    pending_cancels: PendingCancels,
+    /// Wire IDs of in-flight transfers by device and caller request ID
+    wire_ids: Arc<Mutex<HashMap<(DeviceHandle, RequestId), RequestId>>>,
+    /// Server understands `CancelTransfer`
+    supports_cancel: bool,
+    /// Limits in-flight transfers to what the server advertised
+    in_flight: Arc<Semaphore>,
+    /// Wire request ID counter
+    next_wire_id: Arc<AtomicU64>,
+    /// Set once either half of the stream has failed or finished
+    closed: Arc<AtomicBool>,
+}
+
+impl TransferChannel {
+    /// Open a transfer channel on an established connection
+    ///
+    /// `max_in_flight` is the limit advertised by the server in
+    /// `ServerCapabilities`; `supports_cancel` reflects its protocol version.
+    pub async fn open(
+        conn: &Connection,
+        max_in_flight: u32,
+        supports_cancel: bool,
+    ) -> Result<Self> {
+        let (mut send, recv) = conn
+            .open_bi()
+            .await
+            .context("Failed to open transfer channel stream")?;
+
+        let open = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::OpenTransferChannel,
+        };
+        let encoded = encode_framed(&open).context("Failed to encode OpenTransferChannel")?;
+        protocol::write_framed_async(&mut send, &encoded)
+            .await
+            .context("Failed to write OpenTransferChannel")?;
+
+        let max_in_flight = max_in_flight.max(1) as usize;
+        let (frame_tx, frame_rx) = mpsc::channel(max_in_flight);
+        let channel = Self {
+            frame_tx,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            pending_cancels: Arc::new(Mutex::new(HashMap::new())),
+            wire_ids: Arc::new(Mutex::new(HashMap::new())),
+            supports_cancel,
+            in_flight: Arc::new(Semaphore::new(max_in_flight)),
+            next_wire_id: Arc::new(AtomicU64::new(1)),
+            closed: Arc::new(AtomicBool::new(false)),
+        };
+
+        tokio::spawn(Self::writer_task(send, frame_rx, channel.closed.clone()));
+        tokio::spawn(Self::reader_task(
+            recv,
+            channel.pending.clone(),
+            channel.pending_cancels.clone(),
+            channel.closed.clone(),
+        ));
+
+        info!("Transfer channel opened (max in flight: {})", max_in_flight);
+        Ok(channel)
+    }
+
+    /// Whether the channel can no longer carry transfers
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Submit a transfer and wait for its completion
+    pub async fn submit(&self, request: UsbRequest) -> Result<UsbResponse> {
+        if self.is_closed() {
+            return Err(anyhow!("Transfer channel closed"));
+        }
+
+        let _permit = self
+            .in_flight
+            .acquire()
+            .await
+            .map_err(|_| anyhow!("Transfer channel closed"))?;
+
+        let caller_id = request.id;
+        let handle = request.handle;
+        let wire_id = RequestId(self.next_wire_id.fetch_add(1, Ordering::Relaxed));
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(wire_id, tx);
+        self.wire_ids
+            .lock()
+            .await
+            .insert((handle, caller_id), wire_id);
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::SubmitTransfer {
+                request: UsbRequest {
+                    id: wire_id,
+                    ..request
+                },
+            },
+        };
+        let encoded = match encode_framed(&message) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                self.forget(wire_id, handle, caller_id).await;
+                return Err(e).context("Failed to encode transfer");
+            }
+        };
+
+        if self.frame_tx.send(encoded).await.is_err() {
+            self.forget(wire_id, handle, caller_id).await;
+            return Err(anyhow!("Transfer channel closed"));
+        }
+
+        let response = rx.await;
+        self.forget(wire_id, handle, caller_id).await;
+        let mut response =
+            response.map_err(|_| anyhow!("Transfer channel closed before response"))?;
+        response.id = caller_id;
+        Ok(response)
+    }
+
+    /// Ask the server to cancel an in-flight transfer
+    ///
+    /// `request_id` is the caller's ID passed to `submit`. Returns `NotFound`
+    /// if the transfer is unknown or already completed; `submit` then still
+    /// returns its response. A `Cancelled` transfer's `submit` returns
+    /// `UsbError::Cancelled`. Fails if the server predates protocol 1.3.
+    pub async fn cancel(
+        &self,
+        handle: DeviceHandle,
+        request_id: RequestId,
+    ) -> Result<CancelResult> {
+        if !self.supports_cancel {
+            return Err(anyhow!("Server does not support transfer cancellation"));
+        }
+        if self.is_closed() {
+            return Err(anyhow!("Transfer channel closed"));
+        }
+
+        let Some(wire_id) = self
+            .wire_ids
+            .lock()
+            .await
+            .get(&(handle, request_id))
+            .copied()
+        else {
+            return Ok(CancelResult::NotFound);
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.pending_cancels.lock().await.insert(wire_id, tx);
+
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::CancelTransfer {
+                handle,
+                request_id: wire_id,
+            },
+        };
+        let sent = match encode_framed(&message) {
+            Ok(encoded) => self.frame_tx.send(encoded).await.is_ok(),
+            Err(e) => {
+                warn!("Failed to encode CancelTransfer: {}", e);
+                false
+            }
+        };
+        if !sent {
+            self.pending_cancels.lock().await.remove(&wire_id);
+            return Err(anyhow!("Failed to send CancelTransfer"));
+        }
+
+        match tokio::time::timeout(CANCEL_TIMEOUT, rx).await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(_)) => Err(anyhow!("Transfer channel closed before cancel response")),
+            Err(_) => {
+                self.pending_cancels.lock().await.remove(&wire_id);
+                Err(anyhow!("Timed out waiting for cancel response"))
+            }
+        }
+    }
+
+    /// Drop the bookkeeping of a finished transfer
+    async fn forget(&self, wire_id: RequestId, handle: DeviceHandle, caller_id: RequestId) {
+        self.pending.lock().await.remove(&wire_id);
+        let mut wire_ids = self.wire_ids.lock().await;
+        if wire_ids.get(&(handle, caller_id)) == Some(&wire_id) {
+            wire_ids.remove(&(handle, caller_id));
+        }
+    }
+
+    /// Write queued frames to the stream until every handle is dropped
+    async fn writer_task(
+        mut send: SendStream,
+        mut frame_rx: mpsc::Receiver<Vec<u8>>,
+        closed: Arc<AtomicBool>,
+    ) {
+        while let Some(frame) = frame_rx.recv().await {
+            if let Err(e) = protocol::write_framed_async(&mut send, &frame).await {
+                warn!("Transfer channel write failed: {:#}", e);
+                closed.store(true, Ordering::Release);
+                return;
+            }
+        }
+        let _ = send.finish();
+    }
+
+    /// Resolve pending transfers as their responses arrive
+    async fn reader_task(
+        mut recv: RecvStream,
+        pending: PendingResponses,
+        pending_cancels: PendingCancels,
+        closed: Arc<AtomicBool>,
+    ) {
+        loop {
+            let bytes = match protocol::read_framed_async(&mut recv).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    debug!("Transfer channel read ended: {}", e);
+                    break;
+                }
+            };
+
+            let message: Message = match decode_framed(&bytes) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("Failed to decode transfer channel frame: {}", e);
+                    continue;
+                }
+            };
+
+            if let Err(e) = validate_version(&message.version) {
+                warn!("Protocol version mismatch on transfer channel: {}", e);
+                break;
+            }
+
+            match message.payload {
+                MessagePayload::TransferComplete { response } => {
+                    match pending.lock().await.remove(&response.id) {
+                        Some(tx) => {
+                            let _ = tx.send(response);
+                        }
+                        None => warn!(
+                            "Transfer channel response for unknown request {:?}",
+                            response.id
+                        ),
+                    }
+                }
+                MessagePayload::CancelTransferResponse { request_id, result } => {
+                    if let Some(tx) = pending_cancels.lock().await.remove(&request_id) {
+                        let _ = tx.send(result);
+                    }
+                }
+                other => warn!("Unexpected message on transfer channel: {:?}", other),
+            }
+        }
+
+        // Fail everything still waiting; dropping the senders wakes the callers
+        closed.store(true, Ordering::Release);
+        pending.lock().await.clear();
+        pending_cancels.lock().await.clear();
+    }
+}
@@ -0,0 +1,96 @@
+//! Device reservations on the command line and in the TUI
+//!
+//! Parses the `--from`/`--until` times of `--reserve` and renders the
+//! bookings returned by `ListReservationsRequest` in local time.
+
+use anyhow::{Context, Result};
+use jiff::civil::DateTime;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
+use protocol::Reservation;
+
+/// Parse a reservation time: `now`, an instant with an offset or zone, or a
+/// wall-clock time in `timezone`
+pub fn parse_time(time: &str, timezone: &TimeZone) -> Result<u64> {
+    let instant = if time == "now" {
+        Timestamp::now()
+    } else if let Ok(instant) = time.parse::<Timestamp>() {
+        instant
+    } else if let Ok(zoned) = time.parse::<Zoned>() {
+        zoned.timestamp()
+    } else {
+        let local: DateTime = time
+            .parse()
+            .with_context(|| format!("Invalid time '{}', expected e.g. 2026-12-24T10:00", time))?;
+        local.to_zoned(timezone.clone())?.timestamp()
+    };
+    u64::try_from(instant.as_second()).with_context(|| format!("Time '{}' is before 1970", time))
+}
+
+/// Render a Unix time in `timezone`
+pub fn format_time(unix_secs: u64, timezone: &TimeZone) -> String {
+    let at =
+        Timestamp::from_second(unix_secs.try_into().unwrap_or(i64::MAX)).unwrap_or(Timestamp::MAX);
+    at.to_zoned(timezone.clone())
+        .strftime("%a %Y-%m-%d %H:%M %Z")
+        .to_string()
+}
+
+/// Render a reservation as one line: id, device, holder, period and note
+pub fn format_reservation(reservation: &Reservation, timezone: &TimeZone) -> String {
+    let mut line = format!(
+        "#{:<4} device {:<4} {:<20} {} - {}",
+        reservation.id.0,
+        reservation.device_id.0,
+        reservation.holder_label(),
+        format_time(reservation.starts_at, timezone),
+        format_time(reservation.ends_at, timezone)
+    );
+    if let Some(note) = &reservation.note {
+        line.push_str("  ");
+        line.push_str(note);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{DeviceId, ReservationId};
+
+    #[test]
+    fn test_parse_time_accepts_local_and_absolute_times() {
+        let helsinki = TimeZone::get("Europe/Helsinki").unwrap();
+
+        // Wall-clock times are in the given zone (UTC+3 in summer)
+        assert_eq!(
+            parse_time("2026-07-01T12:00", &helsinki).unwrap(),
+            parse_time("2026-07-01T09:00Z", &helsinki).unwrap()
+        );
+        assert_eq!(
+            parse_time("2026-07-01T12:00+03:00[Europe/Helsinki]", &TimeZone::UTC).unwrap(),
+            1_782_896_400
+        );
+        assert!(parse_time("now", &helsinki).unwrap() > 1_782_896_400 - 365 * 86_400);
+        assert!(parse_time("tomorrow", &helsinki).is_err());
+    }
+
+    #[test]
+    fn test_format_reservation() {
+        let reservation = Reservation {
+            id: ReservationId(7),
+            device_id: DeviceId(2),
+            holder: "ab".repeat(32),
+            holder_name: Some("lab-pc".to_string()),
+            starts_at: 1_782_896_400,
+            ends_at: 1_782_903_600,
+            note: Some("firmware tests".to_string()),
+        };
+
+        assert_eq!(
+            format_reservation(&reservation, &TimeZone::UTC),
+            "#7    device 2    lab-pc               Wed 2026-07-01 09:00 UTC - \
+             Wed 2026-07-01 11:00 UTC  firmware tests"
+        );
+    }
+}
@@ -6,7 +6,7 @@
 use crate::network::{ConnectionQuality, ConnectionState, HealthMetrics, HealthState};
 use common::{MetricsSnapshot, TransferMetrics};
 use iroh::PublicKey as EndpointId;
-use protocol::{DeviceHandle, DeviceId, DeviceInfo};
+use protocol::{DeviceHandle, DeviceId, DeviceInfo, Reservation, ServerMetricsSummary};
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::sync::Arc;
@@ -88,6 +88,10 @@ pub struct ServerInfo {
     pub metrics: Arc<TransferMetrics>,
     /// Connection health metrics (optional, updated periodically)
     pub health: Option<HealthMetrics>,
+    /// Metrics reported by the server (polled with GetMetricsRequest)
+    pub server_metrics: Option<ServerMetricsSummary>,
+    /// Current and upcoming device reservations on the server
+    pub reservations: Vec<Reservation>,
 }
 
 /// Information about a remote device with local status
@@ -125,6 +129,12 @@ pub enum InputMode {
     ConfirmQuit,
     /// Showing QR code for client EndpointId
     QrCode,
+    /// Showing a remote device's descriptors (`lsusb -v` view)
+    Descriptors {
+        title: String,
+        text: String,
+        scroll: u16,
+    },
 }
 
 /// User action to be processed by the main loop
@@ -144,6 +154,8 @@ pub enum AppAction {
     DetachDevice(EndpointId, DeviceHandle),
     /// Refresh device list from server
     RefreshDevices(EndpointId),
+    /// Fetch and show the descriptors of a remote device
+    ShowDescriptors(EndpointId, DeviceId),
     /// Add a new server by EndpointId string
     AddServer(String),
     /// Connection state changed
@@ -254,6 +266,8 @@ impl App {
                     error: None,
                     metrics: Arc::new(TransferMetrics::new()),
                     health: None,
+                    server_metrics: None,
+                    reservations: Vec::new(),
                 },
             );
             self.server_order.push(endpoint_id);
@@ -301,6 +315,54 @@ impl App {
         }
     }
 
+    /// Use the transfer metrics recorded by the network connection for a server
+    pub fn set_server_transfer_metrics(
+        &mut self,
+        endpoint_id: &EndpointId,
+        metrics: Arc<TransferMetrics>,
+    ) {
+        if let Some(server) = self.servers.get_mut(endpoint_id) {
+            server.metrics = metrics;
+        }
+    }
+
+    /// Update the metrics summary reported by a server
+    pub fn update_server_reported_metrics(
+        &mut self,
+        endpoint_id: &EndpointId,
+        metrics: ServerMetricsSummary,
+    ) {
+        if let Some(server) = self.servers.get_mut(endpoint_id) {
+            server.server_metrics = Some(metrics);
+        }
+    }
+
+    /// Get the metrics summary reported by the selected server
+    pub fn selected_server_reported_metrics(&self) -> Option<&ServerMetricsSummary> {
+        self.selected_server()
+            .and_then(|s| s.server_metrics.as_ref())
+    }
+
+    /// Update the reservations listed by a server
+    pub fn update_reservations(
+        &mut self,
+        endpoint_id: &EndpointId,
+        reservations: Vec<Reservation>,
+    ) {
+        if let Some(server) = self.servers.get_mut(endpoint_id) {
+            server.reservations = reservations;
+        }
+    }
+
+    /// Get the current or next reservation of a device on the selected server
+    pub fn device_reservation(&self, device_id: DeviceId) -> Option<&Reservation> {
+        self.selected_server()?
+            .reservations
+            .iter()
+            .filter(|reservation| reservation.device_id == device_id)
+            .min_by_key(|reservation| reservation.starts_at)
+    }
+
     /// Get metrics for a server
     pub fn get_server_metrics(&self, endpoint_id: &EndpointId) -> Option<MetricsSnapshot> {
         self.servers
@@ -413,6 +475,9 @@ impl App {
             server.status = status;
             if status == ServerStatus::Connected {
                 server.error = None;
+            } else {
+                server.server_metrics = None;
+                server.reservations.clear();
             }
         }
     }
@@ -775,6 +840,18 @@ impl App {
         }
     }
 
+    /// Handle 'v' key (view descriptors of the selected device)
+    pub fn handle_show_descriptors(&mut self) -> AppAction {
+        if self.active_pane == ActivePane::Devices {
+            if let (Some(server_id), Some(device)) =
+                (self.selected_server_id(), self.selected_device())
+            {
+                return AppAction::ShowDescriptors(server_id, device.info.id);
+            }
+        }
+        AppAction::None
+    }
+
     /// Handle input in AddServer mode
     pub fn handle_add_server_input(&mut self, c: char) {
         if let InputMode::AddServer { input } = &mut self.input_mode {
@@ -816,6 +893,23 @@ impl App {
         self.input_mode = InputMode::QrCode;
     }
 
+    /// Show a device's descriptors
+    pub fn show_descriptors(&mut self, title: String, text: String) {
+        self.input_mode = InputMode::Descriptors {
+            title,
+            text,
+            scroll: 0,
+        };
+    }
+
+    /// Scroll the descriptor view by `lines` (negative scrolls up)
+    pub fn scroll_descriptors(&mut self, lines: i32) {
+        if let InputMode::Descriptors { text, scroll, .. } = &mut self.input_mode {
+            let max = text.lines().count().saturating_sub(1) as i32;
+            *scroll = (*scroll as i32 + lines).clamp(0, max) as u16;
+        }
+    }
+
     /// Show quit confirmation
     pub fn show_quit_confirm(&mut self) {
         self.input_mode = InputMode::ConfirmQuit;
@@ -891,6 +985,28 @@ mod tests {
         assert_eq!(server.name, Some("Test Server".to_string()));
     }
 
+    #[test]
+    fn test_server_reported_metrics() {
+        let mut app = App::new(mock_endpoint_id());
+        let server_id = mock_endpoint_id();
+        app.add_server(server_id, None);
+        app.update_server_status(&server_id, ServerStatus::Connected);
+
+        app.update_server_reported_metrics(
+            &server_id,
+            ServerMetricsSummary {
+                total: Default::default(),
+                devices: vec![],
+                clients: vec![],
+            },
+        );
+        assert!(app.selected_server_reported_metrics().is_some());
+
+        // Stale server metrics are dropped when the connection goes away
+        app.update_server_status(&server_id, ServerStatus::Disconnected);
+        assert!(app.selected_server_reported_metrics().is_none());
+    }
+
     #[test]
     fn test_navigate() {
         let endpoint_id = mock_endpoint_id();
@@ -931,4 +1047,23 @@ mod tests {
         app.toggle_pane();
         assert_eq!(app.active_pane, ActivePane::Servers);
     }
+
+    #[test]
+    fn test_descriptor_scroll() {
+        let mut app = App::new(mock_endpoint_id());
+        app.show_descriptors("Device 1".to_string(), "a\nb\nc".to_string());
+
+        app.scroll_descriptors(1);
+        app.scroll_descriptors(10);
+        assert!(matches!(
+            app.input_mode,
+            InputMode::Descriptors { scroll: 2, .. }
+        ));
+
+        app.scroll_descriptors(-10);
+        assert!(matches!(
+            app.input_mode,
+            InputMode::Descriptors { scroll: 0, .. }
+        ));
+    }
 }
@@ -61,6 +61,7 @@ impl EventHandler {
             InputMode::Help => self.handle_help_mode(app, key),
             InputMode::ConfirmQuit => self.handle_confirm_quit_mode(app, key),
             InputMode::QrCode => self.handle_qr_code_mode(app, key),
+            InputMode::Descriptors { .. } => self.handle_descriptors_mode(app, key),
         }
     }
 
@@ -95,6 +96,7 @@ impl EventHandler {
             KeyCode::Char('a') => app.handle_attach_or_add(),
             KeyCode::Char('d') => app.handle_disconnect(),
             KeyCode::Char('r') => app.handle_refresh(),
+            KeyCode::Char('v') => app.handle_show_descriptors(),
 
             // Help
             KeyCode::Char('?') => {
@@ -171,6 +173,21 @@ impl EventHandler {
             _ => AppAction::None,
         }
     }
+
+    /// Handle key events in the descriptor view
+    fn handle_descriptors_mode(&self, app: &mut App, key: KeyEvent) -> AppAction {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('v') | KeyCode::Enter | KeyCode::Char('q') => {
+                app.cancel_input();
+            }
+            KeyCode::Up | KeyCode::Char('k') => app.scroll_descriptors(-1),
+            KeyCode::Down | KeyCode::Char('j') => app.scroll_descriptors(1),
+            KeyCode::PageUp => app.scroll_descriptors(-10),
+            KeyCode::PageDown | KeyCode::Char(' ') => app.scroll_descriptors(10),
+            _ => {}
+        }
+        AppAction::None
+    }
 }
 
 /// Async event handler for use with tokio
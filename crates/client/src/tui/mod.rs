@@ -37,7 +37,7 @@ use ratatui::{Terminal, backend::CrosstermBackend};
 use std::io::{self, Stdout};
 use std::sync::Arc;
 use tokio::sync::mpsc;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 use crate::config::ClientConfig;
 use crate::network::ConnectionState;
@@ -68,6 +68,12 @@ pub enum TuiMessage {
     StatusMessage(String),
     /// Health metrics update for a server
     HealthUpdate(EndpointId, crate::network::HealthMetrics),
+    /// Metrics summary reported by a server
+    ServerMetricsUpdate(EndpointId, protocol::ServerMetricsSummary),
+    /// Descriptors of a device, rendered for display (title, text)
+    DescriptorsReceived(String, String),
+    /// Current and upcoming reservations listed by a server
+    ReservationsReceived(EndpointId, Vec<protocol::Reservation>),
 }
 
 /// TUI runner that manages the terminal and event loop
@@ -146,6 +152,8 @@ impl TuiRunner {
 
         // Spawn health metrics update task
         self.spawn_health_update_task();
+        // Spawn server metrics polling task
+        self.spawn_server_metrics_task();
 
         // Create health update interval (every 2 seconds for UI responsiveness)
         let mut health_interval = tokio::time::interval(std::time::Duration::from_secs(2));
@@ -173,6 +181,23 @@ impl TuiRunner {
                             // Interrupt data is handled directly by SocketBridge, not TUI
                             // This is high-frequency data that shouldn't be processed here
                         }
+                        DeviceNotification::QuotaStatus { quotas } => {
+                            let summary: Vec<String> =
+                                quotas.iter().map(ToString::to_string).collect();
+                            if !summary.is_empty() {
+                                self.app.set_status(format!("Quotas: {}", summary.join("; ")));
+                            }
+                        }
+                        DeviceNotification::ReservationChanged { update } => {
+                            self.app.set_status(format!(
+                                "Reservation {} of device {} by {} {}",
+                                update.reservation.id.0,
+                                update.reservation.device_id.0,
+                                update.reservation.holder_label(),
+                                update.event
+                            ));
+                            self.spawn_refresh_reservations(endpoint_id);
+                        }
                     }
                 }
                 // Process any pending messages from async tasks
@@ -251,6 +276,39 @@ impl TuiRunner {
         });
     }
 
+    /// Spawn a background task that polls each connected server for its metrics
+    fn spawn_server_metrics_task(&self) {
+        let client = self.client.clone();
+        let tx = self.message_tx.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+            loop {
+                interval.tick().await;
+                for endpoint_id in client.connected_servers().await {
+                    match client.get_server_metrics(endpoint_id).await {
+                        Ok(metrics) => {
+                            if tx
+                                .send(TuiMessage::ServerMetricsUpdate(endpoint_id, metrics))
+                                .await
+                                .is_err()
+                            {
+                                return;
+                            }
+                        }
+                        Err(e) => {
+                            debug!(
+                                "Failed to fetch metrics from {}: {:#}",
+                                truncate_id(&endpoint_id),
+                                e
+                            );
+                        }
+                    }
+                }
+            }
+        });
+    }
+
     /// Handle TUI message from async task
     fn handle_message(&mut self, msg: TuiMessage) {
         match msg {
@@ -311,6 +369,16 @@ impl TuiRunner {
             TuiMessage::HealthUpdate(endpoint_id, health) => {
                 self.app.update_server_health(&endpoint_id, health);
             }
+            TuiMessage::ServerMetricsUpdate(endpoint_id, metrics) => {
+                self.app
+                    .update_server_reported_metrics(&endpoint_id, metrics);
+            }
+            TuiMessage::ReservationsReceived(endpoint_id, reservations) => {
+                self.app.update_reservations(&endpoint_id, reservations);
+            }
+            TuiMessage::DescriptorsReceived(title, text) => {
+                self.app.show_descriptors(title, text);
+            }
         }
     }
 
@@ -376,6 +444,18 @@ impl TuiRunner {
                 }
                 self.spawn_refresh_devices(endpoint_id);
             }
+            AppAction::ShowDescriptors(endpoint_id, device_id) => {
+                let device_info = self
+                    .app
+                    .devices
+                    .get(&endpoint_id)
+                    .and_then(|devices| devices.iter().find(|d| d.info.id == device_id))
+                    .map(|device| device.info.clone());
+                if let Some(device_info) = device_info {
+                    self.app.set_status("Reading descriptors...".to_string());
+                    self.spawn_fetch_descriptors(endpoint_id, device_info);
+                }
+            }
             AppAction::AddServer(server_str) => {
                 // Try to parse as connection URL first (p2p-usb://connect/<endpoint_id>)
                 let endpoint_str = qr::parse_connection_url(&server_str)
@@ -385,8 +465,18 @@ impl TuiRunner {
                 match endpoint_str.parse::<EndpointId>() {
                     Ok(endpoint_id) => {
                         self.app.add_server(endpoint_id, None);
-                        self.app
-                            .set_status(format!("Added server {}", truncate_id(&endpoint_id)));
+                        // An invite enrolls this client on its first connection
+                        if let Some(token) = qr::parse_invite_token(&server_str) {
+                            self.client.add_allowed_server(endpoint_id).await;
+                            self.client.set_invite(endpoint_id, token.to_string()).await;
+                            self.app.set_status(format!(
+                                "Added server {} with invite",
+                                truncate_id(&endpoint_id)
+                            ));
+                        } else {
+                            self.app
+                                .set_status(format!("Added server {}", truncate_id(&endpoint_id)));
+                        }
                     }
                     Err(e) => {
                         self.app.set_status(format!("Invalid EndpointId: {}", e));
@@ -397,6 +487,9 @@ impl TuiRunner {
                 ConnectionState::Connected => {
                     self.app
                         .update_server_status(&endpoint_id, ServerStatus::Connected);
+                    if let Some(metrics) = self.client.transfer_metrics(endpoint_id).await {
+                        self.app.set_server_transfer_metrics(&endpoint_id, metrics);
+                    }
                     self.app
                         .set_status(format!("Connected to {}", truncate_id(&endpoint_id)));
                 }
@@ -416,6 +509,14 @@ impl TuiRunner {
                         delay.as_secs_f32()
                     ));
                 }
+                ConnectionState::AwaitingApproval => {
+                    self.app
+                        .update_server_status(&endpoint_id, ServerStatus::Connecting);
+                    self.app.set_status(format!(
+                        "Waiting for {} to approve this client...",
+                        truncate_id(&endpoint_id)
+                    ));
+                }
             },
         }
         Ok(())
@@ -442,6 +543,7 @@ impl TuiRunner {
                             warn!("Failed to list devices: {}", e);
                         }
                     }
+                    send_reservations(&client, endpoint_id, &tx).await;
                 }
                 Err(e) => {
                     let _ = tx
@@ -534,7 +636,8 @@ impl TuiRunner {
 
             // Create device proxy
             let device_proxy =
-                match IrohClient::create_device_proxy(client, endpoint_id, device_info).await {
+                match IrohClient::create_device_proxy(client, endpoint_id, device_info, None).await
+                {
                     Ok(proxy) => proxy,
                     Err(e) => {
                         let _ = tx
@@ -596,6 +699,33 @@ impl TuiRunner {
         });
     }
 
+    /// Spawn async task to fetch and render a device's descriptors
+    fn spawn_fetch_descriptors(&self, endpoint_id: EndpointId, device_info: DeviceInfo) {
+        let client = self.client.clone();
+        let tx = self.message_tx.clone();
+
+        tokio::spawn(async move {
+            match client.get_descriptors(endpoint_id, device_info.id).await {
+                Ok(descriptors) => {
+                    let title = format!(
+                        "Device {} ({:04x}:{:04x})",
+                        device_info.id.0, device_info.vendor_id, device_info.product_id
+                    );
+                    let text = crate::lsusb::format_verbose(&device_info, &descriptors);
+                    let _ = tx.send(TuiMessage::DescriptorsReceived(title, text)).await;
+                }
+                Err(e) => {
+                    let _ = tx
+                        .send(TuiMessage::StatusMessage(format!(
+                            "Failed to read descriptors: {}",
+                            e
+                        )))
+                        .await;
+                }
+            }
+        });
+    }
+
     /// Spawn async task to refresh device list
     fn spawn_refresh_devices(&self, endpoint_id: EndpointId) {
         let client = self.client.clone();
@@ -617,10 +747,38 @@ impl TuiRunner {
                         .await;
                 }
             }
+            send_reservations(&client, endpoint_id, &tx).await;
+        });
+    }
+
+    /// Spawn async task to refresh the reservations of a server
+    fn spawn_refresh_reservations(&self, endpoint_id: EndpointId) {
+        let client = self.client.clone();
+        let tx = self.message_tx.clone();
+
+        tokio::spawn(async move {
+            send_reservations(&client, endpoint_id, &tx).await;
         });
     }
 }
 
+/// Fetch a server's reservations for the device list
+async fn send_reservations(
+    client: &IrohClient,
+    endpoint_id: EndpointId,
+    tx: &mpsc::Sender<TuiMessage>,
+) {
+    match client.list_reservations(endpoint_id, None).await {
+        Ok(reservations) => {
+            let _ = tx
+                .send(TuiMessage::ReservationsReceived(endpoint_id, reservations))
+                .await;
+        }
+        // Servers older than protocol 1.12 have no reservations
+        Err(e) => debug!("Failed to list reservations: {}", e),
+    }
+}
+
 impl Drop for TuiRunner {
     fn drop(&mut self) {
         // Restore terminal state
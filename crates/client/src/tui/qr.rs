@@ -19,9 +19,22 @@ pub fn generate_connection_url(endpoint_id: &EndpointId) -> String {
 /// Parse EndpointId from connection URL
 ///
 /// Returns Some(endpoint_id_string) if the URL matches the expected format,
-/// None otherwise.
+/// None otherwise. A query such as `?invite=<token>` is ignored.
 pub fn parse_connection_url(url: &str) -> Option<&str> {
-    url.strip_prefix(URL_SCHEME)
+    let rest = url.strip_prefix(URL_SCHEME)?;
+    Some(rest.split_once('?').map_or(rest, |(endpoint, _)| endpoint))
+}
+
+/// Parse the pairing invite token from a connection URL
+///
+/// Servers hand out `p2p-usb://connect/<endpoint>?invite=<token>` to enroll
+/// a client without exchanging EndpointIds by hand.
+pub fn parse_invite_token(url: &str) -> Option<&str> {
+    let (_, query) = url.strip_prefix(URL_SCHEME)?.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("invite="))
+        .filter(|token| !token.is_empty())
 }
 
 /// Generate QR code as lines of text for terminal display
@@ -171,6 +184,22 @@ mod tests {
         assert!(invalid.is_none());
     }
 
+    #[test]
+    fn test_parse_invite_url() {
+        let endpoint_id = mock_endpoint_id();
+        let url = format!("{}?invite=00ff", generate_connection_url(&endpoint_id));
+
+        assert_eq!(
+            parse_connection_url(&url),
+            Some(endpoint_id.to_string().as_str())
+        );
+        assert_eq!(parse_invite_token(&url), Some("00ff"));
+        assert_eq!(
+            parse_invite_token(&generate_connection_url(&endpoint_id)),
+            None
+        );
+    }
+
     #[test]
     fn test_generate_qr_lines() {
         let endpoint_id = mock_endpoint_id();
@@ -13,6 +13,9 @@ use ratatui::{
 use super::app::{ActivePane, App, DeviceStatus, InputMode, ServerStatus, ToastType};
 use super::qr;
 use crate::network::{ConnectionQuality, HealthState};
+use crate::reservations;
+use protocol::Reservation;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Colors used in the UI
 mod colors {
@@ -27,6 +30,7 @@ mod colors {
     pub const AVAILABLE: Color = Color::White;
     pub const BUSY: Color = Color::Yellow;
     pub const ATTACHING: Color = Color::Yellow;
+    pub const RESERVED: Color = Color::Magenta;
 
     pub const ACTIVE_BORDER: Color = Color::Cyan;
     pub const INACTIVE_BORDER: Color = Color::Gray;
@@ -83,6 +87,13 @@ pub fn render(frame: &mut Frame, app: &App) {
         InputMode::QrCode => {
             render_qr_code_dialog(frame, app);
         }
+        InputMode::Descriptors {
+            title,
+            text,
+            scroll,
+        } => {
+            render_descriptors_dialog(frame, title, text, *scroll);
+        }
         InputMode::Normal => {}
     }
 
@@ -361,6 +372,7 @@ fn render_device_list(frame: &mut Frame, app: &App, area: Rect) {
         frame.render_widget(paragraph, area);
         return;
     }
+    let timezone = jiff::tz::TimeZone::system();
     let items: Vec<ListItem> = devices
         .iter()
         .map(|device| {
@@ -383,7 +395,7 @@ fn render_device_list(frame: &mut Frame, app: &App, area: Rect) {
                 .map(|p| p.as_str())
                 .unwrap_or("Unknown Device");
 
-            let line = Line::from(vec![
+            let mut spans = vec![
                 Span::styled(
                     format!("{} ", status_icon),
                     Style::default().fg(status_color),
@@ -394,9 +406,15 @@ fn render_device_list(frame: &mut Frame, app: &App, area: Rect) {
                 ),
                 Span::styled(format!("{} ", vid_pid), Style::default().fg(Color::Cyan)),
                 Span::raw(name),
-            ]);
+            ];
+            if let Some(reservation) = app.device_reservation(device.info.id) {
+                spans.push(Span::styled(
+                    format!("  {}", describe_reservation(reservation, &timezone)),
+                    Style::default().fg(colors::RESERVED),
+                ));
+            }
 
-            ListItem::new(line)
+            ListItem::new(Line::from(spans))
         })
         .collect();
 
@@ -427,6 +445,27 @@ fn render_device_list(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_stateful_widget(list, area, &mut state);
 }
 
+/// Describe a device's current or next reservation for its list row
+fn describe_reservation(reservation: &Reservation, timezone: &jiff::tz::TimeZone) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if reservation.is_active_at(now) {
+        format!(
+            "booked by {} until {}",
+            reservation.holder_label(),
+            reservations::format_time(reservation.ends_at, timezone)
+        )
+    } else {
+        format!(
+            "booked by {} from {}",
+            reservation.holder_label(),
+            reservations::format_time(reservation.starts_at, timezone)
+        )
+    }
+}
+
 /// Render the metrics panel
 fn render_metrics_panel(frame: &mut Frame, app: &App, area: Rect) {
     
@@ -449,10 +488,11 @@ fn render_metrics_panel(frame: &mut Frame, app: &App, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Percentage(25), // Transfer stats
-            Constraint::Percentage(25), // Latency
-            Constraint::Percentage(25), // Throughput
-            Constraint::Percentage(25), // Connection quality
+            Constraint::Percentage(20), // Transfer stats
+            Constraint::Percentage(20), // Latency
+            Constraint::Percentage(20), // Throughput
+            Constraint::Percentage(20), // Connection quality
+            Constraint::Percentage(20), // Server metrics
         ])
         .split(area);
 
@@ -665,6 +705,57 @@ fn render_metrics_panel(frame: &mut Frame, app: &App, area: Rect) {
             .title_style(Style::default().fg(Color::White)),
     );
     frame.render_widget(quality_block, chunks[3]);
+
+    render_server_metrics(frame, app, chunks[4]);
+}
+
+/// Render the selected server's own view of this client's traffic
+fn render_server_metrics(frame: &mut Frame, app: &App, area: Rect) {
+    let server_lines = if let Some(summary) = app.selected_server_reported_metrics() {
+        // The server only includes our own entry in `clients`
+        let ours = summary.clients.first().map(|c| &c.metrics);
+        let (latency, loss) = ours
+            .map(|m| (m.latency.format_avg(), m.format_loss_rate()))
+            .unwrap_or(("-".into(), "-".into()));
+        vec![
+            Line::from(vec![
+                Span::styled("Latency: ", Style::default().fg(Color::DarkGray)),
+                Span::styled(latency, Style::default().fg(Color::Cyan)),
+            ]),
+            Line::from(vec![
+                Span::styled("Loss: ", Style::default().fg(Color::DarkGray)),
+                Span::styled(loss, Style::default().fg(Color::White)),
+            ]),
+            Line::from(vec![
+                Span::styled("Total TX: ", Style::default().fg(Color::DarkGray)),
+                Span::styled(
+                    summary.total.format_throughput_tx(),
+                    Style::default().fg(Color::Magenta),
+                ),
+            ]),
+            Line::from(vec![
+                Span::styled("Uptime: ", Style::default().fg(Color::DarkGray)),
+                Span::styled(
+                    summary.total.format_uptime(),
+                    Style::default().fg(Color::White),
+                ),
+            ]),
+        ]
+    } else {
+        vec![Line::from(Span::styled(
+            "No data",
+            Style::default().fg(Color::DarkGray),
+        ))]
+    };
+
+    let server_block = Paragraph::new(server_lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(colors::INACTIVE_BORDER))
+            .title(" Server View ")
+            .title_style(Style::default().fg(Color::White)),
+    );
+    frame.render_widget(server_block, area);
 }
 
 /// Render the bottom help bar
@@ -674,13 +765,14 @@ fn render_help_bar(frame: &mut Frame, app: &App, area: Rect) {
             if app.active_pane == ActivePane::Servers {
                 "Tab: Switch | j/k: Navigate | c: Connect | d: Disconnect | a: Add | r: Refresh | Q: QR | q: Quit | ?: Help"
             } else {
-                "Tab: Switch | j/k: Navigate | a: Attach | d: Detach | r: Refresh | Q: QR | q: Quit | ?: Help"
+                "Tab: Switch | j/k: Navigate | a: Attach | d: Detach | v: Descriptors | r: Refresh | Q: QR | q: Quit | ?: Help"
             }
         }
         InputMode::AddServer { .. } => "Enter: Confirm | Esc: Cancel",
         InputMode::Help => "Press ? or Esc to close",
         InputMode::ConfirmQuit => "y: Quit | n: Cancel",
         InputMode::QrCode => "Press Esc or Q to close",
+        InputMode::Descriptors { .. } => "j/k: Scroll | PgUp/PgDn: Page | Esc: Close",
     };
 
     let paragraph = Paragraph::new(help_text)
@@ -802,6 +894,10 @@ fn render_help_overlay(frame: &mut Frame) {
             Span::styled("  d            ", Style::default().fg(Color::Cyan)),
             Span::raw("Detach selected device"),
         ]),
+        Line::from(vec![
+            Span::styled("  v            ", Style::default().fg(Color::Cyan)),
+            Span::raw("Show descriptors (lsusb -v)"),
+        ]),
         Line::from(""),
         Line::from(Span::styled(
             "General",
@@ -874,6 +970,26 @@ fn render_help_overlay(frame: &mut Frame) {
     frame.render_widget(paragraph, area);
 }
 
+/// Render a device's descriptors in a scrollable dialog
+fn render_descriptors_dialog(frame: &mut Frame, title: &str, text: &str, scroll: u16) {
+    let area = centered_rect(80, 85, frame.area());
+
+    // Clear the area first
+    frame.render_widget(Clear, area);
+
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::default()
+                .title(format!(" {} ", title))
+                .title_style(Style::default().add_modifier(Modifier::BOLD))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(colors::ACTIVE_BORDER)),
+        )
+        .scroll((scroll, 0));
+
+    frame.render_widget(paragraph, area);
+}
+
 /// Render the quit confirmation dialog
 fn render_quit_dialog(frame: &mut Frame) {
     let area = centered_rect(40, 15, frame.area());
@@ -24,6 +24,7 @@ use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 use std::time::{Duration, Instant};
+use tokio::sync::Notify;
 use tracing::{debug, error, trace, warn};
 
 /// Adaptive jitter buffer for handling variable network conditions
@@ -270,6 +271,8 @@ pub struct EndpointReceiveBuffer {
     reports: Mutex<VecDeque<ReceivedReport>>,
     /// Condition variable for waiting threads
     data_available: Condvar,
+    /// Wakes async waiters in `take`
+    data_notify: Notify,
     /// Next expected sequence number (for gap detection)
     next_expected_seq: AtomicU64,
     /// Highest sequence number received
@@ -280,6 +283,8 @@ pub struct EndpointReceiveBuffer {
     total_received: AtomicU64,
     /// Reports served to kernel
     total_served: AtomicU64,
+    /// `total_received` when the last acknowledgement was generated
+    acked_received: AtomicU64,
     /// Sequence gaps detected
     gaps_detected: AtomicU64,
     /// Checksum verification failures
@@ -351,11 +356,13 @@ impl EndpointReceiveBuffer {
             endpoint,
             reports: Mutex::new(VecDeque::with_capacity(max_size)),
             data_available: Condvar::new(),
+            data_notify: Notify::new(),
             next_expected_seq: AtomicU64::new(0),
             highest_seq: AtomicU64::new(0),
             last_contiguous_seq: AtomicU64::new(0),
             total_received: AtomicU64::new(0),
             total_served: AtomicU64::new(0),
+            acked_received: AtomicU64::new(0),
             gaps_detected: AtomicU64::new(0),
             checksum_failures: AtomicU64::new(0),
             max_size,
@@ -390,6 +397,7 @@ impl EndpointReceiveBuffer {
         self.missing_sequences.lock().unwrap().clear();
         self.missing_timestamps.lock().unwrap().clear();
         self.data_available.notify_all();
+        self.data_notify.notify_waiters();
         debug!(
             "Deactivated receive buffer for endpoint 0x{:02x}, cleared {} reports",
             self.endpoint, count
@@ -513,6 +521,7 @@ impl EndpointReceiveBuffer {
 
         // Notify waiting threads
         self.data_available.notify_one();
+        self.data_notify.notify_one();
 
         // Acknowledge every 10 reports or on gap
         let should_ack = gap_detected || (seq > prev_highest && seq % 10 == 0);
@@ -634,6 +643,23 @@ impl EndpointReceiveBuffer {
         report
     }
 
+    /// Wait for the next report for the kernel
+    ///
+    /// Returns None once the buffer is deactivated. Cancel-safe: a report is
+    /// only removed when it is returned.
+    pub async fn take(&self) -> Option<ReceivedReport> {
+        loop {
+            let notified = self.data_notify.notified();
+            if let Some(report) = self.try_take() {
+                return Some(report);
+            }
+            if !self.is_active() {
+                return None;
+            }
+            notified.await;
+        }
+    }
+
     /// Get buffer statistics
     pub fn stats(&self) -> ReceiveBufferStats {
         ReceiveBufferStats {
@@ -655,6 +681,13 @@ impl EndpointReceiveBuffer {
         self.highest_seq.load(Ordering::Relaxed)
     }
 
+    /// Reports received since the previous call (for InterruptAck)
+    pub fn reports_since_ack(&self) -> u32 {
+        let total = self.total_received.load(Ordering::Relaxed);
+        let previous = self.acked_received.swap(total, Ordering::Relaxed);
+        total.saturating_sub(previous) as u32
+    }
+
     /// Get the current buffer fill ratio (0.0 - 1.0)
     pub fn fill_ratio(&self) -> f64 {
         let len = self.reports.lock().unwrap().len();
@@ -981,7 +1014,9 @@ impl InterruptReceiveManager {
 
     /// Process incoming InterruptData message
     ///
-    /// Returns (gap_detected, should_send_ack, ack_seq) if an ack should be sent
+    /// Returns (gap_detected, ack_seq) if an ack should be sent. The ack
+    /// covers the last contiguous sequence, so reports still missing stay
+    /// available for retransmission on the server.
     pub fn process_interrupt_data(
         &self,
         device_handle: u32,
@@ -1006,7 +1041,7 @@ impl InterruptReceiveManager {
 
         if should_ack {
             let buffer = manager.get(endpoint)?;
-            Some((gap_detected, buffer.highest_acked_seq()))
+            Some((gap_detected, buffer.last_contiguous_seq()))
         } else {
             None
         }
@@ -1150,6 +1185,46 @@ mod tests {
         assert_eq!(r4.sequence, 2);
     }
 
+    #[tokio::test]
+    async fn test_async_take() {
+        let buffer = Arc::new(EndpointReceiveBuffer::new(0x81, 100));
+        buffer.activate(0);
+
+        let waiter = tokio::spawn({
+            let buffer = buffer.clone();
+            async move { buffer.take().await }
+        });
+        tokio::task::yield_now().await;
+        buffer.store(make_report(0, 0x81, vec![4], 1000));
+        assert_eq!(waiter.await.unwrap().unwrap().sequence, 0);
+
+        // Deactivation wakes waiters without a report
+        let waiter = tokio::spawn({
+            let buffer = buffer.clone();
+            async move { buffer.take().await }
+        });
+        tokio::task::yield_now().await;
+        buffer.deactivate();
+        assert!(waiter.await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ack_covers_contiguous_reports() {
+        let manager = InterruptReceiveManager::new(100);
+        manager.get_or_create(1).activate_endpoint(0x81, 0);
+
+        let report = |seq: u64| {
+            let checksum = compute_interrupt_checksum(seq, 0x81, &[], 1000);
+            manager.process_interrupt_data(1, 0x81, seq, vec![], 1000, checksum)
+        };
+        for seq in 0..4 {
+            report(seq);
+        }
+        // Seq 4 is lost; the gap is reported and not acknowledged past it
+        assert_eq!(report(5), Some((true, 3)));
+        assert_eq!(report(10), Some((true, 3)));
+    }
+
     #[test]
     fn test_device_manager() {
         let manager = DeviceReceiveBufferManager::new(1, 100);
@@ -44,6 +44,7 @@ use tracing::{debug, info, warn};
 
 use super::GlobalDeviceId;
 use super::device::VirtualDevice;
+use super::interrupt_receive_buffer::InterruptReceiveManager;
 use super::socket_bridge::SocketBridge;
 use crate::network::device_proxy::DeviceProxy;
 
@@ -66,6 +67,8 @@ pub struct LinuxVirtualUsbManager {
     /// Each bit represents a port: bit 0 = port 8, bit 7 = port 15
     /// 1 = allocated, 0 = free
     ss_ports: Arc<RwLock<u8>>,
+    /// Receive buffers for streamed interrupt endpoints (shared with bridges)
+    interrupt_buffers: Arc<InterruptReceiveManager>,
 }
 
 impl LinuxVirtualUsbManager {
@@ -74,7 +77,7 @@ impl LinuxVirtualUsbManager {
     /// # Errors
     ///
     /// Returns error if vhci_hcd module is not loaded or accessible.
-    pub async fn new() -> Result<Self> {
+    pub async fn new(interrupt_buffers: Arc<InterruptReceiveManager>) -> Result<Self> {
         // Check if vhci_hcd is available
         let vhci_path = Self::find_vhci_device()?;
 
@@ -95,6 +98,7 @@ impl LinuxVirtualUsbManager {
             vhci_path,
             hs_ports: Arc::new(RwLock::new(hs_bitmap)),
             ss_ports: Arc::new(RwLock::new(ss_bitmap)),
+            interrupt_buffers,
         })
     }
 
@@ -221,9 +225,14 @@ impl LinuxVirtualUsbManager {
         let devid = handle.0;
 
         // Create socket bridge for USB/IP protocol
-        let (socket_bridge, vhci_fd) = SocketBridge::new(device_proxy.clone(), devid, port)
-            .await
-            .context("Failed to create socket bridge")?;
+        let (socket_bridge, vhci_fd) = SocketBridge::new(
+            device_proxy.clone(),
+            self.interrupt_buffers.clone(),
+            devid,
+            port,
+        )
+        .await
+        .context("Failed to create socket bridge")?;
 
         let socket_bridge = Arc::new(socket_bridge);
 
@@ -701,6 +710,7 @@ mod tests {
             vhci_path: PathBuf::from("/nonexistent/test/vhci"),
             hs_ports: Arc::new(RwLock::new(0)),
             ss_ports: Arc::new(RwLock::new(0)),
+            interrupt_buffers: Arc::new(InterruptReceiveManager::default()),
         }
     }
 
@@ -28,6 +28,21 @@ use crate::network::device_proxy::DeviceProxy;
 // Re-export interrupt receive buffer types
 pub use interrupt_receive_buffer::{AggregatedIntegrityMetrics, InterruptReceiveManager};
 
+/// Flow-control message owed to the server for a streamed endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterruptFeedback {
+    /// Acknowledge every report up to `last_seq`
+    Ack {
+        last_seq: u64,
+        reports_received: u32,
+    },
+    /// Request retransmission of reports lost on the way
+    Nack {
+        missing_sequences: Vec<u64>,
+        last_contiguous_seq: u64,
+    },
+}
+
 /// Unique device identifier across all connected servers
 ///
 /// Since DeviceHandle is server-assigned and different servers may assign
@@ -119,7 +134,7 @@ impl VirtualUsbManager {
         #[cfg(target_os = "linux")]
         {
             Ok(Self {
-                inner: linux::LinuxVirtualUsbManager::new().await?,
+                inner: linux::LinuxVirtualUsbManager::new(interrupt_manager.clone()).await?,
                 interrupt_manager,
             })
         }
@@ -243,10 +258,8 @@ impl VirtualUsbManager {
     /// Process incoming interrupt data from the server
     ///
     /// Verifies checksum and stores in the receive buffer.
-    /// Returns (stored_ok, checksum_valid) tuple.
-    ///
-    /// - stored_ok: true if data was stored (buffer active and checksum valid)
-    /// - checksum_valid: true if checksum verification passed
+    /// Returns (checksum_valid, feedback) where feedback is the ACK or NACK
+    /// to send back to the server, if one is due.
     pub fn process_interrupt_data(
         &self,
         device_handle: u32,
@@ -255,7 +268,7 @@ impl VirtualUsbManager {
         data: Vec<u8>,
         timestamp_us: u64,
         checksum: u32,
-    ) -> (bool, bool) {
+    ) -> (bool, Option<InterruptFeedback>) {
         use protocol::integrity::verify_interrupt_checksum;
 
         // First, verify checksum before delegating to manager
@@ -264,24 +277,43 @@ impl VirtualUsbManager {
 
         if !checksum_valid {
             // Don't even try to store corrupted data
-            return (false, false);
+            return (false, None);
         }
 
         // Process through the manager (creates buffer if needed)
-        let result = self.interrupt_manager.process_interrupt_data(
+        let Some((gap_detected, last_seq)) = self.interrupt_manager.process_interrupt_data(
             device_handle,
             endpoint,
             sequence,
             data,
             timestamp_us,
             checksum,
-        );
-
-        // If we got Some(_), the data was processed
-        // The stored_ok depends on whether the buffer was active
-        let stored_ok = result.is_some();
-
-        (stored_ok, checksum_valid)
+        ) else {
+            return (true, None);
+        };
+        let Some(buffer) = self
+            .interrupt_manager
+            .get(device_handle)
+            .and_then(|manager| manager.get(endpoint))
+        else {
+            return (true, None);
+        };
+
+        let nack = gap_detected
+            .then(|| buffer.generate_nack_info())
+            .flatten()
+            .map(
+                |(missing_sequences, last_contiguous_seq)| InterruptFeedback::Nack {
+                    missing_sequences,
+                    last_contiguous_seq,
+                },
+            );
+        let feedback = nack.unwrap_or_else(|| InterruptFeedback::Ack {
+            last_seq,
+            reports_received: buffer.reports_since_ack(),
+        });
+
+        (true, Some(feedback))
     }
 
     /// Get aggregated integrity metrics across all devices
@@ -15,7 +15,11 @@
 //! 2. Pass vhci_fd to vhci_hcd via sysfs attach
 //! 3. Keep bridge_fd alive for ongoing CMD_SUBMIT/RET_SUBMIT communication
 //! 4. Kernel sends CMD_SUBMIT for USB transfers, we respond with RET_SUBMIT
-//! 5. Kernel sends CMD_UNLINK to cancel pending transfers, we respond with RET_UNLINK
+//! 5. Kernel sends CMD_UNLINK to cancel pending transfers; we cancel them on the
+//!    server (protocol 1.3+) and respond with RET_UNLINK
+//! 6. Interrupt IN endpoints are streamed by the server when it supports it;
+//!    their URBs are answered from the interrupt receive buffer instead of
+//!    a transfer round trip each
 //!
 //! ## Message Types
 //!
@@ -31,14 +35,17 @@
 //!
 //! See `usbip_protocol.rs` for detailed message format documentation.
 
+use super::interrupt_receive_buffer::{EndpointReceiveBuffer, InterruptReceiveManager};
 use super::usbip_protocol::{
     UsbIpCmdSubmit, UsbIpCmdUnlink, UsbIpCommand, UsbIpHeader, UsbIpIsoPacketDescriptor,
-    UsbIpMessage, UsbIpRetSubmit, UsbIpRetUnlink, optimal_urb_buffer_size,
-    usb_response_to_usbip, usb_response_to_usbip_full, usbip_to_usb_request,
+    UsbIpMessage, UsbIpRetSubmit, UsbIpRetUnlink, filter_config_descriptor,
+    is_config_descriptor_request, optimal_urb_buffer_size, special_request, usb_response_to_usbip,
+    usb_response_to_usbip_full, usbip_to_usb_request,
 };
 use crate::network::device_proxy::DeviceProxy;
 use anyhow::{Context, Result, anyhow};
-use std::collections::{HashMap, HashSet};
+use protocol::{CancelResult, TransferResult, TransferType, UsbResponse};
+use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::os::unix::net::UnixStream;
@@ -47,6 +54,23 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::{Mutex as AsyncMutex, RwLock, oneshot};
 use tracing::{debug, error, info, trace};
 
+/// Transfers that can still be unlinked
+///
+/// Maps seqnum -> unlink sender; sending the CMD_UNLINK seqnum asks the
+/// transfer task to cancel and answer with RET_UNLINK.
+type PendingTransfers = Arc<RwLock<HashMap<u32, oneshot::Sender<u32>>>>;
+
+/// Interrupt IN endpoints the server was asked to stream
+struct InterruptStreams {
+    /// Receive buffers fed by `InterruptData` notifications
+    buffers: Arc<InterruptReceiveManager>,
+    /// Receive buffer per endpoint (None = server refused, poll instead)
+    endpoints: RwLock<HashMap<u8, Option<Arc<EndpointReceiveBuffer>>>>,
+}
+
+/// RET_UNLINK status for a URB that was cancelled before completing
+const UNLINK_CANCELLED: i32 = -104; // -ECONNRESET
+
 /// Socket bridge for USB/IP protocol
 ///
 /// Bridges vhci_hcd kernel driver (via Unix socketpair) to DeviceProxy (via QUIC)
@@ -68,8 +92,7 @@ pub struct SocketBridge {
     /// Running flag
     running: Arc<AtomicBool>,
     /// Pending transfers tracker for CMD_UNLINK cancellation support
-    /// Maps seqnum -> cancellation sender (sending () triggers cancellation)
-    pending_transfers: Arc<RwLock<HashMap<u32, oneshot::Sender<()>>>>,
+    pending_transfers: PendingTransfers,
     /// Optimal URB buffer size based on device speed
     optimal_buffer_size: usize,
     /// Per-endpoint locks for serializing interrupt IN transfers
@@ -77,20 +100,10 @@ pub struct SocketBridge {
     /// This prevents race conditions where multiple concurrent reads from the
     /// same interrupt endpoint cause duplicate or lost HID reports
     interrupt_endpoint_locks: Arc<RwLock<HashMap<u8, Arc<AsyncMutex<()>>>>>,
-    /// Set of seqnums that have been unlinked via CMD_UNLINK
-    /// Used to suppress late RET_SUBMIT responses for cancelled transfers
-    /// The kernel removes URBs from its pending list immediately on CMD_UNLINK,
-    /// so any RET_SUBMIT arriving after that causes "cannot find urb" errors
-    ///
-    /// Note: This set is periodically cleaned when it exceeds MAX_UNLINKED_SEQNUMS
-    /// to prevent unbounded memory growth from completed transfers that were unlinked late.
-    unlinked_seqnums: Arc<RwLock<HashSet<u32>>>,
+    /// Streamed interrupt IN endpoints
+    interrupt_streams: Arc<InterruptStreams>,
 }
 
-/// Maximum number of unlinked seqnums to track before cleanup
-/// After this limit, old entries are cleared since transfers should have long completed
-const MAX_UNLINKED_SEQNUMS: usize = 100;
-
 impl SocketBridge {
     /// Create a new socketpair-based socket bridge
     ///
@@ -102,6 +115,7 @@ impl SocketBridge {
     /// and immediately starts CMD_SUBMIT/RET_SUBMIT communication on the socket.
     pub async fn new(
         device_proxy: Arc<DeviceProxy>,
+        interrupt_buffers: Arc<InterruptReceiveManager>,
         devid: u32,
         port: u8,
     ) -> Result<(Self, RawFd)> {
@@ -148,7 +162,10 @@ impl SocketBridge {
             pending_transfers: Arc::new(RwLock::new(HashMap::new())),
             optimal_buffer_size: buffer_size,
             interrupt_endpoint_locks: Arc::new(RwLock::new(HashMap::new())),
-            unlinked_seqnums: Arc::new(RwLock::new(HashSet::new())),
+            interrupt_streams: Arc::new(InterruptStreams {
+                buffers: interrupt_buffers,
+                endpoints: RwLock::new(HashMap::new()),
+            }),
         };
 
         debug!(
@@ -204,6 +221,8 @@ impl SocketBridge {
     /// Stop the bridge
     pub fn stop(&self) {
         self.running.store(false, Ordering::Release);
+        // Wake transfers waiting for streamed interrupt reports
+        self.interrupt_streams.buffers.remove_device(self.devid);
     }
 
     /// Main bridge loop (concurrent version)
@@ -282,6 +301,19 @@ impl SocketBridge {
                         header.seqnum, header.ep, header.direction, cmd.transfer_buffer_length
                     );
 
+                    // Register the transfer before spawning so a CMD_UNLINK read
+                    // right after this CMD_SUBMIT always finds it
+                    let (unlink_tx, unlink_rx) = oneshot::channel();
+                    rt.block_on(async {
+                        let mut pending = self.pending_transfers.write().await;
+                        pending.insert(header.seqnum, unlink_tx);
+                        trace!(
+                            "Registered pending transfer: seqnum={}, total_pending={}",
+                            header.seqnum,
+                            pending.len()
+                        );
+                    });
+
                     // Spawn async task to handle CMD_SUBMIT concurrently
                     // This allows multiple transfers to be in-flight simultaneously,
                     // which is crucial for HID devices where key-up must follow key-down quickly
@@ -289,7 +321,7 @@ impl SocketBridge {
                     let write_socket = self.write_socket.clone();
                     let pending_transfers = self.pending_transfers.clone();
                     let interrupt_locks = self.interrupt_endpoint_locks.clone();
-                    let unlinked_seqnums = self.unlinked_seqnums.clone();
+                    let interrupt_streams = self.interrupt_streams.clone();
                     let running = self.running.clone();
                     let devid = self.devid;
 
@@ -299,7 +331,8 @@ impl SocketBridge {
                             write_socket,
                             pending_transfers,
                             interrupt_locks,
-                            unlinked_seqnums,
+                            interrupt_streams,
+                            unlink_rx,
                             running,
                             devid,
                             header,
@@ -323,6 +356,7 @@ impl SocketBridge {
             }
         }
 
+        self.interrupt_streams.buffers.remove_device(self.devid);
         info!("Socket bridge stopped for port {}", self.port);
         Ok(())
     }
@@ -332,12 +366,19 @@ impl SocketBridge {
     /// This is the async version that runs in spawned tasks for concurrent processing.
     /// For interrupt IN transfers, we serialize requests per-endpoint to prevent race
     /// conditions where multiple concurrent USB reads cause duplicate or lost HID reports.
+    ///
+    /// The transfer is already registered in `pending_transfers`; `unlink_rx`
+    /// yields the CMD_UNLINK seqnum if the kernel unlinks it. Like the usbip
+    /// stub driver, a transfer cancelled on the server is answered with
+    /// RET_UNLINK(-ECONNRESET) only, while one that completed first gets its
+    /// RET_SUBMIT followed by RET_UNLINK(0).
     async fn handle_cmd_submit_async(
         device_proxy: Arc<DeviceProxy>,
         socket: Arc<std::sync::Mutex<UnixStream>>,
-        pending_transfers: Arc<RwLock<HashMap<u32, oneshot::Sender<()>>>>,
+        pending_transfers: PendingTransfers,
         interrupt_locks: Arc<RwLock<HashMap<u8, Arc<AsyncMutex<()>>>>>,
-        unlinked_seqnums: Arc<RwLock<HashSet<u32>>>,
+        interrupt_streams: Arc<InterruptStreams>,
+        mut unlink_rx: oneshot::Receiver<u32>,
         running: Arc<AtomicBool>,
         devid: u32,
         header: UsbIpHeader,
@@ -353,7 +394,9 @@ impl SocketBridge {
         // and transfer type from setup packet or endpoint characteristics.
         // For simplicity, we serialize ALL IN transfers on high-numbered endpoints (>= 0x81)
         // as these are typically interrupt IN endpoints for HID devices.
-        let is_interrupt_in = header.direction == 1 && header.ep > 0;
+        // Isochronous streams rely on several URBs being queued, so they are
+        // never serialized.
+        let is_interrupt_in = header.direction == 1 && header.ep > 0 && cmd.number_of_packets == 0;
         let endpoint_addr: u8 = if is_interrupt_in {
             (header.ep | 0x80) as u8 // Add IN direction bit
         } else {
@@ -380,55 +423,183 @@ impl SocketBridge {
             None
         };
 
-        // Acquire the guard (must be done after lock is stored in endpoint_lock)
+        // Acquire the guard (must be done after lock is stored in endpoint_lock).
+        // A transfer unlinked while waiting never reaches the server.
         let _endpoint_guard = if let Some(ref lock) = endpoint_lock {
             trace!(
                 "Acquiring endpoint lock: seqnum={}, ep=0x{:02x}",
                 seqnum, endpoint_addr
             );
-            Some(lock.lock().await)
+            tokio::select! {
+                guard = lock.lock() => Some(guard),
+                unlink = &mut unlink_rx => {
+                    if let Ok(unlink_seqnum) = unlink
+                        && running.load(Ordering::Acquire)
+                    {
+                        debug!("Unlinked queued transfer: seqnum={}", seqnum);
+                        Self::send_ret_unlink(&socket, devid, unlink_seqnum, UNLINK_CANCELLED)?;
+                    }
+                    return Ok(());
+                }
+            }
         } else {
             None
         };
 
-        // Create cancellation channel for this transfer
-        let (cancel_tx, _cancel_rx) = oneshot::channel::<()>();
+        // Reset, clear-halt, set-configuration and set-interface are
+        // performed as device operations when the server supports them
+        let operation = special_request(&header, &cmd);
 
-        // Register this transfer as pending (for CMD_UNLINK support)
-        {
-            let mut pending = pending_transfers.write().await;
-            pending.insert(seqnum, cancel_tx);
-            trace!(
-                "Registered pending transfer: seqnum={}, total_pending={}",
-                seqnum,
-                pending.len()
-            );
-        }
+        // With only some interfaces attached the configuration descriptor is
+        // read in full and filtered, so wTotalLength matches what we return
+        let shared_interfaces = device_proxy
+            .interfaces()
+            .filter(|_| is_config_descriptor_request(&header, &cmd))
+            .map(<[u8]>::to_vec);
 
         // Convert USB/IP to our protocol
-        let usb_request = usbip_to_usb_request(&device_proxy, &header, &cmd, data).await?;
+        let mut usb_request = match usbip_to_usb_request(&device_proxy, &header, &cmd, data).await {
+            Ok(request) => request,
+            Err(e) => {
+                if pending_transfers.write().await.remove(&seqnum).is_none()
+                    && let Ok(unlink_seqnum) = unlink_rx.try_recv()
+                {
+                    Self::send_ret_unlink(&socket, devid, unlink_seqnum, UNLINK_CANCELLED)?;
+                }
+                return Err(e);
+            }
+        };
+        let request_id = usb_request.id;
+        if shared_interfaces.is_some()
+            && let TransferType::Control { data, .. } = &mut usb_request.transfer
+        {
+            data.resize(u16::MAX as usize, 0);
+        }
 
         trace!(
             "Submitting USB request: seqnum={}, id={}, ep=0x{:02x}, serialized={}",
             seqnum, usb_request.id.0, endpoint_addr, is_interrupt_in
         );
 
-        // Submit to device proxy (async)
-        let result = device_proxy.submit_transfer(usb_request).await;
+        // Streamed interrupt endpoints are answered from the receive buffer.
+        // Nothing is pending on the server, so an unlink needs no cancel.
+        let stream = if is_interrupt_in && operation.is_none() {
+            Self::interrupt_stream(&device_proxy, &interrupt_streams, devid, endpoint_addr).await
+        } else {
+            None
+        };
+        let streamed = match stream {
+            Some(buffer) => tokio::select! {
+                report = buffer.take() => {
+                    if report.is_none() && !running.load(Ordering::Acquire) {
+                        return Ok(());
+                    }
+                    report
+                }
+                unlink = &mut unlink_rx => {
+                    if let Ok(unlink_seqnum) = unlink
+                        && running.load(Ordering::Acquire)
+                    {
+                        debug!("Unlinked streamed transfer: seqnum={}", seqnum);
+                        Self::send_ret_unlink(&socket, devid, unlink_seqnum, UNLINK_CANCELLED)?;
+                    }
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
 
-        // Remove from pending transfers
-        {
-            let mut pending = pending_transfers.write().await;
-            pending.remove(&seqnum);
+        let (result, late_unlink) = if let Some(report) = streamed {
             trace!(
-                "Removed pending transfer: seqnum={}, remaining={}",
+                "Serving streamed report: seqnum={}, ep=0x{:02x}, seq={}, len={}",
                 seqnum,
-                pending.len()
+                endpoint_addr,
+                report.sequence,
+                report.data.len()
             );
-        }
+            let response = UsbResponse {
+                id: request_id,
+                result: TransferResult::Success {
+                    data: report.data,
+                    checksum: None,
+                },
+            };
+            (Ok(response), None)
+        } else {
+            // Submit to device proxy (async), watching for CMD_UNLINK
+            let submit = async {
+                if let Some(operation) = operation {
+                    match device_proxy.device_operation(operation).await {
+                        Ok(result) => {
+                            debug!("Device operation {:?}: {:?}", operation, result);
+                            let result = match result {
+                                Ok(()) => TransferResult::Success {
+                                    data: Vec::new(),
+                                    checksum: None,
+                                },
+                                Err(error) => TransferResult::Error { error },
+                            };
+                            return Ok(UsbResponse {
+                                id: request_id,
+                                result,
+                            });
+                        }
+                        Err(e) => debug!(
+                            "Sending {:?} as a control transfer instead: {:#}",
+                            operation, e
+                        ),
+                    }
+                }
+                device_proxy.submit_transfer(usb_request).await
+            };
+            tokio::pin!(submit);
+            tokio::select! {
+                result = &mut submit => (result, None),
+                unlink = &mut unlink_rx => {
+                    let Ok(unlink_seqnum) = unlink else {
+                        return Ok(());
+                    };
+                    match device_proxy.cancel_transfer(request_id).await {
+                        Ok(CancelResult::NotFound) => {
+                            // Completed on the server before the cancel arrived
+                            debug!("Unlink of seqnum={} lost the race with completion", seqnum);
+                            (submit.await, Some(unlink_seqnum))
+                        }
+                        outcome => {
+                            match outcome {
+                                Ok(_) => info!("Cancelled transfer on server: seqnum={}", seqnum),
+                                Err(e) => debug!(
+                                    "Server cannot cancel seqnum={}, dropping response: {:#}",
+                                    seqnum, e
+                                ),
+                            }
+                            if running.load(Ordering::Acquire) {
+                                Self::send_ret_unlink(&socket, devid, unlink_seqnum, UNLINK_CANCELLED)?;
+                            }
+                            // Let the transfer finish so the proxy releases its state;
+                            // the kernel has already given the URB back
+                            let _ = submit.await;
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        };
 
         // Handle transfer result
-        let usb_response = result.context("Failed to submit transfer to device proxy")?;
+        let usb_response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                let unlinked = match pending_transfers.write().await.remove(&seqnum) {
+                    Some(_) => late_unlink,
+                    None => unlink_rx.try_recv().ok().or(late_unlink),
+                };
+                if let Some(unlink_seqnum) = unlinked {
+                    Self::send_ret_unlink(&socket, devid, unlink_seqnum, UNLINK_CANCELLED)?;
+                }
+                return Err(e).context("Failed to submit transfer to device proxy");
+            }
+        };
 
         // Convert response back to USB/IP
         let is_isochronous = cmd.number_of_packets > 0;
@@ -443,6 +614,30 @@ impl SocketBridge {
             }
         };
 
+        // vhci_hcd expects one descriptor per submitted packet, even when the
+        // whole transfer failed
+        if is_isochronous && converted.iso_packets.is_empty() {
+            converted.iso_packets = cmd
+                .iso_packets
+                .iter()
+                .map(|p| UsbIpIsoPacketDescriptor {
+                    offset: p.offset,
+                    length: p.length,
+                    actual_length: 0,
+                    status: converted.ret.status as u32,
+                })
+                .collect();
+            converted.ret.number_of_packets = cmd.number_of_packets;
+            converted.ret.error_count = cmd.number_of_packets;
+        }
+
+        if let Some(interfaces) = &shared_interfaces
+            && converted.ret.status == 0
+        {
+            converted.data = filter_config_descriptor(&converted.data, interfaces);
+            converted.ret.actual_length = converted.data.len() as u32;
+        }
+
         // Clamp response data to kernel's requested buffer size
         if header.direction == 1 && converted.data.len() > max_data_len {
             trace!(
@@ -472,19 +667,19 @@ impl SocketBridge {
             return Ok(());
         }
 
-        // Check if this seqnum was unlinked while we were processing
-        // If so, suppress the response to avoid "cannot find urb" kernel errors
-        {
-            let mut unlinked = unlinked_seqnums.write().await;
-            if unlinked.remove(&seqnum) {
-                debug!(
-                    "Suppressing late RET_SUBMIT for unlinked seqnum={}, remaining unlinked: {}",
-                    seqnum,
-                    unlinked.len()
-                );
-                return Ok(());
-            }
-        }
+        // Deregister and write RET_SUBMIT under the pending lock, so a
+        // CMD_UNLINK either reaches this task or sees the transfer completed
+        // after its RET_SUBMIT was written
+        let mut pending = pending_transfers.write().await;
+        let unlink_seqnum = match pending.remove(&seqnum) {
+            Some(_) => late_unlink,
+            None => unlink_rx.try_recv().ok().or(late_unlink),
+        };
+        trace!(
+            "Removed pending transfer: seqnum={}, remaining={}",
+            seqnum,
+            pending.len()
+        );
 
         // Double-check running flag before write (it may have changed during async operations)
         if !running.load(Ordering::Acquire) {
@@ -497,17 +692,68 @@ impl SocketBridge {
 
         // Send RET_SUBMIT back to vhci_hcd
         Self::send_ret_submit_async(
-            socket,
+            socket.clone(),
             devid,
             &header,
             converted.ret,
             converted.data,
             converted.iso_packets,
         )?;
+        drop(pending);
+
+        // The URB completed before it could be cancelled
+        if let Some(unlink_seqnum) = unlink_seqnum {
+            Self::send_ret_unlink(&socket, devid, unlink_seqnum, 0)?;
+        }
 
         Ok(())
     }
 
+    /// Receive buffer of a streamed interrupt IN endpoint
+    ///
+    /// The first transfer on an endpoint asks the server to stream it. If the
+    /// server refuses (older server, not an interrupt endpoint), the endpoint
+    /// keeps using regular transfers. Callers hold the endpoint lock, so each
+    /// endpoint is set up once.
+    async fn interrupt_stream(
+        device_proxy: &DeviceProxy,
+        interrupt_streams: &InterruptStreams,
+        devid: u32,
+        endpoint: u8,
+    ) -> Option<Arc<EndpointReceiveBuffer>> {
+        if let Some(buffer) = interrupt_streams.endpoints.read().await.get(&endpoint) {
+            return buffer.clone();
+        }
+
+        let buffer = match device_proxy.start_interrupt_stream(endpoint, 0).await {
+            Ok(info) => {
+                info!(
+                    "Streaming interrupt endpoint 0x{:02x} of device {} (interval={}ms)",
+                    endpoint, devid, info.poll_interval_ms
+                );
+                let buffer = interrupt_streams
+                    .buffers
+                    .get_or_create(devid)
+                    .get_or_create(endpoint);
+                buffer.activate(info.start_sequence);
+                Some(buffer)
+            }
+            Err(e) => {
+                debug!(
+                    "Polling interrupt endpoint 0x{:02x} with transfers: {:#}",
+                    endpoint, e
+                );
+                None
+            }
+        };
+        interrupt_streams
+            .endpoints
+            .write()
+            .await
+            .insert(endpoint, buffer.clone());
+        buffer
+    }
+
     /// Send RET_SUBMIT back to vhci_hcd (for async handler)
     ///
     /// IMPORTANT: We buffer the entire message and send it in a single write_all call.
@@ -549,16 +795,16 @@ impl SocketBridge {
         // Write padding (8 bytes)
         message.extend_from_slice(&[0u8; RET_SUBMIT_PADDING]);
 
-        // Write ISO packet descriptors if any
-        for iso_packet in &iso_packets {
-            iso_packet.write_to(&mut message)?;
-        }
-
         // Write response data if any
         if !data.is_empty() {
             message.extend_from_slice(&data);
         }
 
+        // Write ISO packet descriptors if any (after the data, like usbip stub)
+        for iso_packet in &iso_packets {
+            iso_packet.write_to(&mut message)?;
+        }
+
         debug!(
             "RET_SUBMIT: seqnum={}, status={}, actual_length={}, data_len={}, total_msg_len={}, header_bytes={:02x?}",
             request_header.seqnum,
@@ -654,7 +900,7 @@ impl SocketBridge {
                     .context("Failed to read CMD_SUBMIT payload")?;
 
                 let mut cursor = std::io::Cursor::new(&cmd_buf);
-                let cmd = UsbIpCmdSubmit::read_from(&mut cursor)?;
+                let mut cmd = UsbIpCmdSubmit::read_from(&mut cursor)?;
 
                 // Read data if OUT transfer (direction = 0)
                 let mut data = Vec::new();
@@ -665,6 +911,16 @@ impl SocketBridge {
                         .context("Failed to read transfer data")?;
                 }
 
+                // ISO descriptors follow the transfer buffer
+                if cmd.number_of_packets > 0 {
+                    let mut iso_buf =
+                        vec![0u8; cmd.number_of_packets as usize * UsbIpIsoPacketDescriptor::SIZE];
+                    socket
+                        .read_exact(&mut iso_buf)
+                        .context("Failed to read ISO packet descriptors")?;
+                    cmd.read_iso_packets(&mut std::io::Cursor::new(&iso_buf))?;
+                }
+
                 Ok(UsbIpMessage::Submit { header, cmd, data })
             }
             UsbIpCommand::CmdUnlink => {
@@ -696,14 +952,11 @@ impl SocketBridge {
 
     /// Handle CMD_UNLINK by cancelling a pending transfer (blocking version)
     ///
-    /// This method looks up the pending transfer by seqnum_unlink and cancels it
-    /// if still in progress. Per USB/IP protocol:
-    /// - If transfer is found and cancelled: return status 0 (success)
-    /// - If transfer already completed: return status -ENOENT (-2)
-    ///
-    /// IMPORTANT: We always track unlinked seqnums to suppress late RET_SUBMIT responses.
-    /// The kernel removes URBs from its pending list on unlink, so any RET_SUBMIT arriving
-    /// after that causes "cannot find urb of seqnum" errors and disconnects the device.
+    /// Hands the CMD_UNLINK seqnum to the task running the transfer, which
+    /// cancels it on the server and sends RET_UNLINK once the outcome is known.
+    /// If the transfer is no longer pending its RET_SUBMIT has already been
+    /// written, so we answer RET_UNLINK(0) right away, like the usbip stub
+    /// driver does for URBs that completed before the unlink.
     fn handle_cmd_unlink_blocking(
         &self,
         rt: &tokio::runtime::Handle,
@@ -717,71 +970,35 @@ impl SocketBridge {
             header.seqnum, seqnum_unlink
         );
 
-        // ALWAYS track unlinked seqnums to suppress late responses
-        // This prevents "cannot find urb" kernel errors when server responses arrive late
-        rt.block_on(async {
-            let mut unlinked = self.unlinked_seqnums.write().await;
-
-            // Clean up if we have too many tracked seqnums (prevents memory leak)
-            // After MAX_UNLINKED_SEQNUMS, transfers should have long completed
-            if unlinked.len() >= MAX_UNLINKED_SEQNUMS {
-                debug!(
-                    "Clearing {} stale unlinked seqnums (exceeded limit of {})",
-                    unlinked.len(),
-                    MAX_UNLINKED_SEQNUMS
-                );
-                unlinked.clear();
-            }
-
-            unlinked.insert(seqnum_unlink);
-            trace!(
-                "Tracking unlinked seqnum {}, total unlinked: {}",
-                seqnum_unlink,
-                unlinked.len()
-            );
-        });
-
-        // Try to find and cancel the pending transfer
-        let cancel_tx = rt.block_on(async {
+        // Send while holding the lock so the transfer task observes the
+        // unlink when it deregisters
+        let handed_off = rt.block_on(async {
             let mut pending = self.pending_transfers.write().await;
-            pending.remove(&seqnum_unlink)
+            pending
+                .remove(&seqnum_unlink)
+                .is_some_and(|tx| tx.send(header.seqnum).is_ok())
         });
 
-        let status = match cancel_tx {
-            Some(tx) => {
-                // Found the pending transfer - send cancellation signal
-                // Note: send() may fail if receiver was already dropped (transfer completed
-                // between our lookup and now), which is fine - we still report success
-                let _ = tx.send(());
-                info!(
-                    "Cancelled pending transfer: seqnum_unlink={} (CMD_UNLINK seqnum={})",
-                    seqnum_unlink, header.seqnum
-                );
-                0 // Success: transfer was cancelled
-            }
-            None => {
-                // Transfer not found - may be in flight to server
-                // We still need to suppress any late response (tracked above)
-                debug!(
-                    "Transfer in flight or completed: seqnum_unlink={} (CMD_UNLINK seqnum={})",
-                    seqnum_unlink, header.seqnum
-                );
-                -2 // -ENOENT: not found in pending list
-            }
-        };
-
-        // Send RET_UNLINK response (blocking)
-        self.send_ret_unlink_blocking(header.seqnum, status)?;
+        if handed_off {
+            debug!(
+                "Unlinking pending transfer: seqnum_unlink={} (CMD_UNLINK seqnum={})",
+                seqnum_unlink, header.seqnum
+            );
+            return Ok(());
+        }
 
-        trace!(
-            "Sent RET_UNLINK: seqnum={}, status={}",
-            header.seqnum, status
+        debug!(
+            "Transfer already completed: seqnum_unlink={} (CMD_UNLINK seqnum={})",
+            seqnum_unlink, header.seqnum
         );
+        Self::send_ret_unlink(&self.write_socket, self.devid, header.seqnum, 0)?;
+
+        trace!("Sent RET_UNLINK: seqnum={}, status=0", header.seqnum);
 
         Ok(())
     }
 
-    /// Send RET_UNLINK back to vhci_hcd (blocking version)
+    /// Send RET_UNLINK back to vhci_hcd
     ///
     /// Per USB/IP protocol, RET_UNLINK consists of:
     /// - Header (20 bytes): command=0x0004, seqnum, devid, direction=0, ep=0
@@ -791,9 +1008,14 @@ impl SocketBridge {
     ///
     /// IMPORTANT: We buffer the entire message and send it in a single write_all call
     /// to ensure atomicity - either the whole message is sent or nothing is sent.
-    fn send_ret_unlink_blocking(&self, seqnum: u32, status: i32) -> Result<()> {
+    fn send_ret_unlink(
+        socket: &std::sync::Mutex<UnixStream>,
+        devid: u32,
+        seqnum: u32,
+        status: i32,
+    ) -> Result<()> {
         // Build header
-        let header = UsbIpHeader::new(UsbIpCommand::RetUnlink, seqnum, self.devid);
+        let header = UsbIpHeader::new(UsbIpCommand::RetUnlink, seqnum, devid);
 
         // USB/IP header union is always 28 bytes (size of largest member: cmd_submit)
         // RET_UNLINK only uses 4 bytes (status), rest must be padding
@@ -818,13 +1040,12 @@ impl SocketBridge {
             "RET_UNLINK: seqnum={}, status={} ({}), total_msg_len={}",
             seqnum,
             status,
-            if status == 0 { "cancelled" } else { "not found" },
+            if status == 0 { "completed" } else { "cancelled" },
             message.len()
         );
 
         // Send entire message atomically
-        let mut socket = self
-            .write_socket
+        let mut socket = socket
             .lock()
             .map_err(|e| anyhow!("Failed to lock write_socket: {}", e))?;
 
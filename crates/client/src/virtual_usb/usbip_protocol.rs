@@ -21,7 +21,8 @@
 use anyhow::Result;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use protocol::{
-    DeviceSpeed, IsoPacketDescriptor, RequestId, TransferType, UsbRequest, UsbResponse,
+    DeviceOperation, DeviceSpeed, IsoPacketDescriptor, RequestId, TransferType, UsbRequest,
+    UsbResponse,
 };
 use std::io::{Read, Write};
 
@@ -225,6 +226,9 @@ pub struct UsbIpCmdSubmit {
     /// Setup packet for control transfers (8 bytes)
     pub setup: [u8; 8],
     /// ISO packet descriptors (only if number_of_packets > 0)
+    ///
+    /// On the wire these follow the OUT transfer buffer, so they are read
+    /// separately with [`read_iso_packets`](Self::read_iso_packets).
     pub iso_packets: Vec<UsbIpIsoPacketDescriptor>,
 }
 
@@ -248,14 +252,8 @@ impl UsbIpCmdSubmit {
 
         // NO padding - kernel struct is __packed (28 bytes total)
 
-        // Read ISO descriptors if number_of_packets > 0
-        // We must read them to keep the stream in sync, even if we don't support ISO transfers yet
-        let mut iso_packets = Vec::new();
-        if number_of_packets > 0 {
-            for _ in 0..number_of_packets {
-                iso_packets.push(UsbIpIsoPacketDescriptor::read_from(reader)?);
-            }
-        }
+        // ISO descriptors are NOT read here: vhci_hcd sends them after the
+        // OUT transfer buffer (see read_iso_packets)
 
         Ok(Self {
             transfer_flags,
@@ -264,11 +262,24 @@ impl UsbIpCmdSubmit {
             number_of_packets,
             interval,
             setup,
-            iso_packets,
+            iso_packets: Vec::new(),
         })
     }
 
-    /// Write CMD_SUBMIT to a writer (28 bytes total + optional ISO descriptors)
+    /// Read the ISO packet descriptors that follow the transfer buffer
+    ///
+    /// Must be called after the OUT data (if any) has been consumed, otherwise
+    /// the stream falls out of sync.
+    pub fn read_iso_packets<R: Read>(&mut self, reader: &mut R) -> Result<()> {
+        self.iso_packets = (0..self.number_of_packets)
+            .map(|_| UsbIpIsoPacketDescriptor::read_from(reader))
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Write CMD_SUBMIT to a writer (28 bytes total)
+    ///
+    /// ISO descriptors are not included; they go after the transfer buffer.
     pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
         writer.write_u32::<BigEndian>(self.transfer_flags)?;
         writer.write_u32::<BigEndian>(self.transfer_buffer_length)?;
@@ -277,11 +288,6 @@ impl UsbIpCmdSubmit {
         writer.write_u32::<BigEndian>(self.interval)?;
         writer.write_all(&self.setup)?;
 
-        // Write ISO descriptors if any
-        for packet in &self.iso_packets {
-            packet.write_to(writer)?;
-        }
-
         Ok(())
     }
 }
@@ -521,6 +527,95 @@ pub async fn usbip_to_usb_request(
     })
 }
 
+/// Recognise control requests that must be performed as device operations
+///
+/// Like the kernel's usbip stub, CLEAR_FEATURE(ENDPOINT_HALT), SET_INTERFACE,
+/// SET_CONFIGURATION and SET_FEATURE(PORT_RESET) on endpoint 0 are not sent
+/// as raw control transfers: libusb needs the dedicated calls so the server
+/// keeps its claimed interfaces and endpoint state consistent.
+pub fn special_request(header: &UsbIpHeader, cmd: &UsbIpCmdSubmit) -> Option<DeviceOperation> {
+    if header.ep != 0 || cmd.setup == [0u8; 8] {
+        return None;
+    }
+
+    let request_type = cmd.setup[0];
+    let request = cmd.setup[1];
+    let value = u16::from_le_bytes([cmd.setup[2], cmd.setup[3]]);
+    let index = u16::from_le_bytes([cmd.setup[4], cmd.setup[5]]);
+
+    match (request_type, request) {
+        // CLEAR_FEATURE(ENDPOINT_HALT), wIndex = endpoint address
+        (0x02, 0x01) if value == 0 => Some(DeviceOperation::ClearHalt {
+            endpoint: index as u8,
+        }),
+        // SET_INTERFACE, wIndex = interface, wValue = alternate setting
+        (0x01, 0x0B) => Some(DeviceOperation::SetInterface {
+            interface: index as u8,
+            alt_setting: value as u8,
+        }),
+        // SET_CONFIGURATION, wValue = configuration value
+        (0x00, 0x09) => Some(DeviceOperation::SetConfiguration {
+            configuration: value as u8,
+        }),
+        // Hub class SET_FEATURE(PORT_RESET) addressed to the device's port
+        (0x23, 0x03) if value == 4 => Some(DeviceOperation::Reset),
+        _ => None,
+    }
+}
+
+/// Whether a submit is GET_DESCRIPTOR(CONFIGURATION) on endpoint 0
+pub fn is_config_descriptor_request(header: &UsbIpHeader, cmd: &UsbIpCmdSubmit) -> bool {
+    header.ep == 0 && cmd.setup[0] == 0x80 && cmd.setup[1] == 0x06 && cmd.setup[3] == 0x02
+}
+
+/// Strip interfaces that were not attached from a configuration descriptor
+///
+/// Keeps the configuration header, the descriptors of each listed interface
+/// (including its endpoints, class-specific descriptors and any association
+/// descriptor starting at it) and rewrites wTotalLength and bNumInterfaces so
+/// the host only binds drivers to the interfaces it actually owns.
+pub fn filter_config_descriptor(descriptor: &[u8], interfaces: &[u8]) -> Vec<u8> {
+    if descriptor.len() < 9 || descriptor[1] != 0x02 {
+        return descriptor.to_vec();
+    }
+
+    let header_len = (descriptor[0] as usize).clamp(9, descriptor.len());
+    let mut filtered = descriptor[..header_len].to_vec();
+    let mut kept = Vec::new();
+    let mut keep = false;
+    let mut offset = header_len;
+
+    while offset + 2 <= descriptor.len() {
+        let len = descriptor[offset] as usize;
+        if len < 2 || offset + len > descriptor.len() {
+            break;
+        }
+        let desc = &descriptor[offset..offset + len];
+        match desc[1] {
+            // Interface descriptor: bInterfaceNumber
+            0x04 if len >= 3 => {
+                keep = interfaces.contains(&desc[2]);
+                if keep && !kept.contains(&desc[2]) {
+                    kept.push(desc[2]);
+                }
+            }
+            // Interface association descriptor: bFirstInterface
+            0x0B if len >= 3 => keep = interfaces.contains(&desc[2]),
+            _ => {}
+        }
+        if keep {
+            filtered.extend_from_slice(desc);
+        }
+        offset += len;
+    }
+
+    let total = (filtered.len() as u16).to_le_bytes();
+    filtered[2] = total[0];
+    filtered[3] = total[1];
+    filtered[4] = kept.len() as u8;
+    filtered
+}
+
 /// Result from converting UsbResponse to USB/IP format
 pub struct UsbIpConvertedResponse {
     pub ret: UsbIpRetSubmit,
@@ -586,6 +681,7 @@ pub fn usb_response_to_usbip_full(response: &UsbResponse) -> UsbIpConvertedRespo
                 protocol::UsbError::Access => -13,       // EACCES
                 protocol::UsbError::NotFound => -2,      // ENOENT
                 protocol::UsbError::Other { .. } => -5,  // EIO
+                protocol::UsbError::Cancelled => -104,   // ECONNRESET (unlinked)
             };
             let ret = UsbIpRetSubmit::error(errno);
             UsbIpConvertedResponse {
@@ -643,6 +739,150 @@ mod tests {
         assert_eq!(decoded.setup, cmd.setup);
     }
 
+    fn control_submit(setup: [u8; 8]) -> UsbIpCmdSubmit {
+        UsbIpCmdSubmit {
+            transfer_flags: 0,
+            transfer_buffer_length: 0,
+            start_frame: 0,
+            number_of_packets: 0,
+            interval: 0,
+            setup,
+            iso_packets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_special_request_detection() {
+        let header = UsbIpHeader::new(UsbIpCommand::CmdSubmit, 1, 1);
+
+        let clear_halt = control_submit([0x02, 0x01, 0x00, 0x00, 0x81, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            special_request(&header, &clear_halt),
+            Some(DeviceOperation::ClearHalt { endpoint: 0x81 })
+        );
+
+        let set_interface = control_submit([0x01, 0x0B, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            special_request(&header, &set_interface),
+            Some(DeviceOperation::SetInterface {
+                interface: 1,
+                alt_setting: 2
+            })
+        );
+
+        let set_config = control_submit([0x00, 0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            special_request(&header, &set_config),
+            Some(DeviceOperation::SetConfiguration { configuration: 1 })
+        );
+
+        let port_reset = control_submit([0x23, 0x03, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            special_request(&header, &port_reset),
+            Some(DeviceOperation::Reset)
+        );
+    }
+
+    #[test]
+    fn test_special_request_ignores_other_requests() {
+        let header = UsbIpHeader::new(UsbIpCommand::CmdSubmit, 1, 1);
+
+        // GET_DESCRIPTOR goes through as a normal control transfer
+        let get_descriptor = control_submit([0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x12, 0x00]);
+        assert_eq!(special_request(&header, &get_descriptor), None);
+
+        // CLEAR_FEATURE with a feature other than ENDPOINT_HALT
+        let clear_feature = control_submit([0x02, 0x01, 0x01, 0x00, 0x81, 0x00, 0x00, 0x00]);
+        assert_eq!(special_request(&header, &clear_feature), None);
+
+        // Same setup bytes on a non-control endpoint
+        let mut bulk_header = header.clone();
+        bulk_header.ep = 2;
+        let set_config = control_submit([0x00, 0x09, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(special_request(&bulk_header, &set_config), None);
+    }
+
+    #[test]
+    fn test_config_descriptor_request_detection() {
+        let header = UsbIpHeader::new(UsbIpCommand::CmdSubmit, 1, 1);
+
+        let config = control_submit([0x80, 0x06, 0x00, 0x02, 0x00, 0x00, 0xFF, 0x00]);
+        assert!(is_config_descriptor_request(&header, &config));
+
+        let device = control_submit([0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x12, 0x00]);
+        assert!(!is_config_descriptor_request(&header, &device));
+    }
+
+    #[test]
+    fn test_filter_config_descriptor() {
+        #[rustfmt::skip]
+        let descriptor: Vec<u8> = vec![
+            // Configuration: wTotalLength = 57, bNumInterfaces = 2
+            0x09, 0x02, 0x39, 0x00, 0x02, 0x01, 0x00, 0xA0, 0x32,
+            // Interface 0 (HID) + HID descriptor + 1 endpoint
+            0x09, 0x04, 0x00, 0x00, 0x01, 0x03, 0x01, 0x01, 0x00,
+            0x09, 0x21, 0x11, 0x01, 0x00, 0x01, 0x22, 0x3F, 0x00,
+            0x07, 0x05, 0x81, 0x03, 0x08, 0x00, 0x0A,
+            // Interface 1 (mass storage) + 2 endpoints
+            0x09, 0x04, 0x01, 0x00, 0x02, 0x08, 0x06, 0x50, 0x00,
+            0x07, 0x05, 0x82, 0x02, 0x00, 0x02, 0x00,
+            0x07, 0x05, 0x02, 0x02, 0x00, 0x02, 0x00,
+        ];
+        assert_eq!(descriptor.len(), 0x39);
+
+        let filtered = filter_config_descriptor(&descriptor, &[1]);
+        assert_eq!(filtered.len(), 9 + 9 + 7 + 7);
+        assert_eq!(u16::from_le_bytes([filtered[2], filtered[3]]), 32);
+        assert_eq!(filtered[4], 1);
+        assert_eq!(&filtered[9..18], &descriptor[34..43]);
+
+        // Keeping every interface leaves the descriptor unchanged
+        assert_eq!(filter_config_descriptor(&descriptor, &[0, 1]), descriptor);
+    }
+
+    #[test]
+    fn test_cmd_submit_iso_packets_follow_data() {
+        let mut buf = Vec::new();
+        UsbIpCmdSubmit {
+            transfer_flags: 0,
+            transfer_buffer_length: 4,
+            start_frame: 0,
+            number_of_packets: 2,
+            interval: 1,
+            setup: [0; 8],
+            iso_packets: Vec::new(),
+        }
+        .write_to(&mut buf)
+        .unwrap();
+        assert_eq!(buf.len(), UsbIpCmdSubmit::SIZE);
+
+        // OUT data, then one descriptor per packet
+        buf.extend_from_slice(&[1, 2, 3, 4]);
+        for offset in [0, 2] {
+            UsbIpIsoPacketDescriptor {
+                offset,
+                length: 2,
+                actual_length: 0,
+                status: 0,
+            }
+            .write_to(&mut buf)
+            .unwrap();
+        }
+
+        let mut cursor = Cursor::new(buf);
+        let mut cmd = UsbIpCmdSubmit::read_from(&mut cursor).unwrap();
+        assert!(cmd.iso_packets.is_empty());
+
+        let mut data = vec![0u8; cmd.transfer_buffer_length as usize];
+        cursor.read_exact(&mut data).unwrap();
+        assert_eq!(data, vec![1, 2, 3, 4]);
+
+        cmd.read_iso_packets(&mut cursor).unwrap();
+        assert_eq!(cmd.iso_packets.len(), 2);
+        assert_eq!(cmd.iso_packets[1].offset, 2);
+        assert_eq!(cmd.iso_packets[1].length, 2);
+    }
+
     #[test]
     fn test_ret_submit_success() {
         let ret = UsbIpRetSubmit::success(18);
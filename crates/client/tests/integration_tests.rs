@@ -494,6 +494,7 @@ mod usbip_tests {
             (UsbError::Io, -5),
             (UsbError::Access, -13),
             (UsbError::NotFound, -2),
+            (UsbError::Cancelled, -104),
         ];
 
         for (error, expected_errno) in mappings {
@@ -508,6 +509,7 @@ mod usbip_tests {
                 UsbError::Access => -13,
                 UsbError::NotFound => -2,
                 UsbError::Other { .. } => -5,
+                UsbError::Cancelled => -104,
             };
             assert_eq!(errno, expected_errno);
         }
@@ -871,6 +873,10 @@ fn test_mock_device_info_with_empty_optionals() {
         protocol: 0x00,
         speed: DeviceSpeed::High,
         num_configurations: 1,
+        interfaces: Vec::new(),
+        stable_id: "1234:5678@1-1".to_string(),
+        port_numbers: vec![1],
+        parent_hub: None,
     };
 
     // Should serialize/deserialize correctly
@@ -22,6 +22,19 @@ pub enum UsbCommand {
             tokio::sync::oneshot::Sender<Result<protocol::DeviceHandle, protocol::AttachError>>,
     },
 
+    /// Attach some interfaces of a device for client access
+    AttachInterfaces {
+        /// Device ID to attach
+        device_id: protocol::DeviceId,
+        /// Client NodeId (for permission tracking)
+        client_id: String,
+        /// bInterfaceNumber of each interface to claim
+        interfaces: Vec<u8>,
+        /// Channel to send response back
+        response:
+            tokio::sync::oneshot::Sender<Result<protocol::DeviceHandle, protocol::AttachError>>,
+    },
+
     /// Detach a device
     DetachDevice {
         /// Device handle to detach
@@ -40,6 +53,19 @@ pub enum UsbCommand {
         response: tokio::sync::oneshot::Sender<protocol::UsbResponse>,
     },
 
+    /// Cancel an in-flight USB transfer
+    ///
+    /// The response is `true` if the transfer was still pending and will
+    /// complete with `UsbError::Cancelled`.
+    CancelTransfer {
+        /// Device handle the transfer was submitted to
+        handle: protocol::DeviceHandle,
+        /// Request ID of the transfer
+        request_id: protocol::RequestId,
+        /// Channel to send response back
+        response: tokio::sync::oneshot::Sender<bool>,
+    },
+
     /// Reset a USB device
     ResetDevice {
         /// Device handle to reset
@@ -48,6 +74,38 @@ pub enum UsbCommand {
         response: tokio::sync::oneshot::Sender<Result<(), protocol::UsbError>>,
     },
 
+    /// Clear a halt/stall condition on an endpoint
+    ClearHalt {
+        /// Device handle
+        handle: protocol::DeviceHandle,
+        /// Endpoint address (includes direction bit)
+        endpoint: u8,
+        /// Channel to send response back
+        response: tokio::sync::oneshot::Sender<Result<(), protocol::UsbError>>,
+    },
+
+    /// Activate a configuration, re-claiming its interfaces
+    SetConfiguration {
+        /// Device handle
+        handle: protocol::DeviceHandle,
+        /// bConfigurationValue to activate
+        configuration: u8,
+        /// Channel to send response back
+        response: tokio::sync::oneshot::Sender<Result<(), protocol::UsbError>>,
+    },
+
+    /// Select an alternate setting of a claimed interface
+    SetInterface {
+        /// Device handle
+        handle: protocol::DeviceHandle,
+        /// bInterfaceNumber
+        interface: u8,
+        /// bAlternateSetting to select
+        alt_setting: u8,
+        /// Channel to send response back
+        response: tokio::sync::oneshot::Sender<Result<(), protocol::UsbError>>,
+    },
+
     /// Get sharing status for a device
     GetSharingStatus {
         /// Device ID to query
@@ -78,10 +136,71 @@ pub enum UsbCommand {
         response: tokio::sync::oneshot::Sender<protocol::UnlockResult>,
     },
 
+    /// Look up the descriptor details of an interrupt endpoint
+    GetEndpointInfo {
+        /// Device handle
+        handle: protocol::DeviceHandle,
+        /// Endpoint address (includes direction bit)
+        endpoint: u8,
+        /// Channel to send response back
+        response: tokio::sync::oneshot::Sender<Result<EndpointInfo, protocol::UsbError>>,
+    },
+
+    /// Read the full descriptor set of a device
+    GetDescriptors {
+        /// Device ID to query
+        device_id: protocol::DeviceId,
+        /// Channel to send response back
+        response:
+            tokio::sync::oneshot::Sender<Result<protocol::DeviceDescriptors, protocol::UsbError>>,
+    },
+
+    /// Replace the filters deciding which devices are shared
+    ///
+    /// Devices the new filters exclude are removed as if unplugged, and
+    /// connected devices they now include are added. The response carries
+    /// the number of devices removed and added.
+    SetFilters {
+        /// Filter expressions (empty = share all devices)
+        filters: Vec<String>,
+        /// Channel to send response back
+        response: tokio::sync::oneshot::Sender<(usize, usize)>,
+    },
+
+    /// Withhold a device from sharing, or share it again
+    ///
+    /// A withheld device is removed as if unplugged and stays hidden, also
+    /// across replugs, until it is shared again. The response tells whether
+    /// anything changed.
+    SetDeviceShared {
+        /// Device ID to change
+        device_id: protocol::DeviceId,
+        /// Whether clients may see and attach the device
+        shared: bool,
+        /// Channel to send response back
+        response: tokio::sync::oneshot::Sender<Result<bool, protocol::UsbError>>,
+    },
+
+    /// List devices withheld from sharing, as last seen
+    ListWithheldDevices {
+        /// Channel to send response back
+        response: tokio::sync::oneshot::Sender<Vec<protocol::DeviceInfo>>,
+    },
+
     /// Shutdown the USB thread gracefully
     Shutdown,
 }
 
+/// Endpoint descriptor details needed to poll an endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndpointInfo {
+    /// Maximum bytes per service interval (wMaxPacketSize including
+    /// high-bandwidth transactions)
+    pub max_packet_size: u16,
+    /// Polling interval in milliseconds (at least 1)
+    pub interval_ms: u8,
+}
+
 /// USB events from the device manager
 #[derive(Debug, Clone)]
 pub enum UsbEvent {
@@ -152,6 +271,15 @@ impl UsbBridge {
             .map_err(|e| crate::Error::Channel(e.to_string()))
     }
 
+    /// Send a command to the USB thread from a plain OS thread
+    ///
+    /// Must not be called from within the Tokio runtime.
+    pub fn send_command_blocking(&self, cmd: UsbCommand) -> crate::Result<()> {
+        self.cmd_tx
+            .send_blocking(cmd)
+            .map_err(|e| crate::Error::Channel(e.to_string()))
+    }
+
     /// Receive an event from the USB thread
     pub async fn recv_event(&self) -> crate::Result<UsbEvent> {
         self.event_rx
@@ -0,0 +1,719 @@
+//! Device filter expressions
+//!
+//! A single language for server USB filters, device policies and client
+//! auto-attach patterns. Terms are combined with `and`, `or` and `not` (or
+//! `&&`, `||`, `!`) and parentheses; adjacent terms are implicitly and-ed:
+//!
+//! ```text
+//! vid:04f9 and (class:07 or product:"*Laser*")
+//! vid:1050 pid:0400-04ff not serial:TEST*
+//! port:1-1.* and speed:high
+//! ```
+//!
+//! Terms:
+//! - `vid:04f9`, `vid:04f9-04ff` - vendor ID or inclusive range (hex)
+//! - `pid:0042`, `pid:0040-004f` - product ID or inclusive range (hex)
+//! - `class:07`, `subclass:01`, `protocol:02` - device or any interface code (hex)
+//! - `serial:GLOB`, `manufacturer:GLOB`, `product:GLOB` - strings, matched
+//!   case-insensitively with `*` and `?` wildcards
+//! - `port:1-1.4`, `port:1-1.*` - physical port (see [`PortSelector`])
+//! - `speed:low|full|high|super|super+` - negotiated speed
+//! - `id:STABLE_ID` - `DeviceInfo::stable_id`
+//! - `*` - any device
+//!
+//! The older pattern forms are still terms: `VID:PID` with `*` wildcards and
+//! an optional `0x` prefix (`04f9:*`, `0x1234:0x5678`), a stable ID
+//! (`1050:0407:SERIAL`, `04f9:0042@1-1.4`), and a bare word, which matches a
+//! substring of the product name (`YubiKey`, `"Laser Printer"`).
+
+use crate::error::{Error, Result};
+use crate::usb_types::PortSelector;
+use protocol::{DeviceInfo, DeviceSpeed};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Keys accepted in `key:value` terms, for error messages
+const KEYS: &str =
+    "vid, pid, class, subclass, protocol, serial, manufacturer, product, port, speed or id";
+
+/// Parsed device filter expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceFilter {
+    /// Expression as written
+    source: String,
+    /// Parsed expression tree
+    expr: Expr,
+}
+
+/// How narrowly a filter selects devices, most specific first
+///
+/// Used to pick one of several matching device policies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Specificity {
+    /// One device model or identity (`VID:PID`, stable ID)
+    Device,
+    /// One physical port (`port:PATH`)
+    Port,
+    /// One vendor (`VID:*`)
+    Vendor,
+    /// Any other expression
+    Expression,
+    /// Every device (`*`)
+    Any,
+}
+
+impl DeviceFilter {
+    /// Parse a filter expression
+    ///
+    /// Errors are `Error::Config` naming the expression, the column and
+    /// what was expected there.
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            source,
+            tokens,
+            pos: 0,
+        };
+
+        if parser.tokens.is_empty() {
+            return Err(parser.error(0, "empty filter"));
+        }
+        let expr = parser.parse_or()?;
+        if let Some((column, token)) = parser.tokens.get(parser.pos) {
+            return Err(parser.error(*column, format!("unexpected {}", token)));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// Whether a device is selected by this filter
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        self.expr.matches(device)
+    }
+
+    /// Whether matching looks at string descriptors or the stable ID
+    ///
+    /// The server has to open a device to read those, so filters that only
+    /// use IDs, codes, port and speed can be checked without doing so.
+    pub fn needs_strings(&self) -> bool {
+        self.expr.needs_strings()
+    }
+
+    /// How narrowly this filter selects devices
+    pub fn specificity(&self) -> Specificity {
+        match &self.expr {
+            Expr::Term(Term::Any) => Specificity::Any,
+            Expr::Term(
+                Term::VidPid {
+                    vendor: Some(_),
+                    product: Some(_),
+                }
+                | Term::StableId(_),
+            ) => Specificity::Device,
+            Expr::Term(Term::Port(_)) => Specificity::Port,
+            Expr::Term(Term::VidPid {
+                vendor: Some(_),
+                product: None,
+            }) => Specificity::Vendor,
+            _ => Specificity::Expression,
+        }
+    }
+}
+
+impl FromStr for DeviceFilter {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        Self::parse(source)
+    }
+}
+
+impl fmt::Display for DeviceFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Expression tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Term(Term),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, device: &DeviceInfo) -> bool {
+        match self {
+            Self::Term(term) => term.matches(device),
+            Self::Not(expr) => !expr.matches(device),
+            Self::And(exprs) => exprs.iter().all(|expr| expr.matches(device)),
+            Self::Or(exprs) => exprs.iter().any(|expr| expr.matches(device)),
+        }
+    }
+
+    fn needs_strings(&self) -> bool {
+        match self {
+            Self::Term(term) => matches!(
+                term,
+                Term::Serial(_)
+                    | Term::Manufacturer(_)
+                    | Term::ProductName(_)
+                    | Term::StableId(_)
+                    | Term::Name(_)
+            ),
+            Self::Not(expr) => expr.needs_strings(),
+            Self::And(exprs) | Self::Or(exprs) => exprs.iter().any(Expr::needs_strings),
+        }
+    }
+}
+
+/// A single condition on a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    /// `*`
+    Any,
+    /// `VID:PID`, None being a `*` wildcard
+    VidPid {
+        vendor: Option<u16>,
+        product: Option<u16>,
+    },
+    Vendor(RangeInclusive<u16>),
+    Product(RangeInclusive<u16>),
+    Class(u8),
+    Subclass(u8),
+    Protocol(u8),
+    Serial(Glob),
+    Manufacturer(Glob),
+    ProductName(Glob),
+    Port(PortSelector),
+    Speed(DeviceSpeed),
+    StableId(String),
+    /// Bare word, matching a product name substring (lowercase)
+    Name(String),
+}
+
+impl Term {
+    fn matches(&self, device: &DeviceInfo) -> bool {
+        match self {
+            Self::Any => true,
+            Self::VidPid { vendor, product } => {
+                vendor.is_none_or(|vid| vid == device.vendor_id)
+                    && product.is_none_or(|pid| pid == device.product_id)
+            }
+            Self::Vendor(range) => range.contains(&device.vendor_id),
+            Self::Product(range) => range.contains(&device.product_id),
+            Self::Class(code) => {
+                device.class == *code || device.interfaces.iter().any(|i| i.class == *code)
+            }
+            Self::Subclass(code) => {
+                device.subclass == *code || device.interfaces.iter().any(|i| i.subclass == *code)
+            }
+            Self::Protocol(code) => {
+                device.protocol == *code || device.interfaces.iter().any(|i| i.protocol == *code)
+            }
+            Self::Serial(glob) => glob.matches(device.serial_number.as_deref()),
+            Self::Manufacturer(glob) => glob.matches(device.manufacturer.as_deref()),
+            Self::ProductName(glob) => glob.matches(device.product.as_deref()),
+            Self::Port(selector) => selector.matches(device.bus_number, &device.port_numbers),
+            Self::Speed(speed) => device.speed == *speed,
+            Self::StableId(id) => device.stable_id.eq_ignore_ascii_case(id),
+            Self::Name(name) => device
+                .product
+                .as_ref()
+                .is_some_and(|product| product.to_lowercase().contains(name.as_str())),
+        }
+    }
+}
+
+/// Case-insensitive glob with `*` and `?`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Glob(Vec<char>);
+
+impl Glob {
+    fn new(pattern: &str) -> Self {
+        Self(pattern.to_lowercase().chars().collect())
+    }
+
+    /// Whether a string matches; a missing string never does
+    fn matches(&self, text: Option<&str>) -> bool {
+        let Some(text) = text else {
+            return false;
+        };
+        let text: Vec<char> = text.to_lowercase().chars().collect();
+        let pattern = &self.0;
+
+        // Backtrack to the most recent `*` on mismatch
+        let (mut p, mut t) = (0, 0);
+        let mut star: Option<(usize, usize)> = None;
+        while t < text.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    star = Some((p, t));
+                    p += 1;
+                }
+                Some(&c) if c == '?' || c == text[t] => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match star {
+                    Some((star_p, star_t)) => {
+                        p = star_p + 1;
+                        t = star_t + 1;
+                        star = Some((star_p, star_t + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p..].iter().all(|&c| c == '*')
+    }
+}
+
+/// Lexical token
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    Word(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open => write!(f, "'('"),
+            Self::Close => write!(f, "')'"),
+            Self::Not => write!(f, "'not'"),
+            Self::And => write!(f, "'and'"),
+            Self::Or => write!(f, "'or'"),
+            Self::Word(word) => write!(f, "'{}'", word),
+        }
+    }
+}
+
+/// Split an expression into tokens with their (0-based) character columns
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let token = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '!' => Token::Not,
+            c @ ('&' | '|') => {
+                if chars.get(i + 1) != Some(&c) {
+                    return Err(filter_error(source, i, format!("expected '{}{}'", c, c)));
+                }
+                i += 1;
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            _ => {
+                let mut word = String::new();
+                let mut quoted = false;
+                while let Some(&c) = chars.get(i) {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '&' | '|') {
+                        break;
+                    }
+                    if c == '"' {
+                        let len = chars[i + 1..]
+                            .iter()
+                            .position(|&c| c == '"')
+                            .ok_or_else(|| filter_error(source, i, "unterminated quote"))?;
+                        word.extend(&chars[i + 1..i + 1 + len]);
+                        quoted = true;
+                        i += len + 2;
+                    } else {
+                        word.push(c);
+                        i += 1;
+                    }
+                }
+                tokens.push((start, keyword(&word, quoted).unwrap_or(Token::Word(word))));
+                continue;
+            }
+        };
+        tokens.push((start, token));
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// Operator spelled as a word (`and`, `or`, `not`), unless quoted
+fn keyword(word: &str, quoted: bool) -> Option<Token> {
+    if quoted {
+        return None;
+    }
+    match word.to_ascii_lowercase().as_str() {
+        "and" => Some(Token::And),
+        "or" => Some(Token::Or),
+        "not" => Some(Token::Not),
+        _ => None,
+    }
+}
+
+/// Recursive-descent parser; `or` binds loosest, then `and`, then `not`
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.parse_and()?];
+        while self.eat(&Token::Or) {
+            exprs.push(self.parse_and()?);
+        }
+        Ok(flatten(exprs, Expr::Or))
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.parse_unary()?];
+        loop {
+            // Adjacent terms are an implicit `and`
+            let implicit = matches!(self.peek(), Some(Token::Word(_) | Token::Not | Token::Open));
+            if !self.eat(&Token::And) && !implicit {
+                break;
+            }
+            exprs.push(self.parse_unary()?);
+        }
+        Ok(flatten(exprs, Expr::And))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        let Some((column, token)) = self.tokens.get(self.pos).cloned() else {
+            let end = self.source.chars().count();
+            return Err(self.error(end, "expected a filter term at end of expression"));
+        };
+        self.pos += 1;
+
+        match token {
+            Token::Not => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Token::Open => {
+                let expr = self.parse_or()?;
+                if !self.eat(&Token::Close) {
+                    let at = self.tokens.get(self.pos).map(|(column, _)| *column);
+                    return Err(self.error(
+                        at.unwrap_or_else(|| self.source.chars().count()),
+                        format!("missing ')' for '(' at column {}", column + 1),
+                    ));
+                }
+                Ok(expr)
+            }
+            Token::Word(word) => parse_term(&word)
+                .map(Expr::Term)
+                .map_err(|reason| self.error(column, reason)),
+            token => Err(self.error(column, format!("expected a filter term, found {}", token))),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    /// Consume the next token if it is `token`
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, column: usize, reason: impl fmt::Display) -> Error {
+        filter_error(self.source, column, reason)
+    }
+}
+
+/// Single expression as itself, several joined by `join`
+fn flatten(mut exprs: Vec<Expr>, join: fn(Vec<Expr>) -> Expr) -> Expr {
+    if exprs.len() == 1 {
+        exprs.remove(0)
+    } else {
+        join(exprs)
+    }
+}
+
+fn filter_error(source: &str, column: usize, reason: impl fmt::Display) -> Error {
+    Error::Config(format!(
+        "Invalid device filter '{}' at column {}: {}",
+        source,
+        column + 1,
+        reason
+    ))
+}
+
+/// Parse one term; the error is the reason only, without position
+fn parse_term(word: &str) -> std::result::Result<Term, String> {
+    if word == "*" {
+        return Ok(Term::Any);
+    }
+
+    let Some((key, value)) = word.split_once(':') else {
+        if has_hex_prefix(word) {
+            return Err(format!(
+                "'{}' looks like a vendor ID, use 'VID:PID' (e.g. '{}:*') or 'vid:{}'",
+                word, word, word
+            ));
+        }
+        return Ok(Term::Name(word.to_lowercase()));
+    };
+
+    match key.to_ascii_lowercase().as_str() {
+        "vid" => parse_range(value, "vendor ID").map(Term::Vendor),
+        "pid" => parse_range(value, "product ID").map(Term::Product),
+        "class" => parse_code(value, "class").map(Term::Class),
+        "subclass" => parse_code(value, "subclass").map(Term::Subclass),
+        "protocol" => parse_code(value, "protocol").map(Term::Protocol),
+        "serial" => Ok(Term::Serial(Glob::new(value))),
+        "manufacturer" => Ok(Term::Manufacturer(Glob::new(value))),
+        "product" => Ok(Term::ProductName(Glob::new(value))),
+        "port" => value.parse().map(Term::Port).map_err(|e| match e {
+            Error::Config(message) => message,
+            other => other.to_string(),
+        }),
+        "speed" => parse_speed(value).map(Term::Speed),
+        "id" if value.is_empty() => Err("empty stable ID".to_string()),
+        "id" => Ok(Term::StableId(value.to_string())),
+        _ if key == "*" || has_hex_prefix(key) || is_hex(key) => parse_legacy(key, value, word),
+        _ => Err(format!("unknown filter key '{}' (expected {})", key, KEYS)),
+    }
+}
+
+/// `VID:PID` with wildcards, or a stable ID as the server formats it
+fn parse_legacy(key: &str, value: &str, word: &str) -> std::result::Result<Term, String> {
+    // vvvv:pppp:SERIAL or vvvv:pppp@BUS-PORT
+    let stable_pid = value.split([':', '@']).next().unwrap_or_default();
+    if key.len() == 4 && is_hex(key) && stable_pid.len() == 4 && is_hex(stable_pid) {
+        if value.len() > 4 {
+            return Ok(Term::StableId(word.to_string()));
+        }
+    } else if value.contains(':') {
+        return Err(format!(
+            "'{}' has too many ':', expected VID:PID (e.g. '0x1234:0x5678' or '0x1234:*')",
+            word
+        ));
+    }
+
+    let wildcard = |text: &str, what: &str| match text {
+        "*" => Ok(None),
+        _ => parse_hex(text, what).map(Some),
+    };
+    Ok(Term::VidPid {
+        vendor: wildcard(key, "vendor ID")?,
+        product: wildcard(value, "product ID")?,
+    })
+}
+
+/// Hex ID or inclusive `LOW-HIGH` range
+fn parse_range(value: &str, what: &str) -> std::result::Result<RangeInclusive<u16>, String> {
+    let (low, high) = value.split_once('-').unwrap_or((value, value));
+    let (low, high) = (parse_hex(low, what)?, parse_hex(high, what)?);
+    if low > high {
+        return Err(format!("empty {} range '{}'", what, value));
+    }
+    Ok(low..=high)
+}
+
+/// 16-bit hex ID with optional `0x` prefix
+fn parse_hex(text: &str, what: &str) -> std::result::Result<u16, String> {
+    let digits = strip_hex_prefix(text);
+    if digits.is_empty() || digits.len() > 4 || !is_hex(digits) {
+        return Err(format!(
+            "invalid {} '{}' (expected 1-4 hex digits, e.g. '04f9')",
+            what, text
+        ));
+    }
+    u16::from_str_radix(digits, 16).map_err(|e| e.to_string())
+}
+
+/// 8-bit hex class, subclass or protocol code
+fn parse_code(text: &str, what: &str) -> std::result::Result<u8, String> {
+    let digits = strip_hex_prefix(text);
+    if digits.is_empty() || digits.len() > 2 || !is_hex(digits) {
+        return Err(format!(
+            "invalid {} '{}' (expected 1-2 hex digits, e.g. '07')",
+            what, text
+        ));
+    }
+    u8::from_str_radix(digits, 16).map_err(|e| e.to_string())
+}
+
+fn parse_speed(text: &str) -> std::result::Result<DeviceSpeed, String> {
+    match text.to_ascii_lowercase().as_str() {
+        "low" => Ok(DeviceSpeed::Low),
+        "full" => Ok(DeviceSpeed::Full),
+        "high" => Ok(DeviceSpeed::High),
+        "super" => Ok(DeviceSpeed::Super),
+        "super+" | "superplus" => Ok(DeviceSpeed::SuperPlus),
+        _ => Err(format!(
+            "invalid speed '{}' (expected low, full, high, super or super+)",
+            text
+        )),
+    }
+}
+
+fn has_hex_prefix(text: &str) -> bool {
+    text.starts_with("0x") || text.starts_with("0X")
+}
+
+fn strip_hex_prefix(text: &str) -> &str {
+    if has_hex_prefix(text) {
+        &text[2..]
+    } else {
+        text
+    }
+}
+
+fn is_hex(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_mock_device_info;
+    use protocol::InterfaceInfo;
+
+    fn matches(filter: &str, device: &DeviceInfo) -> bool {
+        DeviceFilter::parse(filter).unwrap().matches(device)
+    }
+
+    fn error(filter: &str) -> String {
+        DeviceFilter::parse(filter).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_legacy_patterns() {
+        let device = create_mock_device_info(7, 0x04f9, 0x0042);
+
+        assert!(matches("04f9:0042", &device));
+        assert!(matches("0x04F9:0x0042", &device));
+        assert!(matches("04f9:*", &device));
+        assert!(matches("*:0x0042", &device));
+        assert!(matches("*", &device));
+        assert!(matches("04f9:0042:SN000007", &device));
+        assert!(matches("test product", &device));
+        assert!(!matches("04f9:0043", &device));
+        assert!(!matches("04f9:0042:SN000008", &device));
+        assert!(!matches("printer", &device));
+    }
+
+    #[test]
+    fn test_keyed_terms() {
+        let mut device = create_mock_device_info(3, 0x1050, 0x0407);
+        device.interfaces.push(InterfaceInfo {
+            number: 1,
+            class: 0x03,
+            subclass: 0x01,
+            protocol: 0x01,
+            num_endpoints: 1,
+        });
+        device.port_numbers = vec![1, 4];
+
+        assert!(matches("vid:1050 pid:0400-04ff", &device));
+        assert!(!matches("pid:0408-04ff", &device));
+        assert!(matches("class:03 subclass:1 protocol:0x01", &device));
+        assert!(matches("serial:SN*3", &device));
+        assert!(matches("manufacturer:\"test manufacturer ?\"", &device));
+        assert!(matches("product:*PRODUCT*", &device));
+        assert!(!matches("product:Product*", &device));
+        assert!(matches("port:1-1.* speed:high", &device));
+        assert!(!matches("speed:super+", &device));
+        assert!(matches("id:1050:0407:SN000003", &device));
+    }
+
+    #[test]
+    fn test_combinators() {
+        let printer = create_mock_device_info(1, 0x04f9, 0x0042);
+        let key = create_mock_device_info(2, 0x1050, 0x0407);
+
+        // `and` binds tighter than `or`
+        let filter = DeviceFilter::parse("vid:04f9 and pid:0043 or vid:1050").unwrap();
+        assert!(!filter.matches(&printer));
+        assert!(filter.matches(&key));
+
+        let filter = DeviceFilter::parse("vid:04f9 && (pid:0043 || pid:0042)").unwrap();
+        assert!(filter.matches(&printer));
+        assert!(!filter.matches(&key));
+
+        assert!(matches("not vid:04f9", &key));
+        assert!(!matches("!vid:1050", &key));
+        assert!(matches("!(vid:04f9 or vid:046d) serial:SN*", &key));
+        assert!(!matches("\"and\"", &key));
+    }
+
+    #[test]
+    fn test_glob() {
+        let glob = Glob::new("a*b?c");
+        assert!(glob.matches(Some("abbc")));
+        assert!(glob.matches(Some("AxxxbYc")));
+        assert!(!glob.matches(Some("abc")));
+        assert!(!glob.matches(None));
+        assert!(Glob::new("*").matches(Some("")));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(error("").contains("empty filter"));
+        assert!(error("vid:04f9 and").contains("column 13: expected a filter term"));
+        assert!(error("(vid:04f9").contains("missing ')'"));
+        assert!(error("vid:04f9)").contains("column 9: unexpected ')'"));
+        assert!(error("vid:04f9 & pid:1").contains("expected '&&'"));
+        assert!(error("product:\"Laser").contains("unterminated quote"));
+        assert!(error("vendor:04f9").contains("unknown filter key 'vendor'"));
+        assert!(error("vid:04f9-0001").contains("empty vendor ID range"));
+        assert!(error("class:123").contains("invalid class '123'"));
+        assert!(error("speed:warp").contains("invalid speed"));
+        assert!(error("port:1.4").contains("Invalid port path '1.4'"));
+        assert!(error("0x1234").contains("looks like a vendor ID"));
+        assert!(error("0xGHIJ:0x5678").contains("invalid vendor ID '0xGHIJ'"));
+        assert!(error("0x12345:0x5678").contains("invalid vendor ID"));
+        assert!(error("0x1234:0x5678:0x9abc").contains("too many ':'"));
+    }
+
+    #[test]
+    fn test_needs_strings() {
+        let needs_strings = |filter: &str| DeviceFilter::parse(filter).unwrap().needs_strings();
+
+        assert!(!needs_strings(
+            "04f9:* or port:1-1.* and not class:07 speed:high"
+        ));
+        assert!(needs_strings("vid:1050 and not serial:TEST*"));
+        assert!(needs_strings("1050:0407:SN1"));
+        assert!(needs_strings("YubiKey"));
+    }
+
+    #[test]
+    fn test_specificity() {
+        let specificity = |filter: &str| DeviceFilter::parse(filter).unwrap().specificity();
+
+        assert_eq!(specificity("04f9:0042"), Specificity::Device);
+        assert_eq!(specificity("1050:0407:SN1"), Specificity::Device);
+        assert_eq!(specificity("port:1-1.4"), Specificity::Port);
+        assert_eq!(specificity("04f9:*"), Specificity::Vendor);
+        assert_eq!(specificity("class:07"), Specificity::Expression);
+        assert_eq!(
+            specificity("04f9:0042 or 04f9:0043"),
+            Specificity::Expression
+        );
+        assert_eq!(specificity("*"), Specificity::Any);
+        assert!(Specificity::Device < Specificity::Any);
+    }
+}
@@ -5,6 +5,10 @@
 //! - Connection management utilities
 //! - Node ID handling
 //! - ALPN protocol constants
+//! - Endpoint binding with configurable relays
+
+use crate::{Error, Result};
+use iroh::{Endpoint, RelayMap, RelayMode, RelayUrl, SecretKey};
 
 /// ALPN protocol identifier for rust-p2p-usb
 pub const ALPN_PROTOCOL: &[u8] = b"rust-p2p-usb/1";
@@ -16,3 +20,81 @@ pub const ALPN_PROTOCOL: &[u8] = b"rust-p2p-usb/1";
 pub fn generate_test_endpoint_id() -> iroh::PublicKey {
     iroh::SecretKey::generate(&mut rand::rng()).public()
 }
+
+/// Relay mode for the `relay_servers` config option
+///
+/// `None` uses Iroh's default relays, an empty list disables relays and any
+/// other list replaces the defaults with those relay URLs.
+pub fn relay_mode(relay_servers: Option<&[String]>) -> Result<RelayMode> {
+    match relay_servers {
+        None => Ok(RelayMode::Default),
+        Some([]) => Ok(RelayMode::Disabled),
+        Some(urls) => {
+            let map = urls
+                .iter()
+                .map(|url| {
+                    url.parse::<RelayUrl>()
+                        .map_err(|e| Error::Config(format!("Invalid relay URL '{}': {}", url, e)))
+                })
+                .collect::<Result<RelayMap>>()?;
+            Ok(RelayMode::Custom(map))
+        }
+    }
+}
+
+/// Bind an Iroh endpoint speaking `alpn`
+///
+/// Waits for the endpoint to reach its home relay before returning. With
+/// relays disabled, discovery is turned off as well and the endpoint is only
+/// reachable on its direct addresses, so peers need a full `EndpointAddr`.
+pub async fn bind_endpoint(
+    secret_key: SecretKey,
+    alpn: Vec<u8>,
+    relay_servers: Option<&[String]>,
+) -> Result<Endpoint> {
+    let relay_mode = relay_mode(relay_servers)?;
+    let relays_enabled = !matches!(relay_mode, RelayMode::Disabled);
+
+    let mut builder = Endpoint::builder()
+        .secret_key(secret_key)
+        .alpns(vec![alpn])
+        .relay_mode(relay_mode);
+    if !relays_enabled {
+        builder = builder.clear_discovery();
+    }
+
+    let endpoint = builder
+        .bind()
+        .await
+        .map_err(|e| Error::Network(format!("Failed to bind endpoint: {}", e)))?;
+
+    // Wait for endpoint to discover its addresses before accepting connections
+    if relays_enabled {
+        endpoint.online().await;
+    }
+
+    Ok(endpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_mode_from_config() {
+        assert!(matches!(relay_mode(None).unwrap(), RelayMode::Default));
+        assert!(matches!(
+            relay_mode(Some(&[])).unwrap(),
+            RelayMode::Disabled
+        ));
+
+        let urls = vec!["https://relay.example.com".to_string()];
+        match relay_mode(Some(&urls)).unwrap() {
+            RelayMode::Custom(map) => assert_eq!(map.len(), 1),
+            other => panic!("expected custom relays, got {:?}", other),
+        }
+
+        let invalid = vec!["not a url".to_string()];
+        assert!(matches!(relay_mode(Some(&invalid)), Err(Error::Config(_))));
+    }
+}
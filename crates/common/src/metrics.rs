@@ -2,7 +2,10 @@
 //!
 //! This module provides thread-safe metrics collection for monitoring
 //! USB transfer performance, bandwidth usage, and connection quality.
+//! Besides the rolling windows shown in the TUIs, transfers feed cumulative
+//! latency histograms for the Prometheus exporter.
 
+use protocol::{ProtocolLatencyStats, ProtocolMetrics, TransferResult, TransferType};
 use std::collections::VecDeque;
 use std::sync::RwLock;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -180,6 +183,150 @@ impl LatencyStats {
     }
 }
 
+/// Upper bounds of the latency histogram buckets, in microseconds
+///
+/// From 100 µs (a control transfer on a LAN) to 5 s (a slow bulk read over
+/// a relay); anything slower only counts towards `+Inf`.
+pub const LATENCY_BUCKETS_US: [u64; 15] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000,
+];
+
+/// Cumulative latency histogram (never pruned, unlike the rolling stats)
+#[derive(Debug, Default)]
+struct LatencyHistogram {
+    /// Samples per bucket of `LATENCY_BUCKETS_US`, plus one for `+Inf`
+    buckets: [AtomicU64; LATENCY_BUCKETS_US.len() + 1],
+    /// Sum of all samples in microseconds
+    sum_us: AtomicU64,
+}
+
+impl LatencyHistogram {
+    fn observe(&self, latency_us: u64) {
+        let bucket = LATENCY_BUCKETS_US
+            .iter()
+            .position(|&bound| latency_us <= bound)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(latency_us, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = self
+            .buckets
+            .iter()
+            .map(|count| {
+                cumulative += count.load(Ordering::Relaxed);
+                cumulative
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            sum_us: self.sum_us.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        for count in &self.buckets {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.sum_us.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Latency histogram snapshot
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    /// Cumulative sample counts for each bound in `LATENCY_BUCKETS_US`,
+    /// followed by the total count (`+Inf`)
+    pub buckets: Vec<u64>,
+    /// Sum of all samples in microseconds
+    pub sum_us: u64,
+}
+
+impl HistogramSnapshot {
+    /// Number of samples
+    pub fn count(&self) -> u64 {
+        self.buckets.last().copied().unwrap_or(0)
+    }
+}
+
+/// How a transfer ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferOutcome {
+    /// Completed, with the bytes sent and received by the recording side
+    Completed {
+        bytes_sent: u64,
+        bytes_received: u64,
+        latency: Duration,
+    },
+    /// Failed with a USB or transport error
+    Failed,
+}
+
+/// USB transfer type, as a metrics label
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TransferKind {
+    Control,
+    Interrupt,
+    Bulk,
+    Isochronous,
+}
+
+impl TransferKind {
+    /// All transfer types, in label order
+    pub const ALL: [TransferKind; 4] = [
+        TransferKind::Control,
+        TransferKind::Interrupt,
+        TransferKind::Bulk,
+        TransferKind::Isochronous,
+    ];
+
+    /// Transfer type of a request
+    pub fn of(transfer: &TransferType) -> Self {
+        match transfer {
+            TransferType::Control { .. } => Self::Control,
+            TransferType::Interrupt { .. } => Self::Interrupt,
+            TransferType::Bulk { .. } => Self::Bulk,
+            TransferType::Isochronous { .. } => Self::Isochronous,
+        }
+    }
+
+    /// Lowercase name used as label value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Control => "control",
+            Self::Interrupt => "interrupt",
+            Self::Bulk => "bulk",
+            Self::Isochronous => "isochronous",
+        }
+    }
+}
+
+/// Transfer metrics for each transfer type
+#[derive(Debug, Default)]
+pub struct TransferKindMetrics {
+    kinds: [TransferMetrics; 4],
+}
+
+impl TransferKindMetrics {
+    /// Create empty metrics for every transfer type
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Metrics of one transfer type
+    pub fn get(&self, kind: TransferKind) -> &TransferMetrics {
+        &self.kinds[kind as usize]
+    }
+
+    /// Metrics of every transfer type, in label order
+    pub fn iter(&self) -> impl Iterator<Item = (TransferKind, &TransferMetrics)> {
+        TransferKind::ALL.into_iter().zip(self.kinds.iter())
+    }
+}
+
 /// Transfer metrics for a single device or connection
 #[derive(Debug)]
 pub struct TransferMetrics {
@@ -195,8 +342,14 @@ pub struct TransferMetrics {
     retries: AtomicU64,
     /// Active transfer count
     active_transfers: AtomicU64,
+    /// Transfers held back by the rate limiter
+    throttled: AtomicU64,
+    /// Time spent waiting for the rate limiter in microseconds
+    throttle_wait_us: AtomicU64,
     /// Rolling latency statistics (protected by RwLock for mutable access)
     latency_stats: RwLock<RollingStats>,
+    /// Latency of every completed transfer
+    latency_histogram: LatencyHistogram,
     /// Rolling throughput for sent data
     throughput_tx: RwLock<RollingThroughput>,
     /// Rolling throughput for received data
@@ -222,7 +375,10 @@ impl TransferMetrics {
             transfers_failed: AtomicU64::new(0),
             retries: AtomicU64::new(0),
             active_transfers: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            throttle_wait_us: AtomicU64::new(0),
             latency_stats: RwLock::new(RollingStats::new(window)),
+            latency_histogram: LatencyHistogram::default(),
             throughput_tx: RwLock::new(RollingThroughput::new(window)),
             throughput_rx: RwLock::new(RollingThroughput::new(window)),
             connected_at: RwLock::new(None),
@@ -265,6 +421,7 @@ impl TransferMetrics {
         if let Ok(mut stats) = self.latency_stats.write() {
             stats.add_sample(latency_us);
         }
+        self.latency_histogram.observe(latency_us);
 
         // Record throughput samples
         if bytes_sent > 0 {
@@ -285,6 +442,18 @@ impl TransferMetrics {
         self.transfers_failed.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Record the end of a transfer previously passed to `transfer_started`
+    pub fn transfer_finished(&self, outcome: TransferOutcome) {
+        match outcome {
+            TransferOutcome::Completed {
+                bytes_sent,
+                bytes_received,
+                latency,
+            } => self.transfer_completed(bytes_sent, bytes_received, latency),
+            TransferOutcome::Failed => self.transfer_failed(),
+        }
+    }
+
     /// Record a transfer with success/failure indication
     ///
     /// Convenience method that calls either transfer_completed or transfer_failed
@@ -309,6 +478,13 @@ impl TransferMetrics {
         self.retries.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Record a transfer the rate limiter made wait for `wait`
+    pub fn record_throttled(&self, wait: Duration) {
+        self.throttled.fetch_add(1, Ordering::Relaxed);
+        self.throttle_wait_us
+            .fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
     /// Get total bytes sent
     pub fn total_bytes_sent(&self) -> u64 {
         self.bytes_sent.load(Ordering::Relaxed)
@@ -339,6 +515,21 @@ impl TransferMetrics {
         self.active_transfers.load(Ordering::Relaxed)
     }
 
+    /// Get the number of transfers held back by the rate limiter
+    pub fn throttled(&self) -> u64 {
+        self.throttled.load(Ordering::Relaxed)
+    }
+
+    /// Get the total time transfers waited for the rate limiter
+    pub fn throttle_wait(&self) -> Duration {
+        Duration::from_micros(self.throttle_wait_us.load(Ordering::Relaxed))
+    }
+
+    /// Get the latency histogram of all completed transfers
+    pub fn latency_histogram(&self) -> HistogramSnapshot {
+        self.latency_histogram.snapshot()
+    }
+
     /// Get latency statistics
     pub fn latency_stats(&self) -> LatencyStats {
         let stats = self.latency_stats.read().unwrap();
@@ -392,6 +583,9 @@ impl TransferMetrics {
         self.transfers_completed.store(0, Ordering::Relaxed);
         self.transfers_failed.store(0, Ordering::Relaxed);
         self.retries.store(0, Ordering::Relaxed);
+        self.throttled.store(0, Ordering::Relaxed);
+        self.throttle_wait_us.store(0, Ordering::Relaxed);
+        self.latency_histogram.reset();
 
         let window = rolling_window_duration();
         if let Ok(mut stats) = self.latency_stats.write() {
@@ -531,6 +725,56 @@ impl MetricsSnapshot {
     }
 }
 
+impl From<&MetricsSnapshot> for ProtocolMetrics {
+    fn from(snapshot: &MetricsSnapshot) -> Self {
+        Self {
+            bytes_sent: snapshot.bytes_sent,
+            bytes_received: snapshot.bytes_received,
+            transfers_completed: snapshot.transfers_completed,
+            transfers_failed: snapshot.transfers_failed,
+            retries: snapshot.retries,
+            active_transfers: snapshot.active_transfers,
+            latency: ProtocolLatencyStats {
+                min_us: snapshot.latency.min_us,
+                max_us: snapshot.latency.max_us,
+                avg_us: snapshot.latency.avg_us,
+                sample_count: snapshot.latency.sample_count,
+            },
+            throughput_tx_bps: snapshot.throughput_tx_bps,
+            throughput_rx_bps: snapshot.throughput_rx_bps,
+            loss_rate: snapshot.loss_rate,
+            retry_rate: snapshot.retry_rate,
+            uptime_secs: snapshot.uptime.map(|d| d.as_secs()),
+        }
+    }
+}
+
+impl From<&TransferMetrics> for ProtocolMetrics {
+    fn from(metrics: &TransferMetrics) -> Self {
+        Self::from(&MetricsSnapshot::from_metrics(metrics))
+    }
+}
+
+/// Data carried by a transfer request (OUT payload), in bytes
+pub fn request_payload_size(transfer: &TransferType) -> u64 {
+    match transfer {
+        TransferType::Control { data, .. }
+        | TransferType::Interrupt { data, .. }
+        | TransferType::Bulk { data, .. }
+        | TransferType::Isochronous { data, .. } => data.len() as u64,
+    }
+}
+
+/// Data carried back by a transfer result (IN payload), in bytes
+pub fn response_payload_size(result: &TransferResult) -> u64 {
+    match result {
+        TransferResult::Success { data, .. } | TransferResult::IsochronousSuccess { data, .. } => {
+            data.len() as u64
+        }
+        TransferResult::Error { .. } => 0,
+    }
+}
+
 /// Format bytes as human-readable string
 pub fn format_bytes(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -599,6 +843,24 @@ mod tests {
         assert_eq!(metrics.transfers_completed(), 1);
     }
 
+    #[test]
+    fn test_protocol_metrics_conversion() {
+        let metrics = TransferMetrics::new();
+        metrics.mark_connected();
+        metrics.record_transfer(64, 512, Duration::from_millis(4), true);
+        metrics.record_transfer(0, 0, Duration::from_millis(1), false);
+
+        let protocol = ProtocolMetrics::from(&metrics);
+        assert_eq!(protocol.bytes_sent, 64);
+        assert_eq!(protocol.bytes_received, 512);
+        assert_eq!(protocol.transfers_completed, 1);
+        assert_eq!(protocol.transfers_failed, 1);
+        assert_eq!(protocol.latency.avg_us, 4000);
+        assert_eq!(protocol.latency.sample_count, 1);
+        assert!((protocol.loss_rate - 0.5).abs() < 0.001);
+        assert_eq!(protocol.uptime_secs, Some(0));
+    }
+
     #[test]
     fn test_latency_stats() {
         let metrics = TransferMetrics::new();
@@ -665,6 +927,28 @@ mod tests {
         assert_eq!(snapshot.connection_quality_label(), "Excellent");
     }
 
+    #[test]
+    fn test_latency_histogram_is_cumulative() {
+        let metrics = TransferMetrics::new();
+        for latency in [
+            Duration::from_micros(80),
+            Duration::from_millis(3),
+            Duration::from_secs(9),
+        ] {
+            metrics.transfer_started();
+            metrics.transfer_completed(0, 0, latency);
+        }
+
+        let histogram = metrics.latency_histogram();
+        assert_eq!(histogram.buckets.len(), LATENCY_BUCKETS_US.len() + 1);
+        assert_eq!(histogram.buckets[0], 1); // <= 100 µs
+        assert_eq!(histogram.buckets[4], 1); // <= 2.5 ms
+        assert_eq!(histogram.buckets[5], 2); // <= 5 ms
+        assert_eq!(histogram.buckets[LATENCY_BUCKETS_US.len() - 1], 2);
+        assert_eq!(histogram.count(), 3);
+        assert_eq!(histogram.sum_us, 80 + 3_000 + 9_000_000);
+    }
+
     #[test]
     fn test_metrics_reset() {
         let metrics = TransferMetrics::new();
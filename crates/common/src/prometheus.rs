@@ -0,0 +1,376 @@
+//! Prometheus metrics endpoint
+//!
+//! Renders metrics in the Prometheus text exposition format (which
+//! OpenMetrics scrapers accept too) and serves them on `GET /metrics` from a
+//! small HTTP listener. Both binaries bind it to a local address when
+//! `metrics_addr` is configured; what gets exported is up to the caller's
+//! render function, which runs on every scrape.
+
+use crate::metrics::{HistogramSnapshot, LATENCY_BUCKETS_US, TransferMetrics};
+use std::fmt::Write as _;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Longest request head accepted from a scraper
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+/// Time a scraper gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Name suffix, type, help text and accessor of one transfer figure
+type Figure = (
+    &'static str,
+    MetricType,
+    &'static str,
+    fn(&TransferMetrics) -> u64,
+);
+
+/// Type of a metric family
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Histogram => "histogram",
+        }
+    }
+}
+
+/// Builder of a metrics page in the text exposition format
+///
+/// Samples of one family must follow its [`Self::family`] header.
+#[derive(Debug, Default)]
+pub struct MetricsEncoder {
+    out: String,
+}
+
+impl MetricsEncoder {
+    /// Start an empty page
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the `# HELP` and `# TYPE` header of a metric family
+    pub fn family(&mut self, name: &str, metric_type: MetricType, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, metric_type.as_str());
+    }
+
+    /// Write one sample
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.out.push_str(name);
+        write_labels(&mut self.out, labels, None);
+        let _ = writeln!(self.out, " {}", value);
+    }
+
+    /// Write the samples of one latency histogram series (seconds)
+    pub fn histogram(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        histogram: &HistogramSnapshot,
+    ) {
+        let bounds = LATENCY_BUCKETS_US
+            .iter()
+            .map(|&bound_us| format!("{}", bound_us as f64 / 1_000_000.0))
+            .chain(std::iter::once("+Inf".to_string()));
+        for (bound, count) in bounds.zip(&histogram.buckets) {
+            let _ = write!(self.out, "{}_bucket", name);
+            write_labels(&mut self.out, labels, Some(&bound));
+            let _ = writeln!(self.out, " {}", count);
+        }
+        self.out.push_str(name);
+        self.out.push_str("_sum");
+        write_labels(&mut self.out, labels, None);
+        let _ = writeln!(self.out, " {}", histogram.sum_us as f64 / 1_000_000.0);
+        self.out.push_str(name);
+        self.out.push_str("_count");
+        write_labels(&mut self.out, labels, None);
+        let _ = writeln!(self.out, " {}", histogram.count());
+    }
+
+    /// Write the transfer families of `series` under `prefix`
+    ///
+    /// Each series is one label set, such as a client or a device, with its
+    /// counters, active transfers and latency histogram.
+    pub fn transfer_metrics(
+        &mut self,
+        prefix: &str,
+        series: &[(Vec<(&str, String)>, &TransferMetrics)],
+    ) {
+        let figures: [Figure; 4] = [
+            (
+                "bytes_sent_total",
+                MetricType::Counter,
+                "Bytes returned by IN transfers",
+                TransferMetrics::total_bytes_sent,
+            ),
+            (
+                "bytes_received_total",
+                MetricType::Counter,
+                "Bytes carried by OUT transfers",
+                TransferMetrics::total_bytes_received,
+            ),
+            (
+                "transfer_retries_total",
+                MetricType::Counter,
+                "Transfers retried",
+                TransferMetrics::total_retries,
+            ),
+            (
+                "active_transfers",
+                MetricType::Gauge,
+                "Transfers in flight",
+                TransferMetrics::active_transfers,
+            ),
+        ];
+
+        let name = format!("{}_transfers_total", prefix);
+        self.family(&name, MetricType::Counter, "Transfers by result");
+        for (labels, metrics) in series {
+            for (result, count) in [
+                ("completed", metrics.transfers_completed()),
+                ("failed", metrics.transfers_failed()),
+            ] {
+                let mut labels = borrow_labels(labels);
+                labels.push(("result", result));
+                self.sample(&name, &labels, count as f64);
+            }
+        }
+
+        for (suffix, metric_type, help, value) in figures {
+            let name = format!("{}_{}", prefix, suffix);
+            self.family(&name, metric_type, help);
+            for (labels, metrics) in series {
+                self.sample(&name, &borrow_labels(labels), value(metrics) as f64);
+            }
+        }
+
+        let name = format!("{}_transfer_latency_seconds", prefix);
+        self.family(
+            &name,
+            MetricType::Histogram,
+            "USB round trip of completed transfers",
+        );
+        for (labels, metrics) in series {
+            self.histogram(&name, &borrow_labels(labels), &metrics.latency_histogram());
+        }
+    }
+
+    /// The finished page
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn borrow_labels<'a>(labels: &'a [(&'a str, String)]) -> Vec<(&'a str, &'a str)> {
+    labels
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect()
+}
+
+fn write_labels(out: &mut String, labels: &[(&str, &str)], le: Option<&str>) {
+    if labels.is_empty() && le.is_none() {
+        return;
+    }
+    out.push('{');
+    let le = le.map(|bound| ("le", bound));
+    for (i, (name, value)) in labels.iter().copied().chain(le).enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{}=\"", name);
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    out.push('}');
+}
+
+/// HTTP listener serving `GET /metrics`
+pub struct MetricsExporter {
+    listener: TcpListener,
+}
+
+impl MetricsExporter {
+    /// Listen on `addr`
+    pub async fn bind(addr: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Metrics endpoint listening on http://{}/metrics", addr);
+        Ok(Self { listener })
+    }
+
+    /// Address the listener is bound to
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answer scrapes with the page `render` builds, until the task is aborted
+    pub async fn run<F, Fut>(self, render: F) -> std::io::Result<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = String> + Send,
+    {
+        let render = Arc::new(render);
+        loop {
+            let (stream, peer) = self.listener.accept().await?;
+            let render = render.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_scrape(stream, render.as_ref()).await {
+                    debug!("Metrics request from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+/// Answer one HTTP request and close the connection
+async fn serve_scrape<F, Fut>(mut stream: TcpStream, render: &F) -> std::io::Result<()>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = String>,
+{
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await {
+        Ok(head) => head?,
+        Err(_) => return Ok(()),
+    };
+
+    let mut words = head.split_whitespace();
+    let method = words.next().unwrap_or_default();
+    let path = words.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", CONTENT_TYPE, render().await),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Read up to the blank line that ends the request head
+async fn read_request_head(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+        if head.len() > MAX_REQUEST_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "request head too long",
+            ));
+        }
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_metrics_page() {
+        let metrics = TransferMetrics::new();
+        metrics.transfer_started();
+        metrics.transfer_completed(64, 8, Duration::from_millis(2));
+
+        let mut encoder = MetricsEncoder::new();
+        encoder.transfer_metrics(
+            "p2p_usb_device",
+            &[(vec![("device", "3".to_string())], &metrics)],
+        );
+        let page = encoder.finish();
+
+        assert!(page.contains("# TYPE p2p_usb_device_transfers_total counter\n"));
+        assert!(
+            page.contains("p2p_usb_device_transfers_total{device=\"3\",result=\"completed\"} 1\n")
+        );
+        assert!(page.contains("p2p_usb_device_bytes_sent_total{device=\"3\"} 64\n"));
+        assert!(page.contains("# TYPE p2p_usb_device_active_transfers gauge\n"));
+        assert!(page.contains(
+            "p2p_usb_device_transfer_latency_seconds_bucket{device=\"3\",le=\"0.001\"} 0\n"
+        ));
+        assert!(page.contains(
+            "p2p_usb_device_transfer_latency_seconds_bucket{device=\"3\",le=\"0.0025\"} 1\n"
+        ));
+        assert!(page.contains(
+            "p2p_usb_device_transfer_latency_seconds_bucket{device=\"3\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(page.contains("p2p_usb_device_transfer_latency_seconds_sum{device=\"3\"} 0.002\n"));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        let mut encoder = MetricsEncoder::new();
+        encoder.sample("up", &[("name", "a\"b\\c\nd")], 1.0);
+        encoder.sample("plain", &[], 0.5);
+        assert_eq!(
+            encoder.finish(),
+            "up{name=\"a\\\"b\\\\c\\nd\"} 1\nplain 0.5\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exporter_serves_metrics_path_only() {
+        let exporter = MetricsExporter::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let server = tokio::spawn(exporter.run(|| async { "up 1\n".to_string() }));
+
+        let get = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("\r\n\r\nup 1\n"));
+        assert!(get("/").await.starts_with("HTTP/1.1 404"));
+
+        server.abort();
+    }
+}
@@ -11,7 +11,7 @@
 //! assert_eq!(device.vendor_id, 0x1234);
 //! ```
 
-use protocol::{DeviceHandle, DeviceId, DeviceInfo, DeviceSpeed, RequestId};
+use protocol::{DeviceHandle, DeviceId, DeviceInfo, DeviceSpeed, InterfaceInfo, RequestId};
 use std::future::Future;
 use std::time::Duration;
 
@@ -48,6 +48,10 @@ pub fn create_mock_device_info(id: u32, vendor_id: u16, product_id: u16) -> Devi
         protocol: 0x00,
         speed: DeviceSpeed::High,
         num_configurations: 1,
+        interfaces: Vec::new(),
+        stable_id: format!("{:04x}:{:04x}:SN{:06}", vendor_id, product_id, id),
+        port_numbers: vec![1],
+        parent_hub: None,
     }
 }
 
@@ -82,6 +86,10 @@ pub fn create_mock_device_info_with_class(
         protocol,
         speed: DeviceSpeed::High,
         num_configurations: 1,
+        interfaces: Vec::new(),
+        stable_id: format!("{:04x}:{:04x}:SN{:06}", vendor_id, product_id, id),
+        port_numbers: vec![1],
+        parent_hub: None,
     }
 }
 
@@ -100,6 +108,30 @@ pub fn create_mock_hub_device(id: u32) -> DeviceInfo {
     create_mock_device_info_with_class(id, 0x05e3, 0x0608, 0x09, 0x00, 0x00)
 }
 
+/// Create a mock composite device info (HID keyboard + mass storage)
+///
+/// Interface 0 is a boot keyboard, interface 1 a mass storage function.
+pub fn create_mock_composite_device(id: u32) -> DeviceInfo {
+    let mut device = create_mock_device_info(id, 0x0bda, 0x5411);
+    device.interfaces = vec![
+        InterfaceInfo {
+            number: 0,
+            class: 0x03,
+            subclass: 0x01,
+            protocol: 0x01,
+            num_endpoints: 1,
+        },
+        InterfaceInfo {
+            number: 1,
+            class: 0x08,
+            subclass: 0x06,
+            protocol: 0x50,
+            num_endpoints: 2,
+        },
+    ];
+    device
+}
+
 /// Create a mock DeviceInfo with specific speed
 pub fn create_mock_device_info_with_speed(
     id: u32,
@@ -121,6 +153,10 @@ pub fn create_mock_device_info_with_speed(
         protocol: 0x00,
         speed,
         num_configurations: 1,
+        interfaces: Vec::new(),
+        stable_id: format!("{:04x}:{:04x}:SN{:06}", vendor_id, product_id, id),
+        port_numbers: vec![1],
+        parent_hub: None,
     }
 }
 
@@ -218,6 +254,26 @@ pub fn generate_test_endpoint_id() -> iroh::PublicKey {
     iroh::SecretKey::generate(&mut rand::rng()).public()
 }
 
+/// Loopback address of a local endpoint
+///
+/// Endpoints bound with relays disabled have no discovery, so a peer in the
+/// same process dials them on the loopback interface at their bound ports.
+pub fn loopback_endpoint_addr(
+    id: iroh::PublicKey,
+    bound_sockets: &[std::net::SocketAddr],
+) -> iroh::EndpointAddr {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    let addrs = bound_sockets.iter().map(|addr| {
+        let ip = match addr.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        };
+        iroh::TransportAddr::Ip(SocketAddr::new(ip, addr.port()))
+    });
+    iroh::EndpointAddr::from_parts(id, addrs)
+}
+
 /// Create a mock USB descriptor response (GET_DESCRIPTOR Device)
 ///
 /// Returns a standard 18-byte device descriptor
@@ -350,6 +406,16 @@ mod tests {
         assert_eq!(device.class, 0x03); // HID
     }
 
+    #[test]
+    fn test_create_mock_composite_device() {
+        let device = create_mock_composite_device(1);
+
+        assert_eq!(device.class, 0x00); // Defined per interface
+        assert_eq!(device.interfaces.len(), 2);
+        assert_eq!(device.interfaces[0].class, 0x03); // HID
+        assert_eq!(device.interfaces[1].class, 0x08); // Mass Storage
+    }
+
     #[test]
     fn test_create_mock_device_descriptor() {
         let desc = create_mock_device_descriptor();
@@ -1,8 +1,14 @@
 //! USB type abstractions and utilities
+//!
+//! Shared USB-related types used by both server and client, such as the
+//! physical port selectors accepted by device filters (`port:1-1.4`).
 
-// TODO: Implement USB type abstractions
-// This module will contain shared USB-related types and utilities
-// used by both server and client.
+use crate::error::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Prefix of port-path patterns in filters, policies and auto-attach lists
+pub const PORT_PATTERN_PREFIX: &str = "port:";
 
 /// Placeholder for USB device abstraction
 #[derive(Debug, Clone)]
@@ -11,3 +17,130 @@ pub struct UsbDevice {
     pub product_id: u16,
     pub description: String,
 }
+
+/// Physical port selector in Linux sysfs notation
+///
+/// `1-1.4` selects the device on port 4 of the hub at `1-1`; `1-1.*` selects
+/// any device below that hub and `1-*` any device on bus 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortSelector {
+    /// Bus number
+    bus: u8,
+    /// Port numbers from the root hub
+    ports: Vec<u8>,
+    /// Whether devices below `ports` match rather than `ports` itself
+    subtree: bool,
+}
+
+impl PortSelector {
+    /// Parse a `port:` pattern; None if the pattern is not a port selector
+    pub fn from_pattern(pattern: &str) -> Option<Result<Self>> {
+        pattern
+            .strip_prefix(PORT_PATTERN_PREFIX)
+            .map(|path| path.parse())
+    }
+
+    /// Whether a device at `bus` / `ports` is selected
+    pub fn matches(&self, bus: u8, ports: &[u8]) -> bool {
+        if bus != self.bus {
+            return false;
+        }
+        if self.subtree {
+            ports.len() > self.ports.len() && ports.starts_with(&self.ports)
+        } else {
+            ports == self.ports.as_slice()
+        }
+    }
+}
+
+impl FromStr for PortSelector {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            Error::Config(format!(
+                "Invalid port path '{}': {} (expected e.g. '1-1.4' or '1-1.*')",
+                path, reason
+            ))
+        };
+
+        let (bus, ports) = path
+            .split_once('-')
+            .ok_or_else(|| invalid("missing '-' after the bus number"))?;
+        let bus = bus.parse().map_err(|_| invalid("bad bus number"))?;
+
+        let mut components: Vec<&str> = ports.split('.').collect();
+        let subtree = components.last() == Some(&"*");
+        if subtree {
+            components.pop();
+        }
+        let ports = components
+            .iter()
+            .map(|port| match port.parse::<u8>() {
+                Ok(port) if port > 0 => Ok(port),
+                _ => Err(invalid(&format!("bad port number '{}'", port))),
+            })
+            .collect::<Result<Vec<u8>>>()?;
+
+        if ports.is_empty() && !subtree {
+            return Err(invalid("no port numbers"));
+        }
+        Ok(Self {
+            bus,
+            ports,
+            subtree,
+        })
+    }
+}
+
+impl fmt::Display for PortSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut components: Vec<String> = self.ports.iter().map(|port| port.to_string()).collect();
+        if self.subtree {
+            components.push("*".to_string());
+        }
+        write!(f, "{}-{}", self.bus, components.join("."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_port() {
+        let selector: PortSelector = "1-1.4".parse().unwrap();
+        assert!(selector.matches(1, &[1, 4]));
+        assert!(!selector.matches(1, &[1, 4, 2]));
+        assert!(!selector.matches(1, &[1]));
+        assert!(!selector.matches(2, &[1, 4]));
+        assert_eq!(selector.to_string(), "1-1.4");
+    }
+
+    #[test]
+    fn test_hub_subtree() {
+        let selector: PortSelector = "1-1.*".parse().unwrap();
+        assert!(selector.matches(1, &[1, 4]));
+        assert!(selector.matches(1, &[1, 4, 2]));
+        assert!(!selector.matches(1, &[1]));
+        assert!(!selector.matches(1, &[2, 1]));
+
+        let bus: PortSelector = "3-*".parse().unwrap();
+        assert!(bus.matches(3, &[2]));
+        assert!(!bus.matches(1, &[2]));
+    }
+
+    #[test]
+    fn test_from_pattern() {
+        assert!(PortSelector::from_pattern("04f9:0042").is_none());
+        assert!(PortSelector::from_pattern("port:1-2").unwrap().is_ok());
+
+        let err = PortSelector::from_pattern("port:1-x.2")
+            .unwrap()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("bad port number 'x'"), "{}", err);
+        assert!("1".parse::<PortSelector>().is_err());
+        assert!("1-0".parse::<PortSelector>().is_err());
+    }
+}
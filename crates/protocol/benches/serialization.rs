@@ -59,6 +59,10 @@ fn benchmark_device_list(c: &mut Criterion) {
             protocol: 0x50,
             speed: DeviceSpeed::High,
             num_configurations: 1,
+            interfaces: Vec::new(),
+            stable_id: format!("1234:{:04x}:SN{:08}", 0x5678 + i as u16, i),
+            port_numbers: vec![1],
+            parent_hub: None,
         })
         .collect();
 
@@ -191,6 +195,10 @@ fn benchmark_framing(c: &mut Criterion) {
             protocol: 0x50,
             speed: DeviceSpeed::High,
             num_configurations: 1,
+            interfaces: Vec::new(),
+            stable_id: format!("1234:5678:{}", "C".repeat(30)),
+            port_numbers: vec![1],
+            parent_hub: None,
         })
         .collect();
 
@@ -314,6 +314,10 @@ mod tests {
                 protocol: 0x50,
                 speed: DeviceSpeed::High,
                 num_configurations: 1,
+                interfaces: Vec::new(),
+                stable_id: "1234:5678:ABC123".to_string(),
+                port_numbers: vec![1],
+                parent_hub: None,
             },
             DeviceInfo {
                 id: DeviceId(2),
@@ -329,6 +333,10 @@ mod tests {
                 protocol: 0x00,
                 speed: DeviceSpeed::Full,
                 num_configurations: 1,
+                interfaces: Vec::new(),
+                stable_id: "abcd:ef01@2-1".to_string(),
+                port_numbers: vec![1],
+                parent_hub: None,
             },
         ];
 
@@ -446,6 +454,10 @@ mod tests {
                 protocol: 0x50,
                 speed: DeviceSpeed::High,
                 num_configurations: 1,
+                interfaces: Vec::new(),
+                stable_id: format!("1234:5678:{}", "C".repeat(50)),
+                port_numbers: vec![1],
+                parent_hub: None,
             };
             100 // 100 devices
         ];
@@ -0,0 +1,219 @@
+//! Protocol 1.1 wire layout of `DeviceInfo`
+//!
+//! Since 1.5, `DeviceInfo` also carries the interface list, the stable ID and
+//! the physical port path. Postcard encodes a struct as its bare field
+//! sequence, so older peers cannot decode the extended layout. The original
+//! message variants carrying devices therefore keep the 1.1 layout through
+//! the serde adapters below, and the `V2` variants carry the full struct.
+//! Devices decoded from the 1.1 layout have no interfaces, an empty stable ID
+//! and no port path.
+
+use crate::types::{
+    AggregatedNotification, DeviceHandle, DeviceId, DeviceInfo, DeviceRemovalReason, DeviceSpeed,
+    DeviceStatusChangeReason,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// `DeviceInfo` as encoded by protocol 1.1
+#[derive(Serialize, Deserialize)]
+struct DeviceInfoV1 {
+    id: DeviceId,
+    vendor_id: u16,
+    product_id: u16,
+    bus_number: u8,
+    device_address: u8,
+    manufacturer: Option<String>,
+    product: Option<String>,
+    serial_number: Option<String>,
+    class: u8,
+    subclass: u8,
+    protocol: u8,
+    speed: DeviceSpeed,
+    num_configurations: u8,
+}
+
+impl From<&DeviceInfo> for DeviceInfoV1 {
+    fn from(device: &DeviceInfo) -> Self {
+        Self {
+            id: device.id,
+            vendor_id: device.vendor_id,
+            product_id: device.product_id,
+            bus_number: device.bus_number,
+            device_address: device.device_address,
+            manufacturer: device.manufacturer.clone(),
+            product: device.product.clone(),
+            serial_number: device.serial_number.clone(),
+            class: device.class,
+            subclass: device.subclass,
+            protocol: device.protocol,
+            speed: device.speed,
+            num_configurations: device.num_configurations,
+        }
+    }
+}
+
+impl From<DeviceInfoV1> for DeviceInfo {
+    fn from(device: DeviceInfoV1) -> Self {
+        Self {
+            id: device.id,
+            vendor_id: device.vendor_id,
+            product_id: device.product_id,
+            bus_number: device.bus_number,
+            device_address: device.device_address,
+            manufacturer: device.manufacturer,
+            product: device.product,
+            serial_number: device.serial_number,
+            class: device.class,
+            subclass: device.subclass,
+            protocol: device.protocol,
+            speed: device.speed,
+            num_configurations: device.num_configurations,
+            interfaces: Vec::new(),
+            stable_id: String::new(),
+            port_numbers: Vec::new(),
+            parent_hub: None,
+        }
+    }
+}
+
+/// `AggregatedNotification` as encoded by protocol 1.1
+///
+/// Variant order must match [`AggregatedNotification`].
+#[derive(Serialize, Deserialize)]
+enum AggregatedNotificationV1 {
+    Arrived(DeviceInfoV1),
+    Removed {
+        device_id: DeviceId,
+        invalidated_handles: Vec<DeviceHandle>,
+        reason: DeviceRemovalReason,
+    },
+    StatusChanged {
+        device_id: DeviceId,
+        device_info: Option<DeviceInfoV1>,
+        reason: DeviceStatusChangeReason,
+    },
+}
+
+impl From<&AggregatedNotification> for AggregatedNotificationV1 {
+    fn from(notification: &AggregatedNotification) -> Self {
+        match notification {
+            AggregatedNotification::Arrived(device) => Self::Arrived(device.into()),
+            AggregatedNotification::Removed {
+                device_id,
+                invalidated_handles,
+                reason,
+            } => Self::Removed {
+                device_id: *device_id,
+                invalidated_handles: invalidated_handles.clone(),
+                reason: reason.clone(),
+            },
+            AggregatedNotification::StatusChanged {
+                device_id,
+                device_info,
+                reason,
+            } => Self::StatusChanged {
+                device_id: *device_id,
+                device_info: device_info.as_ref().map(Into::into),
+                reason: reason.clone(),
+            },
+        }
+    }
+}
+
+impl From<AggregatedNotificationV1> for AggregatedNotification {
+    fn from(notification: AggregatedNotificationV1) -> Self {
+        match notification {
+            AggregatedNotificationV1::Arrived(device) => Self::Arrived(device.into()),
+            AggregatedNotificationV1::Removed {
+                device_id,
+                invalidated_handles,
+                reason,
+            } => Self::Removed {
+                device_id,
+                invalidated_handles,
+                reason,
+            },
+            AggregatedNotificationV1::StatusChanged {
+                device_id,
+                device_info,
+                reason,
+            } => Self::StatusChanged {
+                device_id,
+                device_info: device_info.map(Into::into),
+                reason,
+            },
+        }
+    }
+}
+
+/// Adapter for a single `DeviceInfo`
+pub(crate) mod device {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(device: &DeviceInfo, serializer: S) -> Result<S::Ok, S::Error> {
+        DeviceInfoV1::from(device).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DeviceInfo, D::Error> {
+        DeviceInfoV1::deserialize(deserializer).map(Into::into)
+    }
+}
+
+/// Adapter for `Option<DeviceInfo>`
+pub(crate) mod optional_device {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        device: &Option<DeviceInfo>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        device
+            .as_ref()
+            .map(DeviceInfoV1::from)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DeviceInfo>, D::Error> {
+        Option::<DeviceInfoV1>::deserialize(deserializer).map(|device| device.map(Into::into))
+    }
+}
+
+/// Adapter for `Vec<DeviceInfo>`
+pub(crate) mod device_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        devices: &[DeviceInfo],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(devices.iter().map(DeviceInfoV1::from))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<DeviceInfo>, D::Error> {
+        Vec::<DeviceInfoV1>::deserialize(deserializer)
+            .map(|devices| devices.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Adapter for `Vec<AggregatedNotification>`
+pub(crate) mod notification_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        notifications: &[AggregatedNotification],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(notifications.iter().map(AggregatedNotificationV1::from))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<AggregatedNotification>, D::Error> {
+        Vec::<AggregatedNotificationV1>::deserialize(deserializer)
+            .map(|notifications| notifications.into_iter().map(Into::into).collect())
+    }
+}
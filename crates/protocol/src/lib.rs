@@ -47,6 +47,7 @@
 pub mod codec;
 pub mod error;
 pub mod integrity;
+mod legacy;
 pub mod messages;
 pub mod types;
 pub mod version;
@@ -61,11 +62,15 @@ pub use codec::{read_framed_async, write_framed_async};
 pub use error::{ProtocolError, Result};
 pub use messages::{Message, MessagePayload};
 pub use types::{
-    AggregatedNotification, AttachError, ClientMetrics, DetachError, DeviceHandle, DeviceId,
-    DeviceInfo, DeviceMetrics, DeviceRemovalReason, DeviceSharingStatus, DeviceSpeed,
-    DeviceStatusChangeReason, ForceDetachReason, InterruptStreamInfo, InterruptStreamStats,
-    IsoPacketDescriptor, IsoPacketResult, LockResult, ProtocolLatencyStats, ProtocolMetrics,
-    QueuePositionUpdate, RequestId, ServerMetricsSummary, SharingMode, SuperSpeedConfig,
+    AggregatedNotification, AttachError, CancelResult, ClientMetrics, ConfigurationDescriptor,
+    DetachError, DeviceDescriptors, DeviceHandle, DeviceId, DeviceInfo, DeviceMetrics,
+    DeviceOperation, DeviceRemovalReason, DeviceSharingStatus, DeviceSpeed,
+    DeviceStatusChangeReason, EndpointDescriptor, ForceDetachReason, InterfaceDescriptor,
+    InterfaceInfo, InterruptStreamInfo, InterruptStreamStats, IsoPacketDescriptor, IsoPacketResult,
+    LockResult, ParentHub, ProtocolLatencyStats, ProtocolMetrics, QueuePositionUpdate, QuotaKind,
+    QuotaUsage, RequestId, Reservation, ReservationError, ReservationEvent, ReservationId,
+    ReservationUpdate, ServerMetricsSummary, SharingMode, StringDescriptor, SuperSpeedConfig,
     TransferResult, TransferType, UnlockResult, UsbError, UsbRequest, UsbResponse,
+    format_port_path,
 };
 pub use version::{CURRENT_VERSION, ProtocolVersion};
@@ -8,10 +8,12 @@
 //! - Connection management (ping/pong, errors)
 
 use crate::types::{
-    AggregatedNotification, AttachError, DetachError, DeviceHandle, DeviceId, DeviceInfo,
-    DeviceRemovalReason, DeviceSharingStatus, DeviceStatusChangeReason, ForceDetachReason,
-    InterruptStreamInfo, InterruptStreamStats, LockResult, ProtocolMetrics, QueuePositionUpdate,
-    ServerMetricsSummary, SharingMode, UnlockResult, UsbRequest, UsbResponse,
+    AggregatedNotification, AttachError, CancelResult, DetachError, DeviceDescriptors,
+    DeviceHandle, DeviceId, DeviceInfo, DeviceOperation, DeviceRemovalReason, DeviceSharingStatus,
+    DeviceStatusChangeReason, ForceDetachReason, InterruptStreamInfo, InterruptStreamStats,
+    LockResult, ProtocolMetrics, QueuePositionUpdate, QuotaUsage, RequestId, Reservation,
+    ReservationError, ReservationId, ReservationUpdate, ServerMetricsSummary, SharingMode,
+    UnlockResult, UsbError, UsbRequest, UsbResponse,
 };
 use crate::version::ProtocolVersion;
 use serde::{Deserialize, Serialize};
@@ -38,9 +40,10 @@ pub enum MessagePayload {
     /// Request list of available USB devices from server
     ListDevicesRequest,
 
-    /// Response containing list of available USB devices
+    /// Response containing list of available USB devices (1.1 device layout)
     ListDevicesResponse {
         /// List of devices available on the server
+        #[serde(with = "crate::legacy::device_list")]
         devices: Vec<DeviceInfo>,
     },
 
@@ -115,9 +118,10 @@ pub enum MessagePayload {
     },
 
     // Hotplug notifications (server -> client, via unidirectional stream)
-    /// Device was hot-plugged (connected) on server
+    /// Device was hot-plugged (connected) on server (1.1 device layout)
     DeviceArrivedNotification {
         /// Full device information
+        #[serde(with = "crate::legacy::device")]
         device: DeviceInfo,
     },
 
@@ -133,12 +137,15 @@ pub enum MessagePayload {
 
     // Capability negotiation (bidirectional, on connection)
     /// Client announces supported features
+    ///
+    /// Always the first message, so it keeps the 1.0 layout; the envelope
+    /// version tells the server which features the client understands.
     ClientCapabilities {
         /// Client supports push notifications
         supports_push_notifications: bool,
     },
 
-    /// Server announces supported features
+    /// Server announces supported features (answer to clients before 1.2)
     ServerCapabilities {
         /// Server will send push notifications
         will_send_notifications: bool,
@@ -172,19 +179,21 @@ pub enum MessagePayload {
         reason: ForceDetachReason,
     },
 
-    /// Device capability/status changed notification (server -> client)
+    /// Device capability/status changed notification (server -> client, 1.1 device layout)
     DeviceStatusChangedNotification {
         /// Device ID that changed
         device_id: DeviceId,
         /// Updated device info (if still available)
+        #[serde(with = "crate::legacy::optional_device")]
         device_info: Option<DeviceInfo>,
         /// Reason for the status change
         reason: DeviceStatusChangeReason,
     },
 
-    /// Aggregated notification batch (server -> client)
+    /// Aggregated notification batch (server -> client, 1.1 device layout)
     AggregatedNotifications {
         /// List of notifications in this batch
+        #[serde(with = "crate::legacy::notification_list")]
         notifications: Vec<AggregatedNotification>,
     },
 
@@ -355,6 +364,208 @@ pub enum MessagePayload {
         /// Statistics from the stopped stream
         stats: Option<InterruptStreamStats>,
     },
+
+    // Persistent transfer channel
+    /// Open the persistent transfer channel (client -> server)
+    ///
+    /// Sent as the first frame of a long-lived bidirectional stream. After it,
+    /// the client writes any number of `SubmitTransfer` frames and the server
+    /// answers each with a `TransferComplete` frame on the same stream. Responses
+    /// may arrive out of order and are matched to requests by `RequestId`.
+    /// Only valid when the server offered `transfer_channel` in
+    /// `ServerCapabilitiesV2`; otherwise each transfer uses its own
+    /// bidirectional stream.
+    OpenTransferChannel,
+
+    /// Cancel an in-flight transfer (client -> server, protocol 1.3+)
+    ///
+    /// Sent on the transfer channel after the matching `SubmitTransfer`. The
+    /// server aborts the USB transfer if it has not completed yet; in that case
+    /// the transfer's `TransferComplete` carries `UsbError::Cancelled`.
+    CancelTransfer {
+        /// Device the transfer was submitted to
+        handle: DeviceHandle,
+        /// Request ID of the transfer to cancel
+        request_id: RequestId,
+    },
+
+    /// Outcome of a `CancelTransfer` request
+    CancelTransferResponse {
+        /// Request ID of the transfer
+        request_id: RequestId,
+        /// Whether the transfer was cancelled
+        result: CancelResult,
+    },
+
+    // Device operations (protocol 1.4+)
+    /// Reset, clear a halt, or change configuration/alternate setting
+    DeviceOperationRequest {
+        /// Handle of the attached device
+        handle: DeviceHandle,
+        /// Operation to perform
+        operation: DeviceOperation,
+    },
+
+    /// Response to device operation request
+    DeviceOperationResponse {
+        /// Device handle
+        handle: DeviceHandle,
+        /// Success (unit) or error
+        result: Result<(), UsbError>,
+    },
+
+    // Interface-level sharing (protocol 1.5+)
+    /// Request to attach only some interfaces of a composite device
+    ///
+    /// Answered with `AttachDeviceResponse`. The remaining interfaces stay
+    /// with the server's kernel drivers or go to other clients.
+    AttachInterfacesRequest {
+        /// ID of the device
+        device_id: DeviceId,
+        /// bInterfaceNumber of each interface to claim
+        interfaces: Vec<u8>,
+    },
+
+    // Descriptor discovery (protocol 1.6+)
+    /// Request the full descriptor set of a device (no attach needed)
+    GetDescriptorsRequest {
+        /// ID of the device
+        device_id: DeviceId,
+    },
+
+    /// Response with the device's descriptors
+    GetDescriptorsResponse {
+        /// ID of the device
+        device_id: DeviceId,
+        /// Descriptors or error
+        result: Result<DeviceDescriptors, UsbError>,
+    },
+
+    // Variants below extend earlier ones. Postcard is not self-describing,
+    // so fields are never added to an existing variant; a new variant is
+    // appended instead and only sent to peers whose version understands it.
+    /// Server announces supported features (answer to clients from 1.2)
+    ServerCapabilitiesV2 {
+        /// Server will send push notifications
+        will_send_notifications: bool,
+        /// Server accepts a persistent transfer channel (see `OpenTransferChannel`)
+        transfer_channel: bool,
+        /// Maximum transfers the server will run concurrently on the channel
+        max_in_flight_transfers: u32,
+    },
+
+    /// `ListDevicesResponse` with full device information (clients from 1.5)
+    ListDevicesResponseV2 {
+        /// List of devices available on the server
+        devices: Vec<DeviceInfo>,
+    },
+
+    /// `DeviceArrivedNotification` with full device information (clients from 1.5)
+    DeviceArrivedNotificationV2 {
+        /// Full device information
+        device: DeviceInfo,
+    },
+
+    /// `DeviceStatusChangedNotification` with full device information (clients from 1.5)
+    DeviceStatusChangedNotificationV2 {
+        /// Device ID that changed
+        device_id: DeviceId,
+        /// Updated device info (if still available)
+        device_info: Option<DeviceInfo>,
+        /// Reason for the status change
+        reason: DeviceStatusChangeReason,
+    },
+
+    /// `AggregatedNotifications` with full device information (clients from 1.5)
+    AggregatedNotificationsV2 {
+        /// List of notifications in this batch
+        notifications: Vec<AggregatedNotification>,
+    },
+
+    // Client approval (protocol 1.9+)
+    /// The operator has not approved this client yet
+    ///
+    /// Answers `ClientCapabilities` from clients the server neither approved
+    /// nor denied. The server keeps the stream open and later writes
+    /// `ApprovalGranted`, after which the client repeats the capability
+    /// exchange, or `ApprovalDenied` before closing the connection.
+    ApprovalPending,
+
+    /// The operator approved this client
+    ApprovalGranted,
+
+    /// The operator denied this client
+    ApprovalDenied,
+
+    // Pairing invites (protocol 1.10+)
+    /// `ClientCapabilities` carrying a pairing invite (clients from 1.10)
+    ///
+    /// Only sent by clients holding an invite token. A server that does not
+    /// know the client yet redeems the token instead of parking it and
+    /// answers `ApprovalGranted`, or an `Error` when the token is invalid,
+    /// expired or already used.
+    ClientCapabilitiesV2 {
+        /// Client supports push notifications
+        supports_push_notifications: bool,
+        /// Single-use token from a `p2p-usb://connect/...?invite=` URL
+        invite_token: Option<String>,
+    },
+
+    // Usage quotas (protocol 1.11+)
+    /// Ask for the client's usage quotas (client -> server)
+    GetQuotaStatusRequest,
+
+    /// Usage quotas that apply to the client (server -> client)
+    ///
+    /// Answers `GetQuotaStatusRequest`, and is pushed after each attach
+    /// while any quota applies. Time quotas are listed for attached devices.
+    QuotaStatus {
+        /// Usage of each quota in its current period
+        quotas: Vec<QuotaUsage>,
+    },
+
+    // Device reservations (protocol 1.12+)
+    /// Book a device for a period of time (client -> server)
+    CreateReservationRequest {
+        /// Device to reserve
+        device_id: DeviceId,
+        /// Start of the period, Unix seconds (a past start means now)
+        starts_at: u64,
+        /// End of the period, Unix seconds
+        ends_at: u64,
+        /// Free-form note shown to other clients
+        note: Option<String>,
+    },
+
+    /// Result of a reservation request (server -> client)
+    CreateReservationResponse {
+        result: Result<Reservation, ReservationError>,
+    },
+
+    /// List current and upcoming reservations (client -> server)
+    ListReservationsRequest {
+        /// Only list reservations of this device
+        device_id: Option<DeviceId>,
+    },
+
+    /// Current and upcoming reservations, ordered by start (server -> client)
+    ///
+    /// Lists every client's bookings of devices the client may use, so it
+    /// can plan around them.
+    ListReservationsResponse { reservations: Vec<Reservation> },
+
+    /// Cancel one of the client's reservations (client -> server)
+    CancelReservationRequest { id: ReservationId },
+
+    /// Result of a cancel request (server -> client)
+    CancelReservationResponse {
+        result: Result<(), ReservationError>,
+    },
+
+    /// A reservation started or ended (server -> client, push)
+    ///
+    /// Sent to the holder, and to clients attached to the reserved device.
+    ReservationNotification { update: ReservationUpdate },
 }
 
 #[cfg(test)]
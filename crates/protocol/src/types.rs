@@ -7,8 +7,9 @@ use serde::{Deserialize, Serialize};
 
 /// Unique device identifier (server-assigned)
 ///
-/// Used to identify USB devices on the server. This ID is stable for the
-/// lifetime of the device connection and is used when attaching to devices.
+/// Used to identify USB devices on the server and when attaching to devices.
+/// Servers persist the ID for each `DeviceInfo::stable_id`, so a device keeps
+/// its ID across replugs and server restarts.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct DeviceId(pub u32);
 
@@ -60,6 +61,171 @@ pub struct DeviceInfo {
     pub speed: DeviceSpeed,
     /// Number of configurations
     pub num_configurations: u8,
+    /// Interfaces of the active configuration (alternate setting 0)
+    ///
+    /// Clients pick from these for `AttachInterfacesRequest`. Empty when the
+    /// configuration descriptor could not be read.
+    pub interfaces: Vec<InterfaceInfo>,
+    /// Identity that survives replugs and server restarts
+    ///
+    /// `vvvv:pppp:SERIAL` for devices with a serial number, otherwise
+    /// `vvvv:pppp@BUS-PORT.PORT` from the physical port path (e.g.
+    /// `1050:0407@1-1.3`).
+    pub stable_id: String,
+    /// Port numbers from the root hub down to the device
+    ///
+    /// With `bus_number` this is the physical port path (bus 1, ports
+    /// `[1, 4]` is `1-1.4`), which unlike `device_address` survives replugs.
+    /// Empty if the server could not read it.
+    pub port_numbers: Vec<u8>,
+    /// Hub the device is plugged into (None if unknown)
+    pub parent_hub: Option<ParentHub>,
+}
+
+impl DeviceInfo {
+    /// Physical port path in Linux sysfs notation (e.g. `1-1.4`)
+    pub fn port_path(&self) -> String {
+        format_port_path(self.bus_number, &self.port_numbers)
+    }
+}
+
+/// Hub a device is plugged into
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParentHub {
+    /// USB Vendor ID of the hub
+    pub vendor_id: u16,
+    /// USB Product ID of the hub
+    pub product_id: u16,
+    /// Port numbers of the hub itself (empty for a root hub)
+    pub port_numbers: Vec<u8>,
+}
+
+/// Format a bus and port chain as a port path (`1-1.4`, or `1` for no ports)
+pub fn format_port_path(bus: u8, port_numbers: &[u8]) -> String {
+    if port_numbers.is_empty() {
+        return bus.to_string();
+    }
+    let ports: Vec<String> = port_numbers.iter().map(|port| port.to_string()).collect();
+    format!("{}-{}", bus, ports.join("."))
+}
+
+/// Interface of a USB device's active configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceInfo {
+    /// bInterfaceNumber
+    pub number: u8,
+    /// USB interface class
+    pub class: u8,
+    /// USB interface subclass
+    pub subclass: u8,
+    /// USB interface protocol
+    pub protocol: u8,
+    /// Number of endpoints (excluding endpoint 0)
+    pub num_endpoints: u8,
+}
+
+/// Full descriptor set of a device, returned by `GetDescriptorsRequest`
+///
+/// Configurations are parsed into a tree; class-specific descriptors that
+/// follow a configuration, interface or endpoint are kept raw in `extra`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceDescriptors {
+    /// bcdUSB
+    pub usb_version: u16,
+    /// bcdDevice
+    pub device_version: u16,
+    /// bMaxPacketSize0
+    pub max_packet_size0: u8,
+    /// iManufacturer (0 = none)
+    pub manufacturer_index: u8,
+    /// iProduct (0 = none)
+    pub product_index: u8,
+    /// iSerialNumber (0 = none)
+    pub serial_number_index: u8,
+    /// bConfigurationValue of the active configuration (None if unconfigured)
+    pub active_configuration: Option<u8>,
+    /// All configurations of the device
+    pub configurations: Vec<ConfigurationDescriptor>,
+    /// Raw BOS descriptor set (USB 2.01+ devices only)
+    pub bos: Option<Vec<u8>>,
+    /// String descriptors referenced by the other descriptors
+    ///
+    /// Empty when the server could not open the device.
+    pub strings: Vec<StringDescriptor>,
+}
+
+impl DeviceDescriptors {
+    /// Look up a string descriptor by index
+    pub fn string(&self, index: u8) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|s| s.index == index)
+            .map(|s| s.value.as_str())
+    }
+}
+
+/// Configuration descriptor with its interfaces
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigurationDescriptor {
+    /// bConfigurationValue
+    pub value: u8,
+    /// iConfiguration (0 = none)
+    pub string_index: u8,
+    /// bmAttributes
+    pub attributes: u8,
+    /// Maximum power draw in mA
+    pub max_power_ma: u16,
+    /// Every alternate setting of every interface
+    pub interfaces: Vec<InterfaceDescriptor>,
+    /// Class-specific descriptors following the configuration descriptor
+    pub extra: Vec<u8>,
+}
+
+/// Interface descriptor (one alternate setting) with its endpoints
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceDescriptor {
+    /// bInterfaceNumber
+    pub number: u8,
+    /// bAlternateSetting
+    pub alt_setting: u8,
+    /// USB interface class
+    pub class: u8,
+    /// USB interface subclass
+    pub subclass: u8,
+    /// USB interface protocol
+    pub protocol: u8,
+    /// iInterface (0 = none)
+    pub string_index: u8,
+    /// Endpoints of this alternate setting
+    pub endpoints: Vec<EndpointDescriptor>,
+    /// Class-specific descriptors (e.g. HID, CDC functional descriptors)
+    pub extra: Vec<u8>,
+}
+
+/// Endpoint descriptor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointDescriptor {
+    /// bEndpointAddress (bit 7 set for IN)
+    pub address: u8,
+    /// bmAttributes (transfer, synchronization and usage type)
+    pub attributes: u8,
+    /// wMaxPacketSize, including the high-bandwidth multiplier bits
+    pub max_packet_size: u16,
+    /// bInterval
+    pub interval: u8,
+    /// Class-specific and SuperSpeed companion descriptors
+    pub extra: Vec<u8>,
+}
+
+/// Decoded string descriptor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StringDescriptor {
+    /// Descriptor index
+    pub index: u8,
+    /// LANGID the string was read in
+    pub language: u16,
+    /// String contents
+    pub value: String,
 }
 
 /// USB device speed
@@ -281,6 +447,8 @@ pub enum UsbError {
     Access,
     /// Other error with message
     Other { message: String },
+    /// Transfer was cancelled by a `CancelTransfer` request
+    Cancelled,
 }
 
 impl From<AttachError> for UsbError {
@@ -418,6 +586,207 @@ pub enum ForceDetachReason {
     ServerShutdown,
     /// Device was physically disconnected
     DeviceDisconnected,
+    /// A usage quota ran out (policy enforcement, clients from 1.11)
+    QuotaExceeded {
+        /// The quota that ran out
+        quota: QuotaUsage,
+    },
+    /// Another client's reservation of the device started (clients from 1.12)
+    Reserved {
+        /// The reservation that took the device
+        reservation: Reservation,
+    },
+}
+
+/// Kind of a rolling usage quota
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum QuotaKind {
+    /// Attach time per device per day, in seconds
+    DailyTime,
+    /// Bytes transferred per day, across all devices
+    DailyData,
+    /// Bytes transferred per calendar month, across all devices
+    MonthlyData,
+}
+
+impl std::fmt::Display for QuotaKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaKind::DailyTime => write!(f, "daily time"),
+            QuotaKind::DailyData => write!(f, "daily data"),
+            QuotaKind::MonthlyData => write!(f, "monthly data"),
+        }
+    }
+}
+
+/// How much of a usage quota a client has used in the current period
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// Which quota this is
+    pub kind: QuotaKind,
+    /// Device the quota applies to (time quotas only)
+    pub device_id: Option<DeviceId>,
+    /// Used in the current period (seconds or bytes)
+    pub used: u64,
+    /// Allowed per period (seconds or bytes)
+    pub limit: u64,
+    /// When the current period ends, in the server's time zone
+    pub resets_at: String,
+}
+
+impl QuotaUsage {
+    /// What is left of the quota (seconds or bytes)
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.used)
+    }
+
+    /// Whether the quota is used up
+    pub fn is_exhausted(&self) -> bool {
+        self.used >= self.limit
+    }
+}
+
+impl std::fmt::Display for QuotaUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            QuotaKind::DailyTime => write!(
+                f,
+                "{} quota{}: {}m of {}m used, resets {}",
+                self.kind,
+                self.device_id
+                    .map(|id| format!(" for device {}", id.0))
+                    .unwrap_or_default(),
+                self.used / 60,
+                self.limit / 60,
+                self.resets_at
+            ),
+            QuotaKind::DailyData | QuotaKind::MonthlyData => write!(
+                f,
+                "{} quota: {:.1} of {:.1} MB used, resets {}",
+                self.kind,
+                self.used as f64 / 1_000_000.0,
+                self.limit as f64 / 1_000_000.0,
+                self.resets_at
+            ),
+        }
+    }
+}
+
+/// Server-assigned reservation identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ReservationId(pub u64);
+
+/// A booking of a device for a period of time
+///
+/// While a reservation is active only its holder can attach or lock the
+/// device. Times are Unix timestamps in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Reservation {
+    /// Server-assigned ID
+    pub id: ReservationId,
+    /// Reserved device
+    pub device_id: DeviceId,
+    /// Endpoint ID of the client holding the reservation
+    pub holder: String,
+    /// Name the server knows the holder by, if any
+    pub holder_name: Option<String>,
+    /// Start of the reservation (inclusive)
+    pub starts_at: u64,
+    /// End of the reservation (exclusive)
+    pub ends_at: u64,
+    /// Free-form note from the holder
+    pub note: Option<String>,
+}
+
+impl Reservation {
+    /// Whether the reservation covers the given time
+    pub fn is_active_at(&self, unix_secs: u64) -> bool {
+        self.starts_at <= unix_secs && unix_secs < self.ends_at
+    }
+
+    /// Whether the reservation overlaps the period `[starts_at, ends_at)`
+    pub fn overlaps(&self, starts_at: u64, ends_at: u64) -> bool {
+        self.starts_at < ends_at && starts_at < self.ends_at
+    }
+
+    /// Holder name, or the endpoint ID when the server has no name for it
+    pub fn holder_label(&self) -> &str {
+        self.holder_name.as_deref().unwrap_or(&self.holder)
+    }
+}
+
+/// Why a reservation request failed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ReservationError {
+    /// Device ID not known to the server
+    DeviceNotFound,
+    /// Policy does not let the client use the device
+    NotAllowed {
+        /// Human-readable reason
+        reason: String,
+    },
+    /// The period overlaps an existing reservation
+    Conflict {
+        /// The reservation in the way
+        reservation: Reservation,
+    },
+    /// The period is empty, in the past or beyond the server's limits
+    InvalidPeriod {
+        /// Human-readable reason
+        reason: String,
+    },
+    /// No reservation with that ID
+    NotFound,
+    /// The reservation belongs to another client
+    NotHolder,
+}
+
+impl std::fmt::Display for ReservationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReservationError::DeviceNotFound => write!(f, "device not found"),
+            ReservationError::NotAllowed { reason } => write!(f, "not allowed: {}", reason),
+            ReservationError::Conflict { reservation } => write!(
+                f,
+                "overlaps reservation {} by {}",
+                reservation.id.0,
+                reservation.holder_label()
+            ),
+            ReservationError::InvalidPeriod { reason } => write!(f, "invalid period: {}", reason),
+            ReservationError::NotFound => write!(f, "reservation not found"),
+            ReservationError::NotHolder => write!(f, "reservation belongs to another client"),
+        }
+    }
+}
+
+/// What happened to a reservation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ReservationEvent {
+    /// The reservation period began
+    Started,
+    /// The reservation period ended
+    Ended,
+    /// The reservation was cancelled while active
+    Cancelled,
+}
+
+impl std::fmt::Display for ReservationEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReservationEvent::Started => write!(f, "started"),
+            ReservationEvent::Ended => write!(f, "ended"),
+            ReservationEvent::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// Reservation start/end notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReservationUpdate {
+    /// The reservation concerned
+    pub reservation: Reservation,
+    /// What happened to it
+    pub event: ReservationEvent,
 }
 
 /// Serializable latency statistics for protocol exchange
@@ -694,6 +1063,42 @@ pub enum LockResult {
     NotAvailable { reason: String },
 }
 
+/// Device-level operation performed with a dedicated libusb call
+///
+/// These requests arrive as standard control transfers on endpoint 0, but
+/// libusb refuses them as raw transfers on a handle with claimed interfaces.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DeviceOperation {
+    /// Port reset (`libusb_reset_device`)
+    Reset,
+    /// CLEAR_FEATURE(ENDPOINT_HALT) (`libusb_clear_halt`)
+    ClearHalt {
+        /// Endpoint address (includes direction bit)
+        endpoint: u8,
+    },
+    /// SET_CONFIGURATION (`libusb_set_configuration`)
+    SetConfiguration {
+        /// bConfigurationValue to activate
+        configuration: u8,
+    },
+    /// SET_INTERFACE (`libusb_set_interface_alt_setting`)
+    SetInterface {
+        /// bInterfaceNumber
+        interface: u8,
+        /// bAlternateSetting to select
+        alt_setting: u8,
+    },
+}
+
+/// Result of a transfer cancellation request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CancelResult {
+    /// Transfer was cancelled; its `TransferComplete` carries `UsbError::Cancelled`
+    Cancelled,
+    /// Transfer was unknown or had already completed
+    NotFound,
+}
+
 /// Result of an unlock request
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum UnlockResult {
@@ -13,7 +13,7 @@ pub struct ProtocolVersion {
 /// Current protocol version
 pub const CURRENT_VERSION: ProtocolVersion = ProtocolVersion {
     major: 1,
-    minor: 1,
+    minor: 12,
     patch: 0,
 };
 
@@ -22,6 +22,61 @@ impl ProtocolVersion {
     pub fn is_compatible_with(&self, other: &ProtocolVersion) -> bool {
         self.major == other.major && self.minor >= other.minor
     }
+
+    /// Whether a peer at this version understands `ServerCapabilitiesV2` and
+    /// the persistent transfer channel
+    pub fn supports_transfer_channel(&self) -> bool {
+        self.major == 1 && self.minor >= 2
+    }
+
+    /// Whether a peer at this version understands `CancelTransfer`
+    pub fn supports_cancel_transfer(&self) -> bool {
+        self.major == 1 && self.minor >= 3
+    }
+
+    /// Whether a peer at this version understands `DeviceOperationRequest`
+    pub fn supports_device_operations(&self) -> bool {
+        self.major == 1 && self.minor >= 4
+    }
+
+    /// Whether a peer at this version understands `AttachInterfacesRequest`
+    pub fn supports_interface_sharing(&self) -> bool {
+        self.major == 1 && self.minor >= 5
+    }
+
+    /// Whether a peer at this version decodes the `V2` device list and
+    /// notification variants carrying interfaces, stable IDs and port paths
+    pub fn supports_extended_device_info(&self) -> bool {
+        self.major == 1 && self.minor >= 5
+    }
+
+    /// Whether a peer at this version understands `GetDescriptorsRequest`
+    pub fn supports_descriptors(&self) -> bool {
+        self.major == 1 && self.minor >= 6
+    }
+
+    /// Whether a peer at this version waits through `ApprovalPending`
+    pub fn supports_client_approval(&self) -> bool {
+        self.major == 1 && self.minor >= 9
+    }
+
+    /// Whether a peer at this version understands `ClientCapabilitiesV2`
+    /// and pairing invites
+    pub fn supports_invites(&self) -> bool {
+        self.major == 1 && self.minor >= 10
+    }
+
+    /// Whether a peer at this version understands `QuotaStatus` and
+    /// `ForceDetachReason::QuotaExceeded`
+    pub fn supports_quotas(&self) -> bool {
+        self.major == 1 && self.minor >= 11
+    }
+
+    /// Whether a peer at this version understands the reservation messages
+    /// and `ForceDetachReason::Reserved`
+    pub fn supports_reservations(&self) -> bool {
+        self.major == 1 && self.minor >= 12
+    }
 }
 
 #[cfg(test)]
@@ -50,4 +105,31 @@ mod tests {
         assert!(!v1_0_0.is_compatible_with(&v1_1_0));
         assert!(!v2_0_0.is_compatible_with(&v1_0_0));
     }
+
+    #[test]
+    fn test_feature_support() {
+        let version = |minor| ProtocolVersion {
+            major: 1,
+            minor,
+            patch: 0,
+        };
+        let cases: [(fn(&ProtocolVersion) -> bool, u8); 10] = [
+            (ProtocolVersion::supports_transfer_channel, 2),
+            (ProtocolVersion::supports_cancel_transfer, 3),
+            (ProtocolVersion::supports_device_operations, 4),
+            (ProtocolVersion::supports_interface_sharing, 5),
+            (ProtocolVersion::supports_extended_device_info, 5),
+            (ProtocolVersion::supports_descriptors, 6),
+            (ProtocolVersion::supports_client_approval, 9),
+            (ProtocolVersion::supports_invites, 10),
+            (ProtocolVersion::supports_quotas, 11),
+            (ProtocolVersion::supports_reservations, 12),
+        ];
+
+        for (supports, minor) in cases {
+            assert!(!supports(&version(minor - 1)), "1.{} supported", minor - 1);
+            assert!(supports(&version(minor)), "1.{} not supported", minor);
+            assert!(supports(&CURRENT_VERSION));
+        }
+    }
 }
@@ -36,6 +36,10 @@ fn create_test_device(id: u32, vendor_id: u16, product_id: u16) -> DeviceInfo {
         protocol: 0x00,
         speed: DeviceSpeed::High,
         num_configurations: 1,
+        interfaces: Vec::new(),
+        stable_id: format!("{:04x}:{:04x}:SN{:06}", vendor_id, product_id, id),
+        port_numbers: vec![1],
+        parent_hub: None,
     }
 }
 
@@ -4,12 +4,15 @@
 //! verifying codec round-trips and version compatibility.
 
 use protocol::{
-    AggregatedNotification, AttachError, ClientMetrics, DetachError, DeviceHandle, DeviceId,
-    DeviceInfo, DeviceMetrics, DeviceRemovalReason, DeviceSharingStatus, DeviceSpeed,
-    DeviceStatusChangeReason, ForceDetachReason, IsoPacketDescriptor, LockResult, Message,
-    MessagePayload, ProtocolLatencyStats, ProtocolMetrics, ProtocolVersion, QueuePositionUpdate,
-    RequestId, ServerMetricsSummary, SharingMode, TransferResult, TransferType, UnlockResult,
-    UsbError, UsbRequest, UsbResponse, CURRENT_VERSION,
+    AggregatedNotification, AttachError, CancelResult, ClientMetrics, ConfigurationDescriptor,
+    DetachError, DeviceDescriptors, DeviceHandle, DeviceId, DeviceInfo, DeviceMetrics,
+    DeviceOperation, DeviceRemovalReason, DeviceSharingStatus, DeviceSpeed,
+    DeviceStatusChangeReason, EndpointDescriptor, ForceDetachReason, InterfaceDescriptor,
+    InterfaceInfo, IsoPacketDescriptor, LockResult, Message, MessagePayload,
+    ProtocolLatencyStats, ProtocolMetrics, ProtocolVersion, QueuePositionUpdate, QuotaKind,
+    QuotaUsage, RequestId, Reservation, ReservationError, ReservationEvent, ReservationId,
+    ReservationUpdate, ServerMetricsSummary, SharingMode, StringDescriptor, TransferResult,
+    TransferType, UnlockResult, UsbError, UsbRequest, UsbResponse, CURRENT_VERSION,
 };
 use protocol::{decode_framed, decode_message, encode_framed, encode_message, validate_version};
 use std::io::Cursor;
@@ -29,6 +32,22 @@ fn make_test_device_info(id: u32) -> DeviceInfo {
         protocol: 0x50,
         speed: DeviceSpeed::High,
         num_configurations: 1,
+        interfaces: Vec::new(),
+        stable_id: format!("1234:5678:SN{:08}", id),
+        port_numbers: vec![1],
+        parent_hub: None,
+    }
+}
+
+fn make_test_reservation() -> Reservation {
+    Reservation {
+        id: ReservationId(7),
+        device_id: DeviceId(3),
+        holder: "ab".repeat(32),
+        holder_name: Some("hil-rig-1".to_string()),
+        starts_at: 1_792_245_600,
+        ends_at: 1_792_252_800,
+        note: Some("JTAG flashing".to_string()),
     }
 }
 
@@ -369,6 +388,419 @@ mod message_roundtrip {
             }
         }
     }
+
+    #[test]
+    fn test_server_capabilities_v2_roundtrip() {
+        let msg = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::ServerCapabilitiesV2 {
+                will_send_notifications: true,
+                transfer_channel: true,
+                max_in_flight_transfers: 32,
+            },
+        };
+
+        let bytes = encode_message(&msg).expect("Failed to encode");
+        let decoded = decode_message(&bytes).expect("Failed to decode");
+
+        match decoded.payload {
+            MessagePayload::ServerCapabilitiesV2 {
+                will_send_notifications,
+                transfer_channel,
+                max_in_flight_transfers,
+            } => {
+                assert!(will_send_notifications);
+                assert!(transfer_channel);
+                assert_eq!(max_in_flight_transfers, 32);
+            }
+            _ => panic!("Expected ServerCapabilitiesV2"),
+        }
+    }
+
+    #[test]
+    fn test_approval_messages_roundtrip() {
+        for payload in [
+            MessagePayload::ApprovalPending,
+            MessagePayload::ApprovalGranted,
+            MessagePayload::ApprovalDenied,
+        ] {
+            let msg = Message {
+                version: CURRENT_VERSION,
+                payload: payload.clone(),
+            };
+
+            let bytes = encode_message(&msg).expect("Failed to encode");
+            let decoded = decode_message(&bytes).expect("Failed to decode");
+            assert_eq!(
+                std::mem::discriminant(&decoded.payload),
+                std::mem::discriminant(&payload)
+            );
+        }
+    }
+
+    #[test]
+    fn test_client_capabilities_v2_roundtrip() {
+        let msg = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::ClientCapabilitiesV2 {
+                supports_push_notifications: true,
+                invite_token: Some("0123456789abcdef".to_string()),
+            },
+        };
+
+        let bytes = encode_message(&msg).expect("Failed to encode");
+        let decoded = decode_message(&bytes).expect("Failed to decode");
+
+        match decoded.payload {
+            MessagePayload::ClientCapabilitiesV2 {
+                supports_push_notifications,
+                invite_token,
+            } => {
+                assert!(supports_push_notifications);
+                assert_eq!(invite_token.as_deref(), Some("0123456789abcdef"));
+            }
+            _ => panic!("Expected ClientCapabilitiesV2"),
+        }
+    }
+
+    #[test]
+    fn test_quota_status_roundtrip() {
+        let quotas = vec![
+            QuotaUsage {
+                kind: QuotaKind::DailyTime,
+                device_id: Some(DeviceId(3)),
+                used: 5400,
+                limit: 7200,
+                resets_at: "2026-10-18T00:00:00+03:00[Europe/Helsinki]".to_string(),
+            },
+            QuotaUsage {
+                kind: QuotaKind::MonthlyData,
+                device_id: None,
+                used: 60_000_000_000,
+                limit: 50_000_000_000,
+                resets_at: "2026-11-01T00:00:00+02:00[Europe/Helsinki]".to_string(),
+            },
+        ];
+        let msg = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::QuotaStatus {
+                quotas: quotas.clone(),
+            },
+        };
+
+        let bytes = encode_message(&msg).expect("Failed to encode");
+        let decoded = decode_message(&bytes).expect("Failed to decode");
+
+        match decoded.payload {
+            MessagePayload::QuotaStatus {
+                quotas: decoded_quotas,
+            } => {
+                assert_eq!(decoded_quotas, quotas);
+                assert_eq!(decoded_quotas[0].remaining(), 1800);
+                assert!(!decoded_quotas[0].is_exhausted());
+                assert_eq!(decoded_quotas[1].remaining(), 0);
+                assert!(decoded_quotas[1].is_exhausted());
+            }
+            _ => panic!("Expected QuotaStatus"),
+        }
+
+        let request = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::GetQuotaStatusRequest,
+        };
+        let bytes = encode_message(&request).expect("Failed to encode");
+        let decoded = decode_message(&bytes).expect("Failed to decode");
+        assert!(matches!(
+            decoded.payload,
+            MessagePayload::GetQuotaStatusRequest
+        ));
+    }
+
+    #[test]
+    fn test_reservation_roundtrip() {
+        let reservation = make_test_reservation();
+        assert!(reservation.is_active_at(1_792_245_600));
+        assert!(!reservation.is_active_at(1_792_252_800));
+        assert!(reservation.overlaps(1_792_252_000, 1_792_260_000));
+        assert!(!reservation.overlaps(1_792_252_800, 1_792_260_000));
+        assert_eq!(reservation.holder_label(), "hil-rig-1");
+
+        let payloads = vec![
+            MessagePayload::CreateReservationRequest {
+                device_id: DeviceId(3),
+                starts_at: reservation.starts_at,
+                ends_at: reservation.ends_at,
+                note: reservation.note.clone(),
+            },
+            MessagePayload::CreateReservationResponse {
+                result: Ok(reservation.clone()),
+            },
+            MessagePayload::CreateReservationResponse {
+                result: Err(ReservationError::Conflict {
+                    reservation: reservation.clone(),
+                }),
+            },
+            MessagePayload::ListReservationsRequest {
+                device_id: Some(DeviceId(3)),
+            },
+            MessagePayload::ListReservationsResponse {
+                reservations: vec![reservation.clone()],
+            },
+            MessagePayload::CancelReservationRequest {
+                id: ReservationId(7),
+            },
+            MessagePayload::CancelReservationResponse {
+                result: Err(ReservationError::NotHolder),
+            },
+            MessagePayload::ReservationNotification {
+                update: ReservationUpdate {
+                    reservation: reservation.clone(),
+                    event: ReservationEvent::Started,
+                },
+            },
+        ];
+
+        for payload in payloads {
+            let msg = Message {
+                version: CURRENT_VERSION,
+                payload,
+            };
+            let bytes = encode_message(&msg).expect("Failed to encode");
+            let decoded = decode_message(&bytes).expect("Failed to decode");
+            assert_eq!(
+                format!("{:?}", decoded.payload),
+                format!("{:?}", msg.payload)
+            );
+        }
+    }
+
+    #[test]
+    fn test_open_transfer_channel_roundtrip() {
+        let msg = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::OpenTransferChannel,
+        };
+
+        let bytes = encode_message(&msg).expect("Failed to encode");
+        let decoded = decode_message(&bytes).expect("Failed to decode");
+
+        assert!(matches!(decoded.payload, MessagePayload::OpenTransferChannel));
+    }
+
+    #[test]
+    fn test_cancel_transfer_roundtrip() {
+        let msg = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::CancelTransfer {
+                handle: DeviceHandle(3),
+                request_id: RequestId(77),
+            },
+        };
+
+        let bytes = encode_message(&msg).expect("Failed to encode");
+        let decoded = decode_message(&bytes).expect("Failed to decode");
+
+        match decoded.payload {
+            MessagePayload::CancelTransfer { handle, request_id } => {
+                assert_eq!(handle, DeviceHandle(3));
+                assert_eq!(request_id, RequestId(77));
+            }
+            _ => panic!("Expected CancelTransfer"),
+        }
+
+        for result in [CancelResult::Cancelled, CancelResult::NotFound] {
+            let msg = Message {
+                version: CURRENT_VERSION,
+                payload: MessagePayload::CancelTransferResponse {
+                    request_id: RequestId(77),
+                    result: result.clone(),
+                },
+            };
+
+            let bytes = encode_message(&msg).expect("Failed to encode");
+            match decode_message(&bytes).expect("Failed to decode").payload {
+                MessagePayload::CancelTransferResponse {
+                    request_id,
+                    result: decoded,
+                } => {
+                    assert_eq!(request_id, RequestId(77));
+                    assert_eq!(decoded, result);
+                }
+                _ => panic!("Expected CancelTransferResponse"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_device_operation_roundtrip() {
+        let operations = [
+            DeviceOperation::Reset,
+            DeviceOperation::ClearHalt { endpoint: 0x81 },
+            DeviceOperation::SetConfiguration { configuration: 2 },
+            DeviceOperation::SetInterface {
+                interface: 1,
+                alt_setting: 3,
+            },
+        ];
+
+        for operation in operations {
+            let msg = Message {
+                version: CURRENT_VERSION,
+                payload: MessagePayload::DeviceOperationRequest {
+                    handle: DeviceHandle(5),
+                    operation,
+                },
+            };
+
+            let bytes = encode_message(&msg).expect("Failed to encode");
+            match decode_message(&bytes).expect("Failed to decode").payload {
+                MessagePayload::DeviceOperationRequest {
+                    handle,
+                    operation: decoded,
+                } => {
+                    assert_eq!(handle, DeviceHandle(5));
+                    assert_eq!(decoded, operation);
+                }
+                _ => panic!("Expected DeviceOperationRequest"),
+            }
+        }
+
+        for result in [Ok(()), Err(UsbError::Pipe)] {
+            let msg = Message {
+                version: CURRENT_VERSION,
+                payload: MessagePayload::DeviceOperationResponse {
+                    handle: DeviceHandle(5),
+                    result: result.clone(),
+                },
+            };
+
+            let bytes = encode_message(&msg).expect("Failed to encode");
+            match decode_message(&bytes).expect("Failed to decode").payload {
+                MessagePayload::DeviceOperationResponse {
+                    handle,
+                    result: decoded,
+                } => {
+                    assert_eq!(handle, DeviceHandle(5));
+                    assert_eq!(decoded, result);
+                }
+                _ => panic!("Expected DeviceOperationResponse"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_attach_interfaces_roundtrip() {
+        let msg = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::AttachInterfacesRequest {
+                device_id: DeviceId(3),
+                interfaces: vec![0, 2],
+            },
+        };
+
+        let bytes = encode_message(&msg).expect("Failed to encode");
+        match decode_message(&bytes).expect("Failed to decode").payload {
+            MessagePayload::AttachInterfacesRequest {
+                device_id,
+                interfaces,
+            } => {
+                assert_eq!(device_id, DeviceId(3));
+                assert_eq!(interfaces, vec![0, 2]);
+            }
+            _ => panic!("Expected AttachInterfacesRequest"),
+        }
+    }
+
+    #[test]
+    fn test_device_info_interfaces_roundtrip() {
+        let interface = InterfaceInfo {
+            number: 1,
+            class: 0x08,
+            subclass: 0x06,
+            protocol: 0x50,
+            num_endpoints: 2,
+        };
+        let mut device = make_test_device_info(4);
+        device.interfaces = vec![interface.clone()];
+
+        let msg = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::ListDevicesResponseV2 {
+                devices: vec![device],
+            },
+        };
+
+        let bytes = encode_message(&msg).expect("Failed to encode");
+        match decode_message(&bytes).expect("Failed to decode").payload {
+            MessagePayload::ListDevicesResponseV2 { devices } => {
+                assert_eq!(devices.len(), 1);
+                assert_eq!(devices[0].interfaces, vec![interface]);
+                assert_eq!(devices[0].stable_id, "1234:5678:SN00000004");
+            }
+            _ => panic!("Expected ListDevicesResponseV2"),
+        }
+    }
+
+    #[test]
+    fn test_get_descriptors_roundtrip() {
+        let descriptors = DeviceDescriptors {
+            usb_version: 0x0210,
+            device_version: 0x0100,
+            max_packet_size0: 64,
+            manufacturer_index: 1,
+            product_index: 2,
+            serial_number_index: 0,
+            active_configuration: Some(1),
+            configurations: vec![ConfigurationDescriptor {
+                value: 1,
+                string_index: 0,
+                attributes: 0x80,
+                max_power_ma: 100,
+                interfaces: vec![InterfaceDescriptor {
+                    number: 0,
+                    alt_setting: 0,
+                    class: 0x08,
+                    subclass: 0x06,
+                    protocol: 0x50,
+                    string_index: 0,
+                    endpoints: vec![EndpointDescriptor {
+                        address: 0x81,
+                        attributes: 0x02,
+                        max_packet_size: 512,
+                        interval: 0,
+                        extra: Vec::new(),
+                    }],
+                    extra: Vec::new(),
+                }],
+                extra: Vec::new(),
+            }],
+            bos: Some(vec![0x05, 0x0f, 0x05, 0x00, 0x00]),
+            strings: vec![StringDescriptor {
+                index: 2,
+                language: 0x0409,
+                value: "Flash Drive".to_string(),
+            }],
+        };
+
+        let msg = Message {
+            version: CURRENT_VERSION,
+            payload: MessagePayload::GetDescriptorsResponse {
+                device_id: DeviceId(5),
+                result: Ok(descriptors.clone()),
+            },
+        };
+
+        let bytes = encode_message(&msg).expect("Failed to encode");
+        match decode_message(&bytes).expect("Failed to decode").payload {
+            MessagePayload::GetDescriptorsResponse { device_id, result } => {
+                assert_eq!(device_id, DeviceId(5));
+                let decoded = result.expect("Expected descriptors");
+                assert_eq!(decoded, descriptors);
+                assert_eq!(decoded.string(2), Some("Flash Drive"));
+            }
+            _ => panic!("Expected GetDescriptorsResponse"),
+        }
+    }
 }
 
 mod transfer_messages {
@@ -609,6 +1041,7 @@ mod transfer_messages {
             UsbError::Other {
                 message: "Custom USB error".to_string(),
             },
+            UsbError::Cancelled,
         ];
 
         for error in errors {
@@ -781,6 +1214,18 @@ mod notification_messages {
             ForceDetachReason::AdminAction { reason: None },
             ForceDetachReason::ServerShutdown,
             ForceDetachReason::DeviceDisconnected,
+            ForceDetachReason::QuotaExceeded {
+                quota: QuotaUsage {
+                    kind: QuotaKind::DailyData,
+                    device_id: None,
+                    used: 5_000_000_000,
+                    limit: 5_000_000_000,
+                    resets_at: "2026-10-18T00:00:00+00:00[UTC]".to_string(),
+                },
+            },
+            ForceDetachReason::Reserved {
+                reservation: make_test_reservation(),
+            },
         ];
 
         for reason in reasons {
@@ -1399,6 +1844,171 @@ mod version_compatibility {
         assert!(!v2_0.is_compatible_with(&v1_0));
         assert!(!v1_0.is_compatible_with(&v2_0));
     }
+
+    /// Framed bytes of a protocol 1.1 peer, as encoded by release 0.1.0
+    const V1_1_CLIENT_CAPABILITIES: [u8; 9] = [0, 0, 0, 5, 1, 1, 0, 15, 1];
+    const V1_1_SERVER_CAPABILITIES: [u8; 9] = [0, 0, 0, 5, 1, 1, 0, 16, 1];
+
+    #[test]
+    fn test_decode_v1_1_capabilities() {
+        let client = decode_framed(&V1_1_CLIENT_CAPABILITIES).expect("1.1 client frame");
+        assert_eq!(client.version.minor, 1);
+        assert!(!client.version.supports_transfer_channel());
+        assert!(matches!(
+            client.payload,
+            MessagePayload::ClientCapabilities {
+                supports_push_notifications: true
+            }
+        ));
+
+        let server = decode_framed(&V1_1_SERVER_CAPABILITIES).expect("1.1 server frame");
+        assert!(matches!(
+            server.payload,
+            MessagePayload::ServerCapabilities {
+                will_send_notifications: true
+            }
+        ));
+    }
+
+    #[test]
+    fn test_capabilities_readable_by_v1_1() {
+        // A 1.1 peer decodes these with its own variant indices and layout
+        for (payload, expected) in [
+            (
+                MessagePayload::ClientCapabilities {
+                    supports_push_notifications: true,
+                },
+                V1_1_CLIENT_CAPABILITIES,
+            ),
+            (
+                MessagePayload::ServerCapabilities {
+                    will_send_notifications: true,
+                },
+                V1_1_SERVER_CAPABILITIES,
+            ),
+        ] {
+            let framed = encode_framed(&Message {
+                version: CURRENT_VERSION,
+                payload,
+            })
+            .expect("Failed to encode");
+            // Only the envelope's minor version differs
+            assert_eq!(framed[..5], expected[..5]);
+            assert_eq!(framed[6..], expected[6..]);
+        }
+    }
+
+    /// Device as listed by a 1.1 server, with the fields added since set
+    fn v1_1_device() -> DeviceInfo {
+        DeviceInfo {
+            id: DeviceId(3),
+            vendor_id: 0x1234,
+            product_id: 0x5678,
+            bus_number: 1,
+            device_address: 4,
+            manufacturer: None,
+            product: Some("Key".to_string()),
+            serial_number: None,
+            class: 3,
+            subclass: 0,
+            protocol: 0,
+            speed: DeviceSpeed::Full,
+            num_configurations: 1,
+            interfaces: vec![InterfaceInfo {
+                number: 0,
+                class: 3,
+                subclass: 1,
+                protocol: 1,
+                num_endpoints: 1,
+            }],
+            stable_id: "1234:5678@1-2".to_string(),
+            port_numbers: vec![2],
+            parent_hub: None,
+        }
+    }
+
+    /// `ListDevicesResponse` and `AggregatedNotifications` (arrived, then
+    /// status changed) carrying `v1_1_device`, as encoded by release 0.1.0
+    const V1_1_LIST_DEVICES: [u8; 29] = [
+        0, 0, 0, 25, 1, 1, 0, 1, 1, 3, 180, 36, 248, 172, 1, 1, 4, 0, 1, 3, 75, 101, 121, 0, 3, 0,
+        0, 1, 1,
+    ];
+    const V1_1_AGGREGATED: [u8; 54] = [
+        0, 0, 0, 50, 1, 1, 0, 20, 2, 0, 3, 180, 36, 248, 172, 1, 1, 4, 0, 1, 3, 75, 101, 121, 0, 3,
+        0, 0, 1, 1, 2, 3, 1, 3, 180, 36, 248, 172, 1, 1, 4, 0, 1, 3, 75, 101, 121, 0, 3, 0, 0, 1,
+        1, 0,
+    ];
+
+    #[test]
+    fn test_decode_v1_1_device_list() {
+        let message = decode_framed(&V1_1_LIST_DEVICES).expect("1.1 device list");
+        let MessagePayload::ListDevicesResponse { devices } = message.payload else {
+            panic!("Expected ListDevicesResponse");
+        };
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, DeviceId(3));
+        assert_eq!(devices[0].product.as_deref(), Some("Key"));
+        assert_eq!(devices[0].num_configurations, 1);
+        assert!(devices[0].interfaces.is_empty());
+        assert!(devices[0].stable_id.is_empty());
+
+        let message = decode_framed(&V1_1_AGGREGATED).expect("1.1 notification batch");
+        let MessagePayload::AggregatedNotifications { notifications } = message.payload else {
+            panic!("Expected AggregatedNotifications");
+        };
+        assert!(matches!(
+            &notifications[..],
+            [
+                AggregatedNotification::Arrived(DeviceInfo {
+                    id: DeviceId(3),
+                    ..
+                }),
+                AggregatedNotification::StatusChanged {
+                    device_info: Some(DeviceInfo {
+                        id: DeviceId(3),
+                        ..
+                    }),
+                    reason: DeviceStatusChangeReason::DeviceReset,
+                    ..
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_device_lists_readable_by_v1_1() {
+        let device = v1_1_device();
+        for (payload, expected) in [
+            (
+                MessagePayload::ListDevicesResponse {
+                    devices: vec![device.clone()],
+                },
+                &V1_1_LIST_DEVICES[..],
+            ),
+            (
+                MessagePayload::AggregatedNotifications {
+                    notifications: vec![
+                        AggregatedNotification::Arrived(device.clone()),
+                        AggregatedNotification::StatusChanged {
+                            device_id: device.id,
+                            device_info: Some(device.clone()),
+                            reason: DeviceStatusChangeReason::DeviceReset,
+                        },
+                    ],
+                },
+                &V1_1_AGGREGATED[..],
+            ),
+        ] {
+            let framed = encode_framed(&Message {
+                version: CURRENT_VERSION,
+                payload,
+            })
+            .expect("Failed to encode");
+            // Only the envelope's minor version differs
+            assert_eq!(framed[..5], expected[..5]);
+            assert_eq!(framed[6..], expected[6..]);
+        }
+    }
 }
 
 mod device_speed_variants {
@@ -1429,6 +2039,10 @@ mod device_speed_variants {
                 protocol: 0,
                 speed: speed.clone(),
                 num_configurations: 1,
+                interfaces: Vec::new(),
+                stable_id: "1234:5678@1-1".to_string(),
+                port_numbers: vec![1],
+                parent_hub: None,
             };
 
             let msg = Message {
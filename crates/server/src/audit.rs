@@ -7,13 +7,14 @@
 #![allow(dead_code)]
 
 use crate::config::AuditConfig;
+use crate::directory::SharedClientDirectory;
 use anyhow::{Context, Result};
 use protocol::{DeviceHandle, DeviceId, DeviceInfo};
 use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Write};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, SystemTime};
 use tokio::sync::{Mutex, mpsc};
 use tracing::{debug, error, warn};
@@ -40,7 +41,10 @@ impl AuditLevel {
             AuditLevel::Off => false,
             AuditLevel::Security => matches!(
                 event_type,
-                AuditEventType::AuthenticationFailure | AuditEventType::ConfigurationChange
+                AuditEventType::AuthenticationFailure
+                    | AuditEventType::ConfigurationChange
+                    | AuditEventType::InviteCreated
+                    | AuditEventType::InviteRedeemed
             ),
             AuditLevel::Standard => !matches!(event_type, AuditEventType::TransferStatistics),
             AuditLevel::All => true,
@@ -74,6 +78,10 @@ pub enum AuditEventType {
     DeviceArrived,
     /// Device hotplug (removed)
     DeviceRemoved,
+    /// Pairing invite minted
+    InviteCreated,
+    /// Pairing invite redeemed by a client
+    InviteRedeemed,
 }
 
 /// Result of an operation
@@ -163,6 +171,9 @@ pub struct AuditEntry {
     /// Client EndpointId (if applicable)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub endpoint_id: Option<String>,
+    /// Client name from `[clients]` (if the client has one)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
     /// Device ID (if applicable)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub device_id: Option<u32>,
@@ -190,6 +201,7 @@ impl AuditEntry {
             timestamp,
             event_type,
             endpoint_id: None,
+            client_name: None,
             device_id: None,
             result,
             details: None,
@@ -219,6 +231,20 @@ impl AuditEntry {
         self.details = Some(details);
         self
     }
+
+    /// Entry for a newly minted pairing invite (never the token itself)
+    pub fn invite_created(invite_id: &str, valid_for: Duration, devices: &[String]) -> Self {
+        Self::new(AuditEventType::InviteCreated, AuditResult::Success).with_details(
+            AuditDetails::Message {
+                message: format!(
+                    "Invite {} valid for {}s, devices: {}",
+                    invite_id,
+                    valid_for.as_secs(),
+                    describe_devices(devices)
+                ),
+            },
+        )
+    }
 }
 
 /// Convert Unix timestamp to ISO 8601 format
@@ -282,6 +308,15 @@ fn days_to_ymd(days: u64) -> (u32, u32, u32) {
     (year as u32, month, day)
 }
 
+/// Device scope of an invite for audit messages
+fn describe_devices(devices: &[String]) -> String {
+    if devices.is_empty() {
+        "all".to_string()
+    } else {
+        devices.join(", ")
+    }
+}
+
 /// Check if a year is a leap year
 fn is_leap_year(year: i32) -> bool {
     (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
@@ -303,6 +338,8 @@ pub struct AuditLogger {
     sender: mpsc::UnboundedSender<AuditMessage>,
     /// Configuration
     config: AuditConfig,
+    /// Client names, set once the server has loaded them
+    directory: OnceLock<SharedClientDirectory>,
 }
 
 impl AuditLogger {
@@ -322,16 +359,32 @@ impl AuditLogger {
             writer.run(receiver).await;
         });
 
-        Some(Self { sender, config })
+        Some(Self {
+            sender,
+            config,
+            directory: OnceLock::new(),
+        })
+    }
+
+    /// Name clients in logged entries using `directory`
+    pub fn set_client_directory(&self, directory: SharedClientDirectory) {
+        let _ = self.directory.set(directory);
     }
 
     /// Log an audit entry
-    pub fn log(&self, entry: AuditEntry) {
+    pub fn log(&self, mut entry: AuditEntry) {
         // Check if this event type should be logged
         if !self.config.level.should_log(&entry.event_type) {
             return;
         }
 
+        if entry.client_name.is_none()
+            && let (Some(endpoint_id), Some(directory)) =
+                (entry.endpoint_id.as_deref(), self.directory.get())
+        {
+            entry.client_name = directory.name_of(endpoint_id);
+        }
+
         if let Err(e) = self.sender.send(AuditMessage::Log(entry)) {
             warn!("Failed to send audit log entry: {}", e);
         }
@@ -472,6 +525,25 @@ impl AuditLogger {
         self.log(entry);
     }
 
+    /// Log a newly minted pairing invite
+    pub fn log_invite_created(&self, invite_id: &str, valid_for: Duration, devices: &[String]) {
+        self.log(AuditEntry::invite_created(invite_id, valid_for, devices));
+    }
+
+    /// Log a client enrolling with a pairing invite
+    pub fn log_invite_redeemed(&self, endpoint_id: &str, invite_id: &str, devices: &[String]) {
+        let entry = AuditEntry::new(AuditEventType::InviteRedeemed, AuditResult::Success)
+            .with_endpoint_id(endpoint_id)
+            .with_details(AuditDetails::Message {
+                message: format!(
+                    "Invite {} redeemed, devices: {}",
+                    invite_id,
+                    describe_devices(devices)
+                ),
+            });
+        self.log(entry);
+    }
+
     /// Log server start
     pub fn log_server_started(&self, version: &str) {
         let entry = AuditEntry::new(AuditEventType::ServerStarted, AuditResult::Success)
@@ -688,6 +760,17 @@ impl AuditWriter {
 /// Shared audit logger handle
 pub type SharedAuditLogger = Arc<Option<AuditLogger>>;
 
+/// Write one entry straight to the audit log
+///
+/// For short-lived commands that exit before a background `AuditLogger`
+/// would get to the entry.
+pub fn write_entry_now(config: &AuditConfig, entry: &AuditEntry) -> Result<()> {
+    if !config.enabled || !config.level.should_log(&entry.event_type) {
+        return Ok(());
+    }
+    AuditWriter::new(config.clone()).write_entry(entry)
+}
+
 /// Create a shared audit logger from configuration
 pub fn create_audit_logger(config: AuditConfig) -> SharedAuditLogger {
     Arc::new(AuditLogger::new(config))
@@ -784,6 +867,7 @@ mod tests {
         assert!(AuditLevel::Standard.should_log(&AuditEventType::ClientConnected));
         assert!(!AuditLevel::Security.should_log(&AuditEventType::ClientConnected));
         assert!(AuditLevel::Security.should_log(&AuditEventType::AuthenticationFailure));
+        assert!(AuditLevel::Security.should_log(&AuditEventType::InviteRedeemed));
         assert!(!AuditLevel::Off.should_log(&AuditEventType::AuthenticationFailure));
     }
 
@@ -825,6 +909,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_logged_entries_carry_client_names() {
+        let alice = iroh::SecretKey::generate(&mut rand::rng()).public();
+        let stranger = iroh::SecretKey::generate(&mut rand::rng()).public();
+        let clients = std::collections::BTreeMap::from([("alice".to_string(), alice.to_string())]);
+        let directory =
+            crate::directory::ClientDirectory::new(&clients, &Default::default()).unwrap();
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let logger = AuditLogger {
+            sender,
+            config: AuditConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            directory: OnceLock::new(),
+        };
+        logger.set_client_directory(Arc::new(directory));
+
+        logger.log_client_connected(&alice.to_string(), None);
+        logger.log_client_connected(&stranger.to_string(), None);
+
+        let mut names = Vec::new();
+        while let Ok(AuditMessage::Log(entry)) = receiver.try_recv() {
+            names.push(entry.client_name);
+        }
+        assert_eq!(names, vec![Some("alice".to_string()), None]);
+    }
+
     #[test]
     fn test_audit_entry_serialization() {
         let entry = AuditEntry::new(AuditEventType::DeviceAttach, AuditResult::Success)
@@ -1,10 +1,16 @@
 //! Server configuration management
 
 use crate::audit::AuditLevel;
+use crate::directory::ClientDirectory;
+use crate::schedule::Schedule;
 use anyhow::{Context, Result, anyhow};
+use common::DeviceFilter;
+use jiff::tz::{Offset, TimeZone};
 use protocol::SharingMode;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
@@ -14,6 +20,12 @@ pub struct ServerConfig {
     pub usb: UsbSettings,
     pub security: SecuritySettings,
     pub iroh: IrohSettings,
+    /// Human-readable client names: name = EndpointId
+    #[serde(default)]
+    pub clients: BTreeMap<String, String>,
+    /// Named client groups: name = [client names, EndpointIds or "@group"]
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
     /// Device passthrough policies
     #[serde(default)]
     pub device_policies: Vec<DevicePolicy>,
@@ -29,8 +41,18 @@ pub struct ServerConfig {
     /// Device sharing configuration
     #[serde(default)]
     pub sharing: SharingSettings,
+    /// Per-client data quotas
+    #[serde(default)]
+    pub quotas: QuotaSettings,
+    /// Device reservations
+    #[serde(default)]
+    pub reservations: ReservationSettings,
+    /// IANA time zone for time windows and blackout dates, e.g.
+    /// "Europe/Helsinki" (follows DST; overrides `timezone_offset_hours`)
+    #[serde(default)]
+    pub timezone: Option<String>,
     /// Timezone offset in hours from UTC for time window calculations
-    /// (e.g., +2 for CEST, -5 for EST)
+    /// (e.g., +2 for CEST, -5 for EST), used when `timezone` is unset
     #[serde(default)]
     pub timezone_offset_hours: i32,
 }
@@ -98,18 +120,146 @@ pub struct ServerSettings {
     pub bind_addr: Option<String>,
     pub service_mode: bool,
     pub log_level: String,
+    /// Unix socket of the admin control API (`p2p-usb-server ctl`)
+    /// If None, uses $XDG_RUNTIME_DIR/p2p-usb/control.sock or /run/p2p-usb/control.sock
+    #[serde(default)]
+    pub control_socket: Option<PathBuf>,
+    /// Address of the Prometheus `/metrics` endpoint, e.g. "127.0.0.1:9464"
+    /// If None, no metrics endpoint is served
+    #[serde(default)]
+    pub metrics_addr: Option<SocketAddr>,
+}
+
+impl ServerSettings {
+    /// Path of the admin control socket
+    pub fn control_socket_path(&self) -> PathBuf {
+        if let Some(path) = &self.control_socket {
+            return PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref());
+        }
+        if let Some(runtime_dir) = dirs::runtime_dir() {
+            runtime_dir.join("p2p-usb").join("control.sock")
+        } else {
+            PathBuf::from("/run/p2p-usb/control.sock")
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsbSettings {
     pub auto_share: bool,
+    /// Device filter expressions; a device is shared if any matches (empty = all)
     pub filters: Vec<String>,
+    /// File mapping stable device identities to persistent device IDs
+    /// If None, uses default XDG path: ~/.local/share/p2p-usb/devices.toml
+    #[serde(default)]
+    pub device_registry_path: Option<PathBuf>,
+    /// Where shared devices come from (libusb or emulated)
+    #[serde(default)]
+    pub backend: UsbBackendKind,
+    /// Virtual devices served by the emulated backend
+    /// If empty, the emulated backend serves a keyboard and a serial loopback
+    #[serde(default)]
+    pub emulated_devices: Vec<EmulatedDeviceConfig>,
+}
+
+/// USB backend of the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UsbBackendKind {
+    /// Devices connected to this machine (default)
+    #[default]
+    Libusb,
+    /// Software-emulated devices, for testing without USB hardware
+    Emulated,
+}
+
+/// Virtual device of the emulated USB backend
+///
+/// Example configuration:
+/// ```toml
+/// [usb]
+/// backend = "emulated"
+///
+/// [[usb.emulated_devices]]
+/// kind = "hid-keyboard"
+/// script = "hello\n"  # Typed once the client polls the keyboard
+///
+/// [[usb.emulated_devices]]
+/// kind = "cdc-acm"  # Echoes everything written to it
+///
+/// [[usb.emulated_devices]]
+/// kind = "mass-storage"
+/// image = "~/disk.img"
+/// read_only = true
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum EmulatedDeviceConfig {
+    /// HID boot keyboard
+    HidKeyboard {
+        /// Text typed once the client starts polling
+        #[serde(default)]
+        script: String,
+    },
+    /// CDC-ACM serial port that echoes what is written to it
+    CdcAcm,
+    /// Bulk-only mass storage backed by a disk image
+    MassStorage {
+        /// Image file; its size is rounded down to whole 512-byte blocks
+        image: PathBuf,
+        /// Refuse writes
+        #[serde(default)]
+        read_only: bool,
+    },
+}
+
+impl UsbSettings {
+    /// Path of the persistent device ID registry
+    pub fn device_registry_path(&self) -> PathBuf {
+        if let Some(path) = &self.device_registry_path {
+            return PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref());
+        }
+        if let Some(data_dir) = dirs::data_local_dir() {
+            data_dir.join("p2p-usb").join("devices.toml")
+        } else {
+            PathBuf::from("/var/lib/p2p-usb/devices.toml")
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecuritySettings {
+    /// Clients admitted without asking: EndpointIds, client names or "@group"
     pub approved_clients: Vec<String>,
+    /// Clients the operator denied; rejected without asking again
+    #[serde(default)]
+    pub denied_clients: Vec<String>,
+    /// Park clients that are not approved until the operator decides
     pub require_approval: bool,
+    /// Device filters limiting what a client may attach (absent = no limit)
+    ///
+    /// Keyed by EndpointId, client name or "@group". Filled in when a client
+    /// enrolls with an invite limited to some devices.
+    #[serde(default)]
+    pub client_devices: BTreeMap<String, Vec<String>>,
+    /// File holding unredeemed pairing invites
+    /// If None, uses default XDG path: ~/.local/share/p2p-usb/invites.toml
+    #[serde(default)]
+    pub invites_path: Option<PathBuf>,
+}
+
+impl SecuritySettings {
+    /// Path of the pairing invite store
+    pub fn invites_path(&self) -> PathBuf {
+        if let Some(path) = &self.invites_path {
+            return PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref());
+        }
+        if let Some(data_dir) = dirs::data_local_dir() {
+            data_dir.join("p2p-usb").join("invites.toml")
+        } else {
+            PathBuf::from("/var/lib/p2p-usb/invites.toml")
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -131,8 +281,15 @@ pub struct IrohSettings {
 /// [[device_policies]]
 /// device_filter = "04f9:*"  # Brother devices
 /// allowed_clients = ["endpoint1", "endpoint2"]
-/// time_windows = ["09:00-17:00"]
+/// time_windows = ["Mon-Fri 09:00-17:00"]
+/// blackout_dates = ["2026-12-24..2026-12-26"]
 /// max_session_duration = "1h"
+/// daily_time_quota = "3h"  # Per client, reconnecting does not reset it
+///
+/// [[device_policies]]
+/// device_filter = "0bda:5411"  # Docking station
+/// allowed_interfaces = [0, 1]
+/// restricted_interface_classes = [8]  # Keep its card reader local
 ///
 /// [[device_policies]]
 /// device_filter = "*"  # Default policy
@@ -140,11 +297,15 @@ pub struct IrohSettings {
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DevicePolicy {
-    /// Device filter pattern (VID:PID format, e.g., "04f9:*" or "*" for default)
+    /// Device filter expression (see [`common::filter`]), e.g. "04f9:*" or "*" for default
     /// Can use short form like "04f9:1234" without 0x prefix
+    /// "class:08" matches devices with that class on the device or any interface
+    /// "port:1-1.4" matches whatever is plugged into that port ("port:1-1.*" below a hub)
+    /// Expressions combine terms, e.g. "vid:04f9 and not class:07"
     #[serde(alias = "filter")]
     pub device_filter: String,
-    /// List of allowed client EndpointIds (empty = all approved clients, "*" = any)
+    /// Allowed clients: EndpointIds, client names or "@group" references
+    /// (empty = all approved clients, "*" = any)
     #[serde(default)]
     pub allowed_clients: Vec<String>,
     /// Human-readable description
@@ -162,17 +323,29 @@ pub struct DevicePolicy {
 
     // Time-based access control fields
     /// Time windows when access is allowed (e.g., ["09:00-17:00"])
-    /// Format: "HH:MM-HH:MM" (24-hour format)
+    /// Format: "[DAYS] HH:MM-HH:MM [ZONE]" (24-hour format), e.g.
+    /// "Mon-Fri 08:00-18:00 Europe/Helsinki" (see [`crate::schedule`])
     /// Supports overnight windows like "22:00-06:00"
     /// Empty or None means no time restriction
     #[serde(default)]
     pub time_windows: Option<Vec<String>>,
 
+    /// Dates access is closed all day, e.g. ["2026-12-24..2026-12-26"]
+    /// Single "YYYY-MM-DD" dates or inclusive "YYYY-MM-DD..YYYY-MM-DD" ranges
+    #[serde(default)]
+    pub blackout_dates: Vec<String>,
+
     /// Maximum session duration (parsed from string like "1h", "30m", "1h30m")
     /// None means no duration limit
     #[serde(default, with = "duration_serde")]
     pub max_session_duration: Option<Duration>,
 
+    /// Attach time each client may spend on a matching device per day,
+    /// across sessions (e.g. "3h"); the day ends at midnight in the server
+    /// time zone. None means no time quota
+    #[serde(default, with = "duration_serde")]
+    pub daily_time_quota: Option<Duration>,
+
     /// Device classes that are restricted (denied) for this policy
     /// USB device class codes: 1=Audio, 2=CDC, 3=HID, 6=Image, 7=Printer,
     /// 8=Mass Storage, 9=Hub, 10=CDC-Data, 11=Smart Card, 13=Content Security,
@@ -180,6 +353,21 @@ pub struct DevicePolicy {
     /// None means no class restrictions
     #[serde(default)]
     pub restricted_device_classes: Option<Vec<u8>>,
+
+    // Interface-level access control (composite devices)
+    /// Interface numbers clients may claim (e.g., [0, 1])
+    /// Attaching the whole device counts as claiming every interface, so a
+    /// device with other interfaces can then only be attached by interface
+    /// None means all interfaces
+    #[serde(default)]
+    pub allowed_interfaces: Option<Vec<u8>>,
+
+    /// Interface classes that are restricted (denied) for this policy
+    /// Same class codes as restricted_device_classes, matched against each
+    /// claimed interface (composite devices report class 0 on the device)
+    /// None means no interface class restrictions
+    #[serde(default)]
+    pub restricted_interface_classes: Option<Vec<u8>>,
 }
 
 impl DevicePolicy {
@@ -192,6 +380,8 @@ impl DevicePolicy {
     }
 }
 
+pub use duration_serde::{format_duration, parse_duration};
+
 /// Custom serde module for Duration
 mod duration_serde {
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -265,7 +455,8 @@ mod duration_serde {
         Ok(Duration::from_secs(total_secs))
     }
 
-    fn format_duration(d: Duration) -> String {
+    /// Format a duration the way `parse_duration` reads it
+    pub fn format_duration(d: Duration) -> String {
         let secs = d.as_secs();
         let hours = secs / 3600;
         let mins = (secs % 3600) / 60;
@@ -327,6 +518,134 @@ impl SharingSettings {
     }
 }
 
+/// Per-client data quotas
+///
+/// Caps the bytes each client transfers per day and per calendar month, in
+/// the server time zone, across all devices. A client over a quota is
+/// detached from every device and cannot attach until the period ends.
+///
+/// # Example Configuration
+/// ```toml
+/// [quotas]
+/// daily_data = "5GB"
+/// monthly_data = "50GB"
+///
+/// [quotas.clients]
+/// "@lab" = { daily_data = "20GB" }
+/// alice = { monthly_data = "200GB" }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct QuotaSettings {
+    /// Bytes each client may transfer per day (e.g. "5GB"; None = no limit)
+    #[serde(default)]
+    pub daily_data: Option<String>,
+    /// Bytes each client may transfer per month (e.g. "50GB"; None = no limit)
+    #[serde(default)]
+    pub monthly_data: Option<String>,
+    /// Limits for some clients: client name, EndpointId or "@group" =
+    /// limits replacing the defaults above (entries naming the client win
+    /// over groups)
+    #[serde(default)]
+    pub clients: BTreeMap<String, DataQuota>,
+    /// File quota usage is kept in across restarts
+    /// If None, uses default XDG path: ~/.local/share/p2p-usb/quotas.toml
+    #[serde(default)]
+    pub usage_path: Option<PathBuf>,
+}
+
+/// Data limits of one `[quotas.clients]` entry
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct DataQuota {
+    /// Bytes per day (e.g. "20GB"; None = the default limit)
+    #[serde(default)]
+    pub daily_data: Option<String>,
+    /// Bytes per month (e.g. "200GB"; None = the default limit)
+    #[serde(default)]
+    pub monthly_data: Option<String>,
+}
+
+impl QuotaSettings {
+    /// Path of the persisted quota usage
+    pub fn usage_path(&self) -> PathBuf {
+        if let Some(path) = &self.usage_path {
+            return PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref());
+        }
+        if let Some(data_dir) = dirs::data_local_dir() {
+            data_dir.join("p2p-usb").join("quotas.toml")
+        } else {
+            PathBuf::from("/var/lib/p2p-usb/quotas.toml")
+        }
+    }
+}
+
+/// Device reservations
+///
+/// Clients can book a device for a period; while a booking is active only
+/// its holder can attach or lock the device, and other clients' sessions
+/// are detached when it starts.
+///
+/// # Example Configuration
+/// ```toml
+/// [reservations]
+/// max_duration = "8h"
+/// max_advance = "168h"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ReservationSettings {
+    /// Longest period a client may book at once (e.g. "8h"; None = no limit)
+    #[serde(default, with = "duration_serde")]
+    pub max_duration: Option<Duration>,
+    /// How far ahead a booking may end (e.g. "168h"; None = no limit)
+    #[serde(default, with = "duration_serde")]
+    pub max_advance: Option<Duration>,
+    /// File reservations are kept in across restarts
+    /// If None, uses default XDG path: ~/.local/share/p2p-usb/reservations.toml
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+impl ReservationSettings {
+    /// Path of the persisted reservations
+    pub fn path(&self) -> PathBuf {
+        if let Some(path) = &self.path {
+            return PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).as_ref());
+        }
+        if let Some(data_dir) = dirs::data_local_dir() {
+            data_dir.join("p2p-usb").join("reservations.toml")
+        } else {
+            PathBuf::from("/var/lib/p2p-usb/reservations.toml")
+        }
+    }
+}
+
+/// Parse a data size like "500MB", "5GB" or "1.5GiB" to bytes
+///
+/// KB, MB, GB and TB are powers of 1000, KiB, MiB, GiB and TiB powers of
+/// 1024; a plain number is bytes.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size '{}'", s))?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        other => return Err(format!("Unknown size unit '{}' in '{}'", other, s)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
 /// Bandwidth limiting configuration
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct BandwidthSettings {
@@ -394,6 +713,9 @@ pub struct QosSettings {
     /// Enable priority aging (boost priority of waiting requests to prevent starvation)
     #[serde(default = "QosSettings::default_priority_aging")]
     pub priority_aging: bool,
+    /// Transfers admitted to the USB subsystem at once; the rest wait in priority order
+    #[serde(default = "QosSettings::default_max_in_flight")]
+    pub max_in_flight: usize,
 }
 
 impl Default for QosSettings {
@@ -405,6 +727,7 @@ impl Default for QosSettings {
             bulk_priority: Self::default_bulk_priority(),
             client_quota_mbps: Self::default_client_quota_mbps(),
             priority_aging: Self::default_priority_aging(),
+            max_in_flight: Self::default_max_in_flight(),
         }
     }
 }
@@ -429,6 +752,10 @@ impl QosSettings {
     fn default_priority_aging() -> bool {
         true // Enable by default to prevent starvation
     }
+
+    fn default_max_in_flight() -> usize {
+        64
+    }
 }
 
 impl Default for ServerConfig {
@@ -438,46 +765,62 @@ impl Default for ServerConfig {
                 bind_addr: Some("127.0.0.1:8080".to_string()),
                 service_mode: false,
                 log_level: "info".to_string(),
+                control_socket: None,
+                metrics_addr: None,
             },
             usb: UsbSettings {
                 auto_share: false,
                 filters: Vec::new(),
+                device_registry_path: None,
+                backend: UsbBackendKind::default(),
+                emulated_devices: Vec::new(),
             },
             security: SecuritySettings {
                 approved_clients: Vec::new(),
+                denied_clients: Vec::new(),
                 require_approval: true,
+                client_devices: BTreeMap::new(),
+                invites_path: None,
             },
             iroh: IrohSettings {
                 relay_servers: None,
                 secret_key_path: None,
             },
+            clients: BTreeMap::new(),
+            groups: BTreeMap::new(),
             device_policies: Vec::new(),
             audit: AuditConfig::default(),
             bandwidth: BandwidthSettings::default(),
             qos: QosSettings::default(),
             sharing: SharingSettings::default(),
+            quotas: QuotaSettings::default(),
+            reservations: ReservationSettings::default(),
+            timezone: None,
             timezone_offset_hours: 0,
         }
     }
 }
 
 impl ServerConfig {
+    /// Configuration file to load: `path`, or the first standard location that exists
+    pub fn find_path(path: Option<PathBuf>) -> Option<PathBuf> {
+        if path.is_some() {
+            return path;
+        }
+
+        // Try standard locations in order
+        let candidates = vec![
+            Self::default_path(),
+            PathBuf::from("/etc/p2p-usb/server.toml"),
+        ];
+
+        candidates.into_iter().find(|p| p.exists())
+    }
+
     /// Load configuration from the specified path
     pub fn load(path: Option<PathBuf>) -> Result<Self> {
-        let config_path = if let Some(p) = path {
-            p
-        } else {
-            // Try standard locations in order
-            let candidates = vec![
-                Self::default_path(),
-                PathBuf::from("/etc/p2p-usb/server.toml"),
-            ];
-
-            candidates
-                .into_iter()
-                .find(|p| p.exists())
-                .ok_or_else(|| anyhow!("No configuration file found, using defaults"))?
-        };
+        let config_path = Self::find_path(path)
+            .ok_or_else(|| anyhow!("No configuration file found, using defaults"))?;
 
         let content = fs::read_to_string(&config_path)
             .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
@@ -520,6 +863,29 @@ impl ServerConfig {
         Ok(())
     }
 
+    /// Time zone of time windows that name none
+    ///
+    /// `timezone` when set, otherwise the fixed `timezone_offset_hours`.
+    pub fn timezone(&self) -> Result<TimeZone> {
+        match self.timezone {
+            Some(ref name) => {
+                TimeZone::get(name).with_context(|| format!("Unknown time zone '{}'", name))
+            }
+            None => {
+                let offset = i8::try_from(self.timezone_offset_hours)
+                    .ok()
+                    .and_then(|hours| Offset::from_hours(hours).ok())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Invalid timezone_offset_hours {}",
+                            self.timezone_offset_hours
+                        )
+                    })?;
+                Ok(TimeZone::fixed(offset))
+            }
+        }
+    }
+
     /// Get the default configuration file path
     pub fn default_path() -> PathBuf {
         if let Some(config_dir) = dirs::config_dir() {
@@ -541,10 +907,19 @@ impl ServerConfig {
             ));
         }
 
-        // Validate USB filters (VID:PID format)
+        // Validate USB filters and policy device filters
         for filter in &self.usb.filters {
             Self::validate_filter(filter)?;
         }
+        for policy in &self.device_policies {
+            Self::validate_filter(&policy.device_filter)?;
+            Schedule::new(
+                policy.time_windows.as_deref().unwrap_or_default(),
+                &policy.blackout_dates,
+            )
+            .with_context(|| format!("Invalid device policy '{}'", policy.device_filter))?;
+        }
+        self.timezone()?;
 
         // Validate approved client node IDs (basic format check)
         for client_id in &self.security.approved_clients {
@@ -553,57 +928,62 @@ impl ServerConfig {
             }
             // Note: Full NodeId validation would require iroh types, done at runtime
         }
-
-        Ok(())
-    }
-
-    /// Validate a USB device filter pattern (VID:PID)
-    fn validate_filter(filter: &str) -> Result<()> {
-        let parts: Vec<&str> = filter.split(':').collect();
-        if parts.len() != 2 {
-            return Err(anyhow!(
-                "Invalid filter format '{}', expected VID:PID (e.g., '0x1234:0x5678' or '0x1234:*')",
-                filter
-            ));
+        for filter in self.security.client_devices.values().flatten() {
+            Self::validate_filter(filter)?;
         }
 
-        let (vid, pid) = (parts[0], parts[1]);
-
-        // Validate VID
-        if vid != "*" {
-            Self::validate_hex_id(vid, "VID")?;
+        // Client names and groups, and the groups other settings refer to
+        let directory = ClientDirectory::new(&self.clients, &self.groups)?;
+        let client_lists = [
+            ("security.approved_clients", &self.security.approved_clients),
+            ("security.denied_clients", &self.security.denied_clients),
+        ];
+        let policy_lists = self
+            .device_policies
+            .iter()
+            .map(|policy| ("allowed_clients", &policy.allowed_clients));
+        for (setting, clients) in client_lists.into_iter().chain(policy_lists) {
+            Self::validate_group_refs(&directory, setting, clients)?;
+        }
+        for client in self.security.client_devices.keys() {
+            Self::validate_group_refs(&directory, "security.client_devices", [client])?;
         }
 
-        // Validate PID
-        if pid != "*" {
-            Self::validate_hex_id(pid, "PID")?;
+        // Quota sizes, and the clients quota overrides name
+        let quotas = &self.quotas;
+        let overrides = quotas.clients.values();
+        let sizes = [&quotas.daily_data, &quotas.monthly_data]
+            .into_iter()
+            .chain(overrides.flat_map(|quota| [&quota.daily_data, &quota.monthly_data]));
+        for size in sizes.flatten() {
+            parse_size(size).map_err(|e| anyhow!("Invalid quota: {}", e))?;
+        }
+        for client in quotas.clients.keys() {
+            directory
+                .resolve(client)
+                .with_context(|| format!("Invalid client '{}' in quotas.clients", client))?;
         }
 
         Ok(())
     }
 
-    /// Validate a hex ID (VID or PID)
-    fn validate_hex_id(id: &str, name: &str) -> Result<()> {
-        if !id.starts_with("0x") && !id.starts_with("0X") {
-            return Err(anyhow!(
-                "Invalid {} '{}', must start with '0x' (e.g., '0x1234')",
-                name,
-                id
-            ));
+    /// Check that every `@group` in a client list names a group
+    fn validate_group_refs<'a>(
+        directory: &ClientDirectory,
+        setting: &str,
+        clients: impl IntoIterator<Item = &'a String>,
+    ) -> Result<()> {
+        for client in clients.into_iter().filter(|c| c.starts_with('@')) {
+            directory
+                .resolve(client)
+                .with_context(|| format!("Invalid client '{}' in {}", client, setting))?;
         }
+        Ok(())
+    }
 
-        let hex_part = &id[2..];
-        if hex_part.is_empty() || hex_part.len() > 4 {
-            return Err(anyhow!(
-                "Invalid {} '{}', hex part must be 1-4 digits",
-                name,
-                id
-            ));
-        }
-
-        u16::from_str_radix(hex_part, 16)
-            .map_err(|_| anyhow!("Invalid {} '{}', not a valid hex number", name, id))?;
-
+    /// Validate a device filter expression
+    fn validate_filter(filter: &str) -> Result<()> {
+        DeviceFilter::parse(filter)?;
         Ok(())
     }
 }
@@ -634,15 +1014,102 @@ mod tests {
         assert!(ServerConfig::validate_filter("*:0x5678").is_ok());
         assert!(ServerConfig::validate_filter("*:*").is_ok());
         assert!(ServerConfig::validate_filter("0xABCD:0xEF01").is_ok());
+        assert!(ServerConfig::validate_filter("port:1-1.4").is_ok());
+        assert!(ServerConfig::validate_filter("port:1-1.*").is_ok());
+        // The 0x prefix is optional, as in policies and auto_attach
+        assert!(ServerConfig::validate_filter("1234:5678").is_ok());
+        assert!(ServerConfig::validate_filter("vid:04f9 and (class:07 or speed:high)").is_ok());
     }
 
     #[test]
     fn test_validate_filter_invalid() {
-        assert!(ServerConfig::validate_filter("1234:5678").is_err());
+        // Without the 0x prefix the IDs must still be 1-4 hex digits
+        assert!(ServerConfig::validate_filter("12345:5678").is_err());
+        assert!(ServerConfig::validate_filter("1234:56g8").is_err());
         assert!(ServerConfig::validate_filter("0x1234").is_err());
         assert!(ServerConfig::validate_filter("0x1234:0x5678:0x9abc").is_err());
         assert!(ServerConfig::validate_filter("0xGHIJ:0x5678").is_err());
         assert!(ServerConfig::validate_filter("0x12345:0x5678").is_err());
+        assert!(ServerConfig::validate_filter("port:1.4").is_err());
+        assert!(ServerConfig::validate_filter("vid:04f9 and").is_err());
+    }
+
+    #[test]
+    fn test_validate_policy_filter() {
+        let mut config = ServerConfig::default();
+        config
+            .device_policies
+            .push(toml::from_str("device_filter = \"vendor:04f9\"").unwrap());
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("unknown filter key 'vendor'"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_group_references() {
+        let mut config = ServerConfig::default();
+        config.clients.insert(
+            "alice".to_string(),
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_string(),
+        );
+        config
+            .groups
+            .insert("lab".to_string(), vec!["alice".to_string()]);
+        config.security.approved_clients = vec!["@lab".to_string()];
+        let policy = r#"
+            device_filter = "04f9:*"
+            allowed_clients = ["@lab", "alice"]
+        "#;
+        config.device_policies.push(toml::from_str(policy).unwrap());
+        assert!(config.validate().is_ok());
+
+        config.device_policies[0].allowed_clients = vec!["@labs".to_string()];
+        let err = format!("{:#}", config.validate().unwrap_err());
+        assert!(err.contains("Unknown group '@labs'"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_time_zones_and_schedules() {
+        let mut config = ServerConfig {
+            timezone: Some("Europe/Helsinki".to_string()),
+            ..ServerConfig::default()
+        };
+        let policy = r#"
+            device_filter = "04f9:*"
+            allowed_clients = ["*"]
+            time_windows = ["Mon-Fri 08:00-18:00", "Sat 22:00-02:00 America/New_York"]
+            blackout_dates = ["2026-12-24..2026-12-26"]
+        "#;
+        config.device_policies.push(toml::from_str(policy).unwrap());
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.timezone().unwrap().iana_name(),
+            Some("Europe/Helsinki")
+        );
+
+        config.device_policies[0].blackout_dates = vec!["2026-12-26..2026-12-24".to_string()];
+        assert!(config.validate().is_err());
+        config.device_policies[0].blackout_dates.clear();
+
+        config.timezone = Some("Europe/Helsingfors".to_string());
+        let err = format!("{:#}", config.validate().unwrap_err());
+        assert!(err.contains("Unknown time zone"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("20GB"), Ok(20_000_000_000));
+        assert_eq!(parse_size("1.5 MB"), Ok(1_500_000));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert!(parse_size("GB").is_err());
+        assert!(parse_size("10 parsecs").is_err());
+
+        let mut config = ServerConfig::default();
+        config.quotas.monthly_data = Some("200GB".to_string());
+        assert!(config.validate().is_ok());
+        config.quotas.daily_data = Some("lots".to_string());
+        assert!(config.validate().is_err());
     }
 
     #[test]
@@ -655,6 +1122,60 @@ mod tests {
         assert_eq!(config.usb.auto_share, parsed.usb.auto_share);
     }
 
+    #[test]
+    fn test_emulated_backend_config() {
+        let config: ServerConfig = toml::from_str(
+            r#"
+            [server]
+            service_mode = true
+            log_level = "info"
+
+            [usb]
+            auto_share = true
+            filters = []
+            backend = "emulated"
+
+            [[usb.emulated_devices]]
+            kind = "hid-keyboard"
+            script = "hi"
+
+            [[usb.emulated_devices]]
+            kind = "cdc-acm"
+
+            [[usb.emulated_devices]]
+            kind = "mass-storage"
+            image = "/tmp/disk.img"
+
+            [security]
+            approved_clients = []
+            require_approval = false
+
+            [iroh]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.usb.backend, UsbBackendKind::Emulated);
+        assert_eq!(
+            config.usb.emulated_devices,
+            vec![
+                EmulatedDeviceConfig::HidKeyboard {
+                    script: "hi".to_string()
+                },
+                EmulatedDeviceConfig::CdcAcm,
+                EmulatedDeviceConfig::MassStorage {
+                    image: PathBuf::from("/tmp/disk.img"),
+                    read_only: false
+                },
+            ]
+        );
+
+        // Round-trips through the saved form
+        let saved = toml::to_string_pretty(&config).unwrap();
+        let parsed: ServerConfig = toml::from_str(&saved).unwrap();
+        assert_eq!(parsed.usb.emulated_devices, config.usb.emulated_devices);
+    }
+
     #[test]
     fn test_validate_log_level() {
         let mut config = ServerConfig::default();
@@ -0,0 +1,298 @@
+//! `p2p-usb-server ctl` - command-line client of the control socket
+
+use anyhow::{Context, Result, bail};
+use clap::Subcommand;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+use super::{Attachment, ControlRequest, ControlResponse, MetricsFigures};
+
+/// Operator commands sent to a running server
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum CtlCommand {
+    /// Show server status
+    Status,
+    /// List connected clients and the devices they hold
+    Clients,
+    /// Disconnect a client (it may reconnect; deny it to keep it out)
+    Kick {
+        /// EndpointId of the client
+        endpoint_id: String,
+        /// Reason given to the client
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// List shared and withheld devices
+    Devices,
+    /// Share a withheld device again
+    Share {
+        /// Device ID (see `ctl devices`)
+        device_id: u32,
+    },
+    /// Withhold a device from sharing, detaching it from its clients
+    Unshare {
+        /// Device ID (see `ctl devices`)
+        device_id: u32,
+    },
+    /// Force-detach a device handle from the client holding it
+    Detach {
+        /// Device handle (see `ctl clients`)
+        handle: u32,
+        /// Reason given to the client
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Show the device policies and client scopes in effect
+    Policies,
+    /// Check whether the policies let a client attach a device
+    Check {
+        /// EndpointId of the client
+        endpoint_id: String,
+        /// Device ID (see `ctl devices`)
+        device_id: u32,
+    },
+    /// Show transfer metrics
+    Metrics {
+        /// Only this client
+        #[arg(long, value_name = "ENDPOINT_ID")]
+        client: Option<String>,
+    },
+    /// Re-read the configuration file (like SIGHUP)
+    Reload,
+}
+
+impl From<CtlCommand> for ControlRequest {
+    fn from(command: CtlCommand) -> Self {
+        match command {
+            CtlCommand::Status => ControlRequest::Status,
+            CtlCommand::Clients => ControlRequest::Clients,
+            CtlCommand::Kick {
+                endpoint_id,
+                reason,
+            } => ControlRequest::Disconnect {
+                endpoint_id,
+                reason,
+            },
+            CtlCommand::Devices => ControlRequest::Devices,
+            CtlCommand::Share { device_id } => ControlRequest::SetSharing {
+                device_id,
+                shared: true,
+            },
+            CtlCommand::Unshare { device_id } => ControlRequest::SetSharing {
+                device_id,
+                shared: false,
+            },
+            CtlCommand::Detach { handle, reason } => ControlRequest::Detach { handle, reason },
+            CtlCommand::Policies => ControlRequest::Policies,
+            CtlCommand::Check {
+                endpoint_id,
+                device_id,
+            } => ControlRequest::CheckAccess {
+                endpoint_id,
+                device_id,
+            },
+            CtlCommand::Metrics { client } => ControlRequest::Metrics {
+                endpoint_id: client,
+            },
+            CtlCommand::Reload => ControlRequest::Reload,
+        }
+    }
+}
+
+/// Send one request to the server listening on `socket`
+pub async fn request(socket: &Path, request: &ControlRequest) -> Result<ControlResponse> {
+    let stream = UnixStream::connect(socket).await.with_context(|| {
+        format!(
+            "Failed to connect to {} (is the server running?)",
+            socket.display()
+        )
+    })?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+
+    let mut answer = String::new();
+    BufReader::new(reader).read_line(&mut answer).await?;
+    if answer.is_empty() {
+        bail!("Server closed the control connection without answering");
+    }
+    serde_json::from_str(&answer).context("Malformed answer from server")
+}
+
+/// Run `command` against the server and print the answer
+///
+/// With `json` the answer is printed as the server sent it.
+pub async fn run(socket: &Path, command: CtlCommand, json: bool) -> Result<()> {
+    let response = request(socket, &command.into()).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+        return match response {
+            ControlResponse::Error { message } => bail!(message),
+            _ => Ok(()),
+        };
+    }
+
+    match response {
+        ControlResponse::Status(status) => {
+            println!("Server v{}", status.version);
+            println!("EndpointId:        {}", status.endpoint_id);
+            println!(
+                "Uptime:            {}",
+                crate::config::format_duration(std::time::Duration::from_secs(status.uptime_secs))
+            );
+            println!("Clients:           {}", status.clients);
+            println!(
+                "Devices:           {} shared, {} withheld",
+                status.shared_devices, status.withheld_devices
+            );
+            println!("Active sessions:   {}", status.active_sessions);
+            println!("Pending approvals: {}", status.pending_approvals);
+        }
+
+        ControlResponse::Clients(clients) => {
+            if clients.is_empty() {
+                println!("No clients connected.");
+            }
+            for client in clients {
+                println!(
+                    "{}  connected {}",
+                    client.endpoint_id,
+                    crate::config::format_duration(std::time::Duration::from_secs(
+                        client.connected_secs
+                    ))
+                );
+                for attachment in client.attached {
+                    println!(
+                        "    handle {:<4} device {}",
+                        attachment.handle, attachment.device_id
+                    );
+                }
+            }
+        }
+
+        ControlResponse::Devices(devices) => {
+            if devices.is_empty() {
+                println!("No devices.");
+            }
+            for device in devices {
+                println!(
+                    "  [{}] {:04x}:{:04x} - {} {}{}",
+                    device.device_id,
+                    device.vendor_id,
+                    device.product_id,
+                    device.manufacturer.as_deref().unwrap_or("Unknown"),
+                    device.product.as_deref().unwrap_or("Unknown"),
+                    if device.shared { "" } else { "  [withheld]" }
+                );
+                println!(
+                    "      Port: {}  Stable ID: {}",
+                    device.port, device.stable_id
+                );
+                for Attachment {
+                    handle,
+                    endpoint_id,
+                    ..
+                } in device.attached
+                {
+                    println!("      Attached by {} (handle {})", endpoint_id, handle);
+                }
+            }
+        }
+
+        ControlResponse::Policies(report) => {
+            println!("Active sessions: {}", report.active_sessions);
+            match report.timezone {
+                Some(timezone) => println!("Time zone: {}", timezone),
+                None => println!("Time zone offset: {:+}h", report.timezone_offset_hours),
+            }
+            if report.device_policies.is_empty() {
+                println!("No device policies: every approved client may attach any device.");
+            } else {
+                #[derive(serde::Serialize)]
+                struct Policies<'a> {
+                    device_policies: &'a [crate::config::DevicePolicy],
+                }
+                let policies = Policies {
+                    device_policies: &report.device_policies,
+                };
+                println!();
+                print!("{}", toml::to_string_pretty(&policies)?);
+            }
+            if !report.client_devices.is_empty() {
+                println!();
+                println!("Client scopes:");
+                for (client, filters) in report.client_devices {
+                    println!("  {}: {}", client, filters.join(", "));
+                }
+            }
+        }
+
+        ControlResponse::Access(report) => match report.reason {
+            None => println!(
+                "Allowed: {} may attach device {}",
+                report.endpoint_id, report.device_id
+            ),
+            Some(reason) => println!(
+                "Denied: {} may not attach device {}: {}",
+                report.endpoint_id, report.device_id, reason
+            ),
+        },
+
+        ControlResponse::Metrics(report) => {
+            print_figures("Total", &report.total);
+            for (client, figures) in &report.clients {
+                print_figures(&format!("Client {}", client), figures);
+            }
+            for (device_id, figures) in &report.devices {
+                print_figures(&format!("Device {}", device_id), figures);
+            }
+        }
+
+        ControlResponse::Reloaded(changes) => {
+            if changes.is_empty() {
+                println!("Configuration reloaded, nothing changed.");
+            }
+            for change in changes {
+                println!(
+                    "{}: {} -> {}{}",
+                    change.setting,
+                    change.old_value.as_deref().unwrap_or("(unset)"),
+                    change.new_value.as_deref().unwrap_or("(unset)"),
+                    if change.is_live() {
+                        ""
+                    } else {
+                        "  (needs a restart)"
+                    }
+                );
+            }
+        }
+
+        ControlResponse::Done { message } => println!("{}", message),
+        ControlResponse::Error { message } => bail!(message),
+    }
+    Ok(())
+}
+
+fn print_figures(label: &str, figures: &MetricsFigures) {
+    println!("{}:", label);
+    println!(
+        "    transfers: {} completed, {} failed, {} active",
+        figures.transfers_completed, figures.transfers_failed, figures.active_transfers
+    );
+    println!(
+        "    bytes:     {} sent, {} received",
+        figures.bytes_sent, figures.bytes_received
+    );
+    println!(
+        "    latency:   {:.2} ms avg, {:.2} ms max",
+        figures.latency_avg_us as f64 / 1000.0,
+        figures.latency_max_us as f64 / 1000.0
+    );
+    println!(
+        "    rate:      {:.0} B/s tx, {:.0} B/s rx",
+        figures.throughput_tx_bps, figures.throughput_rx_bps
+    );
+}
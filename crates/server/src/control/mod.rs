@@ -0,0 +1,216 @@
+//! Admin control socket
+//!
+//! A running server listens on a Unix-domain socket (see
+//! `server.control_socket`) for operator commands: list connected clients
+//! and shared devices, disconnect a client, withhold a device from sharing,
+//! force-detach a device, inspect policies, read metrics and reload the
+//! configuration. It is the headless counterpart of the TUI and what
+//! `p2p-usb-server ctl ...` talks to.
+//!
+//! The protocol is one JSON object per line: the client writes a
+//! [`ControlRequest`], the server answers with a [`ControlResponse`]. A
+//! connection may carry any number of requests. The socket is created with
+//! owner-only permissions; whoever can open it is trusted as the operator.
+
+pub mod client;
+pub mod server;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::config::DevicePolicy;
+use crate::network::reload::SettingChange;
+
+pub use client::CtlCommand;
+pub use server::{ControlServer, ControlState};
+
+/// Request sent to the control socket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// Overview of the running server
+    Status,
+    /// Connected clients and the devices they hold
+    Clients,
+    /// Close a client's connection; it may reconnect
+    Disconnect {
+        endpoint_id: String,
+        reason: Option<String>,
+    },
+    /// Shared and withheld devices
+    Devices,
+    /// Withhold a device from sharing or share it again
+    SetSharing { device_id: u32, shared: bool },
+    /// Force-detach a device handle (`ForceDetachReason::AdminAction`)
+    Detach { handle: u32, reason: Option<String> },
+    /// Device policies and client scopes in effect
+    Policies,
+    /// Whether the policies let a client attach a device
+    CheckAccess { endpoint_id: String, device_id: u32 },
+    /// Transfer metrics, for one client or all of them
+    Metrics { endpoint_id: Option<String> },
+    /// Re-read the configuration file
+    Reload,
+}
+
+/// Answer from the control socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlResponse {
+    Status(ServerStatus),
+    Clients(Vec<ClientEntry>),
+    Devices(Vec<DeviceEntry>),
+    Policies(PolicyReport),
+    Access(AccessReport),
+    Metrics(MetricsReport),
+    /// Settings that changed, including those that need a restart
+    Reloaded(Vec<SettingChange>),
+    /// The command was carried out
+    Done {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Overview of the running server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub version: String,
+    pub endpoint_id: String,
+    pub uptime_secs: u64,
+    /// Running client connections
+    pub clients: usize,
+    /// Devices clients can see
+    pub shared_devices: usize,
+    /// Devices the operator withheld from sharing
+    pub withheld_devices: usize,
+    /// Clients waiting for approval
+    pub pending_approvals: usize,
+    /// Attach sessions the policy engine is tracking
+    pub active_sessions: usize,
+}
+
+/// A connected client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientEntry {
+    pub endpoint_id: String,
+    /// Seconds since the connection was admitted
+    pub connected_secs: u64,
+    pub attached: Vec<Attachment>,
+}
+
+/// A device handle held by a client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub handle: u32,
+    pub device_id: u32,
+    pub endpoint_id: String,
+}
+
+/// A device the server shares or withholds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceEntry {
+    pub device_id: u32,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub stable_id: String,
+    /// Port path in sysfs notation (`1-1.4`)
+    pub port: String,
+    /// False when the operator withheld the device
+    pub shared: bool,
+    /// Handles clients hold on the device
+    pub attached: Vec<Attachment>,
+}
+
+/// Device policies and client scopes in effect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyReport {
+    /// IANA time zone of time windows (None = `timezone_offset_hours`)
+    #[serde(default)]
+    pub timezone: Option<String>,
+    pub timezone_offset_hours: i32,
+    pub device_policies: Vec<DevicePolicy>,
+    /// Device filters each client is limited to
+    pub client_devices: BTreeMap<String, Vec<String>>,
+    /// Attach sessions the policy engine is tracking
+    pub active_sessions: usize,
+}
+
+/// Outcome of a policy check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessReport {
+    pub endpoint_id: String,
+    pub device_id: u32,
+    pub allowed: bool,
+    /// Why access is denied
+    pub reason: Option<String>,
+}
+
+/// Transfer metrics from the server's registry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsReport {
+    pub total: MetricsFigures,
+    /// Per client, keyed by EndpointId
+    pub clients: BTreeMap<String, MetricsFigures>,
+    /// Per shared device, keyed by device ID
+    pub devices: BTreeMap<u32, MetricsFigures>,
+}
+
+/// Counters and rates of one metrics snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsFigures {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub transfers_completed: u64,
+    pub transfers_failed: u64,
+    pub active_transfers: u64,
+    pub latency_avg_us: u64,
+    pub latency_max_us: u64,
+    pub throughput_tx_bps: f64,
+    pub throughput_rx_bps: f64,
+}
+
+impl From<common::MetricsSnapshot> for MetricsFigures {
+    fn from(snapshot: common::MetricsSnapshot) -> Self {
+        Self {
+            bytes_sent: snapshot.bytes_sent,
+            bytes_received: snapshot.bytes_received,
+            transfers_completed: snapshot.transfers_completed,
+            transfers_failed: snapshot.transfers_failed,
+            active_transfers: snapshot.active_transfers,
+            latency_avg_us: snapshot.latency.avg_us,
+            latency_max_us: snapshot.latency.max_us,
+            throughput_tx_bps: snapshot.throughput_tx_bps,
+            throughput_rx_bps: snapshot.throughput_rx_bps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_wire_format() {
+        let request = ControlRequest::Detach {
+            handle: 3,
+            reason: Some("maintenance".to_string()),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            json,
+            r#"{"command":"detach","handle":3,"reason":"maintenance"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(&json).unwrap(),
+            request
+        );
+
+        let status: ControlRequest = serde_json::from_str(r#"{"command":"status"}"#).unwrap();
+        assert_eq!(status, ControlRequest::Status);
+    }
+}
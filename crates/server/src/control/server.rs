@@ -0,0 +1,429 @@
+//! Control socket listener
+//!
+//! Accepts connections on the admin socket and answers each request line
+//! from the server's shared state: the client registry, the USB subsystem,
+//! the policy engine, the metrics registry and the configuration reloader.
+
+use anyhow::{Context, Result, anyhow, bail};
+use common::{UsbBridge, UsbCommand};
+use iroh::PublicKey as EndpointId;
+use protocol::{DeviceHandle, DeviceId, DeviceInfo};
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{debug, info, warn};
+
+use super::{
+    AccessReport, Attachment, ClientEntry, ControlRequest, ControlResponse, DeviceEntry,
+    MetricsFigures, MetricsReport, PolicyReport, ServerStatus,
+};
+use crate::audit::SharedAuditLogger;
+use crate::network::{
+    IrohServer, SharedApprovalQueue, SharedClientRegistry, SharedConfigReloader,
+    SharedServerMetrics,
+};
+use crate::policy::{PolicyDecision, PolicyEngine};
+
+/// Longest request line accepted (requests are small JSON objects)
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+/// Server state the control socket reads and acts on
+#[derive(Clone)]
+pub struct ControlState {
+    endpoint_id: EndpointId,
+    started_at: Instant,
+    clients: SharedClientRegistry,
+    usb_bridge: UsbBridge,
+    approvals: SharedApprovalQueue,
+    policy_engine: Arc<PolicyEngine>,
+    metrics: SharedServerMetrics,
+    reloader: SharedConfigReloader,
+    audit_logger: SharedAuditLogger,
+}
+
+impl ControlState {
+    /// Collect the shared state of `server`
+    pub fn new(
+        server: &IrohServer,
+        usb_bridge: UsbBridge,
+        audit_logger: SharedAuditLogger,
+    ) -> Self {
+        Self {
+            endpoint_id: server.endpoint_id(),
+            started_at: Instant::now(),
+            clients: server.clients(),
+            usb_bridge,
+            approvals: server.approvals(),
+            policy_engine: server.policy_engine(),
+            metrics: server.metrics(),
+            reloader: server.reloader(),
+            audit_logger,
+        }
+    }
+}
+
+/// Listener on the admin control socket
+///
+/// The socket file is removed when the listener is dropped.
+pub struct ControlServer {
+    listener: UnixListener,
+    path: PathBuf,
+    state: ControlState,
+}
+
+impl ControlServer {
+    /// Listen on `path`, replacing a socket left behind by a dead server
+    ///
+    /// Fails when another server is already listening there.
+    pub fn bind(path: &Path, state: ControlState) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create control socket directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+
+        if path.exists() {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                bail!("Another server is already listening on {}", path.display());
+            }
+            fs::remove_file(path).with_context(|| {
+                format!("Failed to remove stale control socket: {}", path.display())
+            })?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind control socket: {}", path.display()))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to restrict control socket: {}", path.display()))?;
+
+        info!("Control socket listening on {}", path.display());
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            state,
+        })
+    }
+
+    /// Accept control connections until the task is aborted
+    pub async fn run(self) -> Result<()> {
+        loop {
+            let (stream, _) = self
+                .listener
+                .accept()
+                .await
+                .context("Failed to accept control connection")?;
+            let state = self.state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_connection(stream, state).await {
+                    debug!("Control connection ended: {:#}", e);
+                }
+            });
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!(
+                "Failed to remove control socket {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Answer requests on one connection until the client hangs up
+async fn serve_connection(stream: UnixStream, state: ControlState) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_LEN));
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        // Every request gets a fresh length budget
+        reader.get_mut().set_limit(MAX_REQUEST_LEN);
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(request) => {
+                debug!("Control request: {:?}", request);
+                handle_request(&state, request)
+                    .await
+                    .unwrap_or_else(|e| ControlResponse::Error {
+                        message: format!("{:#}", e),
+                    })
+            }
+            Err(e) => ControlResponse::Error {
+                message: format!("Invalid request: {}", e),
+            },
+        };
+
+        let mut bytes = serde_json::to_vec(&response)?;
+        bytes.push(b'\n');
+        writer.write_all(&bytes).await?;
+    }
+}
+
+/// Carry out one request
+pub async fn handle_request(
+    state: &ControlState,
+    request: ControlRequest,
+) -> Result<ControlResponse> {
+    match request {
+        ControlRequest::Status => {
+            let shared_devices = list_devices(&state.usb_bridge).await?.len();
+            let withheld_devices = list_withheld_devices(&state.usb_bridge).await?.len();
+            Ok(ControlResponse::Status(ServerStatus {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                endpoint_id: state.endpoint_id.to_string(),
+                uptime_secs: state.started_at.elapsed().as_secs(),
+                clients: state.clients.len(),
+                shared_devices,
+                withheld_devices,
+                pending_approvals: state.approvals.pending_count(),
+                active_sessions: state.policy_engine.active_session_count().await,
+            }))
+        }
+
+        ControlRequest::Clients => {
+            let now = SystemTime::now();
+            let clients = state
+                .clients
+                .clients()
+                .await
+                .into_iter()
+                .map(|client| {
+                    let endpoint_id = client.endpoint_id.to_string();
+                    ClientEntry {
+                        connected_secs: now
+                            .duration_since(client.connected_at)
+                            .unwrap_or_default()
+                            .as_secs(),
+                        attached: client
+                            .attached
+                            .iter()
+                            .map(|(handle, device_id)| Attachment {
+                                handle: handle.0,
+                                device_id: device_id.0,
+                                endpoint_id: endpoint_id.clone(),
+                            })
+                            .collect(),
+                        endpoint_id,
+                    }
+                })
+                .collect();
+            Ok(ControlResponse::Clients(clients))
+        }
+
+        ControlRequest::Disconnect {
+            endpoint_id,
+            reason,
+        } => {
+            let client = parse_endpoint_id(&endpoint_id)?;
+            let reason = reason.unwrap_or_else(|| "Disconnected by operator".to_string());
+            if !state.clients.disconnect(&client, &reason) {
+                bail!("Client {} is not connected", client);
+            }
+            info!("Operator disconnected {}: {}", client, reason);
+            Ok(done(format!("Disconnected {}", client)))
+        }
+
+        ControlRequest::Devices => {
+            let mut attachments: HashMap<u32, Vec<Attachment>> = HashMap::new();
+            for client in state.clients.clients().await {
+                for (handle, device_id) in client.attached {
+                    attachments
+                        .entry(device_id.0)
+                        .or_default()
+                        .push(Attachment {
+                            handle: handle.0,
+                            device_id: device_id.0,
+                            endpoint_id: client.endpoint_id.to_string(),
+                        });
+                }
+            }
+
+            let shared = list_devices(&state.usb_bridge).await?;
+            let withheld = list_withheld_devices(&state.usb_bridge).await?;
+            let mut devices: Vec<DeviceEntry> = shared
+                .into_iter()
+                .map(|device| (device, true))
+                .chain(withheld.into_iter().map(|device| (device, false)))
+                .map(|(device, shared)| DeviceEntry {
+                    attached: attachments.remove(&device.id.0).unwrap_or_default(),
+                    device_id: device.id.0,
+                    vendor_id: device.vendor_id,
+                    product_id: device.product_id,
+                    port: device.port_path(),
+                    manufacturer: device.manufacturer,
+                    product: device.product,
+                    stable_id: device.stable_id,
+                    shared,
+                })
+                .collect();
+            devices.sort_by_key(|device| device.device_id);
+            Ok(ControlResponse::Devices(devices))
+        }
+
+        ControlRequest::SetSharing { device_id, shared } => {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            state
+                .usb_bridge
+                .send_command(UsbCommand::SetDeviceShared {
+                    device_id: DeviceId(device_id),
+                    shared,
+                    response: tx,
+                })
+                .await
+                .context("Failed to send sharing change")?;
+            let changed = rx
+                .await
+                .context("USB subsystem did not answer")?
+                .map_err(|e| anyhow!("Device {}: {:?}", device_id, e))?;
+
+            let state_name = if shared { "shared" } else { "withheld" };
+            if !changed {
+                return Ok(done(format!(
+                    "Device {} is already {}",
+                    device_id, state_name
+                )));
+            }
+            info!("Operator {} device {}", state_name, device_id);
+            if let Some(ref logger) = *state.audit_logger {
+                logger.log_config_change(
+                    &format!("device {} sharing", device_id),
+                    Some(if shared { "withheld" } else { "shared" }.to_string()),
+                    Some(state_name.to_string()),
+                );
+            }
+            Ok(done(format!("Device {} is now {}", device_id, state_name)))
+        }
+
+        ControlRequest::Detach { handle, reason } => {
+            match state
+                .clients
+                .force_detach(DeviceHandle(handle), reason)
+                .await
+            {
+                Some(client) => Ok(done(format!("Detached handle {} from {}", handle, client))),
+                None => bail!("No connected client holds handle {}", handle),
+            }
+        }
+
+        ControlRequest::Policies => {
+            let config = state.reloader.current().await;
+            Ok(ControlResponse::Policies(PolicyReport {
+                timezone: config.timezone,
+                timezone_offset_hours: config.timezone_offset_hours,
+                device_policies: config.device_policies,
+                client_devices: config.security.client_devices,
+                active_sessions: state.policy_engine.active_session_count().await,
+            }))
+        }
+
+        ControlRequest::CheckAccess {
+            endpoint_id,
+            device_id,
+        } => {
+            let client = parse_endpoint_id(&endpoint_id)?;
+            let device = list_devices(&state.usb_bridge)
+                .await?
+                .into_iter()
+                .find(|device| device.id.0 == device_id)
+                .ok_or_else(|| anyhow!("Device {} is not shared", device_id))?;
+            let (allowed, reason) = match state.policy_engine.check_access(&client, &device) {
+                PolicyDecision::Allow => (true, None),
+                PolicyDecision::Deny(reason) => (false, Some(reason.to_string())),
+            };
+            Ok(ControlResponse::Access(AccessReport {
+                endpoint_id: client.to_string(),
+                device_id,
+                allowed,
+                reason,
+            }))
+        }
+
+        ControlRequest::Metrics { endpoint_id } => {
+            let client_ids = match endpoint_id {
+                Some(endpoint_id) => {
+                    let client = parse_endpoint_id(&endpoint_id)?.to_string();
+                    if !state.metrics.client_ids().contains(&client) {
+                        bail!("No metrics for client {}", client);
+                    }
+                    vec![client]
+                }
+                None => state.metrics.client_ids(),
+            };
+            let clients = client_ids
+                .into_iter()
+                .filter_map(|id| {
+                    let snapshot = state.metrics.client_snapshot(&id)?;
+                    Some((id, MetricsFigures::from(snapshot)))
+                })
+                .collect();
+            let devices = list_devices(&state.usb_bridge)
+                .await?
+                .into_iter()
+                .filter_map(|device| {
+                    let snapshot = state.metrics.device_snapshot(device.id)?;
+                    Some((device.id.0, MetricsFigures::from(snapshot)))
+                })
+                .collect();
+            Ok(ControlResponse::Metrics(MetricsReport {
+                total: state.metrics.total_snapshot().into(),
+                clients,
+                devices,
+            }))
+        }
+
+        ControlRequest::Reload => {
+            let changes = state.reloader.reload().await?;
+            Ok(ControlResponse::Reloaded(changes))
+        }
+    }
+}
+
+fn done(message: String) -> ControlResponse {
+    ControlResponse::Done { message }
+}
+
+fn parse_endpoint_id(endpoint_id: &str) -> Result<EndpointId> {
+    endpoint_id
+        .parse()
+        .map_err(|e| anyhow!("Invalid EndpointId '{}': {}", endpoint_id, e))
+}
+
+/// Devices the USB subsystem shares
+async fn list_devices(usb_bridge: &UsbBridge) -> Result<Vec<DeviceInfo>> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    usb_bridge
+        .send_command(UsbCommand::ListDevices { response: tx })
+        .await
+        .context("Failed to send ListDevices command")?;
+    rx.await.context("Failed to receive device list")
+}
+
+/// Devices the operator withheld from sharing
+async fn list_withheld_devices(usb_bridge: &UsbBridge) -> Result<Vec<DeviceInfo>> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    usb_bridge
+        .send_command(UsbCommand::ListWithheldDevices { response: tx })
+        .await
+        .context("Failed to send ListWithheldDevices command")?;
+    rx.await.context("Failed to receive withheld devices")
+}
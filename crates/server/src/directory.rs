@@ -0,0 +1,300 @@
+//! Client names and groups
+//!
+//! `[clients]` gives EndpointIds human-readable names and `[groups]` collects
+//! clients under a group name:
+//!
+//! ```toml
+//! [clients]
+//! alice = "e8f5a338..."
+//! bob = "0c1d2e3f..."
+//!
+//! [groups]
+//! lab = ["alice", "bob"]
+//! firmware = ["alice", "4a5b6c7d..."]
+//! engineers = ["@lab", "@firmware"]
+//! ```
+//!
+//! Wherever the configuration lists clients (`security.approved_clients`,
+//! `security.denied_clients`, the keys of `security.client_devices` and
+//! `allowed_clients` of device policies) an entry may be an EndpointId, a
+//! client name or an `@group` reference. Groups may include other groups, so
+//! a role is a group of groups; cycles are rejected at config load.
+
+use anyhow::{Result, anyhow, bail};
+use iroh::PublicKey as EndpointId;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+
+/// Shared handle to the client directory
+pub type SharedClientDirectory = Arc<ClientDirectory>;
+
+/// Client names and groups, replaced as a whole on reload
+#[derive(Debug, Default)]
+pub struct ClientDirectory {
+    entries: RwLock<Arc<Entries>>,
+}
+
+/// Resolved contents of `[clients]` and `[groups]`
+#[derive(Debug, Default)]
+struct Entries {
+    /// EndpointId of each client name
+    clients: BTreeMap<String, EndpointId>,
+    /// Name of each named client
+    names: HashMap<EndpointId, String>,
+    /// Members of each group, with nested groups expanded
+    groups: BTreeMap<String, BTreeSet<EndpointId>>,
+}
+
+impl ClientDirectory {
+    /// Build a directory from `[clients]` and `[groups]`
+    ///
+    /// Fails on an invalid EndpointId, a duplicate name, a group member that
+    /// is neither a client nor a group, or groups that include each other.
+    pub fn new(
+        clients: &BTreeMap<String, String>,
+        groups: &BTreeMap<String, Vec<String>>,
+    ) -> Result<Self> {
+        Ok(Self {
+            entries: RwLock::new(Arc::new(Entries::new(clients, groups)?)),
+        })
+    }
+
+    /// Replace the names and groups (the old ones stay on error)
+    pub fn replace(
+        &self,
+        clients: &BTreeMap<String, String>,
+        groups: &BTreeMap<String, Vec<String>>,
+    ) -> Result<()> {
+        let entries = Entries::new(clients, groups)?;
+        *self.entries.write().unwrap() = Arc::new(entries);
+        Ok(())
+    }
+
+    fn entries(&self) -> Arc<Entries> {
+        self.entries.read().unwrap().clone()
+    }
+
+    /// Clients an entry refers to: an EndpointId, a client name or `@group`
+    pub fn resolve(&self, entry: &str) -> Result<BTreeSet<EndpointId>> {
+        self.entries().resolve(entry)
+    }
+
+    /// Clients all `entries` refer to
+    ///
+    /// Entries that do not resolve are logged and skipped.
+    pub fn resolve_all(&self, entries: &[String]) -> HashSet<EndpointId> {
+        let directory = self.entries();
+        entries
+            .iter()
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                directory
+                    .resolve(entry)
+                    .inspect_err(|e| warn!("Ignoring client '{}': {:#}", entry, e))
+                    .ok()
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Whether `entry` refers to `client` ("*" refers to every client)
+    pub fn matches(&self, entry: &str, client: &EndpointId) -> bool {
+        if entry == "*" {
+            return true;
+        }
+        let directory = self.entries();
+        if let Some(group) = entry.strip_prefix('@') {
+            return directory
+                .groups
+                .get(group)
+                .is_some_and(|members| members.contains(client));
+        }
+        if let Some(id) = directory.clients.get(entry) {
+            return id == client;
+        }
+        // EndpointIds are hex, compare case-insensitively
+        entry.eq_ignore_ascii_case(&client.to_string())
+    }
+
+    /// Name of a client, if it has one
+    pub fn name(&self, client: &EndpointId) -> Option<String> {
+        self.entries().names.get(client).cloned()
+    }
+
+    /// Name of a client given as an EndpointId string, if it has one
+    pub fn name_of(&self, client: &str) -> Option<String> {
+        let client = client.parse::<EndpointId>().ok()?;
+        self.name(&client)
+    }
+
+    /// How to show a client to the operator: its name, or the EndpointId
+    /// cut to its first 16 characters
+    pub fn label(&self, client: &str) -> String {
+        match self.name_of(client) {
+            Some(name) => name,
+            None if client.len() > 20 => format!("{}...", &client[..16]),
+            None => client.to_string(),
+        }
+    }
+}
+
+impl Entries {
+    fn new(
+        clients: &BTreeMap<String, String>,
+        groups: &BTreeMap<String, Vec<String>>,
+    ) -> Result<Self> {
+        let mut entries = Entries::default();
+        for (name, id) in clients {
+            if name.is_empty() || name.starts_with('@') || name == "*" {
+                bail!("Invalid client name '{}'", name);
+            }
+            let id = id
+                .parse::<EndpointId>()
+                .map_err(|e| anyhow!("Invalid EndpointId of client '{}': {}", name, e))?;
+            if let Some(other) = entries.names.insert(id, name.clone()) {
+                bail!(
+                    "Clients '{}' and '{}' have the same EndpointId",
+                    other,
+                    name
+                );
+            }
+            entries.clients.insert(name.clone(), id);
+        }
+
+        for group in groups.keys() {
+            let mut path = Vec::new();
+            let members = entries.expand(group, groups, &mut path)?;
+            entries.groups.insert(group.clone(), members);
+        }
+        Ok(entries)
+    }
+
+    /// Members of `group`, expanding nested groups
+    ///
+    /// `path` holds the groups being expanded, to catch cycles.
+    fn expand(
+        &self,
+        group: &str,
+        groups: &BTreeMap<String, Vec<String>>,
+        path: &mut Vec<String>,
+    ) -> Result<BTreeSet<EndpointId>> {
+        if let Some(members) = self.groups.get(group) {
+            return Ok(members.clone());
+        }
+        if path.iter().any(|g| g == group) {
+            path.push(group.to_string());
+            bail!("Groups include each other: {}", path.join(" -> "));
+        }
+        let Some(entries) = groups.get(group) else {
+            bail!("Unknown group '@{}'", group);
+        };
+
+        path.push(group.to_string());
+        let mut members = BTreeSet::new();
+        for entry in entries {
+            match entry.strip_prefix('@') {
+                Some(nested) => members.extend(self.expand(nested, groups, path)?),
+                None => members.extend(self.resolve(entry).map_err(|e| {
+                    anyhow!("Invalid member '{}' of group '{}': {:#}", entry, group, e)
+                })?),
+            }
+        }
+        path.pop();
+        Ok(members)
+    }
+
+    fn resolve(&self, entry: &str) -> Result<BTreeSet<EndpointId>> {
+        if let Some(group) = entry.strip_prefix('@') {
+            return self
+                .groups
+                .get(group)
+                .cloned()
+                .ok_or_else(|| anyhow!("Unknown group '@{}'", group));
+        }
+        if let Some(id) = self.clients.get(entry) {
+            return Ok(BTreeSet::from([*id]));
+        }
+        let id = entry
+            .parse::<EndpointId>()
+            .map_err(|_| anyhow!("Not a client name or EndpointId"))?;
+        Ok(BTreeSet::from([id]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> EndpointId {
+        iroh::SecretKey::generate(&mut rand::rng()).public()
+    }
+
+    fn table<V: Clone>(entries: &[(&str, V)]) -> BTreeMap<String, V> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    fn list(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_groups_resolve_names_ids_and_nested_groups() {
+        let (alice, bob, carol) = (key(), key(), key());
+        let clients = table(&[("alice", alice.to_string()), ("bob", bob.to_string())]);
+        let groups = table(&[
+            ("lab", list(&["alice", "bob"])),
+            ("firmware", vec![carol.to_string()]),
+            ("engineers", list(&["@lab", "@firmware"])),
+        ]);
+        let directory = ClientDirectory::new(&clients, &groups).unwrap();
+
+        assert_eq!(
+            directory.resolve("@engineers").unwrap(),
+            BTreeSet::from([alice, bob, carol])
+        );
+        assert_eq!(directory.resolve("bob").unwrap(), BTreeSet::from([bob]));
+        assert!(directory.resolve("@nobody").is_err());
+        assert!(directory.resolve("dave").is_err());
+
+        assert!(directory.matches("@lab", &alice));
+        assert!(!directory.matches("@lab", &carol));
+        assert!(directory.matches("alice", &alice));
+        assert!(directory.matches(&carol.to_string().to_uppercase(), &carol));
+        assert!(directory.matches("*", &carol));
+
+        let resolved = directory.resolve_all(&list(&["@lab", "dave", ""]));
+        assert_eq!(resolved, HashSet::from([alice, bob]));
+
+        assert_eq!(directory.label(&alice.to_string()), "alice");
+        assert_eq!(
+            directory.label(&carol.to_string()),
+            format!("{}...", &carol.to_string()[..16])
+        );
+    }
+
+    #[test]
+    fn test_invalid_directories_are_rejected() {
+        let alice = key();
+        let clients = table(&[("alice", alice.to_string())]);
+
+        let cycle = table(&[("a", list(&["@b"])), ("b", list(&["alice", "@a"]))]);
+        let err = ClientDirectory::new(&clients, &cycle).unwrap_err();
+        assert!(err.to_string().contains("include each other"), "{}", err);
+
+        let unknown = table(&[("lab", list(&["alice", "@missing"]))]);
+        assert!(ClientDirectory::new(&clients, &unknown).is_err());
+
+        let typo = table(&[("lab", list(&["alcie"]))]);
+        assert!(ClientDirectory::new(&clients, &typo).is_err());
+
+        let twice = table(&[("alice", alice.to_string()), ("al", alice.to_string())]);
+        assert!(ClientDirectory::new(&twice, &BTreeMap::new()).is_err());
+
+        let bad_id = table(&[("alice", "not-a-key".to_string())]);
+        assert!(ClientDirectory::new(&bad_id, &BTreeMap::new()).is_err());
+    }
+}
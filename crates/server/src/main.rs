@@ -5,21 +5,38 @@
 
 mod audit;
 mod config;
+mod control;
+mod directory;
 mod network;
 pub mod policy;
+mod policy_cli;
 pub mod qos;
+mod quota;
+mod reservation;
+mod schedule;
 mod service;
+#[cfg(test)]
+mod test_harness;
 mod tui;
 mod usb;
 
 use anyhow::{Context, Result};
+use audit::AuditEntry;
 use audit::create_audit_logger;
-use clap::Parser;
-use common::{UsbBridge, UsbCommand, create_usb_bridge, setup_logging};
-use network::IrohServer;
+use clap::{Parser, Subcommand};
+use common::{
+    MetricsExporter, UsbBridge, UsbCommand, create_usb_bridge, load_or_generate_secret_key,
+    setup_logging,
+};
+use control::{ControlServer, ControlState, CtlCommand};
+use network::{InviteStore, IrohServer, MetricsPage, SharedConfigReloader};
+use policy_cli::PolicyCommand;
+use std::path::PathBuf;
+use std::time::Duration;
 use tokio::signal;
-use tracing::{error, info};
-use usb::spawn_usb_worker;
+use tokio::signal::unix::SignalKind;
+use tracing::{error, info, warn};
+use usb::{DeviceRegistry, open_backend, spawn_usb_worker};
 
 #[derive(Parser, Debug)]
 #[command(name = "p2p-usb-server")]
@@ -48,6 +65,20 @@ EXAMPLES:
     # Run with debug logging
     p2p-usb-server --log-level debug
 
+    # Invite a new client, limited to Brother printers, for one hour
+    p2p-usb-server --invite --invite-ttl 1h --invite-device '04f9:*'
+
+    # Apply an edited configuration without dropping sessions
+    kill -HUP $(pidof p2p-usb-server)
+
+    # Inspect and manage a running server from the command line
+    p2p-usb-server ctl clients
+    p2p-usb-server ctl detach 3 --reason maintenance
+
+    # Why is a client denied a device, and are any policies dead?
+    p2p-usb-server policy explain --client alice --device 04f9:0042 --at '2026-12-24 10:00'
+    p2p-usb-server policy lint
+
 CONFIGURATION:
     The server looks for configuration files in the following order:
     1. Path specified with --config
@@ -55,6 +86,17 @@ CONFIGURATION:
     3. /etc/p2p-usb/server.toml
     4. Built-in defaults
 
+    On SIGHUP the server re-reads its configuration file and applies the
+    client lists, device policies, bandwidth limits and USB filters live.
+    Sessions the new configuration forbids are detached; others continue.
+
+    A running server answers `p2p-usb-server ctl` commands on its control
+    socket (server.control_socket, default $XDG_RUNTIME_DIR/p2p-usb/control.sock
+    or /run/p2p-usb/control.sock).
+
+    With server.metrics_addr set (e.g. \"127.0.0.1:9464\") the server serves
+    Prometheus metrics on http://<metrics_addr>/metrics.
+
 For more information, visit: https://github.com/kimasplund/rust-p2p-usb
 ")]
 struct Args {
@@ -74,9 +116,46 @@ struct Args {
     #[arg(long)]
     list_devices: bool,
 
+    /// Create a single-use pairing invite, print its connection URL and exit
+    #[arg(long)]
+    invite: bool,
+
+    /// How long the invite stays valid (e.g. 15m, 1h)
+    #[arg(long, value_name = "DURATION", default_value = "15m", value_parser = config::parse_duration)]
+    invite_ttl: Duration,
+
+    /// Limit the invited client to devices matching this filter (repeatable)
+    #[arg(long = "invite-device", value_name = "FILTER", requires = "invite")]
+    invite_devices: Vec<String>,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(short, long, value_name = "LEVEL")]
     log_level: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage a running server through its control socket
+    Ctl {
+        /// Control socket (default: server.control_socket from the configuration)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Print the server's answer as JSON
+        #[arg(long)]
+        json: bool,
+
+        #[command(subcommand)]
+        command: CtlCommand,
+    },
+    /// Explain and lint device policies of the configuration
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommand,
+    },
 }
 
 #[tokio::main]
@@ -98,6 +177,25 @@ async fn main() -> Result<()> {
     } else {
         config::ServerConfig::load_or_default()
     };
+    if args.invite {
+        return create_invite(&config, args.invite_ttl, args.invite_devices);
+    }
+    match args.command {
+        Some(Command::Ctl {
+            socket,
+            json,
+            command,
+        }) => {
+            let socket = socket.unwrap_or_else(|| config.server.control_socket_path());
+            return control::client::run(&socket, command, json).await;
+        }
+        Some(Command::Policy { command }) => return policy_cli::run(&config, command),
+        None => {}
+    }
+
+    // Approval decisions are written back to the file the configuration came from
+    let config_path = config::ServerConfig::find_path(args.config.clone())
+        .unwrap_or_else(config::ServerConfig::default_path);
 
     // Use CLI log level if specified, otherwise use config value
     let log_level = args
@@ -112,10 +210,16 @@ async fn main() -> Result<()> {
     info!("Log level: {}", log_level);
 
     // Initialize USB subsystem
+    let backend = open_backend(&config.usb).context("Failed to initialize USB backend")?;
     let (usb_bridge, worker) = create_usb_bridge();
     // Start USB worker thread (hybrid architecture: sync USB ops in dedicated thread)
+    // Device IDs are looked up in the persistent registry so they stay stable
+    let registry = DeviceRegistry::load(&config.usb.device_registry_path()).unwrap_or_else(|e| {
+        warn!("Device IDs will not persist: {:#}", e);
+        DeviceRegistry::in_memory()
+    });
     // Pass configured filters to restrict which devices are shared
-    let usb_worker_handle = spawn_usb_worker(worker, config.usb.filters.clone());
+    let usb_worker_handle = spawn_usb_worker(worker, backend, config.usb.filters.clone(), registry);
 
     if args.list_devices {
         let result = list_devices_mode(usb_bridge.clone()).await;
@@ -136,10 +240,10 @@ async fn main() -> Result<()> {
 
     let result = if service_mode {
         info!("Running in service mode (headless)");
-        run_service(config, usb_bridge.clone()).await
+        run_service(config, config_path, usb_bridge.clone()).await
     } else {
         info!("Running in TUI mode (interactive)");
-        run_tui(config, usb_bridge.clone()).await
+        run_tui(config, config_path, usb_bridge.clone()).await
     };
 
     // Cleanup: Shutdown USB worker thread
@@ -191,6 +295,7 @@ async fn list_devices_mode(usb_bridge: UsbBridge) -> Result<()> {
             if let Some(serial) = &device.serial_number {
                 println!("      Serial: {}", serial);
             }
+            println!("      Stable ID: {}", device.stable_id);
             println!();
         }
     }
@@ -199,7 +304,11 @@ async fn list_devices_mode(usb_bridge: UsbBridge) -> Result<()> {
 }
 
 /// Run in service mode (headless, systemd-compatible)
-async fn run_service(config: config::ServerConfig, usb_bridge: UsbBridge) -> Result<()> {
+async fn run_service(
+    config: config::ServerConfig,
+    config_path: PathBuf,
+    usb_bridge: UsbBridge,
+) -> Result<()> {
     info!("Starting P2P USB Server in service mode");
 
     if service::is_systemd() {
@@ -219,11 +328,17 @@ async fn run_service(config: config::ServerConfig, usb_bridge: UsbBridge) -> Res
     // Initialize Iroh server with audit logger
     let server = IrohServer::new(config.clone(), usb_bridge.clone(), audit_logger.clone())
         .await
-        .context("Failed to initialize Iroh server")?;
+        .context("Failed to initialize Iroh server")?
+        .with_config_path(config_path);
 
     info!("Server EndpointId: {}", server.endpoint_id());
     info!("Listening on: {:?}", server.local_addrs());
 
+    let reload_handle = spawn_reload_on_sighup(server.reloader())?;
+    let control_handle =
+        spawn_control_socket(&config, &server, usb_bridge.clone(), audit_logger.clone());
+    let metrics_handle = spawn_metrics_exporter(&config, &server).await;
+
     // Start watchdog task if enabled
     let watchdog_handle = service::spawn_watchdog_task()
         .await
@@ -262,8 +377,15 @@ async fn run_service(config: config::ServerConfig, usb_bridge: UsbBridge) -> Res
     // Notify systemd we're stopping
     service::notify_stopping().context("Failed to notify systemd stopping")?;
 
-    // Stop watchdog
+    // Stop watchdog, reload handler, control socket and metrics endpoint
     watchdog_handle.abort();
+    reload_handle.abort();
+    if let Some(handle) = control_handle {
+        handle.abort();
+    }
+    if let Some(handle) = metrics_handle {
+        handle.abort();
+    }
 
     // Abort server task (will drop endpoint and close connections)
     server_handle.abort();
@@ -273,7 +395,11 @@ async fn run_service(config: config::ServerConfig, usb_bridge: UsbBridge) -> Res
 }
 
 /// Run in TUI mode (interactive terminal UI)
-async fn run_tui(config: config::ServerConfig, usb_bridge: UsbBridge) -> Result<()> {
+async fn run_tui(
+    config: config::ServerConfig,
+    config_path: PathBuf,
+    usb_bridge: UsbBridge,
+) -> Result<()> {
     // Initialize audit logger
     let audit_logger = create_audit_logger(config.audit.clone());
     if let Some(ref logger) = *audit_logger {
@@ -287,15 +413,27 @@ async fn run_tui(config: config::ServerConfig, usb_bridge: UsbBridge) -> Result<
     // Initialize Iroh server with audit logger
     let server = IrohServer::new(config.clone(), usb_bridge.clone(), audit_logger.clone())
         .await
-        .context("Failed to initialize Iroh server")?;
+        .context("Failed to initialize Iroh server")?
+        .with_config_path(config_path);
 
     let endpoint_id = server.endpoint_id();
     info!("Server EndpointId: {}", endpoint_id);
     info!("Listening on: {:?}", server.local_addrs());
 
+    let _reload_handle = spawn_reload_on_sighup(server.reloader())?;
+    let control_handle =
+        spawn_control_socket(&config, &server, usb_bridge.clone(), audit_logger.clone());
+    let metrics_handle = spawn_metrics_exporter(&config, &server).await;
+
     // Create channel for network events to TUI
     // Note: network_tx will be used by the server to send events when network layer is integrated
     let (_network_tx, network_rx) = tokio::sync::mpsc::unbounded_channel();
+    let app = tui::App::new(endpoint_id, usb_bridge, network_rx, config.usb.auto_share)
+        .with_metrics(server.metrics())
+        .with_approvals(server.approvals())
+        .with_invites(server.invites())
+        .with_client_directory(server.client_directory())
+        .with_reservations(server.policy_engine());
 
     // Spawn server task in background
     // Note: In a full implementation, the server would send NetworkEvents through network_tx
@@ -308,7 +446,15 @@ async fn run_tui(config: config::ServerConfig, usb_bridge: UsbBridge) -> Result<
     });
 
     // Run the TUI (this blocks until user quits)
-    let tui_result = tui::run(endpoint_id, usb_bridge, network_rx, config.usb.auto_share).await;
+    let tui_result = tui::run(app).await;
+
+    // Removes the control socket
+    if let Some(handle) = control_handle {
+        handle.abort();
+    }
+    if let Some(handle) = metrics_handle {
+        handle.abort();
+    }
 
     // Log server shutdown
     if let Some(ref logger) = *audit_logger {
@@ -322,6 +468,115 @@ async fn run_tui(config: config::ServerConfig, usb_bridge: UsbBridge) -> Result<
     tui_result
 }
 
+/// Reload the configuration file whenever the process receives SIGHUP
+fn spawn_reload_on_sighup(reloader: SharedConfigReloader) -> Result<tokio::task::JoinHandle<()>> {
+    let mut hangup =
+        signal::unix::signal(SignalKind::hangup()).context("Failed to install SIGHUP handler")?;
+
+    Ok(tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+            // Failures are logged by the reloader; the old configuration stays
+            let _ = reloader.reload().await;
+        }
+    }))
+}
+
+/// Serve the admin control socket in the background
+///
+/// The server keeps running without it if the socket cannot be created.
+fn spawn_control_socket(
+    config: &config::ServerConfig,
+    server: &IrohServer,
+    usb_bridge: UsbBridge,
+    audit_logger: audit::SharedAuditLogger,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let state = ControlState::new(server, usb_bridge, audit_logger);
+    match ControlServer::bind(&config.server.control_socket_path(), state) {
+        Ok(control) => Some(tokio::spawn(async move {
+            if let Err(e) = control.run().await {
+                error!("Control socket error: {:#}", e);
+            }
+        })),
+        Err(e) => {
+            warn!("Control socket disabled: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Serve Prometheus metrics in the background if `server.metrics_addr` is set
+///
+/// The server keeps running without them if the address cannot be bound.
+async fn spawn_metrics_exporter(
+    config: &config::ServerConfig,
+    server: &IrohServer,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let addr = config.server.metrics_addr?;
+    let page = MetricsPage::new(server);
+    match MetricsExporter::bind(addr).await {
+        Ok(exporter) => Some(tokio::spawn(async move {
+            let render = move || {
+                let page = page.clone();
+                async move { page.render().await }
+            };
+            if let Err(e) = exporter.run(render).await {
+                error!("Metrics endpoint error: {:#}", e);
+            }
+        })),
+        Err(e) => {
+            warn!("Metrics endpoint disabled: failed to bind {}: {}", addr, e);
+            None
+        }
+    }
+}
+
+/// Mint a pairing invite and print the URL that redeems it
+///
+/// The invite is written to the invite store, so a server that is already
+/// running accepts it.
+fn create_invite(config: &config::ServerConfig, ttl: Duration, devices: Vec<String>) -> Result<()> {
+    let secret_key = load_or_generate_secret_key(config.iroh.secret_key_path.as_deref())
+        .context("Failed to load or generate secret key")?;
+    let store = InviteStore::new(config.security.invites_path());
+    let invite = store
+        .mint(ttl, devices)
+        .context("Failed to create invite")?;
+
+    // The process exits right away, so bypass the background audit writer
+    let entry = AuditEntry::invite_created(&invite.id, ttl, &invite.devices);
+    if let Err(e) = audit::write_entry_now(&config.audit, &entry) {
+        eprintln!("Warning: failed to audit invite: {:#}", e);
+    }
+
+    let url = tui::qr::generate_invite_url(&secret_key.public(), &invite.token);
+    for line in tui::qr::generate_qr_lines(&url) {
+        let row: String = line
+            .spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        println!("{}", row);
+    }
+    println!();
+    println!("{}", url);
+    println!();
+    println!(
+        "Single use, valid for {} (invite {}), devices: {}",
+        config::format_duration(ttl),
+        invite.id,
+        if invite.devices.is_empty() {
+            "all".to_string()
+        } else {
+            invite.devices.join(", ")
+        }
+    );
+    if !config.security.require_approval {
+        println!("Note: security.require_approval is off, so any client can connect anyway");
+    }
+    Ok(())
+}
+
 /// Shutdown USB worker thread gracefully
 async fn shutdown_usb_worker(usb_bridge: UsbBridge) -> Result<()> {
     usb_bridge
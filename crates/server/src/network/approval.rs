@@ -0,0 +1,428 @@
+//! Client approval queue
+//!
+//! With `security.require_approval`, clients that are not in
+//! `approved_clients` are parked here instead of being dropped. The operator
+//! approves or denies each one from the TUI; the decision updates the live
+//! allowlist, wakes the waiting connection and is written back to the server
+//! configuration file so it survives a restart. Clients redeeming a pairing
+//! invite are enrolled through the same path without asking the operator.
+
+use anyhow::{Context, Result};
+use iroh::PublicKey as EndpointId;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+use tokio::sync::{RwLock, watch};
+use tracing::{debug, info};
+
+use crate::audit::SharedAuditLogger;
+use crate::config::ServerConfig;
+
+/// Maximum number of clients waiting for a decision at once
+///
+/// Unknown clients beyond this are rejected rather than queued.
+pub const MAX_PENDING_CLIENTS: usize = 64;
+
+/// Shared handle to the approval queue
+pub type SharedApprovalQueue = Arc<ApprovalQueue>;
+
+/// Operator decision for a parked client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    Approved,
+    Denied,
+}
+
+/// A client waiting for the operator's decision
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingClient {
+    pub endpoint_id: EndpointId,
+    /// When the client first connected while unknown
+    pub requested_at: SystemTime,
+}
+
+/// Queue entry; the sender wakes every connection parked for this client
+struct PendingEntry {
+    client: PendingClient,
+    decision: watch::Sender<Option<ApprovalDecision>>,
+}
+
+/// Clients awaiting approval, plus the allow and deny lists decisions feed
+pub struct ApprovalQueue {
+    /// Live allowlist, shared with `IrohServer`
+    allowed: Arc<RwLock<HashSet<EndpointId>>>,
+    /// Clients the operator denied
+    denied: RwLock<HashSet<EndpointId>>,
+    /// Whether clients off the allowlist must be approved (`require_approval`)
+    require_approval: AtomicBool,
+    /// Clients waiting for a decision
+    pending: Mutex<HashMap<EndpointId, PendingEntry>>,
+    /// Configuration written back after each decision
+    config: tokio::sync::Mutex<ServerConfig>,
+    /// File decisions are persisted to (in-memory only when unset)
+    config_path: OnceLock<PathBuf>,
+    /// Audit logger for recording decisions
+    audit_logger: SharedAuditLogger,
+}
+
+impl ApprovalQueue {
+    /// Create a queue around the server's allowlist and deny list
+    pub fn new(
+        config: ServerConfig,
+        allowed: Arc<RwLock<HashSet<EndpointId>>>,
+        denied: HashSet<EndpointId>,
+        audit_logger: SharedAuditLogger,
+    ) -> Self {
+        Self {
+            allowed,
+            denied: RwLock::new(denied),
+            require_approval: AtomicBool::new(config.security.require_approval),
+            pending: Mutex::new(HashMap::new()),
+            config: tokio::sync::Mutex::new(config),
+            config_path: OnceLock::new(),
+            audit_logger,
+        }
+    }
+
+    /// Persist decisions to `path` (only the first call takes effect)
+    pub fn set_config_path(&self, path: PathBuf) {
+        let _ = self.config_path.set(path);
+    }
+
+    /// Whether clients off the allowlist must be approved
+    pub fn requires_approval(&self) -> bool {
+        self.require_approval.load(Ordering::Relaxed)
+    }
+
+    /// Check whether a client may connect without asking the operator
+    pub async fn admits(&self, endpoint_id: &EndpointId) -> bool {
+        !self.requires_approval() || self.is_allowed(endpoint_id).await
+    }
+
+    /// Replace the allow and deny lists, e.g. after a configuration reload
+    ///
+    /// Parked clients the new lists settle are woken with that decision;
+    /// without `require_approval` every parked client is let in.
+    pub async fn replace_lists(
+        &self,
+        allowed: HashSet<EndpointId>,
+        denied: HashSet<EndpointId>,
+        require_approval: bool,
+    ) {
+        self.require_approval
+            .store(require_approval, Ordering::Relaxed);
+        *self.allowed.write().await = allowed.clone();
+        *self.denied.write().await = denied.clone();
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|endpoint_id, entry| {
+            let decision = if allowed.contains(endpoint_id) || !require_approval {
+                ApprovalDecision::Approved
+            } else if denied.contains(endpoint_id) {
+                ApprovalDecision::Denied
+            } else {
+                return true;
+            };
+            info!(
+                "Client {} settled by configuration: {:?}",
+                endpoint_id, decision
+            );
+            entry.decision.send_replace(Some(decision));
+            false
+        });
+    }
+
+    /// Check whether a client is on the allowlist
+    pub async fn is_allowed(&self, endpoint_id: &EndpointId) -> bool {
+        self.allowed.read().await.contains(endpoint_id)
+    }
+
+    /// Check whether the operator denied a client
+    pub async fn is_denied(&self, endpoint_id: &EndpointId) -> bool {
+        self.denied.read().await.contains(endpoint_id)
+    }
+
+    /// Park a client until the operator decides
+    ///
+    /// Returns a receiver that yields the decision, or `None` when the queue
+    /// is full. Parking a client that is already waiting shares its entry.
+    pub fn park(
+        &self,
+        endpoint_id: EndpointId,
+    ) -> Option<watch::Receiver<Option<ApprovalDecision>>> {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(entry) = pending.get(&endpoint_id) {
+            return Some(entry.decision.subscribe());
+        }
+        if pending.len() >= MAX_PENDING_CLIENTS {
+            return None;
+        }
+
+        let (decision, rx) = watch::channel(None);
+        pending.insert(
+            endpoint_id,
+            PendingEntry {
+                client: PendingClient {
+                    endpoint_id,
+                    requested_at: SystemTime::now(),
+                },
+                decision,
+            },
+        );
+        info!("Client {} is awaiting operator approval", endpoint_id);
+        Some(rx)
+    }
+
+    /// Clients waiting for a decision, oldest first
+    pub fn pending(&self) -> Vec<PendingClient> {
+        let mut clients: Vec<PendingClient> = self
+            .pending
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.client.clone())
+            .collect();
+        clients.sort_by_key(|client| client.requested_at);
+        clients
+    }
+
+    /// Number of clients waiting for a decision
+    pub fn pending_count(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Approve a client and add it to `approved_clients`
+    pub async fn approve(&self, endpoint_id: EndpointId) -> Result<()> {
+        self.decide(endpoint_id, ApprovalDecision::Approved, None)
+            .await
+    }
+
+    /// Deny a client and add it to `denied_clients`
+    pub async fn deny(&self, endpoint_id: EndpointId) -> Result<()> {
+        self.decide(endpoint_id, ApprovalDecision::Denied, None)
+            .await
+    }
+
+    /// Approve a client that redeemed an invite
+    ///
+    /// `devices` is recorded in `client_devices` as the client's device
+    /// scope; an empty list clears any earlier scope.
+    pub async fn enroll(&self, endpoint_id: EndpointId, devices: &[String]) -> Result<()> {
+        self.decide(endpoint_id, ApprovalDecision::Approved, Some(devices))
+            .await
+    }
+
+    /// Apply a decision, wake waiting connections and persist it
+    ///
+    /// The live lists are updated even if writing the configuration fails.
+    async fn decide(
+        &self,
+        endpoint_id: EndpointId,
+        decision: ApprovalDecision,
+        devices: Option<&[String]>,
+    ) -> Result<()> {
+        match decision {
+            ApprovalDecision::Approved => {
+                self.denied.write().await.remove(&endpoint_id);
+                self.allowed.write().await.insert(endpoint_id);
+                info!("Approved client: {}", endpoint_id);
+            }
+            ApprovalDecision::Denied => {
+                self.allowed.write().await.remove(&endpoint_id);
+                self.denied.write().await.insert(endpoint_id);
+                info!("Denied client: {}", endpoint_id);
+            }
+        }
+
+        if let Some(entry) = self.pending.lock().unwrap().remove(&endpoint_id) {
+            entry.decision.send_replace(Some(decision));
+        }
+
+        if let Some(ref logger) = *self.audit_logger {
+            let setting = match decision {
+                ApprovalDecision::Approved => "security.approved_clients",
+                ApprovalDecision::Denied => "security.denied_clients",
+            };
+            logger.log_config_change(setting, None, Some(endpoint_id.to_string()));
+        }
+
+        self.persist(endpoint_id, decision, devices).await
+    }
+
+    /// Write a decision back to the configuration file
+    ///
+    /// The file is re-read first so edits made since startup are kept.
+    async fn persist(
+        &self,
+        endpoint_id: EndpointId,
+        decision: ApprovalDecision,
+        devices: Option<&[String]>,
+    ) -> Result<()> {
+        let Some(path) = self.config_path.get() else {
+            debug!("No configuration file, approval decision kept in memory");
+            return Ok(());
+        };
+
+        let mut config = self.config.lock().await;
+        if path.exists() {
+            *config =
+                ServerConfig::load(Some(path.clone())).context("Failed to reload configuration")?;
+        }
+
+        let security = &mut config.security;
+        let is_other = |client: &String| client.parse::<EndpointId>().ok() != Some(endpoint_id);
+        security.approved_clients.retain(is_other);
+        security.denied_clients.retain(is_other);
+        match decision {
+            ApprovalDecision::Approved => security.approved_clients.push(endpoint_id.to_string()),
+            ApprovalDecision::Denied => security.denied_clients.push(endpoint_id.to_string()),
+        }
+        if let Some(devices) = devices {
+            security
+                .client_devices
+                .retain(|client, _| client.parse::<EndpointId>().ok() != Some(endpoint_id));
+            if !devices.is_empty() {
+                security
+                    .client_devices
+                    .insert(endpoint_id.to_string(), devices.to_vec());
+            }
+        }
+
+        config.save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::create_audit_logger;
+    use crate::config::AuditConfig;
+    use common::test_utils::generate_test_endpoint_id;
+
+    fn queue(config: ServerConfig) -> ApprovalQueue {
+        ApprovalQueue::new(
+            config,
+            Arc::new(RwLock::new(HashSet::new())),
+            HashSet::new(),
+            create_audit_logger(AuditConfig::default()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_approve_wakes_parked_client_and_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.toml");
+        let queue = queue(ServerConfig::default());
+        queue.set_config_path(path.clone());
+
+        let client = generate_test_endpoint_id();
+        let mut decision = queue.park(client).unwrap();
+        assert_eq!(queue.pending_count(), 1);
+        assert_eq!(queue.pending()[0].endpoint_id, client);
+
+        queue.approve(client).await.unwrap();
+        assert_eq!(
+            *decision.borrow_and_update(),
+            Some(ApprovalDecision::Approved)
+        );
+        assert!(queue.is_allowed(&client).await);
+        assert!(queue.pending().is_empty());
+
+        let saved = ServerConfig::load(Some(path)).unwrap();
+        assert_eq!(saved.security.approved_clients, vec![client.to_string()]);
+        assert!(saved.security.denied_clients.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_deny_replaces_earlier_approval() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.toml");
+        let queue = queue(ServerConfig::default());
+        queue.set_config_path(path.clone());
+
+        let client = generate_test_endpoint_id();
+        queue.approve(client).await.unwrap();
+        let mut decision = queue.park(client).unwrap();
+        queue.deny(client).await.unwrap();
+
+        assert_eq!(
+            *decision.borrow_and_update(),
+            Some(ApprovalDecision::Denied)
+        );
+        assert!(!queue.is_allowed(&client).await);
+        assert!(queue.is_denied(&client).await);
+
+        let saved = ServerConfig::load(Some(path)).unwrap();
+        assert!(saved.security.approved_clients.is_empty());
+        assert_eq!(saved.security.denied_clients, vec![client.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_enroll_records_device_scope() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.toml");
+        let queue = queue(ServerConfig::default());
+        queue.set_config_path(path.clone());
+
+        let client = generate_test_endpoint_id();
+        queue.enroll(client, &["04f9:*".to_string()]).await.unwrap();
+        assert!(queue.is_allowed(&client).await);
+
+        let saved = ServerConfig::load(Some(path.clone())).unwrap();
+        assert_eq!(saved.security.approved_clients, vec![client.to_string()]);
+        assert_eq!(
+            saved.security.client_devices.get(&client.to_string()),
+            Some(&vec!["04f9:*".to_string()])
+        );
+
+        queue.enroll(client, &[]).await.unwrap();
+        let saved = ServerConfig::load(Some(path)).unwrap();
+        assert!(saved.security.client_devices.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_replace_lists_settles_parked_clients() {
+        let queue = queue(ServerConfig::default());
+        let (approved, denied, waiting) = (
+            generate_test_endpoint_id(),
+            generate_test_endpoint_id(),
+            generate_test_endpoint_id(),
+        );
+        let mut approved_decision = queue.park(approved).unwrap();
+        let mut denied_decision = queue.park(denied).unwrap();
+        queue.park(waiting).unwrap();
+
+        queue
+            .replace_lists(HashSet::from([approved]), HashSet::from([denied]), true)
+            .await;
+        assert_eq!(
+            *approved_decision.borrow_and_update(),
+            Some(ApprovalDecision::Approved)
+        );
+        assert_eq!(
+            *denied_decision.borrow_and_update(),
+            Some(ApprovalDecision::Denied)
+        );
+        assert_eq!(queue.pending()[0].endpoint_id, waiting);
+        assert!(queue.admits(&approved).await);
+        assert!(!queue.admits(&waiting).await);
+
+        queue
+            .replace_lists(HashSet::new(), HashSet::new(), false)
+            .await;
+        assert!(queue.pending().is_empty());
+        assert!(queue.admits(&waiting).await);
+    }
+
+    #[test]
+    fn test_park_is_bounded() {
+        let queue = queue(ServerConfig::default());
+        for _ in 0..MAX_PENDING_CLIENTS {
+            assert!(queue.park(generate_test_endpoint_id()).is_some());
+        }
+        assert!(queue.park(generate_test_endpoint_id()).is_none());
+        assert_eq!(queue.pending_count(), MAX_PENDING_CLIENTS);
+    }
+}
@@ -0,0 +1,248 @@
+//! Connected clients
+//!
+//! Every client connection registers here while it runs, so the admin
+//! control socket can list who is connected and what they hold, and can
+//! reach into a connection to disconnect it or force-detach one of its
+//! devices. Commands are carried out by the connection's own task, which
+//! owns the QUIC connection and the attachment state.
+
+use iroh::PublicKey as EndpointId;
+use protocol::{DeviceHandle, DeviceId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::{mpsc, oneshot};
+
+use super::transfer_channel::AttachedDevicesMap;
+
+/// Shared handle to the connected-client registry
+pub type SharedClientRegistry = Arc<ClientRegistry>;
+
+/// Command sent to a connection's task by the operator
+#[derive(Debug)]
+pub enum ClientCommand {
+    /// Close the connection; the client may reconnect
+    Disconnect { reason: String },
+    /// Detach a device the client holds (`ForceDetachReason::AdminAction`)
+    ForceDetach {
+        handle: DeviceHandle,
+        reason: Option<String>,
+        /// Whether the client held the handle
+        response: oneshot::Sender<bool>,
+    },
+}
+
+/// A connected client as shown to the operator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectedClient {
+    pub endpoint_id: EndpointId,
+    /// When the connection was admitted
+    pub connected_at: SystemTime,
+    /// Attached devices, sorted by handle
+    pub attached: Vec<(DeviceHandle, DeviceId)>,
+}
+
+/// Registry entry of one running connection
+struct ClientEntry {
+    endpoint_id: EndpointId,
+    connected_at: SystemTime,
+    attached: AttachedDevicesMap,
+    commands: mpsc::UnboundedSender<ClientCommand>,
+}
+
+/// Running client connections, keyed by a per-connection ID
+///
+/// A client that reconnects before its old connection is torn down shows
+/// up twice for a moment; commands go to every connection of the client.
+#[derive(Default)]
+pub struct ClientRegistry {
+    connections: Mutex<HashMap<u64, ClientEntry>>,
+    next_id: AtomicU64,
+}
+
+impl ClientRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a running connection
+    ///
+    /// Returns the ID to unregister it with and the receiver its task
+    /// takes operator commands from.
+    pub fn register(
+        &self,
+        endpoint_id: EndpointId,
+        attached: AttachedDevicesMap,
+    ) -> (u64, mpsc::UnboundedReceiver<ClientCommand>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (commands, receiver) = mpsc::unbounded_channel();
+        self.connections.lock().unwrap().insert(
+            id,
+            ClientEntry {
+                endpoint_id,
+                connected_at: SystemTime::now(),
+                attached,
+                commands,
+            },
+        );
+        (id, receiver)
+    }
+
+    /// Forget a connection that has closed
+    pub fn unregister(&self, id: u64) {
+        self.connections.lock().unwrap().remove(&id);
+    }
+
+    /// Number of running connections
+    pub fn len(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    /// Connected clients, oldest connection first
+    pub async fn clients(&self) -> Vec<ConnectedClient> {
+        let entries: Vec<(EndpointId, SystemTime, AttachedDevicesMap)> = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| {
+                (
+                    entry.endpoint_id,
+                    entry.connected_at,
+                    entry.attached.clone(),
+                )
+            })
+            .collect();
+
+        let mut clients = Vec::with_capacity(entries.len());
+        for (endpoint_id, connected_at, attached) in entries {
+            let mut attached: Vec<(DeviceHandle, DeviceId)> = attached
+                .read()
+                .await
+                .iter()
+                .map(|(handle, device_id)| (*handle, *device_id))
+                .collect();
+            attached.sort_by_key(|(handle, _)| handle.0);
+            clients.push(ConnectedClient {
+                endpoint_id,
+                connected_at,
+                attached,
+            });
+        }
+        clients.sort_by_key(|client| client.connected_at);
+        clients
+    }
+
+    /// Disconnect every connection of `endpoint_id`
+    ///
+    /// Returns false when the client is not connected.
+    pub fn disconnect(&self, endpoint_id: &EndpointId, reason: &str) -> bool {
+        let mut found = false;
+        for entry in self.connections.lock().unwrap().values() {
+            if entry.endpoint_id == *endpoint_id {
+                found |= entry
+                    .commands
+                    .send(ClientCommand::Disconnect {
+                        reason: reason.to_string(),
+                    })
+                    .is_ok();
+            }
+        }
+        found
+    }
+
+    /// Force-detach `handle` from whichever client holds it
+    ///
+    /// Returns the client that held it, or None when no connection does.
+    pub async fn force_detach(
+        &self,
+        handle: DeviceHandle,
+        reason: Option<String>,
+    ) -> Option<EndpointId> {
+        let entries: Vec<(
+            EndpointId,
+            AttachedDevicesMap,
+            mpsc::UnboundedSender<ClientCommand>,
+        )> = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| {
+                (
+                    entry.endpoint_id,
+                    entry.attached.clone(),
+                    entry.commands.clone(),
+                )
+            })
+            .collect();
+
+        for (endpoint_id, attached, commands) in entries {
+            if !attached.read().await.contains_key(&handle) {
+                continue;
+            }
+            let (tx, rx) = oneshot::channel();
+            let command = ClientCommand::ForceDetach {
+                handle,
+                reason: reason.clone(),
+                response: tx,
+            };
+            if commands.send(command).is_ok() && rx.await.unwrap_or(false) {
+                return Some(endpoint_id);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::test_utils::generate_test_endpoint_id;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_commands_reach_the_holding_connection() {
+        let registry = ClientRegistry::new();
+        let (alice, bob) = (generate_test_endpoint_id(), generate_test_endpoint_id());
+        let alice_devices: AttachedDevicesMap = Arc::new(RwLock::new(HashMap::new()));
+        alice_devices
+            .write()
+            .await
+            .insert(DeviceHandle(7), DeviceId(3));
+        let (alice_id, mut alice_rx) = registry.register(alice, alice_devices);
+        let (_, mut bob_rx) = registry.register(bob, Arc::new(RwLock::new(HashMap::new())));
+
+        let clients = registry.clients().await;
+        assert_eq!(clients.len(), 2);
+        let listed = clients.iter().find(|c| c.endpoint_id == alice).unwrap();
+        assert_eq!(listed.attached, vec![(DeviceHandle(7), DeviceId(3))]);
+
+        // Answer like a connection task would
+        tokio::spawn(async move {
+            while let Some(command) = alice_rx.recv().await {
+                if let ClientCommand::ForceDetach { response, .. } = command {
+                    let _ = response.send(true);
+                }
+            }
+        });
+        assert_eq!(
+            registry.force_detach(DeviceHandle(7), None).await,
+            Some(alice)
+        );
+        assert_eq!(registry.force_detach(DeviceHandle(8), None).await, None);
+        assert!(bob_rx.try_recv().is_err());
+
+        assert!(registry.disconnect(&bob, "maintenance"));
+        assert!(matches!(
+            bob_rx.try_recv(),
+            Ok(ClientCommand::Disconnect { reason }) if reason == "maintenance"
+        ));
+
+        registry.unregister(alice_id);
+        assert!(!registry.disconnect(&alice, "gone"));
+        assert_eq!(registry.len(), 1);
+    }
+}
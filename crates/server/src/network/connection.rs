@@ -5,25 +5,37 @@
 
 use anyhow::{Context, Result, anyhow};
 use common::UsbBridge;
-use common::{RateLimitResult, SharedRateLimiter, UsbCommand, UsbEvent};
+use common::{UsbCommand, UsbEvent};
 use iroh::PublicKey as EndpointId;
-use iroh::endpoint::{Connection, RecvStream, SendStream};
+use iroh::endpoint::{Connection, RecvStream, SendStream, VarInt};
 
 use protocol::{
-    AttachError, CURRENT_VERSION, DeviceHandle, DeviceId, DeviceRemovalReason, ForceDetachReason,
-    Message, MessagePayload, RequestId, TransferType, UsbRequest, decode_framed, encode_framed,
-    validate_version,
+    AttachError, CURRENT_VERSION, DeviceHandle, DeviceId, DeviceOperation, DeviceRemovalReason,
+    ForceDetachReason, Message, MessagePayload, Reservation, ReservationError, ReservationEvent,
+    ReservationId, ReservationUpdate, TransferResult, UsbError, UsbRequest, UsbResponse,
+    decode_framed, encode_framed, validate_version,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::{Mutex, broadcast};
+use tokio::sync::{RwLock, watch};
 use tokio::time;
 use tracing::{debug, error, info, trace, warn};
 
 use crate::audit::{AuditResult, SharedAuditLogger};
+use crate::network::approval::SharedApprovalQueue;
+use crate::network::clients::{ClientCommand, SharedClientRegistry};
+use crate::network::interrupt_stream::InterruptStreams;
+use crate::network::metrics::SharedServerMetrics;
 use crate::network::notification_aggregator::{NotificationAggregator, PendingNotification};
-use crate::policy::{PolicyDecision, PolicyDenialReason, PolicyEngine};
+use crate::network::server::{DISCONNECTED_BY_OPERATOR, NOT_ADMITTED};
+use crate::network::transfer_channel::{
+    AttachedDevicesMap, MAX_IN_FLIGHT_TRANSFERS, RateLimiterSlot, TransferDispatcher,
+    serve_transfer_channel,
+};
+use crate::policy::{PolicyDecision, PolicyDenialReason, PolicyEngine, SessionExpiredReason};
+use crate::qos::SharedQosManager;
+use crate::reservation;
 
 /// Timeout for receiving messages (2 minutes)
 const MESSAGE_TIMEOUT: Duration = Duration::from_secs(120);
@@ -31,16 +43,34 @@ const MESSAGE_TIMEOUT: Duration = Duration::from_secs(120);
 /// Keep-alive ping interval (30 seconds)
 const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
 
-/// Pending USB transfer awaiting completion or cancellation
-struct PendingTransfer {
-    /// Request ID for matching responses
-    request_id: RequestId,
-    /// Sender to signal cancellation
-    cancel_tx: broadcast::Sender<()>,
-}
+/// How often sessions are checked against their policy limits (30 seconds)
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(30);
 
-/// Tracks pending transfers per device handle
-type PendingTransfersMap = Arc<Mutex<HashMap<DeviceHandle, Vec<PendingTransfer>>>>;
+/// How long before a policy ends a session the client is warned (5 minutes)
+const EXPIRY_WARNING: Duration = Duration::from_secs(300);
+
+/// Server-wide services shared by every client connection
+#[derive(Clone)]
+pub struct ConnectionServices {
+    /// Bridge to USB subsystem
+    pub usb_bridge: UsbBridge,
+    /// Audit logger for compliance logging
+    pub audit_logger: SharedAuditLogger,
+    /// Rate limiter for bandwidth control
+    pub rate_limiter: RateLimiterSlot,
+    /// Policy engine for access control
+    pub policy_engine: Arc<PolicyEngine>,
+    /// Server-wide transfer metrics
+    pub metrics: SharedServerMetrics,
+    /// QoS scheduler
+    pub qos: SharedQosManager,
+    /// Allow and deny lists, consulted again after a configuration reload
+    pub approvals: SharedApprovalQueue,
+    /// Changes after each configuration reload
+    pub config_reloads: watch::Receiver<u64>,
+    /// Connected clients, reachable from the control socket
+    pub clients: SharedClientRegistry,
+}
 
 /// Per-client connection handler
 ///
@@ -55,23 +85,45 @@ pub struct ClientConnection {
     /// Bridge to USB subsystem
     usb_bridge: UsbBridge,
     /// Attached devices (handle -> device_id mapping)
-    attached_devices: HashMap<DeviceHandle, DeviceId>,
-    /// Pending transfers per device handle (for cancellation on hot-unplug)
-    pending_transfers: PendingTransfersMap,
+    attached_devices: AttachedDevicesMap,
+    /// Transfer execution shared by per-request streams and the transfer channel
+    transfers: TransferDispatcher,
+    /// Proactive interrupt streams pushed to the client
+    interrupt_streams: InterruptStreams,
     /// Last activity timestamp (for keep-alive)
     last_activity: Instant,
     /// Client supports push notifications (determined during capability exchange)
     client_supports_push: bool,
+    /// Client negotiated the persistent transfer channel
+    client_supports_transfer_channel: bool,
+    /// Client decodes the `V2` device list and notification variants
+    client_supports_extended_device_info: bool,
+    /// Client decodes `QuotaStatus` and `ForceDetachReason::QuotaExceeded`
+    client_supports_quotas: bool,
+    /// Client decodes `ReservationNotification` and `ForceDetachReason::Reserved`
+    client_supports_reservations: bool,
     /// Audit logger for compliance logging
     audit_logger: SharedAuditLogger,
-    /// Rate limiter for bandwidth control (optional)
-    rate_limiter: Option<SharedRateLimiter>,
     /// Notification aggregator for batching rapid device events
     notification_aggregator: NotificationAggregator,
     /// Policy engine for access control
     policy_engine: Arc<PolicyEngine>,
     /// Device info cache for policy checks (device_id -> device_info)
     device_info_cache: HashMap<DeviceId, protocol::DeviceInfo>,
+    /// Server-wide transfer metrics (also holds this client's own reports)
+    metrics: SharedServerMetrics,
+    /// QoS scheduler shared by all clients
+    qos: SharedQosManager,
+    /// Allow and deny lists
+    approvals: SharedApprovalQueue,
+    /// Changes after each configuration reload
+    config_reloads: watch::Receiver<u64>,
+    /// Connected clients, reachable from the control socket
+    clients: SharedClientRegistry,
+    /// Policy expiry each session has been warned about
+    expiry_warnings: HashMap<DeviceHandle, Instant>,
+    /// Active reservations the client has been told started
+    announced_reservations: HashMap<ReservationId, Reservation>,
 }
 
 impl ClientConnection {
@@ -79,24 +131,59 @@ impl ClientConnection {
     pub fn new(
         endpoint_id: EndpointId,
         connection: Connection,
-        usb_bridge: UsbBridge,
-        audit_logger: SharedAuditLogger,
-        rate_limiter: Option<SharedRateLimiter>,
-        policy_engine: Arc<PolicyEngine>,
+        services: ConnectionServices,
     ) -> Self {
+        let ConnectionServices {
+            usb_bridge,
+            audit_logger,
+            rate_limiter,
+            policy_engine,
+            metrics,
+            qos,
+            approvals,
+            config_reloads,
+            clients,
+        } = services;
+        let attached_devices: AttachedDevicesMap = Arc::new(RwLock::new(HashMap::new()));
+        let transfers = TransferDispatcher::new(
+            endpoint_id,
+            usb_bridge.clone(),
+            rate_limiter,
+            attached_devices.clone(),
+            metrics.clone(),
+            qos.clone(),
+            policy_engine.clone(),
+        );
+        let interrupt_streams = InterruptStreams::new(
+            connection.clone(),
+            usb_bridge.clone(),
+            attached_devices.clone(),
+        );
+
         Self {
             endpoint_id,
             connection,
             usb_bridge,
-            attached_devices: HashMap::new(),
-            pending_transfers: Arc::new(Mutex::new(HashMap::new())),
+            attached_devices,
+            transfers,
+            interrupt_streams,
             last_activity: Instant::now(),
             client_supports_push: false,
+            client_supports_transfer_channel: false,
+            client_supports_extended_device_info: false,
+            client_supports_quotas: false,
+            client_supports_reservations: false,
             audit_logger,
-            rate_limiter,
             notification_aggregator: NotificationAggregator::new(),
             policy_engine,
             device_info_cache: HashMap::new(),
+            metrics,
+            qos,
+            approvals,
+            config_reloads,
+            clients,
+            expiry_warnings: HashMap::new(),
+            announced_reservations: HashMap::new(),
         }
     }
 
@@ -114,28 +201,50 @@ impl ClientConnection {
             .context("Failed to read client capabilities")?;
         let message: Message = decode_framed(&message_bytes)?;
 
-        if let MessagePayload::ClientCapabilities {
+        // An invite only matters before the client is admitted; a client
+        // that already is may still present one, which is left unused
+        let supports_push_notifications = match message.payload {
+            MessagePayload::ClientCapabilities {
+                supports_push_notifications,
+            }
+            | MessagePayload::ClientCapabilitiesV2 {
+                supports_push_notifications,
+                ..
+            } => supports_push_notifications,
+            payload => {
+                return Err(anyhow!("Expected ClientCapabilities, got {:?}", payload));
+            }
+        };
+
+        // Newer features are implied by the client's protocol version
+        self.client_supports_push = supports_push_notifications;
+        self.client_supports_transfer_channel = message.version.supports_transfer_channel();
+        self.client_supports_extended_device_info = message.version.supports_extended_device_info();
+        self.client_supports_quotas = message.version.supports_quotas();
+        self.client_supports_reservations = message.version.supports_reservations();
+        info!(
+            "Client capabilities: version={}.{}, push_notifications={}, transfer_channel={}",
+            message.version.major,
+            message.version.minor,
             supports_push_notifications,
-        } = message.payload
-        {
-            self.client_supports_push = supports_push_notifications;
-            info!(
-                "Client capabilities: push_notifications={}",
-                supports_push_notifications
-            );
-        } else {
-            return Err(anyhow!(
-                "Expected ClientCapabilities, got {:?}",
-                message.payload
-            ));
-        }
+            self.client_supports_transfer_channel
+        );
 
-        // Send server capabilities
+        // Send server capabilities in a form the client can decode
+        let payload = if self.client_supports_transfer_channel {
+            MessagePayload::ServerCapabilitiesV2 {
+                will_send_notifications: true,
+                transfer_channel: true,
+                max_in_flight_transfers: MAX_IN_FLIGHT_TRANSFERS,
+            }
+        } else {
+            MessagePayload::ServerCapabilities {
+                will_send_notifications: true,
+            }
+        };
         let response = Message {
             version: CURRENT_VERSION,
-            payload: MessagePayload::ServerCapabilities {
-                will_send_notifications: true,
-            },
+            payload,
         };
         let response_bytes = encode_framed(&response)?;
         protocol::write_framed_async(&mut send, &response_bytes).await?;
@@ -151,6 +260,12 @@ impl ClientConnection {
     /// closes or an error occurs.
     pub async fn run(&mut self) -> Result<()> {
         info!("Starting connection handler for {}", self.endpoint_id);
+        self.metrics.client_connected(&self.endpoint_id.to_string());
+        self.metrics
+            .watch_connection(&self.endpoint_id.to_string(), self.connection.clone());
+        self.qos
+            .register_client(&self.endpoint_id.to_string())
+            .await;
 
         // Exchange capabilities with client
         if let Err(e) = self.exchange_capabilities().await {
@@ -167,6 +282,12 @@ impl ClientConnection {
             Self::keepalive_task(connection_clone, endpoint_id).await;
         });
 
+        let mut config_reloads = self.config_reloads.clone();
+        let (registration, mut commands) = self
+            .clients
+            .register(self.endpoint_id, self.attached_devices.clone());
+        let mut session_checks = time::interval(SESSION_CHECK_INTERVAL);
+        session_checks.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
         loop {
             let flush_delay = self
                 .notification_aggregator
@@ -215,7 +336,7 @@ impl ClientConnection {
                 }
 
                 // Check for expired sessions (every 30 seconds)
-                _ = time::sleep(Duration::from_secs(30)) => {
+                _ = session_checks.tick() => {
                     let idle_time = self.last_activity.elapsed();
                     if idle_time > Duration::from_secs(180) {
                         warn!("Connection idle for {:?}, closing", idle_time);
@@ -226,6 +347,42 @@ impl ClientConnection {
                     if let Err(e) = self.handle_expired_sessions().await {
                         warn!("Failed to handle expired sessions: {:#}", e);
                     }
+                    self.push_reservation_changes().await;
+                }
+
+                // Re-check sessions against a reloaded configuration
+                Ok(()) = config_reloads.changed() => {
+                    if !self.apply_config_reload().await {
+                        info!("Client {} is no longer approved, disconnecting", self.endpoint_id);
+                        self.connection
+                            .close(VarInt::from_u32(NOT_ADMITTED), b"no longer approved");
+                        break;
+                    }
+                }
+
+                // Carry out the operator's commands from the control socket
+                Some(command) = commands.recv() => {
+                    match command {
+                        ClientCommand::Disconnect { reason } => {
+                            info!(
+                                "Disconnecting {} on operator request: {}",
+                                self.endpoint_id, reason
+                            );
+                            self.connection.close(
+                                VarInt::from_u32(DISCONNECTED_BY_OPERATOR),
+                                reason.as_bytes(),
+                            );
+                            break;
+                        }
+                        ClientCommand::ForceDetach {
+                            handle,
+                            reason,
+                            response,
+                        } => {
+                            let detached = self.detach_for_operator(handle, reason).await;
+                            let _ = response.send(detached);
+                        }
+                    }
                 }
             }
 
@@ -238,6 +395,7 @@ impl ClientConnection {
         }
 
         // Cleanup: detach all devices
+        self.clients.unregister(registration);
         self.cleanup().await;
 
         info!("Connection handler stopped for {}", self.endpoint_id);
@@ -272,6 +430,51 @@ impl ClientConnection {
             return Ok(());
         }
 
+        // The persistent transfer channel takes over the stream for its lifetime
+        if let MessagePayload::OpenTransferChannel = message.payload {
+            return self.open_transfer_channel(send, recv).await;
+        }
+
+        // Interrupt flow control and metrics updates are fire-and-forget: no response frame
+        match message.payload {
+            MessagePayload::InterruptAck {
+                handle,
+                endpoint,
+                last_seq,
+                reports_received: _,
+            } => {
+                self.interrupt_streams
+                    .acknowledge(handle, endpoint, last_seq);
+                return send
+                    .finish()
+                    .context("Failed to finish interrupt ack stream");
+            }
+            MessagePayload::InterruptNack {
+                handle,
+                endpoint,
+                missing_sequences,
+                last_contiguous_seq: _,
+            } => {
+                self.interrupt_streams
+                    .retransmit(handle, endpoint, &missing_sequences);
+                return send
+                    .finish()
+                    .context("Failed to finish interrupt nack stream");
+            }
+            MessagePayload::ClientMetricsUpdate { metrics } => {
+                trace!(
+                    "Metrics from {}: loss={:.3} avg_latency={}us",
+                    self.endpoint_id, metrics.loss_rate, metrics.latency.avg_us
+                );
+                self.metrics
+                    .record_client_report(&self.endpoint_id.to_string(), metrics);
+                return send
+                    .finish()
+                    .context("Failed to finish metrics update stream");
+            }
+            _ => {}
+        }
+
         // Handle message and get response
         let response_payload = self.handle_message(message.payload).await?;
 
@@ -286,15 +489,53 @@ impl ClientConnection {
         Ok(())
     }
 
+    /// Hand a stream over to the persistent transfer channel
+    ///
+    /// The channel runs in its own task so transfers proceed concurrently with
+    /// the request/response streams handled by this connection.
+    async fn open_transfer_channel(&self, mut send: SendStream, recv: RecvStream) -> Result<()> {
+        if !self.client_supports_transfer_channel {
+            warn!(
+                "Client {} opened a transfer channel without negotiating it",
+                self.endpoint_id
+            );
+            let error_response = Message {
+                version: CURRENT_VERSION,
+                payload: MessagePayload::Error {
+                    message: "Transfer channel not negotiated".to_string(),
+                },
+            };
+            let response_bytes = encode_framed(&error_response)?;
+            protocol::write_framed_async(&mut send, &response_bytes).await?;
+            return Ok(());
+        }
+
+        let dispatcher = self.transfers.clone();
+        tokio::spawn(async move {
+            if let Err(e) =
+                serve_transfer_channel(dispatcher, send, recv, MAX_IN_FLIGHT_TRANSFERS).await
+            {
+                warn!("Transfer channel error: {:#}", e);
+            }
+        });
+
+        Ok(())
+    }
+
     /// Handle a protocol message and return response payload
     async fn handle_message(&mut self, payload: MessagePayload) -> Result<MessagePayload> {
         match payload {
             MessagePayload::ListDevicesRequest => self.handle_list_devices().await,
 
             MessagePayload::AttachDeviceRequest { device_id } => {
-                self.handle_attach_device(device_id).await
+                self.handle_attach_device(device_id, None).await
             }
 
+            MessagePayload::AttachInterfacesRequest {
+                device_id,
+                interfaces,
+            } => self.handle_attach_device(device_id, Some(interfaces)).await,
+
             MessagePayload::DetachDeviceRequest { handle } => {
                 self.handle_detach_device(handle).await
             }
@@ -345,6 +586,79 @@ impl ClientConnection {
                 self.handle_unlock_device(handle).await
             }
 
+            MessagePayload::StartInterruptStreamRequest {
+                handle,
+                endpoint,
+                buffer_hint,
+            } => {
+                let result = self
+                    .interrupt_streams
+                    .start(handle, endpoint, buffer_hint, self.client_supports_push)
+                    .await;
+                if let Err(ref e) = result {
+                    warn!(
+                        "Failed to start interrupt stream for {:?} ep {:#x}: {}",
+                        handle, endpoint, e
+                    );
+                }
+                Ok(MessagePayload::StartInterruptStreamResponse {
+                    handle,
+                    endpoint,
+                    result,
+                })
+            }
+
+            MessagePayload::StopInterruptStreamRequest { handle, endpoint } => {
+                let stats = self.interrupt_streams.stop(handle, endpoint).await;
+                Ok(MessagePayload::StopInterruptStreamResponse {
+                    handle,
+                    endpoint,
+                    stats,
+                })
+            }
+
+            MessagePayload::DeviceOperationRequest { handle, operation } => {
+                self.handle_device_operation(handle, operation).await
+            }
+
+            MessagePayload::GetDescriptorsRequest { device_id } => {
+                self.handle_get_descriptors(device_id).await
+            }
+
+            MessagePayload::GetMetricsRequest => Ok(MessagePayload::GetMetricsResponse {
+                metrics: self.metrics.summary_for(&self.endpoint_id.to_string()),
+            }),
+
+            MessagePayload::GetQuotaStatusRequest => Ok(MessagePayload::QuotaStatus {
+                quotas: self.policy_engine.quota_status(&self.endpoint_id).await,
+            }),
+
+            MessagePayload::CreateReservationRequest {
+                device_id,
+                starts_at,
+                ends_at,
+                note,
+            } => {
+                self.handle_create_reservation(device_id, starts_at, ends_at, note)
+                    .await
+            }
+
+            MessagePayload::ListReservationsRequest { device_id } => {
+                Ok(MessagePayload::ListReservationsResponse {
+                    reservations: self.policy_engine.reservations(device_id),
+                })
+            }
+
+            MessagePayload::CancelReservationRequest { id } => {
+                let result = self
+                    .policy_engine
+                    .cancel_reservation(id, Some(&self.endpoint_id))
+                    .await
+                    .map(|_| ());
+                self.push_reservation_changes().await;
+                Ok(MessagePayload::CancelReservationResponse { result })
+            }
+
             _ => {
                 warn!("Unexpected message type: {:?}", payload);
                 Ok(MessagePayload::Error {
@@ -367,44 +681,41 @@ impl ClientConnection {
         // Wait for response
         let devices = rx.await?;
 
-        Ok(MessagePayload::ListDevicesResponse { devices })
+        if self.client_supports_extended_device_info {
+            Ok(MessagePayload::ListDevicesResponseV2 { devices })
+        } else {
+            Ok(MessagePayload::ListDevicesResponse { devices })
+        }
     }
 
-    /// Handle AttachDeviceRequest
-    async fn handle_attach_device(&mut self, device_id: DeviceId) -> Result<MessagePayload> {
+    /// Handle AttachDeviceRequest and AttachInterfacesRequest
+    ///
+    /// `interfaces` limits the attachment to some interfaces of the device.
+    async fn handle_attach_device(
+        &mut self,
+        device_id: DeviceId,
+        interfaces: Option<Vec<u8>>,
+    ) -> Result<MessagePayload> {
         info!(
-            "Attach device request: {:?} from {}",
-            device_id, self.endpoint_id
+            "Attach device request: {:?} (interfaces {:?}) from {}",
+            device_id, interfaces, self.endpoint_id
         );
 
-        // Get device info for policy check (from cache or fetch)
-        let device_info = if let Some(info) = self.device_info_cache.get(&device_id) {
-            info.clone()
-        } else {
-            // Fetch device list to get info
-            let (tx, rx) = tokio::sync::oneshot::channel();
-            self.usb_bridge
-                .send_command(UsbCommand::ListDevices { response: tx })
-                .await?;
-            let devices = rx.await?;
-
-            // Update cache with all devices
-            for dev in &devices {
-                self.device_info_cache.insert(dev.id, dev.clone());
-            }
-
-            match devices.into_iter().find(|d| d.id == device_id) {
-                Some(info) => info,
-                None => {
-                    return Ok(MessagePayload::AttachDeviceResponse {
-                        result: Err(AttachError::DeviceNotFound),
-                    });
-                }
-            }
+        // Get device info for policy check
+        let Some(device_info) = self.lookup_device_info(device_id).await? else {
+            return Ok(MessagePayload::AttachDeviceResponse {
+                result: Err(AttachError::DeviceNotFound),
+            });
         };
 
         // Check policy before attaching
-        let policy_decision = self.policy_engine.check_access(&self.endpoint_id, &device_info);
+        let policy_decision = match &interfaces {
+            Some(interfaces) => {
+                self.policy_engine
+                    .check_interface_access(&self.endpoint_id, &device_info, interfaces)
+            }
+            None => self.policy_engine.check_access(&self.endpoint_id, &device_info),
+        };
         match policy_decision {
             PolicyDecision::Allow => {
                 // Policy allows access, continue with attach
@@ -417,6 +728,7 @@ impl ClientConnection {
                     "Policy denied attach for device {:?} from {}: {}",
                     device_id, endpoint_id_str, reason
                 );
+                self.metrics.policy_denied(&endpoint_id_str, &reason);
 
                 // Audit log: policy denied attach
                 if let Some(ref logger) = *self.audit_logger {
@@ -438,13 +750,20 @@ impl ClientConnection {
 
         // Send command to USB subsystem
         let (tx, rx) = tokio::sync::oneshot::channel();
-        self.usb_bridge
-            .send_command(UsbCommand::AttachDevice {
+        let command = match interfaces.clone() {
+            Some(interfaces) => UsbCommand::AttachInterfaces {
                 device_id,
                 client_id: self.endpoint_id.to_string(),
+                interfaces,
                 response: tx,
-            })
-            .await?;
+            },
+            None => UsbCommand::AttachDevice {
+                device_id,
+                client_id: self.endpoint_id.to_string(),
+                response: tx,
+            },
+        };
+        self.usb_bridge.send_command(command).await?;
 
         // Wait for response
         let result = rx.await?;
@@ -453,7 +772,10 @@ impl ClientConnection {
         let endpoint_id_str = self.endpoint_id.to_string();
         match &result {
             Ok(handle) => {
-                self.attached_devices.insert(*handle, device_id);
+                self.attached_devices
+                    .write()
+                    .await
+                    .insert(*handle, device_id);
                 info!(
                     "Device attached: handle={:?}, device={:?}",
                     handle, device_id
@@ -461,8 +783,16 @@ impl ClientConnection {
 
                 // Register session with policy engine for duration/time window monitoring
                 self.policy_engine
-                    .register_session(*handle, device_id, &device_info, self.endpoint_id)
+                    .register_session(
+                        *handle,
+                        device_id,
+                        &device_info,
+                        interfaces,
+                        self.endpoint_id,
+                    )
                     .await;
+                self.qos.register_device_info(*handle, &device_info).await;
+                self.push_quota_status().await;
 
                 // Audit log: successful attach
                 if let Some(ref logger) = *self.audit_logger {
@@ -494,6 +824,32 @@ impl ClientConnection {
         Ok(MessagePayload::AttachDeviceResponse { result })
     }
 
+    /// Device info for policy checks, from the cache or a fresh device list
+    ///
+    /// None if the device is not listed, e.g. because `[usb] filters` hide it.
+    async fn lookup_device_info(
+        &mut self,
+        device_id: DeviceId,
+    ) -> Result<Option<protocol::DeviceInfo>> {
+        if let Some(info) = self.device_info_cache.get(&device_id) {
+            return Ok(Some(info.clone()));
+        }
+
+        // Fetch device list to get info
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.usb_bridge
+            .send_command(UsbCommand::ListDevices { response: tx })
+            .await?;
+        let devices = rx.await?;
+
+        // Update cache with all devices
+        for dev in &devices {
+            self.device_info_cache.insert(dev.id, dev.clone());
+        }
+
+        Ok(devices.into_iter().find(|d| d.id == device_id))
+    }
+
     /// Convert policy denial reason to AttachError
     fn policy_denial_to_attach_error(reason: &PolicyDenialReason) -> AttachError {
         match reason {
@@ -523,6 +879,13 @@ impl ClientConnection {
             PolicyDenialReason::NoMatchingPolicy => AttachError::PolicyDenied {
                 reason: "No matching policy found for this device".to_string(),
             },
+            PolicyDenialReason::InterfaceNotAllowed { .. }
+            | PolicyDenialReason::InterfaceClassRestricted { .. }
+            | PolicyDenialReason::OutsideClientScope
+            | PolicyDenialReason::QuotaExceeded { .. }
+            | PolicyDenialReason::Reserved { .. } => AttachError::PolicyDenied {
+                reason: reason.to_string(),
+            },
         }
     }
 
@@ -534,7 +897,10 @@ impl ClientConnection {
         );
 
         // Get device_id before we remove it from tracking
-        let device_id = self.attached_devices.get(&handle).copied();
+        let device_id = self.attached_devices.read().await.get(&handle).copied();
+
+        // Stop interrupt pollers before the handle is closed
+        self.interrupt_streams.stop_handle(handle).await;
 
         // Send command to USB subsystem
         let (tx, rx) = tokio::sync::oneshot::channel();
@@ -551,11 +917,12 @@ impl ClientConnection {
         // Remove from tracked devices and audit log
         let endpoint_id_str = self.endpoint_id.to_string();
         if result.is_ok() {
-            self.attached_devices.remove(&handle);
+            self.attached_devices.write().await.remove(&handle);
             info!("Device detached: handle={:?}", handle);
 
             // Unregister session from policy engine
             self.policy_engine.unregister_session(handle).await;
+            self.qos.unregister_device(handle).await;
 
             // Audit log: successful detach
             if let Some(ref logger) = *self.audit_logger {
@@ -585,135 +952,82 @@ impl ClientConnection {
 
     /// Handle SubmitTransfer
     async fn handle_submit_transfer(&self, request: UsbRequest) -> Result<MessagePayload> {
-        trace!("Submit transfer request: id={:?}", request.id);
-
-        // Verify device is attached
-        if !self.attached_devices.contains_key(&request.handle) {
-            warn!("Transfer to unattached device: {:?}", request.handle);
-            return Ok(MessagePayload::TransferComplete {
-                response: protocol::UsbResponse {
-                    id: request.id,
-                    result: protocol::TransferResult::Error {
-                        error: protocol::UsbError::NotFound,
+        let request_id = request.id;
+        let response = match self.transfers.submit(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Transfer {:?} failed to dispatch: {:#}", request_id, e);
+                UsbResponse {
+                    id: request_id,
+                    result: TransferResult::Error {
+                        error: UsbError::Other {
+                            message: e.to_string(),
+                        },
                     },
-                },
-            });
-        }
-
-        // Calculate transfer data size for rate limiting
-        let transfer_bytes = Self::get_transfer_data_size(&request.transfer);
-
-        // Apply rate limiting if enabled
-        if let Some(ref limiter) = self.rate_limiter {
-            let client_id = self.endpoint_id.to_string();
-            let device_id = self.attached_devices.get(&request.handle).map(|d| d.0);
-
-            // Check rate limit and wait if necessary
-            let result = limiter
-                .check(Some(&client_id), device_id, transfer_bytes)
-                .await;
-
-            match result {
-                RateLimitResult::Allowed => {
-                    // Use try_acquire to atomically check and consume tokens
-                    if !limiter
-                        .try_acquire(Some(&client_id), device_id, transfer_bytes)
-                        .await
-                    {
-                        trace!(
-                            "Rate limit: transfer {:?} delayed, tokens unavailable",
-                            request.id
-                        );
-                    }
-                }
-                RateLimitResult::Wait(duration) => {
-                    debug!(
-                        "Rate limit: transfer {:?} waiting {:?} for {} bytes",
-                        request.id, duration, transfer_bytes
-                    );
-                    tokio::time::sleep(duration).await;
-                    // Record the transfer after waiting
-                    limiter
-                        .record(Some(&client_id), device_id, transfer_bytes)
-                        .await;
                 }
             }
-        }
-
-        // Create cancellation channel for this transfer
-        let (cancel_tx, mut cancel_rx) = broadcast::channel::<()>(1);
-        let pending = PendingTransfer {
-            request_id: request.id,
-            cancel_tx,
         };
+        Ok(MessagePayload::TransferComplete { response })
+    }
 
-        // Register pending transfer
-        let handle = request.handle;
-        let request_id = request.id;
-        {
-            let mut pending_map = self.pending_transfers.lock().await;
-            pending_map.entry(handle).or_default().push(pending);
+    /// Handle DeviceOperationRequest
+    async fn handle_device_operation(
+        &self,
+        handle: DeviceHandle,
+        operation: DeviceOperation,
+    ) -> Result<MessagePayload> {
+        info!(
+            "Device operation {:?} on {:?} from {}",
+            operation, handle, self.endpoint_id
+        );
+
+        // Verify device is attached
+        if !self.attached_devices.read().await.contains_key(&handle) {
+            return Ok(MessagePayload::DeviceOperationResponse {
+                handle,
+                result: Err(UsbError::NotFound),
+            });
         }
 
         // Send command to USB subsystem
         let (tx, rx) = tokio::sync::oneshot::channel();
-        self.usb_bridge
-            .send_command(UsbCommand::SubmitTransfer {
-                handle: request.handle,
-                request: request.clone(),
+        let command = match operation {
+            DeviceOperation::Reset => UsbCommand::ResetDevice {
+                handle,
                 response: tx,
-            })
-            .await?;
-
-        // Wait for either transfer completion or cancellation
-        let response = tokio::select! {
-            result = rx => {
-                match result {
-                    Ok(response) => response,
-                    Err(_) => {
-                        warn!("Transfer response channel closed for {:?}", request_id);
-                        protocol::UsbResponse {
-                            id: request_id,
-                            result: protocol::TransferResult::Error {
-                                error: protocol::UsbError::NoDevice,
-                            },
-                        }
-                    }
-                }
-            }
-            _ = cancel_rx.recv() => {
-                info!("Transfer {:?} cancelled due to device hot-unplug", request_id);
-                protocol::UsbResponse {
-                    id: request_id,
-                    result: protocol::TransferResult::Error {
-                        error: protocol::UsbError::NoDevice,
-                    },
-                }
-            }
+            },
+            DeviceOperation::ClearHalt { endpoint } => UsbCommand::ClearHalt {
+                handle,
+                endpoint,
+                response: tx,
+            },
+            DeviceOperation::SetConfiguration { configuration } => UsbCommand::SetConfiguration {
+                handle,
+                configuration,
+                response: tx,
+            },
+            DeviceOperation::SetInterface {
+                interface,
+                alt_setting,
+            } => UsbCommand::SetInterface {
+                handle,
+                interface,
+                alt_setting,
+                response: tx,
+            },
         };
+        self.usb_bridge.send_command(command).await?;
 
-        // Remove this transfer from pending map
-        {
-            let mut pending_map = self.pending_transfers.lock().await;
-            if let Some(transfers) = pending_map.get_mut(&handle) {
-                transfers.retain(|t| t.request_id != request_id);
-                if transfers.is_empty() {
-                    pending_map.remove(&handle);
-                }
-            }
+        // Wait for response
+        let result = rx.await?;
+        if let Err(ref e) = result {
+            warn!(
+                "Device operation {:?} on {:?} failed: {:?}",
+                operation, handle, e
+            );
         }
 
-        Ok(MessagePayload::TransferComplete { response })
-    }
-
-    /// Get the data size of a transfer for rate limiting
-    fn get_transfer_data_size(transfer: &TransferType) -> u64 {
-        match transfer {
-            TransferType::Control { data, .. } => data.len() as u64,
-            TransferType::Interrupt { data, .. } => data.len() as u64,
-            TransferType::Bulk { data, .. } => data.len() as u64,
-            TransferType::Isochronous { data, .. } => data.len() as u64,
-        }
+        Ok(MessagePayload::DeviceOperationResponse { handle, result })
     }
 
     /// Handle GetSharingStatusRequest
@@ -726,6 +1040,8 @@ impl ClientConnection {
         // Find the handle for this device if attached
         let handle = self
             .attached_devices
+            .read()
+            .await
             .iter()
             .find(|(_, id)| **id == device_id)
             .map(|(h, _)| *h);
@@ -746,6 +1062,51 @@ impl ClientConnection {
         Ok(MessagePayload::GetSharingStatusResponse { result })
     }
 
+    /// Handle GetDescriptorsRequest
+    async fn handle_get_descriptors(&mut self, device_id: DeviceId) -> Result<MessagePayload> {
+        debug!(
+            "Get descriptors request: {:?} from {}",
+            device_id, self.endpoint_id
+        );
+
+        // Same visibility as attach: listed devices only, and only if policy
+        // lets this client use the device. Interface restrictions are not
+        // checked; clients need the descriptors to pick their interfaces.
+        let Some(device_info) = self.lookup_device_info(device_id).await? else {
+            return Ok(MessagePayload::GetDescriptorsResponse {
+                device_id,
+                result: Err(UsbError::NotFound),
+            });
+        };
+        if let PolicyDecision::Deny(reason) =
+            self.policy_engine
+                .check_interface_access(&self.endpoint_id, &device_info, &[])
+        {
+            warn!(
+                "Policy denied descriptors of device {:?} to {}: {}",
+                device_id, self.endpoint_id, reason
+            );
+            self.metrics
+                .policy_denied(&self.endpoint_id.to_string(), &reason);
+            return Ok(MessagePayload::GetDescriptorsResponse {
+                device_id,
+                result: Err(UsbError::Access),
+            });
+        }
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.usb_bridge
+            .send_command(UsbCommand::GetDescriptors {
+                device_id,
+                response: tx,
+            })
+            .await?;
+
+        let result = rx.await?;
+
+        Ok(MessagePayload::GetDescriptorsResponse { device_id, result })
+    }
+
     /// Handle LockDeviceRequest
     async fn handle_lock_device(
         &self,
@@ -758,12 +1119,28 @@ impl ClientConnection {
         );
 
         // Verify device is attached
-        if !self.attached_devices.contains_key(&handle) {
+        let Some(device_id) = self.attached_devices.read().await.get(&handle).copied() else {
             return Ok(MessagePayload::LockDeviceResponse {
                 result: protocol::LockResult::NotAvailable {
                     reason: "Device not attached".to_string(),
                 },
             });
+        };
+
+        // Only the holder of an active reservation may lock the device
+        if let Some(reservation) = self
+            .policy_engine
+            .reserved_for_other(&self.endpoint_id, device_id)
+        {
+            return Ok(MessagePayload::LockDeviceResponse {
+                result: protocol::LockResult::NotAvailable {
+                    reason: format!(
+                        "Reserved by {} until {}",
+                        reservation.holder_label(),
+                        self.policy_engine.local_time(reservation.ends_at)
+                    ),
+                },
+            });
         }
 
         // Send command to USB subsystem
@@ -790,7 +1167,7 @@ impl ClientConnection {
         );
 
         // Verify device is attached
-        if !self.attached_devices.contains_key(&handle) {
+        if !self.attached_devices.read().await.contains_key(&handle) {
             return Ok(MessagePayload::UnlockDeviceResponse {
                 result: protocol::UnlockResult::NotHeld,
             });
@@ -839,6 +1216,123 @@ impl ClientConnection {
         Ok(())
     }
 
+    /// Handle CreateReservationRequest
+    async fn handle_create_reservation(
+        &mut self,
+        device_id: DeviceId,
+        starts_at: u64,
+        ends_at: u64,
+        note: Option<String>,
+    ) -> Result<MessagePayload> {
+        info!(
+            "Reservation request: {:?} from {} to {} by {}",
+            device_id, starts_at, ends_at, self.endpoint_id
+        );
+
+        let Some(device_info) = self.lookup_device_info(device_id).await? else {
+            return Ok(MessagePayload::CreateReservationResponse {
+                result: Err(ReservationError::DeviceNotFound),
+            });
+        };
+        let result = self
+            .policy_engine
+            .create_reservation(&self.endpoint_id, &device_info, starts_at, ends_at, note)
+            .await;
+        if let Err(ref e) = result {
+            warn!(
+                "Reservation of device {:?} by {} refused: {}",
+                device_id, self.endpoint_id, e
+            );
+        }
+        self.push_reservation_changes().await;
+        Ok(MessagePayload::CreateReservationResponse { result })
+    }
+
+    /// Tell the client about reservations that started or ended
+    ///
+    /// Covers reservations the client holds and those of devices it has
+    /// attached. Like queue positions, changes are pushed as they are
+    /// noticed, on each session check and after the client's own requests.
+    async fn push_reservation_changes(&mut self) {
+        if !self.client_supports_reservations {
+            return;
+        }
+
+        let holder = self.endpoint_id.to_string();
+        let attached: HashSet<DeviceId> = self
+            .attached_devices
+            .read()
+            .await
+            .values()
+            .copied()
+            .collect();
+        let active: HashMap<ReservationId, Reservation> = self
+            .policy_engine
+            .active_reservations()
+            .into_iter()
+            .map(|reservation| (reservation.id, reservation))
+            .collect();
+
+        let mut updates = Vec::new();
+        for (id, reservation) in &active {
+            if !self.announced_reservations.contains_key(id)
+                && (reservation.holder == holder || attached.contains(&reservation.device_id))
+            {
+                self.announced_reservations.insert(*id, reservation.clone());
+                updates.push(ReservationUpdate {
+                    reservation: reservation.clone(),
+                    event: ReservationEvent::Started,
+                });
+            }
+        }
+        let now = reservation::unix_now();
+        self.announced_reservations.retain(|id, reservation| {
+            if active.contains_key(id) {
+                return true;
+            }
+            let event = if reservation.ends_at <= now {
+                ReservationEvent::Ended
+            } else {
+                ReservationEvent::Cancelled
+            };
+            updates.push(ReservationUpdate {
+                reservation: reservation.clone(),
+                event,
+            });
+            false
+        });
+
+        for update in updates {
+            debug!(
+                "Reservation {} of device {:?} {}",
+                update.reservation.id.0, update.reservation.device_id, update.event
+            );
+            if let Err(e) = self
+                .send_push_notification(MessagePayload::ReservationNotification { update })
+                .await
+            {
+                warn!("Failed to send reservation notification: {:#}", e);
+            }
+        }
+    }
+
+    /// Push the client's quota usage, if any quota applies to it
+    async fn push_quota_status(&self) {
+        if !self.client_supports_quotas {
+            return;
+        }
+        let quotas = self.policy_engine.quota_status(&self.endpoint_id).await;
+        if quotas.is_empty() {
+            return;
+        }
+        if let Err(e) = self
+            .send_push_notification(MessagePayload::QuotaStatus { quotas })
+            .await
+        {
+            warn!("Failed to send quota status: {:#}", e);
+        }
+    }
+
     /// Flush aggregated notifications to the client
     async fn flush_aggregated_notifications(&mut self) -> Result<()> {
         if let Some(notifications) = self.notification_aggregator.flush() {
@@ -851,8 +1345,12 @@ impl ClientConnection {
                 notifications.len()
             );
 
-            self.send_push_notification(MessagePayload::AggregatedNotifications { notifications })
-                .await
+            let payload = if self.client_supports_extended_device_info {
+                MessagePayload::AggregatedNotificationsV2 { notifications }
+            } else {
+                MessagePayload::AggregatedNotifications { notifications }
+            };
+            self.send_push_notification(payload).await
         } else {
             Ok(())
         }
@@ -883,8 +1381,13 @@ impl ClientConnection {
                     device_id, invalidated_handles, affected_clients
                 );
 
+                self.interrupt_streams.stop_device(device_id).await;
+
                 // Cancel all pending transfers for invalidated handles
-                let cancelled_count = self.cancel_pending_transfers(&invalidated_handles).await;
+                let cancelled_count = self
+                    .transfers
+                    .cancel_pending_transfers(&invalidated_handles)
+                    .await;
                 if cancelled_count > 0 {
                     info!(
                         "Cancelled {} pending transfers for device {:?}",
@@ -894,8 +1397,9 @@ impl ClientConnection {
 
                 // Remove invalidated handles from our attached devices map
                 for handle in &invalidated_handles {
-                    if self.attached_devices.remove(handle).is_some() {
+                    if self.attached_devices.write().await.remove(handle).is_some() {
                         info!("Auto-detached device: handle={:?}", handle);
+                        self.qos.unregister_device(*handle).await;
                     }
                 }
 
@@ -903,6 +1407,8 @@ impl ClientConnection {
                 // (fallback for consistency)
                 let remaining_handles: Vec<DeviceHandle> = self
                     .attached_devices
+                    .read()
+                    .await
                     .iter()
                     .filter(|(_, id)| **id == device_id)
                     .map(|(handle, _)| *handle)
@@ -910,8 +1416,10 @@ impl ClientConnection {
 
                 // Cancel pending transfers for remaining handles too
                 if !remaining_handles.is_empty() {
-                    let additional_cancelled =
-                        self.cancel_pending_transfers(&remaining_handles).await;
+                    let additional_cancelled = self
+                        .transfers
+                        .cancel_pending_transfers(&remaining_handles)
+                        .await;
                     if additional_cancelled > 0 {
                         info!(
                             "Cancelled {} additional pending transfers (fallback)",
@@ -921,7 +1429,7 @@ impl ClientConnection {
                 }
 
                 for handle in remaining_handles {
-                    self.attached_devices.remove(&handle);
+                    self.attached_devices.write().await.remove(&handle);
                     info!("Auto-detached device (fallback): handle={:?}", handle);
                 }
 
@@ -1014,138 +1522,289 @@ impl ClientConnection {
         Ok(())
     }
 
-    /// Cancel all pending transfers for the given device handles
+    /// Handle expired sessions for this client
     ///
-    /// Returns the number of transfers cancelled. Each pending transfer will
-    /// receive a cancellation signal and respond with UsbError::NoDevice.
-    async fn cancel_pending_transfers(&self, handles: &[DeviceHandle]) -> usize {
-        let mut cancelled = 0;
-        let mut pending_map = self.pending_transfers.lock().await;
+    /// Warns the client with a `ForceDetachWarning` countdown before a
+    /// session reaches its duration limit, the end of its time window or the
+    /// end of its time quota, and force-detaches the sessions that have.
+    async fn handle_expired_sessions(&mut self) -> Result<()> {
+        self.warn_expiring_sessions().await;
 
-        for handle in handles {
-            if let Some(transfers) = pending_map.remove(handle) {
-                for transfer in transfers {
-                    debug!(
-                        "Cancelling pending transfer {:?} for device {:?}",
-                        transfer.request_id, handle
-                    );
-                    let _ = transfer.cancel_tx.send(());
-                    cancelled += 1;
-                }
+        // Check all expired sessions - the policy engine tracks them
+        let expired = self.policy_engine.check_expired_sessions().await;
+
+        // Process expired sessions
+        for event in expired {
+            // Only handle events for this client
+            if event.client_id != self.endpoint_id {
+                continue;
+            }
+
+            info!(
+                "Session expired for device {:?} (handle {:?}): {:?}",
+                event.device_id, event.handle, event.reason
+            );
+
+            let note = format!("Session expired: {:?}", event.reason);
+            let reason = self.expiry_detach_reason(event.reason);
+            if self.expiry_warnings.remove(&event.handle).is_some() {
+                self.force_detach(event.handle, reason, note).await;
+            } else {
+                self.warn_and_force_detach(event.handle, event.device_id, reason, note)
+                    .await;
             }
         }
 
-        cancelled
+        Ok(())
     }
 
-    /// Handle expired sessions for this client
+    /// Send a `ForceDetachWarning` for sessions a policy ends soon
     ///
-    /// Checks all attached devices for session expiration and force-detaches
-    /// any that have exceeded their time limits or are outside time windows.
-    async fn handle_expired_sessions(&mut self) -> Result<()> {
-        // Check all expired sessions - the policy engine tracks them
-        let expired = self.policy_engine.check_expired_sessions().await;
-
-        if expired.is_empty() {
-            return Ok(());
+    /// Each session is warned once per expiry; a reload that moves the
+    /// expiry warns again.
+    async fn warn_expiring_sessions(&mut self) {
+        let attached: HashSet<DeviceHandle> =
+            self.attached_devices.read().await.keys().copied().collect();
+        self.expiry_warnings
+            .retain(|handle, _| attached.contains(handle));
+        if !self.client_supports_push {
+            return;
         }
 
-        // Process expired sessions
-        for event in expired {
-                // Only handle events for this client
-                if event.client_id != self.endpoint_id {
-                    continue;
+        let expiring = self
+            .policy_engine
+            .expiring_sessions(&self.endpoint_id, EXPIRY_WARNING)
+            .await;
+        for session in expiring {
+            if !attached.contains(&session.handle)
+                || self.expiry_warnings.get(&session.handle) == Some(&session.expires_at)
+            {
+                continue;
+            }
+            let remaining = session.expires_at.saturating_duration_since(Instant::now());
+            debug!(
+                "Session of device {:?} (handle {:?}) expires in {:?}: {:?}",
+                session.device_id, session.handle, remaining, session.reason
+            );
+            let warning = MessagePayload::ForceDetachWarning {
+                handle: session.handle,
+                device_id: session.device_id,
+                reason: self.expiry_detach_reason(session.reason),
+                seconds_until_detach: remaining.as_secs().try_into().unwrap_or(u32::MAX),
+            };
+            match self.send_push_notification(warning).await {
+                Ok(()) => {
+                    self.expiry_warnings
+                        .insert(session.handle, session.expires_at);
                 }
+                Err(e) => warn!("Failed to send force-detach warning: {:#}", e),
+            }
+        }
+    }
 
-                // Only handle events for handles we're tracking
-                if !self.attached_devices.contains_key(&event.handle) {
-                    continue;
+    /// What to tell the client about a session a policy ends
+    ///
+    /// Clients before 1.11 cannot decode `QuotaExceeded`, nor those before
+    /// 1.12 `Reserved`; they are told the reason as an administrative one
+    /// instead.
+    fn expiry_detach_reason(&self, reason: SessionExpiredReason) -> ForceDetachReason {
+        match reason {
+            SessionExpiredReason::DurationLimitReached => {
+                ForceDetachReason::SessionDurationLimitReached {
+                    duration_secs: 0,     // Session duration not tracked in event
+                    max_duration_secs: 0, // Max duration not tracked in event
                 }
+            }
+            SessionExpiredReason::TimeWindowExpired {
+                current_time,
+                next_window,
+            } => ForceDetachReason::TimeWindowExpired {
+                current_time,
+                next_window,
+            },
+            SessionExpiredReason::QuotaExceeded { quota } if self.client_supports_quotas => {
+                ForceDetachReason::QuotaExceeded { quota }
+            }
+            SessionExpiredReason::QuotaExceeded { quota } => ForceDetachReason::AdminAction {
+                reason: Some(format!("Quota used up: {}", quota)),
+            },
+            SessionExpiredReason::Reserved { reservation } if self.client_supports_reservations => {
+                ForceDetachReason::Reserved { reservation }
+            }
+            SessionExpiredReason::Reserved { reservation } => ForceDetachReason::AdminAction {
+                reason: Some(format!(
+                    "Reserved by {} from {}",
+                    reservation.holder_label(),
+                    self.policy_engine.local_time(reservation.starts_at)
+                )),
+            },
+        }
+    }
 
-                info!(
-                    "Session expired for device {:?} (handle {:?}): {:?}",
-                    event.device_id, event.handle, event.reason
-                );
+    /// Re-check this client's sessions after a configuration reload
+    ///
+    /// Sessions the new policies forbid are detached after a
+    /// `ForceDetachWarning`. Returns false when the client is no longer
+    /// admitted at all; every device is then detached and the connection
+    /// should close.
+    async fn apply_config_reload(&mut self) -> bool {
+        let admitted = self.approvals.admits(&self.endpoint_id).await;
+        let revoked: Vec<(DeviceHandle, DeviceId, String)> = if admitted {
+            self.policy_engine
+                .revoked_sessions(&self.endpoint_id)
+                .await
+                .into_iter()
+                .map(|(session, reason)| (session.handle, session.device_id, reason.to_string()))
+                .collect()
+        } else {
+            self.attached_devices
+                .read()
+                .await
+                .iter()
+                .map(|(handle, device_id)| {
+                    (
+                        *handle,
+                        *device_id,
+                        "Client is no longer approved".to_string(),
+                    )
+                })
+                .collect()
+        };
 
-                // Force detach the device
-                let device_id = self.attached_devices.remove(&event.handle);
-
-                // Send detach command to USB subsystem
-                let (tx, rx) = tokio::sync::oneshot::channel();
-                if let Err(e) = self
-                    .usb_bridge
-                    .send_command(UsbCommand::DetachDevice {
-                        handle: event.handle,
-                        response: tx,
-                    })
-                    .await
-                {
-                    warn!("Failed to send detach command for expired session: {:#}", e);
-                    continue;
-                }
+        for (handle, device_id, why) in revoked {
+            info!(
+                "Configuration reload revoked device {:?} (handle {:?}) for {}: {}",
+                device_id, handle, self.endpoint_id, why
+            );
+            let reason = ForceDetachReason::AdminAction {
+                reason: Some(format!("Configuration reloaded: {}", why)),
+            };
+            let note = format!("Revoked by configuration reload: {}", why);
+            self.warn_and_force_detach(handle, device_id, reason, note)
+                .await;
+        }
 
-                // Wait for response (don't block too long)
-                match tokio::time::timeout(Duration::from_secs(5), rx).await {
-                    Ok(Ok(Ok(()))) => {
-                        info!("Force-detached expired device {:?}", event.handle);
-                    }
-                    Ok(Ok(Err(e))) => {
-                        warn!("Force-detach failed: {:?}", e);
-                    }
-                    Ok(Err(_)) => {
-                        warn!("Force-detach response channel closed");
-                    }
-                    Err(_) => {
-                        warn!("Force-detach response timeout");
-                    }
-                }
+        admitted
+    }
+
+    /// Detach `handle` because the operator asked to
+    ///
+    /// Returns false when this client does not hold the handle.
+    async fn detach_for_operator(&mut self, handle: DeviceHandle, reason: Option<String>) -> bool {
+        let Some(device_id) = self.attached_devices.read().await.get(&handle).copied() else {
+            return false;
+        };
+        info!(
+            "Operator detached device {:?} (handle {:?}) from {}",
+            device_id, handle, self.endpoint_id
+        );
+        let note = format!(
+            "Detached by operator: {}",
+            reason.as_deref().unwrap_or("no reason given")
+        );
+        let reason = ForceDetachReason::AdminAction { reason };
+        self.warn_and_force_detach(handle, device_id, reason, note)
+            .await;
+        true
+    }
 
-                // Unregister from policy engine
-                self.policy_engine.unregister_session(event.handle).await;
+    /// Send a `ForceDetachWarning` and detach right away
+    async fn warn_and_force_detach(
+        &mut self,
+        handle: DeviceHandle,
+        device_id: DeviceId,
+        reason: ForceDetachReason,
+        note: String,
+    ) {
+        if self.client_supports_push {
+            let warning = MessagePayload::ForceDetachWarning {
+                handle,
+                device_id,
+                reason: reason.clone(),
+                seconds_until_detach: 0,
+            };
+            if let Err(e) = self.send_push_notification(warning).await {
+                warn!("Failed to send force-detach warning: {:#}", e);
+            }
+        }
+        self.force_detach(handle, reason, note).await;
+    }
 
-                // Convert reason to ForceDetachReason
-                let reason = match event.reason {
-                    crate::policy::SessionExpiredReason::DurationLimitReached => {
-                        ForceDetachReason::SessionDurationLimitReached {
-                            duration_secs: 0, // Session duration not tracked in event
-                            max_duration_secs: 0, // Max duration not tracked in event
-                        }
-                    }
-                    crate::policy::SessionExpiredReason::TimeWindowExpired => {
-                        ForceDetachReason::TimeWindowExpired {
-                            current_time: "expired".to_string(),
-                            next_window: None, // Next window not tracked in event
-                        }
-                    }
-                };
-
-                // Send notification to client
-                if self.client_supports_push {
-                    let notification = MessagePayload::ForcedDetachNotification {
-                        handle: event.handle,
-                        device_id: event.device_id,
-                        reason,
-                    };
+    /// Detach a device the client did not ask to detach
+    ///
+    /// Tells the client with `ForcedDetachNotification` and records `note`
+    /// in the audit log. Handles this client does not hold are ignored.
+    async fn force_detach(
+        &mut self,
+        handle: DeviceHandle,
+        reason: ForceDetachReason,
+        note: String,
+    ) {
+        // Only handle handles we're tracking
+        let Some(device_id) = self.attached_devices.write().await.remove(&handle) else {
+            return;
+        };
+        self.interrupt_streams.stop_handle(handle).await;
+        self.transfers.cancel_pending_transfers(&[handle]).await;
 
-                    if let Err(e) = self.send_push_notification(notification).await {
-                        warn!("Failed to send force-detach notification: {:#}", e);
-                    }
+        // Send detach command to USB subsystem
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        match self
+            .usb_bridge
+            .send_command(UsbCommand::DetachDevice {
+                handle,
+                response: tx,
+            })
+            .await
+        {
+            // Wait for response (don't block too long)
+            Ok(()) => match tokio::time::timeout(Duration::from_secs(5), rx).await {
+                Ok(Ok(Ok(()))) => {
+                    info!("Force-detached device {:?}", handle);
                 }
-
-                // Audit log
-                let endpoint_id_str = self.endpoint_id.to_string();
-                if let Some(ref logger) = *self.audit_logger {
-                    logger.log_device_detach(
-                        &endpoint_id_str,
-                        event.handle,
-                        device_id,
-                        AuditResult::Success,
-                        Some(format!("Session expired: {:?}", event.reason)),
-                    );
+                Ok(Ok(Err(e))) => {
+                    warn!("Force-detach failed: {:?}", e);
+                }
+                Ok(Err(_)) => {
+                    warn!("Force-detach response channel closed");
+                }
+                Err(_) => {
+                    warn!("Force-detach response timeout");
                 }
+            },
+            Err(e) => {
+                warn!("Failed to send force-detach command: {:#}", e);
             }
+        }
 
-        Ok(())
+        // Unregister from policy engine
+        self.policy_engine.unregister_session(handle).await;
+        self.qos.unregister_device(handle).await;
+
+        // Send notification to client
+        if self.client_supports_push {
+            let notification = MessagePayload::ForcedDetachNotification {
+                handle,
+                device_id,
+                reason,
+            };
+
+            if let Err(e) = self.send_push_notification(notification).await {
+                warn!("Failed to send force-detach notification: {:#}", e);
+            }
+        }
+
+        // Audit log
+        let endpoint_id_str = self.endpoint_id.to_string();
+        if let Some(ref logger) = *self.audit_logger {
+            logger.log_device_detach(
+                &endpoint_id_str,
+                handle,
+                Some(device_id),
+                AuditResult::Success,
+                Some(note),
+            );
+        }
     }
 
     /// Keep-alive task: sends periodic pings
@@ -1201,10 +1860,20 @@ impl ClientConnection {
 
     /// Cleanup when connection closes
     async fn cleanup(&mut self) {
+        self.interrupt_streams.stop_all().await;
+        self.metrics.client_disconnected(&self.endpoint_id.to_string());
+        self.qos.unregister_client(&self.endpoint_id.to_string()).await;
+
         // Cancel all pending transfers first
-        let handles: Vec<DeviceHandle> = self.attached_devices.keys().copied().collect();
+        let handles: Vec<DeviceHandle> = self
+            .attached_devices
+            .read()
+            .await
+            .keys()
+            .copied()
+            .collect();
         if !handles.is_empty() {
-            let cancelled = self.cancel_pending_transfers(&handles).await;
+            let cancelled = self.transfers.cancel_pending_transfers(&handles).await;
             if cancelled > 0 {
                 info!(
                     "Cancelled {} pending transfers during cleanup for {}",
@@ -1213,18 +1882,18 @@ impl ClientConnection {
             }
         }
 
-        if self.attached_devices.is_empty() {
+        if handles.is_empty() {
             return;
         }
 
         info!(
             "Cleaning up {} attached devices for {}",
-            self.attached_devices.len(),
+            handles.len(),
             self.endpoint_id
         );
 
         // Detach all devices
-        for handle in self.attached_devices.keys().copied().collect::<Vec<_>>() {
+        for handle in handles {
             let (tx, rx) = tokio::sync::oneshot::channel();
             if self
                 .usb_bridge
@@ -1239,7 +1908,7 @@ impl ClientConnection {
             }
         }
 
-        self.attached_devices.clear();
+        self.attached_devices.write().await.clear();
     }
 }
 
@@ -0,0 +1,90 @@
+//! Prometheus metrics page of the server
+//!
+//! Renders the shared ServerMetrics registry together with a few server-wide
+//! gauges (connected clients, pending approvals, active sessions) for the
+//! `/metrics` endpoint configured by `server.metrics_addr`.
+
+use common::{MetricType, MetricsEncoder};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::{IrohServer, SharedApprovalQueue, SharedClientRegistry, SharedServerMetrics};
+use crate::policy::PolicyEngine;
+
+/// Server state a scrape reads
+#[derive(Clone)]
+pub struct MetricsPage {
+    started_at: Instant,
+    metrics: SharedServerMetrics,
+    clients: SharedClientRegistry,
+    approvals: SharedApprovalQueue,
+    policy_engine: Arc<PolicyEngine>,
+}
+
+impl MetricsPage {
+    /// Collect the shared state of `server`
+    pub fn new(server: &IrohServer) -> Self {
+        Self {
+            started_at: Instant::now(),
+            metrics: server.metrics(),
+            clients: server.clients(),
+            approvals: server.approvals(),
+            policy_engine: server.policy_engine(),
+        }
+    }
+
+    /// Render the page in the text exposition format
+    pub async fn render(&self) -> String {
+        let mut encoder = MetricsEncoder::new();
+
+        encoder.family(
+            "p2p_usb_server_info",
+            MetricType::Gauge,
+            "Server version (always 1)",
+        );
+        encoder.sample(
+            "p2p_usb_server_info",
+            &[("version", env!("CARGO_PKG_VERSION"))],
+            1.0,
+        );
+        encoder.family(
+            "p2p_usb_server_uptime_seconds",
+            MetricType::Gauge,
+            "Seconds since the server started",
+        );
+        encoder.sample(
+            "p2p_usb_server_uptime_seconds",
+            &[],
+            self.started_at.elapsed().as_secs_f64(),
+        );
+        encoder.family(
+            "p2p_usb_connected_clients",
+            MetricType::Gauge,
+            "Clients currently connected",
+        );
+        encoder.sample("p2p_usb_connected_clients", &[], self.clients.len() as f64);
+        encoder.family(
+            "p2p_usb_pending_approvals",
+            MetricType::Gauge,
+            "Unknown clients waiting for operator approval",
+        );
+        encoder.sample(
+            "p2p_usb_pending_approvals",
+            &[],
+            self.approvals.pending_count() as f64,
+        );
+        encoder.family(
+            "p2p_usb_active_sessions",
+            MetricType::Gauge,
+            "Device sessions tracked by the policy engine",
+        );
+        encoder.sample(
+            "p2p_usb_active_sessions",
+            &[],
+            self.policy_engine.active_session_count().await as f64,
+        );
+
+        self.metrics.encode(&mut encoder);
+        encoder.finish()
+    }
+}
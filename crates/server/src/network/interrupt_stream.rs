@@ -0,0 +1,559 @@
+//! Proactive interrupt streaming
+//!
+//! Serves `StartInterruptStreamRequest` / `StopInterruptStreamRequest` and the
+//! `InterruptAck` / `InterruptNack` flow-control messages. Each streamed
+//! endpoint gets an `InterruptPoller` that keeps one interrupt IN transfer
+//! queued on the USB worker and records every report in its `EndpointBuffer`.
+//! Reports are pushed to the client as `InterruptData` frames on one
+//! long-lived unidirectional stream per connection, so HID reports reach the
+//! client in order and without a request round trip per poll.
+//!
+//! # Flow
+//!
+//! ```text
+//! client                                   server
+//!   │── StartInterruptStreamRequest ──────────>│ start InterruptPoller
+//!   │<──────── StartInterruptStreamResponse ───│
+//!   │<──────────────── InterruptData(seq=0) ───│ (uni stream, buffered)
+//!   │<──────────────── InterruptData(seq=1) ───│
+//!   │── InterruptNack([0]) ───────────────────>│ resend from buffer history
+//!   │<──────────────── InterruptData(seq=0) ───│
+//!   │── InterruptAck(last_seq=1) ─────────────>│ trim buffer up to seq 1
+//! ```
+//!
+//! Reports stay in the endpoint buffer until acknowledged. The queue feeding
+//! the push stream is bounded: when the connection cannot keep up, new reports
+//! are dropped from the queue (but kept in the buffer), the client sees the
+//! sequence gap and NACKs them. If the client falls further behind, the buffer
+//! drops the oldest reports and they can no longer be retransmitted.
+
+use anyhow::{Context, Result};
+use common::{EndpointInfo, UsbBridge, UsbCommand};
+use iroh::endpoint::{Connection, SendStream};
+use protocol::{
+    CURRENT_VERSION, DeviceHandle, DeviceId, InterruptStreamInfo, InterruptStreamStats, Message,
+    MessagePayload, RequestId, TransferResult, TransferType, UsbError, UsbRequest, encode_framed,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::oneshot;
+use tracing::{debug, info, trace, warn};
+
+use crate::network::transfer_channel::AttachedDevicesMap;
+use crate::usb::interrupt_buffer::{InterruptBufferManager, InterruptReport, MAX_BUFFERED_REPORTS};
+
+/// Upper bound for the client's `buffer_hint`
+const MAX_STREAM_BUFFER_REPORTS: usize = 1024;
+
+/// Reports queued for the push stream before new ones are dropped
+const REPORT_QUEUE_CAPACITY: usize = 256;
+
+/// Counters updated by the push task for one stream
+#[derive(Default)]
+struct StreamCounters {
+    /// Reports written to the client (including retransmissions)
+    sent: AtomicU64,
+    /// Highest sequence number written
+    last_sequence: AtomicU64,
+    /// Sum of capture-to-send latencies in microseconds
+    latency_total_us: AtomicU64,
+    /// Reports dropped because the push queue was full
+    queue_dropped: AtomicU64,
+}
+
+/// A report waiting to be pushed to the client
+struct QueuedReport {
+    handle: DeviceHandle,
+    report: InterruptReport,
+    counters: Arc<StreamCounters>,
+}
+
+/// State of one streamed endpoint
+struct StreamState {
+    device_id: DeviceId,
+    info: InterruptStreamInfo,
+    started: Instant,
+    counters: Arc<StreamCounters>,
+    /// Reports trimmed from the buffer by acknowledgements
+    acked: u64,
+}
+
+/// Interrupt streams for one client connection
+pub(crate) struct InterruptStreams {
+    /// Connection used for the push streams
+    connection: Connection,
+    /// Bridge to USB subsystem
+    usb_bridge: UsbBridge,
+    /// Devices attached by this client
+    attached_devices: AttachedDevicesMap,
+    /// Buffers and pollers for the streamed endpoints
+    buffers: Arc<InterruptBufferManager>,
+    /// Active streams keyed by (handle, endpoint)
+    streams: HashMap<(DeviceHandle, u8), StreamState>,
+    /// Queue feeding the push task (started with the first stream)
+    report_tx: Option<mpsc::Sender<QueuedReport>>,
+}
+
+impl InterruptStreams {
+    /// Create an empty stream set for a connection
+    pub(crate) fn new(
+        connection: Connection,
+        usb_bridge: UsbBridge,
+        attached_devices: AttachedDevicesMap,
+    ) -> Self {
+        Self {
+            connection,
+            usb_bridge,
+            attached_devices,
+            buffers: Arc::new(InterruptBufferManager::new()),
+            streams: HashMap::new(),
+            report_tx: None,
+        }
+    }
+
+    /// Start streaming an interrupt IN endpoint
+    ///
+    /// Starting an endpoint that is already streaming returns its existing
+    /// stream info.
+    pub(crate) async fn start(
+        &mut self,
+        handle: DeviceHandle,
+        endpoint: u8,
+        buffer_hint: u32,
+        push_supported: bool,
+    ) -> Result<InterruptStreamInfo, String> {
+        if !push_supported {
+            return Err("Push notifications not negotiated".to_string());
+        }
+        if endpoint & 0x80 == 0 {
+            return Err(format!("Endpoint {:#x} is not an IN endpoint", endpoint));
+        }
+
+        let device_id = self
+            .attached_devices
+            .read()
+            .await
+            .get(&handle)
+            .copied()
+            .ok_or_else(|| "Device not attached".to_string())?;
+
+        if let Some(state) = self.streams.get(&(handle, endpoint)) {
+            return Ok(state.info.clone());
+        }
+
+        let endpoint_info = self
+            .endpoint_info(handle, endpoint)
+            .await
+            .map_err(|e| format!("Endpoint {:#x} unavailable: {:?}", endpoint, e))?;
+
+        let capacity = match buffer_hint {
+            0 => MAX_BUFFERED_REPORTS,
+            hint => (hint as usize).min(MAX_STREAM_BUFFER_REPORTS),
+        };
+        self.buffers.register_device(device_id);
+        self.buffers.register_endpoint_with_capacity(
+            device_id,
+            endpoint,
+            endpoint_info.max_packet_size as usize,
+            capacity,
+        );
+        let Some(buffer) = self.buffers.buffer(device_id, endpoint) else {
+            return Err("Failed to allocate interrupt buffer".to_string());
+        };
+
+        // Read before polling starts so the client sees the first report
+        let start_sequence = buffer.next_seq();
+
+        let counters = Arc::new(StreamCounters::default());
+        let report_tx = self.report_sender();
+        let callback_counters = counters.clone();
+        let read_fn = interrupt_reader(self.usb_bridge.clone(), handle, endpoint);
+        self.buffers.with_device(device_id, |manager| {
+            manager.set_callback(
+                endpoint,
+                Box::new(move |_device_id, report| {
+                    enqueue_report(
+                        &report_tx,
+                        QueuedReport {
+                            handle,
+                            report,
+                            counters: callback_counters.clone(),
+                        },
+                    );
+                }),
+            );
+            manager.start_polling(endpoint, read_fn);
+        });
+
+        let info = InterruptStreamInfo {
+            endpoint,
+            buffer_size: buffer.capacity() as u32,
+            max_report_size: endpoint_info.max_packet_size,
+            poll_interval_ms: endpoint_info.interval_ms,
+            start_sequence,
+        };
+        info!(
+            "Interrupt stream started: handle={:?}, ep={:#x}, max_report={}, interval={}ms",
+            handle, endpoint, info.max_report_size, info.poll_interval_ms
+        );
+
+        self.streams.insert(
+            (handle, endpoint),
+            StreamState {
+                device_id,
+                info: info.clone(),
+                started: Instant::now(),
+                counters,
+                acked: 0,
+            },
+        );
+        Ok(info)
+    }
+
+    /// Stop streaming an endpoint and return its statistics
+    pub(crate) async fn stop(
+        &mut self,
+        handle: DeviceHandle,
+        endpoint: u8,
+    ) -> Option<InterruptStreamStats> {
+        let state = self.streams.remove(&(handle, endpoint))?;
+
+        let buffers = self.buffers.clone();
+        let device_id = state.device_id;
+        // Stopping joins the poller thread, which may be waiting on a transfer
+        let _ = tokio::task::spawn_blocking(move || {
+            buffers.with_device(device_id, |manager| manager.stop_polling(endpoint));
+        })
+        .await;
+
+        let (total_reports, reports_dropped) = self
+            .buffers
+            .buffer(device_id, endpoint)
+            .map(|buffer| buffer.stats())
+            .unwrap_or_default();
+        let reports_dropped =
+            reports_dropped + state.counters.queue_dropped.load(Ordering::Relaxed);
+        let sent = state.counters.sent.load(Ordering::Relaxed);
+        let latency_total_us = state.counters.latency_total_us.load(Ordering::Relaxed);
+
+        info!(
+            "Interrupt stream stopped: handle={:?}, ep={:#x}, sent={}",
+            handle, endpoint, sent
+        );
+
+        Some(InterruptStreamStats {
+            total_reports,
+            reports_sent: sent,
+            reports_dropped,
+            reports_acked: state.acked,
+            last_sequence: state.counters.last_sequence.load(Ordering::Relaxed),
+            avg_latency_us: latency_total_us.checked_div(sent).unwrap_or(0),
+            duration_ms: state.started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Stop every stream of a device handle (detach)
+    pub(crate) async fn stop_handle(&mut self, handle: DeviceHandle) {
+        let endpoints: Vec<u8> = self
+            .streams
+            .keys()
+            .filter(|(h, _)| *h == handle)
+            .map(|(_, endpoint)| *endpoint)
+            .collect();
+        for endpoint in endpoints {
+            self.stop(handle, endpoint).await;
+        }
+    }
+
+    /// Stop every stream of a device (unplug)
+    pub(crate) async fn stop_device(&mut self, device_id: DeviceId) {
+        let keys: Vec<(DeviceHandle, u8)> = self
+            .streams
+            .iter()
+            .filter(|(_, state)| state.device_id == device_id)
+            .map(|(key, _)| *key)
+            .collect();
+        for (handle, endpoint) in keys {
+            self.stop(handle, endpoint).await;
+        }
+
+        let buffers = self.buffers.clone();
+        let _ = tokio::task::spawn_blocking(move || buffers.unregister_device(device_id)).await;
+    }
+
+    /// Stop all streams (connection closing)
+    pub(crate) async fn stop_all(&mut self) {
+        let device_ids: Vec<DeviceId> = self.streams.values().map(|s| s.device_id).collect();
+        for device_id in device_ids {
+            self.stop_device(device_id).await;
+        }
+        self.report_tx = None;
+    }
+
+    /// Trim acknowledged reports from an endpoint's history
+    pub(crate) fn acknowledge(&mut self, handle: DeviceHandle, endpoint: u8, last_seq: u64) {
+        let Some(state) = self.streams.get_mut(&(handle, endpoint)) else {
+            debug!(
+                "InterruptAck for inactive stream: handle={:?}, ep={:#x}",
+                handle, endpoint
+            );
+            return;
+        };
+
+        let trimmed = self
+            .buffers
+            .acknowledge(state.device_id, endpoint, last_seq);
+        state.acked += trimmed as u64;
+        trace!(
+            "InterruptAck handle={:?} ep={:#x} last_seq={} trimmed={}",
+            handle, endpoint, last_seq, trimmed
+        );
+    }
+
+    /// Resend reports the client reported missing
+    pub(crate) fn retransmit(&mut self, handle: DeviceHandle, endpoint: u8, missing: &[u64]) {
+        let Some(state) = self.streams.get(&(handle, endpoint)) else {
+            debug!(
+                "InterruptNack for inactive stream: handle={:?}, ep={:#x}",
+                handle, endpoint
+            );
+            return;
+        };
+
+        let reports = self
+            .buffers
+            .buffer(state.device_id, endpoint)
+            .map(|buffer| buffer.retransmit(missing))
+            .unwrap_or_default();
+        if reports.len() < missing.len() {
+            warn!(
+                "Cannot retransmit {} of {} interrupt reports on ep {:#x} (no longer buffered)",
+                missing.len() - reports.len(),
+                missing.len(),
+                endpoint
+            );
+        }
+
+        let counters = state.counters.clone();
+        let report_tx = self.report_sender();
+        for report in reports {
+            enqueue_report(
+                &report_tx,
+                QueuedReport {
+                    handle,
+                    report,
+                    counters: counters.clone(),
+                },
+            );
+        }
+    }
+
+    /// Look up the endpoint's packet size and interval on the USB worker
+    async fn endpoint_info(
+        &self,
+        handle: DeviceHandle,
+        endpoint: u8,
+    ) -> Result<EndpointInfo, UsbError> {
+        let (tx, rx) = oneshot::channel();
+        self.usb_bridge
+            .send_command(UsbCommand::GetEndpointInfo {
+                handle,
+                endpoint,
+                response: tx,
+            })
+            .await
+            .map_err(|_| UsbError::NoDevice)?;
+        rx.await.map_err(|_| UsbError::NoDevice)?
+    }
+
+    /// Sender for the push task, starting the task if needed
+    fn report_sender(&mut self) -> mpsc::Sender<QueuedReport> {
+        if let Some(tx) = &self.report_tx
+            && !tx.is_closed()
+        {
+            return tx.clone();
+        }
+
+        let (tx, rx) = mpsc::channel(REPORT_QUEUE_CAPACITY);
+        tokio::spawn(push_reports(self.connection.clone(), rx));
+        self.report_tx = Some(tx.clone());
+        tx
+    }
+}
+
+/// Build the poller's read function
+///
+/// Each call submits one interrupt IN transfer through the USB worker and
+/// blocks the poller thread until it completes.
+fn interrupt_reader(
+    usb_bridge: UsbBridge,
+    handle: DeviceHandle,
+    endpoint: u8,
+) -> impl Fn(&mut [u8], Duration) -> Result<usize, rusb::Error> + Send + 'static {
+    let next_id = AtomicU64::new(0);
+    move |buf, timeout| {
+        let (tx, rx) = oneshot::channel();
+        let request = UsbRequest {
+            id: RequestId(next_id.fetch_add(1, Ordering::Relaxed)),
+            handle,
+            transfer: TransferType::Interrupt {
+                endpoint,
+                data: vec![0u8; buf.len()],
+                timeout_ms: timeout.as_millis() as u32,
+            },
+        };
+        usb_bridge
+            .send_command_blocking(UsbCommand::SubmitTransfer {
+                handle,
+                request,
+                response: tx,
+            })
+            .map_err(|_| rusb::Error::NoDevice)?;
+
+        let response = rx.blocking_recv().map_err(|_| rusb::Error::NoDevice)?;
+        match response.result {
+            TransferResult::Success { data, .. } => {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                Ok(len)
+            }
+            // A handle the worker no longer knows means the device is gone
+            TransferResult::Error {
+                error: UsbError::NoDevice | UsbError::NotFound,
+            } => Err(rusb::Error::NoDevice),
+            TransferResult::Error {
+                error: UsbError::Timeout,
+            } => Err(rusb::Error::Timeout),
+            TransferResult::Error { .. } | TransferResult::IsochronousSuccess { .. } => {
+                Err(rusb::Error::Io)
+            }
+        }
+    }
+}
+
+/// Queue a report for the push task, dropping it if the queue is full
+///
+/// Called from poller threads, which must never block on the network. A
+/// dropped report stays in the endpoint buffer, so the client can NACK it.
+fn enqueue_report(report_tx: &mpsc::Sender<QueuedReport>, queued: QueuedReport) {
+    match report_tx.try_send(queued) {
+        Ok(()) => {}
+        Err(TrySendError::Full(queued)) => {
+            queued
+                .counters
+                .queue_dropped
+                .fetch_add(1, Ordering::Relaxed);
+            debug!(
+                "Interrupt push queue full, dropping report seq={} on ep {:#x}",
+                queued.report.seq, queued.report.endpoint
+            );
+        }
+        Err(TrySendError::Closed(_)) => {}
+    }
+}
+
+/// Push queued reports to the client until every sender is dropped
+///
+/// All reports share one unidirectional stream, opened with the first report
+/// and reopened after a write error.
+async fn push_reports(connection: Connection, mut rx: mpsc::Receiver<QueuedReport>) {
+    let mut stream: Option<SendStream> = None;
+    while let Some(QueuedReport {
+        handle,
+        report,
+        counters,
+    }) = rx.recv().await
+    {
+        let latency_us = report.age_us();
+        let sequence = report.seq;
+        let payload = MessagePayload::InterruptData {
+            handle,
+            endpoint: report.endpoint,
+            sequence,
+            data: report.data,
+            timestamp_us: report.timestamp_us,
+            checksum: report.checksum,
+        };
+
+        if let Err(e) = push_message(&connection, &mut stream, payload).await {
+            if connection.close_reason().is_some() {
+                debug!("Connection closed, stopping interrupt push: {:#}", e);
+                break;
+            }
+            warn!("Failed to push interrupt report seq={}: {:#}", sequence, e);
+            stream = None;
+            continue;
+        }
+
+        counters.sent.fetch_add(1, Ordering::Relaxed);
+        counters
+            .last_sequence
+            .fetch_max(sequence, Ordering::Relaxed);
+        counters
+            .latency_total_us
+            .fetch_add(latency_us, Ordering::Relaxed);
+    }
+
+    if let Some(mut send) = stream {
+        let _ = send.finish();
+    }
+}
+
+/// Write one message to the push stream, opening it if needed
+async fn push_message(
+    connection: &Connection,
+    stream: &mut Option<SendStream>,
+    payload: MessagePayload,
+) -> Result<()> {
+    let send = match stream {
+        Some(send) => send,
+        None => stream.insert(
+            connection
+                .open_uni()
+                .await
+                .context("Failed to open unidirectional stream")?,
+        ),
+    };
+    let message = Message {
+        version: CURRENT_VERSION,
+        payload,
+    };
+    let framed = encode_framed(&message)?;
+    protocol::write_framed_async(send, &framed).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(seq: u64, counters: &Arc<StreamCounters>) -> QueuedReport {
+        QueuedReport {
+            handle: DeviceHandle(1),
+            report: InterruptReport::new(seq, 0x81, vec![0; 8]),
+            counters: counters.clone(),
+        }
+    }
+
+    #[test]
+    fn test_full_queue_drops_reports() {
+        let (tx, mut rx) = mpsc::channel(2);
+        let counters = Arc::new(StreamCounters::default());
+
+        for seq in 0..5 {
+            enqueue_report(&tx, queued(seq, &counters));
+        }
+
+        assert_eq!(counters.queue_dropped.load(Ordering::Relaxed), 3);
+        assert_eq!(rx.try_recv().unwrap().report.seq, 0);
+        assert_eq!(rx.try_recv().unwrap().report.seq, 1);
+        assert!(rx.try_recv().is_err());
+
+        // Space frees up as the push task drains the queue
+        enqueue_report(&tx, queued(5, &counters));
+        assert_eq!(rx.try_recv().unwrap().report.seq, 5);
+    }
+}
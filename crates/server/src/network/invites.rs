@@ -0,0 +1,303 @@
+//! Single-use pairing invites
+//!
+//! An invite is a short-lived random token the operator hands out inside a
+//! `p2p-usb://connect/<endpoint>?invite=<token>` URL. A client that is not
+//! approved yet presents it during the capability exchange; a valid token
+//! enrolls the client without the operator typing in its EndpointId and is
+//! burned on first use.
+//!
+//! Invites live in their own file (see `security.invites_path`) rather than
+//! in memory, so `p2p-usb-server --invite` can mint one while the server is
+//! running. The file holds secrets and is written with owner-only
+//! permissions.
+
+use anyhow::{Context, Result};
+use common::DeviceFilter;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::audit::SharedAuditLogger;
+
+/// How long an invite stays valid unless told otherwise
+pub const DEFAULT_INVITE_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Random bytes in an invite token
+const TOKEN_BYTES: usize = 16;
+
+/// Random bytes in an invite's public identifier
+const ID_BYTES: usize = 4;
+
+/// Shared handle to the invite store
+pub type SharedInviteStore = Arc<InviteStore>;
+
+/// An unredeemed pairing invite
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invite {
+    /// Public identifier used in logs and the audit trail
+    pub id: String,
+    /// Secret the client presents
+    pub token: String,
+    /// Unix time (seconds) after which the invite is void
+    pub expires_at: u64,
+    /// Device filters the enrolled client is limited to (empty = all)
+    #[serde(default)]
+    pub devices: Vec<String>,
+}
+
+impl Invite {
+    /// Whether the invite has run out
+    pub fn is_expired(&self) -> bool {
+        unix_now() >= self.expires_at
+    }
+
+    /// Time left before the invite runs out
+    pub fn remaining(&self) -> Duration {
+        Duration::from_secs(self.expires_at.saturating_sub(unix_now()))
+    }
+}
+
+/// On-disk layout of the invite file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct InviteFile {
+    #[serde(default)]
+    invites: Vec<Invite>,
+}
+
+/// File-backed store of unredeemed invites
+pub struct InviteStore {
+    path: PathBuf,
+    /// Serializes read-modify-write cycles within this process
+    lock: Mutex<()>,
+    /// Audit logger for recording minted invites
+    audit_logger: SharedAuditLogger,
+}
+
+impl InviteStore {
+    /// Open the store at `path`; the file is created on the first mint
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+            audit_logger: Arc::new(None),
+        }
+    }
+
+    /// Record minted invites in the audit log
+    pub fn with_audit_logger(mut self, audit_logger: SharedAuditLogger) -> Self {
+        self.audit_logger = audit_logger;
+        self
+    }
+
+    /// Mint a new invite valid for `ttl`
+    ///
+    /// `devices` are filter expressions limiting what the enrolled client
+    /// may attach; an empty list grants the same access as a manual approval.
+    pub fn mint(&self, ttl: Duration, devices: Vec<String>) -> Result<Invite> {
+        for filter in &devices {
+            DeviceFilter::parse(filter)?;
+        }
+
+        let invite = Invite {
+            id: random_hex(ID_BYTES),
+            token: random_hex(TOKEN_BYTES),
+            expires_at: unix_now().saturating_add(ttl.as_secs()),
+            devices,
+        };
+
+        let _guard = self.lock.lock().unwrap();
+        let mut invites = self.load()?;
+        invites.retain(|invite| !invite.is_expired());
+        invites.push(invite.clone());
+        self.save(&invites)?;
+
+        info!(
+            "Minted invite {} valid for {}s",
+            invite.id,
+            invite.remaining().as_secs()
+        );
+        if let Some(ref logger) = *self.audit_logger {
+            logger.log_invite_created(&invite.id, ttl, &invite.devices);
+        }
+        Ok(invite)
+    }
+
+    /// Burn the invite holding `token`
+    ///
+    /// Returns `None` when no live invite matches: the token is unknown,
+    /// already used or expired. Expired invites are dropped along the way.
+    pub fn redeem(&self, token: &str) -> Result<Option<Invite>> {
+        let _guard = self.lock.lock().unwrap();
+        let mut invites = self.load()?;
+        let before = invites.len();
+        invites.retain(|invite| !invite.is_expired());
+
+        let redeemed = invites
+            .iter()
+            .position(|invite| tokens_match(&invite.token, token))
+            .map(|index| invites.remove(index));
+
+        if invites.len() != before {
+            self.save(&invites)?;
+        }
+        if let Some(ref invite) = redeemed {
+            debug!("Redeemed invite {}", invite.id);
+        }
+        Ok(redeemed)
+    }
+
+    /// Invites that can still be redeemed
+    pub fn pending(&self) -> Result<Vec<Invite>> {
+        let _guard = self.lock.lock().unwrap();
+        let mut invites = self.load()?;
+        invites.retain(|invite| !invite.is_expired());
+        Ok(invites)
+    }
+
+    /// Read the invite file; a missing file holds no invites
+    fn load(&self) -> Result<Vec<Invite>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read invites: {}", self.path.display()))?;
+        let file: InviteFile = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse invites: {}", self.path.display()))?;
+        Ok(file.invites)
+    }
+
+    /// Replace the invite file atomically
+    fn save(&self, invites: &[Invite]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create invite directory: {}", parent.display())
+            })?;
+        }
+
+        let file = InviteFile {
+            invites: invites.to_vec(),
+        };
+        let content = toml::to_string_pretty(&file).context("Failed to serialize invites")?;
+
+        let mut tmp_name = OsString::from(self.path.as_os_str());
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+        let mut tmp = create_private(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        tmp.write_all(content.as_bytes())
+            .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        tmp.sync_all()
+            .with_context(|| format!("Failed to sync {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to replace invites: {}", self.path.display()))?;
+
+        // Make the rename itself durable
+        if let Some(parent) = self.path.parent()
+            && let Err(e) = File::open(parent).and_then(|dir| dir.sync_all())
+        {
+            warn!(
+                "Failed to sync invite directory {}: {}",
+                parent.display(),
+                e
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Create a file only the owner can read (Unix only)
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Compare tokens without leaking how much of a guess was right
+fn tokens_match(expected: &str, presented: &str) -> bool {
+    let (expected, presented) = (expected.as_bytes(), presented.as_bytes());
+    expected.len() == presented.len()
+        && expected
+            .iter()
+            .zip(presented)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// `len` random bytes as lowercase hex
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    rand::rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invite_is_single_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = InviteStore::new(dir.path().join("invites.toml"));
+
+        let invite = store.mint(DEFAULT_INVITE_TTL, Vec::new()).unwrap();
+        assert_eq!(invite.token.len(), TOKEN_BYTES * 2);
+        assert_eq!(store.pending().unwrap(), vec![invite.clone()]);
+
+        assert_eq!(store.redeem(&invite.token).unwrap(), Some(invite.clone()));
+        assert_eq!(store.redeem(&invite.token).unwrap(), None);
+        assert!(store.pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_expired_invite_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = InviteStore::new(dir.path().join("invites.toml"));
+
+        let invite = store.mint(Duration::ZERO, Vec::new()).unwrap();
+        assert!(invite.is_expired());
+        assert_eq!(store.redeem(&invite.token).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invites_are_shared_through_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("invites.toml");
+        let minted = InviteStore::new(path.clone())
+            .mint(DEFAULT_INVITE_TTL, vec!["04f9:*".to_string()])
+            .unwrap();
+
+        let store = InviteStore::new(path);
+        assert_eq!(store.redeem("not-a-token").unwrap(), None);
+        let redeemed = store.redeem(&minted.token).unwrap().unwrap();
+        assert_eq!(redeemed.devices, vec!["04f9:*".to_string()]);
+    }
+
+    #[test]
+    fn test_mint_rejects_invalid_device_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("invites.toml");
+        let store = InviteStore::new(path.clone());
+
+        assert!(store.mint(DEFAULT_INVITE_TTL, vec!["vid:".to_string()]).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,598 @@
+//! Server-wide transfer metrics
+//!
+//! Collects per-client and per-device `TransferMetrics` as transfers complete,
+//! together with the `ProtocolMetrics` each client reports about its own view
+//! of the connection (`ClientMetricsUpdate`). The registry is shared by every
+//! `ClientConnection`, answers `GetMetricsRequest`, and backs the server TUI
+//! and the Prometheus endpoint. Transfers are also counted per transfer type,
+//! and the registry keeps rate-limiter throttles, policy denials and each
+//! client's QUIC connection for its health figures. When a QoS scheduler is
+//! attached, its per-priority queue depths are exposed alongside the transfer
+//! counters.
+
+use crate::policy::PolicyDenialReason;
+use crate::qos::{Priority, SharedQosManager};
+use common::{
+    MetricType, MetricsEncoder, MetricsSnapshot, TransferKind, TransferKindMetrics,
+    TransferMetrics, TransferOutcome,
+};
+use iroh::endpoint::Connection;
+use protocol::{ClientMetrics, DeviceId, DeviceMetrics, ProtocolMetrics, ServerMetricsSummary};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Shared handle to the server metrics registry
+pub type SharedServerMetrics = Arc<ServerMetrics>;
+
+/// Metrics tracked for one client
+#[derive(Debug, Default)]
+struct ClientEntry {
+    /// Transfers as measured by the server
+    metrics: Arc<TransferMetrics>,
+    /// Latest metrics reported by the client itself
+    reported: Option<ProtocolMetrics>,
+    /// QUIC connection while the client is connected
+    connection: Option<Connection>,
+}
+
+/// Registry of server, client and device transfer metrics
+#[derive(Debug)]
+pub struct ServerMetrics {
+    /// Server-wide totals
+    total: Arc<TransferMetrics>,
+    /// Per-client metrics keyed by EndpointId string
+    clients: RwLock<HashMap<String, ClientEntry>>,
+    /// Per-device metrics
+    devices: RwLock<HashMap<DeviceId, Arc<TransferMetrics>>>,
+    /// Server-wide metrics per transfer type
+    transfer_types: TransferKindMetrics,
+    /// Policy denials per client and reason
+    policy_denials: RwLock<BTreeMap<(String, &'static str), u64>>,
+    /// QoS scheduler whose queue depths are reported
+    qos: Option<SharedQosManager>,
+}
+
+impl Default for ServerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerMetrics {
+    /// Create an empty registry; server uptime starts now
+    pub fn new() -> Self {
+        let total = Arc::new(TransferMetrics::new());
+        total.mark_connected();
+        Self {
+            total,
+            clients: RwLock::new(HashMap::new()),
+            devices: RwLock::new(HashMap::new()),
+            transfer_types: TransferKindMetrics::new(),
+            policy_denials: RwLock::new(BTreeMap::new()),
+            qos: None,
+        }
+    }
+
+    /// Report queue depths of the given QoS scheduler
+    pub fn with_qos(mut self, qos: SharedQosManager) -> Self {
+        self.qos = Some(qos);
+        self
+    }
+
+    /// Create a shared registry
+    pub fn shared() -> SharedServerMetrics {
+        Arc::new(Self::new())
+    }
+
+    /// Metrics tracker for a client, created on first use
+    pub fn client(&self, client_id: &str) -> Arc<TransferMetrics> {
+        if let Some(entry) = self.clients.read().unwrap().get(client_id) {
+            return entry.metrics.clone();
+        }
+        self.clients
+            .write()
+            .unwrap()
+            .entry(client_id.to_string())
+            .or_default()
+            .metrics
+            .clone()
+    }
+
+    /// Metrics tracker for a device, created on first use
+    pub fn device(&self, device_id: DeviceId) -> Arc<TransferMetrics> {
+        if let Some(metrics) = self.devices.read().unwrap().get(&device_id) {
+            return metrics.clone();
+        }
+        self.devices
+            .write()
+            .unwrap()
+            .entry(device_id)
+            .or_default()
+            .clone()
+    }
+
+    /// Mark a client as connected (starts its uptime)
+    pub fn client_connected(&self, client_id: &str) {
+        self.client(client_id).mark_connected();
+    }
+
+    /// Report the health of a client's QUIC connection until it disconnects
+    pub fn watch_connection(&self, client_id: &str, connection: Connection) {
+        self.clients
+            .write()
+            .unwrap()
+            .entry(client_id.to_string())
+            .or_default()
+            .connection = Some(connection);
+    }
+
+    /// Mark a client as disconnected
+    ///
+    /// Its counters are kept so the TUI can still show them.
+    pub fn client_disconnected(&self, client_id: &str) {
+        if let Some(entry) = self.clients.write().unwrap().get_mut(client_id) {
+            entry.metrics.mark_disconnected();
+            entry.connection = None;
+        }
+    }
+
+    /// Record the start of a transfer for a client and device
+    pub fn transfer_started(&self, client_id: &str, device_id: DeviceId, kind: TransferKind) {
+        self.total.transfer_started();
+        self.client(client_id).transfer_started();
+        self.device(device_id).transfer_started();
+        self.transfer_types.get(kind).transfer_started();
+    }
+
+    /// Record the end of a transfer previously passed to `transfer_started`
+    ///
+    /// Byte counts are from the server's side: sent is data returned to the
+    /// client (IN), received data the client sent to the device (OUT).
+    pub fn transfer_finished(
+        &self,
+        client_id: &str,
+        device_id: DeviceId,
+        kind: TransferKind,
+        outcome: TransferOutcome,
+    ) {
+        let client = self.client(client_id);
+        let device = self.device(device_id);
+        for metrics in [
+            self.total.as_ref(),
+            client.as_ref(),
+            device.as_ref(),
+            self.transfer_types.get(kind),
+        ] {
+            metrics.transfer_finished(outcome);
+        }
+    }
+
+    /// Record a transfer the rate limiter held back for `wait`
+    pub fn transfer_throttled(&self, client_id: &str, device_id: DeviceId, wait: Duration) {
+        self.total.record_throttled(wait);
+        self.client(client_id).record_throttled(wait);
+        self.device(device_id).record_throttled(wait);
+    }
+
+    /// Record a request the policy engine denied
+    pub fn policy_denied(&self, client_id: &str, reason: &PolicyDenialReason) {
+        *self
+            .policy_denials
+            .write()
+            .unwrap()
+            .entry((client_id.to_string(), reason.kind()))
+            .or_default() += 1;
+    }
+
+    /// Store the metrics a client reported about its side of the connection
+    pub fn record_client_report(&self, client_id: &str, metrics: ProtocolMetrics) {
+        self.clients
+            .write()
+            .unwrap()
+            .entry(client_id.to_string())
+            .or_default()
+            .reported = Some(metrics);
+    }
+
+    /// Latest metrics reported by a client, if any
+    pub fn client_report(&self, client_id: &str) -> Option<ProtocolMetrics> {
+        self.clients
+            .read()
+            .unwrap()
+            .get(client_id)
+            .and_then(|entry| entry.reported.clone())
+    }
+
+    /// Server-wide totals snapshot
+    pub fn total_snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot::from_metrics(&self.total)
+    }
+
+    /// Snapshot for one client, if it has been seen
+    pub fn client_snapshot(&self, client_id: &str) -> Option<MetricsSnapshot> {
+        self.clients
+            .read()
+            .unwrap()
+            .get(client_id)
+            .map(|entry| MetricsSnapshot::from_metrics(&entry.metrics))
+    }
+
+    /// Snapshot for one device, if it has carried transfers
+    pub fn device_snapshot(&self, device_id: DeviceId) -> Option<MetricsSnapshot> {
+        self.devices
+            .read()
+            .unwrap()
+            .get(&device_id)
+            .map(|metrics| MetricsSnapshot::from_metrics(metrics))
+    }
+
+    /// Transfers waiting for QoS admission per priority, lowest first
+    ///
+    /// Empty when no QoS scheduler is attached.
+    pub fn queue_depths(&self) -> Vec<(Priority, usize)> {
+        let Some(qos) = &self.qos else {
+            return Vec::new();
+        };
+        let depths = qos.queue_depths();
+        Priority::ALL
+            .iter()
+            .map(|&priority| (priority, depths[priority as usize]))
+            .collect()
+    }
+
+    /// IDs of all clients with metrics, sorted
+    pub fn client_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.clients.read().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Build the summary sent in `GetMetricsResponse`
+    ///
+    /// Totals and per-device figures are server-wide. Per-client entries are
+    /// limited to `client_id` so one client cannot enumerate the others.
+    pub fn summary_for(&self, client_id: &str) -> ServerMetricsSummary {
+        let mut devices: Vec<DeviceMetrics> = self
+            .devices
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(device_id, metrics)| DeviceMetrics {
+                device_id: *device_id,
+                metrics: ProtocolMetrics::from(metrics.as_ref()),
+            })
+            .collect();
+        devices.sort_by_key(|d| d.device_id.0);
+
+        let clients = self
+            .clients
+            .read()
+            .unwrap()
+            .get(client_id)
+            .map(|entry| ClientMetrics {
+                client_id: client_id.to_string(),
+                metrics: ProtocolMetrics::from(entry.metrics.as_ref()),
+            })
+            .into_iter()
+            .collect();
+
+        ServerMetricsSummary {
+            total: ProtocolMetrics::from(self.total.as_ref()),
+            devices,
+            clients,
+        }
+    }
+
+    /// Write every metric of the registry to `encoder`
+    ///
+    /// Transfer families are written server-wide (`p2p_usb_*`), per client,
+    /// per device and per transfer type, followed by rate-limiter throttles,
+    /// policy denials, connection health and QoS queue depths.
+    pub fn encode(&self, encoder: &mut MetricsEncoder) {
+        let clients = self.clients.read().unwrap();
+        let mut client_ids: Vec<&String> = clients.keys().collect();
+        client_ids.sort();
+        let devices = self.devices.read().unwrap();
+        let mut device_ids: Vec<DeviceId> = devices.keys().copied().collect();
+        device_ids.sort_by_key(|device_id| device_id.0);
+
+        let client_series: Vec<(Vec<(&str, String)>, &TransferMetrics)> = client_ids
+            .iter()
+            .map(|id| {
+                (
+                    vec![("client", id.to_string())],
+                    clients[*id].metrics.as_ref(),
+                )
+            })
+            .collect();
+        let device_series: Vec<(Vec<(&str, String)>, &TransferMetrics)> = device_ids
+            .iter()
+            .map(|id| (vec![("device", id.0.to_string())], devices[id].as_ref()))
+            .collect();
+        let type_series: Vec<(Vec<(&str, String)>, &TransferMetrics)> = self
+            .transfer_types
+            .iter()
+            .map(|(kind, metrics)| (vec![("type", kind.as_str().to_string())], metrics))
+            .collect();
+
+        encoder.transfer_metrics("p2p_usb", &[(Vec::new(), self.total.as_ref())]);
+        encoder.transfer_metrics("p2p_usb_client", &client_series);
+        encoder.transfer_metrics("p2p_usb_device", &device_series);
+        encoder.transfer_metrics("p2p_usb_transfer_type", &type_series);
+
+        // Rate limiter
+        let mut throttle_series = vec![(Vec::new(), self.total.as_ref())];
+        throttle_series.extend(client_series.iter().cloned());
+        throttle_series.extend(device_series.iter().cloned());
+        encoder.family(
+            "p2p_usb_rate_limited_total",
+            MetricType::Counter,
+            "Transfers held back by the rate limiter (no labels: all transfers)",
+        );
+        for (labels, metrics) in &throttle_series {
+            let labels: Vec<(&str, &str)> = labels.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            encoder.sample(
+                "p2p_usb_rate_limited_total",
+                &labels,
+                metrics.throttled() as f64,
+            );
+        }
+        encoder.family(
+            "p2p_usb_rate_limit_wait_seconds_total",
+            MetricType::Counter,
+            "Time transfers waited for the rate limiter",
+        );
+        for (labels, metrics) in &throttle_series {
+            let labels: Vec<(&str, &str)> = labels.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            encoder.sample(
+                "p2p_usb_rate_limit_wait_seconds_total",
+                &labels,
+                metrics.throttle_wait().as_secs_f64(),
+            );
+        }
+
+        // Policy
+        encoder.family(
+            "p2p_usb_policy_denials_total",
+            MetricType::Counter,
+            "Attach and descriptor requests denied by policy",
+        );
+        for ((client, reason), count) in self.policy_denials.read().unwrap().iter() {
+            encoder.sample(
+                "p2p_usb_policy_denials_total",
+                &[("client", client), ("reason", reason)],
+                *count as f64,
+            );
+        }
+
+        // Connection health
+        encoder.family(
+            "p2p_usb_client_connected",
+            MetricType::Gauge,
+            "Whether the client is connected",
+        );
+        for id in &client_ids {
+            let connected = clients[*id].metrics.uptime().is_some();
+            encoder.sample(
+                "p2p_usb_client_connected",
+                &[("client", id)],
+                if connected { 1.0 } else { 0.0 },
+            );
+        }
+        encoder.family(
+            "p2p_usb_client_uptime_seconds",
+            MetricType::Gauge,
+            "Time since the client connected",
+        );
+        for id in &client_ids {
+            if let Some(uptime) = clients[*id].metrics.uptime() {
+                encoder.sample(
+                    "p2p_usb_client_uptime_seconds",
+                    &[("client", id)],
+                    uptime.as_secs_f64(),
+                );
+            }
+        }
+        let connections: Vec<(&String, &Connection)> = client_ids
+            .iter()
+            .filter_map(|id| clients[*id].connection.as_ref().map(|c| (*id, c)))
+            .collect();
+        encoder.family(
+            "p2p_usb_client_rtt_seconds",
+            MetricType::Gauge,
+            "Round-trip time of the client's QUIC connection",
+        );
+        for (id, connection) in &connections {
+            encoder.sample(
+                "p2p_usb_client_rtt_seconds",
+                &[("client", id)],
+                connection.rtt().as_secs_f64(),
+            );
+        }
+        encoder.family(
+            "p2p_usb_client_lost_packets_total",
+            MetricType::Counter,
+            "Packets lost on the client's current QUIC connection",
+        );
+        for (id, connection) in &connections {
+            encoder.sample(
+                "p2p_usb_client_lost_packets_total",
+                &[("client", id)],
+                connection.stats().path.lost_packets as f64,
+            );
+        }
+        encoder.family(
+            "p2p_usb_client_reported_loss_ratio",
+            MetricType::Gauge,
+            "Share of failed transfers as last reported by the client",
+        );
+        for id in &client_ids {
+            if let Some(reported) = &clients[*id].reported {
+                encoder.sample(
+                    "p2p_usb_client_reported_loss_ratio",
+                    &[("client", id)],
+                    reported.loss_rate,
+                );
+            }
+        }
+
+        // QoS
+        let depths = self.queue_depths();
+        if !depths.is_empty() {
+            encoder.family(
+                "p2p_usb_qos_queue_depth",
+                MetricType::Gauge,
+                "Transfers waiting for QoS admission",
+            );
+            for (priority, depth) in depths {
+                encoder.sample(
+                    "p2p_usb_qos_queue_depth",
+                    &[("priority", &format!("{:?}", priority).to_lowercase())],
+                    depth as f64,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfers_update_total_client_and_device() {
+        let metrics = ServerMetrics::new();
+        let device = DeviceId(7);
+
+        metrics.transfer_started("alice", device, TransferKind::Bulk);
+        assert_eq!(metrics.total_snapshot().active_transfers, 1);
+        metrics.transfer_finished(
+            "alice",
+            device,
+            TransferKind::Bulk,
+            TransferOutcome::Completed {
+                bytes_sent: 512,
+                bytes_received: 8,
+                latency: Duration::from_millis(2),
+            },
+        );
+        metrics.transfer_started("bob", device, TransferKind::Control);
+        metrics.transfer_finished(
+            "bob",
+            device,
+            TransferKind::Control,
+            TransferOutcome::Failed,
+        );
+
+        let total = metrics.total_snapshot();
+        assert_eq!(total.active_transfers, 0);
+        assert_eq!(total.transfers_completed, 1);
+        assert_eq!(total.transfers_failed, 1);
+
+        let alice = metrics.client_snapshot("alice").unwrap();
+        assert_eq!(alice.bytes_sent, 512);
+        assert_eq!(alice.bytes_received, 8);
+
+        let device_snapshot = metrics.device_snapshot(device).unwrap();
+        assert_eq!(device_snapshot.transfers_completed, 1);
+        assert_eq!(device_snapshot.transfers_failed, 1);
+        assert_eq!(metrics.client_ids(), vec!["alice", "bob"]);
+    }
+
+    #[test]
+    fn test_summary_only_includes_requesting_client() {
+        let metrics = ServerMetrics::new();
+        metrics.transfer_started("alice", DeviceId(1), TransferKind::Bulk);
+        metrics.transfer_finished(
+            "alice",
+            DeviceId(1),
+            TransferKind::Bulk,
+            TransferOutcome::Completed {
+                bytes_sent: 64,
+                bytes_received: 0,
+                latency: Duration::from_millis(1),
+            },
+        );
+        metrics.client_connected("bob");
+
+        let summary = metrics.summary_for("alice");
+        assert_eq!(summary.total.transfers_completed, 1);
+        assert_eq!(summary.devices.len(), 1);
+        assert_eq!(summary.clients.len(), 1);
+        assert_eq!(summary.clients[0].client_id, "alice");
+        assert_eq!(summary.clients[0].metrics.bytes_sent, 64);
+    }
+
+    #[test]
+    fn test_client_report_is_stored() {
+        let metrics = ServerMetrics::new();
+        assert!(metrics.client_report("alice").is_none());
+
+        let report = ProtocolMetrics {
+            loss_rate: 0.25,
+            ..Default::default()
+        };
+        metrics.record_client_report("alice", report);
+        let stored = metrics.client_report("alice").unwrap();
+        assert!((stored.loss_rate - 0.25).abs() < f64::EPSILON);
+        assert!(metrics.client_ids().contains(&"alice".to_string()));
+    }
+
+    #[test]
+    fn test_encode_exports_types_throttles_and_denials() {
+        let metrics = ServerMetrics::new();
+        metrics.client_connected("alice");
+        metrics.transfer_started("alice", DeviceId(2), TransferKind::Interrupt);
+        metrics.transfer_finished(
+            "alice",
+            DeviceId(2),
+            TransferKind::Interrupt,
+            TransferOutcome::Completed {
+                bytes_sent: 8,
+                bytes_received: 0,
+                latency: Duration::from_millis(1),
+            },
+        );
+        metrics.transfer_throttled("alice", DeviceId(2), Duration::from_millis(250));
+        metrics.policy_denied("bob", &PolicyDenialReason::ClientNotAllowed);
+        metrics.policy_denied("bob", &PolicyDenialReason::ClientNotAllowed);
+
+        let mut encoder = MetricsEncoder::new();
+        metrics.encode(&mut encoder);
+        let page = encoder.finish();
+
+        assert!(page.contains("p2p_usb_transfers_total{result=\"completed\"} 1\n"));
+        assert!(
+            page.contains(
+                "p2p_usb_client_transfers_total{client=\"alice\",result=\"completed\"} 1\n"
+            )
+        );
+        assert!(page.contains("p2p_usb_device_bytes_sent_total{device=\"2\"} 8\n"));
+        assert!(page.contains(
+            "p2p_usb_transfer_type_transfers_total{type=\"interrupt\",result=\"completed\"} 1\n"
+        ));
+        assert!(page.contains(
+            "p2p_usb_transfer_type_transfers_total{type=\"bulk\",result=\"completed\"} 0\n"
+        ));
+        assert!(page.contains("p2p_usb_rate_limited_total 1\n"));
+        assert!(page.contains("p2p_usb_rate_limited_total{device=\"2\"} 1\n"));
+        assert!(page.contains("p2p_usb_rate_limit_wait_seconds_total{client=\"alice\"} 0.25\n"));
+        assert!(page.contains(
+            "p2p_usb_policy_denials_total{client=\"bob\",reason=\"client_not_allowed\"} 2\n"
+        ));
+        assert!(page.contains("p2p_usb_client_connected{client=\"alice\"} 1\n"));
+    }
+
+    #[test]
+    fn test_queue_depths_follow_qos() {
+        assert!(ServerMetrics::new().queue_depths().is_empty());
+
+        let qos = Arc::new(crate::qos::QosManager::new(true, 1_000_000));
+        let metrics = ServerMetrics::new().with_qos(qos);
+        let depths = metrics.queue_depths();
+        assert_eq!(depths.len(), 4);
+        assert_eq!(depths[0], (Priority::Low, 0));
+        assert!(depths.iter().all(|(_, depth)| *depth == 0));
+    }
+}
@@ -15,16 +15,39 @@
 //! IrohServer
 //!   ├─> accept connections
 //!   ├─> validate allowlist
+//!   ├─> enroll unknown clients presenting a pairing invite (InviteStore)
+//!   ├─> park unknown clients in the ApprovalQueue until the operator decides
+//!   ├─> apply configuration reloads live (ConfigReloader)
+//!   ├─> export metrics to Prometheus scrapers (MetricsPage)
 //!   └─> spawn ClientConnection per client
 //!         ├─> handle QUIC streams (request/response)
+//!         ├─> serve persistent transfer channel (multiplexed transfers)
+//!         ├─> push interrupt streams (HID reports without polling round trips)
 //!         ├─> route to USB subsystem via UsbBridge
 //!         ├─> track device attachments
+//!         ├─> detach sessions a reloaded configuration forbids
+//!         ├─> register in ClientRegistry for the operator's control commands
+//!         ├─> record transfer metrics (shared ServerMetrics registry)
 //!         └─> cleanup on disconnect
 //! ```
 
+pub mod approval;
+pub mod clients;
 pub mod connection;
+pub mod exporter;
+pub mod interrupt_stream;
+pub mod invites;
+pub mod metrics;
 pub mod notification_aggregator;
+pub mod reload;
 pub mod server;
+pub mod transfer_channel;
 
 // Re-export public types
+pub use approval::{ApprovalDecision, PendingClient, SharedApprovalQueue};
+pub use clients::SharedClientRegistry;
+pub use exporter::MetricsPage;
+pub use invites::{DEFAULT_INVITE_TTL, Invite, InviteStore, SharedInviteStore};
+pub use metrics::{ServerMetrics, SharedServerMetrics};
+pub use reload::SharedConfigReloader;
 pub use server::IrohServer;
@@ -214,6 +214,10 @@ mod tests {
             protocol: 0,
             speed: DeviceSpeed::High,
             num_configurations: 1,
+            interfaces: Vec::new(),
+            stable_id: "1234:5678@1-1".to_string(),
+            port_numbers: vec![1],
+            parent_hub: None,
         }
     }
 
@@ -0,0 +1,380 @@
+//! Configuration reload
+//!
+//! Re-reads the server configuration on SIGHUP or at the operator's request
+//! and applies what can change without a restart: client names and groups,
+//! the client allow and deny lists, device policies and client scopes,
+//! data quotas, reservation limits, bandwidth limits and USB filters.
+//! Connections are told after each reload and force-detach the sessions
+//! the new rules forbid; every other session keeps running.
+//!
+//! Settings that are only read at startup (bind address, Iroh keys, audit
+//! and QoS settings, ...) are reported as needing a restart.
+
+use anyhow::{Context, Result, anyhow};
+use common::{UsbBridge, UsbCommand};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{Mutex, watch};
+use tracing::{debug, info, warn};
+
+use super::approval::SharedApprovalQueue;
+use super::server::IrohServer;
+use super::transfer_channel::RateLimiterSlot;
+use crate::audit::SharedAuditLogger;
+use crate::config::ServerConfig;
+use crate::directory::SharedClientDirectory;
+use crate::policy::PolicyEngine;
+use crate::service;
+
+/// Settings applied without a restart (a section name covers its keys)
+const LIVE_SETTINGS: &[&str] = &[
+    "clients",
+    "groups",
+    "security.approved_clients",
+    "security.denied_clients",
+    "security.require_approval",
+    "security.client_devices",
+    "device_policies",
+    "timezone",
+    "timezone_offset_hours",
+    "quotas.daily_data",
+    "quotas.monthly_data",
+    "quotas.clients",
+    "reservations.max_duration",
+    "reservations.max_advance",
+    "bandwidth",
+    "usb.filters",
+];
+
+/// Shared handle to the configuration reloader
+pub type SharedConfigReloader = Arc<ConfigReloader>;
+
+/// A setting that differs between two configurations
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettingChange {
+    /// Dotted name as in the configuration file, e.g. `security.approved_clients`
+    pub setting: String,
+    /// Previous value in TOML notation (None = unset)
+    pub old_value: Option<String>,
+    /// New value in TOML notation (None = unset)
+    pub new_value: Option<String>,
+}
+
+impl SettingChange {
+    /// Whether the change is applied without a restart
+    pub fn is_live(&self) -> bool {
+        LIVE_SETTINGS.iter().any(|live| {
+            self.setting == *live
+                || self
+                    .setting
+                    .strip_prefix(live)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+
+    /// Whether the change touches `setting` or a key below it
+    fn affects(&self, setting: &str) -> bool {
+        self.setting == setting
+            || self
+                .setting
+                .strip_prefix(setting)
+                .is_some_and(|rest| rest.starts_with('.'))
+    }
+}
+
+/// Re-reads the configuration file and applies the changes
+pub struct ConfigReloader {
+    /// File the configuration is re-read from (reload fails when unset)
+    config_path: OnceLock<PathBuf>,
+    /// Configuration currently in effect
+    current: Mutex<ServerConfig>,
+    /// Client names and groups
+    directory: SharedClientDirectory,
+    /// Allow and deny lists
+    approvals: SharedApprovalQueue,
+    /// Device policies and client scopes
+    policy_engine: Arc<PolicyEngine>,
+    /// Rate limiter shared by all connections
+    rate_limiter: RateLimiterSlot,
+    /// Bridge to the USB subsystem (filters)
+    usb_bridge: UsbBridge,
+    /// Audit logger for recording changes
+    audit_logger: SharedAuditLogger,
+    /// Bumped after each reload that changed something
+    generation: watch::Sender<u64>,
+}
+
+impl ConfigReloader {
+    /// Create a reloader around the services a reload updates
+    pub fn new(
+        config: ServerConfig,
+        directory: SharedClientDirectory,
+        approvals: SharedApprovalQueue,
+        policy_engine: Arc<PolicyEngine>,
+        rate_limiter: RateLimiterSlot,
+        usb_bridge: UsbBridge,
+        audit_logger: SharedAuditLogger,
+    ) -> Self {
+        Self {
+            config_path: OnceLock::new(),
+            current: Mutex::new(config),
+            directory,
+            approvals,
+            policy_engine,
+            rate_limiter,
+            usb_bridge,
+            audit_logger,
+            generation: watch::channel(0).0,
+        }
+    }
+
+    /// Reload from `path` (only the first call takes effect)
+    pub fn set_config_path(&self, path: PathBuf) {
+        let _ = self.config_path.set(path);
+    }
+
+    /// Receiver that changes after each reload
+    ///
+    /// Connections re-check their sessions when it does.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.generation.subscribe()
+    }
+
+    /// Configuration currently in effect
+    pub async fn current(&self) -> ServerConfig {
+        self.current.lock().await.clone()
+    }
+
+    /// Re-read the configuration file and apply what changed
+    ///
+    /// Returns every setting that changed, including those that only take
+    /// effect after a restart. An invalid file leaves the running
+    /// configuration untouched.
+    pub async fn reload(&self) -> Result<Vec<SettingChange>> {
+        let path = self
+            .config_path
+            .get()
+            .ok_or_else(|| anyhow!("No configuration file to reload"))?;
+
+        if let Err(e) = service::notify_reloading() {
+            debug!("Failed to notify systemd of reload: {:#}", e);
+        }
+        let result = self.apply(path).await;
+        if let Err(e) = service::notify_ready() {
+            debug!("Failed to notify systemd after reload: {:#}", e);
+        }
+
+        if let Err(ref e) = result {
+            warn!("Configuration reload failed: {:#}", e);
+        }
+        result
+    }
+
+    /// Load `path`, apply the live changes and record them
+    async fn apply(&self, path: &Path) -> Result<Vec<SettingChange>> {
+        let new = ServerConfig::load(Some(path.to_path_buf()))
+            .context("Failed to reload configuration")?;
+
+        let mut current = self.current.lock().await;
+        let changes = diff_configs(&current, &new)?;
+        if changes.is_empty() {
+            info!("Configuration reloaded, nothing changed");
+            return Ok(changes);
+        }
+
+        let changed = |setting: &str| changes.iter().any(|change| change.affects(setting));
+
+        // Names and groups first: the lists and policies below refer to them
+        let directory_changed = changed("clients") || changed("groups");
+        if directory_changed {
+            self.directory.replace(&new.clients, &new.groups)?;
+            info!(
+                "Client directory reloaded: {} named clients, {} groups",
+                new.clients.len(),
+                new.groups.len()
+            );
+        }
+
+        if directory_changed
+            || changed("security.approved_clients")
+            || changed("security.denied_clients")
+            || changed("security.require_approval")
+        {
+            let allowed =
+                IrohServer::parse_allowlist(&new.security.approved_clients, &self.directory)?;
+            let denied =
+                IrohServer::parse_allowlist(&new.security.denied_clients, &self.directory)?;
+            info!(
+                "Client lists reloaded: {} approved, {} denied, approval {}",
+                allowed.len(),
+                denied.len(),
+                if new.security.require_approval {
+                    "required"
+                } else {
+                    "not required"
+                }
+            );
+            self.approvals
+                .replace_lists(allowed, denied, new.security.require_approval)
+                .await;
+        }
+
+        if directory_changed
+            || changed("device_policies")
+            || changed("timezone")
+            || changed("timezone_offset_hours")
+            || changed("security.client_devices")
+            || changed("quotas")
+            || changed("reservations")
+        {
+            self.policy_engine
+                .reload(
+                    new.device_policies.clone(),
+                    new.timezone()?,
+                    &new.security.client_devices,
+                    &new.quotas,
+                    &new.reservations,
+                )
+                .await;
+        }
+
+        if changed("bandwidth") {
+            let limiter = IrohServer::create_rate_limiter(&new.bandwidth);
+            if limiter.is_none() {
+                info!("Rate limiter disabled");
+            }
+            *self.rate_limiter.write().unwrap() = limiter;
+        }
+
+        if changed("usb.filters") {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            self.usb_bridge
+                .send_command(UsbCommand::SetFilters {
+                    filters: new.usb.filters.clone(),
+                    response: tx,
+                })
+                .await
+                .context("Failed to send new USB filters")?;
+            let (removed, added) = rx.await.context("USB subsystem did not apply filters")?;
+            info!(
+                "USB filters reloaded: {} devices hidden, {} devices shared",
+                removed, added
+            );
+        }
+
+        for change in changes.iter().filter(|change| !change.is_live()) {
+            warn!(
+                "Setting {} changed; it takes effect after a restart",
+                change.setting
+            );
+        }
+        if let Some(ref logger) = *self.audit_logger {
+            for change in &changes {
+                logger.log_config_change(
+                    &change.setting,
+                    change.old_value.clone(),
+                    change.new_value.clone(),
+                );
+            }
+        }
+
+        *current = new;
+        drop(current);
+
+        // Connections re-check their sessions against the new rules
+        self.generation.send_modify(|generation| *generation += 1);
+        info!("Configuration reloaded: {} settings changed", changes.len());
+        Ok(changes)
+    }
+}
+
+/// Settings that differ between two configurations
+///
+/// Sections (`[security]`, `[usb]`, ...) are compared key by key; top-level
+/// values such as `device_policies` as a whole.
+pub fn diff_configs(old: &ServerConfig, new: &ServerConfig) -> Result<Vec<SettingChange>> {
+    let old = toml::Table::try_from(old).context("Failed to serialize configuration")?;
+    let new = toml::Table::try_from(new).context("Failed to serialize configuration")?;
+
+    let mut changes = Vec::new();
+    for key in union_keys(&old, &new) {
+        match (old.get(key), new.get(key)) {
+            (Some(toml::Value::Table(old_section)), Some(toml::Value::Table(new_section))) => {
+                for name in union_keys(old_section, new_section) {
+                    push_change(
+                        &mut changes,
+                        format!("{}.{}", key, name),
+                        old_section.get(name),
+                        new_section.get(name),
+                    );
+                }
+            }
+            (old_value, new_value) => {
+                push_change(&mut changes, key.clone(), old_value, new_value);
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Keys of both tables, each once, in order
+fn union_keys<'a>(a: &'a toml::Table, b: &'a toml::Table) -> Vec<&'a String> {
+    let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+fn push_change(
+    changes: &mut Vec<SettingChange>,
+    setting: String,
+    old_value: Option<&toml::Value>,
+    new_value: Option<&toml::Value>,
+) {
+    if old_value != new_value {
+        changes.push(SettingChange {
+            setting,
+            old_value: old_value.map(toml::Value::to_string),
+            new_value: new_value.map(toml::Value::to_string),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_reports_changed_settings() {
+        let old = ServerConfig::default();
+        let mut new = old.clone();
+        new.security.approved_clients.push("client".to_string());
+        new.bandwidth.per_client_limit = Some("10M".to_string());
+        new.server.log_level = "debug".to_string();
+        new.timezone_offset_hours = 2;
+        new.timezone = Some("Europe/Helsinki".to_string());
+
+        let changes = diff_configs(&old, &new).unwrap();
+        let settings: Vec<&str> = changes.iter().map(|c| c.setting.as_str()).collect();
+        assert_eq!(
+            settings,
+            vec![
+                "bandwidth.per_client_limit",
+                "security.approved_clients",
+                "server.log_level",
+                "timezone",
+                "timezone_offset_hours",
+            ]
+        );
+
+        let approved = &changes[1];
+        assert_eq!(approved.old_value.as_deref(), Some("[]"));
+        assert_eq!(approved.new_value.as_deref(), Some("[\"client\"]"));
+        assert_eq!(changes[0].old_value, None);
+
+        let live: Vec<bool> = changes.iter().map(SettingChange::is_live).collect();
+        assert_eq!(live, vec![true, true, false, true, true]);
+        assert!(diff_configs(&new, &new).unwrap().is_empty());
+    }
+}
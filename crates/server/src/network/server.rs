@@ -4,20 +4,50 @@
 //! and spawns per-client connection handlers.
 
 use anyhow::{Context, Result, anyhow};
+use common::iroh_ext::bind_endpoint;
 use common::{
     ALPN_PROTOCOL, BandwidthLimit, RateLimiter, SharedRateLimiter, UsbBridge,
     load_or_generate_secret_key,
 };
+use iroh::endpoint::{Connection, SendStream, VarInt};
 use iroh::{Endpoint, PublicKey as EndpointId};
+use protocol::{CURRENT_VERSION, Message, MessagePayload, decode_framed, encode_framed};
 use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{RwLock, mpsc};
 use tracing::{debug, error, info, warn};
 
-use super::connection::ClientConnection;
+use super::approval::{ApprovalDecision, ApprovalQueue, SharedApprovalQueue};
+use super::clients::{ClientRegistry, SharedClientRegistry};
+use super::connection::{ClientConnection, ConnectionServices};
+use super::invites::{InviteStore, SharedInviteStore};
+use super::metrics::{ServerMetrics, SharedServerMetrics};
+use super::reload::{ConfigReloader, SharedConfigReloader};
+use super::transfer_channel::RateLimiterSlot;
 use crate::audit::SharedAuditLogger;
-use crate::config::ServerConfig;
+use crate::config::{BandwidthSettings, ServerConfig};
+use crate::directory::{ClientDirectory, SharedClientDirectory};
 use crate::policy::{PolicyEngine, SessionExpiredEvent};
+use crate::qos::{QosManager, SharedQosManager};
+use crate::quota::QuotaLedger;
+use crate::reservation::ReservationBook;
+
+/// How long a parked client waits for the operator before being disconnected
+const APPROVAL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long to wait for a parked client's capabilities
+const CAPABILITY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Grace period for a final approval frame to reach the client before closing
+const CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+/// QUIC close code for clients that were not admitted
+pub(super) const NOT_ADMITTED: u32 = 1;
+
+/// QUIC close code for clients the operator disconnected
+pub(super) const DISCONNECTED_BY_OPERATOR: u32 = 2;
 
 /// Iroh P2P server for USB device sharing
 ///
@@ -28,16 +58,28 @@ pub struct IrohServer {
     endpoint: Endpoint,
     /// Bridge to USB subsystem
     usb_bridge: UsbBridge,
-    /// Allowed client EndpointIds (empty = allow all)
+    /// Client names and groups from the configuration
+    directory: SharedClientDirectory,
+    /// Allowed client EndpointIds
     allowed_clients: Arc<RwLock<HashSet<EndpointId>>>,
-    /// Server configuration
-    config: ServerConfig,
+    /// Clients awaiting operator approval
+    approvals: SharedApprovalQueue,
+    /// Unredeemed pairing invites
+    invites: SharedInviteStore,
+    /// Applies configuration reloads
+    reloader: SharedConfigReloader,
     /// Audit logger
     audit_logger: SharedAuditLogger,
     /// Rate limiter for bandwidth control (optional, shared across all connections)
-    rate_limiter: Option<SharedRateLimiter>,
+    rate_limiter: RateLimiterSlot,
     /// Policy engine for time-based access control and passthrough policies
     policy_engine: Arc<PolicyEngine>,
+    /// Transfer metrics shared by all connections
+    metrics: SharedServerMetrics,
+    /// QoS scheduler shared by all connections
+    qos: SharedQosManager,
+    /// Running client connections
+    clients: SharedClientRegistry,
     /// Channel receiver for session expiration events (for future server-level handling)
     #[allow(dead_code)]
     session_expired_rx: mpsc::UnboundedReceiver<SessionExpiredEvent>,
@@ -65,18 +107,24 @@ impl IrohServer {
             .context("Failed to load or generate secret key")?;
 
         // Create Iroh endpoint with ALPN protocol identifier and persistent key
-        let endpoint = Endpoint::builder()
-            .secret_key(secret_key)
-            .alpns(vec![ALPN_PROTOCOL.to_vec()])
-            .bind()
-            .await
-            .context("Failed to create Iroh endpoint")?;
-
-        // Wait for endpoint to discover its addresses before accepting connections
-        let _ = endpoint.online().await;
-
-        // Parse allowed clients from config
-        let allowed_clients = Self::parse_allowlist(&config.security.approved_clients)?;
+        let endpoint = bind_endpoint(
+            secret_key,
+            ALPN_PROTOCOL.to_vec(),
+            config.iroh.relay_servers.as_deref(),
+        )
+        .await
+        .context("Failed to create Iroh endpoint")?;
+
+        // Resolve allowed and denied clients from config
+        let directory = Arc::new(
+            ClientDirectory::new(&config.clients, &config.groups)
+                .context("Invalid client names or groups")?,
+        );
+        if let Some(ref logger) = *audit_logger {
+            logger.set_client_directory(directory.clone());
+        }
+        let allowed_clients = Self::parse_allowlist(&config.security.approved_clients, &directory)?;
+        let denied_clients = Self::parse_allowlist(&config.security.denied_clients, &directory)?;
 
         let endpoint_id = endpoint.id();
         info!("Server EndpointId: {}", endpoint_id);
@@ -84,7 +132,7 @@ impl IrohServer {
 
         if config.security.require_approval {
             info!(
-                "Client allowlist enabled with {} entries",
+                "Client allowlist enabled with {} entries, unknown clients await approval",
                 allowed_clients.len()
             );
         } else {
@@ -92,39 +140,23 @@ impl IrohServer {
         }
 
         // Create rate limiter if bandwidth limiting is enabled
-        let rate_limiter = if config.bandwidth.enabled {
-            let global_limit = config.bandwidth.global_limit_bps().map(|bps| {
-                let burst = (bps as f64 * config.bandwidth.burst_multiplier) as u64;
-                BandwidthLimit::new(bps, Some(burst))
-            });
-
-            let per_client_limit = config.bandwidth.per_client_limit_bps().map(|bps| {
-                let burst = (bps as f64 * config.bandwidth.burst_multiplier) as u64;
-                BandwidthLimit::new(bps, Some(burst))
-            });
-
-            let per_device_limit = config.bandwidth.per_device_limit_bps().map(|bps| {
-                let burst = (bps as f64 * config.bandwidth.burst_multiplier) as u64;
-                BandwidthLimit::new(bps, Some(burst))
-            });
-
-            let limiter = RateLimiter::new(global_limit, per_client_limit, per_device_limit);
-            info!(
-                "Rate limiter enabled: global={:?}, per_client={:?}, per_device={:?}",
-                config.bandwidth.global_limit,
-                config.bandwidth.per_client_limit,
-                config.bandwidth.per_device_limit
-            );
-            Some(Arc::new(limiter))
-        } else {
-            None
-        };
+        let rate_limiter = Arc::new(std::sync::RwLock::new(Self::create_rate_limiter(
+            &config.bandwidth,
+        )));
 
         // Create policy engine for time-based access control
         let (session_expired_tx, session_expired_rx) = mpsc::unbounded_channel();
+        let quota_usage =
+            QuotaLedger::load(&config.quotas.usage_path()).context("Failed to load quota usage")?;
+        let reservations = ReservationBook::load(&config.reservations.path())
+            .context("Failed to load reservations")?;
         let policy_engine = Arc::new(
             PolicyEngine::new(config.device_policies.clone())
-                .with_timezone_offset(config.timezone_offset_hours)
+                .with_client_directory(directory.clone())
+                .with_client_scopes(&config.security.client_devices)
+                .with_timezone(config.timezone()?)
+                .with_quotas(&config.quotas, quota_usage)
+                .with_reservations(&config.reservations, Arc::new(reservations))
                 .with_expiration_channel(session_expired_tx),
         );
 
@@ -138,14 +170,49 @@ impl IrohServer {
             );
         }
 
+        let qos = Arc::new(QosManager::from_settings(&config.qos));
+        if qos.is_enabled() {
+            info!(
+                "QoS scheduling enabled: max_in_flight={}, client_quota={} Mbps",
+                config.qos.max_in_flight, config.qos.client_quota_mbps
+            );
+        }
+
+        let allowed_clients = Arc::new(RwLock::new(allowed_clients));
+        let approvals = Arc::new(ApprovalQueue::new(
+            config.clone(),
+            allowed_clients.clone(),
+            denied_clients,
+            audit_logger.clone(),
+        ));
+        let invites = Arc::new(
+            InviteStore::new(config.security.invites_path())
+                .with_audit_logger(audit_logger.clone()),
+        );
+        let reloader = Arc::new(ConfigReloader::new(
+            config,
+            directory.clone(),
+            approvals.clone(),
+            policy_engine.clone(),
+            rate_limiter.clone(),
+            usb_bridge.clone(),
+            audit_logger.clone(),
+        ));
+
         Ok(Self {
             endpoint,
             usb_bridge,
-            allowed_clients: Arc::new(RwLock::new(allowed_clients)),
-            config,
+            directory,
+            allowed_clients,
+            approvals,
+            invites,
+            reloader,
             audit_logger,
             rate_limiter,
             policy_engine,
+            metrics: Arc::new(ServerMetrics::new().with_qos(qos.clone())),
+            qos,
+            clients: Arc::new(ClientRegistry::new()),
             session_expired_rx,
         })
     }
@@ -157,6 +224,48 @@ impl IrohServer {
         self.endpoint.id()
     }
 
+    /// Get the shared transfer metrics registry
+    pub fn metrics(&self) -> SharedServerMetrics {
+        self.metrics.clone()
+    }
+
+    /// Persist approval decisions to, and reload from, the given configuration file
+    pub fn with_config_path(self, path: PathBuf) -> Self {
+        self.approvals.set_config_path(path.clone());
+        self.reloader.set_config_path(path);
+        self
+    }
+
+    /// Get the queue of clients awaiting approval
+    pub fn approvals(&self) -> SharedApprovalQueue {
+        self.approvals.clone()
+    }
+
+    /// Get the pairing invite store
+    pub fn invites(&self) -> SharedInviteStore {
+        self.invites.clone()
+    }
+
+    /// Get the handle that reloads the configuration
+    pub fn reloader(&self) -> SharedConfigReloader {
+        self.reloader.clone()
+    }
+
+    /// Get a handle to the running client connections
+    pub fn clients(&self) -> SharedClientRegistry {
+        self.clients.clone()
+    }
+
+    /// Get the client names and groups
+    pub fn client_directory(&self) -> SharedClientDirectory {
+        self.directory.clone()
+    }
+
+    /// Get a handle to the policy engine
+    pub fn policy_engine(&self) -> Arc<PolicyEngine> {
+        self.policy_engine.clone()
+    }
+
     /// Get the server's listening addresses
     pub fn local_addrs(&self) -> Vec<std::net::SocketAddr> {
         self.endpoint.bound_sockets().iter().copied().collect()
@@ -180,25 +289,22 @@ impl IrohServer {
             };
 
             // Spawn task to handle connection
-            let usb_bridge = self.usb_bridge.clone();
-            let allowed_clients = self.allowed_clients.clone();
-            let require_approval = self.config.security.require_approval;
-            let audit_logger = self.audit_logger.clone();
-            let rate_limiter = self.rate_limiter.clone();
-            let policy_engine = self.policy_engine.clone();
+            let invites = self.invites.clone();
+            let services = ConnectionServices {
+                usb_bridge: self.usb_bridge.clone(),
+                audit_logger: self.audit_logger.clone(),
+                rate_limiter: self.rate_limiter.clone(),
+                policy_engine: self.policy_engine.clone(),
+                metrics: self.metrics.clone(),
+                qos: self.qos.clone(),
+                approvals: self.approvals.clone(),
+                config_reloads: self.reloader.subscribe(),
+                clients: self.clients.clone(),
+            };
 
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_connection(
-                    incoming,
-                    usb_bridge,
-                    allowed_clients,
-                    require_approval,
-                    audit_logger,
-                    rate_limiter,
-                    policy_engine,
-                )
-                .await
-                {
+                let result = Self::handle_connection(incoming, invites, services).await;
+                if let Err(e) = result {
                     error!("Connection error: {:#}", e);
                 }
             });
@@ -209,16 +315,16 @@ impl IrohServer {
 
     /// Handle a single client connection
     ///
-    /// Validates the client against the allowlist and spawns a connection handler
+    /// Validates the client against the allowlist, enrolls or parks unknown
+    /// clients and spawns a connection handler
     async fn handle_connection(
         incoming: iroh::endpoint::Incoming,
-        usb_bridge: UsbBridge,
-        allowed_clients: Arc<RwLock<HashSet<EndpointId>>>,
-        require_approval: bool,
-        audit_logger: SharedAuditLogger,
-        rate_limiter: Option<SharedRateLimiter>,
-        policy_engine: Arc<PolicyEngine>,
+        invites: SharedInviteStore,
+        services: ConnectionServices,
     ) -> Result<()> {
+        let audit_logger = services.audit_logger.clone();
+        let approvals = services.approvals.clone();
+
         // Wait for connection to establish
         let connection = incoming.await.context("Failed to establish connection")?;
 
@@ -229,20 +335,27 @@ impl IrohServer {
         debug!("Connection attempt from: {}", remote_endpoint_id);
 
         // Check allowlist if required
-        if require_approval {
-            let clients = allowed_clients.read().await;
-            if !clients.is_empty() && !clients.contains(&remote_endpoint_id) {
+        if !approvals.admits(&remote_endpoint_id).await {
+            if approvals.is_denied(&remote_endpoint_id).await {
                 warn!(
-                    "Rejected connection from unauthorized EndpointId: {}",
+                    "Rejected connection from denied EndpointId: {}",
                     remote_endpoint_id
                 );
 
                 // Audit log: authentication failure
                 if let Some(ref logger) = *audit_logger {
-                    logger.log_auth_failure(&endpoint_id_str, "EndpointId not in allowlist");
+                    logger.log_auth_failure(&endpoint_id_str, "EndpointId denied by operator");
                 }
 
-                return Ok(()); // Silent rejection
+                connection.close(VarInt::from_u32(NOT_ADMITTED), b"denied");
+                return Ok(());
+            }
+
+            let admitted =
+                Self::admit_unknown(&connection, &approvals, &invites, &services).await?;
+            if !admitted {
+                connection.close(VarInt::from_u32(NOT_ADMITTED), b"not approved");
+                return Ok(());
             }
         }
 
@@ -254,14 +367,7 @@ impl IrohServer {
         }
 
         // Create and run client connection handler
-        let mut client_conn = ClientConnection::new(
-            remote_endpoint_id,
-            connection,
-            usb_bridge,
-            audit_logger.clone(),
-            rate_limiter,
-            policy_engine,
-        );
+        let mut client_conn = ClientConnection::new(remote_endpoint_id, connection, services);
 
         client_conn.run().await?;
 
@@ -274,29 +380,241 @@ impl IrohServer {
         Ok(())
     }
 
-    /// Parse allowlist from config strings
+    /// Admit a client that is not on the allowlist
     ///
-    /// EndpointIds should be in hex format (64 characters) or base32 format
-    fn parse_allowlist(approved_clients: &[String]) -> Result<HashSet<EndpointId>> {
-        let mut allowlist = HashSet::new();
+    /// Reads the client's capabilities first: a client presenting a pairing
+    /// invite is enrolled when the token is valid and turned away otherwise;
+    /// any other client is parked until the operator decides. Returns `true`
+    /// once the client is admitted; it then repeats the capability exchange.
+    async fn admit_unknown(
+        connection: &Connection,
+        approvals: &ApprovalQueue,
+        invites: &InviteStore,
+        services: &ConnectionServices,
+    ) -> Result<bool> {
+        // The client opens with its capabilities
+        let (send, mut recv) = tokio::time::timeout(CAPABILITY_TIMEOUT, connection.accept_bi())
+            .await
+            .context("Timeout waiting for capability exchange")?
+            .context("Failed to accept capability exchange stream")?;
+        let message_bytes = protocol::read_framed_async(&mut recv)
+            .await
+            .context("Failed to read client capabilities")?;
+        let message: Message = decode_framed(&message_bytes)?;
+
+        if let MessagePayload::ClientCapabilitiesV2 {
+            invite_token: Some(token),
+            ..
+        } = &message.payload
+        {
+            return Self::redeem_invite(connection, send, token, approvals, invites, services)
+                .await;
+        }
 
-        for client_str in approved_clients {
-            if client_str.is_empty() {
-                continue;
-            }
+        Self::await_approval(
+            connection,
+            send,
+            &message,
+            approvals,
+            &services.audit_logger,
+        )
+        .await
+    }
 
-            // Try to parse EndpointId - Iroh EndpointIds are 32-byte Ed25519 public keys
-            match client_str.parse::<EndpointId>() {
-                Ok(endpoint_id) => {
-                    allowlist.insert(endpoint_id);
-                }
-                Err(e) => {
-                    warn!("Failed to parse EndpointId '{}': {}", client_str, e);
+    /// Enroll a client with the invite it presented
+    ///
+    /// The invite is burned even if writing the enrollment back to the
+    /// configuration fails; the client stays admitted until the restart.
+    async fn redeem_invite(
+        connection: &Connection,
+        mut send: SendStream,
+        token: &str,
+        approvals: &ApprovalQueue,
+        invites: &InviteStore,
+        services: &ConnectionServices,
+    ) -> Result<bool> {
+        let remote_endpoint_id = connection.remote_id();
+        let endpoint_id_str = remote_endpoint_id.to_string();
+        let audit_logger = &services.audit_logger;
+
+        let invite = match invites.redeem(token) {
+            Ok(Some(invite)) => invite,
+            Ok(None) => {
+                warn!("Rejected unusable invite from: {}", remote_endpoint_id);
+                if let Some(ref logger) = **audit_logger {
+                    logger.log_auth_failure(&endpoint_id_str, "Invalid, expired or used invite");
                 }
+                let payload = MessagePayload::Error {
+                    message: "Invite is invalid, expired or already used".to_string(),
+                };
+                Self::send_final(send, payload).await?;
+                return Ok(false);
+            }
+            Err(e) => {
+                error!(
+                    "Failed to check invite from {}: {:#}",
+                    remote_endpoint_id, e
+                );
+                let payload = MessagePayload::Error {
+                    message: "Server could not check the invite".to_string(),
+                };
+                Self::send_final(send, payload).await?;
+                return Ok(false);
+            }
+        };
+
+        services
+            .policy_engine
+            .set_client_scope(remote_endpoint_id, &invite.devices);
+        if let Err(e) = approvals.enroll(remote_endpoint_id, &invite.devices).await {
+            warn!(
+                "Failed to save enrollment of {}: {:#}",
+                remote_endpoint_id, e
+            );
+        }
+
+        info!(
+            "Client {} enrolled with invite {}",
+            remote_endpoint_id, invite.id
+        );
+        if let Some(ref logger) = **audit_logger {
+            logger.log_invite_redeemed(&endpoint_id_str, &invite.id, &invite.devices);
+        }
+
+        Self::send_approval_frame(&mut send, MessagePayload::ApprovalGranted).await?;
+        send.finish()
+            .context("Failed to finish approval response")?;
+        Ok(true)
+    }
+
+    /// Park an unknown client until the operator approves or denies it
+    ///
+    /// The client's capability stream is held open with `ApprovalPending`
+    /// and answered with the decision. Returns `true` once the client is
+    /// approved. Clients before protocol 1.9 get an error and stay queued,
+    /// so an approval lets them in on their next reconnect.
+    async fn await_approval(
+        connection: &Connection,
+        mut send: SendStream,
+        message: &Message,
+        approvals: &ApprovalQueue,
+        audit_logger: &SharedAuditLogger,
+    ) -> Result<bool> {
+        let remote_endpoint_id = connection.remote_id();
+        let endpoint_id_str = remote_endpoint_id.to_string();
+
+        let Some(mut decision) = approvals.park(remote_endpoint_id) else {
+            warn!(
+                "Approval queue full, rejected connection from: {}",
+                remote_endpoint_id
+            );
+            if let Some(ref logger) = **audit_logger {
+                logger.log_auth_failure(&endpoint_id_str, "Approval queue full");
+            }
+            let payload = MessagePayload::Error {
+                message: "Too many clients awaiting approval".to_string(),
+            };
+            Self::send_final(send, payload).await?;
+            return Ok(false);
+        };
+
+        // Audit log: authentication failure until the operator decides
+        if let Some(ref logger) = **audit_logger {
+            logger.log_auth_failure(&endpoint_id_str, "Awaiting operator approval");
+        }
+
+        if !message.version.supports_client_approval() {
+            let payload = MessagePayload::Error {
+                message: "Awaiting operator approval".to_string(),
+            };
+            Self::send_final(send, payload).await?;
+            return Ok(false);
+        }
+
+        Self::send_approval_frame(&mut send, MessagePayload::ApprovalPending).await?;
+
+        let outcome = tokio::select! {
+            result = decision.wait_for(Option::is_some) => result.ok().and_then(|d| *d),
+            _ = connection.closed() => {
+                info!("Client left while awaiting approval: {}", remote_endpoint_id);
+                return Ok(false);
             }
+            _ = tokio::time::sleep(APPROVAL_TIMEOUT) => None,
+        };
+
+        match outcome {
+            Some(ApprovalDecision::Approved) => {
+                Self::send_approval_frame(&mut send, MessagePayload::ApprovalGranted).await?;
+                send.finish()
+                    .context("Failed to finish approval response")?;
+                Ok(true)
+            }
+            Some(ApprovalDecision::Denied) => {
+                Self::send_final(send, MessagePayload::ApprovalDenied).await?;
+                Ok(false)
+            }
+            None => {
+                info!("Approval timed out for: {}", remote_endpoint_id);
+                let payload = MessagePayload::Error {
+                    message: "Timed out awaiting operator approval".to_string(),
+                };
+                Self::send_final(send, payload).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Write one frame on the held capability stream
+    async fn send_approval_frame(send: &mut SendStream, payload: MessagePayload) -> Result<()> {
+        let message = Message {
+            version: CURRENT_VERSION,
+            payload,
+        };
+        protocol::write_framed_async(send, &encode_framed(&message)?)
+            .await
+            .context("Failed to send approval status")
+    }
+
+    /// Write a last frame and give the client a moment to read it
+    async fn send_final(mut send: SendStream, payload: MessagePayload) -> Result<()> {
+        Self::send_approval_frame(&mut send, payload).await?;
+        send.finish()
+            .context("Failed to finish approval response")?;
+        let _ = tokio::time::timeout(CLOSE_GRACE, send.stopped()).await;
+        Ok(())
+    }
+
+    /// Create the rate limiter for the bandwidth settings (None if disabled)
+    pub(super) fn create_rate_limiter(bandwidth: &BandwidthSettings) -> Option<SharedRateLimiter> {
+        if !bandwidth.enabled {
+            return None;
         }
 
-        Ok(allowlist)
+        let limit = |bps: u64| {
+            let burst = (bps as f64 * bandwidth.burst_multiplier) as u64;
+            BandwidthLimit::new(bps, Some(burst))
+        };
+        let limiter = RateLimiter::new(
+            bandwidth.global_limit_bps().map(limit),
+            bandwidth.per_client_limit_bps().map(limit),
+            bandwidth.per_device_limit_bps().map(limit),
+        );
+        info!(
+            "Rate limiter enabled: global={:?}, per_client={:?}, per_device={:?}",
+            bandwidth.global_limit, bandwidth.per_client_limit, bandwidth.per_device_limit
+        );
+        Some(Arc::new(limiter))
+    }
+
+    /// Parse allowlist from config strings
+    ///
+    /// Entries are EndpointIds (hex or base32), client names or `@group`
+    /// references, resolved through `directory`
+    pub(super) fn parse_allowlist(
+        approved_clients: &[String],
+        directory: &ClientDirectory,
+    ) -> Result<HashSet<EndpointId>> {
+        Ok(directory.resolve_all(approved_clients))
     }
 
     /// Add a client to the allowlist at runtime
@@ -357,7 +675,8 @@ mod tests {
     #[tokio::test]
     async fn test_parse_allowlist() {
         let clients = vec![];
-        let allowlist = IrohServer::parse_allowlist(&clients).unwrap();
+        let directory = ClientDirectory::default();
+        let allowlist = IrohServer::parse_allowlist(&clients, &directory).unwrap();
         assert_eq!(allowlist.len(), 0);
     }
 
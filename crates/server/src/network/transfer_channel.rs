@@ -0,0 +1,818 @@
+//! Persistent transfer channel
+//!
+//! Serves the long-lived, request-ID-multiplexed transfer stream negotiated
+//! during the capability exchange. Instead of one QUIC bidirectional stream per
+//! `SubmitTransfer`, the client opens a single stream, sends `OpenTransferChannel`
+//! and then pipelines transfer requests over it. Each request is dispatched to
+//! the USB subsystem in its own task, and `TransferComplete` frames are written
+//! back as soon as they finish, so responses can arrive out of order.
+//!
+//! # Frame Flow
+//!
+//! ```text
+//! client                                   server
+//!   │── OpenTransferChannel ──────────────────>│
+//!   │── SubmitTransfer { id: 1 } ─────────────>│──┐
+//!   │── SubmitTransfer { id: 2 } ─────────────>│──┼─> UsbBridge (concurrent)
+//!   │<──────────── TransferComplete { id: 2 } ──│<─┤
+//!   │<──────────── TransferComplete { id: 1 } ──│<─┘
+//! ```
+//!
+//! A `CancelTransfer` frame (protocol 1.3) aborts a pending transfer: the
+//! server answers with `CancelTransferResponse`, and a cancelled transfer's
+//! `TransferComplete` carries `UsbError::Cancelled`.
+//!
+//! The per-request stream mode stays available for peers that did not
+//! negotiate the channel.
+
+use anyhow::{Context, Result};
+use common::{
+    RateLimitResult, SharedRateLimiter, TransferKind, TransferOutcome, UsbBridge, UsbCommand,
+    response_payload_size,
+};
+use iroh::PublicKey as EndpointId;
+use iroh::endpoint::{RecvStream, SendStream};
+use protocol::{
+    CURRENT_VERSION, CancelResult, DeviceHandle, DeviceId, Message, MessagePayload, RequestId,
+    TransferResult, TransferType, UsbError, UsbRequest, UsbResponse, decode_framed, encode_framed,
+    validate_version,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{Mutex, RwLock, Semaphore, broadcast, mpsc};
+use tracing::{debug, info, trace, warn};
+
+use crate::network::metrics::SharedServerMetrics;
+use crate::policy::PolicyEngine;
+use crate::qos::SharedQosManager;
+
+/// Maximum number of transfers the server runs concurrently on one channel
+pub const MAX_IN_FLIGHT_TRANSFERS: u32 = 32;
+
+/// Pending USB transfer awaiting completion or cancellation
+pub(crate) struct PendingTransfer {
+    /// Request ID for matching responses
+    pub(crate) request_id: RequestId,
+    /// Sender to signal cancellation
+    pub(crate) cancel_tx: broadcast::Sender<()>,
+    /// Handed to the USB subsystem; cancelled through libusb from here on
+    pub(crate) submitted: bool,
+    /// Cancelled by the client before submission
+    pub(crate) cancelled: bool,
+}
+
+/// Tracks pending transfers per device handle
+pub(crate) type PendingTransfersMap = Arc<Mutex<HashMap<DeviceHandle, Vec<PendingTransfer>>>>;
+
+/// Attached devices for a client (handle -> device_id mapping)
+pub(crate) type AttachedDevicesMap = Arc<RwLock<HashMap<DeviceHandle, DeviceId>>>;
+
+/// Server-wide rate limiter (None = bandwidth limiting disabled)
+///
+/// Replaced when a configuration reload changes the bandwidth settings.
+pub type RateLimiterSlot = Arc<std::sync::RwLock<Option<SharedRateLimiter>>>;
+
+/// Executes transfer requests for one client
+///
+/// Cheap to clone: all state is shared with the owning `ClientConnection`,
+/// so the per-request stream path and the persistent channel apply the same
+/// attachment checks, rate limiting, QoS scheduling, metrics, data quota
+/// accounting and hot-unplug cancellation.
+#[derive(Clone)]
+pub(crate) struct TransferDispatcher {
+    /// Client's EndpointId (rate limiter key)
+    endpoint_id: EndpointId,
+    /// Bridge to USB subsystem
+    usb_bridge: UsbBridge,
+    /// Rate limiter for bandwidth control
+    rate_limiter: RateLimiterSlot,
+    /// Devices attached by this client
+    attached_devices: AttachedDevicesMap,
+    /// Pending transfers per device handle (for cancellation on hot-unplug)
+    pending_transfers: PendingTransfersMap,
+    /// Server-wide transfer metrics
+    metrics: SharedServerMetrics,
+    /// QoS scheduler shared by all clients
+    qos: SharedQosManager,
+    /// Policy engine counting transferred bytes against data quotas
+    policy_engine: Arc<PolicyEngine>,
+}
+
+impl TransferDispatcher {
+    /// Create a dispatcher sharing the connection's attachment state
+    pub(crate) fn new(
+        endpoint_id: EndpointId,
+        usb_bridge: UsbBridge,
+        rate_limiter: RateLimiterSlot,
+        attached_devices: AttachedDevicesMap,
+        metrics: SharedServerMetrics,
+        qos: SharedQosManager,
+        policy_engine: Arc<PolicyEngine>,
+    ) -> Self {
+        Self {
+            endpoint_id,
+            usb_bridge,
+            rate_limiter,
+            attached_devices,
+            pending_transfers: Arc::new(Mutex::new(HashMap::new())),
+            metrics,
+            qos,
+            policy_engine,
+        }
+    }
+
+    /// Submit a transfer to the USB subsystem and wait for its completion
+    ///
+    /// Returns an error response (rather than `Err`) for unattached handles and
+    /// transfers cancelled by hot-unplug, so callers can always reply to the client.
+    pub(crate) async fn submit(&self, request: UsbRequest) -> Result<UsbResponse> {
+        let cancel_rx = self.register(request.handle, request.id).await;
+        self.run(request, cancel_rx).await
+    }
+
+    /// Register a transfer as pending so it can be cancelled
+    ///
+    /// The persistent channel registers each request before spawning it, so a
+    /// `CancelTransfer` read right after the `SubmitTransfer` always finds it.
+    pub(crate) async fn register(
+        &self,
+        handle: DeviceHandle,
+        request_id: RequestId,
+    ) -> broadcast::Receiver<()> {
+        let (cancel_tx, cancel_rx) = broadcast::channel::<()>(1);
+        self.pending_transfers
+            .lock()
+            .await
+            .entry(handle)
+            .or_default()
+            .push(PendingTransfer {
+                request_id,
+                cancel_tx,
+                submitted: false,
+                cancelled: false,
+            });
+        cancel_rx
+    }
+
+    /// Run a registered transfer to completion
+    ///
+    /// Attachment checks, rate limiting and QoS admission all give way to a
+    /// cancellation; once the request has reached the USB subsystem, only
+    /// hot-unplug interrupts the wait and `CancelTransfer` goes through libusb.
+    pub(crate) async fn run(
+        &self,
+        request: UsbRequest,
+        mut cancel_rx: broadcast::Receiver<()>,
+    ) -> Result<UsbResponse> {
+        trace!("Submit transfer request: id={:?}", request.id);
+        let handle = request.handle;
+        let request_id = request.id;
+
+        // Verify device is attached
+        let device_id = self.attached_devices.read().await.get(&handle).copied();
+        let Some(device_id) = device_id else {
+            warn!("Transfer to unattached device: {:?}", handle);
+            self.remove_pending(handle, request_id).await;
+            return Ok(UsbResponse {
+                id: request_id,
+                result: TransferResult::Error {
+                    error: UsbError::NotFound,
+                },
+            });
+        };
+
+        // Calculate transfer data size for rate limiting
+        let transfer_bytes = Self::get_transfer_data_size(&request.transfer);
+        let kind = TransferKind::of(&request.transfer);
+
+        // Apply rate limiting, then wait for the QoS scheduler to admit the
+        // transfer
+        let client_id = self.endpoint_id.to_string();
+        let admitted = tokio::select! {
+            admitted = async {
+                self.throttle(&client_id, device_id, transfer_bytes, request_id).await;
+                self.qos.admit(request, &client_id).await
+            } => admitted,
+            _ = cancel_rx.recv() => None,
+        };
+        let Some((request, permit)) = admitted else {
+            info!("Transfer {:?} cancelled while queued", request_id);
+            return Ok(UsbResponse {
+                id: request_id,
+                result: TransferResult::Error {
+                    error: self.take_cancelled(handle, request_id).await,
+                },
+            });
+        };
+
+        // Metrics latency covers the USB round trip, not rate limiting or queueing
+        let started = Instant::now();
+        let long_poll = Self::is_long_poll(&request.transfer);
+
+        // Send command to USB subsystem. The pending map stays locked until the
+        // command is queued so a concurrent `cancel` reaches the USB thread
+        // after the submission.
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        {
+            let mut pending_map = self.pending_transfers.lock().await;
+            let pending = pending_map
+                .get_mut(&handle)
+                .and_then(|transfers| transfers.iter_mut().find(|t| t.request_id == request_id));
+            match pending {
+                Some(pending) if !pending.cancelled => pending.submitted = true,
+                _ => {
+                    drop(pending_map);
+                    info!("Transfer {:?} cancelled before submission", request_id);
+                    return Ok(UsbResponse {
+                        id: request_id,
+                        result: TransferResult::Error {
+                            error: self.take_cancelled(handle, request_id).await,
+                        },
+                    });
+                }
+            }
+            let sent = self
+                .usb_bridge
+                .send_command(UsbCommand::SubmitTransfer {
+                    handle,
+                    request,
+                    response: tx,
+                })
+                .await;
+            if let Err(e) = sent {
+                // The caller replies with an error; don't leave the entry behind
+                remove_entry(&mut pending_map, handle, request_id);
+                return Err(e).context("Failed to submit transfer to USB subsystem");
+            }
+        }
+        self.metrics.transfer_started(&client_id, device_id, kind);
+
+        // An IN transfer on an interrupt or bulk endpoint can stay pending
+        // until the device has data, so it gives its QoS slot back once
+        // submitted; other transfers hold it until the response arrives
+        let _permit = permit.filter(|_| !long_poll);
+
+        // Wait for either transfer completion or cancellation
+        let response = tokio::select! {
+            result = rx => {
+                match result {
+                    Ok(response) => response,
+                    Err(_) => {
+                        warn!("Transfer response channel closed for {:?}", request_id);
+                        UsbResponse {
+                            id: request_id,
+                            result: TransferResult::Error {
+                                error: UsbError::NoDevice,
+                            },
+                        }
+                    }
+                }
+            }
+            _ = cancel_rx.recv() => {
+                info!("Transfer {:?} cancelled due to device hot-unplug", request_id);
+                UsbResponse {
+                    id: request_id,
+                    result: TransferResult::Error {
+                        error: UsbError::NoDevice,
+                    },
+                }
+            }
+        };
+
+        // Remove this transfer from pending map
+        self.remove_pending(handle, request_id).await;
+
+        let outcome = match response.result {
+            TransferResult::Error { .. } => TransferOutcome::Failed,
+            _ => TransferOutcome::Completed {
+                bytes_sent: response_payload_size(&response.result),
+                bytes_received: transfer_bytes,
+                latency: started.elapsed(),
+            },
+        };
+        self.metrics
+            .transfer_finished(&client_id, device_id, kind, outcome);
+        let bytes = transfer_bytes + response_payload_size(&response.result);
+        self.qos.record_transfer(&client_id, bytes).await;
+        self.policy_engine.record_data(&self.endpoint_id, bytes);
+
+        Ok(response)
+    }
+
+    /// Cancel one pending transfer at the client's request
+    ///
+    /// A transfer still waiting for rate limiting or QoS admission completes
+    /// with `UsbError::Cancelled` straight away. One already submitted is
+    /// cancelled in libusb; if it completes first the result is `NotFound`
+    /// and its normal response is delivered.
+    pub(crate) async fn cancel(&self, handle: DeviceHandle, request_id: RequestId) -> CancelResult {
+        // Same attachment check as `run`: a handle this client does not hold
+        // has nothing to cancel
+        if !self.attached_devices.read().await.contains_key(&handle) {
+            warn!("Cancel on unattached device: {:?}", handle);
+            return CancelResult::NotFound;
+        }
+
+        {
+            let mut pending_map = self.pending_transfers.lock().await;
+            let Some(pending) = pending_map
+                .get_mut(&handle)
+                .and_then(|transfers| transfers.iter_mut().find(|t| t.request_id == request_id))
+            else {
+                debug!("Cancel for unknown transfer {:?}", request_id);
+                return CancelResult::NotFound;
+            };
+
+            if !pending.submitted {
+                debug!("Cancelling queued transfer {:?}", request_id);
+                pending.cancelled = true;
+                let _ = pending.cancel_tx.send(());
+                return CancelResult::Cancelled;
+            }
+        }
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let sent = self
+            .usb_bridge
+            .send_command(UsbCommand::CancelTransfer {
+                handle,
+                request_id,
+                response: tx,
+            })
+            .await;
+        match sent {
+            Ok(()) if rx.await.unwrap_or(false) => {
+                debug!("Cancelled in-flight transfer {:?}", request_id);
+                CancelResult::Cancelled
+            }
+            Ok(()) => CancelResult::NotFound,
+            Err(e) => {
+                warn!("Failed to cancel transfer {:?}: {:#}", request_id, e);
+                CancelResult::NotFound
+            }
+        }
+    }
+
+    /// Whether a transfer may wait on the device indefinitely
+    fn is_long_poll(transfer: &TransferType) -> bool {
+        match transfer {
+            TransferType::Interrupt { endpoint, .. } | TransferType::Bulk { endpoint, .. } => {
+                endpoint & 0x80 != 0
+            }
+            TransferType::Control { .. } | TransferType::Isochronous { .. } => false,
+        }
+    }
+
+    /// Wait for the rate limiter to admit `transfer_bytes`, if enabled
+    async fn throttle(
+        &self,
+        client_id: &str,
+        device_id: DeviceId,
+        transfer_bytes: u64,
+        request_id: RequestId,
+    ) {
+        let Some(limiter) = self.rate_limiter.read().unwrap().clone() else {
+            return;
+        };
+        let device_key = Some(device_id.0);
+
+        // Check rate limit and wait if necessary
+        let result = limiter
+            .check(Some(client_id), device_key, transfer_bytes)
+            .await;
+
+        match result {
+            RateLimitResult::Allowed => {
+                // Use try_acquire to atomically check and consume tokens
+                if !limiter
+                    .try_acquire(Some(client_id), device_key, transfer_bytes)
+                    .await
+                {
+                    trace!(
+                        "Rate limit: transfer {:?} delayed, tokens unavailable",
+                        request_id
+                    );
+                }
+            }
+            RateLimitResult::Wait(duration) => {
+                debug!(
+                    "Rate limit: transfer {:?} waiting {:?} for {} bytes",
+                    request_id, duration, transfer_bytes
+                );
+                self.metrics
+                    .transfer_throttled(client_id, device_id, duration);
+                tokio::time::sleep(duration).await;
+                // Record the transfer after waiting
+                limiter
+                    .record(Some(client_id), device_key, transfer_bytes)
+                    .await;
+            }
+        }
+    }
+
+    /// Remove a transfer interrupted before submission
+    ///
+    /// Returns the error to report: `Cancelled` if the client asked for it,
+    /// `NoDevice` if hot-unplug already dropped the entry.
+    async fn take_cancelled(&self, handle: DeviceHandle, request_id: RequestId) -> UsbError {
+        let mut pending_map = self.pending_transfers.lock().await;
+        let Some(transfers) = pending_map.get_mut(&handle) else {
+            return UsbError::NoDevice;
+        };
+        let Some(pos) = transfers.iter().position(|t| t.request_id == request_id) else {
+            return UsbError::NoDevice;
+        };
+        let pending = transfers.remove(pos);
+        if transfers.is_empty() {
+            pending_map.remove(&handle);
+        }
+        if pending.cancelled {
+            UsbError::Cancelled
+        } else {
+            UsbError::NoDevice
+        }
+    }
+
+    /// Remove a finished or cancelled transfer from the pending map
+    async fn remove_pending(&self, handle: DeviceHandle, request_id: RequestId) {
+        remove_entry(
+            &mut *self.pending_transfers.lock().await,
+            handle,
+            request_id,
+        );
+    }
+
+    /// Cancel all pending transfers for the given device handles
+    ///
+    /// Returns the number of transfers cancelled. Each pending transfer will
+    /// receive a cancellation signal and respond with UsbError::NoDevice.
+    pub(crate) async fn cancel_pending_transfers(&self, handles: &[DeviceHandle]) -> usize {
+        let mut cancelled = 0;
+        let mut pending_map = self.pending_transfers.lock().await;
+
+        for handle in handles {
+            if let Some(transfers) = pending_map.remove(handle) {
+                for transfer in transfers {
+                    debug!(
+                        "Cancelling pending transfer {:?} for device {:?}",
+                        transfer.request_id, handle
+                    );
+                    let _ = transfer.cancel_tx.send(());
+                    cancelled += 1;
+                }
+            }
+        }
+
+        cancelled
+    }
+
+    /// Get the data size of a transfer for rate limiting
+    fn get_transfer_data_size(transfer: &TransferType) -> u64 {
+        match transfer {
+            TransferType::Control { data, .. } => data.len() as u64,
+            TransferType::Interrupt { data, .. } => data.len() as u64,
+            TransferType::Bulk { data, .. } => data.len() as u64,
+            TransferType::Isochronous { data, .. } => data.len() as u64,
+        }
+    }
+}
+
+/// Remove one transfer from a locked pending map
+fn remove_entry(
+    pending_map: &mut HashMap<DeviceHandle, Vec<PendingTransfer>>,
+    handle: DeviceHandle,
+    request_id: RequestId,
+) {
+    if let Some(transfers) = pending_map.get_mut(&handle) {
+        transfers.retain(|t| t.request_id != request_id);
+        if transfers.is_empty() {
+            pending_map.remove(&handle);
+        }
+    }
+}
+
+/// Serve a persistent transfer channel until the client finishes the stream
+///
+/// The `OpenTransferChannel` frame has already been consumed by the caller.
+/// At most `max_in_flight` transfers are dispatched concurrently; further
+/// requests wait for a slot, which applies back-pressure to the client through
+/// QUIC flow control.
+pub(crate) async fn serve_transfer_channel(
+    dispatcher: TransferDispatcher,
+    mut send: SendStream,
+    mut recv: RecvStream,
+    max_in_flight: u32,
+) -> Result<()> {
+    let endpoint_id = dispatcher.endpoint_id;
+    info!(
+        "Transfer channel opened by {} (max in flight: {})",
+        endpoint_id, max_in_flight
+    );
+
+    let in_flight = Arc::new(Semaphore::new(max_in_flight.max(1) as usize));
+    let (response_tx, mut response_rx) = mpsc::channel::<MessagePayload>(max_in_flight as usize);
+
+    // Writer task: serializes completed transfers onto the send half
+    let writer = tokio::spawn(async move {
+        while let Some(payload) = response_rx.recv().await {
+            let message = Message {
+                version: CURRENT_VERSION,
+                payload,
+            };
+            let bytes = encode_framed(&message).context("Failed to encode transfer response")?;
+            protocol::write_framed_async(&mut send, &bytes)
+                .await
+                .context("Failed to write transfer response")?;
+        }
+        send.finish().context("Failed to finish transfer channel")?;
+        Ok::<(), anyhow::Error>(())
+    });
+
+    loop {
+        // No read timeout: the channel legitimately idles between transfers
+        let message_bytes = match protocol::read_framed_async(&mut recv).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                debug!("Transfer channel from {} closed: {}", endpoint_id, e);
+                break;
+            }
+        };
+
+        let message: Message = match decode_framed(&message_bytes) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to decode transfer channel frame: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = validate_version(&message.version) {
+            warn!("Protocol version mismatch on transfer channel: {}", e);
+            break;
+        }
+
+        let request = match message.payload {
+            MessagePayload::SubmitTransfer { request } => request,
+            MessagePayload::CancelTransfer { handle, request_id } => {
+                let dispatcher = dispatcher.clone();
+                let response_tx = response_tx.clone();
+                tokio::spawn(async move {
+                    let result = dispatcher.cancel(handle, request_id).await;
+                    let _ = response_tx
+                        .send(MessagePayload::CancelTransferResponse { request_id, result })
+                        .await;
+                });
+                continue;
+            }
+            other => {
+                warn!("Unexpected message on transfer channel: {:?}", other);
+                continue;
+            }
+        };
+
+        let permit = in_flight
+            .clone()
+            .acquire_owned()
+            .await
+            .context("Transfer channel semaphore closed")?;
+        let dispatcher = dispatcher.clone();
+        let response_tx = response_tx.clone();
+        let cancel_rx = dispatcher.register(request.handle, request.id).await;
+
+        tokio::spawn(async move {
+            let request_id = request.id;
+            let response = match dispatcher.run(request, cancel_rx).await {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("Transfer {:?} failed to dispatch: {:#}", request_id, e);
+                    UsbResponse {
+                        id: request_id,
+                        result: TransferResult::Error {
+                            error: UsbError::Other {
+                                message: e.to_string(),
+                            },
+                        },
+                    }
+                }
+            };
+            let _ = response_tx
+                .send(MessagePayload::TransferComplete { response })
+                .await;
+            drop(permit);
+        });
+    }
+
+    // Let in-flight transfers drain, then close the send half
+    drop(response_tx);
+    match writer.await {
+        Ok(result) => result?,
+        Err(e) => warn!("Transfer channel writer task failed: {}", e),
+    }
+
+    info!("Transfer channel closed for {}", endpoint_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QosSettings;
+    use crate::network::metrics::ServerMetrics;
+    use crate::qos::QosManager;
+
+    fn bulk_request(id: u64) -> UsbRequest {
+        UsbRequest {
+            id: RequestId(id),
+            handle: DeviceHandle(1),
+            transfer: TransferType::Bulk {
+                endpoint: 0x81,
+                data: vec![0u8; 64],
+                timeout_ms: 1000,
+                checksum: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_transfer_waiting_for_qos() {
+        let qos = Arc::new(QosManager::from_settings(&QosSettings {
+            enabled: true,
+            max_in_flight: 1,
+            ..Default::default()
+        }));
+        let (usb_bridge, _worker) = common::create_usb_bridge();
+        let attached: AttachedDevicesMap = Arc::new(RwLock::new(HashMap::new()));
+        attached.write().await.insert(DeviceHandle(1), DeviceId(1));
+        let dispatcher = TransferDispatcher::new(
+            EndpointId::from_bytes(&[0u8; 32]).unwrap(),
+            usb_bridge,
+            RateLimiterSlot::default(),
+            attached,
+            Arc::new(ServerMetrics::new()),
+            qos.clone(),
+            Arc::new(PolicyEngine::new(Vec::new())),
+        );
+
+        // Occupy the only QoS slot so the next transfer stays queued
+        let client_id = EndpointId::from_bytes(&[0u8; 32]).unwrap().to_string();
+        let (_, held) = qos.admit(bulk_request(1), &client_id).await.unwrap();
+
+        let cancel_rx = dispatcher.register(DeviceHandle(1), RequestId(2)).await;
+        let queued = tokio::spawn({
+            let dispatcher = dispatcher.clone();
+            async move { dispatcher.run(bulk_request(2), cancel_rx).await }
+        });
+        while qos.queue_depths().iter().sum::<usize>() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(
+            dispatcher.cancel(DeviceHandle(2), RequestId(2)).await,
+            CancelResult::NotFound
+        );
+        assert_eq!(
+            dispatcher.cancel(DeviceHandle(1), RequestId(2)).await,
+            CancelResult::Cancelled
+        );
+        let response = queued.await.unwrap().unwrap();
+        assert!(matches!(
+            response.result,
+            TransferResult::Error {
+                error: UsbError::Cancelled
+            }
+        ));
+        assert_eq!(
+            dispatcher.cancel(DeviceHandle(1), RequestId(2)).await,
+            CancelResult::NotFound
+        );
+        drop(held);
+    }
+
+    #[tokio::test]
+    async fn test_pending_in_transfers_release_qos_slot() {
+        let qos = Arc::new(QosManager::from_settings(&QosSettings {
+            enabled: true,
+            max_in_flight: 1,
+            ..Default::default()
+        }));
+        let (usb_bridge, worker) = common::create_usb_bridge();
+        let worker = Arc::new(worker);
+        let attached: AttachedDevicesMap = Arc::new(RwLock::new(HashMap::new()));
+        attached.write().await.insert(DeviceHandle(1), DeviceId(1));
+        let dispatcher = TransferDispatcher::new(
+            EndpointId::from_bytes(&[0u8; 32]).unwrap(),
+            usb_bridge,
+            RateLimiterSlot::default(),
+            attached,
+            Arc::new(ServerMetrics::new()),
+            qos,
+            Arc::new(PolicyEngine::new(Vec::new())),
+        );
+
+        // The device never answers the bulk IN read
+        let cancel_rx = dispatcher.register(DeviceHandle(1), RequestId(1)).await;
+        let _pending = tokio::spawn({
+            let dispatcher = dispatcher.clone();
+            async move { dispatcher.run(bulk_request(1), cancel_rx).await }
+        });
+        let UsbCommand::SubmitTransfer {
+            response: _never_answered,
+            ..
+        } = tokio::task::spawn_blocking({
+            let worker = worker.clone();
+            move || worker.recv_command().unwrap()
+        })
+        .await
+        .unwrap()
+        else {
+            panic!("expected the bulk IN submission");
+        };
+
+        // A control transfer still gets the only QoS slot
+        let control = UsbRequest {
+            id: RequestId(2),
+            handle: DeviceHandle(1),
+            transfer: TransferType::Control {
+                request_type: 0x80,
+                request: 0x06,
+                value: 0x0100,
+                index: 0,
+                data: vec![0u8; 18],
+            },
+        };
+        let cancel_rx = dispatcher.register(DeviceHandle(1), RequestId(2)).await;
+        let completed = tokio::spawn({
+            let dispatcher = dispatcher.clone();
+            async move { dispatcher.run(control, cancel_rx).await }
+        });
+        let command = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            tokio::task::spawn_blocking(move || worker.recv_command().unwrap()),
+        )
+        .await
+        .expect("control transfer starved by pending IN transfer")
+        .unwrap();
+        let UsbCommand::SubmitTransfer {
+            request, response, ..
+        } = command
+        else {
+            panic!("expected the control submission");
+        };
+        let _ = response.send(UsbResponse {
+            id: request.id,
+            result: TransferResult::Success {
+                data: vec![0u8; 18],
+                checksum: None,
+            },
+        });
+        assert!(matches!(
+            completed.await.unwrap().unwrap().result,
+            TransferResult::Success { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_submit_failure_clears_pending() {
+        let qos = Arc::new(QosManager::from_settings(&QosSettings::default()));
+        let (usb_bridge, worker) = common::create_usb_bridge();
+        // Without a USB worker every command fails to send
+        drop(worker);
+        let attached: AttachedDevicesMap = Arc::new(RwLock::new(HashMap::new()));
+        attached.write().await.insert(DeviceHandle(1), DeviceId(1));
+        let dispatcher = TransferDispatcher::new(
+            EndpointId::from_bytes(&[0u8; 32]).unwrap(),
+            usb_bridge,
+            RateLimiterSlot::default(),
+            attached,
+            Arc::new(ServerMetrics::new()),
+            qos,
+            Arc::new(PolicyEngine::new(Vec::new())),
+        );
+
+        for id in 1..=u64::from(MAX_IN_FLIGHT_TRANSFERS) + 1 {
+            assert!(dispatcher.submit(bulk_request(id)).await.is_err());
+        }
+        assert!(dispatcher.pending_transfers.lock().await.is_empty());
+    }
+
+    #[test]
+    fn test_transfer_data_size() {
+        let bulk = TransferType::Bulk {
+            endpoint: 0x02,
+            data: vec![0u8; 512],
+            timeout_ms: 1000,
+            checksum: None,
+        };
+        assert_eq!(TransferDispatcher::get_transfer_data_size(&bulk), 512);
+
+        let control = TransferType::Control {
+            request_type: 0x80,
+            request: 0x06,
+            value: 0x0100,
+            index: 0,
+            data: vec![],
+        };
+        assert_eq!(TransferDispatcher::get_transfer_data_size(&control), 0);
+    }
+}
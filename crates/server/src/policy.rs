@@ -2,15 +2,43 @@
 //!
 //! Provides time-based access control, session duration limits, and client-specific
 //! permissions for USB device access. Policies can restrict access by:
-//! - Time windows (e.g., 9am-5pm only)
+//! - Time windows (e.g., 9am-5pm on weekdays, in an IANA time zone) and
+//!   blackout dates (see [`crate::schedule`])
 //! - Session duration limits (e.g., max 1 hour)
-//! - Client allowlist/denylist (by EndpointId)
+//! - Client allowlist/denylist (by EndpointId, client name or `@group`)
 //! - Device class restrictions (e.g., no storage devices)
-
-use crate::config::DevicePolicy;
+//! - Device filter expressions (see [`common::filter`]), e.g. `class:08`
+//!   matching the device or any of its interfaces or `port:1-1.*` matching
+//!   the physical port
+//! - Interface restrictions for composite devices (by number or class)
+//! - Per-client device scopes, set when a client enrolls with an invite
+//!   limited to some devices
+//! - Rolling quotas: attach time per client per device per day, and bytes
+//!   per client per day or month (see [`crate::quota`])
+//! - Device reservations: while a client holds a booking of a device, no
+//!   other client may use it (see [`crate::reservation`])
+//!
+//! Policies and scopes can be replaced while sessions are active (see
+//! [`PolicyEngine::reload`]); sessions the new rules forbid are reported by
+//! [`PolicyEngine::revoked_sessions`] so connections can detach them.
+//!
+//! [`PolicyEngine::explain`] records every check behind a decision, for
+//! `p2p-usb-server policy explain`.
+
+use crate::config::{DevicePolicy, QuotaSettings, ReservationSettings, format_duration};
+use crate::directory::{ClientDirectory, SharedClientDirectory};
+use crate::quota::{self, DataLimits, QuotaLedger, QuotaLimits};
+use crate::reservation::{self, ReservationBook, SharedReservationBook};
+use crate::schedule::Schedule;
+use common::{DeviceFilter, Specificity};
 use iroh::PublicKey as EndpointId;
-use protocol::{DeviceHandle, DeviceId, DeviceInfo};
-use std::collections::HashMap;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
+use protocol::{
+    DeviceHandle, DeviceId, DeviceInfo, QuotaKind, QuotaUsage, Reservation, ReservationError,
+    ReservationId,
+};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, RwLock};
@@ -32,7 +60,7 @@ pub enum PolicyDenialReason {
     ClientNotAllowed,
     /// Current time outside allowed time windows
     OutsideTimeWindow {
-        /// Current time formatted as HH:MM
+        /// Current time in the policy's time zone, e.g. "Sat 2026-10-17 14:05 EEST"
         current_time: String,
         /// Allowed time windows
         allowed_windows: Vec<String>,
@@ -49,6 +77,50 @@ pub enum PolicyDenialReason {
     },
     /// No matching policy found and default is deny
     NoMatchingPolicy,
+    /// Interface not in the policy's allowed interfaces
+    InterfaceNotAllowed {
+        /// bInterfaceNumber that was requested
+        interface: u8,
+    },
+    /// Interface class not allowed for this client
+    InterfaceClassRestricted {
+        /// bInterfaceNumber that was requested
+        interface: u8,
+        /// The restricted interface class
+        interface_class: u8,
+    },
+    /// Device outside the devices this client was enrolled for
+    OutsideClientScope,
+    /// A usage quota of this client is used up
+    QuotaExceeded {
+        /// The quota that is used up
+        quota: QuotaUsage,
+    },
+    /// Another client holds a reservation of the device
+    Reserved {
+        /// The reservation in the way
+        reservation: Reservation,
+        /// When it ends in the policy time zone
+        until: String,
+    },
+}
+
+impl PolicyDenialReason {
+    /// Short name of the reason, without details (a metrics label)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::ClientNotAllowed => "client_not_allowed",
+            Self::OutsideTimeWindow { .. } => "outside_time_window",
+            Self::SessionDurationExceeded { .. } => "session_duration_exceeded",
+            Self::DeviceClassRestricted { .. } => "device_class_restricted",
+            Self::NoMatchingPolicy => "no_matching_policy",
+            Self::InterfaceNotAllowed { .. } => "interface_not_allowed",
+            Self::InterfaceClassRestricted { .. } => "interface_class_restricted",
+            Self::OutsideClientScope => "outside_client_scope",
+            Self::QuotaExceeded { .. } => "quota_exceeded",
+            Self::Reserved { .. } => "reserved",
+        }
+    }
 }
 
 impl std::fmt::Display for PolicyDenialReason {
@@ -81,6 +153,29 @@ impl std::fmt::Display for PolicyDenialReason {
                 )
             }
             Self::NoMatchingPolicy => write!(f, "No matching policy found"),
+            Self::InterfaceNotAllowed { interface } => {
+                write!(f, "Interface {} not shared with this client", interface)
+            }
+            Self::InterfaceClassRestricted {
+                interface,
+                interface_class,
+            } => {
+                write!(
+                    f,
+                    "Interface {} (class {}) restricted for this client",
+                    interface, interface_class
+                )
+            }
+            Self::OutsideClientScope => {
+                write!(f, "Device not among those this client may use")
+            }
+            Self::QuotaExceeded { quota } => write!(f, "Quota used up: {}", quota),
+            Self::Reserved { reservation, until } => write!(
+                f,
+                "Reserved by {} until {}",
+                reservation.holder_label(),
+                until
+            ),
         }
     }
 }
@@ -94,12 +189,88 @@ pub struct ActiveSession {
     pub device_id: DeviceId,
     /// Client EndpointId
     pub client_id: EndpointId,
+    /// Device as it was when attached (re-checked on reload)
+    pub device_info: DeviceInfo,
+    /// Interfaces claimed (None = whole device)
+    pub interfaces: Option<Vec<u8>>,
     /// When the session started
     pub started_at: Instant,
     /// Maximum duration allowed (if any)
     pub max_duration: Option<Duration>,
     /// Time window end (if within a window)
     pub window_expires_at: Option<Instant>,
+    /// Closing time of the window and when the policy next allows access
+    pub window_close: Option<WindowClose>,
+    /// Daily attach time quota of the device (if any)
+    pub time_quota: Option<Duration>,
+    /// When attach time was last counted against the quota
+    pub accounted_at: Instant,
+    /// When the time quota runs out, and the quota as it will be then
+    pub quota_end: Option<(Instant, QuotaUsage)>,
+    /// When another client's reservation of the device starts, and that
+    /// reservation
+    pub reserved_from: Option<(Instant, Reservation)>,
+}
+
+impl ActiveSession {
+    /// When the session expires and why (None = no limit)
+    ///
+    /// The earliest of the duration limit, the end of the time window, the
+    /// end of the time quota and the start of another client's reservation.
+    pub fn expiry(&self) -> Option<(Instant, SessionExpiredReason)> {
+        let duration_end = self.max_duration.map(|max| {
+            (
+                self.started_at + max,
+                SessionExpiredReason::DurationLimitReached,
+            )
+        });
+        let window_end = self.window_expires_at.map(|at| {
+            let close = self.window_close.clone().unwrap_or_default();
+            (
+                at,
+                SessionExpiredReason::TimeWindowExpired {
+                    current_time: close.local_time,
+                    next_window: close.next_window,
+                },
+            )
+        });
+        let quota_end = self
+            .quota_end
+            .clone()
+            .map(|(at, quota)| (at, SessionExpiredReason::QuotaExceeded { quota }));
+        let reserved = self
+            .reserved_from
+            .clone()
+            .map(|(at, reservation)| (at, SessionExpiredReason::Reserved { reservation }));
+        duration_end
+            .into_iter()
+            .chain(window_end)
+            .chain(quota_end)
+            .chain(reserved)
+            .min_by_key(|(at, _)| *at)
+    }
+}
+
+/// How the time window of a session ends, as shown to the client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowClose {
+    /// Closing time in the policy's time zone
+    pub local_time: String,
+    /// When the policy next allows access (None = not within a year)
+    pub next_window: Option<String>,
+}
+
+/// A session a policy is about to end
+#[derive(Debug, Clone)]
+pub struct SessionExpiry {
+    /// Handle of the session
+    pub handle: DeviceHandle,
+    /// Device ID
+    pub device_id: DeviceId,
+    /// When the session expires
+    pub expires_at: Instant,
+    /// Why it expires
+    pub reason: SessionExpiredReason,
 }
 
 /// Policy enforcement engine
@@ -107,14 +278,149 @@ pub struct ActiveSession {
 /// Manages device access policies and enforces them on attach requests.
 /// Spawns a background task to monitor session durations and time windows.
 pub struct PolicyEngine {
-    /// Device policies from configuration
-    policies: Vec<DevicePolicy>,
+    /// Device policies from configuration, swapped as a whole on reload
+    policies: std::sync::RwLock<Arc<PolicySet>>,
     /// Active sessions being monitored
     active_sessions: Arc<Mutex<HashMap<DeviceHandle, ActiveSession>>>,
     /// Callback for session expiration notifications
     session_expired_tx: Option<tokio::sync::mpsc::UnboundedSender<SessionExpiredEvent>>,
-    /// Timezone offset in hours from UTC (e.g., +2 for CEST)
-    timezone_offset_hours: i32,
+    /// Time zone of time windows that name none
+    timezone: std::sync::RwLock<TimeZone>,
+    /// Device filters limiting individual clients (absent = no limit)
+    client_scopes: std::sync::RwLock<HashMap<EndpointId, Vec<DeviceFilter>>>,
+    /// Client names and groups policies refer to
+    directory: SharedClientDirectory,
+    /// Data limits of each client, swapped as a whole on reload
+    quota_limits: std::sync::RwLock<Arc<QuotaLimits>>,
+    /// Quota usage of each client
+    quotas: QuotaLedger,
+    /// Limits on new reservations, swapped as a whole on reload
+    reservation_limits: std::sync::RwLock<ReservationSettings>,
+    /// Reservations of every device
+    reservations: SharedReservationBook,
+}
+
+/// Device policies with their parsed filters
+struct PolicySet {
+    policies: Vec<DevicePolicy>,
+    /// Parsed `device_filter` of each policy (None = invalid, never matches)
+    filters: Vec<Option<DeviceFilter>>,
+}
+
+impl PolicySet {
+    fn new(policies: Vec<DevicePolicy>) -> Self {
+        // Filters are validated at config load; skip any that slipped through
+        let filters = policies
+            .iter()
+            .map(|policy| {
+                DeviceFilter::parse(&policy.device_filter)
+                    .inspect_err(|e| warn!("Ignoring device policy: {}", e))
+                    .ok()
+            })
+            .collect();
+        Self { policies, filters }
+    }
+
+    /// Policies whose filter matches a device, with the filter's specificity
+    fn candidates<'a>(
+        &'a self,
+        device_info: &'a DeviceInfo,
+    ) -> impl Iterator<Item = (usize, Specificity)> + 'a {
+        self.filters
+            .iter()
+            .enumerate()
+            .filter_map(|(index, filter)| Some((index, filter.as_ref()?)))
+            .filter(|(_, filter)| filter.matches(device_info))
+            .map(|(index, filter)| (index, filter.specificity()))
+    }
+
+    /// Find the most specific policy matching a device
+    ///
+    /// Exact VID:PID (or stable ID) filters win over `port:` filters, then
+    /// VID:* filters, then any other expression; ties go to the first policy
+    /// in the config. The default "*" policy is not considered here.
+    fn find_matching_policy(&self, device_info: &DeviceInfo) -> Option<usize> {
+        self.candidates(device_info)
+            .filter(|(_, specificity)| *specificity != Specificity::Any)
+            .min_by_key(|(_, specificity)| *specificity)
+            .map(|(index, _)| index)
+    }
+
+    /// Find the default "*" policy
+    fn find_default_policy(&self) -> Option<usize> {
+        self.filters.iter().position(|filter| {
+            filter
+                .as_ref()
+                .is_some_and(|filter| filter.specificity() == Specificity::Any)
+        })
+    }
+
+    /// Index of the policy governing a device: the most specific match or
+    /// the default
+    fn governing(&self, device_info: &DeviceInfo) -> Option<usize> {
+        self.find_matching_policy(device_info)
+            .or_else(|| self.find_default_policy())
+    }
+
+    /// The policy governing a device
+    fn policy_for(&self, device_info: &DeviceInfo) -> Option<&DevicePolicy> {
+        self.governing(device_info)
+            .map(|index| &self.policies[index])
+    }
+}
+
+/// How the policy engine reached a decision (see [`PolicyEngine::explain`])
+#[derive(Debug, Clone)]
+pub struct PolicyExplanation {
+    /// Policies whose filter matches the device (indexes into `device_policies`)
+    pub candidates: Vec<usize>,
+    /// Policy governing the device (None = no policy applies)
+    pub policy: Option<usize>,
+    /// Checks in the order they ran
+    pub checks: Vec<PolicyCheck>,
+    /// Outcome: the first failed check denies access
+    pub decision: PolicyDecision,
+}
+
+/// One check behind a policy decision
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyCheck {
+    /// Setting that was checked, e.g. "allowed_clients"
+    pub setting: &'static str,
+    /// Whether the check let the attach through
+    pub passed: bool,
+    /// What the check found
+    pub detail: String,
+    /// Why the check denies access (None = passed)
+    pub denial: Option<PolicyDenialReason>,
+}
+
+impl PolicyExplanation {
+    fn pass(&mut self, setting: &'static str, detail: impl Into<String>) {
+        self.checks.push(PolicyCheck {
+            setting,
+            passed: true,
+            detail: detail.into(),
+            denial: None,
+        });
+    }
+
+    fn fail(
+        &mut self,
+        setting: &'static str,
+        detail: impl Into<String>,
+        reason: PolicyDenialReason,
+    ) {
+        self.checks.push(PolicyCheck {
+            setting,
+            passed: false,
+            detail: detail.into(),
+            denial: Some(reason.clone()),
+        });
+        if self.decision == PolicyDecision::Allow {
+            self.decision = PolicyDecision::Deny(reason);
+        }
+    }
 }
 
 /// Event emitted when a session expires
@@ -136,26 +442,218 @@ pub enum SessionExpiredReason {
     /// Session duration limit reached
     DurationLimitReached,
     /// Time window expired
-    TimeWindowExpired,
+    TimeWindowExpired {
+        /// Closing time in the policy's time zone
+        current_time: String,
+        /// When the policy next allows access
+        next_window: Option<String>,
+    },
+    /// A usage quota ran out
+    QuotaExceeded {
+        /// The quota that ran out
+        quota: QuotaUsage,
+    },
+    /// Another client's reservation of the device started
+    Reserved {
+        /// The reservation that took the device
+        reservation: Reservation,
+    },
 }
 
 impl PolicyEngine {
     /// Create a new policy engine with the given policies
     pub fn new(policies: Vec<DevicePolicy>) -> Self {
         Self {
-            policies,
+            policies: std::sync::RwLock::new(Arc::new(PolicySet::new(policies))),
             active_sessions: Arc::new(Mutex::new(HashMap::new())),
             session_expired_tx: None,
-            timezone_offset_hours: 0,
+            timezone: std::sync::RwLock::new(TimeZone::UTC),
+            client_scopes: std::sync::RwLock::new(HashMap::new()),
+            directory: Arc::new(ClientDirectory::default()),
+            quota_limits: std::sync::RwLock::new(Arc::new(QuotaLimits::default())),
+            quotas: QuotaLedger::in_memory(),
+            reservation_limits: std::sync::RwLock::new(ReservationSettings::default()),
+            reservations: Arc::new(ReservationBook::in_memory()),
         }
     }
 
-    /// Set the timezone offset for time window calculations
-    pub fn with_timezone_offset(mut self, hours: i32) -> Self {
-        self.timezone_offset_hours = hours;
+    /// Resolve client names and `@group` references with `directory`
+    ///
+    /// Set this before the client scopes, whose keys may be names or groups.
+    pub fn with_client_directory(mut self, directory: SharedClientDirectory) -> Self {
+        self.directory = directory;
         self
     }
 
+    /// Limit clients to devices matching their filters
+    ///
+    /// Keys are EndpointIds, client names or `@group` references as in
+    /// `security.client_devices`; entries that do not resolve are skipped. A
+    /// client listed more than once gets the filters of its last entry.
+    pub fn with_client_scopes<'a>(
+        self,
+        scopes: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+    ) -> Self {
+        self.set_client_scopes(scopes);
+        self
+    }
+
+    /// Set the scope of each client in `scopes`
+    fn set_client_scopes<'a>(
+        &self,
+        scopes: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+    ) {
+        for (client, filters) in scopes {
+            match self.directory.resolve(client) {
+                Ok(client_ids) => {
+                    for client_id in client_ids {
+                        self.set_client_scope(client_id, filters);
+                    }
+                }
+                Err(e) => warn!("Ignoring device scope of '{}': {:#}", client, e),
+            }
+        }
+    }
+
+    /// Limit a client to devices matching any of `filters`
+    ///
+    /// An empty list lifts the limit. Filters that do not parse are skipped,
+    /// so a scope never grows because of a typo.
+    pub fn set_client_scope(&self, client_id: EndpointId, filters: &[String]) {
+        let mut scopes = self.client_scopes.write().unwrap();
+        if filters.is_empty() {
+            scopes.remove(&client_id);
+            return;
+        }
+
+        let parsed = filters
+            .iter()
+            .filter_map(|filter| {
+                DeviceFilter::parse(filter)
+                    .inspect_err(|e| warn!("Ignoring client device scope: {}", e))
+                    .ok()
+            })
+            .collect();
+        scopes.insert(client_id, parsed);
+    }
+
+    /// Set the time zone of time windows that name none
+    ///
+    /// Quota days and months also follow this time zone.
+    pub fn with_timezone(self, timezone: TimeZone) -> Self {
+        *self.timezone.write().unwrap() = timezone;
+        self
+    }
+
+    /// Enforce the data quotas of `settings`, counting usage in `ledger`
+    ///
+    /// Time quotas come from the device policies and are counted in the
+    /// same ledger.
+    pub fn with_quotas(mut self, settings: &QuotaSettings, ledger: QuotaLedger) -> Self {
+        *self.quota_limits.write().unwrap() = Arc::new(QuotaLimits::new(settings));
+        self.quotas = ledger;
+        self
+    }
+
+    /// Enforce the reservations in `book`, limiting new ones by `settings`
+    pub fn with_reservations(
+        mut self,
+        settings: &ReservationSettings,
+        book: SharedReservationBook,
+    ) -> Self {
+        *self.reservation_limits.write().unwrap() = settings.clone();
+        self.reservations = book;
+        self
+    }
+
+    /// Replace the policies, time zone, client scopes, data quotas and
+    /// reservation limits
+    ///
+    /// Limits of active sessions are recalculated under the new rules.
+    /// Sessions the new rules forbid outright are left alone here; see
+    /// [`PolicyEngine::revoked_sessions`]. Usage counted so far and
+    /// existing reservations are kept.
+    pub async fn reload(
+        &self,
+        policies: Vec<DevicePolicy>,
+        timezone: TimeZone,
+        client_scopes: &BTreeMap<String, Vec<String>>,
+        quotas: &QuotaSettings,
+        reservations: &ReservationSettings,
+    ) {
+        *self.policies.write().unwrap() = Arc::new(PolicySet::new(policies));
+        *self.timezone.write().unwrap() = timezone;
+        *self.quota_limits.write().unwrap() = Arc::new(QuotaLimits::new(quotas));
+        *self.reservation_limits.write().unwrap() = reservations.clone();
+
+        self.client_scopes.write().unwrap().clear();
+        self.set_client_scopes(client_scopes);
+
+        let mut sessions = self.active_sessions.lock().await;
+        for session in sessions.values_mut() {
+            let limits = self.session_limits(&session.device_info);
+            session.max_duration = limits.max_duration;
+            session.window_expires_at = limits.window_expires_at;
+            session.window_close = limits.window_close;
+            session.time_quota = limits.time_quota;
+        }
+        self.account_time(&mut sessions);
+        self.schedule_reservations(&mut sessions);
+        info!(
+            "Policies reloaded: {} device policies, {} active sessions",
+            self.policy_set().policies.len(),
+            sessions.len()
+        );
+    }
+
+    /// Active sessions of `client_id` the current rules no longer allow
+    ///
+    /// Returns each session with the reason a new attach would be denied.
+    pub async fn revoked_sessions(
+        &self,
+        client_id: &EndpointId,
+    ) -> Vec<(ActiveSession, PolicyDenialReason)> {
+        let sessions = self.active_sessions.lock().await;
+        sessions
+            .values()
+            .filter(|session| session.client_id == *client_id)
+            .filter_map(|session| {
+                let decision = match &session.interfaces {
+                    Some(interfaces) => {
+                        self.check_interface_access(client_id, &session.device_info, interfaces)
+                    }
+                    None => self.check_access(client_id, &session.device_info),
+                };
+                match decision {
+                    PolicyDecision::Allow => None,
+                    PolicyDecision::Deny(reason) => Some((session.clone(), reason)),
+                }
+            })
+            .collect()
+    }
+
+    /// Snapshot of the current policies
+    fn policy_set(&self) -> Arc<PolicySet> {
+        self.policies.read().unwrap().clone()
+    }
+
+    fn timezone(&self) -> TimeZone {
+        self.timezone.read().unwrap().clone()
+    }
+
+    /// The current time in the policy time zone, which quota periods follow
+    fn now(&self) -> Zoned {
+        Timestamp::now().to_zoned(self.timezone())
+    }
+
+    /// Data limits of a client
+    fn data_limits(&self, client_id: &EndpointId) -> DataLimits {
+        self.quota_limits
+            .read()
+            .unwrap()
+            .for_client(&self.directory, client_id)
+    }
+
     /// Set the channel for session expiration events
     pub fn with_expiration_channel(
         mut self,
@@ -168,275 +666,675 @@ impl PolicyEngine {
     /// Check if a client can access a device
     ///
     /// This is the main policy enforcement function called on attach requests.
+    /// Attaching the whole device claims all of its interfaces.
     pub fn check_access(&self, client_id: &EndpointId, device_info: &DeviceInfo) -> PolicyDecision {
-        let client_str = client_id.to_string();
-
-        // Find matching policy for this device
-        let matching_policy = self.find_matching_policy(device_info);
+        let interfaces: Vec<u8> = device_info.interfaces.iter().map(|i| i.number).collect();
+        self.check_interface_access(client_id, device_info, &interfaces)
+    }
 
-        match matching_policy {
-            Some(policy) => self.evaluate_policy(policy, &client_str, device_info),
-            None => {
-                // No matching policy - check if we have a default "*" policy
-                if let Some(default_policy) = self.find_default_policy() {
-                    self.evaluate_policy(default_policy, &client_str, device_info)
-                } else {
-                    // No policies at all means allow all (backward compatible)
-                    if self.policies.is_empty() {
-                        PolicyDecision::Allow
-                    } else {
-                        PolicyDecision::Deny(PolicyDenialReason::NoMatchingPolicy)
-                    }
-                }
-            }
-        }
+    /// Check if a client can claim some interfaces of a device
+    ///
+    /// Called on `AttachInterfacesRequest`; device-level rules apply as for
+    /// a whole-device attach.
+    pub fn check_interface_access(
+        &self,
+        client_id: &EndpointId,
+        device_info: &DeviceInfo,
+        interfaces: &[u8],
+    ) -> PolicyDecision {
+        self.explain(client_id, device_info, interfaces, Timestamp::now())
+            .decision
     }
 
-    /// Find the most specific policy matching a device
-    fn find_matching_policy(&self, device_info: &DeviceInfo) -> Option<&DevicePolicy> {
-        let device_filter = format!(
-            "{:04x}:{:04x}",
-            device_info.vendor_id, device_info.product_id
-        );
+    /// Decide whether a client can claim some interfaces of a device at
+    /// `at`, recording every check
+    ///
+    /// Unlike an attach, evaluation continues past the first failed check
+    /// so all problems show; the decision is still the first failure.
+    pub fn explain(
+        &self,
+        client_id: &EndpointId,
+        device_info: &DeviceInfo,
+        interfaces: &[u8],
+        at: Timestamp,
+    ) -> PolicyExplanation {
+        let policy_set = self.policy_set();
+        let mut explanation = PolicyExplanation {
+            candidates: policy_set
+                .candidates(device_info)
+                .map(|(index, _)| index)
+                .collect(),
+            policy: policy_set.governing(device_info),
+            checks: Vec::new(),
+            decision: PolicyDecision::Allow,
+        };
 
-        // First, try exact VID:PID match
-        for policy in &self.policies {
-            if Self::filter_matches_exact(&policy.device_filter, &device_filter) {
-                return Some(policy);
+        // Clients enrolled for some devices never see the others
+        if let Some(scope) = self.client_scopes.read().unwrap().get(client_id) {
+            let filters: Vec<String> = scope.iter().map(ToString::to_string).collect();
+            if scope.iter().any(|filter| filter.matches(device_info)) {
+                explanation.pass(
+                    "client_devices",
+                    format!(
+                        "Device is within the client's scope [{}]",
+                        filters.join(", ")
+                    ),
+                );
+            } else {
+                explanation.fail(
+                    "client_devices",
+                    format!("Client is limited to [{}]", filters.join(", ")),
+                    PolicyDenialReason::OutsideClientScope,
+                );
             }
         }
 
-        // Then try VID:* match
-        let vid_wildcard = format!("{:04x}:*", device_info.vendor_id);
-        for policy in &self.policies {
-            if policy.device_filter == vid_wildcard {
-                return Some(policy);
+        match explanation.policy {
+            Some(index) => {
+                let policy = &policy_set.policies[index];
+                let default = policy_set.find_matching_policy(device_info).is_none();
+                explanation.pass(
+                    "device_policies",
+                    format!(
+                        "Policy #{} '{}' governs the device ({})",
+                        index + 1,
+                        policy.device_filter,
+                        if default {
+                            "default policy"
+                        } else {
+                            "most specific match"
+                        }
+                    ),
+                );
+                self.evaluate_policy(
+                    policy,
+                    client_id,
+                    device_info,
+                    interfaces,
+                    at,
+                    &mut explanation,
+                );
             }
+            // No policies at all means allow all (backward compatible)
+            None if policy_set.policies.is_empty() => explanation.pass(
+                "device_policies",
+                "No device policies: every client may attach every device",
+            ),
+            None => explanation.fail(
+                "device_policies",
+                "No policy matches the device and there is no default \"*\" policy",
+                PolicyDenialReason::NoMatchingPolicy,
+            ),
         }
 
-        None
-    }
+        // Data quotas count every device, whichever policy governs this one
+        let now = at.to_zoned(self.timezone());
+        let limits = self.data_limits(client_id);
+        for quota in self.quotas.data_usage(client_id, limits, &now) {
+            let setting = match quota.kind {
+                QuotaKind::MonthlyData => "quotas.monthly_data",
+                _ => "quotas.daily_data",
+            };
+            Self::check_quota(setting, quota, &mut explanation);
+        }
 
-    /// Find the default "*" policy
-    fn find_default_policy(&self) -> Option<&DevicePolicy> {
-        self.policies.iter().find(|p| p.device_filter == "*")
+        // A device another client has booked is theirs alone
+        let secs = at.as_second().max(0) as u64;
+        if let Some(reservation) = self.reservations.active(device_info.id, secs) {
+            let reservation = self.named(reservation);
+            let until = self.local_time(reservation.ends_at);
+            if reservation.holder == client_id.to_string() {
+                explanation.pass(
+                    "reservations",
+                    format!(
+                        "Client holds reservation {} until {}",
+                        reservation.id.0, until
+                    ),
+                );
+            } else {
+                explanation.fail(
+                    "reservations",
+                    format!(
+                        "Reservation {} by {} until {}",
+                        reservation.id.0,
+                        reservation.holder_label(),
+                        until
+                    ),
+                    PolicyDenialReason::Reserved { reservation, until },
+                );
+            }
+        }
+        explanation
     }
 
-    /// Check if a filter matches a device exactly (VID:PID)
-    fn filter_matches_exact(filter: &str, device: &str) -> bool {
-        if filter == "*" {
-            return false; // Handled separately as default
+    /// Record whether a quota still has some left
+    fn check_quota(setting: &'static str, quota: QuotaUsage, explanation: &mut PolicyExplanation) {
+        if quota.is_exhausted() {
+            explanation.fail(
+                setting,
+                format!("Used up: {}", quota),
+                PolicyDenialReason::QuotaExceeded { quota },
+            );
+        } else {
+            explanation.pass(setting, quota.to_string());
         }
-        filter.to_lowercase() == device.to_lowercase()
     }
 
-    /// Evaluate a policy against client and device
+    /// Evaluate a policy against client and device at `at`
     fn evaluate_policy(
         &self,
         policy: &DevicePolicy,
-        client_str: &str,
+        client_id: &EndpointId,
         device_info: &DeviceInfo,
-    ) -> PolicyDecision {
+        interfaces: &[u8],
+        at: Timestamp,
+        explanation: &mut PolicyExplanation,
+    ) {
         // Check client allowlist
-        if !self.is_client_allowed(policy, client_str) {
-            return PolicyDecision::Deny(PolicyDenialReason::ClientNotAllowed);
+        match self.allowing_entry(policy, client_id) {
+            Some(entry) => {
+                explanation.pass("allowed_clients", format!("Client matches '{}'", entry))
+            }
+            None => explanation.fail(
+                "allowed_clients",
+                format!(
+                    "Client matches none of [{}]",
+                    policy.allowed_clients.join(", ")
+                ),
+                PolicyDenialReason::ClientNotAllowed,
+            ),
         }
 
         // Check device class restrictions
         if let Some(ref restricted_classes) = policy.restricted_device_classes {
-            if restricted_classes.contains(&device_info.class) {
-                return PolicyDecision::Deny(PolicyDenialReason::DeviceClassRestricted {
-                    device_class: device_info.class,
-                });
+            let class = device_info.class;
+            if restricted_classes.contains(&class) {
+                explanation.fail(
+                    "restricted_device_classes",
+                    format!("Device class 0x{:02x} is restricted", class),
+                    PolicyDenialReason::DeviceClassRestricted {
+                        device_class: class,
+                    },
+                );
+            } else {
+                explanation.pass(
+                    "restricted_device_classes",
+                    format!("Device class 0x{:02x} is not restricted", class),
+                );
             }
         }
 
-        // Check time windows
-        if let Some(ref time_windows) = policy.time_windows {
-            if !time_windows.is_empty() {
-                let (in_window, current_time) = self.is_within_time_window(time_windows);
-                if !in_window {
-                    return PolicyDecision::Deny(PolicyDenialReason::OutsideTimeWindow {
-                        current_time,
-                        allowed_windows: time_windows.clone(),
-                    });
+        // Check interface restrictions on the claimed interfaces
+        let claimed = device_info
+            .interfaces
+            .iter()
+            .filter(|i| interfaces.contains(&i.number));
+        if policy.allowed_interfaces.is_some() || policy.restricted_interface_classes.is_some() {
+            for interface in claimed {
+                let (number, class) = (interface.number, interface.class);
+                if policy
+                    .allowed_interfaces
+                    .as_ref()
+                    .is_some_and(|allowed| !allowed.contains(&number))
+                {
+                    explanation.fail(
+                        "allowed_interfaces",
+                        format!("Interface {} is not allowed", number),
+                        PolicyDenialReason::InterfaceNotAllowed { interface: number },
+                    );
+                } else if policy
+                    .restricted_interface_classes
+                    .as_ref()
+                    .is_some_and(|restricted| restricted.contains(&class))
+                {
+                    explanation.fail(
+                        "restricted_interface_classes",
+                        format!("Interface {} has restricted class 0x{:02x}", number, class),
+                        PolicyDenialReason::InterfaceClassRestricted {
+                            interface: number,
+                            interface_class: class,
+                        },
+                    );
+                } else {
+                    explanation.pass(
+                        "allowed_interfaces",
+                        format!(
+                            "Interface {} (class 0x{:02x}) may be claimed",
+                            number, class
+                        ),
+                    );
                 }
             }
         }
 
-        PolicyDecision::Allow
-    }
+        // Check time windows and blackout dates
+        if let Some(schedule) = Self::schedule(policy) {
+            let timezone = self.timezone();
+            let local_time = schedule.local_time(at, &timezone);
+            if schedule.is_open(at, &timezone) {
+                let detail = match schedule.closes_at(at, &timezone) {
+                    Some(closes_at) => format!(
+                        "Open at {} until {}",
+                        local_time,
+                        schedule.local_time(closes_at, &timezone)
+                    ),
+                    None => format!("Open at {}", local_time),
+                };
+                explanation.pass("time_windows", detail);
+            } else {
+                let detail = match schedule.next_opening(at, &timezone) {
+                    Some(next) => format!("Closed at {}, opens {}", local_time, next),
+                    None => format!("Closed at {}, does not open within a year", local_time),
+                };
+                explanation.fail(
+                    "time_windows",
+                    detail,
+                    PolicyDenialReason::OutsideTimeWindow {
+                        current_time: local_time,
+                        allowed_windows: policy.time_windows.clone().unwrap_or_default(),
+                    },
+                );
+            }
+        }
 
-    /// Check if a client is allowed by the policy
-    fn is_client_allowed(&self, policy: &DevicePolicy, client_str: &str) -> bool {
-        // Wildcard allows all
-        if policy.allowed_clients.iter().any(|c| c == "*") {
-            return true;
+        // Check the attach time left today
+        if let Some(limit) = policy.daily_time_quota {
+            let now = at.to_zoned(self.timezone());
+            let quota = self
+                .quotas
+                .time_usage(client_id, device_info.id, limit.as_secs(), &now);
+            Self::check_quota("daily_time_quota", quota, explanation);
         }
+    }
 
-        // Check if client is in the list (case-insensitive for hex EndpointIds)
+    /// The `allowed_clients` entry that admits a client (None = not allowed)
+    ///
+    /// Entries are "*", EndpointIds, client names or `@group` references.
+    fn allowing_entry<'a>(
+        &self,
+        policy: &'a DevicePolicy,
+        client_id: &EndpointId,
+    ) -> Option<&'a str> {
         policy
             .allowed_clients
             .iter()
-            .any(|c| c.eq_ignore_ascii_case(client_str))
+            .find(|entry| self.directory.matches(entry, client_id))
+            .map(String::as_str)
     }
 
-    /// Check if current time is within any of the time windows
+    /// Time windows and blackout dates of a policy (None = no restriction)
     ///
-    /// Returns (is_in_window, current_time_string)
-    fn is_within_time_window(&self, windows: &[String]) -> (bool, String) {
-        let now = Self::get_current_time_with_offset(self.timezone_offset_hours);
-        let current_time_str = format!("{:02}:{:02}", now.0, now.1);
-
-        for window in windows {
-            if let Some((start, end)) = Self::parse_time_window(window) {
-                if Self::time_in_range(now, start, end) {
-                    return (true, current_time_str);
-                }
-            }
+    /// Both are validated at config load; a policy whose schedule does not
+    /// parse never allows access.
+    fn schedule(policy: &DevicePolicy) -> Option<Schedule> {
+        let windows = policy.time_windows.as_deref().unwrap_or_default();
+        if windows.is_empty() && policy.blackout_dates.is_empty() {
+            return None;
         }
-
-        (false, current_time_str)
+        Some(
+            Schedule::new(windows, &policy.blackout_dates).unwrap_or_else(|e| {
+                warn!("Ignoring time windows of device policy: {:#}", e);
+                Schedule::closed()
+            }),
+        )
     }
 
-    /// Get current time (hours, minutes) with timezone offset
-    fn get_current_time_with_offset(offset_hours: i32) -> (u8, u8) {
-        use std::time::{SystemTime, UNIX_EPOCH};
-
-        let duration = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default();
-        let secs = duration.as_secs() as i64;
-
-        // Apply timezone offset
-        let secs_with_offset = secs + (offset_hours as i64 * 3600);
+    /// When the time window open now closes, and how that looks to the client
+    fn window_limit(&self, schedule: &Schedule) -> Option<(Instant, WindowClose)> {
+        let now = Timestamp::now();
+        let timezone = self.timezone();
+        let closes_at = schedule.closes_at(now, &timezone)?;
+        let remaining = Duration::try_from(closes_at.duration_since(now)).unwrap_or_default();
+        let close = WindowClose {
+            local_time: schedule.local_time(closes_at, &timezone),
+            next_window: schedule.next_opening(closes_at, &timezone),
+        };
+        Some((Instant::now() + remaining, close))
+    }
 
-        // Calculate hours and minutes
-        let secs_today = secs_with_offset.rem_euclid(86400);
-        let hours = (secs_today / 3600) as u8;
-        let minutes = ((secs_today % 3600) / 60) as u8;
+    /// Get the session duration limit from a matching policy
+    pub fn get_session_duration_limit(&self, device_info: &DeviceInfo) -> Option<Duration> {
+        self.policy_set()
+            .policy_for(device_info)?
+            .max_session_duration
+    }
 
-        (hours, minutes)
+    /// Duration limit, time window end and time quota for a session
+    /// starting now
+    fn session_limits(&self, device_info: &DeviceInfo) -> SessionLimits {
+        let policy_set = self.policy_set();
+        let Some(policy) = policy_set.policy_for(device_info) else {
+            return SessionLimits::default();
+        };
+        let window = Self::schedule(policy).and_then(|schedule| self.window_limit(&schedule));
+        let (window_expires_at, window_close) = window.unzip();
+        SessionLimits {
+            max_duration: policy.max_session_duration,
+            window_expires_at,
+            window_close,
+            time_quota: policy.daily_time_quota,
+        }
     }
 
-    /// Parse a time window string like "09:00-17:00"
-    fn parse_time_window(window: &str) -> Option<((u8, u8), (u8, u8))> {
-        let parts: Vec<&str> = window.split('-').collect();
-        if parts.len() != 2 {
-            return None;
+    /// Count the attach time of sessions with a time quota since they were
+    /// last counted, and move their quota ends accordingly
+    ///
+    /// A new day resets the time used, so a session can outlast the quota
+    /// end it had the evening before.
+    fn account_time(&self, sessions: &mut HashMap<DeviceHandle, ActiveSession>) {
+        let now = self.now();
+        let instant = Instant::now();
+        for session in sessions.values_mut() {
+            let Some(limit) = session.time_quota else {
+                session.quota_end = None;
+                continue;
+            };
+            let secs = instant.duration_since(session.accounted_at).as_secs();
+            self.quotas
+                .add_time(&session.client_id, session.device_id, secs, &now);
+            // Keep the fraction of a second for the next round
+            session.accounted_at += Duration::from_secs(secs);
+
+            let quota = self.quotas.time_usage(
+                &session.client_id,
+                session.device_id,
+                limit.as_secs(),
+                &now,
+            );
+            let ends_at = instant + Duration::from_secs(quota.remaining());
+            session.quota_end = Some((
+                ends_at,
+                QuotaUsage {
+                    used: quota.limit,
+                    ..quota
+                },
+            ));
         }
+    }
 
-        let start = Self::parse_time(parts[0])?;
-        let end = Self::parse_time(parts[1])?;
+    /// Data quota of a client that is used up, if any
+    fn exhausted_data_quota(&self, client_id: &EndpointId, now: &Zoned) -> Option<QuotaUsage> {
+        let limits = self.data_limits(client_id);
+        self.quotas
+            .data_usage(client_id, limits, now)
+            .into_iter()
+            .find(QuotaUsage::is_exhausted)
+    }
 
-        Some((start, end))
+    /// Count bytes a client transferred against its data quotas
+    ///
+    /// Clients without data limits are not counted.
+    pub fn record_data(&self, client_id: &EndpointId, bytes: u64) {
+        if !self.data_limits(client_id).is_unlimited() {
+            self.quotas.add_data(client_id, bytes, &self.now());
+        }
     }
 
-    /// Parse a time string like "09:00" into (hours, minutes)
-    fn parse_time(time: &str) -> Option<(u8, u8)> {
-        let parts: Vec<&str> = time.trim().split(':').collect();
-        if parts.len() != 2 {
-            return None;
+    /// Usage of the quotas that apply to a client
+    ///
+    /// Data quotas, then the time quota of each attached device that has one.
+    pub async fn quota_status(&self, client_id: &EndpointId) -> Vec<QuotaUsage> {
+        let mut sessions = self.active_sessions.lock().await;
+        self.account_time(&mut sessions);
+
+        let now = self.now();
+        let mut quotas = self
+            .quotas
+            .data_usage(client_id, self.data_limits(client_id), &now);
+        for session in sessions.values() {
+            let Some(limit) = session.time_quota else {
+                continue;
+            };
+            if session.client_id != *client_id
+                || quotas
+                    .iter()
+                    .any(|quota| quota.device_id == Some(session.device_id))
+            {
+                continue;
+            }
+            quotas.push(self.quotas.time_usage(
+                client_id,
+                session.device_id,
+                limit.as_secs(),
+                &now,
+            ));
         }
+        quotas
+    }
 
-        let hours: u8 = parts[0].parse().ok()?;
-        let minutes: u8 = parts[1].parse().ok()?;
+    /// Save quota usage if it changed
+    pub fn save_quotas(&self) {
+        if let Err(e) = self.quotas.save_if_dirty() {
+            warn!("Failed to save quota usage: {:#}", e);
+        }
+    }
 
-        if hours > 23 || minutes > 59 {
-            return None;
+    /// Note when another client's reservation of each session's device
+    /// starts
+    ///
+    /// A session keeps the start it has while the reservation stays the
+    /// same, so its client is warned once.
+    fn schedule_reservations(&self, sessions: &mut HashMap<DeviceHandle, ActiveSession>) {
+        let now = reservation::unix_now();
+        let instant = Instant::now();
+        for session in sessions.values_mut() {
+            let next =
+                self.reservations
+                    .next_for_others(session.device_id, &session.client_id, now);
+            let unchanged = match (&session.reserved_from, &next) {
+                (Some((_, current)), Some(next)) => {
+                    current.id == next.id && current.starts_at == next.starts_at
+                }
+                (None, None) => true,
+                _ => false,
+            };
+            if !unchanged {
+                session.reserved_from = next.map(|reservation| {
+                    let wait = reservation.starts_at.saturating_sub(now);
+                    (instant + Duration::from_secs(wait), self.named(reservation))
+                });
+            }
         }
+    }
 
-        Some((hours, minutes))
+    /// A reservation with its holder's name filled in
+    fn named(&self, mut reservation: Reservation) -> Reservation {
+        reservation.holder_name = self.directory.name_of(&reservation.holder);
+        reservation
     }
 
-    /// Check if a time is within a range (handles overnight ranges)
-    fn time_in_range(time: (u8, u8), start: (u8, u8), end: (u8, u8)) -> bool {
-        let time_mins = time.0 as u16 * 60 + time.1 as u16;
-        let start_mins = start.0 as u16 * 60 + start.1 as u16;
-        let end_mins = end.0 as u16 * 60 + end.1 as u16;
+    /// A Unix time as shown to clients, in the policy time zone
+    pub fn local_time(&self, unix_secs: u64) -> String {
+        let at = Timestamp::from_second(unix_secs.try_into().unwrap_or(i64::MAX))
+            .unwrap_or(Timestamp::MAX);
+        quota::describe(&at.to_zoned(self.timezone()))
+    }
 
-        if start_mins <= end_mins {
-            // Normal range (e.g., 09:00-17:00)
-            time_mins >= start_mins && time_mins < end_mins
-        } else {
-            // Overnight range (e.g., 22:00-06:00)
-            time_mins >= start_mins || time_mins < end_mins
+    /// Reserve a device for a client from `starts_at` to `ends_at`
+    ///
+    /// A start in the past means now. The client must be allowed to use the
+    /// device when the reservation starts, time windows included; its quotas
+    /// are not checked, as they may have reset by then. Sessions of other
+    /// clients on the device are told when it will be taken from them.
+    pub async fn create_reservation(
+        &self,
+        client_id: &EndpointId,
+        device_info: &DeviceInfo,
+        starts_at: u64,
+        ends_at: u64,
+        note: Option<String>,
+    ) -> Result<Reservation, ReservationError> {
+        let now = reservation::unix_now();
+        let starts_at = starts_at.max(now);
+        if ends_at <= now {
+            return Err(ReservationError::InvalidPeriod {
+                reason: "ends in the past".to_string(),
+            });
+        }
+        let limits = self.reservation_limits.read().unwrap().clone();
+        if let Some(max) = limits
+            .max_duration
+            .filter(|max| ends_at.saturating_sub(starts_at) > max.as_secs())
+        {
+            return Err(ReservationError::InvalidPeriod {
+                reason: format!("longer than {}", format_duration(max)),
+            });
+        }
+        if let Some(max) = limits
+            .max_advance
+            .filter(|max| ends_at > now.saturating_add(max.as_secs()))
+        {
+            return Err(ReservationError::InvalidPeriod {
+                reason: format!("ends more than {} ahead", format_duration(max)),
+            });
         }
-    }
 
-    /// Calculate when the current time window expires
-    fn calculate_window_expiry(&self, windows: &[String]) -> Option<Instant> {
-        let now = Self::get_current_time_with_offset(self.timezone_offset_hours);
+        let at = Timestamp::from_second(starts_at.try_into().unwrap_or(i64::MAX))
+            .unwrap_or(Timestamp::MAX);
+        let denial = self
+            .explain(client_id, device_info, &[], at)
+            .checks
+            .into_iter()
+            .filter_map(|check| check.denial)
+            .find(|reason| {
+                !matches!(
+                    reason,
+                    PolicyDenialReason::QuotaExceeded { .. } | PolicyDenialReason::Reserved { .. }
+                )
+            });
+        if let Some(reason) = denial {
+            return Err(ReservationError::NotAllowed {
+                reason: reason.to_string(),
+            });
+        }
 
-        for window in windows {
-            if let Some((start, end)) = Self::parse_time_window(window) {
-                if Self::time_in_range(now, start, end) {
-                    // Calculate duration until end
-                    let now_mins = now.0 as i32 * 60 + now.1 as i32;
-                    let end_mins = end.0 as i32 * 60 + end.1 as i32;
+        let reservation = self
+            .reservations
+            .reserve(client_id, device_info.id, starts_at, ends_at, note)
+            .map_err(|e| match e {
+                ReservationError::Conflict { reservation } => ReservationError::Conflict {
+                    reservation: self.named(reservation),
+                },
+                e => e,
+            })?;
+        info!(
+            "Device {:?} reserved by {} from {} to {} (reservation {})",
+            reservation.device_id,
+            self.directory.label(&reservation.holder),
+            self.local_time(reservation.starts_at),
+            self.local_time(reservation.ends_at),
+            reservation.id.0
+        );
 
-                    let mins_until_end = if end_mins > now_mins {
-                        end_mins - now_mins
-                    } else {
-                        // Overnight: until midnight + after midnight portion
-                        (24 * 60 - now_mins) + end_mins
-                    };
+        let mut sessions = self.active_sessions.lock().await;
+        self.schedule_reservations(&mut sessions);
+        Ok(self.named(reservation))
+    }
 
-                    return Some(Instant::now() + Duration::from_secs(mins_until_end as u64 * 60));
-                }
-            }
-        }
+    /// Cancel a reservation
+    ///
+    /// `by` is the client asking, which must hold the reservation; None is
+    /// the operator.
+    pub async fn cancel_reservation(
+        &self,
+        id: ReservationId,
+        by: Option<&EndpointId>,
+    ) -> Result<Reservation, ReservationError> {
+        let reservation = self.reservations.cancel(id, by)?;
+        info!(
+            "Reservation {} of device {:?} by {} cancelled",
+            id.0,
+            reservation.device_id,
+            self.directory.label(&reservation.holder)
+        );
 
-        None
+        let mut sessions = self.active_sessions.lock().await;
+        self.schedule_reservations(&mut sessions);
+        Ok(self.named(reservation))
     }
 
-    /// Get the session duration limit from a matching policy
-    pub fn get_session_duration_limit(&self, device_info: &DeviceInfo) -> Option<Duration> {
-        let policy = self
-            .find_matching_policy(device_info)
-            .or_else(|| self.find_default_policy())?;
+    /// Current and upcoming reservations, ordered by start
+    ///
+    /// `device_id` limits the list to one device.
+    pub fn reservations(&self, device_id: Option<DeviceId>) -> Vec<Reservation> {
+        self.reservations
+            .list(device_id, reservation::unix_now())
+            .into_iter()
+            .map(|reservation| self.named(reservation))
+            .collect()
+    }
 
-        policy.max_session_duration
+    /// Reservations active now
+    pub fn active_reservations(&self) -> Vec<Reservation> {
+        let now = reservation::unix_now();
+        self.reservations
+            .list(None, now)
+            .into_iter()
+            .filter(|reservation| reservation.is_active_at(now))
+            .map(|reservation| self.named(reservation))
+            .collect()
+    }
+
+    /// The active reservation of a device by a client other than
+    /// `client_id`, if any
+    pub fn reserved_for_other(
+        &self,
+        client_id: &EndpointId,
+        device_id: DeviceId,
+    ) -> Option<Reservation> {
+        self.reservations
+            .active(device_id, reservation::unix_now())
+            .filter(|reservation| reservation.holder != client_id.to_string())
+            .map(|reservation| self.named(reservation))
     }
 
     /// Register an active session for monitoring
+    ///
+    /// `interfaces` are the interfaces claimed, None for the whole device.
     pub async fn register_session(
         &self,
         handle: DeviceHandle,
         device_id: DeviceId,
         device_info: &DeviceInfo,
+        interfaces: Option<Vec<u8>>,
         client_id: EndpointId,
     ) {
-        let max_duration = self.get_session_duration_limit(device_info);
+        let limits = self.session_limits(device_info);
 
-        // Calculate window expiry if time windows are configured
-        let window_expires_at = self
-            .find_matching_policy(device_info)
-            .or_else(|| self.find_default_policy())
-            .and_then(|p| p.time_windows.as_ref())
-            .and_then(|w| self.calculate_window_expiry(w));
+        debug!(
+            "Registering session for handle {:?}: max_duration={:?}, window_expires_at={:?}",
+            handle, limits.max_duration, limits.window_expires_at
+        );
 
+        let now = Instant::now();
         let session = ActiveSession {
             handle,
             device_id,
             client_id,
-            started_at: Instant::now(),
-            max_duration,
-            window_expires_at,
+            device_info: device_info.clone(),
+            interfaces,
+            started_at: now,
+            max_duration: limits.max_duration,
+            window_expires_at: limits.window_expires_at,
+            window_close: limits.window_close,
+            time_quota: limits.time_quota,
+            accounted_at: now,
+            quota_end: None,
+            reserved_from: None,
         };
 
-        debug!(
-            "Registering session for handle {:?}: max_duration={:?}, window_expires_at={:?}",
-            handle, max_duration, window_expires_at
-        );
-
         let mut sessions = self.active_sessions.lock().await;
         sessions.insert(handle, session);
+        self.account_time(&mut sessions);
+        self.schedule_reservations(&mut sessions);
     }
 
     /// Unregister a session (called on detach)
+    ///
+    /// Attach time not yet counted against a time quota is counted now.
     pub async fn unregister_session(&self, handle: DeviceHandle) {
         let mut sessions = self.active_sessions.lock().await;
+        self.account_time(&mut sessions);
         if sessions.remove(&handle).is_some() {
             debug!("Unregistered session for handle {:?}", handle);
         }
@@ -444,47 +1342,70 @@ impl PolicyEngine {
 
     /// Check all active sessions for expiration
     ///
-    /// Returns list of expired sessions that need to be force-detached.
+    /// Counts attach time against time quotas and looks up reservations
+    /// first. Every session of a
+    /// client whose data quota is used up expires at once. Returns list of
+    /// expired sessions that need to be force-detached.
     pub async fn check_expired_sessions(&self) -> Vec<SessionExpiredEvent> {
         let now = Instant::now();
-        let mut expired = Vec::new();
+        let zoned = self.now();
+        let mut sessions = self.active_sessions.lock().await;
+        self.account_time(&mut sessions);
+        self.schedule_reservations(&mut sessions);
+
+        sessions
+            .values()
+            .filter_map(|session| {
+                let reason = match self.exhausted_data_quota(&session.client_id, &zoned) {
+                    Some(quota) => SessionExpiredReason::QuotaExceeded { quota },
+                    None => {
+                        let (expires_at, reason) = session.expiry()?;
+                        (now >= expires_at).then_some(reason)?
+                    }
+                };
+                Some(SessionExpiredEvent {
+                    handle: session.handle,
+                    device_id: session.device_id,
+                    client_id: session.client_id,
+                    reason,
+                })
+            })
+            .collect()
+    }
 
+    /// Sessions of `client_id` that expire within `lead` but have not yet
+    ///
+    /// Connections warn their client about these with a countdown.
+    pub async fn expiring_sessions(
+        &self,
+        client_id: &EndpointId,
+        lead: Duration,
+    ) -> Vec<SessionExpiry> {
+        let now = Instant::now();
         let sessions = self.active_sessions.lock().await;
 
-        for (handle, session) in sessions.iter() {
-            // Check duration limit
-            if let Some(max_duration) = session.max_duration {
-                if session.started_at.elapsed() >= max_duration {
-                    expired.push(SessionExpiredEvent {
-                        handle: *handle,
-                        device_id: session.device_id,
-                        client_id: session.client_id,
-                        reason: SessionExpiredReason::DurationLimitReached,
-                    });
-                    continue;
-                }
-            }
-
-            // Check time window expiry
-            if let Some(window_expires_at) = session.window_expires_at {
-                if now >= window_expires_at {
-                    expired.push(SessionExpiredEvent {
-                        handle: *handle,
-                        device_id: session.device_id,
-                        client_id: session.client_id,
-                        reason: SessionExpiredReason::TimeWindowExpired,
-                    });
-                }
-            }
-        }
-
-        expired
+        sessions
+            .values()
+            .filter(|session| session.client_id == *client_id)
+            .filter_map(|session| {
+                let (expires_at, reason) = session.expiry()?;
+                (now < expires_at && expires_at <= now + lead).then_some(SessionExpiry {
+                    handle: session.handle,
+                    device_id: session.device_id,
+                    expires_at,
+                    reason,
+                })
+            })
+            .collect()
     }
 
     /// Spawn a background task to monitor session expirations
     ///
     /// The task checks every 30 seconds for expired sessions and sends
-    /// expiration events through the configured channel.
+    /// expiration events through the configured channel. The sessions stay
+    /// registered: the client's connection warns the client, detaches them
+    /// and unregisters them. Quota usage is saved and ended reservations
+    /// are dropped after each check.
     pub fn spawn_expiration_monitor(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(30));
@@ -506,10 +1427,14 @@ impl PolicyEngine {
                             warn!("Failed to send session expiration event");
                         }
                     }
+                }
 
-                    // Remove from active sessions
-                    let mut sessions = self.active_sessions.lock().await;
-                    sessions.remove(&event.handle);
+                self.save_quotas();
+                for reservation in self.reservations.prune(reservation::unix_now()) {
+                    debug!(
+                        "Reservation {} of device {:?} ended",
+                        reservation.id.0, reservation.device_id
+                    );
                 }
             }
         })
@@ -536,6 +1461,15 @@ impl PolicyEngine {
     }
 }
 
+/// Limits of a session starting now
+#[derive(Debug, Default)]
+struct SessionLimits {
+    max_duration: Option<Duration>,
+    window_expires_at: Option<Instant>,
+    window_close: Option<WindowClose>,
+    time_quota: Option<Duration>,
+}
+
 /// Thread-safe wrapper for policy engine
 pub type SharedPolicyEngine = Arc<RwLock<PolicyEngine>>;
 
@@ -563,6 +1497,10 @@ mod tests {
             protocol: 0,
             speed: protocol::DeviceSpeed::High,
             num_configurations: 1,
+            interfaces: Vec::new(),
+            stable_id: format!("{:04x}:{:04x}@1-1", vid, pid),
+            port_numbers: vec![1],
+            parent_hub: None,
         }
     }
 
@@ -591,22 +1529,76 @@ mod tests {
             lock_timeout_secs: 300,
             max_concurrent_clients: 1,
             time_windows: None,
+            blackout_dates: Vec::new(),
             max_session_duration: None,
+            daily_time_quota: None,
             restricted_device_classes: None,
+            allowed_interfaces: None,
+            restricted_interface_classes: None,
         }
     }
 
     #[test]
     fn test_client_allowlist() {
-        let policy = make_policy("*", vec!["client1"]);
+        let engine = PolicyEngine::new(vec![]);
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
 
-        let engine = PolicyEngine::new(vec![policy]);
-        let _device = make_device_info(0x1234, 0x5678, 0);
+        assert!(
+            engine
+                .allowing_entry(&make_policy("*", vec!["*"]), &client_id)
+                .is_some()
+        );
+        assert!(
+            engine
+                .allowing_entry(
+                    &make_policy(
+                        "*",
+                        vec!["AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA"]
+                    ),
+                    &client_id
+                )
+                .is_some()
+        );
+        assert!(
+            engine
+                .allowing_entry(&make_policy("*", vec!["client1"]), &client_id)
+                .is_none()
+        );
+    }
 
-        // For this test, we need to check the string matching logic
-        // Since we can't easily construct an EndpointId that converts to "client1",
-        // let's test the internal method
-        assert!(engine.is_client_allowed(&make_policy("*", vec!["*"]), "any_client"));
+    #[test]
+    fn test_names_and_groups_in_policies() {
+        let alice: EndpointId = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+            .parse()
+            .unwrap();
+        let bob = iroh::SecretKey::generate(&mut rand::rng()).public();
+        let clients = BTreeMap::from([("alice".to_string(), alice.to_string())]);
+        let groups = BTreeMap::from([("lab".to_string(), vec!["alice".to_string()])]);
+        let directory = Arc::new(ClientDirectory::new(&clients, &groups).unwrap());
+        let scopes = BTreeMap::from([("@lab".to_string(), vec!["04f9:*".to_string()])]);
+
+        let engine = PolicyEngine::new(vec![
+            make_policy("04f9:*", vec!["@lab"]),
+            make_policy("046d:*", vec!["alice"]),
+        ])
+        .with_client_directory(directory)
+        .with_client_scopes(&scopes);
+        let printer = make_device_info(0x04f9, 0x0042, 0);
+        let keyboard = make_device_info(0x046d, 0xc31c, 3);
+
+        assert_eq!(engine.check_access(&alice, &printer), PolicyDecision::Allow);
+        assert_eq!(
+            engine.check_access(&bob, &printer),
+            PolicyDecision::Deny(PolicyDenialReason::ClientNotAllowed)
+        );
+        // Allowed by name, but the scope of @lab keeps alice to printers
+        assert_eq!(
+            engine.check_access(&alice, &keyboard),
+            PolicyDecision::Deny(PolicyDenialReason::OutsideClientScope)
+        );
     }
 
     #[test]
@@ -637,8 +1629,12 @@ mod tests {
             lock_timeout_secs: 300,
             max_concurrent_clients: 1,
             time_windows: None,
+            blackout_dates: Vec::new(),
             max_session_duration: None,
+            daily_time_quota: None,
             restricted_device_classes: Some(vec![8]), // Mass storage
+            allowed_interfaces: None,
+            restricted_interface_classes: None,
         };
 
         let engine = PolicyEngine::new(vec![policy]);
@@ -665,40 +1661,240 @@ mod tests {
     }
 
     #[test]
-    fn test_time_parsing() {
-        assert_eq!(PolicyEngine::parse_time("09:00"), Some((9, 0)));
-        assert_eq!(PolicyEngine::parse_time("17:30"), Some((17, 30)));
-        assert_eq!(PolicyEngine::parse_time("00:00"), Some((0, 0)));
-        assert_eq!(PolicyEngine::parse_time("23:59"), Some((23, 59)));
-        assert_eq!(PolicyEngine::parse_time("24:00"), None);
-        assert_eq!(PolicyEngine::parse_time("invalid"), None);
+    fn test_interface_restrictions() {
+        let mut policy = make_policy("*", vec!["*"]);
+        policy.allowed_interfaces = Some(vec![0, 1]);
+        policy.restricted_interface_classes = Some(vec![8]); // Mass storage
+
+        let engine = PolicyEngine::new(vec![policy]);
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+
+        // Keyboard on interface 0, card reader on interface 1
+        let mut dock = common::test_utils::create_mock_composite_device(1);
+        dock.interfaces.push(protocol::InterfaceInfo {
+            number: 2,
+            class: 0x02,
+            subclass: 0x02,
+            protocol: 0x00,
+            num_endpoints: 1,
+        });
+
+        assert_eq!(
+            engine.check_interface_access(&client_id, &dock, &[0]),
+            PolicyDecision::Allow
+        );
+        assert_eq!(
+            engine.check_interface_access(&client_id, &dock, &[0, 1]),
+            PolicyDecision::Deny(PolicyDenialReason::InterfaceClassRestricted {
+                interface: 1,
+                interface_class: 8,
+            })
+        );
+        assert_eq!(
+            engine.check_interface_access(&client_id, &dock, &[2]),
+            PolicyDecision::Deny(PolicyDenialReason::InterfaceNotAllowed { interface: 2 })
+        );
+
+        // The whole device includes the restricted interfaces
+        assert!(matches!(
+            engine.check_access(&client_id, &dock),
+            PolicyDecision::Deny(_)
+        ));
     }
 
     #[test]
-    fn test_time_window_parsing() {
+    fn test_class_filter_matches_interfaces() {
+        let mut storage_policy = make_policy("class:08", vec!["*"]);
+        storage_policy.restricted_device_classes = Some(vec![0]);
+        let engine = PolicyEngine::new(vec![storage_policy, make_policy("*", vec!["*"])]);
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+
+        // Class is defined per interface; interface 1 is mass storage
+        let dock = common::test_utils::create_mock_composite_device(1);
         assert_eq!(
-            PolicyEngine::parse_time_window("09:00-17:00"),
-            Some(((9, 0), (17, 0)))
+            engine.check_access(&client_id, &dock),
+            PolicyDecision::Deny(PolicyDenialReason::DeviceClassRestricted { device_class: 0 })
         );
+
+        // No mass storage interface: falls through to the default policy
+        let printer = make_device_info(0x1234, 0x5678, 7);
         assert_eq!(
-            PolicyEngine::parse_time_window("22:00-06:00"),
-            Some(((22, 0), (6, 0)))
+            engine.check_access(&client_id, &printer),
+            PolicyDecision::Allow
         );
-        assert_eq!(PolicyEngine::parse_time_window("invalid"), None);
     }
 
     #[test]
-    fn test_time_in_range() {
-        // Normal range
-        assert!(PolicyEngine::time_in_range((10, 0), (9, 0), (17, 0)));
-        assert!(PolicyEngine::time_in_range((9, 0), (9, 0), (17, 0)));
-        assert!(!PolicyEngine::time_in_range((17, 0), (9, 0), (17, 0)));
-        assert!(!PolicyEngine::time_in_range((8, 59), (9, 0), (17, 0)));
+    fn test_port_filter_matches() {
+        let hub_policy = make_policy("port:1-1.*", vec![]);
+        let engine = PolicyEngine::new(vec![hub_policy, make_policy("*", vec!["*"])]);
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+
+        let mut on_hub = make_device_info(0x1234, 0x5678, 7);
+        on_hub.port_numbers = vec![1, 4];
+        assert_eq!(
+            engine.check_access(&client_id, &on_hub),
+            PolicyDecision::Deny(PolicyDenialReason::ClientNotAllowed)
+        );
 
-        // Overnight range
-        assert!(PolicyEngine::time_in_range((23, 0), (22, 0), (6, 0)));
-        assert!(PolicyEngine::time_in_range((2, 0), (22, 0), (6, 0)));
-        assert!(!PolicyEngine::time_in_range((10, 0), (22, 0), (6, 0)));
+        let mut elsewhere = make_device_info(0x1234, 0x5678, 7);
+        elsewhere.port_numbers = vec![2];
+        assert_eq!(
+            engine.check_access(&client_id, &elsewhere),
+            PolicyDecision::Allow
+        );
+    }
+
+    #[test]
+    fn test_expression_filter_precedence() {
+        let brother = make_policy("vid:04f9 and not class:07", vec![]);
+        let engine = PolicyEngine::new(vec![
+            brother,
+            make_policy("04f9:0042", vec!["*"]),
+            make_policy("*", vec!["*"]),
+        ]);
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+
+        // Exact VID:PID wins over the earlier expression
+        assert_eq!(
+            engine.check_access(&client_id, &make_device_info(0x04f9, 0x0042, 3)),
+            PolicyDecision::Allow
+        );
+        assert_eq!(
+            engine.check_access(&client_id, &make_device_info(0x04f9, 0x0043, 3)),
+            PolicyDecision::Deny(PolicyDenialReason::ClientNotAllowed)
+        );
+        // Printers are excluded from the expression
+        assert_eq!(
+            engine.check_access(&client_id, &make_device_info(0x04f9, 0x0043, 7)),
+            PolicyDecision::Allow
+        );
+    }
+
+    #[test]
+    fn test_time_windows_and_blackout_dates() {
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+        let helsinki = TimeZone::get("Europe/Helsinki").unwrap();
+        let today = Timestamp::now().to_zoned(helsinki.clone()).date();
+
+        let mut always = make_policy("04f9:*", vec!["*"]);
+        always.time_windows = Some(vec!["Mon-Sun 00:00-24:00".to_string()]);
+        let mut holiday = make_policy("046d:*", vec!["*"]);
+        holiday.blackout_dates = vec![today.to_string()];
+        let engine = PolicyEngine::new(vec![always, holiday]).with_timezone(helsinki);
+
+        let printer = make_device_info(0x04f9, 0x0042, 0);
+        assert_eq!(
+            engine.check_access(&client_id, &printer),
+            PolicyDecision::Allow
+        );
+        match engine.check_access(&client_id, &make_device_info(0x046d, 0xc31c, 3)) {
+            PolicyDecision::Deny(PolicyDenialReason::OutsideTimeWindow {
+                current_time, ..
+            }) => assert!(
+                current_time.contains(&today.to_string()),
+                "{}",
+                current_time
+            ),
+            other => panic!("unexpected decision {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expiring_sessions_are_reported_before_they_expire() {
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+        let mut policy = make_policy("*", vec!["*"]);
+        policy.max_session_duration = Some(Duration::from_secs(120));
+        let engine = PolicyEngine::new(vec![policy]);
+        let device = make_device_info(0x1234, 0x5678, 0);
+        engine
+            .register_session(DeviceHandle(1), DeviceId(1), &device, None, client_id)
+            .await;
+
+        let soon = engine
+            .expiring_sessions(&client_id, Duration::from_secs(300))
+            .await;
+        assert_eq!(soon.len(), 1);
+        assert_eq!(soon[0].handle, DeviceHandle(1));
+        assert_eq!(soon[0].reason, SessionExpiredReason::DurationLimitReached);
+        assert!(
+            engine
+                .expiring_sessions(&client_id, Duration::from_secs(60))
+                .await
+                .is_empty()
+        );
+        assert!(engine.check_expired_sessions().await.is_empty());
+    }
+
+    #[test]
+    fn test_explain_records_every_check() {
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+        let mut vendor = make_policy("04f9:*", vec!["client1"]);
+        vendor.restricted_device_classes = Some(vec![0x08]);
+        vendor.time_windows = Some(vec!["Mon-Fri 09:00-17:00".to_string()]);
+        let engine = PolicyEngine::new(vec![make_policy("*", vec!["*"]), vendor]);
+        let saturday: Timestamp = "2026-10-17T12:00:00Z".parse().unwrap();
+
+        let storage = make_device_info(0x04f9, 0x0042, 0x08);
+        let explanation = engine.explain(&client_id, &storage, &[], saturday);
+        assert_eq!(explanation.candidates, vec![0, 1]);
+        assert_eq!(explanation.policy, Some(1));
+        let checks: Vec<(&str, bool)> = explanation
+            .checks
+            .iter()
+            .map(|check| (check.setting, check.passed))
+            .collect();
+        assert_eq!(
+            checks,
+            vec![
+                ("device_policies", true),
+                ("allowed_clients", false),
+                ("restricted_device_classes", false),
+                ("time_windows", false),
+            ]
+        );
+        assert!(
+            explanation.checks[3]
+                .detail
+                .contains("opens Mon 2026-10-19 09:00"),
+            "{}",
+            explanation.checks[3].detail
+        );
+        // The first failure decides, as on attach
+        assert_eq!(
+            explanation.decision,
+            PolicyDecision::Deny(PolicyDenialReason::ClientNotAllowed)
+        );
+        assert_eq!(
+            engine.check_access(&client_id, &storage),
+            explanation.decision
+        );
+
+        let keyboard = make_device_info(0x046d, 0xc31c, 3);
+        let explanation = engine.explain(&client_id, &keyboard, &[], saturday);
+        assert_eq!(explanation.policy, Some(0));
+        assert_eq!(explanation.decision, PolicyDecision::Allow);
     }
 
     #[test]
@@ -739,8 +1935,12 @@ mod tests {
             lock_timeout_secs: 300,
             max_concurrent_clients: 1,
             time_windows: None,
+            blackout_dates: Vec::new(),
             max_session_duration: Some(Duration::from_secs(3600)),
+            daily_time_quota: None,
             restricted_device_classes: None,
+            allowed_interfaces: None,
+            restricted_interface_classes: None,
         };
 
         let engine = PolicyEngine::new(vec![policy]);
@@ -753,7 +1953,7 @@ mod tests {
         let device_id = DeviceId(1);
 
         engine
-            .register_session(handle, device_id, &device, client_id)
+            .register_session(handle, device_id, &device, None, client_id)
             .await;
 
         assert_eq!(engine.active_session_count().await, 1);
@@ -766,4 +1966,256 @@ mod tests {
         engine.unregister_session(handle).await;
         assert_eq!(engine.active_session_count().await, 0);
     }
+
+    #[test]
+    fn test_client_scope_limits_devices() {
+        let engine = PolicyEngine::new(vec![]);
+        let printer = make_device_info(0x04f9, 0x0042, 0);
+        let keyboard = make_device_info(0x046d, 0xc31c, 3);
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+
+        engine.set_client_scope(client_id, &["04f9:*".to_string()]);
+        assert_eq!(
+            engine.check_access(&client_id, &printer),
+            PolicyDecision::Allow
+        );
+        assert_eq!(
+            engine.check_access(&client_id, &keyboard),
+            PolicyDecision::Deny(PolicyDenialReason::OutsideClientScope)
+        );
+
+        engine.set_client_scope(client_id, &[]);
+        assert_eq!(
+            engine.check_access(&client_id, &keyboard),
+            PolicyDecision::Allow
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reload_revokes_forbidden_sessions() {
+        let engine = PolicyEngine::new(vec![]);
+        let mut printer = make_device_info(0x04f9, 0x0042, 0);
+        let mut keyboard = make_device_info(0x046d, 0xc31c, 3);
+        printer.id = DeviceId(1);
+        keyboard.id = DeviceId(2);
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+
+        engine
+            .register_session(DeviceHandle(1), printer.id, &printer, None, client_id)
+            .await;
+        engine
+            .register_session(DeviceHandle(2), keyboard.id, &keyboard, None, client_id)
+            .await;
+        assert!(engine.revoked_sessions(&client_id).await.is_empty());
+
+        let mut policy = make_policy("04f9:*", vec!["*"]);
+        policy.max_session_duration = Some(Duration::from_secs(60));
+        let scopes = BTreeMap::from([(client_id.to_string(), vec!["04f9:*".to_string()])]);
+        engine
+            .reload(
+                vec![policy],
+                TimeZone::UTC,
+                &scopes,
+                &QuotaSettings::default(),
+                &ReservationSettings::default(),
+            )
+            .await;
+
+        let revoked = engine.revoked_sessions(&client_id).await;
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].0.handle, DeviceHandle(2));
+        assert_eq!(revoked[0].1, PolicyDenialReason::OutsideClientScope);
+
+        // Limits of the surviving session follow the new policy
+        let remaining = engine.get_session_time_remaining(DeviceHandle(1)).await;
+        assert!(remaining.unwrap() <= Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_quotas_deny_attach_and_expire_sessions() {
+        let mut policy = make_policy("*", vec!["*"]);
+        policy.daily_time_quota = Some(Duration::from_secs(3600));
+        let settings = QuotaSettings {
+            daily_data: Some("1MB".to_string()),
+            ..QuotaSettings::default()
+        };
+        let engine =
+            PolicyEngine::new(vec![policy]).with_quotas(&settings, QuotaLedger::in_memory());
+        let device = make_device_info(0x1234, 0x5678, 0);
+        let client_id: EndpointId =
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+                .parse()
+                .unwrap();
+        let handle = DeviceHandle(1);
+
+        // A session ends where the time quota runs out
+        engine
+            .register_session(handle, device.id, &device, None, client_id)
+            .await;
+        let status = engine.quota_status(&client_id).await;
+        let kinds: Vec<QuotaKind> = status.iter().map(|quota| quota.kind).collect();
+        assert_eq!(kinds, vec![QuotaKind::DailyData, QuotaKind::DailyTime]);
+        assert!(status.iter().all(|quota| quota.used < quota.limit));
+        let soon = engine
+            .expiring_sessions(&client_id, Duration::from_secs(3600))
+            .await;
+        assert!(matches!(
+            soon[0].reason,
+            SessionExpiredReason::QuotaExceeded { .. }
+        ));
+
+        // Going over the data quota expires every session of the client
+        engine.record_data(&client_id, 1_500_000);
+        let expired = engine.check_expired_sessions().await;
+        assert_eq!(expired.len(), 1);
+        match &expired[0].reason {
+            SessionExpiredReason::QuotaExceeded { quota } => {
+                assert_eq!(quota.kind, QuotaKind::DailyData);
+                assert_eq!(quota.used, 1_500_000);
+            }
+            reason => panic!("Expected QuotaExceeded, got {:?}", reason),
+        }
+        engine.unregister_session(handle).await;
+
+        // Reconnecting does not help, nor does another device once the
+        // time quota of this one is used up as well
+        let explanation = engine.explain(&client_id, &device, &[], Timestamp::now());
+        assert!(matches!(
+            explanation.decision,
+            PolicyDecision::Deny(PolicyDenialReason::QuotaExceeded { .. })
+        ));
+        let quotas = QuotaSettings::default();
+        engine
+            .reload(
+                engine.policy_set().policies.clone(),
+                TimeZone::UTC,
+                &BTreeMap::new(),
+                &quotas,
+                &ReservationSettings::default(),
+            )
+            .await;
+        assert_eq!(
+            engine.check_access(&client_id, &device),
+            PolicyDecision::Allow
+        );
+        engine
+            .quotas
+            .add_time(&client_id, device.id, 3600, &engine.now());
+        match engine.check_access(&client_id, &device) {
+            PolicyDecision::Deny(PolicyDenialReason::QuotaExceeded { quota }) => {
+                assert_eq!(quota.kind, QuotaKind::DailyTime);
+                assert_eq!(quota.device_id, Some(device.id));
+            }
+            decision => panic!("Expected a time quota denial, got {:?}", decision),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reservations_take_the_device_from_other_clients() {
+        let alice: EndpointId = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+            .parse()
+            .unwrap();
+        let bob: EndpointId = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+            .parse()
+            .unwrap();
+        let carol: EndpointId = "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc"
+            .parse()
+            .unwrap();
+        let policy = make_policy("*", vec![&alice.to_string(), &bob.to_string()]);
+        let settings = ReservationSettings {
+            max_duration: Some(Duration::from_secs(4 * 3600)),
+            ..ReservationSettings::default()
+        };
+        let engine = PolicyEngine::new(vec![policy])
+            .with_reservations(&settings, Arc::new(ReservationBook::in_memory()));
+        let device = make_device_info(0x1234, 0x5678, 0);
+        let now = reservation::unix_now();
+
+        // Bob's session is warned about Alice's reservation before it starts
+        engine
+            .register_session(DeviceHandle(1), device.id, &device, None, bob)
+            .await;
+        let later = engine
+            .create_reservation(&alice, &device, now + 120, now + 3600, None)
+            .await
+            .unwrap();
+        let soon = engine
+            .expiring_sessions(&bob, Duration::from_secs(300))
+            .await;
+        assert_eq!(soon.len(), 1);
+        assert_eq!(
+            soon[0].reason,
+            SessionExpiredReason::Reserved {
+                reservation: later.clone()
+            }
+        );
+        assert_eq!(engine.check_access(&bob, &device), PolicyDecision::Allow);
+
+        // Periods are checked against the limits, other bookings and policy
+        let conflict = engine
+            .create_reservation(&bob, &device, now + 1800, now + 5400, None)
+            .await;
+        assert_eq!(
+            conflict,
+            Err(ReservationError::Conflict {
+                reservation: later.clone()
+            })
+        );
+        assert!(matches!(
+            engine
+                .create_reservation(&bob, &device, now + 3600, now + 6 * 3600, None)
+                .await,
+            Err(ReservationError::InvalidPeriod { .. })
+        ));
+        assert!(matches!(
+            engine
+                .create_reservation(&carol, &device, now + 3600, now + 7200, None)
+                .await,
+            Err(ReservationError::NotAllowed { .. })
+        ));
+
+        // While a reservation is active only its holder gets the device
+        engine
+            .cancel_reservation(later.id, Some(&alice))
+            .await
+            .unwrap();
+        assert!(
+            engine
+                .expiring_sessions(&bob, Duration::from_secs(300))
+                .await
+                .is_empty()
+        );
+        let active = engine
+            .create_reservation(&alice, &device, 0, now + 600, None)
+            .await
+            .unwrap();
+        assert_eq!(active.starts_at, now);
+        let expired = engine.check_expired_sessions().await;
+        assert_eq!(expired.len(), 1);
+        assert!(matches!(
+            expired[0].reason,
+            SessionExpiredReason::Reserved { .. }
+        ));
+        assert!(matches!(
+            engine.check_access(&bob, &device),
+            PolicyDecision::Deny(PolicyDenialReason::Reserved { .. })
+        ));
+        assert_eq!(engine.check_access(&alice, &device), PolicyDecision::Allow);
+        assert_eq!(
+            engine.reserved_for_other(&bob, device.id),
+            Some(active.clone())
+        );
+        assert_eq!(engine.reserved_for_other(&alice, device.id), None);
+        assert_eq!(
+            engine.cancel_reservation(active.id, Some(&bob)).await,
+            Err(ReservationError::NotHolder)
+        );
+        assert_eq!(engine.active_reservations(), vec![active]);
+    }
 }
@@ -0,0 +1,544 @@
+//! `p2p-usb-server policy ...`: debug device policies without a server
+//!
+//! `policy explain` builds the [`PolicyEngine`] the server would run from the
+//! configuration and shows how it decides on one client attaching one
+//! device: the policies whose filter matches, the one that governs the
+//! device, every check it ran and the decision. Quota and reservation
+//! checks use the usage and bookings the server saved. `policy lint` reports
+//! policies that never apply, can never let anyone in, or contradict other
+//! settings.
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::Subcommand;
+use iroh::PublicKey as EndpointId;
+use jiff::civil::DateTime;
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
+use protocol::{DeviceId, DeviceInfo, DeviceSpeed};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::config::ServerConfig;
+use crate::directory::ClientDirectory;
+use crate::policy::{PolicyDecision, PolicyEngine};
+use crate::quota::QuotaLedger;
+use crate::reservation::ReservationBook;
+use crate::schedule::Schedule;
+use crate::usb::DeviceRegistry;
+
+/// `p2p-usb-server policy` subcommands
+#[derive(Subcommand, Debug)]
+pub enum PolicyCommand {
+    /// Show how the device policies decide on a client attaching a device
+    Explain {
+        /// Client: EndpointId or client name
+        #[arg(long, value_name = "CLIENT")]
+        client: String,
+
+        /// Device: VID:PID or stable ID (VID:PID:SERIAL, VID:PID@BUS-PORT.PORT)
+        #[arg(long, value_name = "DEVICE")]
+        device: String,
+
+        /// USB device class in hex (e.g. 08), for class restrictions
+        #[arg(long, value_name = "CLASS", value_parser = parse_class)]
+        class: Option<u8>,
+
+        /// When to evaluate time windows (default: now); RFC 3339, or
+        /// "YYYY-MM-DD HH:MM" in the configured time zone
+        #[arg(long, value_name = "TIME")]
+        at: Option<String>,
+    },
+    /// Report policies that never apply or can never allow access
+    Lint,
+}
+
+/// Run a `policy` subcommand against `config`
+pub fn run(config: &ServerConfig, command: PolicyCommand) -> Result<()> {
+    let directory = Arc::new(ClientDirectory::new(&config.clients, &config.groups)?);
+    match command {
+        PolicyCommand::Explain {
+            client,
+            device,
+            class,
+            at,
+        } => {
+            let timezone = config.timezone()?;
+            let client = resolve_client(&directory, &client)?;
+            let mut device = parse_device(&device, class.unwrap_or_default())?;
+            // Time quotas count per device ID, kept for each stable identity
+            let registry = DeviceRegistry::load(&config.usb.device_registry_path())?;
+            if let Some(id) = registry.lookup(&device.stable_id) {
+                device.id = id;
+            }
+            let at = match at {
+                Some(at) => parse_time(&at, &timezone)?,
+                None => Timestamp::now(),
+            };
+            explain(config, directory, timezone, client, &device, at)
+        }
+        PolicyCommand::Lint => {
+            let findings = lint(config, &directory, Timestamp::now());
+            for finding in &findings {
+                let policy = &config.device_policies[finding.policy];
+                println!(
+                    "policy #{} '{}': {}",
+                    finding.policy + 1,
+                    policy.device_filter,
+                    finding.message
+                );
+            }
+            if !findings.is_empty() {
+                bail!("{} problem(s) in device policies", findings.len());
+            }
+            println!(
+                "No problems found in {} device policies",
+                config.device_policies.len()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Print the policy engine's reasoning on `client` attaching `device` at `at`
+fn explain(
+    config: &ServerConfig,
+    directory: Arc<ClientDirectory>,
+    timezone: TimeZone,
+    client: EndpointId,
+    device: &DeviceInfo,
+    at: Timestamp,
+) -> Result<()> {
+    let quota_usage = QuotaLedger::load(&config.quotas.usage_path())?;
+    let reservations = ReservationBook::load(&config.reservations.path())?;
+    let engine = PolicyEngine::new(config.device_policies.clone())
+        .with_client_directory(directory.clone())
+        .with_client_scopes(&config.security.client_devices)
+        .with_timezone(timezone.clone())
+        .with_quotas(&config.quotas, quota_usage)
+        .with_reservations(&config.reservations, Arc::new(reservations));
+    let explanation = engine.explain(&client, device, &[], at);
+
+    let client_label = match directory.name(&client) {
+        Some(name) => format!("{} ({})", name, client),
+        None => client.to_string(),
+    };
+    println!("Client:    {}", client_label);
+    println!(
+        "Device:    {:04x}:{:04x} class 0x{:02x}{}",
+        device.vendor_id,
+        device.product_id,
+        device.class,
+        if device.stable_id.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", device.stable_id)
+        }
+    );
+    println!(
+        "Time:      {}",
+        at.to_zoned(timezone).strftime("%a %Y-%m-%d %H:%M %Z")
+    );
+    println!("Admission: {}", admission(config, &directory, &client));
+
+    println!();
+    if explanation.candidates.is_empty() {
+        println!("No policy filter matches the device");
+    } else {
+        println!("Matching policies:");
+        for &index in &explanation.candidates {
+            let policy = &config.device_policies[index];
+            println!(
+                "  {} #{} '{}'{}",
+                if explanation.policy == Some(index) {
+                    "*"
+                } else {
+                    " "
+                },
+                index + 1,
+                policy.device_filter,
+                policy
+                    .description
+                    .as_deref()
+                    .map(|d| format!(" - {}", d))
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    println!();
+    println!("Checks:");
+    for check in &explanation.checks {
+        println!(
+            "  {:<4}  {:<28}  {}",
+            if check.passed { "ok" } else { "FAIL" },
+            check.setting,
+            check.detail
+        );
+    }
+    if let Some(index) = explanation.policy {
+        let policy = &config.device_policies[index];
+        if policy.allowed_interfaces.is_some() || policy.restricted_interface_classes.is_some() {
+            println!(
+                "  Interface rules need the device's interfaces; `ctl check` runs them on a live server"
+            );
+        }
+        if let Some(max) = policy.max_session_duration {
+            println!(
+                "  Sessions end after {}",
+                crate::config::format_duration(max)
+            );
+        }
+    }
+
+    println!();
+    match explanation.decision {
+        PolicyDecision::Allow => println!("Decision: ALLOW"),
+        PolicyDecision::Deny(reason) => println!("Decision: DENY ({})", reason),
+    }
+    Ok(())
+}
+
+/// Whether the server lets `client` connect at all
+fn admission(config: &ServerConfig, directory: &ClientDirectory, client: &EndpointId) -> String {
+    let security = &config.security;
+    if directory
+        .resolve_all(&security.denied_clients)
+        .contains(client)
+    {
+        "refused (security.denied_clients)".to_string()
+    } else if directory
+        .resolve_all(&security.approved_clients)
+        .contains(client)
+    {
+        "approved (security.approved_clients)".to_string()
+    } else if security.require_approval {
+        "unknown client, the operator is asked to approve it".to_string()
+    } else {
+        "admitted (approval not required)".to_string()
+    }
+}
+
+/// The one client an EndpointId or client name refers to
+fn resolve_client(directory: &ClientDirectory, client: &str) -> Result<EndpointId> {
+    let clients = directory.resolve(client)?;
+    match clients.iter().collect::<Vec<_>>()[..] {
+        [client] => Ok(*client),
+        _ => bail!(
+            "'{}' refers to {} clients, name a single client",
+            client,
+            clients.len()
+        ),
+    }
+}
+
+/// A device as `--device` and `--class` describe it
+///
+/// Only the IDs, serial number, port path and class are known; filters on
+/// string descriptors or speed see a device without them.
+fn parse_device(device: &str, class: u8) -> Result<DeviceInfo> {
+    let invalid = || {
+        anyhow!(
+            "Invalid device '{}', expected VID:PID or a stable ID",
+            device
+        )
+    };
+    let (vendor, rest) = device.split_once(':').ok_or_else(invalid)?;
+    let (product, identity) = rest.split_at(rest.find([':', '@']).unwrap_or(rest.len()));
+    let vendor_id = u16::from_str_radix(vendor, 16).map_err(|_| invalid())?;
+    let product_id = u16::from_str_radix(product, 16).map_err(|_| invalid())?;
+
+    let mut info = DeviceInfo {
+        id: DeviceId(0),
+        vendor_id,
+        product_id,
+        bus_number: 0,
+        device_address: 0,
+        manufacturer: None,
+        product: None,
+        serial_number: None,
+        class,
+        subclass: 0,
+        protocol: 0,
+        speed: DeviceSpeed::High,
+        num_configurations: 1,
+        interfaces: Vec::new(),
+        stable_id: String::new(),
+        port_numbers: Vec::new(),
+        parent_hub: None,
+    };
+    if let Some(serial) = identity.strip_prefix(':') {
+        info.serial_number = Some(serial.to_string());
+    } else if let Some(path) = identity.strip_prefix('@') {
+        let (bus, ports) = path.split_once('-').unwrap_or((path, ""));
+        info.bus_number = bus.parse().map_err(|_| invalid())?;
+        info.port_numbers = ports
+            .split('.')
+            .filter(|port| !port.is_empty())
+            .map(|port| port.parse().map_err(|_| invalid()))
+            .collect::<Result<_>>()?;
+    }
+    if !identity.is_empty() {
+        info.stable_id = format!("{:04x}:{:04x}{}", vendor_id, product_id, identity);
+    }
+    Ok(info)
+}
+
+/// Parse `--class` (hex, with or without 0x)
+fn parse_class(class: &str) -> Result<u8> {
+    let digits = class.trim_start_matches("0x");
+    u8::from_str_radix(digits, 16).with_context(|| format!("Invalid device class '{}'", class))
+}
+
+/// Parse `--at`: an instant with an offset or zone, or a wall-clock time in
+/// `timezone`
+fn parse_time(time: &str, timezone: &TimeZone) -> Result<Timestamp> {
+    if let Ok(instant) = time.parse::<Timestamp>() {
+        return Ok(instant);
+    }
+    if let Ok(zoned) = time.parse::<Zoned>() {
+        return Ok(zoned.timestamp());
+    }
+    let local: DateTime = time
+        .parse()
+        .with_context(|| format!("Invalid time '{}', expected e.g. 2026-12-24T10:00", time))?;
+    Ok(local.to_zoned(timezone.clone())?.timestamp())
+}
+
+/// Problem `policy lint` found in a device policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    /// The policy (index into `device_policies`)
+    pub policy: usize,
+    /// What is wrong with it
+    pub message: String,
+}
+
+/// Find policies that never apply or can never allow access
+///
+/// Covers policies shadowed by an earlier one with the same filter, client
+/// lists that admit nobody or only denied clients, schedules that do not
+/// open within a year of `now` and interface lists that allow nothing.
+pub fn lint(
+    config: &ServerConfig,
+    directory: &ClientDirectory,
+    now: Timestamp,
+) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+    let mut finding = |policy: usize, message: String| {
+        findings.push(LintFinding { policy, message });
+    };
+    let denied = directory.resolve_all(&config.security.denied_clients);
+    let timezone = config.timezone().unwrap_or(TimeZone::UTC);
+    let policies = &config.device_policies;
+
+    for (index, policy) in policies.iter().enumerate() {
+        let filter = policy.device_filter.trim();
+        if let Some(earlier) = policies[..index]
+            .iter()
+            .position(|other| other.device_filter.trim() == filter)
+        {
+            finding(
+                index,
+                format!(
+                    "never applies, policy #{} has the same filter and comes first",
+                    earlier + 1
+                ),
+            );
+        }
+
+        let mut clients = BTreeSet::new();
+        let mut everyone = false;
+        for entry in &policy.allowed_clients {
+            if entry == "*" {
+                everyone = true;
+                continue;
+            }
+            match directory.resolve(entry) {
+                Ok(members) if members.is_empty() => finding(
+                    index,
+                    format!("allowed_clients entry '{}' has no members", entry),
+                ),
+                Ok(members) => clients.extend(members),
+                Err(e) => finding(
+                    index,
+                    format!("allowed_clients entry '{}' is ignored: {:#}", entry, e),
+                ),
+            }
+        }
+        let refused: Vec<String> = clients
+            .iter()
+            .filter(|client| denied.contains(*client))
+            .map(|client| directory.label(&client.to_string()))
+            .collect();
+        if !everyone && clients.len() == refused.len() {
+            finding(
+                index,
+                if clients.is_empty() {
+                    "allows no client".to_string()
+                } else {
+                    "allows only clients in security.denied_clients".to_string()
+                },
+            );
+        } else if !refused.is_empty() {
+            finding(
+                index,
+                format!(
+                    "allows {} but security.denied_clients refuses them",
+                    refused.join(", ")
+                ),
+            );
+        }
+
+        let windows = policy.time_windows.as_deref().unwrap_or_default();
+        if let Ok(schedule) = Schedule::new(windows, &policy.blackout_dates)
+            && !schedule.is_open(now, &timezone)
+            && schedule.next_opening(now, &timezone).is_none()
+        {
+            finding(
+                index,
+                "time_windows do not open within a year, blackout_dates cover them".to_string(),
+            );
+        }
+
+        if policy
+            .allowed_interfaces
+            .as_ref()
+            .is_some_and(|interfaces| interfaces.is_empty())
+        {
+            finding(
+                index,
+                "allowed_interfaces is empty, no interface can be claimed".to_string(),
+            );
+        }
+        if policy.max_session_duration.is_some_and(|max| max.is_zero()) {
+            finding(
+                index,
+                "max_session_duration is zero, sessions end as they start".to_string(),
+            );
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> EndpointId {
+        iroh::SecretKey::generate(&mut rand::rng()).public()
+    }
+
+    #[test]
+    fn test_device_arguments() {
+        let printer = parse_device("04f9:0042", 7).unwrap();
+        assert_eq!((printer.vendor_id, printer.product_id), (0x04f9, 0x0042));
+        assert_eq!(printer.class, 7);
+        assert!(printer.stable_id.is_empty());
+
+        let key = parse_device("1050:0407:12345678", 0).unwrap();
+        assert_eq!(key.serial_number.as_deref(), Some("12345678"));
+        assert_eq!(key.stable_id, "1050:0407:12345678");
+
+        let port = parse_device("1050:0407@1-1.3", 0).unwrap();
+        assert_eq!(port.port_path(), "1-1.3");
+        assert_eq!(port.stable_id, "1050:0407@1-1.3");
+
+        assert!(parse_device("printer", 0).is_err());
+        assert!(parse_device("04f9:zz", 0).is_err());
+        assert_eq!(parse_class("0x08").unwrap(), 8);
+    }
+
+    #[test]
+    fn test_times_default_to_the_configured_zone() {
+        let helsinki = TimeZone::get("Europe/Helsinki").unwrap();
+        let expected: Timestamp = "2026-12-24T08:00:00Z".parse().unwrap();
+        assert_eq!(parse_time("2026-12-24 10:00", &helsinki).unwrap(), expected);
+        assert_eq!(
+            parse_time("2026-12-24T10:00:00+02:00", &TimeZone::UTC).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_time("2026-12-24T10:00[Europe/Helsinki]", &TimeZone::UTC).unwrap(),
+            expected
+        );
+        assert!(parse_time("Christmas", &helsinki).is_err());
+    }
+
+    #[test]
+    fn test_lint_finds_dead_policies() {
+        let (alice, bob) = (key(), key());
+        let mut config = ServerConfig::default();
+        config
+            .clients
+            .insert("alice".to_string(), alice.to_string());
+        config.clients.insert("bob".to_string(), bob.to_string());
+        config.groups.insert("nobody".to_string(), Vec::new());
+        config.security.denied_clients = vec!["bob".to_string()];
+        let policies = r#"
+            [[device_policies]]
+            device_filter = "04f9:*"
+            allowed_clients = ["alice", "bob"]
+
+            [[device_policies]]
+            device_filter = "04f9:*"
+            allowed_clients = ["*"]
+
+            [[device_policies]]
+            device_filter = "046d:c31c"
+            allowed_clients = ["@nobody", "bob"]
+            allowed_interfaces = []
+
+            [[device_policies]]
+            device_filter = "*"
+            allowed_clients = ["*"]
+            time_windows = ["Mon-Fri 09:00-17:00"]
+            blackout_dates = ["2026-01-01..2027-12-31"]
+        "#;
+        #[derive(serde::Deserialize)]
+        struct Policies {
+            device_policies: Vec<crate::config::DevicePolicy>,
+        }
+        let parsed: Policies = toml::from_str(policies).unwrap();
+        config.device_policies = parsed.device_policies;
+        let directory = ClientDirectory::new(&config.clients, &config.groups).unwrap();
+
+        let now: Timestamp = "2026-10-17T12:00:00Z".parse().unwrap();
+        let findings: Vec<(usize, String)> = lint(&config, &directory, now)
+            .into_iter()
+            .map(|finding| (finding.policy, finding.message))
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                (
+                    0,
+                    "allows bob but security.denied_clients refuses them".to_string()
+                ),
+                (
+                    1,
+                    "never applies, policy #1 has the same filter and comes first".to_string()
+                ),
+                (
+                    2,
+                    "allowed_clients entry '@nobody' has no members".to_string()
+                ),
+                (
+                    2,
+                    "allows only clients in security.denied_clients".to_string()
+                ),
+                (
+                    2,
+                    "allowed_interfaces is empty, no interface can be claimed".to_string()
+                ),
+                (
+                    3,
+                    "time_windows do not open within a year, blackout_dates cover them".to_string()
+                ),
+            ]
+        );
+
+        config.device_policies.truncate(1);
+        config.security.denied_clients.clear();
+        assert!(lint(&config, &directory, now).is_empty());
+    }
+}
@@ -6,13 +6,28 @@
 //! - Priority queue for pending transfers
 //! - Fair scheduling between clients
 //! - Integration with rate limiting
+//!
+//! When enabled, `TransferDispatcher` passes every transfer through
+//! `QosManager::admit` before it reaches the USB worker. At most
+//! `max_in_flight` transfers are admitted at once; the rest wait in per-priority
+//! FIFO queues, so a keyboard's interrupt transfer overtakes queued bulk traffic
+//! from a disk. Clients within their fair quota are served first.
 
+use crate::config::QosSettings;
 use protocol::{DeviceHandle, DeviceInfo, RequestId, TransferType, UsbRequest};
-use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap, VecDeque};
-use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
+use tokio::sync::oneshot;
+
+/// Wait after which a queued request is boosted one priority level
+const AGING_STEP: Duration = Duration::from_secs(5);
+
+/// Wait after which a queued request is boosted to at least High
+const AGING_MAX: Duration = Duration::from_secs(10);
+
+/// Default number of transfers admitted to the USB subsystem at once
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
 
 /// QoS priority levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -56,6 +71,29 @@ impl Priority {
         }
     }
 
+    /// Map a configured priority level (0-7, higher = more priority)
+    pub fn from_level(level: u8) -> Self {
+        match level {
+            0..=1 => Self::Low,
+            2..=3 => Self::Medium,
+            4..=5 => Self::High,
+            _ => Self::Critical,
+        }
+    }
+
+    /// All priorities, lowest first
+    pub const ALL: [Self; 4] = [Self::Low, Self::Medium, Self::High, Self::Critical];
+
+    /// Lowercase name (for metrics labels and display)
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+            Self::Critical => "critical",
+        }
+    }
+
     /// Combine device and transfer priorities (take the higher one)
     pub fn combined(device_priority: Self, transfer_priority: Self) -> Self {
         if device_priority >= transfer_priority {
@@ -85,6 +123,8 @@ pub struct PrioritizedRequest {
     pub queued_at: Instant,
     /// Estimated transfer size in bytes
     pub estimated_bytes: u64,
+    /// Queue-assigned ticket (unique across clients, unlike request IDs)
+    ticket: u64,
 }
 
 impl PrioritizedRequest {
@@ -101,6 +141,7 @@ impl PrioritizedRequest {
             priority,
             queued_at: Instant::now(),
             estimated_bytes,
+            ticket: 0,
         }
     }
 
@@ -117,18 +158,21 @@ impl PrioritizedRequest {
     /// Calculate effective priority based on wait time (aging)
     ///
     /// Requests that have waited longer get boosted priority to prevent starvation.
-    fn effective_priority(&self) -> (Priority, Duration) {
+    fn effective_priority(&self, aging: bool) -> (Priority, Duration) {
         let wait_time = self.queued_at.elapsed();
+        if !aging {
+            return (self.priority, wait_time);
+        }
 
         // Boost priority after waiting too long (prevent starvation)
-        let boosted = if wait_time > Duration::from_secs(5) {
+        let boosted = if wait_time > AGING_MAX {
+            self.priority.max(Priority::High)
+        } else if wait_time > AGING_STEP {
             match self.priority {
                 Priority::Low => Priority::Medium,
                 Priority::Medium => Priority::High,
                 other => other,
             }
-        } else if wait_time > Duration::from_secs(10) {
-            Priority::High
         } else {
             self.priority
         };
@@ -137,33 +181,6 @@ impl PrioritizedRequest {
     }
 }
 
-impl Eq for PrioritizedRequest {}
-
-impl PartialEq for PrioritizedRequest {
-    fn eq(&self, other: &Self) -> bool {
-        self.request.id == other.request.id
-    }
-}
-
-impl Ord for PrioritizedRequest {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let (self_priority, self_wait) = self.effective_priority();
-        let (other_priority, other_wait) = other.effective_priority();
-
-        // Higher priority first, then longer wait time
-        match self_priority.cmp(&other_priority) {
-            Ordering::Equal => self_wait.cmp(&other_wait),
-            other => other,
-        }
-    }
-}
-
-impl PartialOrd for PrioritizedRequest {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
 /// Fair scheduler state for a single client
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -210,82 +227,139 @@ impl ClientSchedulerState {
 
 /// Priority queue for USB transfer requests
 ///
-/// Implements priority-based scheduling with fair allocation between clients.
+/// Keeps one FIFO per priority level. Requests on the same endpoint share a
+/// priority, so they are never reordered against each other; the next request
+/// is chosen among the queue heads by effective (aged) priority, then wait time.
 #[derive(Debug)]
 pub struct PriorityQueue {
-    /// Requests ordered by priority
-    heap: BinaryHeap<PrioritizedRequest>,
-    /// Request lookup by ID
-    request_ids: HashMap<RequestId, Priority>,
+    /// FIFO per priority level, indexed by `Priority as usize`
+    levels: [VecDeque<PrioritizedRequest>; 4],
+    /// Whether waiting requests are boosted (priority aging)
+    aging: bool,
+    /// Next ticket to assign
+    next_ticket: u64,
 }
 
 impl PriorityQueue {
-    /// Create a new priority queue
+    /// Create a new priority queue with priority aging enabled
     pub fn new() -> Self {
+        Self::with_aging(true)
+    }
+
+    /// Create a new priority queue, choosing whether waiting requests age
+    pub fn with_aging(aging: bool) -> Self {
         Self {
-            heap: BinaryHeap::new(),
-            request_ids: HashMap::new(),
+            levels: Default::default(),
+            aging,
+            next_ticket: 1,
         }
     }
 
-    /// Add a request to the queue
-    pub fn push(&mut self, request: PrioritizedRequest) {
-        self.request_ids.insert(request.id(), request.priority);
-        self.heap.push(request);
+    /// Add a request to the queue, returning its ticket
+    pub fn push(&mut self, mut request: PrioritizedRequest) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        request.ticket = ticket;
+        self.levels[request.priority as usize].push_back(request);
+        ticket
     }
 
     /// Get the next highest-priority request
     pub fn pop(&mut self) -> Option<PrioritizedRequest> {
-        let request = self.heap.pop()?;
-        self.request_ids.remove(&request.id());
-        Some(request)
+        self.pop_next(|_| true)
+    }
+
+    /// Get the next request, preferring clients for which `eligible` is true
+    ///
+    /// Falls back to ineligible clients when no eligible request is queued, so
+    /// the queue never idles while work is waiting.
+    pub fn pop_next(
+        &mut self,
+        mut eligible: impl FnMut(&str) -> bool,
+    ) -> Option<PrioritizedRequest> {
+        let (level, index) = self
+            .select(&mut eligible)
+            .or_else(|| self.select(&mut |_: &str| true))?;
+        self.levels[level].remove(index)
+    }
+
+    /// Locate the best request among the first eligible entry of each level
+    fn select(&self, eligible: &mut impl FnMut(&str) -> bool) -> Option<(usize, usize)> {
+        let mut best: Option<((Priority, Duration), usize, usize)> = None;
+        for (level, queue) in self.levels.iter().enumerate() {
+            let Some(index) = queue.iter().position(|r| eligible(&r.client_id)) else {
+                continue;
+            };
+            let key = queue[index].effective_priority(self.aging);
+            if best.as_ref().is_none_or(|(best_key, _, _)| key > *best_key) {
+                best = Some((key, level, index));
+            }
+        }
+        best.map(|(_, level, index)| (level, index))
     }
 
     /// Peek at the next request without removing it
     pub fn peek(&self) -> Option<&PrioritizedRequest> {
-        self.heap.peek()
+        let (level, index) = self.select(&mut |_: &str| true)?;
+        self.levels[level].get(index)
     }
 
     /// Check if a request is in the queue
     pub fn contains(&self, id: &RequestId) -> bool {
-        self.request_ids.contains_key(id)
+        self.levels.iter().flatten().any(|r| r.id() == *id)
     }
 
     /// Remove a specific request by ID
     pub fn remove(&mut self, id: &RequestId) -> Option<PrioritizedRequest> {
-        if !self.request_ids.remove(id).is_some() {
-            return None;
+        for queue in &mut self.levels {
+            if let Some(index) = queue.iter().position(|r| r.id() == *id) {
+                return queue.remove(index);
+            }
         }
+        None
+    }
 
-        // Rebuild heap without the removed item
-        let old_heap = std::mem::take(&mut self.heap);
-        let mut removed = None;
-
-        for request in old_heap {
-            if request.id() == *id {
-                removed = Some(request);
-            } else {
-                self.heap.push(request);
+    /// Remove a request by its queue ticket
+    fn remove_ticket(&mut self, ticket: u64) -> Option<PrioritizedRequest> {
+        for queue in &mut self.levels {
+            if let Some(index) = queue.iter().position(|r| r.ticket == ticket) {
+                return queue.remove(index);
             }
         }
+        None
+    }
 
+    /// Remove and return all requests for a device
+    pub fn remove_device(&mut self, handle: DeviceHandle) -> Vec<PrioritizedRequest> {
+        let mut removed = Vec::new();
+        for queue in &mut self.levels {
+            let (matching, kept) = queue.drain(..).partition(|r| r.handle() == handle);
+            *queue = kept;
+            removed.extend::<VecDeque<_>>(matching);
+        }
         removed
     }
 
+    /// Number of queued requests per priority, indexed by `Priority as usize`
+    pub fn depths(&self) -> [usize; 4] {
+        [0, 1, 2, 3].map(|level| self.levels[level].len())
+    }
+
     /// Get the number of pending requests
     pub fn len(&self) -> usize {
-        self.heap.len()
+        self.levels.iter().map(VecDeque::len).sum()
     }
 
     /// Check if the queue is empty
     pub fn is_empty(&self) -> bool {
-        self.heap.is_empty()
+        self.levels.iter().all(VecDeque::is_empty)
     }
 
     /// Clear all requests
     pub fn clear(&mut self) {
-        self.heap.clear();
-        self.request_ids.clear();
+        for queue in &mut self.levels {
+            queue.clear();
+        }
     }
 }
 
@@ -393,17 +467,74 @@ impl FairScheduler {
     }
 }
 
-/// QoS manager combining priority queue and fair scheduling
+/// Scheduler state guarded by one lock so admission decisions are atomic
 #[derive(Debug)]
-pub struct QosManager {
+struct QosState {
     /// Priority queue for pending requests
-    queue: Mutex<PriorityQueue>,
+    queue: PriorityQueue,
     /// Fair scheduler for client bandwidth allocation
-    scheduler: Mutex<FairScheduler>,
+    scheduler: FairScheduler,
+    /// Transfers admitted and not yet released
+    in_flight: usize,
+    /// Callers waiting in `admit`, keyed by queue ticket
+    waiters: HashMap<u64, oneshot::Sender<QosGrant>>,
+}
+
+/// A queued request handed back to its `admit` caller
+#[derive(Debug)]
+struct QosGrant {
+    request: UsbRequest,
+    permit: QosPermit,
+}
+
+/// Admission slot held while a transfer is in flight
+///
+/// Dropping the permit frees the slot and admits the next queued request.
+#[derive(Debug)]
+pub struct QosPermit {
+    manager: Option<Arc<QosManager>>,
+}
+
+impl Drop for QosPermit {
+    fn drop(&mut self) {
+        if let Some(manager) = self.manager.take() {
+            manager.release();
+        }
+    }
+}
+
+/// Removes a request from the queue if its `admit` caller goes away
+struct QueuedTicket<'a> {
+    manager: &'a QosManager,
+    ticket: u64,
+}
+
+impl Drop for QueuedTicket<'_> {
+    fn drop(&mut self) {
+        let mut state = self.manager.state.lock().unwrap();
+        if state.waiters.remove(&self.ticket).is_some() {
+            state.queue.remove_ticket(self.ticket);
+        }
+    }
+}
+
+/// QoS manager combining priority queue and fair scheduling
+#[derive(Debug)]
+pub struct QosManager {
+    /// Queue, fair scheduler and admission bookkeeping
+    state: Mutex<QosState>,
     /// Device class cache for priority lookup
     device_classes: Mutex<HashMap<DeviceHandle, u8>>,
     /// QoS enabled flag
     enabled: bool,
+    /// Maximum transfers admitted at once
+    max_in_flight: usize,
+    /// Priority of control transfers
+    control_priority: Priority,
+    /// Priority of interrupt transfers
+    interrupt_priority: Priority,
+    /// Priority of bulk transfers
+    bulk_priority: Priority,
 }
 
 impl QosManager {
@@ -414,13 +545,33 @@ impl QosManager {
     /// * `client_quota` - Per-client bandwidth quota in bytes per second
     pub fn new(enabled: bool, client_quota: u64) -> Self {
         Self {
-            queue: Mutex::new(PriorityQueue::new()),
-            scheduler: Mutex::new(FairScheduler::new(client_quota)),
+            state: Mutex::new(QosState {
+                queue: PriorityQueue::new(),
+                scheduler: FairScheduler::new(client_quota),
+                in_flight: 0,
+                waiters: HashMap::new(),
+            }),
             device_classes: Mutex::new(HashMap::new()),
             enabled,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            control_priority: Priority::High,
+            interrupt_priority: Priority::High,
+            bulk_priority: Priority::Medium,
         }
     }
 
+    /// Create a QoS manager from the `[qos]` configuration section
+    pub fn from_settings(settings: &QosSettings) -> Self {
+        let quota_bytes = settings.client_quota_mbps * 1_000_000 / 8; // Mbps to bytes/sec
+        let mut manager = Self::new(settings.enabled, quota_bytes);
+        manager.state.get_mut().unwrap().queue = PriorityQueue::with_aging(settings.priority_aging);
+        manager.max_in_flight = settings.max_in_flight.max(1);
+        manager.control_priority = Priority::from_level(settings.control_priority);
+        manager.interrupt_priority = Priority::from_level(settings.interrupt_priority);
+        manager.bulk_priority = Priority::from_level(settings.bulk_priority);
+        manager
+    }
+
     /// Check if QoS is enabled
     pub fn is_enabled(&self) -> bool {
         self.enabled
@@ -428,7 +579,7 @@ impl QosManager {
 
     /// Register a device's class for priority lookup
     pub async fn register_device(&self, handle: DeviceHandle, device_class: u8) {
-        let mut classes = self.device_classes.lock().await;
+        let mut classes = self.device_classes.lock().unwrap();
         classes.insert(handle, device_class);
     }
 
@@ -439,27 +590,37 @@ impl QosManager {
 
     /// Unregister a device
     pub async fn unregister_device(&self, handle: DeviceHandle) {
-        let mut classes = self.device_classes.lock().await;
+        let mut classes = self.device_classes.lock().unwrap();
         classes.remove(&handle);
     }
 
     /// Register a client
     pub async fn register_client(&self, client_id: &str) {
-        let mut scheduler = self.scheduler.lock().await;
-        scheduler.register_client(client_id);
+        let mut state = self.state.lock().unwrap();
+        state.scheduler.register_client(client_id);
     }
 
     /// Unregister a client
     pub async fn unregister_client(&self, client_id: &str) {
-        let mut scheduler = self.scheduler.lock().await;
-        scheduler.unregister_client(client_id);
+        let mut state = self.state.lock().unwrap();
+        state.scheduler.unregister_client(client_id);
+    }
+
+    /// Priority configured for a transfer type
+    fn transfer_priority(&self, transfer: &TransferType) -> Priority {
+        match transfer {
+            TransferType::Control { .. } => self.control_priority,
+            TransferType::Interrupt { .. } => self.interrupt_priority,
+            TransferType::Bulk { .. } => self.bulk_priority,
+            TransferType::Isochronous { .. } => Priority::High, // Time-sensitive
+        }
     }
 
     /// Calculate priority for a request
     pub async fn calculate_priority(&self, request: &UsbRequest) -> Priority {
-        let transfer_priority = Priority::for_transfer_type(&request.transfer);
+        let transfer_priority = self.transfer_priority(&request.transfer);
 
-        let device_classes = self.device_classes.lock().await;
+        let device_classes = self.device_classes.lock().unwrap();
         let device_priority = device_classes
             .get(&request.handle)
             .map(|&class| Priority::for_device_class(class))
@@ -478,7 +639,86 @@ impl QosManager {
         }
     }
 
+    /// Wait until a request may be submitted to the USB subsystem
+    ///
+    /// Returns the request together with a permit that holds an admission slot
+    /// until dropped, normally when the transfer completes. Without QoS the request is returned immediately and
+    /// no permit is issued. Returns `None` if the request was cancelled while
+    /// queued (`cancel`, `cancel_device`).
+    pub async fn admit(
+        self: &Arc<Self>,
+        request: UsbRequest,
+        client_id: &str,
+    ) -> Option<(UsbRequest, Option<QosPermit>)> {
+        if !self.enabled {
+            return Some((request, None));
+        }
+
+        let priority = self.calculate_priority(&request).await;
+        let estimated_bytes = Self::estimate_transfer_size(&request.transfer);
+        let prioritized =
+            PrioritizedRequest::new(request, client_id.to_string(), priority, estimated_bytes);
+
+        let (tx, rx) = oneshot::channel();
+        let ticket = {
+            let mut state = self.state.lock().unwrap();
+            let ticket = state.queue.push(prioritized);
+            state.waiters.insert(ticket, tx);
+            ticket
+        };
+        let _queued = QueuedTicket {
+            manager: self,
+            ticket,
+        };
+
+        self.dispatch();
+        let grant = rx.await.ok()?;
+        Some((grant.request, Some(grant.permit)))
+    }
+
+    /// Admit queued requests while admission slots are free
+    fn dispatch(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        while state.in_flight < self.max_in_flight {
+            let QosState {
+                queue, scheduler, ..
+            } = &mut *state;
+            let Some(next) = queue.pop_next(|client| scheduler.is_within_quota(client)) else {
+                break;
+            };
+            // Requests queued via `enqueue` have no waiter and are not admitted here
+            let Some(waiter) = state.waiters.remove(&next.ticket) else {
+                continue;
+            };
+
+            state.in_flight += 1;
+            let grant = QosGrant {
+                request: next.request,
+                permit: QosPermit {
+                    manager: Some(self.clone()),
+                },
+            };
+            if let Err(mut grant) = waiter.send(grant) {
+                // Caller went away; free the slot without re-entering the lock
+                grant.permit.manager = None;
+                state.in_flight -= 1;
+            }
+        }
+    }
+
+    /// Free an admission slot (called when a `QosPermit` is dropped)
+    fn release(self: &Arc<Self>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+        self.dispatch();
+    }
+
     /// Enqueue a request
+    ///
+    /// For callers that dispatch with `dequeue` themselves; transfers served by
+    /// the network layer go through `admit` instead.
     pub async fn enqueue(&self, request: UsbRequest, client_id: &str) {
         if !self.enabled {
             return;
@@ -490,8 +730,8 @@ impl QosManager {
         let prioritized =
             PrioritizedRequest::new(request, client_id.to_string(), priority, estimated_bytes);
 
-        let mut queue = self.queue.lock().await;
-        queue.push(prioritized);
+        let mut state = self.state.lock().unwrap();
+        state.queue.push(prioritized);
     }
 
     /// Dequeue the next request
@@ -500,57 +740,65 @@ impl QosManager {
             return None;
         }
 
-        let mut queue = self.queue.lock().await;
-        queue.pop()
+        let mut state = self.state.lock().unwrap();
+        let QosState {
+            queue, scheduler, ..
+        } = &mut *state;
+        queue.pop_next(|client| scheduler.is_within_quota(client))
     }
 
     /// Check if a client is within their fair quota
     pub async fn is_client_within_quota(&self, client_id: &str) -> bool {
-        let mut scheduler = self.scheduler.lock().await;
-        scheduler.is_within_quota(client_id)
+        let mut state = self.state.lock().unwrap();
+        state.scheduler.is_within_quota(client_id)
     }
 
     /// Record a completed transfer
     pub async fn record_transfer(&self, client_id: &str, bytes: u64) {
-        let mut scheduler = self.scheduler.lock().await;
-        scheduler.record_transfer(client_id, bytes);
+        let mut state = self.state.lock().unwrap();
+        state.scheduler.record_transfer(client_id, bytes);
     }
 
     /// Get the number of pending requests
     pub async fn pending_count(&self) -> usize {
-        let queue = self.queue.lock().await;
-        queue.len()
+        let state = self.state.lock().unwrap();
+        state.queue.len()
+    }
+
+    /// Number of queued requests per priority, indexed by `Priority as usize`
+    pub fn queue_depths(&self) -> [usize; 4] {
+        self.state.lock().unwrap().queue.depths()
+    }
+
+    /// Number of transfers currently admitted
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().in_flight
     }
 
     /// Cancel a pending request
     pub async fn cancel(&self, request_id: &RequestId) -> bool {
-        let mut queue = self.queue.lock().await;
-        queue.remove(request_id).is_some()
+        let mut state = self.state.lock().unwrap();
+        match state.queue.remove(request_id) {
+            Some(removed) => {
+                state.waiters.remove(&removed.ticket);
+                true
+            }
+            None => false,
+        }
     }
 
     /// Cancel all requests for a device
     pub async fn cancel_device(&self, handle: DeviceHandle) {
-        let mut queue = self.queue.lock().await;
-
-        // Find all requests for this device
-        let old_len = queue.len();
-        let to_remove: Vec<RequestId> = {
-            let heap = &queue.heap;
-            heap.iter()
-                .filter(|r| r.handle() == handle)
-                .map(|r| r.id())
-                .collect()
-        };
-
-        // Remove them
-        for id in to_remove {
-            queue.remove(&id);
+        let mut state = self.state.lock().unwrap();
+        let removed = state.queue.remove_device(handle);
+        for request in &removed {
+            state.waiters.remove(&request.ticket);
         }
 
-        if queue.len() != old_len {
+        if !removed.is_empty() {
             tracing::debug!(
                 "Cancelled {} requests for device {:?}",
-                old_len - queue.len(),
+                removed.len(),
                 handle
             );
         }
@@ -693,4 +941,161 @@ mod tests {
         assert!(dequeued.is_some());
         assert_eq!(manager.pending_count().await, 0);
     }
+
+    fn create_interrupt_request(id: u64, handle: u32) -> UsbRequest {
+        UsbRequest {
+            id: RequestId(id),
+            handle: DeviceHandle(handle),
+            transfer: TransferType::Interrupt {
+                endpoint: 0x81,
+                data: vec![],
+                timeout_ms: 100,
+            },
+        }
+    }
+
+    #[test]
+    fn test_priority_queue_fifo_within_level() {
+        let mut queue = PriorityQueue::new();
+        for id in 1..=3 {
+            queue.push(PrioritizedRequest::new(
+                create_test_request(id, 1),
+                "client1".to_string(),
+                Priority::Medium,
+                64,
+            ));
+        }
+        assert_eq!(queue.depths(), [0, 3, 0, 0]);
+
+        let ids: Vec<u64> = std::iter::from_fn(|| queue.pop())
+            .map(|r| r.id().0)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_priority_aging() {
+        let mut old = PrioritizedRequest::new(
+            create_test_request(1, 1),
+            "client1".to_string(),
+            Priority::Low,
+            64,
+        );
+        old.queued_at = Instant::now() - Duration::from_secs(6);
+        assert_eq!(old.effective_priority(true).0, Priority::Medium);
+        assert_eq!(old.effective_priority(false).0, Priority::Low);
+
+        old.queued_at = Instant::now() - Duration::from_secs(11);
+        assert_eq!(old.effective_priority(true).0, Priority::High);
+    }
+
+    #[test]
+    fn test_pop_next_prefers_eligible_clients() {
+        let mut queue = PriorityQueue::new();
+        queue.push(PrioritizedRequest::new(
+            create_test_request(1, 1),
+            "greedy".to_string(),
+            Priority::High,
+            64,
+        ));
+        queue.push(PrioritizedRequest::new(
+            create_test_request(2, 2),
+            "polite".to_string(),
+            Priority::Medium,
+            64,
+        ));
+
+        // Over-quota client is passed over while others are waiting
+        let next = queue.pop_next(|client| client != "greedy").unwrap();
+        assert_eq!(next.client_id, "polite");
+        // ...but still served when nobody else is queued
+        let next = queue.pop_next(|client| client != "greedy").unwrap();
+        assert_eq!(next.client_id, "greedy");
+    }
+
+    #[tokio::test]
+    async fn test_admit_orders_by_priority() {
+        let settings = QosSettings {
+            enabled: true,
+            max_in_flight: 1,
+            ..Default::default()
+        };
+        let manager = Arc::new(QosManager::from_settings(&settings));
+        manager.register_device(DeviceHandle(1), 0x08).await; // Mass storage
+        manager.register_device(DeviceHandle(2), 0x03).await; // HID
+
+        // First bulk transfer takes the only slot
+        let (_, permit) = manager
+            .admit(create_test_request(1, 1), "client1")
+            .await
+            .unwrap();
+        assert_eq!(manager.in_flight(), 1);
+
+        let bulk = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.admit(create_test_request(2, 1), "client1").await }
+        });
+        let interrupt = tokio::spawn({
+            let manager = manager.clone();
+            async move {
+                manager
+                    .admit(create_interrupt_request(3, 2), "client2")
+                    .await
+            }
+        });
+        while manager.pending_count().await < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(manager.queue_depths(), [0, 1, 1, 0]);
+
+        // Releasing the slot admits the interrupt transfer ahead of the queued bulk one
+        drop(permit);
+        let (request, permit) = interrupt.await.unwrap().unwrap();
+        assert_eq!(request.id, RequestId(3));
+        assert!(!bulk.is_finished());
+
+        drop(permit);
+        let (request, _permit) = bulk.await.unwrap().unwrap();
+        assert_eq!(request.id, RequestId(2));
+        assert_eq!(manager.pending_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_admit_cancel_device() {
+        let settings = QosSettings {
+            enabled: true,
+            max_in_flight: 1,
+            ..Default::default()
+        };
+        let manager = Arc::new(QosManager::from_settings(&settings));
+        let (_, permit) = manager
+            .admit(create_test_request(1, 1), "client1")
+            .await
+            .unwrap();
+
+        let queued = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.admit(create_test_request(2, 1), "client1").await }
+        });
+        while manager.pending_count().await < 1 {
+            tokio::task::yield_now().await;
+        }
+
+        manager.cancel_device(DeviceHandle(1)).await;
+        assert!(queued.await.unwrap().is_none());
+
+        drop(permit);
+        assert_eq!(manager.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_admit_disabled_passes_through() {
+        let manager = Arc::new(QosManager::new(false, 1_000_000));
+        let (request, permit) = manager
+            .admit(create_test_request(1, 1), "client1")
+            .await
+            .unwrap();
+        assert_eq!(request.id, RequestId(1));
+        assert!(permit.is_none());
+    }
 }
@@ -0,0 +1,526 @@
+//! Rolling usage quotas
+//!
+//! Counts what each client uses of the quotas the policy engine enforces:
+//! attach time per device per day (`DevicePolicy::daily_time_quota`) and
+//! bytes per day and per calendar month across all devices (`[quotas]`).
+//! Periods follow the server time zone; daily counters start over at
+//! midnight and monthly ones on the first of the month.
+//!
+//! Usage is kept in a small TOML file so a server restart does not hand
+//! out fresh quotas:
+//!
+//! ```toml
+//! [clients.<EndpointId>]
+//! day = "2026-10-17"
+//! month = "2026-10"
+//! day_bytes = 1200000000
+//! month_bytes = 8400000000
+//!
+//! [clients.<EndpointId>.device_secs]
+//! "3" = 5400
+//! ```
+
+use crate::config::{QuotaSettings, parse_size};
+use crate::directory::ClientDirectory;
+use anyhow::{Context, Result};
+use iroh::PublicKey as EndpointId;
+use jiff::civil::Date;
+use jiff::{ToSpan, Zoned};
+use protocol::{DeviceId, QuotaKind, QuotaUsage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+
+/// Data limits of one client in bytes (None = no limit)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DataLimits {
+    /// Bytes per day
+    pub daily: Option<u64>,
+    /// Bytes per calendar month
+    pub monthly: Option<u64>,
+}
+
+impl DataLimits {
+    /// Parse the limits of a `[quotas]` section or client entry
+    ///
+    /// Sizes are validated at config load; any that do not parse are
+    /// skipped with a warning.
+    fn parse(daily: &Option<String>, monthly: &Option<String>) -> Self {
+        let parse = |size: &Option<String>| {
+            size.as_deref().and_then(|size| {
+                parse_size(size)
+                    .inspect_err(|e| warn!("Ignoring data quota: {}", e))
+                    .ok()
+            })
+        };
+        Self {
+            daily: parse(daily),
+            monthly: parse(monthly),
+        }
+    }
+
+    /// Whether neither limit is set
+    pub fn is_unlimited(&self) -> bool {
+        self.daily.is_none() && self.monthly.is_none()
+    }
+
+    /// These limits with those `other` sets replacing them
+    fn overridden_by(self, other: &DataLimits) -> Self {
+        Self {
+            daily: other.daily.or(self.daily),
+            monthly: other.monthly.or(self.monthly),
+        }
+    }
+}
+
+/// Data limits of every client, from `[quotas]`
+#[derive(Debug, Default)]
+pub struct QuotaLimits {
+    /// Limits of clients without an entry of their own
+    default: DataLimits,
+    /// `[quotas.clients]` entries, groups first so entries naming a client win
+    overrides: Vec<(String, DataLimits)>,
+}
+
+impl QuotaLimits {
+    /// Parse the data limits of `settings`
+    pub fn new(settings: &QuotaSettings) -> Self {
+        let mut overrides: Vec<(String, DataLimits)> = settings
+            .clients
+            .iter()
+            .map(|(client, quota)| {
+                let limits = DataLimits::parse(&quota.daily_data, &quota.monthly_data);
+                (client.clone(), limits)
+            })
+            .collect();
+        overrides.sort_by_key(|(client, _)| !client.starts_with('@'));
+        Self {
+            default: DataLimits::parse(&settings.daily_data, &settings.monthly_data),
+            overrides,
+        }
+    }
+
+    /// Data limits of `client`
+    pub fn for_client(&self, directory: &ClientDirectory, client: &EndpointId) -> DataLimits {
+        self.overrides
+            .iter()
+            .filter(|(entry, _)| directory.matches(entry, client))
+            .fold(self.default, |limits, (_, entry)| {
+                limits.overridden_by(entry)
+            })
+    }
+}
+
+/// On-disk format of the usage file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageFile {
+    #[serde(default)]
+    clients: BTreeMap<String, ClientUsage>,
+}
+
+/// What one client used in the current periods
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct ClientUsage {
+    /// Day the daily counters cover ("YYYY-MM-DD")
+    day: String,
+    /// Month the monthly counter covers ("YYYY-MM")
+    month: String,
+    /// Bytes transferred on `day`
+    #[serde(default)]
+    day_bytes: u64,
+    /// Bytes transferred in `month`
+    #[serde(default)]
+    month_bytes: u64,
+    /// Attach time on `day` per device ID, in seconds (TOML keys are strings)
+    #[serde(default)]
+    device_secs: BTreeMap<String, u64>,
+}
+
+impl ClientUsage {
+    /// Start the counters of periods that ended before `now` over
+    fn roll(&mut self, now: &Zoned) {
+        let (day, month) = period_keys(now.date());
+        if self.day != day {
+            self.day = day;
+            self.day_bytes = 0;
+            self.device_secs.clear();
+        }
+        if self.month != month {
+            self.month = month;
+            self.month_bytes = 0;
+        }
+    }
+}
+
+/// Keys of the day and month containing `date`
+fn period_keys(date: Date) -> (String, String) {
+    (date.to_string(), date.strftime("%Y-%m").to_string())
+}
+
+/// Usage of every client, persisted across restarts
+#[derive(Debug)]
+pub struct QuotaLedger {
+    /// File usage is saved to (None = not persisted)
+    path: Option<PathBuf>,
+    /// Usage by client
+    usage: Mutex<HashMap<EndpointId, ClientUsage>>,
+    /// Usage changed since the last save
+    dirty: AtomicBool,
+}
+
+impl Default for QuotaLedger {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl QuotaLedger {
+    /// Ledger that is not saved anywhere
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            usage: Mutex::new(HashMap::new()),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Load the usage saved at `path`
+    ///
+    /// A missing file yields an empty ledger that is created on the first
+    /// save. Entries for keys that are not EndpointIds are dropped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file: UsageFile = if path.exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read quota usage: {}", path.display()))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse quota usage: {}", path.display()))?
+        } else {
+            UsageFile::default()
+        };
+
+        let usage = file
+            .clients
+            .into_iter()
+            .filter_map(|(client, usage)| match client.parse::<EndpointId>() {
+                Ok(client_id) => Some((client_id, usage)),
+                Err(e) => {
+                    warn!("Ignoring quota usage of '{}': {}", client, e);
+                    None
+                }
+            })
+            .collect();
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            usage: Mutex::new(usage),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Update the usage of `client` in the periods containing `now`
+    fn update(&self, client: &EndpointId, now: &Zoned, f: impl FnOnce(&mut ClientUsage)) {
+        let mut usage = self.usage.lock().unwrap();
+        let client_usage = usage.entry(*client).or_default();
+        client_usage.roll(now);
+        f(client_usage);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Usage of `client` in the periods containing `now`
+    ///
+    /// Reading leaves the stored counters alone, so looking at another
+    /// time (as `policy explain --at` does) does not reset them.
+    fn current(&self, client: &EndpointId, now: &Zoned) -> ClientUsage {
+        let mut usage = self
+            .usage
+            .lock()
+            .unwrap()
+            .get(client)
+            .cloned()
+            .unwrap_or_default();
+        usage.roll(now);
+        usage
+    }
+
+    /// Add attach time of `client` on a device
+    pub fn add_time(&self, client: &EndpointId, device_id: DeviceId, secs: u64, now: &Zoned) {
+        if secs == 0 {
+            return;
+        }
+        self.update(client, now, |usage| {
+            *usage
+                .device_secs
+                .entry(device_id.0.to_string())
+                .or_default() += secs;
+        });
+    }
+
+    /// Add bytes transferred by `client`
+    pub fn add_data(&self, client: &EndpointId, bytes: u64, now: &Zoned) {
+        if bytes == 0 {
+            return;
+        }
+        self.update(client, now, |usage| {
+            usage.day_bytes += bytes;
+            usage.month_bytes += bytes;
+        });
+    }
+
+    /// Seconds `client` spent attached to a device today
+    pub fn time_used(&self, client: &EndpointId, device_id: DeviceId, now: &Zoned) -> u64 {
+        let usage = self.current(client, now);
+        let device = device_id.0.to_string();
+        usage.device_secs.get(&device).copied().unwrap_or(0)
+    }
+
+    /// Usage of the time quota `limit` of a device
+    pub fn time_usage(
+        &self,
+        client: &EndpointId,
+        device_id: DeviceId,
+        limit: u64,
+        now: &Zoned,
+    ) -> QuotaUsage {
+        QuotaUsage {
+            kind: QuotaKind::DailyTime,
+            device_id: Some(device_id),
+            used: self.time_used(client, device_id, now),
+            limit,
+            resets_at: describe(&day_end(now)),
+        }
+    }
+
+    /// Usage of the data quotas `limits` sets, daily first
+    pub fn data_usage(
+        &self,
+        client: &EndpointId,
+        limits: DataLimits,
+        now: &Zoned,
+    ) -> Vec<QuotaUsage> {
+        let usage = self.current(client, now);
+        let daily = limits.daily.map(|limit| QuotaUsage {
+            kind: QuotaKind::DailyData,
+            device_id: None,
+            used: usage.day_bytes,
+            limit,
+            resets_at: describe(&day_end(now)),
+        });
+        let monthly = limits.monthly.map(|limit| QuotaUsage {
+            kind: QuotaKind::MonthlyData,
+            device_id: None,
+            used: usage.month_bytes,
+            limit,
+            resets_at: describe(&month_end(now)),
+        });
+        daily.into_iter().chain(monthly).collect()
+    }
+
+    /// Write the usage to disk if it changed since the last save
+    ///
+    /// A no-op for in-memory ledgers. Like the device registry, the file is
+    /// replaced through a synced temporary copy, so a crash never leaves a
+    /// truncated file that would load as unused quotas.
+    pub fn save_if_dirty(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let file = UsageFile {
+            clients: self
+                .usage
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(client, usage)| (client.to_string(), usage.clone()))
+                .collect(),
+        };
+        let result = Self::write(path, &file);
+        if result.is_err() {
+            // Try again on the next save
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn write(path: &Path, file: &UsageFile) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create quota directory: {}", parent.display())
+            })?;
+        }
+        let content = toml::to_string_pretty(file).context("Failed to serialize quota usage")?;
+
+        let mut tmp_name = OsString::from(path.as_os_str());
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+        let mut tmp = File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        tmp.write_all(content.as_bytes())
+            .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        tmp.sync_all()
+            .with_context(|| format!("Failed to sync {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace quota usage: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Midnight at the end of the day containing `now`
+pub fn day_end(now: &Zoned) -> Zoned {
+    let tomorrow = now.date().tomorrow().unwrap_or(now.date());
+    tomorrow
+        .to_zoned(now.time_zone().clone())
+        .unwrap_or_else(|_| now.clone())
+}
+
+/// Midnight at the start of the month after the one containing `now`
+fn month_end(now: &Zoned) -> Zoned {
+    let first = now.date().first_of_month();
+    first
+        .checked_add(1.month())
+        .and_then(|next| next.to_zoned(now.time_zone().clone()))
+        .unwrap_or_else(|_| now.clone())
+}
+
+/// A period end as shown to clients, e.g. "Sun 2026-10-18 00:00 EEST"
+pub fn describe(at: &Zoned) -> String {
+    at.strftime("%a %Y-%m-%d %H:%M %Z").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DataQuota;
+
+    const ALICE: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const BOB: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    fn at(time: &str) -> Zoned {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_counters_start_over_each_period() {
+        let ledger = QuotaLedger::in_memory();
+        let alice: EndpointId = ALICE.parse().unwrap();
+        let device = DeviceId(3);
+        let limits = DataLimits {
+            daily: Some(1_000),
+            monthly: Some(10_000),
+        };
+
+        let evening = at("2026-10-31T23:30:00+02:00[Europe/Helsinki]");
+        ledger.add_time(&alice, device, 1800, &evening);
+        ledger.add_data(&alice, 600, &evening);
+        assert_eq!(ledger.time_used(&alice, device, &evening), 1800);
+        let usage = ledger.data_usage(&alice, limits, &evening);
+        assert_eq!(usage[0].used, 600);
+        assert_eq!(usage[0].resets_at, "Sun 2026-11-01 00:00 EET");
+        assert_eq!(usage[1].used, 600);
+        assert_eq!(usage[1].resets_at, "Sun 2026-11-01 00:00 EET");
+
+        // The next day is also the next month
+        let morning = at("2026-11-01T08:00:00+02:00[Europe/Helsinki]");
+        assert_eq!(ledger.time_used(&alice, device, &morning), 0);
+        ledger.add_data(&alice, 300, &morning);
+        let usage = ledger.data_usage(&alice, limits, &morning);
+        assert_eq!((usage[0].used, usage[1].used), (300, 300));
+        assert_eq!(usage[1].resets_at, "Tue 2026-12-01 00:00 EET");
+
+        // Later that month only the daily counter starts over
+        let later = at("2026-11-02T08:00:00+02:00[Europe/Helsinki]");
+        ledger.add_data(&alice, 50, &later);
+        let usage = ledger.data_usage(&alice, limits, &later);
+        assert_eq!((usage[0].used, usage[1].used), (50, 350));
+    }
+
+    #[test]
+    fn test_usage_persists_across_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("quotas.toml");
+        let alice: EndpointId = ALICE.parse().unwrap();
+        let now = at("2026-10-17T14:00:00+00:00[UTC]");
+
+        let ledger = QuotaLedger::load(&path).unwrap();
+        ledger.add_time(&alice, DeviceId(3), 5400, &now);
+        ledger.add_data(&alice, 1_200, &now);
+        ledger.save_if_dirty().unwrap();
+        assert!(path.exists());
+
+        let reloaded = QuotaLedger::load(&path).unwrap();
+        assert_eq!(reloaded.time_used(&alice, DeviceId(3), &now), 5400);
+        let usage = reloaded.data_usage(
+            &alice,
+            DataLimits {
+                daily: Some(1_000),
+                monthly: None,
+            },
+            &now,
+        );
+        assert_eq!(usage.len(), 1);
+        assert!(usage[0].is_exhausted());
+    }
+
+    #[test]
+    fn test_client_limits_override_defaults() {
+        let alice: EndpointId = ALICE.parse().unwrap();
+        let bob: EndpointId = BOB.parse().unwrap();
+        let clients = BTreeMap::from([("alice".to_string(), ALICE.to_string())]);
+        let groups = BTreeMap::from([(
+            "lab".to_string(),
+            vec!["alice".to_string(), BOB.to_string()],
+        )]);
+        let directory = ClientDirectory::new(&clients, &groups).unwrap();
+
+        let settings = QuotaSettings {
+            daily_data: Some("1GB".to_string()),
+            monthly_data: Some("10GB".to_string()),
+            clients: BTreeMap::from([
+                (
+                    "@lab".to_string(),
+                    DataQuota {
+                        daily_data: Some("2GB".to_string()),
+                        monthly_data: None,
+                    },
+                ),
+                (
+                    "alice".to_string(),
+                    DataQuota {
+                        daily_data: Some("5GB".to_string()),
+                        monthly_data: Some("1TB".to_string()),
+                    },
+                ),
+            ]),
+            usage_path: None,
+        };
+        let limits = QuotaLimits::new(&settings);
+
+        assert_eq!(
+            limits.for_client(&directory, &bob),
+            DataLimits {
+                daily: Some(2_000_000_000),
+                monthly: Some(10_000_000_000),
+            }
+        );
+        assert_eq!(
+            limits.for_client(&directory, &alice),
+            DataLimits {
+                daily: Some(5_000_000_000),
+                monthly: Some(1_000_000_000_000),
+            }
+        );
+        assert!(
+            QuotaLimits::default()
+                .for_client(&directory, &alice)
+                .is_unlimited()
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! Device reservations
+//!
+//! Clients book a device for a period ("hil-rig-1 has the JTAG probe from
+//! 14:00 to 16:00 on Thursday"); while a booking is active only its holder
+//! can attach or lock the device. The policy engine enforces bookings (see
+//! [`crate::policy::PolicyEngine::create_reservation`]); this module only
+//! keeps them.
+//!
+//! Bookings are kept in a small TOML file so they survive a restart:
+//!
+//! ```toml
+//! next_id = 4
+//!
+//! [[reservations]]
+//! id = 3
+//! device_id = 2
+//! holder = "<EndpointId>"
+//! starts_at = 1792245600
+//! ends_at = 1792252800
+//! note = "JTAG flashing"
+//! ```
+
+use anyhow::{Context, Result};
+use iroh::PublicKey as EndpointId;
+use protocol::{DeviceId, Reservation, ReservationError, ReservationId};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Longest note kept with a reservation, in characters
+const MAX_NOTE_CHARS: usize = 200;
+
+/// The current time in Unix seconds, which reservations are kept in
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Shared handle to the reservation book
+pub type SharedReservationBook = Arc<ReservationBook>;
+
+/// On-disk format of the reservation file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct BookFile {
+    /// ID the next reservation gets
+    #[serde(default)]
+    next_id: u64,
+    #[serde(default)]
+    reservations: Vec<StoredReservation>,
+}
+
+/// One reservation as saved (holder names are looked up when listing)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StoredReservation {
+    id: u64,
+    device_id: u32,
+    holder: String,
+    starts_at: u64,
+    ends_at: u64,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+impl StoredReservation {
+    fn to_reservation(&self) -> Reservation {
+        Reservation {
+            id: ReservationId(self.id),
+            device_id: DeviceId(self.device_id),
+            holder: self.holder.clone(),
+            holder_name: None,
+            starts_at: self.starts_at,
+            ends_at: self.ends_at,
+            note: self.note.clone(),
+        }
+    }
+}
+
+/// Reservations of every device, persisted across restarts
+///
+/// Times are Unix seconds. Reservations that have ended are dropped by
+/// [`ReservationBook::prune`].
+#[derive(Debug)]
+pub struct ReservationBook {
+    /// File reservations are saved to (None = not persisted)
+    path: Option<PathBuf>,
+    /// Reservations, ordered by start
+    book: Mutex<BookFile>,
+}
+
+impl Default for ReservationBook {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl ReservationBook {
+    /// Book that is not saved anywhere
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            book: Mutex::new(BookFile::default()),
+        }
+    }
+
+    /// Load the reservations saved at `path`
+    ///
+    /// A missing file yields an empty book that is created on the first
+    /// reservation. Entries whose holder is not an EndpointId are dropped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut file: BookFile = if path.exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read reservations: {}", path.display()))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse reservations: {}", path.display()))?
+        } else {
+            BookFile::default()
+        };
+
+        file.reservations
+            .retain(|entry| match entry.holder.parse::<EndpointId>() {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!("Ignoring reservation {}: {}", entry.id, e);
+                    false
+                }
+            });
+        file.reservations.sort_by_key(|entry| entry.starts_at);
+        let highest = file.reservations.iter().map(|entry| entry.id).max();
+        file.next_id = file.next_id.max(highest.map_or(1, |id| id + 1));
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            book: Mutex::new(file),
+        })
+    }
+
+    /// Reserve a device for `[starts_at, ends_at)`
+    ///
+    /// Fails with `Conflict` when the period overlaps another reservation
+    /// of the device, whoever holds it. Limits on the period are the
+    /// policy engine's to check.
+    pub fn reserve(
+        &self,
+        holder: &EndpointId,
+        device_id: DeviceId,
+        starts_at: u64,
+        ends_at: u64,
+        note: Option<String>,
+    ) -> Result<Reservation, ReservationError> {
+        if ends_at <= starts_at {
+            return Err(ReservationError::InvalidPeriod {
+                reason: "ends before it starts".to_string(),
+            });
+        }
+
+        let mut book = self.book.lock().unwrap();
+        if let Some(existing) = book
+            .reservations
+            .iter()
+            .map(StoredReservation::to_reservation)
+            .find(|r| r.device_id == device_id && r.overlaps(starts_at, ends_at))
+        {
+            return Err(ReservationError::Conflict {
+                reservation: existing,
+            });
+        }
+
+        let entry = StoredReservation {
+            id: book.next_id.max(1),
+            device_id: device_id.0,
+            holder: holder.to_string(),
+            starts_at,
+            ends_at,
+            note: note
+                .map(|note| note.trim().chars().take(MAX_NOTE_CHARS).collect::<String>())
+                .filter(|note| !note.is_empty()),
+        };
+        book.next_id = entry.id + 1;
+        let reservation = entry.to_reservation();
+        let index = book
+            .reservations
+            .partition_point(|other| other.starts_at <= starts_at);
+        book.reservations.insert(index, entry);
+        self.save(&book);
+        Ok(reservation)
+    }
+
+    /// Cancel a reservation
+    ///
+    /// `by` is the client asking, which must hold the reservation; None is
+    /// the operator, who may cancel any. Returns the cancelled reservation.
+    pub fn cancel(
+        &self,
+        id: ReservationId,
+        by: Option<&EndpointId>,
+    ) -> Result<Reservation, ReservationError> {
+        let mut book = self.book.lock().unwrap();
+        let index = book
+            .reservations
+            .iter()
+            .position(|entry| entry.id == id.0)
+            .ok_or(ReservationError::NotFound)?;
+        if by.is_some_and(|client| book.reservations[index].holder != client.to_string()) {
+            return Err(ReservationError::NotHolder);
+        }
+        let entry = book.reservations.remove(index);
+        self.save(&book);
+        Ok(entry.to_reservation())
+    }
+
+    /// Reservations that have not ended by `now`, ordered by start
+    ///
+    /// `device_id` limits the list to one device.
+    pub fn list(&self, device_id: Option<DeviceId>, now: u64) -> Vec<Reservation> {
+        self.book
+            .lock()
+            .unwrap()
+            .reservations
+            .iter()
+            .filter(|entry| entry.ends_at > now)
+            .filter(|entry| device_id.is_none_or(|id| id.0 == entry.device_id))
+            .map(StoredReservation::to_reservation)
+            .collect()
+    }
+
+    /// The reservation of a device active at `now`, if any
+    pub fn active(&self, device_id: DeviceId, now: u64) -> Option<Reservation> {
+        self.list(Some(device_id), now)
+            .into_iter()
+            .find(|r| r.is_active_at(now))
+    }
+
+    /// The first reservation of a device by a client other than `client`
+    /// that has not ended by `now`
+    pub fn next_for_others(
+        &self,
+        device_id: DeviceId,
+        client: &EndpointId,
+        now: u64,
+    ) -> Option<Reservation> {
+        let client = client.to_string();
+        self.list(Some(device_id), now)
+            .into_iter()
+            .find(|r| r.holder != client)
+    }
+
+    /// Drop reservations that ended by `now`, returning them
+    pub fn prune(&self, now: u64) -> Vec<Reservation> {
+        let mut book = self.book.lock().unwrap();
+        let (ended, current): (Vec<_>, Vec<_>) = book
+            .reservations
+            .drain(..)
+            .partition(|entry| entry.ends_at <= now);
+        book.reservations = current;
+        if !ended.is_empty() {
+            self.save(&book);
+        }
+        ended
+            .iter()
+            .map(StoredReservation::to_reservation)
+            .collect()
+    }
+
+    /// Write the book to disk, logging failures
+    ///
+    /// A no-op for in-memory books. Reservations change rarely, so each
+    /// change is saved at once; a failed save keeps the change in memory.
+    fn save(&self, book: &BookFile) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = Self::write(path, book) {
+            warn!("Failed to save reservations: {:#}", e);
+        }
+    }
+
+    /// Replace the file through a synced temporary copy, like the quota
+    /// ledger, so a crash never leaves a truncated book
+    fn write(path: &Path, file: &BookFile) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create reservation directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        let content = toml::to_string_pretty(file).context("Failed to serialize reservations")?;
+
+        let mut tmp_name = OsString::from(path.as_os_str());
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+        let mut tmp = File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        tmp.write_all(content.as_bytes())
+            .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        tmp.sync_all()
+            .with_context(|| format!("Failed to sync {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace reservations: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const BOB: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    const HOUR: u64 = 3600;
+    /// Thu 2026-10-22 14:00 UTC
+    const THURSDAY_2PM: u64 = 1_792_677_600;
+
+    #[test]
+    fn test_overlapping_reservations_conflict() {
+        let book = ReservationBook::in_memory();
+        let alice: EndpointId = ALICE.parse().unwrap();
+        let bob: EndpointId = BOB.parse().unwrap();
+        let probe = DeviceId(2);
+
+        let afternoon = book
+            .reserve(
+                &alice,
+                probe,
+                THURSDAY_2PM,
+                THURSDAY_2PM + 2 * HOUR,
+                Some("  JTAG flashing ".to_string()),
+            )
+            .unwrap();
+        assert_eq!(afternoon.id, ReservationId(1));
+        assert_eq!(afternoon.note.as_deref(), Some("JTAG flashing"));
+
+        // Overlapping the end, even by the same holder
+        let err = book
+            .reserve(
+                &bob,
+                probe,
+                THURSDAY_2PM + HOUR,
+                THURSDAY_2PM + 3 * HOUR,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ReservationError::Conflict {
+                reservation: afternoon.clone()
+            }
+        );
+        assert!(
+            book.reserve(&alice, probe, THURSDAY_2PM - HOUR, THURSDAY_2PM + 1, None)
+                .is_err()
+        );
+
+        // Back to back, and on another device, is fine
+        let evening = book
+            .reserve(
+                &bob,
+                probe,
+                THURSDAY_2PM + 2 * HOUR,
+                THURSDAY_2PM + 3 * HOUR,
+                None,
+            )
+            .unwrap();
+        assert!(
+            book.reserve(
+                &bob,
+                DeviceId(3),
+                THURSDAY_2PM,
+                THURSDAY_2PM + 2 * HOUR,
+                None
+            )
+            .is_ok()
+        );
+        assert!(matches!(
+            book.reserve(&bob, probe, THURSDAY_2PM, THURSDAY_2PM, None),
+            Err(ReservationError::InvalidPeriod { .. })
+        ));
+
+        let during = THURSDAY_2PM + HOUR;
+        assert_eq!(book.active(probe, during), Some(afternoon.clone()));
+        assert_eq!(book.next_for_others(probe, &bob, during), Some(afternoon));
+        assert_eq!(book.next_for_others(probe, &alice, during), Some(evening));
+        assert_eq!(book.list(Some(probe), during).len(), 2);
+        assert_eq!(book.list(None, during).len(), 3);
+    }
+
+    #[test]
+    fn test_only_the_holder_cancels() {
+        let book = ReservationBook::in_memory();
+        let alice: EndpointId = ALICE.parse().unwrap();
+        let bob: EndpointId = BOB.parse().unwrap();
+        let reservation = book
+            .reserve(&alice, DeviceId(2), THURSDAY_2PM, THURSDAY_2PM + HOUR, None)
+            .unwrap();
+
+        assert_eq!(
+            book.cancel(reservation.id, Some(&bob)),
+            Err(ReservationError::NotHolder)
+        );
+        assert_eq!(book.cancel(reservation.id, Some(&alice)), Ok(reservation));
+        assert_eq!(
+            book.cancel(ReservationId(1), None),
+            Err(ReservationError::NotFound)
+        );
+
+        // The operator cancels anyone's
+        let reservation = book
+            .reserve(&alice, DeviceId(2), THURSDAY_2PM, THURSDAY_2PM + HOUR, None)
+            .unwrap();
+        assert_eq!(reservation.id, ReservationId(2));
+        assert!(book.cancel(reservation.id, None).is_ok());
+        assert!(book.list(None, 0).is_empty());
+    }
+
+    #[test]
+    fn test_reservations_persist_across_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("reservations.toml");
+        let alice: EndpointId = ALICE.parse().unwrap();
+
+        let book = ReservationBook::load(&path).unwrap();
+        let early = book
+            .reserve(&alice, DeviceId(2), THURSDAY_2PM, THURSDAY_2PM + HOUR, None)
+            .unwrap();
+        let late = book
+            .reserve(
+                &alice,
+                DeviceId(2),
+                THURSDAY_2PM + 2 * HOUR,
+                THURSDAY_2PM + 3 * HOUR,
+                Some("Soak test".to_string()),
+            )
+            .unwrap();
+        assert!(path.exists());
+
+        let reloaded = ReservationBook::load(&path).unwrap();
+        assert_eq!(reloaded.list(None, 0), vec![early.clone(), late.clone()]);
+
+        // Ended reservations are dropped, and IDs are not reused
+        assert_eq!(reloaded.prune(THURSDAY_2PM + HOUR), vec![early]);
+        let reloaded = ReservationBook::load(&path).unwrap();
+        assert_eq!(reloaded.list(None, 0), vec![late]);
+        let next = reloaded
+            .reserve(&alice, DeviceId(2), THURSDAY_2PM, THURSDAY_2PM + HOUR, None)
+            .unwrap();
+        assert_eq!(next.id, ReservationId(3));
+    }
+}
@@ -0,0 +1,483 @@
+//! Policy time windows and blackout dates
+//!
+//! A time window is `[DAYS] HH:MM-HH:MM [ZONE]`:
+//! - `DAYS` lists weekdays and ranges of them, e.g. `Mon-Fri`, `Sat,Sun` or
+//!   `Fri-Mon`; without it the window runs every day
+//! - times are 24-hour wall-clock times and `24:00` ends a window at
+//!   midnight; a window ending at or before its start runs overnight and
+//!   belongs to the day it starts on (`Fri 22:00-06:00` ends Saturday
+//!   morning)
+//! - `ZONE` is an IANA time zone such as `Europe/Helsinki`; without it the
+//!   server's `timezone` (or `timezone_offset_hours`) applies
+//!
+//! Wall-clock times follow the zone's DST rules, so "Mon-Fri 08:00-18:00
+//! Europe/Helsinki" opens at 08:00 local time all year.
+//!
+//! Blackout dates (`2026-12-24`, or the inclusive range
+//! `2026-12-24..2026-12-26`) close every window for the whole day, in the
+//! window's time zone.
+
+use anyhow::{Context, Result, anyhow, bail};
+use jiff::Timestamp;
+use jiff::civil::Date;
+use jiff::tz::TimeZone;
+
+/// Lower-case weekday names, Monday first
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Minutes in a day, the `24:00` end of a window
+const END_OF_DAY: u16 = 24 * 60;
+
+/// How many adjoining windows are followed to find when access ends
+///
+/// A schedule still open after that many (e.g. `00:00-24:00` every day)
+/// never closes as far as session limits are concerned.
+const MAX_ADJOINING_WINDOWS: usize = 16;
+
+/// How far ahead the next opening is looked for, in days
+const MAX_DAYS_AHEAD: i32 = 366;
+
+/// One time window, e.g. "Mon-Fri 08:00-18:00 Europe/Helsinki"
+#[derive(Debug, Clone)]
+pub struct TimeWindow {
+    /// Weekdays the window starts on, bit 0 = Monday
+    days: u8,
+    /// Start in minutes after midnight
+    start: u16,
+    /// End in minutes after midnight (at or before `start` = next day)
+    end: u16,
+    /// Time zone of the wall-clock times (None = the server's)
+    zone: Option<TimeZone>,
+}
+
+impl TimeWindow {
+    /// Parse a window like "Mon-Fri 08:00-18:00 Europe/Helsinki"
+    pub fn parse(window: &str) -> Result<Self> {
+        let mut tokens: Vec<&str> = window.split_whitespace().collect();
+        let starts_with_letter =
+            |token: &&str| token.starts_with(|c: char| c.is_ascii_alphabetic());
+
+        let days = match tokens.first().copied().filter(starts_with_letter) {
+            Some(days) => {
+                let days = parse_days(days)?;
+                tokens.remove(0);
+                days
+            }
+            None => 0x7f,
+        };
+        let zone = match tokens.last().copied().filter(starts_with_letter) {
+            Some(name) => {
+                let zone =
+                    TimeZone::get(name).with_context(|| format!("Unknown time zone '{}'", name))?;
+                tokens.pop();
+                Some(zone)
+            }
+            None => None,
+        };
+
+        // What is left is the time range, possibly written "09:00 - 17:00"
+        let range = tokens.concat();
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Expected a time range like 09:00-17:00"))?;
+        let start = parse_time(start).filter(|start| *start < END_OF_DAY);
+        let start = start.ok_or_else(|| anyhow!("Invalid start time in '{}'", range))?;
+        let end = parse_time(end).ok_or_else(|| anyhow!("Invalid end time in '{}'", range))?;
+        if start == end {
+            bail!("Time window '{}' starts and ends at the same time", range);
+        }
+
+        Ok(Self {
+            days,
+            start,
+            end,
+            zone,
+        })
+    }
+
+    /// A window covering every whole day
+    fn all_day() -> Self {
+        Self {
+            days: 0x7f,
+            start: 0,
+            end: END_OF_DAY,
+            zone: None,
+        }
+    }
+
+    /// Time zone of the window, `default` when it names none
+    fn zone<'a>(&'a self, default: &'a TimeZone) -> &'a TimeZone {
+        self.zone.as_ref().unwrap_or(default)
+    }
+
+    /// Start and end of the occurrence starting on `day`, if the window runs then
+    fn occurrence_on(&self, day: Date, zone: &TimeZone) -> Option<(Timestamp, Timestamp)> {
+        let weekday = day.weekday().to_monday_zero_offset();
+        if self.days & (1 << weekday) == 0 {
+            return None;
+        }
+        let end_day = if self.end <= self.start {
+            day.tomorrow().ok()?
+        } else {
+            day
+        };
+        Some((
+            local_time(day, self.start, zone)?,
+            local_time(end_day, self.end, zone)?,
+        ))
+    }
+
+    /// End of the occurrence containing `now`, if any
+    fn end_of_occurrence_at(&self, now: Timestamp, zone: &TimeZone) -> Option<Timestamp> {
+        let today = now.to_zoned(zone.clone()).date();
+        // An overnight occurrence may have started yesterday
+        [today.yesterday().ok()?, today]
+            .into_iter()
+            .filter_map(|day| self.occurrence_on(day, zone))
+            .find(|(start, end)| *start <= now && now < *end)
+            .map(|(_, end)| end)
+    }
+}
+
+/// The time windows and blackout dates of a device policy
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    /// Windows access is allowed in (empty = never)
+    windows: Vec<TimeWindow>,
+    /// Inclusive date ranges every window is closed on
+    blackouts: Vec<(Date, Date)>,
+}
+
+impl Schedule {
+    /// Build a schedule from a policy's `time_windows` and `blackout_dates`
+    ///
+    /// Without time windows access is allowed all day, except on blackout
+    /// dates.
+    pub fn new(time_windows: &[String], blackout_dates: &[String]) -> Result<Self> {
+        let mut windows = time_windows
+            .iter()
+            .map(|window| {
+                TimeWindow::parse(window)
+                    .with_context(|| format!("Invalid time window '{}'", window))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if windows.is_empty() {
+            windows.push(TimeWindow::all_day());
+        }
+        let blackouts = blackout_dates
+            .iter()
+            .map(|dates| {
+                parse_date_range(dates)
+                    .with_context(|| format!("Invalid blackout date '{}'", dates))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { windows, blackouts })
+    }
+
+    /// A schedule that never allows access
+    pub fn closed() -> Self {
+        Self {
+            windows: Vec::new(),
+            blackouts: Vec::new(),
+        }
+    }
+
+    /// Whether access is allowed at `now`
+    ///
+    /// `default_zone` applies to windows that name no time zone.
+    pub fn is_open(&self, now: Timestamp, default_zone: &TimeZone) -> bool {
+        self.open_until(now, default_zone).is_some()
+    }
+
+    /// When access allowed at `now` ends, following adjoining windows
+    ///
+    /// None when access is not allowed at `now` or does not end in the
+    /// foreseeable future.
+    pub fn closes_at(&self, now: Timestamp, default_zone: &TimeZone) -> Option<Timestamp> {
+        let mut end = self.open_until(now, default_zone)?;
+        for _ in 0..MAX_ADJOINING_WINDOWS {
+            match self.open_until(end, default_zone) {
+                Some(next) if next > end => end = next,
+                _ => return Some(end),
+            }
+        }
+        None
+    }
+
+    /// When access is next allowed after `now`, in the opening window's zone
+    pub fn next_opening(&self, now: Timestamp, default_zone: &TimeZone) -> Option<String> {
+        self.windows
+            .iter()
+            .filter_map(|window| {
+                let zone = window.zone(default_zone);
+                let today = now.to_zoned(zone.clone()).date();
+                (-1..=MAX_DAYS_AHEAD)
+                    .filter_map(|days| today.checked_add(jiff::Span::new().days(days)).ok())
+                    .filter_map(|day| window.occurrence_on(day, zone))
+                    .map(|(start, _)| start)
+                    .find(|start| *start > now && !self.is_blacked_out(*start, zone))
+                    .map(|start| (start, zone))
+            })
+            .min_by_key(|(start, _)| *start)
+            .map(|(start, zone)| describe(start, zone))
+    }
+
+    /// `now` as the schedule's first window sees it, e.g. "Sat 2026-10-17 14:05 EEST"
+    pub fn local_time(&self, now: Timestamp, default_zone: &TimeZone) -> String {
+        let zone = self
+            .windows
+            .first()
+            .map_or(default_zone, |window| window.zone(default_zone));
+        describe(now, zone)
+    }
+
+    /// End of the stretch of a single window that allows access at `now`
+    ///
+    /// The latest end among the windows containing `now`, cut short at
+    /// midnight when the next day is blacked out.
+    fn open_until(&self, now: Timestamp, default_zone: &TimeZone) -> Option<Timestamp> {
+        self.windows
+            .iter()
+            .filter_map(|window| {
+                let zone = window.zone(default_zone);
+                let end = window.end_of_occurrence_at(now, zone)?;
+                if self.is_blacked_out(now, zone) {
+                    return None;
+                }
+                let tomorrow = now.to_zoned(zone.clone()).date().tomorrow().ok()?;
+                let midnight = local_time(tomorrow, 0, zone)?;
+                if midnight < end && self.is_blacked_out(midnight, zone) {
+                    Some(midnight)
+                } else {
+                    Some(end)
+                }
+            })
+            .max()
+    }
+
+    /// Whether the date at `instant` in `zone` is a blackout date
+    fn is_blacked_out(&self, instant: Timestamp, zone: &TimeZone) -> bool {
+        let date = instant.to_zoned(zone.clone()).date();
+        self.blackouts
+            .iter()
+            .any(|(first, last)| *first <= date && date <= *last)
+    }
+}
+
+/// Parse a weekday list like "Mon-Fri" or "Sat,Sun" into a bit set
+fn parse_days(days: &str) -> Result<u8> {
+    let mut set = 0u8;
+    for part in days.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_weekday(first)?, parse_weekday(last)?),
+            None => {
+                let day = parse_weekday(part)?;
+                (day, day)
+            }
+        };
+        // Ranges may wrap around the week, e.g. Fri-Mon
+        let mut day = first;
+        loop {
+            set |= 1 << day;
+            if day == last {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Ok(set)
+}
+
+/// Parse a weekday name or its abbreviation ("Mon", "monday") to 0 = Monday
+fn parse_weekday(day: &str) -> Result<u8> {
+    let day = day.trim().to_ascii_lowercase();
+    WEEKDAYS
+        .iter()
+        .position(|name| day.len() >= 3 && name.starts_with(&day))
+        .map(|index| index as u8)
+        .ok_or_else(|| anyhow!("Unknown weekday '{}'", day))
+}
+
+/// Parse "HH:MM" into minutes after midnight (24:00 allowed)
+fn parse_time(time: &str) -> Option<u16> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: u16 = hours.parse().ok()?;
+    let minutes: u16 = minutes.parse().ok()?;
+    if minutes > 59 || hours * 60 + minutes > END_OF_DAY {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+/// Parse "2026-12-24" or "2026-12-24..2026-12-26" into an inclusive range
+fn parse_date_range(dates: &str) -> Result<(Date, Date)> {
+    let (first, last) = dates.split_once("..").unwrap_or((dates, dates));
+    let first: Date = first.trim().parse()?;
+    let last: Date = last.trim().parse()?;
+    if last < first {
+        bail!("Range ends before it starts");
+    }
+    Ok((first, last))
+}
+
+/// The instant `minutes` after midnight on `day` in `zone`
+///
+/// Times skipped by a DST change resolve to the same time after it.
+fn local_time(day: Date, minutes: u16, zone: &TimeZone) -> Option<Timestamp> {
+    let (day, minutes) = if minutes == END_OF_DAY {
+        (day.tomorrow().ok()?, 0)
+    } else {
+        (day, minutes)
+    };
+    let time = day.at((minutes / 60) as i8, (minutes % 60) as i8, 0, 0);
+    Some(time.to_zoned(zone.clone()).ok()?.timestamp())
+}
+
+/// `instant` in `zone`, e.g. "Mon 2026-03-30 08:00 EEST"
+fn describe(instant: Timestamp, zone: &TimeZone) -> String {
+    instant
+        .to_zoned(zone.clone())
+        .strftime("%a %Y-%m-%d %H:%M %Z")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(instant: &str) -> Timestamp {
+        instant.parse().unwrap()
+    }
+
+    fn schedule(windows: &[&str], blackout_dates: &[&str]) -> Schedule {
+        let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        Schedule::new(&strings(windows), &strings(blackout_dates)).unwrap()
+    }
+
+    #[test]
+    fn test_window_parsing() {
+        let window = TimeWindow::parse("Mon-Fri 08:00-18:00 Europe/Helsinki").unwrap();
+        assert_eq!(window.days, 0b0011111);
+        assert_eq!((window.start, window.end), (8 * 60, 18 * 60));
+        assert!(window.zone.is_some());
+
+        let window = TimeWindow::parse("Fri-Mon,wednesday 22:00 - 24:00").unwrap();
+        assert_eq!(window.days, 0b1110101);
+        assert_eq!((window.start, window.end), (22 * 60, END_OF_DAY));
+        assert!(window.zone.is_none());
+
+        assert_eq!(TimeWindow::parse("09:00-17:00").unwrap().days, 0x7f);
+        for invalid in [
+            "invalid",
+            "Mon-Fri",
+            "25:00-26:00",
+            "24:00-06:00",
+            "09:00-09:00",
+            "09:60-10:00",
+            "Mo 09:00-17:00",
+            "09:00-17:00 Mars/Olympus_Mons",
+        ] {
+            assert!(TimeWindow::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_windows_follow_dst() {
+        let helsinki = schedule(&["Mon-Fri 08:00-18:00 Europe/Helsinki"], &[]);
+        let utc = TimeZone::UTC;
+
+        // Friday before the switch to summer time: 08:00 is 06:00 UTC
+        assert!(!helsinki.is_open(at("2026-03-27T05:59:00Z"), &utc));
+        assert!(helsinki.is_open(at("2026-03-27T06:00:00Z"), &utc));
+        // The Monday after: 08:00 is 05:00 UTC
+        assert!(helsinki.is_open(at("2026-03-30T05:00:00Z"), &utc));
+        assert_eq!(
+            helsinki.closes_at(at("2026-03-30T05:00:00Z"), &utc),
+            Some(at("2026-03-30T15:00:00Z"))
+        );
+        // Not on the weekend in between
+        assert!(!helsinki.is_open(at("2026-03-28T10:00:00Z"), &utc));
+        assert_eq!(
+            helsinki
+                .next_opening(at("2026-03-28T10:00:00Z"), &utc)
+                .as_deref(),
+            Some("Mon 2026-03-30 08:00 EEST")
+        );
+    }
+
+    #[test]
+    fn test_overnight_windows_belong_to_their_start_day() {
+        let friday_night = schedule(&["Fri 22:00-06:00"], &[]);
+        let utc = TimeZone::UTC;
+
+        assert!(friday_night.is_open(at("2026-10-16T23:00:00Z"), &utc));
+        assert!(friday_night.is_open(at("2026-10-17T05:59:00Z"), &utc));
+        assert!(!friday_night.is_open(at("2026-10-17T22:30:00Z"), &utc));
+        assert_eq!(
+            friday_night.closes_at(at("2026-10-16T23:00:00Z"), &utc),
+            Some(at("2026-10-17T06:00:00Z"))
+        );
+
+        // Windows named without a zone use the server's
+        let helsinki = TimeZone::get("Europe/Helsinki").unwrap();
+        assert!(!friday_night.is_open(at("2026-10-16T20:30:00Z"), &utc));
+        assert!(friday_night.is_open(at("2026-10-16T20:30:00Z"), &helsinki));
+    }
+
+    #[test]
+    fn test_adjoining_windows_and_whole_days() {
+        let utc = TimeZone::UTC;
+        let split = schedule(&["09:00-12:00", "12:00-17:00"], &[]);
+        assert_eq!(
+            split.closes_at(at("2026-10-16T10:00:00Z"), &utc),
+            Some(at("2026-10-16T17:00:00Z"))
+        );
+
+        let always = schedule(&[], &[]);
+        assert!(always.is_open(at("2026-10-16T10:00:00Z"), &utc));
+        assert_eq!(always.closes_at(at("2026-10-16T10:00:00Z"), &utc), None);
+
+        assert!(!Schedule::closed().is_open(at("2026-10-16T10:00:00Z"), &utc));
+    }
+
+    #[test]
+    fn test_blackout_dates() {
+        let utc = TimeZone::UTC;
+        let weekdays = schedule(&["Mon-Fri 08:00-18:00"], &["2026-12-24..2026-12-25"]);
+        assert!(weekdays.is_open(at("2026-12-23T10:00:00Z"), &utc));
+        assert!(!weekdays.is_open(at("2026-12-24T10:00:00Z"), &utc));
+        assert_eq!(
+            weekdays
+                .next_opening(at("2026-12-24T10:00:00Z"), &utc)
+                .as_deref(),
+            Some("Mon 2026-12-28 08:00 UTC")
+        );
+
+        // Overnight access stops at the start of a blackout date
+        let nights = schedule(&["22:00-06:00"], &["2026-12-25"]);
+        assert_eq!(
+            nights.closes_at(at("2026-12-24T23:00:00Z"), &utc),
+            Some(at("2026-12-25T00:00:00Z"))
+        );
+
+        // Without windows, only the blackout dates are closed
+        let holidays = schedule(&[], &["2026-12-25"]);
+        assert!(!holidays.is_open(at("2026-12-25T12:00:00Z"), &utc));
+        assert_eq!(
+            holidays.closes_at(at("2026-12-24T12:00:00Z"), &utc),
+            Some(at("2026-12-25T00:00:00Z"))
+        );
+
+        for invalid in ["2026-13-01", "2026-12-26..2026-12-24", "christmas"] {
+            assert!(Schedule::new(&[], &[invalid.to_string()]).is_err());
+        }
+    }
+}
@@ -0,0 +1,775 @@
+//! In-process end-to-end test harness
+//!
+//! Runs a real [`IrohServer`] and [`IrohClient`] in the test process, talking
+//! over loopback with relays and discovery disabled. The server's USB worker
+//! is backed by an [`EmulatedBus`], so tests can list, attach and transfer,
+//! plug and unplug devices, and then check the audit log and metrics the
+//! server recorded, without network access or USB hardware. Further clients
+//! can be created to exercise the approval queue and pairing invites, the
+//! configuration can be rewritten and reloaded while sessions run, and the
+//! admin control socket can be served from the temporary directory.
+
+use anyhow::{Context, Result, anyhow};
+use client::network::{ClientConfig, DeviceNotification, IrohClient};
+use common::test_utils::{DEFAULT_TEST_TIMEOUT, loopback_endpoint_addr, with_timeout};
+use common::{ALPN_PROTOCOL, MetricsSnapshot, UsbBridge, UsbCommand, create_usb_bridge};
+use iroh::EndpointAddr;
+use iroh::PublicKey as EndpointId;
+use protocol::{DeviceHandle, DeviceInfo, RequestId, TransferResult, TransferType, UsbRequest};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::broadcast;
+
+use crate::audit::{AuditEntry, AuditLevel, create_audit_logger};
+use crate::config::ServerConfig;
+use crate::control::{ControlServer, ControlState};
+use crate::network::{
+    IrohServer, SharedApprovalQueue, SharedConfigReloader, SharedInviteStore, SharedServerMetrics,
+};
+use crate::usb::emulated::{EmulatedBackend, EmulatedBus};
+use crate::usb::{DeviceRegistry, spawn_usb_worker};
+
+/// A connected server and client pair over an emulated USB bus
+pub struct LoopbackHarness {
+    /// Client connected to the server
+    pub client: IrohClient,
+    /// EndpointId of the server
+    pub server_id: EndpointId,
+    server_addr: EndpointAddr,
+    approvals: SharedApprovalQueue,
+    invites: SharedInviteStore,
+    reloader: SharedConfigReloader,
+    control: ControlState,
+    config: ServerConfig,
+    config_path: PathBuf,
+    bus: EmulatedBus,
+    usb_bridge: UsbBridge,
+    usb_worker: Option<JoinHandle<Result<(), rusb::Error>>>,
+    server_task: tokio::task::JoinHandle<()>,
+    metrics: SharedServerMetrics,
+    notifications: broadcast::Receiver<DeviceNotification>,
+    audit_path: PathBuf,
+    next_request_id: AtomicU64,
+    dir: TempDir,
+}
+
+impl LoopbackHarness {
+    /// Start a server sharing `bus` and connect a client to it
+    ///
+    /// The server only accepts the harness client, logs every audit event to
+    /// a temporary file and keeps its keys, device registry, approval
+    /// decisions and invites there too.
+    pub async fn start(bus: EmulatedBus) -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+
+        let client = IrohClient::new(ClientConfig {
+            alpn: ALPN_PROTOCOL.to_vec(),
+            secret_key_path: Some(dir.path().join("client.key")),
+            relay_servers: Some(Vec::new()),
+            ..Default::default()
+        })
+        .await?;
+
+        let mut config = ServerConfig::default();
+        config.iroh.relay_servers = Some(Vec::new());
+        config.iroh.secret_key_path = Some(dir.path().join("server.key"));
+        config.security.approved_clients = vec![client.endpoint_id().to_string()];
+        config.security.invites_path = Some(dir.path().join("invites.toml"));
+        config.audit.enabled = true;
+        config.audit.level = AuditLevel::All;
+        config.audit.path = dir.path().join("audit.log");
+        config.audit.stats_interval_secs = 0;
+
+        let (usb_bridge, worker) = create_usb_bridge();
+        let usb_worker = spawn_usb_worker(
+            worker,
+            Box::new(EmulatedBackend::new(bus.clone())),
+            Vec::new(),
+            DeviceRegistry::in_memory(),
+        );
+
+        let audit_logger = create_audit_logger(config.audit.clone());
+        let audit_path = config.audit.path.clone();
+        let config_path = dir.path().join("server.toml");
+        let server = IrohServer::new(config.clone(), usb_bridge.clone(), audit_logger.clone())
+            .await?
+            .with_config_path(config_path.clone());
+        let server_id = server.endpoint_id();
+        let server_addr = loopback_endpoint_addr(server_id, &server.local_addrs());
+        let metrics = server.metrics();
+        let approvals = server.approvals();
+        let invites = server.invites();
+        let reloader = server.reloader();
+        let control = ControlState::new(&server, usb_bridge.clone(), audit_logger);
+        let server_task = tokio::spawn(async move {
+            let _ = server.run().await;
+        });
+
+        client.add_allowed_server(server_id).await;
+        with_timeout(
+            DEFAULT_TEST_TIMEOUT,
+            client.connect_to_server(server_id, Some(server_addr.clone())),
+        )
+        .await??;
+        let notifications = client
+            .subscribe_notifications(server_id)
+            .await
+            .context("Connection has no notification channel")?;
+
+        Ok(Self {
+            client,
+            server_id,
+            server_addr,
+            approvals,
+            invites,
+            reloader,
+            control,
+            config,
+            config_path,
+            bus,
+            usb_bridge,
+            usb_worker: Some(usb_worker),
+            server_task,
+            metrics,
+            notifications,
+            audit_path,
+            next_request_id: AtomicU64::new(1),
+            dir,
+        })
+    }
+
+    /// Create another client that trusts the server
+    ///
+    /// The server has not approved it, so connecting with [`Self::server_addr`]
+    /// parks it in the approval queue until the test decides.
+    pub async fn new_client(&self, name: &str) -> Result<IrohClient> {
+        let client = IrohClient::new(ClientConfig {
+            alpn: ALPN_PROTOCOL.to_vec(),
+            secret_key_path: Some(self.dir.path().join(format!("{}.key", name))),
+            relay_servers: Some(Vec::new()),
+            ..Default::default()
+        })
+        .await?;
+        client.add_allowed_server(self.server_id).await;
+        Ok(client)
+    }
+
+    /// Loopback address of the server
+    pub fn server_addr(&self) -> EndpointAddr {
+        self.server_addr.clone()
+    }
+
+    /// Queue of clients the server is holding for approval
+    pub fn approvals(&self) -> &SharedApprovalQueue {
+        &self.approvals
+    }
+
+    /// Pairing invites the server accepts
+    pub fn invites(&self) -> &SharedInviteStore {
+        &self.invites
+    }
+
+    /// Configuration the server started with
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Configuration file approval decisions are saved to and reloaded from
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    /// Reloader that applies the configuration file to the running server
+    pub fn reloader(&self) -> &SharedConfigReloader {
+        &self.reloader
+    }
+
+    /// Serve the admin control socket from the temporary directory
+    ///
+    /// Returns the socket path; the listener runs until the test ends.
+    pub fn serve_control_socket(&self) -> Result<PathBuf> {
+        let path = self.dir.path().join("control.sock");
+        let server = ControlServer::bind(&path, self.control.clone())?;
+        tokio::spawn(server.run());
+        Ok(path)
+    }
+
+    /// Emulated bus the server shares, for plugging and unplugging devices
+    pub fn bus(&self) -> &EmulatedBus {
+        &self.bus
+    }
+
+    /// Devices the server currently shares
+    pub async fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
+        self.client.list_remote_devices(self.server_id).await
+    }
+
+    /// Attach the shared device whose product string is `product`
+    pub async fn attach(&self, product: &str) -> Result<(DeviceInfo, DeviceHandle)> {
+        let device = self
+            .list_devices()
+            .await?
+            .into_iter()
+            .find(|device| device.product.as_deref() == Some(product))
+            .ok_or_else(|| anyhow!("No shared device named {:?}", product))?;
+        let handle = self.client.attach_device(self.server_id, device.id).await?;
+        Ok((device, handle))
+    }
+
+    /// Submit a transfer through the client and wait for its result
+    pub async fn transfer(
+        &self,
+        handle: DeviceHandle,
+        transfer: TransferType,
+    ) -> Result<TransferResult> {
+        let request = UsbRequest {
+            id: RequestId(self.next_request_id.fetch_add(1, Ordering::Relaxed)),
+            handle,
+            transfer,
+        };
+        let response = with_timeout(
+            DEFAULT_TEST_TIMEOUT,
+            self.client.submit_transfer(self.server_id, request),
+        )
+        .await??;
+        Ok(response.result)
+    }
+
+    /// Wait for the next device notification matching `matches`
+    ///
+    /// Hotplug notifications only arrive after the server's debounce delay
+    /// and notification batching, so this allows for the default timeout.
+    pub async fn wait_for_notification(
+        &mut self,
+        matches: impl Fn(&DeviceNotification) -> bool,
+    ) -> Result<DeviceNotification> {
+        let notifications = &mut self.notifications;
+        with_timeout(DEFAULT_TEST_TIMEOUT, async {
+            loop {
+                match notifications.recv().await {
+                    Ok(notification) if matches(&notification) => return Ok(notification),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(e) => return Err(anyhow!("Notification channel closed: {}", e)),
+                }
+            }
+        })
+        .await?
+    }
+
+    /// Wait until the audit log holds an entry matching `matches`
+    ///
+    /// Returns every entry logged so far. The audit logger writes in the
+    /// background, so this polls the file until the entry shows up.
+    pub async fn wait_for_audit(
+        &self,
+        matches: impl Fn(&AuditEntry) -> bool,
+    ) -> Result<Vec<AuditEntry>> {
+        with_timeout(DEFAULT_TEST_TIMEOUT, async {
+            loop {
+                let entries = self.audit_entries()?;
+                if entries.iter().any(&matches) {
+                    return Ok(entries);
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await?
+    }
+
+    /// Audit entries the server has written so far
+    pub fn audit_entries(&self) -> Result<Vec<AuditEntry>> {
+        let log = match std::fs::read_to_string(&self.audit_path) {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        log.lines()
+            .map(|line| serde_json::from_str(line).context("Malformed audit entry"))
+            .collect()
+    }
+
+    /// Server-side metrics for the harness client
+    pub fn client_metrics(&self) -> Option<MetricsSnapshot> {
+        self.metrics
+            .client_snapshot(&self.client.endpoint_id().to_string())
+    }
+
+    /// Disconnect the client and stop the server and USB worker
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.client.shutdown().await?;
+        self.server_task.abort();
+        self.usb_bridge.send_command(UsbCommand::Shutdown).await?;
+        if let Some(worker) = self.usb_worker.take() {
+            tokio::task::spawn_blocking(move || worker.join())
+                .await?
+                .map_err(|_| anyhow!("USB worker panicked"))??;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditEventType;
+    use crate::network::DEFAULT_INVITE_TTL;
+    use crate::usb::emulated::{CdcAcm, HidKeyboard};
+    use client::network::ConnectionState;
+
+    /// Connect `client` and apply the operator's decision once it is parked
+    async fn connect_and_decide(
+        harness: &LoopbackHarness,
+        client: &IrohClient,
+        approve: bool,
+    ) -> Result<()> {
+        let mut states = client.subscribe();
+        let connect = client.connect_to_server(harness.server_id, Some(harness.server_addr()));
+        let decide = async {
+            loop {
+                let (server_id, state) = states.recv().await?;
+                if server_id == harness.server_id && state == ConnectionState::AwaitingApproval {
+                    break;
+                }
+            }
+            let pending = harness.approvals().pending();
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].endpoint_id, client.endpoint_id());
+            if approve {
+                harness.approvals().approve(client.endpoint_id()).await
+            } else {
+                harness.approvals().deny(client.endpoint_id()).await
+            }
+        };
+        let (connected, decided) = with_timeout(DEFAULT_TEST_TIMEOUT, async {
+            tokio::join!(connect, decide)
+        })
+        .await?;
+        decided?;
+        connected
+    }
+
+    #[tokio::test]
+    async fn test_unknown_client_is_admitted_once_approved() {
+        let bus = EmulatedBus::new();
+        bus.plug(CdcAcm::new()).unwrap();
+        let harness = LoopbackHarness::start(bus).await.unwrap();
+        let other = harness.new_client("other").await.unwrap();
+
+        connect_and_decide(&harness, &other, true).await.unwrap();
+        let devices = other.list_remote_devices(harness.server_id).await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert!(harness.approvals().pending().is_empty());
+
+        let saved = ServerConfig::load(Some(harness.config_path().to_path_buf())).unwrap();
+        assert!(
+            saved
+                .security
+                .approved_clients
+                .contains(&other.endpoint_id().to_string())
+        );
+
+        other.shutdown().await.unwrap();
+        harness.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_client_is_told_when_denied() {
+        let harness = LoopbackHarness::start(EmulatedBus::new()).await.unwrap();
+        let other = harness.new_client("other").await.unwrap();
+
+        let err = connect_and_decide(&harness, &other, false)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("denied access"), "{:#}", err);
+
+        harness
+            .wait_for_audit(|entry| matches!(entry.event_type, AuditEventType::ConfigurationChange))
+            .await
+            .unwrap();
+        let saved = ServerConfig::load(Some(harness.config_path().to_path_buf())).unwrap();
+        assert_eq!(
+            saved.security.denied_clients,
+            vec![other.endpoint_id().to_string()]
+        );
+
+        other.shutdown().await.unwrap();
+        harness.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invite_enrolls_client_once() {
+        let bus = EmulatedBus::new();
+        bus.plug(CdcAcm::new()).unwrap();
+        bus.plug(HidKeyboard::new()).unwrap();
+        let harness = LoopbackHarness::start(bus).await.unwrap();
+        let invite = harness
+            .invites()
+            .mint(DEFAULT_INVITE_TTL, vec!["class:02".to_string()])
+            .unwrap();
+
+        // A valid invite admits the client without asking the operator
+        let other = harness.new_client("other").await.unwrap();
+        other
+            .set_invite(harness.server_id, invite.token.clone())
+            .await;
+        with_timeout(
+            DEFAULT_TEST_TIMEOUT,
+            other.connect_to_server(harness.server_id, Some(harness.server_addr())),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(harness.approvals().pending().is_empty());
+        harness
+            .wait_for_audit(|entry| matches!(entry.event_type, AuditEventType::InviteRedeemed))
+            .await
+            .unwrap();
+
+        // ... limited to the devices the invite names
+        let devices = other.list_remote_devices(harness.server_id).await.unwrap();
+        let product = |name: &str| {
+            devices
+                .iter()
+                .find(|device| device.product.as_deref() == Some(name))
+                .unwrap()
+                .id
+        };
+        let serial = product("Emulated Serial Port");
+        let keyboard = product("Emulated Keyboard");
+        assert!(other.attach_device(harness.server_id, serial).await.is_ok());
+        let err = other
+            .attach_device(harness.server_id, keyboard)
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("this client may use"),
+            "{:#}",
+            err
+        );
+
+        let saved = ServerConfig::load(Some(harness.config_path().to_path_buf())).unwrap();
+        assert_eq!(
+            saved
+                .security
+                .client_devices
+                .get(&other.endpoint_id().to_string()),
+            Some(&vec!["class:02".to_string()])
+        );
+
+        // The invite is burned after its first use
+        let late = harness.new_client("late").await.unwrap();
+        late.set_invite(harness.server_id, invite.token).await;
+        let err = with_timeout(
+            DEFAULT_TEST_TIMEOUT,
+            late.connect_to_server(harness.server_id, Some(harness.server_addr())),
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("already used"), "{:#}", err);
+
+        late.shutdown().await.unwrap();
+        other.shutdown().await.unwrap();
+        harness.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_control_and_bulk_transfers_over_loopback() {
+        let bus = EmulatedBus::new();
+        bus.plug(CdcAcm::new()).unwrap();
+        let harness = LoopbackHarness::start(bus).await.unwrap();
+
+        let devices = harness.list_devices().await.unwrap();
+        assert_eq!(devices.len(), 1);
+        let (device, handle) = harness.attach("Emulated Serial Port").await.unwrap();
+
+        // SET_LINE_CODING then GET_LINE_CODING
+        let coding = vec![0x80, 0x25, 0x00, 0x00, 0, 0, 8];
+        let control = |request_type, request, data| TransferType::Control {
+            request_type,
+            request,
+            value: 0,
+            index: 0,
+            data,
+        };
+        let result = harness
+            .transfer(handle, control(0x21, 0x20, coding.clone()))
+            .await
+            .unwrap();
+        assert!(matches!(result, TransferResult::Success { .. }));
+        match harness
+            .transfer(handle, control(0xa1, 0x21, vec![0; 7]))
+            .await
+            .unwrap()
+        {
+            TransferResult::Success { data, .. } => assert_eq!(data, coding),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // Bulk OUT fills the loopback, bulk IN reads it back
+        let bulk = |endpoint, data| TransferType::Bulk {
+            endpoint,
+            data,
+            timeout_ms: 1000,
+            checksum: None,
+        };
+        let result = harness
+            .transfer(handle, bulk(0x02, b"ping".to_vec()))
+            .await
+            .unwrap();
+        assert!(matches!(result, TransferResult::Success { .. }));
+        match harness
+            .transfer(handle, bulk(0x82, vec![0; 64]))
+            .await
+            .unwrap()
+        {
+            TransferResult::Success { data, .. } => assert_eq!(data, b"ping"),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let client_id = harness.client.endpoint_id().to_string();
+        let entries = harness
+            .wait_for_audit(|entry| matches!(entry.event_type, AuditEventType::DeviceAttach))
+            .await
+            .unwrap();
+        assert!(entries.iter().any(|entry| {
+            matches!(entry.event_type, AuditEventType::ClientConnected)
+                && entry.endpoint_id.as_deref() == Some(client_id.as_str())
+        }));
+        let attach = entries
+            .iter()
+            .find(|entry| matches!(entry.event_type, AuditEventType::DeviceAttach))
+            .unwrap();
+        assert_eq!(attach.device_id, Some(device.id.0));
+
+        let metrics = harness.client_metrics().unwrap();
+        assert_eq!(metrics.transfers_completed, 4);
+        assert_eq!(metrics.transfers_failed, 0);
+        // Request payloads include the buffers sized for IN transfers
+        assert_eq!(metrics.bytes_received, (2 * coding.len() + 4 + 64) as u64);
+        assert_eq!(metrics.bytes_sent, (coding.len() + 4) as u64);
+
+        harness.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_transfer_and_hotplug() {
+        let mut harness = LoopbackHarness::start(EmulatedBus::new()).await.unwrap();
+        assert!(harness.list_devices().await.unwrap().is_empty());
+
+        let keyboard = HidKeyboard::new();
+        let input = keyboard.input();
+        let address = harness.bus().plug(keyboard).unwrap();
+        let arrived = harness
+            .wait_for_notification(|n| matches!(n, DeviceNotification::DeviceArrived { .. }))
+            .await
+            .unwrap();
+        let DeviceNotification::DeviceArrived { device } = arrived else {
+            unreachable!()
+        };
+        assert_eq!(device.product.as_deref(), Some("Emulated Keyboard"));
+
+        let (_, handle) = harness.attach("Emulated Keyboard").await.unwrap();
+        input.type_text("a");
+        let result = harness
+            .transfer(
+                handle,
+                TransferType::Interrupt {
+                    endpoint: 0x81,
+                    data: vec![0; 8],
+                    timeout_ms: 1000,
+                },
+            )
+            .await
+            .unwrap();
+        match result {
+            TransferResult::Success { data, .. } => assert_eq!(data, [0, 0, 0x04, 0, 0, 0, 0, 0]),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        assert!(harness.bus().unplug(address));
+        harness
+            .wait_for_notification(|n| {
+                matches!(n, DeviceNotification::DeviceRemoved { device_id, .. }
+                    if *device_id == device.id)
+            })
+            .await
+            .unwrap();
+        assert!(harness.list_devices().await.unwrap().is_empty());
+
+        harness.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_detaches_only_forbidden_sessions() {
+        let bus = EmulatedBus::new();
+        bus.plug(CdcAcm::new()).unwrap();
+        bus.plug(HidKeyboard::new()).unwrap();
+        let harness = LoopbackHarness::start(bus).await.unwrap();
+
+        let (serial, serial_handle) = harness.attach("Emulated Serial Port").await.unwrap();
+        let (keyboard, keyboard_handle) = harness.attach("Emulated Keyboard").await.unwrap();
+
+        // Limit the client to the serial port
+        let client_id = harness.client.endpoint_id().to_string();
+        let mut config = harness.config().clone();
+        config.security.client_devices.insert(
+            client_id.clone(),
+            vec![format!(
+                "{:04x}:{:04x}",
+                serial.vendor_id, serial.product_id
+            )],
+        );
+        config.save(harness.config_path()).unwrap();
+
+        let changes = harness.reloader().reload().await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].setting, "security.client_devices");
+
+        let entries = harness
+            .wait_for_audit(|entry| {
+                matches!(entry.event_type, AuditEventType::DeviceDetach)
+                    && entry.device_id == Some(keyboard.id.0)
+            })
+            .await
+            .unwrap();
+        assert!(
+            entries
+                .iter()
+                .any(|entry| matches!(entry.event_type, AuditEventType::ConfigurationChange))
+        );
+
+        let keyboard_result = harness
+            .transfer(
+                keyboard_handle,
+                TransferType::Interrupt {
+                    endpoint: 0x81,
+                    data: vec![0; 8],
+                    timeout_ms: 100,
+                },
+            )
+            .await;
+        assert!(!matches!(
+            keyboard_result,
+            Ok(TransferResult::Success { .. })
+        ));
+
+        let serial_result = harness
+            .transfer(
+                serial_handle,
+                TransferType::Bulk {
+                    endpoint: 0x02,
+                    data: b"still here".to_vec(),
+                    timeout_ms: 1000,
+                    checksum: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(matches!(serial_result, TransferResult::Success { .. }));
+
+        harness.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_detaches_and_withholds_devices() {
+        use crate::control::client::request;
+        use crate::control::{ControlRequest, ControlResponse};
+
+        let bus = EmulatedBus::new();
+        bus.plug(CdcAcm::new()).unwrap();
+        bus.plug(HidKeyboard::new()).unwrap();
+        let mut harness = LoopbackHarness::start(bus).await.unwrap();
+        let socket = harness.serve_control_socket().unwrap();
+
+        let (keyboard, keyboard_handle) = harness.attach("Emulated Keyboard").await.unwrap();
+        let serial = harness
+            .list_devices()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|device| device.product.as_deref() == Some("Emulated Serial Port"))
+            .unwrap();
+
+        let ControlResponse::Clients(clients) =
+            request(&socket, &ControlRequest::Clients).await.unwrap()
+        else {
+            panic!("expected the client list");
+        };
+        assert_eq!(clients.len(), 1);
+        assert_eq!(
+            clients[0].endpoint_id,
+            harness.client.endpoint_id().to_string()
+        );
+        assert_eq!(clients[0].attached[0].handle, keyboard_handle.0);
+        assert_eq!(clients[0].attached[0].device_id, keyboard.id.0);
+
+        // Force-detach the keyboard from the client
+        let detach = ControlRequest::Detach {
+            handle: keyboard_handle.0,
+            reason: Some("maintenance".to_string()),
+        };
+        let response = request(&socket, &detach).await.unwrap();
+        assert!(matches!(response, ControlResponse::Done { .. }));
+        harness
+            .wait_for_audit(|entry| {
+                matches!(entry.event_type, AuditEventType::DeviceDetach)
+                    && entry.device_id == Some(keyboard.id.0)
+            })
+            .await
+            .unwrap();
+        let response = request(&socket, &detach).await.unwrap();
+        assert!(matches!(response, ControlResponse::Error { .. }));
+
+        // Withhold the serial port, then share it again
+        let unshare = ControlRequest::SetSharing {
+            device_id: serial.id.0,
+            shared: false,
+        };
+        let response = request(&socket, &unshare).await.unwrap();
+        assert!(matches!(response, ControlResponse::Done { .. }));
+        harness
+            .wait_for_notification(|n| {
+                matches!(n, DeviceNotification::DeviceRemoved { device_id, .. }
+                    if *device_id == serial.id)
+            })
+            .await
+            .unwrap();
+        assert!(
+            harness
+                .list_devices()
+                .await
+                .unwrap()
+                .iter()
+                .all(|device| device.id != serial.id)
+        );
+        let ControlResponse::Devices(devices) =
+            request(&socket, &ControlRequest::Devices).await.unwrap()
+        else {
+            panic!("expected the device list");
+        };
+        let listed = devices
+            .iter()
+            .find(|device| device.device_id == serial.id.0)
+            .unwrap();
+        assert!(!listed.shared);
+
+        let share = ControlRequest::SetSharing {
+            device_id: serial.id.0,
+            shared: true,
+        };
+        let response = request(&socket, &share).await.unwrap();
+        assert!(matches!(response, ControlResponse::Done { .. }));
+        harness
+            .wait_for_notification(|n| {
+                matches!(n, DeviceNotification::DeviceArrived { device } if device.id == serial.id)
+            })
+            .await
+            .unwrap();
+
+        harness.shutdown().await.unwrap();
+    }
+}
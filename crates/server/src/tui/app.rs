@@ -4,13 +4,13 @@
 //! the UI rendering and the USB/network subsystems.
 
 use anyhow::{Context, Result};
-use common::{MetricsSnapshot, TransferMetrics, UsbBridge, UsbCommand, UsbEvent};
+use common::{MetricsSnapshot, TransferKind, TransferOutcome, UsbBridge, UsbCommand, UsbEvent};
 use crossterm::{
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use iroh::PublicKey as EndpointId;
-use protocol::{DeviceInfo, SharingMode};
+use protocol::{DeviceId, DeviceInfo, ProtocolMetrics, Reservation, ReservationId, SharingMode};
 use ratatui::{Terminal, backend::CrosstermBackend};
 use std::collections::{HashMap, HashSet};
 use std::io::{self, Stdout};
@@ -20,7 +20,15 @@ use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
 
 use super::events::{Action, Event, EventHandler};
+use super::qr;
 use super::ui;
+use crate::directory::{ClientDirectory, SharedClientDirectory};
+use crate::network::{
+    ApprovalDecision, DEFAULT_INVITE_TTL, Invite, PendingClient, ServerMetrics,
+    SharedApprovalQueue, SharedInviteStore, SharedServerMetrics,
+};
+use crate::policy::PolicyEngine;
+use crate::qos::Priority;
 
 /// Session time info for policy-limited sessions
 #[derive(Debug, Clone)]
@@ -59,8 +67,12 @@ pub enum Dialog {
     Clients,
     /// Confirm device reset
     ConfirmReset,
-    /// QR code dialog showing server EndpointId
+    /// QR code dialog showing server EndpointId (and the current invite)
     QrCode,
+    /// Clients awaiting approval
+    Approvals,
+    /// Current and upcoming device reservations
+    Reservations,
 }
 
 /// Application state
@@ -89,12 +101,26 @@ pub struct App {
     auto_share: bool,
     /// Pending reset action confirmed by user
     pub pending_reset: bool,
-    /// Per-client transfer metrics
-    client_metrics: HashMap<String, Arc<TransferMetrics>>,
-    /// Per-device transfer metrics
-    device_metrics: HashMap<u32, Arc<TransferMetrics>>,
-    /// Total server-wide metrics
-    total_metrics: Arc<TransferMetrics>,
+    /// Server-wide, per-client and per-device transfer metrics
+    metrics: SharedServerMetrics,
+    /// Clients awaiting operator approval (None when not attached to a server)
+    approvals: Option<SharedApprovalQueue>,
+    /// Selected row in the approvals dialog
+    selected_approval: usize,
+    /// Approval decision confirmed by user
+    pub pending_approval: Option<(EndpointId, ApprovalDecision)>,
+    /// Pairing invite store (None when not attached to a server)
+    invites: Option<SharedInviteStore>,
+    /// Invite shown in the QR code dialog
+    invite: Option<Invite>,
+    /// Client names shown instead of EndpointIds
+    directory: SharedClientDirectory,
+    /// Policy engine holding device reservations (None when not attached to a server)
+    policy_engine: Option<Arc<PolicyEngine>>,
+    /// Selected row in the reservations dialog
+    selected_reservation: usize,
+    /// Reservation cancellation confirmed by user
+    pub pending_cancellation: Option<ReservationId>,
 }
 
 /// Network events for updating the TUI
@@ -113,6 +139,7 @@ pub enum NetworkEvent {
     TransferCompleted {
         endpoint_id: String,
         device_id: u32,
+        kind: TransferKind,
         bytes_sent: u64,
         bytes_received: u64,
         success: bool,
@@ -135,9 +162,6 @@ impl App {
         network_rx: mpsc::UnboundedReceiver<NetworkEvent>,
         auto_share: bool,
     ) -> Self {
-        let total_metrics = Arc::new(TransferMetrics::new());
-        total_metrics.mark_connected();
-
         Self {
             endpoint_id,
             devices: HashMap::new(),
@@ -151,59 +175,209 @@ impl App {
             network_rx,
             auto_share,
             pending_reset: false,
-            client_metrics: HashMap::new(),
-            device_metrics: HashMap::new(),
-            total_metrics,
+            metrics: ServerMetrics::shared(),
+            approvals: None,
+            selected_approval: 0,
+            pending_approval: None,
+            invites: None,
+            invite: None,
+            directory: Arc::new(ClientDirectory::default()),
+            policy_engine: None,
+            selected_reservation: 0,
+            pending_cancellation: None,
         }
     }
 
-    /// Get total server metrics snapshot
-    pub fn total_metrics(&self) -> MetricsSnapshot {
-        MetricsSnapshot::from_metrics(&self.total_metrics)
+    /// Show the metrics recorded by the network server instead of a private registry
+    pub fn with_metrics(mut self, metrics: SharedServerMetrics) -> Self {
+        self.metrics = metrics;
+        self
     }
 
-    /// Get metrics for a specific client
-    pub fn client_metrics(&self, client_id: &str) -> Option<MetricsSnapshot> {
-        self.client_metrics
-            .get(client_id)
-            .map(|m| MetricsSnapshot::from_metrics(m))
+    /// Show and decide the clients parked by the network server
+    pub fn with_approvals(mut self, approvals: SharedApprovalQueue) -> Self {
+        self.approvals = Some(approvals);
+        self
     }
 
-    /// Get metrics for a specific device
-    pub fn device_metrics(&self, device_id: u32) -> Option<MetricsSnapshot> {
-        self.device_metrics
-            .get(&device_id)
-            .map(|m| MetricsSnapshot::from_metrics(m))
+    /// Mint pairing invites into the network server's invite store
+    pub fn with_invites(mut self, invites: SharedInviteStore) -> Self {
+        self.invites = Some(invites);
+        self
+    }
+
+    /// Show clients by their names from `[clients]`
+    pub fn with_client_directory(mut self, directory: SharedClientDirectory) -> Self {
+        self.directory = directory;
+        self
+    }
+
+    /// Show and cancel the reservations the policy engine enforces
+    pub fn with_reservations(mut self, policy_engine: Arc<PolicyEngine>) -> Self {
+        self.policy_engine = Some(policy_engine);
+        self
+    }
+
+    /// Get how a client is shown: its name, or its shortened EndpointId
+    pub fn client_label(&self, endpoint_id: &str) -> String {
+        self.directory.label(endpoint_id)
+    }
+
+    /// Get the name of a client, if it has one
+    pub fn client_name(&self, endpoint_id: &EndpointId) -> Option<String> {
+        self.directory.name(endpoint_id)
+    }
+
+    /// Get the invite shown in the QR code dialog, if still unused and valid
+    pub fn invite(&self) -> Option<&Invite> {
+        self.invite.as_ref().filter(|invite| !invite.is_expired())
     }
 
-    /// Get or create metrics for a client
-    pub fn get_or_create_client_metrics(&mut self, client_id: &str) -> Arc<TransferMetrics> {
-        self.client_metrics
-            .entry(client_id.to_string())
-            .or_insert_with(|| {
-                let metrics = Arc::new(TransferMetrics::new());
-                metrics.mark_connected();
-                metrics
-            })
-            .clone()
+    /// Get the URL the QR code dialog encodes
+    pub fn connection_url(&self) -> String {
+        match self.invite() {
+            Some(invite) => qr::generate_invite_url(&self.endpoint_id, &invite.token),
+            None => qr::generate_connection_url(&self.endpoint_id),
+        }
+    }
+
+    /// Mint an invite for anyone scanning the QR code dialog
+    fn create_invite(&mut self) {
+        let Some(invites) = self.invites.as_ref() else {
+            return;
+        };
+        match invites.mint(DEFAULT_INVITE_TTL, Vec::new()) {
+            Ok(invite) => self.invite = Some(invite),
+            Err(e) => warn!("Failed to create invite: {:#}", e),
+        }
+    }
+
+    /// Forget the shown invite once a client has redeemed it
+    fn refresh_invite(&mut self) {
+        let (Some(invite), Some(invites)) = (self.invite.as_ref(), self.invites.as_ref()) else {
+            return;
+        };
+        match invites.pending() {
+            Ok(pending) if !pending.iter().any(|p| p.id == invite.id) => self.invite = None,
+            Ok(_) => {}
+            Err(e) => warn!("Failed to read invites: {:#}", e),
+        }
     }
 
-    /// Get or create metrics for a device
-    pub fn get_or_create_device_metrics(&mut self, device_id: u32) -> Arc<TransferMetrics> {
-        self.device_metrics
-            .entry(device_id)
-            .or_insert_with(|| Arc::new(TransferMetrics::new()))
-            .clone()
+    /// Get the clients awaiting approval, oldest first
+    pub fn pending_approvals(&self) -> Vec<PendingClient> {
+        self.approvals
+            .as_ref()
+            .map(|approvals| approvals.pending())
+            .unwrap_or_default()
     }
 
-    /// Get the underlying total metrics tracker (for recording transfers)
-    pub fn total_metrics_tracker(&self) -> Arc<TransferMetrics> {
-        self.total_metrics.clone()
+    /// Get the selected row in the approvals dialog
+    pub fn selected_approval(&self) -> usize {
+        self.selected_approval
+    }
+
+    /// Queue a decision for the selected pending client
+    fn decide_selected_approval(&mut self, decision: ApprovalDecision) {
+        if let Some(client) = self.pending_approvals().get(self.selected_approval) {
+            self.pending_approval = Some((client.endpoint_id, decision));
+        }
+    }
+
+    /// Apply the decision confirmed in the approvals dialog
+    pub async fn apply_pending_approval(&mut self) -> Result<()> {
+        let (Some((endpoint_id, decision)), Some(approvals)) =
+            (self.pending_approval.take(), self.approvals.as_ref())
+        else {
+            return Ok(());
+        };
+
+        match decision {
+            ApprovalDecision::Approved => approvals.approve(endpoint_id).await?,
+            ApprovalDecision::Denied => approvals.deny(endpoint_id).await?,
+        }
+
+        let remaining = approvals.pending_count();
+        self.selected_approval = self.selected_approval.min(remaining.saturating_sub(1));
+        Ok(())
+    }
+
+    /// Get the current and upcoming reservations, ordered by start
+    pub fn reservations(&self) -> Vec<Reservation> {
+        self.policy_engine
+            .as_ref()
+            .map(|engine| engine.reservations(None))
+            .unwrap_or_default()
+    }
+
+    /// Get the current or next reservation of a device
+    pub fn device_reservation(&self, device_id: u32) -> Option<Reservation> {
+        self.policy_engine
+            .as_ref()?
+            .reservations(Some(DeviceId(device_id)))
+            .into_iter()
+            .next()
+    }
+
+    /// Get a Unix time as the policy engine shows it to clients
+    pub fn reservation_time(&self, unix_secs: u64) -> String {
+        match &self.policy_engine {
+            Some(engine) => engine.local_time(unix_secs),
+            None => unix_secs.to_string(),
+        }
+    }
+
+    /// Get the selected row in the reservations dialog
+    pub fn selected_reservation(&self) -> usize {
+        self.selected_reservation
+    }
+
+    /// Cancel the reservation confirmed in the reservations dialog
+    ///
+    /// Clients attached meanwhile keep their sessions; the holder is told
+    /// on its connection's next reservation check.
+    pub async fn apply_pending_cancellation(&mut self) -> Result<()> {
+        let (Some(id), Some(engine)) = (self.pending_cancellation.take(), &self.policy_engine)
+        else {
+            return Ok(());
+        };
+        if let Err(e) = engine.cancel_reservation(id, None).await {
+            anyhow::bail!("Failed to cancel reservation {}: {}", id.0, e);
+        }
+
+        let remaining = engine.reservations(None).len();
+        self.selected_reservation = self.selected_reservation.min(remaining.saturating_sub(1));
+        Ok(())
+    }
+
+    /// Get total server metrics snapshot
+    pub fn total_metrics(&self) -> MetricsSnapshot {
+        self.metrics.total_snapshot()
+    }
+
+    /// Transfers waiting for QoS admission per priority (empty without QoS)
+    pub fn qos_queue_depths(&self) -> Vec<(Priority, usize)> {
+        self.metrics.queue_depths()
+    }
+
+    /// Get metrics for a specific client
+    pub fn client_metrics(&self, client_id: &str) -> Option<MetricsSnapshot> {
+        self.metrics.client_snapshot(client_id)
+    }
+
+    /// Get the metrics a client last reported about its own side of the connection
+    pub fn client_reported_metrics(&self, client_id: &str) -> Option<ProtocolMetrics> {
+        self.metrics.client_report(client_id)
+    }
+
+    /// Get metrics for a specific device
+    pub fn device_metrics(&self, device_id: u32) -> Option<MetricsSnapshot> {
+        self.metrics.device_snapshot(DeviceId(device_id))
     }
 
     /// Get all client IDs with metrics
-    pub fn client_ids_with_metrics(&self) -> Vec<&str> {
-        self.client_metrics.keys().map(|s| s.as_str()).collect()
+    pub fn client_ids_with_metrics(&self) -> Vec<String> {
+        self.metrics.client_ids()
     }
 
     /// Get the server's EndpointId
@@ -269,6 +443,23 @@ impl App {
             Action::CloseDialog => {
                 self.dialog = Dialog::None;
             }
+            Action::Up if self.dialog == Dialog::Reservations => {
+                self.selected_reservation = self.selected_reservation.saturating_sub(1);
+            }
+            Action::Down if self.dialog == Dialog::Reservations => {
+                if self.selected_reservation + 1 < self.reservations().len() {
+                    self.selected_reservation += 1;
+                }
+            }
+            Action::Up if self.dialog == Dialog::Approvals => {
+                self.selected_approval = self.selected_approval.saturating_sub(1);
+            }
+            Action::Down if self.dialog == Dialog::Approvals => {
+                let pending = self.pending_approvals().len();
+                if self.selected_approval + 1 < pending {
+                    self.selected_approval += 1;
+                }
+            }
             Action::Up => {
                 if self.dialog == Dialog::None && !self.device_order.is_empty() {
                     if self.selected_index > 0 {
@@ -309,6 +500,9 @@ impl App {
                         self.dialog = Dialog::None;
                         self.pending_reset = true;
                     }
+                    Dialog::Approvals => {
+                        self.decide_selected_approval(ApprovalDecision::Approved);
+                    }
                     _ => {}
                 }
             }
@@ -317,6 +511,25 @@ impl App {
                     self.dialog = Dialog::Clients;
                 }
             }
+            Action::ViewApprovals => {
+                if self.dialog == Dialog::None {
+                    self.selected_approval = 0;
+                    self.dialog = Dialog::Approvals;
+                }
+            }
+            Action::ViewReservations => {
+                if self.dialog == Dialog::None {
+                    self.selected_reservation = 0;
+                    self.dialog = Dialog::Reservations;
+                }
+            }
+            Action::CancelReservation => {
+                if self.dialog == Dialog::Reservations
+                    && let Some(reservation) = self.reservations().get(self.selected_reservation)
+                {
+                    self.pending_cancellation = Some(reservation.id);
+                }
+            }
             Action::ShowHelp => {
                 self.dialog = Dialog::Help;
             }
@@ -325,6 +538,12 @@ impl App {
                     self.dialog = Dialog::QrCode;
                 }
             }
+            Action::CreateInvite => {
+                if matches!(self.dialog, Dialog::None | Dialog::QrCode) {
+                    self.create_invite();
+                    self.dialog = Dialog::QrCode;
+                }
+            }
             Action::Refresh => {
                 // Refresh will be handled in the main loop by re-fetching devices
                 debug!("Refresh requested");
@@ -339,6 +558,13 @@ impl App {
                     // Reset confirmed
                     self.dialog = Dialog::None;
                     self.pending_reset = true;
+                } else if self.dialog == Dialog::Approvals {
+                    self.decide_selected_approval(ApprovalDecision::Approved);
+                }
+            }
+            Action::Deny => {
+                if self.dialog == Dialog::Approvals {
+                    self.decide_selected_approval(ApprovalDecision::Denied);
                 }
             }
             Action::None => {}
@@ -415,6 +641,7 @@ impl App {
 
         self.devices = new_devices;
         self.device_order = new_order;
+        self.sort_by_port();
 
         // Adjust selected index if necessary
         if !self.device_order.is_empty() && self.selected_index >= self.device_order.len() {
@@ -424,6 +651,24 @@ impl App {
         Ok(())
     }
 
+    /// Order devices by bus and port path so hubs precede their children
+    ///
+    /// Keeps the same device selected.
+    fn sort_by_port(&mut self) {
+        let selected = self.device_order.get(self.selected_index).copied();
+        let devices = &self.devices;
+        self.device_order.sort_by_key(|id| {
+            devices
+                .get(id)
+                .map(|device| (device.info.bus_number, device.info.port_numbers.clone()))
+        });
+        if let Some(position) =
+            selected.and_then(|id| self.device_order.iter().position(|&x| x == id))
+        {
+            self.selected_index = position;
+        }
+    }
+
     /// Process USB events (hotplug)
     pub fn handle_usb_event(&mut self, event: UsbEvent) {
         match event {
@@ -447,6 +692,7 @@ impl App {
                 );
                 if !self.device_order.contains(&id) {
                     self.device_order.push(id);
+                    self.sort_by_port();
                 }
             }
             UsbEvent::DeviceLeft { device_id, .. } => {
@@ -475,17 +721,14 @@ impl App {
             NetworkEvent::ClientConnected { endpoint_id } => {
                 self.connection_count += 1;
                 info!("Client connected: {}", endpoint_id);
-                let metrics = self.get_or_create_client_metrics(&endpoint_id);
-                metrics.mark_connected();
+                self.metrics.client_connected(&endpoint_id);
             }
             NetworkEvent::ClientDisconnected { endpoint_id } => {
                 if self.connection_count > 0 {
                     self.connection_count -= 1;
                 }
                 info!("Client disconnected: {}", endpoint_id);
-                if let Some(metrics) = self.client_metrics.get(&endpoint_id) {
-                    metrics.mark_disconnected();
-                }
+                self.metrics.client_disconnected(&endpoint_id);
                 // Remove client from all devices
                 for device in self.devices.values_mut() {
                     device.clients.remove(&endpoint_id);
@@ -499,7 +742,7 @@ impl App {
                     device.clients.insert(endpoint_id.clone());
                     info!("Client {} attached to device {}", endpoint_id, device_id);
                 }
-                let _ = self.get_or_create_device_metrics(device_id);
+                let _ = self.metrics.device(DeviceId(device_id));
             }
             NetworkEvent::ClientDetachedDevice {
                 endpoint_id,
@@ -513,24 +756,28 @@ impl App {
             NetworkEvent::TransferCompleted {
                 endpoint_id,
                 device_id,
+                kind,
                 bytes_sent,
                 bytes_received,
                 success,
                 latency_us,
             } => {
                 let latency = std::time::Duration::from_micros(latency_us);
-
-                // Record to total metrics tracker
-                let total_tracker = self.total_metrics_tracker();
-                total_tracker.record_transfer(bytes_sent, bytes_received, latency, success);
-
-                // Record to client metrics
-                let client_metrics = self.get_or_create_client_metrics(&endpoint_id);
-                client_metrics.record_transfer(bytes_sent, bytes_received, latency, success);
-
-                // Record to device metrics
-                let device_metrics = self.get_or_create_device_metrics(device_id);
-                device_metrics.record_transfer(bytes_sent, bytes_received, latency, success);
+                let device_id = DeviceId(device_id);
+
+                // Record to total, client and device metrics
+                self.metrics.transfer_started(&endpoint_id, device_id, kind);
+                let outcome = if success {
+                    TransferOutcome::Completed {
+                        bytes_sent,
+                        bytes_received,
+                        latency,
+                    }
+                } else {
+                    TransferOutcome::Failed
+                };
+                self.metrics
+                    .transfer_finished(&endpoint_id, device_id, kind, outcome);
             }
             NetworkEvent::SessionTimeUpdate {
                 device_id,
@@ -625,20 +872,15 @@ impl Drop for Tui {
 
 /// Run the TUI application
 ///
-/// This is the main entry point for the TUI mode.
-pub async fn run(
-    endpoint_id: EndpointId,
-    usb_bridge: UsbBridge,
-    network_rx: mpsc::UnboundedReceiver<NetworkEvent>,
-    auto_share: bool,
-) -> Result<()> {
+/// This is the main entry point for the TUI mode. `app` is built with
+/// `App::new` and the `with_*` methods for the server services it shows.
+pub async fn run(mut app: App) -> Result<()> {
+    let usb_bridge = app.usb_bridge.clone();
+
     // Initialize TUI
     let mut tui = Tui::new()?;
     tui.enter()?;
 
-    // Create app state
-    let mut app = App::new(endpoint_id, usb_bridge.clone(), network_rx, auto_share);
-
     // Initial device list fetch
     if let Err(e) = app.refresh_devices().await {
         warn!("Failed to fetch initial device list: {:#}", e);
@@ -663,6 +905,16 @@ pub async fn run(
             }
         }
 
+        // Handle approval decision (written back to the configuration file)
+        if let Err(e) = app.apply_pending_approval().await {
+            warn!("Failed to save approval decision: {:#}", e);
+        }
+
+        // Handle reservation cancellation
+        if let Err(e) = app.apply_pending_cancellation().await {
+            warn!("{:#}", e);
+        }
+
         // Handle events
         tokio::select! {
             // Terminal events (keyboard, resize, tick)
@@ -687,6 +939,9 @@ pub async fn run(
                     Some(Event::Tick) => {
                         // Periodic update - drain network events
                         app.drain_network_events();
+                        if app.dialog() == &Dialog::QrCode {
+                            app.refresh_invite();
+                        }
                     }
                     None => {
                         // Event channel closed
@@ -721,6 +976,9 @@ pub async fn run(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::approval::ApprovalQueue;
+    use crate::network::invites::InviteStore;
+    use crate::reservation::ReservationBook;
     use protocol::DeviceId;
 
     fn create_test_device_info(id: u32) -> DeviceInfo {
@@ -738,6 +996,10 @@ mod tests {
             protocol: 0,
             speed: protocol::DeviceSpeed::High,
             num_configurations: 1,
+            interfaces: Vec::new(),
+            stable_id: "1234:5678:12345".to_string(),
+            port_numbers: vec![1],
+            parent_hub: None,
         }
     }
 
@@ -774,6 +1036,26 @@ mod tests {
         assert_eq!(app.device_order, vec![42]);
     }
 
+    #[test]
+    fn test_devices_sorted_by_port() {
+        let (_, network_rx) = mpsc::unbounded_channel();
+        let (usb_bridge, _worker) = common::create_usb_bridge();
+        let endpoint_id = EndpointId::from_bytes(&[0u8; 32]).unwrap();
+
+        let mut app = App::new(endpoint_id, usb_bridge, network_rx, false);
+
+        for (id, ports) in [(1, vec![2]), (2, vec![1, 4]), (3, vec![1])] {
+            let mut device = create_test_device_info(id);
+            device.port_numbers = ports;
+            app.handle_usb_event(UsbEvent::DeviceArrived { device });
+        }
+
+        // Hub on 1-1 comes before the device plugged into it
+        assert_eq!(app.device_order, vec![3, 2, 1]);
+        // Selection followed the first device as others were inserted above it
+        assert_eq!(app.selected_device_id(), Some(1));
+    }
+
     #[test]
     fn test_handle_usb_event_left() {
         let (_, network_rx) = mpsc::unbounded_channel();
@@ -882,4 +1164,109 @@ mod tests {
         app.handle_action(Action::Quit);
         assert!(app.should_quit);
     }
+
+    #[tokio::test]
+    async fn test_approvals_dialog_decides_selected_client() {
+        let (_, network_rx) = mpsc::unbounded_channel();
+        let (usb_bridge, _worker) = common::create_usb_bridge();
+        let endpoint_id = EndpointId::from_bytes(&[0u8; 32]).unwrap();
+        let approvals = Arc::new(ApprovalQueue::new(
+            crate::config::ServerConfig::default(),
+            Default::default(),
+            HashSet::new(),
+            Arc::new(None),
+        ));
+        let first = common::test_utils::generate_test_endpoint_id();
+        let second = common::test_utils::generate_test_endpoint_id();
+        let _first_decision = approvals.park(first);
+        let _second_decision = approvals.park(second);
+
+        let mut app =
+            App::new(endpoint_id, usb_bridge, network_rx, false).with_approvals(approvals.clone());
+        app.handle_action(Action::ViewApprovals);
+        assert_eq!(app.dialog, Dialog::Approvals);
+
+        // Navigation moves within the pending list, then 'n' denies
+        app.handle_action(Action::Down);
+        app.handle_action(Action::Down);
+        assert_eq!(app.selected_approval(), 1);
+        app.handle_action(Action::Deny);
+        assert_eq!(
+            app.pending_approval,
+            Some((second, ApprovalDecision::Denied))
+        );
+        app.apply_pending_approval().await.unwrap();
+        assert!(approvals.is_denied(&second).await);
+        assert_eq!(app.selected_approval(), 0);
+
+        app.handle_action(Action::Confirm);
+        app.apply_pending_approval().await.unwrap();
+        assert!(approvals.is_allowed(&first).await);
+        assert!(app.pending_approvals().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reservations_dialog_cancels_selected_reservation() {
+        let (_, network_rx) = mpsc::unbounded_channel();
+        let (usb_bridge, _worker) = common::create_usb_bridge();
+        let endpoint_id = EndpointId::from_bytes(&[0u8; 32]).unwrap();
+        let book = Arc::new(ReservationBook::in_memory());
+        let holder = common::test_utils::generate_test_endpoint_id();
+        let now = crate::reservation::unix_now();
+        let first = book
+            .reserve(&holder, DeviceId(1), now, now + 3600, None)
+            .unwrap();
+        let second = book
+            .reserve(&holder, DeviceId(2), now + 60, now + 3600, None)
+            .unwrap();
+        let engine = Arc::new(
+            PolicyEngine::new(Vec::new()).with_reservations(&Default::default(), book.clone()),
+        );
+
+        let mut app =
+            App::new(endpoint_id, usb_bridge, network_rx, false).with_reservations(engine);
+        assert_eq!(app.device_reservation(2), Some(second.clone()));
+        app.handle_action(Action::ViewReservations);
+        assert_eq!(app.dialog, Dialog::Reservations);
+
+        app.handle_action(Action::Down);
+        app.handle_action(Action::Down);
+        assert_eq!(app.selected_reservation(), 1);
+        app.handle_action(Action::CancelReservation);
+        assert_eq!(app.pending_cancellation, Some(second.id));
+        app.apply_pending_cancellation().await.unwrap();
+        assert_eq!(app.reservations(), vec![first]);
+        assert_eq!(app.selected_reservation(), 0);
+        assert_eq!(app.device_reservation(2), None);
+    }
+
+    #[tokio::test]
+    async fn test_create_invite_shows_invite_url() {
+        let (_, network_rx) = mpsc::unbounded_channel();
+        let (usb_bridge, _worker) = common::create_usb_bridge();
+        let endpoint_id = EndpointId::from_bytes(&[0u8; 32]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let invites = Arc::new(InviteStore::new(dir.path().join("invites.toml")));
+
+        let mut app =
+            App::new(endpoint_id, usb_bridge, network_rx, false).with_invites(invites.clone());
+        assert_eq!(
+            app.connection_url(),
+            qr::generate_connection_url(&endpoint_id)
+        );
+
+        app.handle_action(Action::CreateInvite);
+        assert_eq!(app.dialog, Dialog::QrCode);
+        let invite = app.invite().cloned().unwrap();
+        assert!(app.connection_url().ends_with(&invite.token));
+
+        // Once redeemed, the dialog falls back to the plain connection URL
+        invites.redeem(&invite.token).unwrap();
+        app.refresh_invite();
+        assert!(app.invite().is_none());
+        assert_eq!(
+            app.connection_url(),
+            qr::generate_connection_url(&endpoint_id)
+        );
+    }
 }
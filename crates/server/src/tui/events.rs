@@ -34,10 +34,18 @@ pub enum Action {
     ViewDetails,
     /// View connected clients
     ViewClients,
+    /// View clients awaiting approval
+    ViewApprovals,
+    /// View device reservations
+    ViewReservations,
+    /// Cancel the selected reservation (x)
+    CancelReservation,
     /// Show help dialog
     ShowHelp,
     /// Show QR code dialog
     ShowQrCode,
+    /// Mint a pairing invite and show it as a QR code
+    CreateInvite,
     /// Close dialog/popup
     CloseDialog,
     /// Refresh device list
@@ -46,6 +54,8 @@ pub enum Action {
     ResetDevice,
     /// Confirm action (Enter/y)
     Confirm,
+    /// Deny the selected client (n)
+    Deny,
     /// No action
     None,
 }
@@ -66,7 +76,12 @@ impl From<KeyEvent> for Action {
             KeyCode::Char(' ') | KeyCode::Char('s') => Action::ToggleSharing,
             KeyCode::Enter => Action::ViewDetails, // Also confirms dialogs via context handling
             KeyCode::Char('y') => Action::Confirm,
+            KeyCode::Char('n') => Action::Deny,
             KeyCode::Char('c') => Action::ViewClients,
+            KeyCode::Char('a') => Action::ViewApprovals,
+            KeyCode::Char('b') => Action::ViewReservations,
+            KeyCode::Char('x') => Action::CancelReservation,
+            KeyCode::Char('i') => Action::CreateInvite,
             KeyCode::Char('?') => Action::ShowHelp,
             KeyCode::Char('Q') => Action::ShowQrCode, // Uppercase Q for QR code
             KeyCode::Char('r') => Action::Refresh,
@@ -178,6 +193,18 @@ mod tests {
 
         let help = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::NONE);
         assert_eq!(Action::from(help), Action::ShowHelp);
+
+        let approvals = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE);
+        assert_eq!(Action::from(approvals), Action::ViewApprovals);
+
+        let deny = KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE);
+        assert_eq!(Action::from(deny), Action::Deny);
+
+        let invite = KeyEvent::new(KeyCode::Char('i'), KeyModifiers::NONE);
+        assert_eq!(Action::from(invite), Action::CreateInvite);
+
+        let bookings = KeyEvent::new(KeyCode::Char('b'), KeyModifiers::NONE);
+        assert_eq!(Action::from(bookings), Action::ViewReservations);
     }
 
     #[test]
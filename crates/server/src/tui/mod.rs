@@ -17,6 +17,9 @@
 //! - `Space`: Toggle device sharing on/off
 //! - `Enter`: View device details
 //! - `c`: View connected clients
+//! - `a`: View clients awaiting approval (`y` approves, `n` denies)
+//! - `Q`: Show QR code for connecting to this server
+//! - `i`: Show QR code with a single-use pairing invite
 //! - `r`: Refresh device list
 //! - `?`: Show help
 //! - `q`: Quit (closes dialog first if one is open)
@@ -25,17 +28,23 @@
 //! # Example
 //!
 //! ```ignore
+//! use server::network::IrohServer;
 //! use server::tui;
 //! use common::UsbBridge;
 //! use tokio::sync::mpsc;
 //!
 //! async fn run_tui_mode(
-//!     endpoint_id: iroh::PublicKey,
+//!     server: &IrohServer,
 //!     usb_bridge: UsbBridge,
 //!     network_rx: mpsc::UnboundedReceiver<tui::NetworkEvent>,
 //!     auto_share: bool,
 //! ) -> anyhow::Result<()> {
-//!     tui::run(endpoint_id, usb_bridge, network_rx, auto_share).await
+//!     let app = tui::App::new(server.endpoint_id(), usb_bridge, network_rx, auto_share)
+//!         .with_metrics(server.metrics())
+//!         .with_approvals(server.approvals())
+//!         .with_invites(server.invites())
+//!         .with_client_directory(server.client_directory());
+//!     tui::run(app).await
 //! }
 //! ```
 
@@ -1,6 +1,7 @@
 //! QR code generation and terminal rendering
 //!
-//! Generates QR codes for easy server connection sharing.
+//! Generates QR codes for easy server connection sharing, optionally
+//! carrying a pairing invite that enrolls the scanning client.
 //! Uses Unicode block characters for compact terminal display.
 
 use iroh::PublicKey as EndpointId;
@@ -16,6 +17,11 @@ pub fn generate_connection_url(endpoint_id: &EndpointId) -> String {
     format!("{}{}", URL_SCHEME, endpoint_id)
 }
 
+/// Generate connection URL carrying a pairing invite token
+pub fn generate_invite_url(endpoint_id: &EndpointId, token: &str) -> String {
+    format!("{}?invite={}", generate_connection_url(endpoint_id), token)
+}
+
 /// Parse EndpointId from connection URL
 ///
 /// Returns Some(endpoint_id_string) if the URL matches the expected format,
@@ -25,7 +31,8 @@ pub fn generate_connection_url(endpoint_id: &EndpointId) -> String {
 /// It's included in the server crate for completeness and testing.
 #[cfg(test)]
 pub fn parse_connection_url(url: &str) -> Option<&str> {
-    url.strip_prefix(URL_SCHEME)
+    let rest = url.strip_prefix(URL_SCHEME)?;
+    Some(rest.split_once('?').map_or(rest, |(endpoint, _)| endpoint))
 }
 
 /// Generate QR code as lines of text for terminal display
@@ -37,15 +44,13 @@ pub fn parse_connection_url(url: &str) -> Option<&str> {
 /// - Space: both modules white
 ///
 /// This allows displaying 2 vertical modules per character row.
-pub fn generate_qr_lines(endpoint_id: &EndpointId) -> Vec<Line<'static>> {
-    let url = generate_connection_url(endpoint_id);
-
+pub fn generate_qr_lines(url: &str) -> Vec<Line<'static>> {
     // Generate QR code with auto-detected version and medium error correction
-    let qr = match QrCode::with_error_correction_level(&url, EcLevel::M) {
+    let qr = match QrCode::with_error_correction_level(url, EcLevel::M) {
         Ok(qr) => qr,
         Err(_) => {
             // Fallback: try with explicit version for longer data
-            match QrCode::with_version(&url, Version::Normal(10), EcLevel::L) {
+            match QrCode::with_version(url, Version::Normal(10), EcLevel::L) {
                 Ok(qr) => qr,
                 Err(_) => {
                     return vec![Line::from(Span::styled(
@@ -125,13 +130,11 @@ pub fn generate_qr_lines(endpoint_id: &EndpointId) -> Vec<Line<'static>> {
 ///
 /// Returns (width, height) in characters.
 /// Height is approximately half of width due to half-block rendering.
-pub fn calculate_qr_dimensions(endpoint_id: &EndpointId) -> (usize, usize) {
-    let url = generate_connection_url(endpoint_id);
-
-    let qr = match QrCode::with_error_correction_level(&url, EcLevel::M) {
+pub fn calculate_qr_dimensions(url: &str) -> (usize, usize) {
+    let qr = match QrCode::with_error_correction_level(url, EcLevel::M) {
         Ok(qr) => qr,
         Err(_) => {
-            match QrCode::with_version(&url, Version::Normal(10), EcLevel::L) {
+            match QrCode::with_version(url, Version::Normal(10), EcLevel::L) {
                 Ok(qr) => qr,
                 Err(_) => return (30, 15), // Fallback dimensions
             }
@@ -175,10 +178,22 @@ mod tests {
         assert!(invalid.is_none());
     }
 
+    #[test]
+    fn test_invite_url_keeps_endpoint() {
+        let endpoint_id = mock_endpoint_id();
+        let url = generate_invite_url(&endpoint_id, "00ff");
+
+        assert!(url.ends_with("?invite=00ff"));
+        assert_eq!(
+            parse_connection_url(&url),
+            Some(endpoint_id.to_string().as_str())
+        );
+    }
+
     #[test]
     fn test_generate_qr_lines() {
         let endpoint_id = mock_endpoint_id();
-        let lines = generate_qr_lines(&endpoint_id);
+        let lines = generate_qr_lines(&generate_connection_url(&endpoint_id));
 
         // Should generate multiple lines
         assert!(!lines.is_empty());
@@ -192,7 +207,7 @@ mod tests {
     #[test]
     fn test_calculate_qr_dimensions() {
         let endpoint_id = mock_endpoint_id();
-        let (width, height) = calculate_qr_dimensions(&endpoint_id);
+        let (width, height) = calculate_qr_dimensions(&generate_connection_url(&endpoint_id));
 
         // QR code should have reasonable dimensions
         assert!(width > 20);
@@ -3,7 +3,7 @@
 //! Implements the visual layout and rendering for the server TUI.
 //! Uses ratatui widgets for the device table, status bar, and dialogs.
 
-use protocol::{DeviceSpeed, SharingMode};
+use protocol::{DeviceInfo, DeviceSpeed, SharingMode, format_port_path};
 use ratatui::{
     Frame,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
@@ -11,7 +11,7 @@ use ratatui::{
     text::{Line, Span},
     widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState, Wrap},
 };
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use super::app::{App, DeviceState, Dialog};
 use super::qr;
@@ -45,6 +45,8 @@ pub fn render(frame: &mut Frame, app: &App) {
         Dialog::Clients => render_clients_dialog(frame, app),
         Dialog::ConfirmReset => render_confirm_reset_dialog(frame, app),
         Dialog::QrCode => render_qr_code_dialog(frame, app),
+        Dialog::Approvals => render_approvals_dialog(frame, app),
+        Dialog::Reservations => render_reservations_dialog(frame, app),
     }
 }
 
@@ -59,7 +61,7 @@ fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
         endpoint_id
     };
 
-    let status_text = vec![
+    let mut status_text = vec![
         Span::styled("EndpointId: ", Style::default().fg(Color::DarkGray)),
         Span::styled(endpoint_display, Style::default().fg(Color::Cyan)),
         Span::raw("  |  "),
@@ -73,6 +75,17 @@ fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
         Span::styled(uptime, Style::default().fg(Color::Green)),
     ];
 
+    let pending = app.pending_approvals().len();
+    if pending > 0 {
+        status_text.push(Span::raw("  |  "));
+        status_text.push(Span::styled(
+            format!("{} awaiting approval (a)", pending),
+            Style::default()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+
     let status = Paragraph::new(Line::from(status_text))
         .block(
             Block::default()
@@ -91,15 +104,17 @@ fn render_device_list(frame: &mut Frame, app: &App, area: Rect) {
     let devices = app.devices();
 
     // Table header
-    let header_cells = ["ID", "VID:PID", "Name", "Mode", "Status", "Clients", "Time"]
-        .iter()
-        .map(|h| {
-            Cell::from(*h).style(
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            )
-        });
+    let header_cells = [
+        "ID", "Port", "VID:PID", "Name", "Mode", "Status", "Clients", "Time",
+    ]
+    .iter()
+    .map(|h| {
+        Cell::from(*h).style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+    });
     let header = Row::new(header_cells).height(1);
 
     // Table rows
@@ -117,6 +132,7 @@ fn render_device_list(frame: &mut Frame, app: &App, area: Rect) {
         rows,
         [
             Constraint::Length(4),  // ID
+            Constraint::Length(14), // Port (hub tree)
             Constraint::Length(10), // VID:PID
             Constraint::Min(15),    // Name
             Constraint::Length(4),  // Mode (E/S/R)
@@ -205,6 +221,7 @@ fn create_device_row(device: &DeviceState, _is_selected: bool) -> Row<'static> {
 
     let cells = vec![
         Cell::from(format!("{}", info.id.0)),
+        Cell::from(format_port_tree(info)).style(Style::default().fg(Color::DarkGray)),
         Cell::from(format!("{:04x}:{:04x}", info.vendor_id, info.product_id)),
         Cell::from(name),
         Cell::from(mode_char).style(mode_style),
@@ -216,6 +233,34 @@ fn create_device_row(device: &DeviceState, _is_selected: bool) -> Row<'static> {
     Row::new(cells)
 }
 
+/// Port path indented by hub depth, so the sorted device list reads as a tree
+///
+/// A device on a root hub port shows as `1-2`, one behind a hub on that
+/// port as ` └1-2.4`, and each further hub level adds two spaces.
+fn format_port_tree(info: &DeviceInfo) -> String {
+    let depth = info.port_numbers.len();
+    if depth <= 1 {
+        return info.port_path();
+    }
+    format!("{} └{}", "  ".repeat(depth - 2), info.port_path())
+}
+
+/// Describe the hub a device is plugged into
+fn format_parent_hub(info: &DeviceInfo) -> String {
+    match &info.parent_hub {
+        Some(hub) if hub.port_numbers.is_empty() => {
+            format!("Root hub (bus {})", info.bus_number)
+        }
+        Some(hub) => format!(
+            "{:04x}:{:04x} on {}",
+            hub.vendor_id,
+            hub.product_id,
+            format_port_path(info.bus_number, &hub.port_numbers)
+        ),
+        None => "Unknown".to_string(),
+    }
+}
+
 /// Format session time remaining for display
 fn format_session_time(secs: u64) -> String {
     if secs >= 3600 {
@@ -247,7 +292,7 @@ fn render_metrics_panel(frame: &mut Frame, app: &App, area: Rect) {
         .split(area);
 
     // Transfer statistics
-    let transfer_lines = vec![
+    let mut transfer_lines = vec![
         Line::from(vec![
             Span::styled("Transfers: ", Style::default().fg(Color::DarkGray)),
             Span::styled(
@@ -287,6 +332,26 @@ fn render_metrics_panel(frame: &mut Frame, app: &App, area: Rect) {
         ]),
     ];
 
+    // QoS queue depth per priority, highest first
+    let queue_depths = app.qos_queue_depths();
+    if !queue_depths.is_empty() {
+        let mut spans = vec![Span::styled(
+            "Queued: ",
+            Style::default().fg(Color::DarkGray),
+        )];
+        for (priority, depth) in queue_depths.iter().rev() {
+            spans.push(Span::styled(
+                format!("{}:{} ", priority.label()[..1].to_uppercase(), depth),
+                Style::default().fg(if *depth > 0 {
+                    Color::Yellow
+                } else {
+                    Color::DarkGray
+                }),
+            ));
+        }
+        transfer_lines.push(Line::from(spans));
+    }
+
     let transfer_block = Paragraph::new(transfer_lines).block(
         Block::default()
             .borders(Borders::ALL)
@@ -481,6 +546,20 @@ fn render_help_bar(frame: &mut Frame, area: Rect) {
                 .add_modifier(Modifier::BOLD),
         ),
         Span::raw(" Clients  "),
+        Span::styled(
+            "a",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(" Approvals  "),
+        Span::styled(
+            "b",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(" Bookings  "),
         Span::styled(
             "Q",
             Style::default()
@@ -561,6 +640,14 @@ fn render_help_dialog(frame: &mut Frame) {
             Span::styled("  c            ", Style::default().fg(Color::Cyan)),
             Span::raw("View connected clients"),
         ]),
+        Line::from(vec![
+            Span::styled("  a            ", Style::default().fg(Color::Cyan)),
+            Span::raw("Approve (y) or deny (n) waiting clients"),
+        ]),
+        Line::from(vec![
+            Span::styled("  b            ", Style::default().fg(Color::Cyan)),
+            Span::raw("View or cancel (x) device reservations"),
+        ]),
         Line::from(vec![
             Span::styled("  r            ", Style::default().fg(Color::Cyan)),
             Span::raw("Refresh device list"),
@@ -573,6 +660,10 @@ fn render_help_dialog(frame: &mut Frame) {
             Span::styled("  Q            ", Style::default().fg(Color::Cyan)),
             Span::raw("Show QR code for connection"),
         ]),
+        Line::from(vec![
+            Span::styled("  i            ", Style::default().fg(Color::Cyan)),
+            Span::raw("Show QR code with a single-use invite"),
+        ]),
         Line::from(""),
         Line::from(vec![Span::styled(
             "General",
@@ -720,6 +811,14 @@ fn render_device_details_dialog(frame: &mut Frame, app: &App) {
                     Style::default().fg(Color::White),
                 ),
             ]),
+            Line::from(vec![
+                Span::styled("Port:            ", Style::default().fg(Color::DarkGray)),
+                Span::styled(info.port_path(), Style::default().fg(Color::White)),
+            ]),
+            Line::from(vec![
+                Span::styled("Hub:             ", Style::default().fg(Color::DarkGray)),
+                Span::styled(format_parent_hub(info), Style::default().fg(Color::White)),
+            ]),
             Line::from(vec![
                 Span::styled("Speed:           ", Style::default().fg(Color::DarkGray)),
                 Span::styled(format_speed(info.speed), Style::default().fg(Color::Yellow)),
@@ -778,6 +877,21 @@ fn render_device_details_dialog(frame: &mut Frame, app: &App) {
             ]),
         ];
 
+        if let Some(reservation) = device_id.and_then(|id| app.device_reservation(id)) {
+            lines.push(Line::from(vec![
+                Span::styled("Reservation:     ", Style::default().fg(Color::DarkGray)),
+                Span::styled(
+                    format!(
+                        "{} from {} until {}",
+                        reservation.holder_label(),
+                        app.reservation_time(reservation.starts_at),
+                        app.reservation_time(reservation.ends_at)
+                    ),
+                    Style::default().fg(Color::Magenta),
+                ),
+            ]));
+        }
+
         // Add device metrics if available
         if let Some(metrics) = device_metrics {
             lines.push(Line::from(""));
@@ -890,13 +1004,9 @@ fn render_clients_dialog(frame: &mut Frame, app: &App) {
         )]));
         lines.push(Line::from(""));
 
-        for client_id in client_ids {
-            // Truncate client ID for display
-            let display_id = if client_id.len() > 20 {
-                format!("{}...", &client_id[..16])
-            } else {
-                client_id.to_string()
-            };
+        for client_id in &client_ids {
+            // Client name, or the truncated client ID
+            let display_id = app.client_label(client_id);
 
             lines.push(Line::from(vec![Span::styled(
                 display_id,
@@ -945,6 +1055,30 @@ fn render_clients_dialog(frame: &mut Frame, app: &App) {
                         metrics.latency.format_avg(),
                         Style::default().fg(Color::White),
                     ),
+                    Span::raw("  "),
+                    Span::styled("Loss: ", Style::default().fg(Color::DarkGray)),
+                    Span::styled(
+                        metrics.format_loss_rate(),
+                        Style::default().fg(Color::White),
+                    ),
+                ]));
+            }
+
+            // The client's own view of the connection (ClientMetricsUpdate)
+            if let Some(reported) = app.client_reported_metrics(client_id) {
+                lines.push(Line::from(vec![
+                    Span::raw("  "),
+                    Span::styled("Client view: ", Style::default().fg(Color::DarkGray)),
+                    Span::styled(
+                        reported.latency.format_avg(),
+                        Style::default().fg(Color::White),
+                    ),
+                    Span::raw("  "),
+                    Span::styled("Loss: ", Style::default().fg(Color::DarkGray)),
+                    Span::styled(
+                        reported.format_loss_rate(),
+                        Style::default().fg(Color::White),
+                    ),
                 ]));
             }
             lines.push(Line::from(""));
@@ -987,12 +1121,8 @@ fn render_clients_dialog(frame: &mut Frame, app: &App) {
             )]));
 
             for client in &device.clients {
-                // Truncate client ID for display
-                let display_id = if client.len() > 20 {
-                    format!("{}...", &client[..16])
-                } else {
-                    client.clone()
-                };
+                // Client name, or the truncated client ID
+                let display_id = app.client_label(client);
                 lines.push(Line::from(vec![
                     Span::raw("  "),
                     Span::styled(display_id, Style::default().fg(Color::White)),
@@ -1016,6 +1146,144 @@ fn render_clients_dialog(frame: &mut Frame, app: &App) {
     frame.render_widget(paragraph, area);
 }
 
+/// Render the approvals dialog
+fn render_approvals_dialog(frame: &mut Frame, app: &App) {
+    let area = centered_rect(70, 50, frame.area());
+    let pending = app.pending_approvals();
+
+    let mut lines = Vec::new();
+    if pending.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No clients awaiting approval",
+            Style::default().fg(Color::DarkGray),
+        )));
+    } else {
+        for (idx, client) in pending.iter().enumerate() {
+            let waiting = SystemTime::now()
+                .duration_since(client.requested_at)
+                .unwrap_or_default();
+            let style = if idx == app.selected_approval() {
+                Style::default()
+                    .fg(Color::Cyan)
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Cyan)
+            };
+            // The full ID stays visible: the operator checks it before approving
+            let display_id = match app.client_name(&client.endpoint_id) {
+                Some(name) => format!("{} ({})", name, client.endpoint_id),
+                None => client.endpoint_id.to_string(),
+            };
+            lines.push(Line::from(vec![
+                Span::styled(display_id, style),
+                Span::raw("  "),
+                Span::styled(
+                    format!("waiting {}", format_duration(waiting)),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]));
+        }
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled("y / Enter", Style::default().fg(Color::Green)),
+        Span::raw(" approve  "),
+        Span::styled("n", Style::default().fg(Color::Red)),
+        Span::raw(" deny  "),
+        Span::styled("Esc", Style::default().fg(Color::DarkGray)),
+        Span::raw(" close"),
+    ]));
+
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(format!(" Awaiting Approval ({}) ", pending.len()))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Magenta)),
+        )
+        .wrap(Wrap { trim: false });
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(paragraph, area);
+}
+
+/// Render the reservations dialog
+fn render_reservations_dialog(frame: &mut Frame, app: &App) {
+    let area = centered_rect(80, 50, frame.area());
+    let reservations = app.reservations();
+
+    let mut lines = Vec::new();
+    if reservations.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No current or upcoming reservations",
+            Style::default().fg(Color::DarkGray),
+        )));
+    } else {
+        for (idx, reservation) in reservations.iter().enumerate() {
+            let style = if idx == app.selected_reservation() {
+                Style::default()
+                    .fg(Color::Cyan)
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Cyan)
+            };
+            let mut spans = vec![
+                Span::styled(
+                    format!("#{} device {}", reservation.id.0, reservation.device_id.0),
+                    style,
+                ),
+                Span::raw("  "),
+                Span::styled(
+                    reservation.holder_label().to_string(),
+                    Style::default().fg(Color::White),
+                ),
+                Span::raw("  "),
+                Span::styled(
+                    format!(
+                        "{} - {}",
+                        app.reservation_time(reservation.starts_at),
+                        app.reservation_time(reservation.ends_at)
+                    ),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ];
+            if let Some(note) = &reservation.note {
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(
+                    note.clone(),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+            lines.push(Line::from(spans));
+        }
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled("x", Style::default().fg(Color::Red)),
+        Span::raw(" cancel  "),
+        Span::styled("Esc", Style::default().fg(Color::DarkGray)),
+        Span::raw(" close"),
+    ]));
+
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(format!(" Reservations ({}) ", reservations.len()))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Magenta)),
+        )
+        .wrap(Wrap { trim: false });
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(paragraph, area);
+}
+
 /// Render the confirm reset dialog
 fn render_confirm_reset_dialog(frame: &mut Frame, app: &App) {
     let area = centered_rect(40, 20, frame.area());
@@ -1072,7 +1340,8 @@ fn render_confirm_reset_dialog(frame: &mut Frame, app: &App) {
 /// Render the QR code dialog
 fn render_qr_code_dialog(frame: &mut Frame, app: &App) {
     // Calculate QR code dimensions to size the dialog appropriately
-    let (qr_width, qr_height) = qr::calculate_qr_dimensions(app.endpoint_id());
+    let connection_url = app.connection_url();
+    let (qr_width, qr_height) = qr::calculate_qr_dimensions(&connection_url);
 
     // Add padding for border, title, and endpoint text
     let dialog_width = (qr_width + 4).max(50) as u16;
@@ -1087,17 +1356,23 @@ fn render_qr_code_dialog(frame: &mut Frame, app: &App) {
 
     let endpoint_id = app.endpoint_id();
     let endpoint_str = endpoint_id.to_string();
-    let connection_url = qr::generate_connection_url(endpoint_id);
 
     // Generate QR code lines
-    let qr_lines = qr::generate_qr_lines(endpoint_id);
+    let qr_lines = qr::generate_qr_lines(&connection_url);
 
     // Build content: header, QR code, and footer with endpoint info
     let mut content_lines = Vec::new();
 
     // Header
+    let header = match app.invite() {
+        Some(invite) => format!(
+            "Scan to enroll a client (single use, expires in {} min)",
+            invite.remaining().as_secs().div_ceil(60)
+        ),
+        None => "Scan to connect to this server".to_string(),
+    };
     content_lines.push(Line::from(vec![Span::styled(
-        "Scan to connect to this server",
+        header,
         Style::default()
             .fg(Color::Yellow)
             .add_modifier(Modifier::BOLD),
@@ -1130,7 +1405,7 @@ fn render_qr_code_dialog(frame: &mut Frame, app: &App) {
 
     content_lines.push(Line::from(""));
     content_lines.push(Line::from(vec![Span::styled(
-        "Press Esc or Q to close",
+        "Press i for a new invite, Esc or Q to close",
         Style::default().fg(Color::DarkGray),
     )]));
 
@@ -1225,6 +1500,17 @@ mod tests {
         assert_eq!(format_speed(DeviceSpeed::SuperPlus), "Super+ (10 Gbps)");
     }
 
+    #[test]
+    fn test_format_port_tree() {
+        let mut info = common::test_utils::create_mock_device_info(1, 0x1234, 0x5678);
+        info.port_numbers = vec![2];
+        assert_eq!(format_port_tree(&info), "1-2");
+        info.port_numbers = vec![2, 4];
+        assert_eq!(format_port_tree(&info), " └1-2.4");
+        info.port_numbers = vec![2, 4, 1];
+        assert_eq!(format_port_tree(&info), "   └1-2.4.1");
+    }
+
     #[test]
     fn test_centered_rect() {
         let area = Rect::new(0, 0, 100, 50);
@@ -0,0 +1,146 @@
+//! USB backends
+//!
+//! The device manager does not talk to libusb directly. It enumerates devices,
+//! receives hot-plug notifications and runs the event loop through a
+//! [`UsbBackend`], and drives each device through [`BackendDevice`].
+//!
+//! Two backends exist:
+//! - [`LibusbBackend`](crate::usb::libusb::LibusbBackend) for real hardware
+//! - [`EmulatedBackend`](crate::usb::emulated::EmulatedBackend) for scripted
+//!   virtual devices, so the server runs without USB hardware or permissions
+
+use crate::config::{UsbBackendKind, UsbSettings};
+use crate::usb::emulated::{EmulatedBackend, EmulatedBus};
+use crate::usb::libusb::LibusbBackend;
+use crate::usb::manager::HotplugSink;
+use anyhow::{Context as _, Result};
+use common::EndpointInfo;
+use protocol::{
+    AttachError, DeviceDescriptors, DeviceHandle, DeviceId, DeviceInfo, InterfaceInfo, RequestId,
+    UsbError, UsbRequest, UsbResponse,
+};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Source of USB devices and their events
+pub trait UsbBackend: Send {
+    /// Currently connected devices
+    fn devices(&self) -> Result<Vec<Box<dyn BackendDevice>>, rusb::Error>;
+
+    /// Look up one connected device, e.g. after a hot-plug arrival
+    fn device(&self, bus: u8, address: u8) -> Option<Box<dyn BackendDevice>>;
+
+    /// Start reporting arrivals and removals to `hotplug`
+    fn register_hotplug(&mut self, hotplug: HotplugSink) -> Result<(), rusb::Error>;
+
+    /// Wait up to `timeout` for USB events and handle them
+    ///
+    /// Finished transfers are answered afterwards by
+    /// [`BackendDevice::process_transfer_completions`].
+    fn handle_events(&self, timeout: Duration) -> Result<(), rusb::Error>;
+}
+
+/// One device of a [`UsbBackend`]
+///
+/// Transfers are asynchronous: [`submit_transfer`](Self::submit_transfer)
+/// queues them, and their responses are sent from
+/// [`process_transfer_completions`](Self::process_transfer_completions).
+pub trait BackendDevice {
+    /// Server-assigned device ID
+    fn id(&self) -> DeviceId;
+
+    /// Set the device ID and stable identity once they have been assigned
+    fn set_identity(&mut self, id: DeviceId, stable_id: String);
+
+    /// Bus number
+    fn bus_number(&self) -> u8;
+
+    /// Device address on the bus
+    fn device_address(&self) -> u8;
+
+    /// Device information including string descriptors
+    fn device_info(&self) -> DeviceInfo;
+
+    /// Device information without string descriptors
+    ///
+    /// Does not open the device.
+    fn descriptor_info(&self) -> DeviceInfo;
+
+    /// Stable identities of this device, preferred first
+    fn stable_ids(&self) -> Vec<String>;
+
+    /// bConfigurationValue of the active configuration
+    fn active_configuration(&self) -> Option<u8>;
+
+    /// Interfaces of the active configuration (alternate setting 0)
+    fn interfaces(&self) -> Vec<InterfaceInfo>;
+
+    /// Endpoint addresses of the given interfaces, across all alternate settings
+    fn interface_endpoints(&self, interfaces: &[u8]) -> Vec<u8>;
+
+    /// Full descriptor set for `GetDescriptorsRequest`
+    fn descriptors(&self) -> Result<DeviceDescriptors, UsbError>;
+
+    /// Open the device and claim all interfaces of the active configuration
+    fn open(&mut self) -> Result<(), AttachError>;
+
+    /// Open the device and claim only some of its interfaces
+    fn open_interfaces(&mut self, interfaces: &[u8]) -> Result<(), AttachError>;
+
+    /// Release some interfaces while the device stays open for others
+    fn release_interfaces(&mut self, interfaces: &[u8]);
+
+    /// Cancel outstanding transfers and close the device
+    fn close(&mut self);
+
+    /// Check if the device is open
+    fn is_open(&self) -> bool;
+
+    /// Queue a transfer; the response is sent once it completes
+    fn submit_transfer(&mut self, request: UsbRequest, response: oneshot::Sender<UsbResponse>);
+
+    /// Cancel one transfer submitted on `handle`
+    ///
+    /// Returns `true` if the transfer was still queued or in flight; its
+    /// response then reports `UsbError::Cancelled`.
+    fn cancel_transfer(&mut self, handle: DeviceHandle, request_id: RequestId) -> bool;
+
+    /// Answer transfers that finished since the last call
+    ///
+    /// Returns the number of transfers completed.
+    fn process_transfer_completions(&mut self) -> usize;
+
+    /// Check if any transfers are queued or in flight
+    fn has_pending_transfers(&self) -> bool;
+
+    /// Reset the device, cancelling transfers in flight
+    fn reset(&mut self) -> Result<(), rusb::Error>;
+
+    /// Clear a halt condition on an endpoint
+    fn clear_halt(&mut self, endpoint: u8) -> Result<(), rusb::Error>;
+
+    /// Activate a configuration
+    fn set_configuration(&mut self, configuration: u8) -> Result<(), rusb::Error>;
+
+    /// Select an alternate setting of a claimed interface
+    fn set_interface(&mut self, interface: u8, alt_setting: u8) -> Result<(), rusb::Error>;
+
+    /// Look up an interrupt endpoint in the active configuration
+    fn endpoint_info(&self, endpoint: u8) -> Result<EndpointInfo, UsbError>;
+}
+
+/// Open the backend selected in the `[usb]` settings
+///
+/// The emulated backend starts with the devices listed in `emulated_devices`.
+pub fn open_backend(settings: &UsbSettings) -> Result<Box<dyn UsbBackend>> {
+    match settings.backend {
+        UsbBackendKind::Libusb => {
+            let backend = LibusbBackend::new().context("Failed to initialize libusb")?;
+            Ok(Box::new(backend))
+        }
+        UsbBackendKind::Emulated => {
+            let bus = EmulatedBus::from_config(&settings.emulated_devices)?;
+            Ok(Box::new(EmulatedBackend::new(bus)))
+        }
+    }
+}
@@ -1,36 +1,57 @@
-//! USB device abstraction
+//! libusb device
 //!
 //! This module provides a wrapper around rusb::Device with cached descriptors
-//! and convenient conversion to protocol types.
-
-use protocol::{AttachError, DeviceId, DeviceInfo, DeviceSpeed, SuperSpeedConfig};
-use rusb::{Context, Device, DeviceDescriptor, DeviceHandle};
+//! and convenient conversion to protocol types. It is the [`BackendDevice`]
+//! of the libusb backend.
+
+use crate::usb::backend::BackendDevice;
+use crate::usb::urb::UrbEngine;
+use common::EndpointInfo;
+use protocol::{
+    AttachError, ConfigurationDescriptor, DeviceDescriptors, DeviceId, DeviceInfo, DeviceSpeed,
+    EndpointDescriptor, InterfaceDescriptor, InterfaceInfo, ParentHub, RequestId, StringDescriptor,
+    SuperSpeedConfig, TransferResult, UsbError, UsbRequest, UsbResponse, format_port_path,
+};
+use rusb::{Context, Device, DeviceDescriptor, DeviceHandle, UsbContext};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
 use tracing::{debug, warn};
 
-/// USB device wrapper with cached information
+/// How long to wait for cancelled transfers before closing a handle
+const TRANSFER_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Timeout for descriptor reads on endpoint 0
+const DESCRIPTOR_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// libusb device wrapper with cached information
 pub struct UsbDevice {
     /// Underlying rusb device
     device: Device<Context>,
     /// Device ID (server-assigned)
     id: DeviceId,
+    /// Identity that survives replugs (see [`stable_ids`])
+    stable_id: String,
     /// Cached device descriptor
     descriptor: DeviceDescriptor,
     /// Device handle (if opened)
     handle: Option<DeviceHandle<Context>>,
-    /// Cached device speed for optimization decisions
+    /// Cached device speed
     speed: DeviceSpeed,
-    /// Transfer configuration based on device speed
-    transfer_config: SuperSpeedConfig,
-    /// Number of interfaces (cached when device is opened)
-    num_interfaces: u8,
+    /// Interfaces claimed on the open handle
+    claimed_interfaces: Vec<u8>,
+    /// Asynchronous transfers in flight on the open handle
+    urbs: UrbEngine,
 }
 
 impl UsbDevice {
     /// Create a new USB device wrapper
     ///
-    /// Reads and caches the device descriptor and determines optimal transfer
-    /// configuration based on device speed.
-    pub fn new(device: Device<Context>, id: DeviceId) -> Result<Self, rusb::Error> {
+    /// Reads and caches the device descriptor and speed.
+    pub fn new(
+        device: Device<Context>,
+        id: DeviceId,
+        stable_id: String,
+    ) -> Result<Self, rusb::Error> {
         let descriptor = device.device_descriptor()?;
         let speed = map_device_speed(device.speed());
         let transfer_config = SuperSpeedConfig::for_speed(speed);
@@ -46,96 +67,33 @@ impl UsbDevice {
         Ok(Self {
             device,
             id,
+            stable_id,
             descriptor,
             handle: None,
             speed,
-            transfer_config,
-            num_interfaces: 0,
+            claimed_interfaces: Vec::new(),
+            urbs: UrbEngine::new(),
         })
     }
 
-    /// Get the device ID
-    pub fn id(&self) -> DeviceId {
-        self.id
+    /// Port numbers from the root hub down to the device (empty if unknown)
+    fn port_numbers(&self) -> Vec<u8> {
+        self.device.port_numbers().unwrap_or_default()
     }
 
-    /// Get the bus number
-    pub fn bus_number(&self) -> u8 {
-        self.device.bus_number()
-    }
-
-    /// Get the device address
-    pub fn device_address(&self) -> u8 {
-        self.device.address()
-    }
-
-    /// Get the device speed
-    pub fn speed(&self) -> DeviceSpeed {
-        self.speed
-    }
-
-    /// Get the transfer configuration for this device
-    pub fn transfer_config(&self) -> &SuperSpeedConfig {
-        &self.transfer_config
-    }
-
-    /// Check if this is a SuperSpeed (USB 3.0+) device
-    pub fn is_superspeed(&self) -> bool {
-        self.speed.is_superspeed()
-    }
-
-    /// Get the maximum bulk transfer size for this device
-    ///
-    /// USB 3.0 SuperSpeed devices support up to 1MB transfers,
-    /// while USB 2.0 devices are limited to 64KB.
-    pub fn max_bulk_transfer_size(&self) -> usize {
-        self.transfer_config.max_bulk_size
-    }
-
-    /// Get the optimal URB buffer size for USB/IP transfers
-    pub fn optimal_urb_buffer_size(&self) -> usize {
-        self.transfer_config.urb_buffer_size
-    }
-
-    /// Convert to protocol DeviceInfo
-    ///
-    /// Reads string descriptors (manufacturer, product, serial) if available.
-    pub fn device_info(&self) -> DeviceInfo {
-        // Try to open device temporarily to read strings
-        let strings = self
-            .device
-            .open()
-            .ok()
-            .and_then(|handle| self.read_string_descriptors(&handle));
-
-        let (manufacturer, product, serial_number) = strings.unwrap_or((None, None, None));
-
-        DeviceInfo {
-            id: self.id,
-            vendor_id: self.descriptor.vendor_id(),
-            product_id: self.descriptor.product_id(),
-            bus_number: self.bus_number(),
-            device_address: self.device_address(),
-            manufacturer,
-            product,
-            serial_number,
-            class: self.descriptor.class_code(),
-            subclass: self.descriptor.sub_class_code(),
-            protocol: self.descriptor.protocol_code(),
-            speed: self.speed,
-            num_configurations: self.descriptor.num_configurations(),
-        }
+    /// Hub the device is plugged into
+    fn parent_hub(&self) -> Option<ParentHub> {
+        let parent = self.device.get_parent()?;
+        let descriptor = parent.device_descriptor().ok()?;
+        Some(ParentHub {
+            vendor_id: descriptor.vendor_id(),
+            product_id: descriptor.product_id(),
+            port_numbers: parent.port_numbers().unwrap_or_default(),
+        })
     }
 
-    /// Open the device for transfers
-    ///
-    /// This must be called before submitting any transfers.
-    /// This will automatically detach kernel drivers and claim interface 0.
-    pub fn open(&mut self) -> Result<(), AttachError> {
-        if self.handle.is_some() {
-            return Ok(()); // Already open
-        }
-
+    /// Open the libusb handle without claiming interfaces
+    fn open_handle(&mut self) -> Result<(), AttachError> {
         let handle = self.device.open().map_err(|e| {
             warn!("Failed to open device: {}", e);
             match e {
@@ -162,20 +120,20 @@ impl UsbDevice {
             debug!("Enabled auto-detach kernel driver for device {:?}", self.id);
         }
 
-        // Get the number of interfaces from the active configuration
-        let num_interfaces = match self.device.active_config_descriptor() {
-            Ok(config) => config.num_interfaces(),
-            Err(e) => {
-                warn!("Failed to get config descriptor, assuming 1 interface: {}", e);
-                1
-            }
+        self.handle = Some(handle);
+        Ok(())
+    }
+
+    /// Detach kernel drivers from and claim the given interfaces
+    fn claim_interfaces(&mut self, interfaces: &[u8]) {
+        let Some(handle) = self.handle.as_ref() else {
+            return;
         };
-        debug!("Device {:?} has {} interface(s)", self.id, num_interfaces);
 
-        // Detach kernel drivers from ALL interfaces
+        // Detach kernel drivers first
         // This is necessary because Linux kernel drivers (like usbhid, usb-storage)
         // will have claimed the interfaces, preventing us from accessing them
-        for iface in 0..num_interfaces {
+        for &iface in interfaces {
             match handle.kernel_driver_active(iface) {
                 Ok(true) => {
                     debug!(
@@ -197,31 +155,350 @@ impl UsbDevice {
             }
         }
 
-        // Claim all interfaces for our exclusive use
-        for iface in 0..num_interfaces {
+        // Claim the interfaces for our exclusive use
+        for &iface in interfaces {
             if let Err(e) = handle.claim_interface(iface) {
                 warn!("Failed to claim interface {}: {}", iface, e);
                 // Continue anyway - some interfaces may not be claimable
             } else {
                 debug!("Claimed interface {} on device {:?}", iface, self.id);
             }
+            // Remembered even on failure so close() tries to restore the driver
+            if !self.claimed_interfaces.contains(&iface) {
+                self.claimed_interfaces.push(iface);
+            }
+        }
+    }
+
+    /// Cancel all asynchronous transfers and wait for libusb to release them
+    ///
+    /// Must run before the handle is closed or reset: libusb still owns the
+    /// buffers of in-flight transfers.
+    fn cancel_transfers(&mut self) {
+        let Some(handle) = self.handle.as_ref() else {
+            return;
+        };
+        if !self.urbs.is_busy() {
+            return;
         }
 
-        // Store number of interfaces for close()
-        self.num_interfaces = num_interfaces;
+        debug!(
+            "Cancelling {} in-flight transfers on device {:?}",
+            self.urbs.in_flight(),
+            self.id
+        );
+        self.urbs.cancel_all();
+
+        let deadline = Instant::now() + TRANSFER_DRAIN_TIMEOUT;
+        while self.urbs.in_flight() > 0 && Instant::now() < deadline {
+            let _ = handle
+                .context()
+                .handle_events(Some(Duration::from_millis(10)));
+            self.urbs.process_completions(handle.as_raw());
+        }
 
-        self.handle = Some(handle);
+        if self.urbs.in_flight() > 0 {
+            warn!(
+                "{} transfers on device {:?} did not finish cancelling",
+                self.urbs.in_flight(),
+                self.id
+            );
+        }
+    }
+
+    /// Cancel transfers on some endpoints and wait for libusb to release them
+    ///
+    /// Used when interfaces are released while the handle stays open for
+    /// other clients.
+    fn cancel_endpoint_transfers(&mut self, endpoints: &[u8]) {
+        let Some(handle) = self.handle.as_ref() else {
+            return;
+        };
+        self.urbs.cancel_endpoints(endpoints);
+
+        let deadline = Instant::now() + TRANSFER_DRAIN_TIMEOUT;
+        while self.urbs.in_flight_on(endpoints) > 0 && Instant::now() < deadline {
+            let _ = handle
+                .context()
+                .handle_events(Some(Duration::from_millis(10)));
+            self.urbs.process_completions(handle.as_raw());
+        }
+    }
+
+    /// Read string descriptors from device
+    fn read_string_descriptors(
+        &self,
+        handle: &DeviceHandle<Context>,
+    ) -> Option<(Option<String>, Option<String>, Option<String>)> {
+        let manufacturer = self
+            .descriptor
+            .manufacturer_string_index()
+            .and_then(|idx| handle.read_string_descriptor_ascii(idx).ok());
+
+        let product = self
+            .descriptor
+            .product_string_index()
+            .and_then(|idx| handle.read_string_descriptor_ascii(idx).ok());
+
+        let serial_number = self
+            .descriptor
+            .serial_number_string_index()
+            .and_then(|idx| handle.read_string_descriptor_ascii(idx).ok());
+
+        Some((manufacturer, product, serial_number))
+    }
+}
+
+impl BackendDevice for UsbDevice {
+    /// Get the device ID
+    fn id(&self) -> DeviceId {
+        self.id
+    }
+
+    /// Set the device ID and stable identity once they have been assigned
+    fn set_identity(&mut self, id: DeviceId, stable_id: String) {
+        self.id = id;
+        self.stable_id = stable_id;
+    }
+
+    /// Get the bus number
+    fn bus_number(&self) -> u8 {
+        self.device.bus_number()
+    }
+
+    /// Get the device address
+    fn device_address(&self) -> u8 {
+        self.device.address()
+    }
+
+    /// Convert to protocol DeviceInfo
+    ///
+    /// Reads string descriptors (manufacturer, product, serial) if available.
+    fn device_info(&self) -> DeviceInfo {
+        // Try to open device temporarily to read strings
+        let strings = self
+            .device
+            .open()
+            .ok()
+            .and_then(|handle| self.read_string_descriptors(&handle));
+
+        let (manufacturer, product, serial_number) = strings.unwrap_or((None, None, None));
+
+        DeviceInfo {
+            manufacturer,
+            product,
+            serial_number,
+            ..self.descriptor_info()
+        }
+    }
+
+    /// Device information without string descriptors
+    ///
+    /// Built from the cached descriptors and port data only, so the device is
+    /// not opened.
+    fn descriptor_info(&self) -> DeviceInfo {
+        DeviceInfo {
+            id: self.id,
+            vendor_id: self.descriptor.vendor_id(),
+            product_id: self.descriptor.product_id(),
+            bus_number: self.bus_number(),
+            device_address: self.device_address(),
+            manufacturer: None,
+            product: None,
+            serial_number: None,
+            class: self.descriptor.class_code(),
+            subclass: self.descriptor.sub_class_code(),
+            protocol: self.descriptor.protocol_code(),
+            speed: self.speed,
+            num_configurations: self.descriptor.num_configurations(),
+            interfaces: self.interfaces(),
+            stable_id: self.stable_id.clone(),
+            port_numbers: self.port_numbers(),
+            parent_hub: self.parent_hub(),
+        }
+    }
+
+    /// Stable identities of this device, preferred first (see [`stable_ids`])
+    fn stable_ids(&self) -> Vec<String> {
+        stable_ids(&self.device, &self.descriptor)
+    }
+
+    /// bConfigurationValue of the active configuration
+    fn active_configuration(&self) -> Option<u8> {
+        self.device
+            .active_config_descriptor()
+            .ok()
+            .map(|config| config.number())
+    }
+
+    /// Interfaces of the active configuration (alternate setting 0)
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        let Ok(config) = self.device.active_config_descriptor() else {
+            return Vec::new();
+        };
+
+        config
+            .interfaces()
+            .filter_map(|iface| iface.descriptors().next())
+            .map(|alt| InterfaceInfo {
+                number: alt.interface_number(),
+                class: alt.class_code(),
+                subclass: alt.sub_class_code(),
+                protocol: alt.protocol_code(),
+                num_endpoints: alt.num_endpoints(),
+            })
+            .collect()
+    }
+
+    /// Endpoint addresses of the given interfaces, across all alternate settings
+    fn interface_endpoints(&self, interfaces: &[u8]) -> Vec<u8> {
+        let Ok(config) = self.device.active_config_descriptor() else {
+            return Vec::new();
+        };
+
+        let mut endpoints: Vec<u8> = config
+            .interfaces()
+            .filter(|iface| interfaces.contains(&iface.number()))
+            .flat_map(|iface| iface.descriptors())
+            .flat_map(|alt| {
+                alt.endpoint_descriptors()
+                    .map(|ep| ep.address())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        endpoints.sort_unstable();
+        endpoints.dedup();
+        endpoints
+    }
+
+    /// Full descriptor set for `GetDescriptorsRequest`
+    ///
+    /// Configuration descriptors come from libusb's cache. BOS and string
+    /// descriptors need the device open; if it is neither attached nor
+    /// openable they are left out.
+    fn descriptors(&self) -> Result<DeviceDescriptors, UsbError> {
+        let configurations = (0..self.descriptor.num_configurations())
+            .map(|index| {
+                self.device
+                    .config_descriptor(index)
+                    .map(|config| convert_config_descriptor(&config))
+                    .map_err(crate::usb::transfers::map_rusb_error)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut descriptors = DeviceDescriptors {
+            usb_version: version_to_bcd(self.descriptor.usb_version()),
+            device_version: version_to_bcd(self.descriptor.device_version()),
+            max_packet_size0: self.descriptor.max_packet_size(),
+            manufacturer_index: self.descriptor.manufacturer_string_index().unwrap_or(0),
+            product_index: self.descriptor.product_string_index().unwrap_or(0),
+            serial_number_index: self.descriptor.serial_number_string_index().unwrap_or(0),
+            active_configuration: self.active_configuration(),
+            configurations,
+            bos: None,
+            strings: Vec::new(),
+        };
+
+        // Reuse the open handle of an attached device
+        let temporary;
+        let handle = match &self.handle {
+            Some(handle) => handle,
+            None => match self.device.open() {
+                Ok(handle) => {
+                    temporary = handle;
+                    &temporary
+                }
+                Err(e) => {
+                    debug!("Cannot open {:?} to read strings: {}", self.id, e);
+                    return Ok(descriptors);
+                }
+            },
+        };
+
+        if descriptors.usb_version >= 0x0201 {
+            descriptors.bos = read_bos_descriptor(handle);
+        }
+        descriptors.strings = read_all_strings(handle, &descriptors);
+        Ok(descriptors)
+    }
+
+    /// Open the device for transfers
+    ///
+    /// This must be called before submitting any transfers.
+    /// This will automatically detach kernel drivers and claim all interfaces
+    /// of the active configuration.
+    fn open(&mut self) -> Result<(), AttachError> {
+        if self.handle.is_some() {
+            return Ok(()); // Already open
+        }
+
+        self.open_handle()?;
+
+        // Get the interfaces of the active configuration
+        let interfaces: Vec<u8> = match self.device.active_config_descriptor() {
+            Ok(config) => config.interfaces().map(|iface| iface.number()).collect(),
+            Err(e) => {
+                warn!("Failed to get config descriptor, assuming 1 interface: {}", e);
+                vec![0]
+            }
+        };
+        debug!("Device {:?} has {} interface(s)", self.id, interfaces.len());
+
+        self.claim_interfaces(&interfaces);
+        Ok(())
+    }
+
+    /// Open the device and claim only some of its interfaces
+    ///
+    /// The handle may already be open for other interfaces. Kernel drivers of
+    /// the remaining interfaces are left bound.
+    fn open_interfaces(&mut self, interfaces: &[u8]) -> Result<(), AttachError> {
+        if self.handle.is_none() {
+            self.open_handle()?;
+        }
+
+        self.claim_interfaces(interfaces);
         Ok(())
     }
 
+    /// Release some interfaces and give them back to the kernel
+    ///
+    /// Transfers on their endpoints are cancelled first; the handle stays open
+    /// for the remaining interfaces.
+    fn release_interfaces(&mut self, interfaces: &[u8]) {
+        let endpoints = self.interface_endpoints(interfaces);
+        self.cancel_endpoint_transfers(&endpoints);
+
+        let Some(handle) = self.handle.as_ref() else {
+            return;
+        };
+        for &iface in interfaces {
+            if let Err(e) = handle.release_interface(iface) {
+                warn!("Failed to release interface {}: {}", iface, e);
+            } else {
+                debug!("Released interface {} on device {:?}", iface, self.id);
+            }
+            if let Err(e) = handle.attach_kernel_driver(iface) {
+                debug!(
+                    "Could not reattach kernel driver to interface {} (may not have been detached): {}",
+                    iface, e
+                );
+            }
+        }
+        self.claimed_interfaces
+            .retain(|iface| !interfaces.contains(iface));
+    }
+
     /// Close the device
     ///
     /// This will release all claimed interfaces and reattach kernel drivers
     /// to restore the device to normal kernel control.
-    pub fn close(&mut self) {
+    fn close(&mut self) {
+        self.cancel_transfers();
+
         if let Some(handle) = self.handle.take() {
             // Release all interfaces before closing
-            for iface in 0..self.num_interfaces {
+            for &iface in &self.claimed_interfaces {
                 if let Err(e) = handle.release_interface(iface) {
                     warn!("Failed to release interface {}: {}", iface, e);
                 } else {
@@ -232,7 +509,7 @@ impl UsbDevice {
             // Reattach kernel drivers to restore device to kernel control
             // This allows the device to be used normally on the server again
             // after we're done sharing it via USB/IP
-            for iface in 0..self.num_interfaces {
+            for &iface in &self.claimed_interfaces {
                 if let Err(e) = handle.attach_kernel_driver(iface) {
                     // This may fail if no driver was attached originally, which is fine
                     debug!(
@@ -247,76 +524,409 @@ impl UsbDevice {
                 }
             }
 
-            self.num_interfaces = 0;
+            self.claimed_interfaces.clear();
             debug!("Closed device {:?}", self.id);
         }
     }
 
     /// Check if device is open
-    pub fn is_open(&self) -> bool {
+    fn is_open(&self) -> bool {
         self.handle.is_some()
     }
 
-    /// Get mutable reference to device handle
+    /// Submit a transfer asynchronously
+    ///
+    /// The response is sent once the transfer completes and
+    /// [`process_transfer_completions`](Self::process_transfer_completions)
+    /// has run.
+    fn submit_transfer(&mut self, request: UsbRequest, response: oneshot::Sender<UsbResponse>) {
+        let Some(handle) = self.handle.as_ref() else {
+            warn!("Device {:?} not open for transfer", self.id);
+            let _ = response.send(UsbResponse {
+                id: request.id,
+                result: TransferResult::Error {
+                    error: UsbError::NotFound,
+                },
+            });
+            return;
+        };
+
+        self.urbs.submit(handle.as_raw(), request, response);
+    }
+
+    /// Cancel one asynchronous transfer submitted on `handle`
+    ///
+    /// Returns `true` if the transfer was still queued or in flight; its
+    /// response then reports `UsbError::Cancelled`.
+    fn cancel_transfer(&mut self, handle: protocol::DeviceHandle, request_id: RequestId) -> bool {
+        self.urbs.cancel(handle, request_id)
+    }
+
+    /// Complete transfers that finished during the last event loop iteration
     ///
-    /// Returns None if device is not open.
-    pub fn handle_mut(&mut self) -> Option<&mut DeviceHandle<Context>> {
-        self.handle.as_mut()
+    /// Returns the number of transfers completed.
+    fn process_transfer_completions(&mut self) -> usize {
+        match self.handle.as_ref() {
+            Some(handle) => self.urbs.process_completions(handle.as_raw()),
+            None => 0,
+        }
+    }
+
+    /// Check if any asynchronous transfers are queued or in flight
+    fn has_pending_transfers(&self) -> bool {
+        self.urbs.is_busy()
     }
 
-    /// Claim an interface
+    /// Reset the device
     ///
-    /// This must be called before submitting transfers to non-zero endpoints.
-    pub fn claim_interface(&mut self, interface: u8) -> Result<(), rusb::Error> {
+    /// This will reset the device and invalidate any claimed interfaces.
+    fn reset(&mut self) -> Result<(), rusb::Error> {
+        self.cancel_transfers();
         let handle = self.handle.as_mut().ok_or(rusb::Error::InvalidParam)?;
 
-        handle.claim_interface(interface)?;
-        debug!("Claimed interface {} on device {:?}", interface, self.id);
+        handle.reset()?;
+        debug!("Reset device {:?}", self.id);
         Ok(())
     }
 
-    /// Release an interface
-    pub fn release_interface(&mut self, interface: u8) -> Result<(), rusb::Error> {
+    /// Clear a halt condition on an endpoint
+    fn clear_halt(&mut self, endpoint: u8) -> Result<(), rusb::Error> {
         let handle = self.handle.as_mut().ok_or(rusb::Error::InvalidParam)?;
 
-        handle.release_interface(interface)?;
-        debug!("Released interface {} on device {:?}", interface, self.id);
+        handle.clear_halt(endpoint)?;
+        debug!(
+            "Cleared halt on endpoint {:#x} of device {:?}",
+            endpoint, self.id
+        );
         Ok(())
     }
 
-    /// Reset the device
+    /// Activate a configuration
     ///
-    /// This will reset the device and invalidate any claimed interfaces.
-    pub fn reset(&mut self) -> Result<(), rusb::Error> {
+    /// Selecting the configuration that is already active does nothing; this
+    /// is what client kernels do after enumeration, and it keeps the claims of
+    /// other clients sharing the device by interface intact. Otherwise libusb
+    /// refuses to change configuration while interfaces are claimed, so
+    /// in-flight transfers are cancelled and the interfaces released first. The
+    /// interfaces of whichever configuration is active afterwards are claimed
+    /// again, even if the change failed.
+    fn set_configuration(&mut self, configuration: u8) -> Result<(), rusb::Error> {
+        if self.handle.is_none() {
+            return Err(rusb::Error::InvalidParam);
+        }
+        if self
+            .device
+            .active_config_descriptor()
+            .is_ok_and(|config| config.number() == configuration)
+        {
+            debug!(
+                "Configuration {} already active on device {:?}",
+                configuration, self.id
+            );
+            return Ok(());
+        }
+
+        self.cancel_transfers();
         let handle = self.handle.as_mut().ok_or(rusb::Error::InvalidParam)?;
 
-        handle.reset()?;
-        debug!("Reset device {:?}", self.id);
+        for &iface in &self.claimed_interfaces {
+            if let Err(e) = handle.release_interface(iface) {
+                debug!("Failed to release interface {}: {}", iface, e);
+            }
+        }
+
+        let result = handle.set_active_configuration(configuration);
+
+        let interfaces: Vec<u8> = self
+            .device
+            .active_config_descriptor()
+            .map(|config| config.interfaces().map(|iface| iface.number()).collect())
+            .unwrap_or_default();
+        for &iface in &interfaces {
+            // Auto-detach (enabled in open) unbinds any kernel driver first
+            if let Err(e) = handle.claim_interface(iface) {
+                warn!("Failed to claim interface {}: {}", iface, e);
+            }
+        }
+        self.claimed_interfaces = interfaces;
+
+        result?;
+        debug!(
+            "Set configuration {} on device {:?} ({} interface(s))",
+            configuration,
+            self.id,
+            self.claimed_interfaces.len()
+        );
         Ok(())
     }
 
-    /// Read string descriptors from device
-    fn read_string_descriptors(
-        &self,
-        handle: &DeviceHandle<Context>,
-    ) -> Option<(Option<String>, Option<String>, Option<String>)> {
-        let manufacturer = self
-            .descriptor
-            .manufacturer_string_index()
-            .and_then(|idx| handle.read_string_descriptor_ascii(idx).ok());
+    /// Select an alternate setting of a claimed interface
+    fn set_interface(&mut self, interface: u8, alt_setting: u8) -> Result<(), rusb::Error> {
+        let handle = self.handle.as_mut().ok_or(rusb::Error::InvalidParam)?;
 
-        let product = self
-            .descriptor
-            .product_string_index()
-            .and_then(|idx| handle.read_string_descriptor_ascii(idx).ok());
+        handle.set_alternate_setting(interface, alt_setting)?;
+        debug!(
+            "Set interface {} to alternate setting {} on device {:?}",
+            interface, alt_setting, self.id
+        );
+        Ok(())
+    }
 
-        let serial_number = self
-            .descriptor
-            .serial_number_string_index()
-            .and_then(|idx| handle.read_string_descriptor_ascii(idx).ok());
+    /// Look up an interrupt endpoint in the active configuration
+    ///
+    /// Returns the bytes per service interval and the polling interval in
+    /// milliseconds (high-speed and faster intervals are in 125us units).
+    /// Endpoints of other transfer types are rejected with `InvalidParam`.
+    fn endpoint_info(&self, endpoint: u8) -> Result<EndpointInfo, UsbError> {
+        let config = self
+            .device
+            .active_config_descriptor()
+            .map_err(crate::usb::transfers::map_rusb_error)?;
+
+        let descriptor = config
+            .interfaces()
+            .flat_map(|interface| interface.descriptors())
+            .flat_map(|alt| alt.endpoint_descriptors().collect::<Vec<_>>())
+            .find(|ep| ep.address() == endpoint)
+            .ok_or(UsbError::NotFound)?;
+        if descriptor.transfer_type() != rusb::TransferType::Interrupt {
+            return Err(UsbError::InvalidParam);
+        }
 
-        Some((manufacturer, product, serial_number))
+        Ok(interrupt_endpoint_info(
+            self.speed,
+            descriptor.max_packet_size(),
+            descriptor.interval(),
+        ))
+    }
+}
+
+impl Drop for UsbDevice {
+    fn drop(&mut self) {
+        // Hot-unplugged devices are dropped without close(); in-flight
+        // transfers must still be reaped before the handle goes away
+        self.cancel_transfers();
+    }
+}
+
+/// Service interval size and polling interval of an interrupt endpoint
+///
+/// `max_packet_size` and `interval` are the raw wMaxPacketSize and bInterval.
+/// High-speed and faster intervals are in 125us units.
+pub fn interrupt_endpoint_info(
+    speed: DeviceSpeed,
+    max_packet_size: u16,
+    interval: u8,
+) -> EndpointInfo {
+    // Bits 11..12 of wMaxPacketSize are extra transactions per microframe
+    let raw = max_packet_size;
+    let max_packet_size = (raw & 0x7ff) * (1 + ((raw >> 11) & 0x3));
+
+    let interval = interval.max(1);
+    let interval_ms = match speed {
+        DeviceSpeed::Low | DeviceSpeed::Full => interval,
+        _ => {
+            let micros = 125u32 << (interval.min(16) - 1);
+            (micros / 1000).clamp(1, u8::MAX as u32) as u8
+        }
+    };
+
+    EndpointInfo {
+        max_packet_size,
+        interval_ms,
+    }
+}
+
+/// Encode a descriptor version back into BCD (bcdUSB / bcdDevice)
+fn version_to_bcd(version: rusb::Version) -> u16 {
+    let major = version.major() as u16;
+    ((major / 10) << 12)
+        | ((major % 10) << 8)
+        | ((version.minor() as u16) << 4)
+        | version.sub_minor() as u16
+}
+
+/// Convert a libusb configuration descriptor into the protocol tree
+fn convert_config_descriptor(config: &rusb::ConfigDescriptor) -> ConfigurationDescriptor {
+    let interfaces = config
+        .interfaces()
+        .flat_map(|iface| iface.descriptors())
+        .map(|alt| InterfaceDescriptor {
+            number: alt.interface_number(),
+            alt_setting: alt.setting_number(),
+            class: alt.class_code(),
+            subclass: alt.sub_class_code(),
+            protocol: alt.protocol_code(),
+            string_index: alt.description_string_index().unwrap_or(0),
+            endpoints: alt
+                .endpoint_descriptors()
+                .map(|ep| EndpointDescriptor {
+                    address: ep.address(),
+                    attributes: endpoint_attributes(&ep),
+                    max_packet_size: ep.max_packet_size(),
+                    interval: ep.interval(),
+                    extra: ep.extra().map(<[u8]>::to_vec).unwrap_or_default(),
+                })
+                .collect(),
+            extra: alt.extra().to_vec(),
+        })
+        .collect();
+
+    // bmAttributes bit 7 is reserved and always set
+    let mut attributes = 0x80;
+    if config.self_powered() {
+        attributes |= 0x40;
+    }
+    if config.remote_wakeup() {
+        attributes |= 0x20;
+    }
+
+    ConfigurationDescriptor {
+        value: config.number(),
+        string_index: config.description_string_index().unwrap_or(0),
+        attributes,
+        max_power_ma: config.max_power(),
+        interfaces,
+        extra: config.extra().to_vec(),
+    }
+}
+
+/// Rebuild bmAttributes from the fields libusb decodes
+fn endpoint_attributes(endpoint: &rusb::EndpointDescriptor) -> u8 {
+    let transfer = match endpoint.transfer_type() {
+        rusb::TransferType::Control => 0,
+        rusb::TransferType::Isochronous => 1,
+        rusb::TransferType::Bulk => 2,
+        rusb::TransferType::Interrupt => 3,
+    };
+    let sync = match endpoint.sync_type() {
+        rusb::SyncType::NoSync => 0,
+        rusb::SyncType::Asynchronous => 1,
+        rusb::SyncType::Adaptive => 2,
+        rusb::SyncType::Synchronous => 3,
+    };
+    let usage = match endpoint.usage_type() {
+        rusb::UsageType::Data => 0,
+        rusb::UsageType::Feedback => 1,
+        rusb::UsageType::FeedbackData => 2,
+        rusb::UsageType::Reserved => 3,
+    };
+    transfer | (sync << 2) | (usage << 4)
+}
+
+/// Read the BOS descriptor set with GET_DESCRIPTOR(BOS)
+fn read_bos_descriptor(handle: &DeviceHandle<Context>) -> Option<Vec<u8>> {
+    let mut header = [0u8; 5];
+    let len = handle
+        .read_control(0x80, 0x06, 0x0F00, 0, &mut header, DESCRIPTOR_TIMEOUT)
+        .ok()?;
+    if len < header.len() || header[1] != 0x0F {
+        return None;
+    }
+
+    let mut bos = vec![0u8; u16::from_le_bytes([header[2], header[3]]) as usize];
+    let len = handle
+        .read_control(0x80, 0x06, 0x0F00, 0, &mut bos, DESCRIPTOR_TIMEOUT)
+        .ok()?;
+    bos.truncate(len);
+    Some(bos)
+}
+
+/// Read every string descriptor referenced by `descriptors`
+///
+/// Strings are read in the device's first language.
+fn read_all_strings(
+    handle: &DeviceHandle<Context>,
+    descriptors: &DeviceDescriptors,
+) -> Vec<StringDescriptor> {
+    let Some(language) = handle
+        .read_languages(DESCRIPTOR_TIMEOUT)
+        .ok()
+        .and_then(|languages| languages.first().copied())
+    else {
+        return Vec::new();
+    };
+
+    let mut indices = vec![
+        descriptors.manufacturer_index,
+        descriptors.product_index,
+        descriptors.serial_number_index,
+    ];
+    for config in &descriptors.configurations {
+        indices.push(config.string_index);
+        indices.extend(config.interfaces.iter().map(|iface| iface.string_index));
+    }
+    indices.retain(|&index| index != 0);
+    indices.sort_unstable();
+    indices.dedup();
+
+    indices
+        .into_iter()
+        .filter_map(|index| {
+            handle
+                .read_string_descriptor(language, index, DESCRIPTOR_TIMEOUT)
+                .ok()
+                .map(|value| StringDescriptor {
+                    index,
+                    language: language.lang_id(),
+                    value,
+                })
+        })
+        .collect()
+}
+
+/// Stable identities of a device, preferred first
+///
+/// The serial-number form follows the device to another port; the port-path
+/// form tells apart identical devices without a (unique) serial number.
+/// Opening the device to read the serial may fail without permissions, in
+/// which case only the port-path form is returned.
+pub fn stable_ids(device: &Device<Context>, descriptor: &DeviceDescriptor) -> Vec<String> {
+    let serial = descriptor.serial_number_string_index().and_then(|index| {
+        let handle = device.open().ok()?;
+        handle.read_string_descriptor_ascii(index).ok()
+    });
+    let ports = device.port_numbers().unwrap_or_default();
+
+    let mut ids = Vec::with_capacity(2);
+    if let Some(serial) = serial.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        ids.push(stable_id(
+            descriptor.vendor_id(),
+            descriptor.product_id(),
+            Some(serial),
+            device.bus_number(),
+            &ports,
+        ));
     }
+    ids.push(stable_id(
+        descriptor.vendor_id(),
+        descriptor.product_id(),
+        None,
+        device.bus_number(),
+        &ports,
+    ));
+    ids
+}
+
+/// Format a stable identity (`vvvv:pppp:SERIAL` or `vvvv:pppp@BUS-PORT.PORT`)
+pub fn stable_id(
+    vendor_id: u16,
+    product_id: u16,
+    serial: Option<&str>,
+    bus: u8,
+    ports: &[u8],
+) -> String {
+    if let Some(serial) = serial {
+        return format!("{:04x}:{:04x}:{}", vendor_id, product_id, serial);
+    }
+
+    // Without port numbers the bus alone is the best we have
+    format!(
+        "{:04x}:{:04x}@{}",
+        vendor_id,
+        product_id,
+        format_port_path(bus, ports)
+    )
 }
 
 /// Map rusb device speed to protocol DeviceSpeed
@@ -347,6 +957,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_version_to_bcd() {
+        for bcd in [0x0110, 0x0200, 0x0201, 0x0320, 0x1234] {
+            assert_eq!(version_to_bcd(rusb::Version::from_bcd(bcd)), bcd);
+        }
+    }
+
+    #[test]
+    fn test_stable_id_format() {
+        assert_eq!(
+            stable_id(0x1050, 0x0407, Some("12345678"), 1, &[1, 3]),
+            "1050:0407:12345678"
+        );
+        assert_eq!(
+            stable_id(0x1050, 0x0407, None, 1, &[1, 3]),
+            "1050:0407@1-1.3"
+        );
+        assert_eq!(stable_id(0x04f9, 0x0042, None, 2, &[4]), "04f9:0042@2-4");
+    }
+
     #[test]
     fn test_device_id_copy() {
         let id1 = DeviceId(42);
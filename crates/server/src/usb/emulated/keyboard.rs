@@ -0,0 +1,326 @@
+//! Emulated HID boot keyboard
+//!
+//! Types text queued through its [`KeyboardInput`] as press and release
+//! reports on its interrupt IN endpoint.
+
+use super::{DeviceModel, Setup, VirtualFunction};
+use protocol::{
+    ConfigurationDescriptor, DeviceSpeed, EndpointDescriptor, InterfaceDescriptor, UsbError,
+};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Interrupt IN endpoint carrying the input reports
+const REPORT_ENDPOINT: u8 = 0x81;
+
+/// Size of a boot keyboard input report
+const REPORT_SIZE: usize = 8;
+
+/// Left shift bit of the modifier byte
+const MODIFIER_SHIFT: u8 = 0x02;
+
+/// Boot keyboard report descriptor (HID 1.11, appendix B.1)
+const REPORT_DESCRIPTOR: [u8; 63] = [
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xa1, 0x01, // Collection (Application)
+    0x05, 0x07, //   Usage Page (Key Codes)
+    0x19, 0xe0, //   Usage Minimum (224)
+    0x29, 0xe7, //   Usage Maximum (231)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute): modifiers
+    0x95, 0x01, //   Report Count (1)
+    0x75, 0x08, //   Report Size (8)
+    0x81, 0x01, //   Input (Constant): reserved byte
+    0x95, 0x05, //   Report Count (5)
+    0x75, 0x01, //   Report Size (1)
+    0x05, 0x08, //   Usage Page (LEDs)
+    0x19, 0x01, //   Usage Minimum (1)
+    0x29, 0x05, //   Usage Maximum (5)
+    0x91, 0x02, //   Output (Data, Variable, Absolute): LEDs
+    0x95, 0x01, //   Report Count (1)
+    0x75, 0x03, //   Report Size (3)
+    0x91, 0x01, //   Output (Constant): LED padding
+    0x95, 0x06, //   Report Count (6)
+    0x75, 0x08, //   Report Size (8)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x65, //   Logical Maximum (101)
+    0x05, 0x07, //   Usage Page (Key Codes)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0x65, //   Usage Maximum (101)
+    0x81, 0x00, //   Input (Data, Array): key array
+    0xc0, // End Collection
+];
+
+/// HID descriptor pointing at the report descriptor
+fn hid_descriptor() -> Vec<u8> {
+    let length = (REPORT_DESCRIPTOR.len() as u16).to_le_bytes();
+    vec![9, 0x21, 0x11, 0x01, 0x00, 0x01, 0x22, length[0], length[1]]
+}
+
+/// Queue of input reports shared with the keyboard
+///
+/// Stays usable after the keyboard has been plugged into a bus.
+#[derive(Clone, Default)]
+pub struct KeyboardInput {
+    reports: Arc<Mutex<VecDeque<[u8; REPORT_SIZE]>>>,
+}
+
+impl KeyboardInput {
+    /// Queue keystrokes typing `text`
+    ///
+    /// Each character is pressed and released; characters without a key on
+    /// a US layout are skipped.
+    pub fn type_text(&self, text: &str) {
+        let mut reports = self.reports.lock().unwrap();
+        for (modifier, key) in text.chars().filter_map(key_code) {
+            reports.push_back([modifier, 0, key, 0, 0, 0, 0, 0]);
+            reports.push_back([0; REPORT_SIZE]);
+        }
+    }
+
+    fn next_report(&self) -> Option<[u8; REPORT_SIZE]> {
+        self.reports.lock().unwrap().pop_front()
+    }
+}
+
+/// USB HID boot keyboard
+pub struct HidKeyboard {
+    input: KeyboardInput,
+    /// Last report sent, returned by GET_REPORT
+    last_report: [u8; REPORT_SIZE],
+    /// Idle rate in 4 ms units (reports are only sent on change)
+    idle_rate: u8,
+    /// 0 boot protocol, 1 report protocol
+    protocol: u8,
+    /// LED state set by the host
+    leds: u8,
+}
+
+impl HidKeyboard {
+    /// Create a keyboard with nothing to type
+    pub fn new() -> Self {
+        Self {
+            input: KeyboardInput::default(),
+            last_report: [0; REPORT_SIZE],
+            idle_rate: 0,
+            protocol: 1,
+            leds: 0,
+        }
+    }
+
+    /// Handle to queue keystrokes
+    pub fn input(&self) -> KeyboardInput {
+        self.input.clone()
+    }
+}
+
+impl Default for HidKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualFunction for HidKeyboard {
+    fn model(&self) -> DeviceModel {
+        DeviceModel {
+            product_id: 0x0001,
+            class: 0,
+            subclass: 0,
+            protocol: 0,
+            device_version: 0x0100,
+            speed: DeviceSpeed::Full,
+            product: "Emulated Keyboard",
+            configuration: ConfigurationDescriptor {
+                value: 1,
+                string_index: 0,
+                attributes: 0xa0, // Bus powered, remote wakeup
+                max_power_ma: 100,
+                interfaces: vec![InterfaceDescriptor {
+                    number: 0,
+                    alt_setting: 0,
+                    class: 0x03,
+                    subclass: 0x01, // Boot interface
+                    protocol: 0x01, // Keyboard
+                    string_index: 0,
+                    endpoints: vec![EndpointDescriptor {
+                        address: REPORT_ENDPOINT,
+                        attributes: 0x03,
+                        max_packet_size: REPORT_SIZE as u16,
+                        interval: 10,
+                        extra: Vec::new(),
+                    }],
+                    extra: hid_descriptor(),
+                }],
+                extra: Vec::new(),
+            },
+        }
+    }
+
+    fn control(&mut self, setup: &Setup, data: &[u8]) -> Result<Vec<u8>, UsbError> {
+        match (setup.request_type, setup.request) {
+            // GET_DESCRIPTOR (interface): HID or report descriptor
+            (0x81, 0x06) => match setup.value >> 8 {
+                0x21 => Ok(hid_descriptor()),
+                0x22 => Ok(REPORT_DESCRIPTOR.to_vec()),
+                _ => Err(UsbError::Pipe),
+            },
+            // GET_REPORT: last input report, or the LED output report
+            (0xa1, 0x01) => match setup.value >> 8 {
+                0x01 => Ok(self.last_report.to_vec()),
+                0x02 => Ok(vec![self.leds]),
+                _ => Err(UsbError::Pipe),
+            },
+            // GET_IDLE
+            (0xa1, 0x02) => Ok(vec![self.idle_rate]),
+            // GET_PROTOCOL
+            (0xa1, 0x03) => Ok(vec![self.protocol]),
+            // SET_REPORT: output report with the LED state
+            (0x21, 0x09) => {
+                self.leds = data.first().copied().unwrap_or(0);
+                Ok(Vec::new())
+            }
+            // SET_IDLE
+            (0x21, 0x0a) => {
+                self.idle_rate = (setup.value >> 8) as u8;
+                Ok(Vec::new())
+            }
+            // SET_PROTOCOL
+            (0x21, 0x0b) => {
+                self.protocol = setup.value as u8;
+                Ok(Vec::new())
+            }
+            _ => Err(UsbError::Pipe),
+        }
+    }
+
+    fn write(&mut self, _endpoint: u8, _data: &[u8]) -> Option<Result<(), UsbError>> {
+        Some(Err(UsbError::NotFound))
+    }
+
+    fn read(&mut self, endpoint: u8, length: usize) -> Option<Result<Vec<u8>, UsbError>> {
+        if endpoint != REPORT_ENDPOINT {
+            return Some(Err(UsbError::NotFound));
+        }
+
+        let report = self.input.next_report()?;
+        self.last_report = report;
+        Some(Ok(report[..length.min(REPORT_SIZE)].to_vec()))
+    }
+
+    fn reset(&mut self) {
+        self.last_report = [0; REPORT_SIZE];
+        self.idle_rate = 0;
+        self.protocol = 1;
+        self.leds = 0;
+    }
+}
+
+/// Modifier byte and usage ID typing `c` on a US layout
+fn key_code(c: char) -> Option<(u8, u8)> {
+    const SHIFTED_DIGITS: &str = ")!@#$%^&*(";
+    const PUNCTUATION: &str = "-=[]\\;'`,./";
+    const SHIFTED_PUNCTUATION: &str = "_+{}|:\"~<>?";
+    const PUNCTUATION_KEYS: [u8; 11] = [
+        0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38,
+    ];
+
+    let digit_key = |digit: u8| if digit == 0 { 0x27 } else { 0x1d + digit };
+    match c {
+        'a'..='z' => Some((0, 0x04 + (c as u8 - b'a'))),
+        'A'..='Z' => Some((MODIFIER_SHIFT, 0x04 + (c as u8 - b'A'))),
+        '0'..='9' => Some((0, digit_key(c as u8 - b'0'))),
+        '\n' => Some((0, 0x28)),
+        '\t' => Some((0, 0x2b)),
+        ' ' => Some((0, 0x2c)),
+        _ => {
+            if let Some(digit) = SHIFTED_DIGITS.find(c) {
+                Some((MODIFIER_SHIFT, digit_key(digit as u8)))
+            } else if let Some(index) = PUNCTUATION.find(c) {
+                Some((0, PUNCTUATION_KEYS[index]))
+            } else {
+                SHIFTED_PUNCTUATION
+                    .find(c)
+                    .map(|index| (MODIFIER_SHIFT, PUNCTUATION_KEYS[index]))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_codes() {
+        assert_eq!(key_code('a'), Some((0, 0x04)));
+        assert_eq!(key_code('Z'), Some((MODIFIER_SHIFT, 0x1d)));
+        assert_eq!(key_code('1'), Some((0, 0x1e)));
+        assert_eq!(key_code('0'), Some((0, 0x27)));
+        assert_eq!(key_code('!'), Some((MODIFIER_SHIFT, 0x1e)));
+        assert_eq!(key_code(')'), Some((MODIFIER_SHIFT, 0x27)));
+        assert_eq!(key_code('/'), Some((0, 0x38)));
+        assert_eq!(key_code('?'), Some((MODIFIER_SHIFT, 0x38)));
+        assert_eq!(key_code('\n'), Some((0, 0x28)));
+        assert_eq!(key_code('é'), None);
+    }
+
+    #[test]
+    fn test_typing_produces_press_and_release() {
+        let mut keyboard = HidKeyboard::new();
+        keyboard.input().type_text("Hi");
+
+        let mut reports = Vec::new();
+        while let Some(report) = keyboard.read(REPORT_ENDPOINT, REPORT_SIZE) {
+            reports.push(report.unwrap());
+        }
+        assert_eq!(
+            reports,
+            vec![
+                vec![MODIFIER_SHIFT, 0, 0x0b, 0, 0, 0, 0, 0],
+                vec![0; REPORT_SIZE],
+                vec![0, 0, 0x0c, 0, 0, 0, 0, 0],
+                vec![0; REPORT_SIZE],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_class_requests() {
+        let mut keyboard = HidKeyboard::new();
+        let setup = |request_type, request, value| Setup {
+            request_type,
+            request,
+            value,
+            index: 0,
+        };
+
+        let report_descriptor = keyboard.control(&setup(0x81, 0x06, 0x2200), &[]).unwrap();
+        assert_eq!(report_descriptor.len(), hid_descriptor()[7] as usize);
+
+        keyboard.control(&setup(0x21, 0x0a, 0x7d00), &[]).unwrap();
+        assert_eq!(
+            keyboard.control(&setup(0xa1, 0x02, 0), &[]).unwrap(),
+            [0x7d]
+        );
+
+        keyboard.control(&setup(0x21, 0x0b, 0), &[]).unwrap();
+        assert_eq!(keyboard.control(&setup(0xa1, 0x03, 0), &[]).unwrap(), [0]);
+
+        keyboard
+            .control(&setup(0x21, 0x09, 0x0200), &[0x02])
+            .unwrap();
+        assert_eq!(
+            keyboard.control(&setup(0xa1, 0x01, 0x0200), &[]).unwrap(),
+            [0x02]
+        );
+
+        assert!(matches!(
+            keyboard.control(&setup(0xa1, 0x42, 0), &[]),
+            Err(UsbError::Pipe)
+        ));
+    }
+}
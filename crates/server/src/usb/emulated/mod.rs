@@ -0,0 +1,1226 @@
+//! Emulated USB backend
+//!
+//! Serves virtual devices implemented in software, so the server and everything
+//! above the USB layer runs in CI and on machines without USB hardware or
+//! permissions. Devices are plugged into an [`EmulatedBus`], which can also
+//! unplug them again to simulate hot-plug.
+//!
+//! Each device is a [`VirtualFunction`] described by a [`DeviceModel`].
+//! Standard requests on endpoint 0 (descriptors, configuration, alternate
+//! settings) are answered here; class requests and transfers on the other
+//! endpoints go to the function:
+//! - [`HidKeyboard`]: boot keyboard typing scripted text
+//! - [`CdcAcm`]: serial port echoing everything written to it
+//! - [`MassStorage`]: bulk-only SCSI disk backed by an image file
+
+mod keyboard;
+mod serial;
+mod storage;
+
+pub use keyboard::HidKeyboard;
+pub use serial::CdcAcm;
+pub use storage::MassStorage;
+
+use crate::config::EmulatedDeviceConfig;
+use crate::usb::backend::{BackendDevice, UsbBackend};
+use crate::usb::device::{interrupt_endpoint_info, stable_id};
+use crate::usb::manager::HotplugSink;
+use anyhow::{Context as _, Result};
+use common::EndpointInfo;
+use protocol::{
+    AttachError, ConfigurationDescriptor, DeviceDescriptors, DeviceHandle, DeviceId, DeviceInfo,
+    DeviceSpeed, InterfaceInfo, RequestId, StringDescriptor, TransferResult, TransferType,
+    UsbError, UsbRequest, UsbResponse,
+    integrity::{compute_checksum, verify_checksum},
+};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tracing::{debug, info};
+
+/// Bus number of the emulated root hub
+const EMULATED_BUS: u8 = 1;
+
+/// Highest port of the emulated root hub (addresses go up to 127)
+const MAX_PORT: u8 = 126;
+
+/// Vendor ID of the virtual devices (pid.codes open-source vendor ID)
+const VENDOR_ID: u16 = 0x1209;
+
+/// Manufacturer string of the virtual devices
+const MANUFACTURER: &str = "rust-p2p-usb";
+
+/// bMaxPacketSize0 of the virtual devices
+const MAX_PACKET_SIZE0: u8 = 64;
+
+/// Control IN buffer size when the request does not give one
+const DEFAULT_CONTROL_IN_SIZE: usize = 64;
+
+/// Language of the string descriptors (US English)
+const LANGUAGE_ID: u16 = 0x0409;
+
+/// Setup packet of a control transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Setup {
+    /// bmRequestType
+    pub request_type: u8,
+    /// bRequest
+    pub request: u8,
+    /// wValue
+    pub value: u16,
+    /// wIndex
+    pub index: u16,
+}
+
+impl Setup {
+    /// Standard (chapter 9) request
+    fn is_standard(&self) -> bool {
+        self.request_type & 0x60 == 0
+    }
+
+    /// Recipient bits (0 device, 1 interface, 2 endpoint)
+    fn recipient(&self) -> u8 {
+        self.request_type & 0x1f
+    }
+}
+
+/// Descriptors of a virtual device
+#[derive(Debug, Clone)]
+pub struct DeviceModel {
+    /// idProduct (idVendor is the pid.codes vendor ID)
+    pub product_id: u16,
+    /// bDeviceClass
+    pub class: u8,
+    /// bDeviceSubClass
+    pub subclass: u8,
+    /// bDeviceProtocol
+    pub protocol: u8,
+    /// bcdDevice
+    pub device_version: u16,
+    /// Bus speed
+    pub speed: DeviceSpeed,
+    /// Product string
+    pub product: &'static str,
+    /// The only configuration (bConfigurationValue 1)
+    pub configuration: ConfigurationDescriptor,
+}
+
+/// Behaviour of a virtual device
+///
+/// Transfers that cannot complete yet return `None` and are retried after the
+/// next event loop iteration, like a NAK on a real bus.
+pub trait VirtualFunction: Send {
+    /// Descriptors of the device
+    fn model(&self) -> DeviceModel;
+
+    /// Class and vendor requests, and GET_DESCRIPTOR for an interface
+    ///
+    /// Returns the data stage of IN requests. Unsupported requests stall.
+    fn control(&mut self, setup: &Setup, data: &[u8]) -> Result<Vec<u8>, UsbError>;
+
+    /// Data written to an OUT endpoint
+    fn write(&mut self, endpoint: u8, data: &[u8]) -> Option<Result<(), UsbError>>;
+
+    /// Read up to `length` bytes from an IN endpoint
+    fn read(&mut self, endpoint: u8, length: usize) -> Option<Result<Vec<u8>, UsbError>>;
+
+    /// Port or bus reset
+    fn reset(&mut self) {}
+}
+
+/// A virtual device plugged into the bus
+struct Slot {
+    /// Root hub port (the device address is one higher)
+    port: u8,
+    /// Descriptors, read once when plugged in
+    model: DeviceModel,
+    /// Device behaviour
+    function: Mutex<Box<dyn VirtualFunction>>,
+    /// Cleared when unplugged
+    connected: AtomicBool,
+}
+
+impl Slot {
+    fn address(&self) -> u8 {
+        self.port + 1
+    }
+
+    /// Serial number; follows the port so IDs survive restarts
+    fn serial(&self) -> String {
+        format!("EMU{:04}", self.port)
+    }
+}
+
+#[derive(Default)]
+struct BusState {
+    /// Plugged devices by port
+    slots: BTreeMap<u8, Arc<Slot>>,
+    /// Where to report plugs and unplugs
+    hotplug: Option<HotplugSink>,
+}
+
+/// Root hub of the emulated backend
+///
+/// Cloning gives another handle to the same bus, so tests and scripts can
+/// plug and unplug devices while the server runs.
+#[derive(Clone, Default)]
+pub struct EmulatedBus {
+    state: Arc<Mutex<BusState>>,
+}
+
+impl EmulatedBus {
+    /// Create an empty bus
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a bus with the configured devices
+    ///
+    /// Without configured devices, a keyboard and a serial loopback are
+    /// plugged in.
+    pub fn from_config(devices: &[EmulatedDeviceConfig]) -> Result<Self> {
+        let bus = Self::new();
+        if devices.is_empty() {
+            bus.plug(HidKeyboard::new());
+            bus.plug(CdcAcm::new());
+            return Ok(bus);
+        }
+
+        for device in devices {
+            match device {
+                EmulatedDeviceConfig::HidKeyboard { script } => {
+                    let keyboard = HidKeyboard::new();
+                    keyboard.input().type_text(script);
+                    bus.plug(keyboard);
+                }
+                EmulatedDeviceConfig::CdcAcm => {
+                    bus.plug(CdcAcm::new());
+                }
+                EmulatedDeviceConfig::MassStorage { image, read_only } => {
+                    let path = PathBuf::from(shellexpand::tilde(&image.to_string_lossy()).as_ref());
+                    let storage = MassStorage::open(&path, *read_only).with_context(|| {
+                        format!("Failed to open disk image: {}", path.display())
+                    })?;
+                    bus.plug(storage);
+                }
+            }
+        }
+        Ok(bus)
+    }
+
+    /// Plug a device into the lowest free port
+    ///
+    /// Returns the device address, or `None` if every port is in use.
+    pub fn plug(&self, function: impl VirtualFunction + 'static) -> Option<u8> {
+        let mut state = self.state.lock().unwrap();
+        let port = (1..=MAX_PORT).find(|port| !state.slots.contains_key(port))?;
+
+        let slot = Arc::new(Slot {
+            port,
+            model: function.model(),
+            function: Mutex::new(Box::new(function)),
+            connected: AtomicBool::new(true),
+        });
+        let address = slot.address();
+        info!(
+            "Emulated device plugged in: {} at bus={}, addr={}",
+            slot.model.product, EMULATED_BUS, address
+        );
+        state.slots.insert(port, slot);
+
+        if let Some(hotplug) = &state.hotplug {
+            hotplug.device_arrived(EMULATED_BUS, address);
+        }
+        Some(address)
+    }
+
+    /// Unplug the device at `address`
+    ///
+    /// Its outstanding transfers fail with `NoDevice`. Returns false if no
+    /// device has that address.
+    #[allow(dead_code)] // Only tests simulate unplugs so far
+    pub fn unplug(&self, address: u8) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(slot) = address
+            .checked_sub(1)
+            .and_then(|port| state.slots.remove(&port))
+        else {
+            return false;
+        };
+
+        slot.connected.store(false, Ordering::Release);
+        info!(
+            "Emulated device unplugged: {} at bus={}, addr={}",
+            slot.model.product, EMULATED_BUS, address
+        );
+        if let Some(hotplug) = &state.hotplug {
+            hotplug.device_left(EMULATED_BUS, address);
+        }
+        true
+    }
+
+    fn slots(&self) -> Vec<Arc<Slot>> {
+        self.state.lock().unwrap().slots.values().cloned().collect()
+    }
+
+    fn slot(&self, address: u8) -> Option<Arc<Slot>> {
+        let port = address.checked_sub(1)?;
+        self.state.lock().unwrap().slots.get(&port).cloned()
+    }
+}
+
+/// USB backend serving the devices of an [`EmulatedBus`]
+pub struct EmulatedBackend {
+    bus: EmulatedBus,
+}
+
+impl EmulatedBackend {
+    /// Serve the devices plugged into `bus`
+    pub fn new(bus: EmulatedBus) -> Self {
+        Self { bus }
+    }
+}
+
+impl UsbBackend for EmulatedBackend {
+    fn devices(&self) -> Result<Vec<Box<dyn BackendDevice>>, rusb::Error> {
+        Ok(self
+            .bus
+            .slots()
+            .into_iter()
+            .map(|slot| Box::new(EmulatedDevice::new(slot)) as Box<dyn BackendDevice>)
+            .collect())
+    }
+
+    fn device(&self, bus: u8, address: u8) -> Option<Box<dyn BackendDevice>> {
+        if bus != EMULATED_BUS {
+            return None;
+        }
+        let slot = self.bus.slot(address)?;
+        Some(Box::new(EmulatedDevice::new(slot)))
+    }
+
+    fn register_hotplug(&mut self, hotplug: HotplugSink) -> Result<(), rusb::Error> {
+        self.bus.state.lock().unwrap().hotplug = Some(hotplug);
+        Ok(())
+    }
+
+    fn handle_events(&self, timeout: Duration) -> Result<(), rusb::Error> {
+        // Virtual devices have no interrupts to wait for; pending transfers
+        // are polled after every iteration
+        std::thread::sleep(timeout);
+        Ok(())
+    }
+}
+
+/// Bulk or interrupt transfer waiting for its device
+struct PendingTransfer {
+    request_id: RequestId,
+    handle: DeviceHandle,
+    endpoint: u8,
+    /// Data of OUT transfers
+    data: Vec<u8>,
+    /// Requested length of IN transfers
+    length: usize,
+    /// Checksum bulk IN data like the libusb backend does
+    bulk: bool,
+    /// None for transfers without a timeout
+    deadline: Option<Instant>,
+    response: oneshot::Sender<UsbResponse>,
+}
+
+impl PendingTransfer {
+    fn is_in(&self) -> bool {
+        self.endpoint & 0x80 != 0
+    }
+}
+
+/// [`BackendDevice`] of a virtual device
+struct EmulatedDevice {
+    slot: Arc<Slot>,
+    id: DeviceId,
+    stable_id: String,
+    open: bool,
+    claimed_interfaces: Vec<u8>,
+    /// bConfigurationValue, 0 while unconfigured
+    configuration: u8,
+    /// Selected alternate settings (absent = 0)
+    alt_settings: HashMap<u8, u8>,
+    /// Bulk and interrupt transfers in submission order
+    pending: VecDeque<PendingTransfer>,
+    /// Responses to send on the next completion pass
+    completed: Vec<(oneshot::Sender<UsbResponse>, UsbResponse)>,
+}
+
+impl EmulatedDevice {
+    fn new(slot: Arc<Slot>) -> Self {
+        Self {
+            configuration: slot.model.configuration.value,
+            slot,
+            id: DeviceId(0),
+            stable_id: String::new(),
+            open: false,
+            claimed_interfaces: Vec::new(),
+            alt_settings: HashMap::new(),
+            pending: VecDeque::new(),
+            completed: Vec::new(),
+        }
+    }
+
+    fn model(&self) -> &DeviceModel {
+        &self.slot.model
+    }
+
+    fn is_connected(&self) -> bool {
+        self.slot.connected.load(Ordering::Acquire)
+    }
+
+    fn respond(
+        &mut self,
+        response: oneshot::Sender<UsbResponse>,
+        id: RequestId,
+        result: TransferResult,
+    ) {
+        self.completed.push((response, UsbResponse { id, result }));
+    }
+
+    fn respond_error(
+        &mut self,
+        response: oneshot::Sender<UsbResponse>,
+        id: RequestId,
+        error: UsbError,
+    ) {
+        self.respond(response, id, TransferResult::Error { error });
+    }
+
+    /// Strings by descriptor index (1 manufacturer, 2 product, 3 serial)
+    fn strings(&self) -> Vec<StringDescriptor> {
+        [
+            MANUFACTURER.to_string(),
+            self.model().product.to_string(),
+            self.slot.serial(),
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|(value, index)| StringDescriptor {
+            index,
+            language: LANGUAGE_ID,
+            value,
+        })
+        .collect()
+    }
+
+    /// Standard 18-byte device descriptor
+    fn device_descriptor(&self) -> Vec<u8> {
+        let model = self.model();
+        let mut descriptor = vec![18, 0x01];
+        descriptor.extend_from_slice(&usb_version(model.speed).to_le_bytes());
+        descriptor.extend_from_slice(&[
+            model.class,
+            model.subclass,
+            model.protocol,
+            MAX_PACKET_SIZE0,
+        ]);
+        descriptor.extend_from_slice(&VENDOR_ID.to_le_bytes());
+        descriptor.extend_from_slice(&model.product_id.to_le_bytes());
+        descriptor.extend_from_slice(&model.device_version.to_le_bytes());
+        descriptor.extend_from_slice(&[1, 2, 3, 1]);
+        descriptor
+    }
+
+    /// Endpoint addresses of the claimed interfaces
+    fn claimed_endpoints(&self) -> Vec<u8> {
+        self.interface_endpoints(&self.claimed_interfaces)
+    }
+
+    /// Answer a control transfer
+    fn control(&mut self, setup: &Setup, data: &[u8]) -> Result<Vec<u8>, UsbError> {
+        if !setup.is_standard() || setup.recipient() == 0x01 && setup.request == 0x06 {
+            return self.slot.function.lock().unwrap().control(setup, data);
+        }
+
+        match (setup.recipient(), setup.request) {
+            // GET_STATUS
+            (_, 0x00) => Ok(vec![0, 0]),
+            // CLEAR_FEATURE, SET_FEATURE, SET_ADDRESS
+            (_, 0x01 | 0x03 | 0x05) => Ok(Vec::new()),
+            // GET_DESCRIPTOR
+            (0x00, 0x06) => self.get_descriptor((setup.value >> 8) as u8, setup.value as u8),
+            // GET_CONFIGURATION
+            (0x00, 0x08) => Ok(vec![self.configuration]),
+            // SET_CONFIGURATION
+            (0x00, 0x09) => self
+                .set_configuration(setup.value as u8)
+                .map(|()| Vec::new())
+                .map_err(crate::usb::transfers::map_rusb_error),
+            // GET_INTERFACE
+            (0x01, 0x0a) => Ok(vec![
+                self.alt_settings
+                    .get(&(setup.index as u8))
+                    .copied()
+                    .unwrap_or(0),
+            ]),
+            // SET_INTERFACE
+            (0x01, 0x0b) => self
+                .set_interface(setup.index as u8, setup.value as u8)
+                .map(|()| Vec::new())
+                .map_err(crate::usb::transfers::map_rusb_error),
+            _ => Err(UsbError::Pipe),
+        }
+    }
+
+    /// GET_DESCRIPTOR addressed to the device
+    fn get_descriptor(&self, kind: u8, index: u8) -> Result<Vec<u8>, UsbError> {
+        match (kind, index) {
+            (0x01, _) => Ok(self.device_descriptor()),
+            (0x02, 0) => Ok(encode_configuration(&self.model().configuration)),
+            (0x03, 0) => Ok([4, 0x03]
+                .into_iter()
+                .chain(LANGUAGE_ID.to_le_bytes())
+                .collect()),
+            (0x03, index) => self
+                .strings()
+                .into_iter()
+                .find(|string| string.index == index)
+                .map(|string| encode_string(&string.value))
+                .ok_or(UsbError::Pipe),
+            // No device qualifier, BOS or other descriptors
+            _ => Err(UsbError::Pipe),
+        }
+    }
+
+    /// Complete the pending transfers the device is ready for
+    ///
+    /// Transfers on one endpoint complete in order: once one waits, the
+    /// later ones on its endpoint wait too.
+    fn run_pending(&mut self) {
+        let now = Instant::now();
+        let mut waiting = HashSet::new();
+        let mut still_pending = VecDeque::with_capacity(self.pending.len());
+
+        while let Some(transfer) = self.pending.pop_front() {
+            if waiting.contains(&transfer.endpoint) {
+                still_pending.push_back(transfer);
+                continue;
+            }
+
+            let result = {
+                let mut function = self.slot.function.lock().unwrap();
+                if transfer.is_in() {
+                    function
+                        .read(transfer.endpoint, transfer.length)
+                        .map(|result| result.map(|data| transfer_data(data, transfer.bulk)))
+                } else {
+                    function
+                        .write(transfer.endpoint, &transfer.data)
+                        .map(|result| result.map(|()| transfer_data(Vec::new(), false)))
+                }
+            };
+
+            let result = match result {
+                Some(Ok(result)) => result,
+                Some(Err(error)) => TransferResult::Error { error },
+                None if transfer.deadline.is_some_and(|deadline| now >= deadline) => {
+                    // IN timeouts report no data so clients simply resubmit,
+                    // as with the libusb backend
+                    if transfer.is_in() {
+                        transfer_data(Vec::new(), false)
+                    } else {
+                        TransferResult::Error {
+                            error: UsbError::Timeout,
+                        }
+                    }
+                }
+                None => {
+                    waiting.insert(transfer.endpoint);
+                    still_pending.push_back(transfer);
+                    continue;
+                }
+            };
+            self.respond(transfer.response, transfer.request_id, result);
+        }
+
+        self.pending = still_pending;
+    }
+
+    /// Fail the pending transfers on `endpoints` (all if None)
+    fn cancel_pending(&mut self, endpoints: Option<&[u8]>, error: UsbError) {
+        let (cancelled, kept): (VecDeque<_>, VecDeque<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|transfer| {
+                endpoints.is_none_or(|endpoints| endpoints.contains(&transfer.endpoint))
+            });
+        self.pending = kept;
+        for transfer in cancelled {
+            self.respond_error(transfer.response, transfer.request_id, error.clone());
+        }
+    }
+}
+
+impl BackendDevice for EmulatedDevice {
+    fn id(&self) -> DeviceId {
+        self.id
+    }
+
+    fn set_identity(&mut self, id: DeviceId, stable_id: String) {
+        self.id = id;
+        self.stable_id = stable_id;
+    }
+
+    fn bus_number(&self) -> u8 {
+        EMULATED_BUS
+    }
+
+    fn device_address(&self) -> u8 {
+        self.slot.address()
+    }
+
+    fn device_info(&self) -> DeviceInfo {
+        DeviceInfo {
+            manufacturer: Some(MANUFACTURER.to_string()),
+            product: Some(self.model().product.to_string()),
+            serial_number: Some(self.slot.serial()),
+            ..self.descriptor_info()
+        }
+    }
+
+    fn descriptor_info(&self) -> DeviceInfo {
+        let model = self.model();
+        DeviceInfo {
+            id: self.id,
+            vendor_id: VENDOR_ID,
+            product_id: model.product_id,
+            bus_number: EMULATED_BUS,
+            device_address: self.slot.address(),
+            manufacturer: None,
+            product: None,
+            serial_number: None,
+            class: model.class,
+            subclass: model.subclass,
+            protocol: model.protocol,
+            speed: model.speed,
+            num_configurations: 1,
+            interfaces: self.interfaces(),
+            stable_id: self.stable_id.clone(),
+            port_numbers: vec![self.slot.port],
+            parent_hub: None,
+        }
+    }
+
+    fn stable_ids(&self) -> Vec<String> {
+        let product_id = self.model().product_id;
+        let serial = self.slot.serial();
+        let ports = [self.slot.port];
+        vec![
+            stable_id(VENDOR_ID, product_id, Some(&serial), EMULATED_BUS, &ports),
+            stable_id(VENDOR_ID, product_id, None, EMULATED_BUS, &ports),
+        ]
+    }
+
+    fn active_configuration(&self) -> Option<u8> {
+        (self.configuration != 0).then_some(self.configuration)
+    }
+
+    fn interfaces(&self) -> Vec<InterfaceInfo> {
+        if self.configuration == 0 {
+            return Vec::new();
+        }
+
+        self.model()
+            .configuration
+            .interfaces
+            .iter()
+            .filter(|alt| alt.alt_setting == 0)
+            .map(|alt| InterfaceInfo {
+                number: alt.number,
+                class: alt.class,
+                subclass: alt.subclass,
+                protocol: alt.protocol,
+                num_endpoints: alt.endpoints.len() as u8,
+            })
+            .collect()
+    }
+
+    fn interface_endpoints(&self, interfaces: &[u8]) -> Vec<u8> {
+        let mut endpoints: Vec<u8> = self
+            .model()
+            .configuration
+            .interfaces
+            .iter()
+            .filter(|alt| interfaces.contains(&alt.number))
+            .flat_map(|alt| alt.endpoints.iter().map(|ep| ep.address))
+            .collect();
+        endpoints.sort_unstable();
+        endpoints.dedup();
+        endpoints
+    }
+
+    fn descriptors(&self) -> Result<DeviceDescriptors, UsbError> {
+        let model = self.model();
+        Ok(DeviceDescriptors {
+            usb_version: usb_version(model.speed),
+            device_version: model.device_version,
+            max_packet_size0: MAX_PACKET_SIZE0,
+            manufacturer_index: 1,
+            product_index: 2,
+            serial_number_index: 3,
+            active_configuration: self.active_configuration(),
+            configurations: vec![model.configuration.clone()],
+            bos: None,
+            strings: self.strings(),
+        })
+    }
+
+    fn open(&mut self) -> Result<(), AttachError> {
+        if self.open {
+            return Ok(());
+        }
+        let interfaces: Vec<u8> = self.interfaces().iter().map(|iface| iface.number).collect();
+        self.open_interfaces(&interfaces)
+    }
+
+    fn open_interfaces(&mut self, interfaces: &[u8]) -> Result<(), AttachError> {
+        if !self.is_connected() {
+            return Err(AttachError::DeviceNotFound);
+        }
+
+        self.open = true;
+        for &iface in interfaces {
+            if !self.claimed_interfaces.contains(&iface) {
+                self.claimed_interfaces.push(iface);
+            }
+        }
+        debug!(
+            "Opened emulated device {:?}, interfaces {:?}",
+            self.id, self.claimed_interfaces
+        );
+        Ok(())
+    }
+
+    fn release_interfaces(&mut self, interfaces: &[u8]) {
+        let endpoints = self.interface_endpoints(interfaces);
+        self.cancel_pending(Some(&endpoints), UsbError::Cancelled);
+        self.claimed_interfaces
+            .retain(|iface| !interfaces.contains(iface));
+    }
+
+    fn close(&mut self) {
+        self.cancel_pending(None, UsbError::Cancelled);
+        self.open = false;
+        self.claimed_interfaces.clear();
+        self.alt_settings.clear();
+        debug!("Closed emulated device {:?}", self.id);
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn submit_transfer(&mut self, request: UsbRequest, response: oneshot::Sender<UsbResponse>) {
+        let id = request.id;
+        if !self.open {
+            return self.respond_error(response, id, UsbError::NotFound);
+        }
+        if !self.is_connected() {
+            return self.respond_error(response, id, UsbError::NoDevice);
+        }
+
+        let (endpoint, data, timeout_ms, bulk) = match request.transfer {
+            TransferType::Control {
+                request_type,
+                request,
+                value,
+                index,
+                data,
+            } => {
+                let is_in = request_type & 0x80 != 0;
+                let length = if is_in && data.is_empty() {
+                    DEFAULT_CONTROL_IN_SIZE
+                } else {
+                    data.len()
+                };
+                let setup = Setup {
+                    request_type,
+                    request,
+                    value,
+                    index,
+                };
+                let out_data = if is_in { &[][..] } else { &data[..] };
+                let result = match self.control(&setup, out_data) {
+                    Ok(mut reply) if is_in => {
+                        reply.truncate(length);
+                        transfer_data(reply, false)
+                    }
+                    Ok(_) => transfer_data(Vec::new(), false),
+                    Err(error) => TransferResult::Error { error },
+                };
+                return self.respond(response, id, result);
+            }
+            TransferType::Bulk {
+                endpoint,
+                data,
+                timeout_ms,
+                checksum,
+            } => {
+                if endpoint & 0x80 == 0
+                    && let Some(expected) = checksum
+                    && !verify_checksum(&data, expected)
+                {
+                    return self.respond_error(
+                        response,
+                        id,
+                        UsbError::Other {
+                            message: "Checksum mismatch".to_string(),
+                        },
+                    );
+                }
+                (endpoint, data, timeout_ms, true)
+            }
+            TransferType::Interrupt {
+                endpoint,
+                data,
+                timeout_ms,
+            } => (endpoint, data, timeout_ms, false),
+            TransferType::Isochronous { .. } => {
+                return self.respond_error(
+                    response,
+                    id,
+                    UsbError::Other {
+                        message: "Isochronous transfers are not emulated".to_string(),
+                    },
+                );
+            }
+        };
+
+        if !self.claimed_endpoints().contains(&endpoint) {
+            return self.respond_error(response, id, UsbError::NotFound);
+        }
+
+        let is_in = endpoint & 0x80 != 0;
+        self.pending.push_back(PendingTransfer {
+            request_id: id,
+            handle: request.handle,
+            endpoint,
+            length: if is_in { data.len() } else { 0 },
+            data: if is_in { Vec::new() } else { data },
+            bulk,
+            deadline: (timeout_ms > 0)
+                .then(|| Instant::now() + Duration::from_millis(timeout_ms as u64)),
+            response,
+        });
+        self.run_pending();
+    }
+
+    fn cancel_transfer(&mut self, handle: DeviceHandle, request_id: RequestId) -> bool {
+        let Some(position) = self
+            .pending
+            .iter()
+            .position(|transfer| transfer.handle == handle && transfer.request_id == request_id)
+        else {
+            return false;
+        };
+
+        if let Some(transfer) = self.pending.remove(position) {
+            debug!("Request {:?} cancelled", request_id);
+            self.respond_error(transfer.response, request_id, UsbError::Cancelled);
+        }
+        true
+    }
+
+    fn process_transfer_completions(&mut self) -> usize {
+        if self.is_connected() {
+            self.run_pending();
+        } else {
+            self.cancel_pending(None, UsbError::NoDevice);
+        }
+
+        let completed = std::mem::take(&mut self.completed);
+        let count = completed.len();
+        for (response, result) in completed {
+            let _ = response.send(result);
+        }
+        count
+    }
+
+    fn has_pending_transfers(&self) -> bool {
+        !self.pending.is_empty() || !self.completed.is_empty()
+    }
+
+    fn reset(&mut self) -> Result<(), rusb::Error> {
+        if !self.open {
+            return Err(rusb::Error::InvalidParam);
+        }
+        self.cancel_pending(None, UsbError::Cancelled);
+        self.alt_settings.clear();
+        self.slot.function.lock().unwrap().reset();
+        debug!("Reset emulated device {:?}", self.id);
+        Ok(())
+    }
+
+    fn clear_halt(&mut self, _endpoint: u8) -> Result<(), rusb::Error> {
+        if !self.open {
+            return Err(rusb::Error::InvalidParam);
+        }
+        Ok(())
+    }
+
+    fn set_configuration(&mut self, configuration: u8) -> Result<(), rusb::Error> {
+        if !self.open {
+            return Err(rusb::Error::InvalidParam);
+        }
+        if configuration == self.configuration {
+            return Ok(());
+        }
+        if configuration != 0 && configuration != self.model().configuration.value {
+            return Err(rusb::Error::NotFound);
+        }
+
+        self.cancel_pending(None, UsbError::Cancelled);
+        self.alt_settings.clear();
+        self.configuration = configuration;
+        debug!(
+            "Set configuration {} on emulated device {:?}",
+            configuration, self.id
+        );
+        Ok(())
+    }
+
+    fn set_interface(&mut self, interface: u8, alt_setting: u8) -> Result<(), rusb::Error> {
+        if !self.open {
+            return Err(rusb::Error::InvalidParam);
+        }
+        let exists = self.configuration != 0
+            && self
+                .model()
+                .configuration
+                .interfaces
+                .iter()
+                .any(|alt| alt.number == interface && alt.alt_setting == alt_setting);
+        if !exists {
+            return Err(rusb::Error::NotFound);
+        }
+
+        self.alt_settings.insert(interface, alt_setting);
+        Ok(())
+    }
+
+    fn endpoint_info(&self, endpoint: u8) -> Result<EndpointInfo, UsbError> {
+        let descriptor = self
+            .model()
+            .configuration
+            .interfaces
+            .iter()
+            .flat_map(|alt| &alt.endpoints)
+            .find(|ep| ep.address == endpoint)
+            .ok_or(UsbError::NotFound)?;
+        if descriptor.attributes & 0x03 != 0x03 {
+            return Err(UsbError::InvalidParam);
+        }
+
+        Ok(interrupt_endpoint_info(
+            self.model().speed,
+            descriptor.max_packet_size,
+            descriptor.interval,
+        ))
+    }
+}
+
+/// Successful transfer result; bulk IN data carries a checksum
+fn transfer_data(data: Vec<u8>, checksum: bool) -> TransferResult {
+    let checksum = (checksum && !data.is_empty()).then(|| compute_checksum(&data));
+    TransferResult::Success { data, checksum }
+}
+
+/// bcdUSB for a bus speed
+fn usb_version(speed: DeviceSpeed) -> u16 {
+    match speed {
+        DeviceSpeed::Low | DeviceSpeed::Full => 0x0110,
+        DeviceSpeed::High => 0x0200,
+        DeviceSpeed::Super | DeviceSpeed::SuperPlus => 0x0300,
+    }
+}
+
+/// Encode a configuration descriptor with its interfaces and endpoints
+fn encode_configuration(config: &ConfigurationDescriptor) -> Vec<u8> {
+    let mut numbers: Vec<u8> = config.interfaces.iter().map(|alt| alt.number).collect();
+    numbers.dedup();
+
+    let mut bytes = vec![
+        9,
+        0x02,
+        0,
+        0,
+        numbers.len() as u8,
+        config.value,
+        config.string_index,
+        config.attributes,
+        (config.max_power_ma / 2).min(u8::MAX as u16) as u8,
+    ];
+    bytes.extend_from_slice(&config.extra);
+
+    for alt in &config.interfaces {
+        bytes.extend_from_slice(&[
+            9,
+            0x04,
+            alt.number,
+            alt.alt_setting,
+            alt.endpoints.len() as u8,
+            alt.class,
+            alt.subclass,
+            alt.protocol,
+            alt.string_index,
+        ]);
+        bytes.extend_from_slice(&alt.extra);
+
+        for ep in &alt.endpoints {
+            bytes.extend_from_slice(&[7, 0x05, ep.address, ep.attributes]);
+            bytes.extend_from_slice(&ep.max_packet_size.to_le_bytes());
+            bytes.push(ep.interval);
+            bytes.extend_from_slice(&ep.extra);
+        }
+    }
+
+    let total = (bytes.len() as u16).to_le_bytes();
+    bytes[2..4].copy_from_slice(&total);
+    bytes
+}
+
+/// Encode a string descriptor (UTF-16LE)
+fn encode_string(value: &str) -> Vec<u8> {
+    let units: Vec<u16> = value.encode_utf16().take(126).collect();
+    let mut bytes = vec![(2 + units.len() * 2) as u8, 0x03];
+    bytes.extend(units.iter().flat_map(|unit| unit.to_le_bytes()));
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{EndpointDescriptor, InterfaceDescriptor};
+
+    /// Open the first device of a bus with `function` plugged in
+    fn open_device(function: impl VirtualFunction + 'static) -> EmulatedDevice {
+        let bus = EmulatedBus::new();
+        let address = bus.plug(function).unwrap();
+        let mut device = EmulatedDevice::new(bus.slot(address).unwrap());
+        device.open().unwrap();
+        device
+    }
+
+    /// Submit a transfer and collect its response after one completion pass
+    fn transfer(device: &mut EmulatedDevice, transfer: TransferType) -> Option<TransferResult> {
+        let (tx, mut rx) = oneshot::channel();
+        device.submit_transfer(
+            UsbRequest {
+                id: RequestId(1),
+                handle: DeviceHandle(1),
+                transfer,
+            },
+            tx,
+        );
+        device.process_transfer_completions();
+        rx.try_recv().ok().map(|response| response.result)
+    }
+
+    fn control_in(request_type: u8, request: u8, value: u16, length: usize) -> TransferType {
+        TransferType::Control {
+            request_type,
+            request,
+            value,
+            index: 0,
+            data: vec![0; length],
+        }
+    }
+
+    fn success_data(result: Option<TransferResult>) -> Vec<u8> {
+        match result {
+            Some(TransferResult::Success { data, .. }) => data,
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_plug_and_unplug() {
+        let bus = EmulatedBus::new();
+        let backend = EmulatedBackend::new(bus.clone());
+
+        assert_eq!(bus.plug(HidKeyboard::new()), Some(2));
+        assert_eq!(bus.plug(CdcAcm::new()), Some(3));
+        assert_eq!(backend.devices().unwrap().len(), 2);
+
+        assert!(bus.unplug(2));
+        assert!(!bus.unplug(2));
+        assert!(backend.device(EMULATED_BUS, 2).is_none());
+
+        // The free port is reused, so the device gets the same identity
+        assert_eq!(bus.plug(HidKeyboard::new()), Some(2));
+        let device = backend.device(EMULATED_BUS, 2).unwrap();
+        assert_eq!(device.stable_ids()[0], "1209:0001:EMU0001");
+        assert_eq!(device.stable_ids()[1], "1209:0001@1-1");
+    }
+
+    #[test]
+    fn test_standard_descriptors() {
+        let mut device = open_device(CdcAcm::new());
+
+        let descriptor = success_data(transfer(&mut device, control_in(0x80, 0x06, 0x0100, 18)));
+        assert_eq!(descriptor, device.device_descriptor());
+        assert_eq!(&descriptor[8..12], &[0x09, 0x12, 0x02, 0x00]);
+
+        // A short read of the configuration returns its header with wTotalLength
+        let header = success_data(transfer(&mut device, control_in(0x80, 0x06, 0x0200, 9)));
+        let full = encode_configuration(&device.model().configuration);
+        assert_eq!(header, full[..9]);
+        assert_eq!(
+            u16::from_le_bytes([header[2], header[3]]) as usize,
+            full.len()
+        );
+
+        let product = success_data(transfer(&mut device, control_in(0x80, 0x06, 0x0302, 255)));
+        assert_eq!(product, encode_string("Emulated Serial Port"));
+
+        // No device qualifier on a full-speed device
+        assert!(matches!(
+            transfer(&mut device, control_in(0x80, 0x06, 0x0600, 10)),
+            Some(TransferResult::Error {
+                error: UsbError::Pipe
+            })
+        ));
+    }
+
+    #[test]
+    fn test_encode_configuration() {
+        let config = ConfigurationDescriptor {
+            value: 1,
+            string_index: 0,
+            attributes: 0x80,
+            max_power_ma: 100,
+            interfaces: vec![InterfaceDescriptor {
+                number: 0,
+                alt_setting: 0,
+                class: 3,
+                subclass: 1,
+                protocol: 1,
+                string_index: 0,
+                endpoints: vec![EndpointDescriptor {
+                    address: 0x81,
+                    attributes: 0x03,
+                    max_packet_size: 8,
+                    interval: 10,
+                    extra: Vec::new(),
+                }],
+                extra: vec![0xaa],
+            }],
+            extra: Vec::new(),
+        };
+
+        assert_eq!(
+            encode_configuration(&config),
+            vec![
+                9, 0x02, 26, 0, 1, 1, 0, 0x80, 50, // configuration
+                9, 0x04, 0, 0, 1, 3, 1, 1, 0, 0xaa, // interface + extra
+                7, 0x05, 0x81, 0x03, 8, 0, 10, // endpoint
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfers_need_open_claimed_endpoint() {
+        let bus = EmulatedBus::new();
+        let address = bus.plug(CdcAcm::new()).unwrap();
+        let mut device = EmulatedDevice::new(bus.slot(address).unwrap());
+        let bulk_out = || TransferType::Bulk {
+            endpoint: 0x02,
+            data: b"x".to_vec(),
+            timeout_ms: 0,
+            checksum: None,
+        };
+
+        assert!(matches!(
+            transfer(&mut device, bulk_out()),
+            Some(TransferResult::Error {
+                error: UsbError::NotFound
+            })
+        ));
+
+        // Interface 1 holds the bulk endpoints
+        device.open_interfaces(&[0]).unwrap();
+        assert!(matches!(
+            transfer(&mut device, bulk_out()),
+            Some(TransferResult::Error {
+                error: UsbError::NotFound
+            })
+        ));
+        device.open_interfaces(&[1]).unwrap();
+        assert!(matches!(
+            transfer(&mut device, bulk_out()),
+            Some(TransferResult::Success { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pending_transfer_cancel_and_unplug() {
+        let bus = EmulatedBus::new();
+        let address = bus.plug(HidKeyboard::new()).unwrap();
+        let mut device = EmulatedDevice::new(bus.slot(address).unwrap());
+        device.open().unwrap();
+        let interrupt_in = || TransferType::Interrupt {
+            endpoint: 0x81,
+            data: vec![0; 8],
+            timeout_ms: 0,
+        };
+
+        // Nothing typed: the transfer waits
+        assert!(transfer(&mut device, interrupt_in()).is_none());
+        assert!(device.has_pending_transfers());
+        assert!(!device.cancel_transfer(DeviceHandle(2), RequestId(1)));
+        assert!(device.cancel_transfer(DeviceHandle(1), RequestId(1)));
+        device.process_transfer_completions();
+        assert!(!device.has_pending_transfers());
+
+        let (tx, mut rx) = oneshot::channel();
+        device.submit_transfer(
+            UsbRequest {
+                id: RequestId(2),
+                handle: DeviceHandle(1),
+                transfer: interrupt_in(),
+            },
+            tx,
+        );
+        bus.unplug(address);
+        device.process_transfer_completions();
+        assert!(matches!(
+            rx.try_recv().unwrap().result,
+            TransferResult::Error {
+                error: UsbError::NoDevice
+            }
+        ));
+    }
+
+    #[test]
+    fn test_in_timeout_reports_no_data() {
+        let mut device = open_device(HidKeyboard::new());
+        let (tx, mut rx) = oneshot::channel();
+        device.submit_transfer(
+            UsbRequest {
+                id: RequestId(1),
+                handle: DeviceHandle(1),
+                transfer: TransferType::Interrupt {
+                    endpoint: 0x81,
+                    data: vec![0; 8],
+                    timeout_ms: 1,
+                },
+            },
+            tx,
+        );
+
+        std::thread::sleep(Duration::from_millis(5));
+        device.process_transfer_completions();
+        assert!(matches!(
+            rx.try_recv().unwrap().result,
+            TransferResult::Success { data, .. } if data.is_empty()
+        ));
+    }
+}
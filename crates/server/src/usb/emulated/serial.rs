@@ -0,0 +1,242 @@
+//! Emulated CDC-ACM serial port
+//!
+//! Loops everything written to its bulk OUT endpoint back to its bulk IN
+//! endpoint.
+
+use super::{DeviceModel, Setup, VirtualFunction};
+use protocol::{
+    ConfigurationDescriptor, DeviceSpeed, EndpointDescriptor, InterfaceDescriptor, UsbError,
+};
+use std::collections::VecDeque;
+
+/// Interrupt IN endpoint for serial state notifications
+const NOTIFY_ENDPOINT: u8 = 0x83;
+
+/// Bulk OUT endpoint
+const DATA_OUT_ENDPOINT: u8 = 0x02;
+
+/// Bulk IN endpoint
+const DATA_IN_ENDPOINT: u8 = 0x82;
+
+/// Bytes buffered before writes are NAKed until the host reads
+const LOOPBACK_CAPACITY: usize = 64 * 1024;
+
+/// Line coding set by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineCoding {
+    baud_rate: u32,
+    /// 0 = 1, 1 = 1.5, 2 = 2 stop bits
+    stop_bits: u8,
+    /// 0 none, 1 odd, 2 even, 3 mark, 4 space
+    parity: u8,
+    data_bits: u8,
+}
+
+impl LineCoding {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = self.baud_rate.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[self.stop_bits, self.parity, self.data_bits]);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; 7] = bytes.get(..7)?.try_into().ok()?;
+        Some(Self {
+            baud_rate: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            stop_bits: bytes[4],
+            parity: bytes[5],
+            data_bits: bytes[6],
+        })
+    }
+}
+
+impl Default for LineCoding {
+    /// 115200 8N1
+    fn default() -> Self {
+        Self {
+            baud_rate: 115_200,
+            stop_bits: 0,
+            parity: 0,
+            data_bits: 8,
+        }
+    }
+}
+
+/// USB CDC-ACM serial port in loopback
+pub struct CdcAcm {
+    buffer: VecDeque<u8>,
+    line_coding: LineCoding,
+}
+
+impl CdcAcm {
+    /// Create a loopback with an empty buffer
+    pub fn new() -> Self {
+        Self {
+            buffer: VecDeque::new(),
+            line_coding: LineCoding::default(),
+        }
+    }
+}
+
+impl Default for CdcAcm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualFunction for CdcAcm {
+    fn model(&self) -> DeviceModel {
+        let bulk = |address| EndpointDescriptor {
+            address,
+            attributes: 0x02,
+            max_packet_size: 64,
+            interval: 0,
+            extra: Vec::new(),
+        };
+
+        DeviceModel {
+            product_id: 0x0002,
+            class: 0x02,
+            subclass: 0,
+            protocol: 0,
+            device_version: 0x0100,
+            speed: DeviceSpeed::Full,
+            product: "Emulated Serial Port",
+            configuration: ConfigurationDescriptor {
+                value: 1,
+                string_index: 0,
+                attributes: 0x80,
+                max_power_ma: 100,
+                interfaces: vec![
+                    InterfaceDescriptor {
+                        number: 0,
+                        alt_setting: 0,
+                        class: 0x02,
+                        subclass: 0x02, // Abstract control model
+                        protocol: 0x01, // AT commands
+                        string_index: 0,
+                        endpoints: vec![EndpointDescriptor {
+                            address: NOTIFY_ENDPOINT,
+                            attributes: 0x03,
+                            max_packet_size: 16,
+                            interval: 16,
+                            extra: Vec::new(),
+                        }],
+                        extra: vec![
+                            5, 0x24, 0x00, 0x10, 0x01, // Header, CDC 1.10
+                            5, 0x24, 0x01, 0x00, 0x01, // Call management
+                            4, 0x24, 0x02, 0x02, // ACM: line coding and serial state
+                            5, 0x24, 0x06, 0x00, 0x01, // Union: control 0, data 1
+                        ],
+                    },
+                    InterfaceDescriptor {
+                        number: 1,
+                        alt_setting: 0,
+                        class: 0x0a,
+                        subclass: 0,
+                        protocol: 0,
+                        string_index: 0,
+                        endpoints: vec![bulk(DATA_OUT_ENDPOINT), bulk(DATA_IN_ENDPOINT)],
+                        extra: Vec::new(),
+                    },
+                ],
+                extra: Vec::new(),
+            },
+        }
+    }
+
+    fn control(&mut self, setup: &Setup, data: &[u8]) -> Result<Vec<u8>, UsbError> {
+        match (setup.request_type, setup.request) {
+            // SET_LINE_CODING
+            (0x21, 0x20) => {
+                self.line_coding = LineCoding::from_bytes(data).ok_or(UsbError::Pipe)?;
+                Ok(Vec::new())
+            }
+            // GET_LINE_CODING
+            (0xa1, 0x21) => Ok(self.line_coding.to_bytes()),
+            // SET_CONTROL_LINE_STATE: DTR and RTS do not affect the loopback
+            (0x21, 0x22) => Ok(Vec::new()),
+            _ => Err(UsbError::Pipe),
+        }
+    }
+
+    fn write(&mut self, endpoint: u8, data: &[u8]) -> Option<Result<(), UsbError>> {
+        if endpoint != DATA_OUT_ENDPOINT {
+            return Some(Err(UsbError::NotFound));
+        }
+        if self.buffer.len() + data.len() > LOOPBACK_CAPACITY {
+            return None;
+        }
+
+        self.buffer.extend(data);
+        Some(Ok(()))
+    }
+
+    fn read(&mut self, endpoint: u8, length: usize) -> Option<Result<Vec<u8>, UsbError>> {
+        match endpoint {
+            DATA_IN_ENDPOINT if !self.buffer.is_empty() => {
+                let length = length.min(self.buffer.len());
+                Some(Ok(self.buffer.drain(..length).collect()))
+            }
+            // Nothing to read yet, and no serial state changes to report
+            DATA_IN_ENDPOINT | NOTIFY_ENDPOINT => None,
+            _ => Some(Err(UsbError::NotFound)),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.line_coding = LineCoding::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loopback_echoes_in_order() {
+        let mut serial = CdcAcm::new();
+        assert!(serial.read(DATA_IN_ENDPOINT, 64).is_none());
+
+        serial.write(DATA_OUT_ENDPOINT, b"hello ").unwrap().unwrap();
+        serial.write(DATA_OUT_ENDPOINT, b"world").unwrap().unwrap();
+        assert_eq!(serial.read(DATA_IN_ENDPOINT, 4).unwrap().unwrap(), b"hell");
+        assert_eq!(
+            serial.read(DATA_IN_ENDPOINT, 64).unwrap().unwrap(),
+            b"o world"
+        );
+        assert!(serial.read(DATA_IN_ENDPOINT, 64).is_none());
+    }
+
+    #[test]
+    fn test_full_buffer_naks_writes() {
+        let mut serial = CdcAcm::new();
+        let chunk = vec![0x55; LOOPBACK_CAPACITY];
+        serial.write(DATA_OUT_ENDPOINT, &chunk).unwrap().unwrap();
+        assert!(serial.write(DATA_OUT_ENDPOINT, b"x").is_none());
+
+        serial.read(DATA_IN_ENDPOINT, 1).unwrap().unwrap();
+        assert!(serial.write(DATA_OUT_ENDPOINT, b"x").is_some());
+    }
+
+    #[test]
+    fn test_line_coding() {
+        let mut serial = CdcAcm::new();
+        let setup = |request_type, request| Setup {
+            request_type,
+            request,
+            value: 0,
+            index: 0,
+        };
+
+        let default = serial.control(&setup(0xa1, 0x21), &[]).unwrap();
+        assert_eq!(default, [0x00, 0xc2, 0x01, 0x00, 0, 0, 8]);
+
+        let coding = [0x80, 0x25, 0x00, 0x00, 2, 2, 7]; // 9600 7E2
+        serial.control(&setup(0x21, 0x20), &coding).unwrap();
+        assert_eq!(serial.control(&setup(0xa1, 0x21), &[]).unwrap(), coding);
+
+        assert!(serial.control(&setup(0x21, 0x20), &coding[..3]).is_err());
+    }
+}
@@ -0,0 +1,636 @@
+//! Emulated USB mass storage device
+//!
+//! Bulk-only transport (BOT) disk backed by an image file. Each command is a
+//! command block wrapper (CBW) on the bulk OUT endpoint, followed by an
+//! optional data stage and a command status wrapper (CSW) on the bulk IN
+//! endpoint. Commands are the SCSI subset hosts use for a removable disk.
+
+use super::{DeviceModel, Setup, VirtualFunction};
+use protocol::{
+    ConfigurationDescriptor, DeviceSpeed, EndpointDescriptor, InterfaceDescriptor, UsbError,
+};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use tracing::warn;
+
+/// Bulk IN endpoint (data and CSW)
+const BULK_IN_ENDPOINT: u8 = 0x81;
+
+/// Bulk OUT endpoint (CBW and data)
+const BULK_OUT_ENDPOINT: u8 = 0x02;
+
+/// Logical block size of the disk
+const BLOCK_SIZE: u64 = 512;
+
+/// "USBC"
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+
+/// "USBS"
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+
+const CBW_LENGTH: usize = 31;
+
+/// CSW status: command passed
+const STATUS_PASSED: u8 = 0;
+
+/// CSW status: command failed, details through REQUEST SENSE
+const STATUS_FAILED: u8 = 1;
+
+/// Sense key, additional sense code and qualifier of the last failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sense {
+    key: u8,
+    asc: u8,
+    ascq: u8,
+}
+
+impl Sense {
+    const NONE: Self = Self::new(0x00, 0x00, 0x00);
+    const UNRECOVERED_READ_ERROR: Self = Self::new(0x03, 0x11, 0x00);
+    const WRITE_ERROR: Self = Self::new(0x03, 0x0c, 0x00);
+    const INVALID_COMMAND: Self = Self::new(0x05, 0x20, 0x00);
+    const LBA_OUT_OF_RANGE: Self = Self::new(0x05, 0x21, 0x00);
+    const WRITE_PROTECTED: Self = Self::new(0x07, 0x27, 0x00);
+
+    const fn new(key: u8, asc: u8, ascq: u8) -> Self {
+        Self { key, asc, ascq }
+    }
+}
+
+/// Command block wrapper
+#[derive(Debug)]
+struct Cbw {
+    tag: u32,
+    data_length: u32,
+    data_in: bool,
+    command: [u8; 16],
+}
+
+impl Cbw {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != CBW_LENGTH
+            || u32::from_le_bytes(bytes[0..4].try_into().ok()?) != CBW_SIGNATURE
+        {
+            return None;
+        }
+
+        Some(Self {
+            tag: u32::from_le_bytes(bytes[4..8].try_into().ok()?),
+            data_length: u32::from_le_bytes(bytes[8..12].try_into().ok()?),
+            data_in: bytes[12] & 0x80 != 0,
+            command: bytes[15..31].try_into().ok()?,
+        })
+    }
+}
+
+/// Command status wrapper
+#[derive(Debug, Clone, Copy)]
+struct Csw {
+    tag: u32,
+    residue: u32,
+    status: u8,
+}
+
+impl Csw {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(13);
+        bytes.extend_from_slice(&CSW_SIGNATURE.to_le_bytes());
+        bytes.extend_from_slice(&self.tag.to_le_bytes());
+        bytes.extend_from_slice(&self.residue.to_le_bytes());
+        bytes.push(self.status);
+        bytes
+    }
+}
+
+/// Where the device is in the BOT protocol
+enum Stage {
+    /// Waiting for a CBW
+    Command,
+    /// Sending data to the host
+    DataIn {
+        data: Vec<u8>,
+        offset: usize,
+        csw: Csw,
+    },
+    /// Receiving WRITE(10) data from the host
+    DataOut {
+        lba: u64,
+        data: Vec<u8>,
+        expected: usize,
+        csw: Csw,
+    },
+    /// Failed data stage: the next transfer in the host's data direction stalls
+    Stall { csw: Csw, data_in: bool },
+    /// Sending the CSW
+    Status(Csw),
+}
+
+/// Outcome of a SCSI command before the data stage
+enum Reply {
+    /// Data for the host (possibly empty)
+    Data(Vec<u8>),
+    /// WRITE(10): expect this many bytes at `lba`
+    Write { lba: u64, length: usize },
+    /// Command failed
+    Failed(Sense),
+}
+
+/// USB mass storage disk
+pub struct MassStorage {
+    image: File,
+    blocks: u64,
+    read_only: bool,
+    stage: Stage,
+    sense: Sense,
+}
+
+impl MassStorage {
+    /// Serve `path` as a disk
+    ///
+    /// The image must hold at least one 512-byte block; a partial last block
+    /// is ignored.
+    pub fn open(path: &Path, read_only: bool) -> io::Result<Self> {
+        let image = OpenOptions::new().read(true).write(!read_only).open(path)?;
+        let blocks = image.metadata()?.len() / BLOCK_SIZE;
+        if blocks == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "disk image is smaller than one block",
+            ));
+        }
+
+        Ok(Self {
+            image,
+            blocks,
+            read_only,
+            stage: Stage::Command,
+            sense: Sense::NONE,
+        })
+    }
+
+    /// Start executing a CBW
+    fn command(&mut self, cbw: Cbw) {
+        let reply = self.execute(&cbw.command);
+        let data_length = cbw.data_length as usize;
+        let mut csw = Csw {
+            tag: cbw.tag,
+            residue: cbw.data_length,
+            status: STATUS_PASSED,
+        };
+
+        self.stage = match reply {
+            Reply::Data(mut data) if cbw.data_in || data.is_empty() => {
+                self.sense = Sense::NONE;
+                data.truncate(if cbw.data_in { data_length } else { 0 });
+                csw.residue = (data_length - data.len()) as u32;
+                if data.is_empty() {
+                    Stage::Status(csw)
+                } else {
+                    Stage::DataIn {
+                        data,
+                        offset: 0,
+                        csw,
+                    }
+                }
+            }
+            Reply::Write { lba, length } if !cbw.data_in && length <= data_length => {
+                self.sense = Sense::NONE;
+                csw.residue = (data_length - length) as u32;
+                if length == 0 {
+                    Stage::Status(csw)
+                } else {
+                    Stage::DataOut {
+                        lba,
+                        data: Vec::with_capacity(length),
+                        expected: length,
+                        csw,
+                    }
+                }
+            }
+            Reply::Failed(sense) => {
+                self.sense = sense;
+                Self::failed(csw, cbw.data_in)
+            }
+            // Data direction or length disagrees with the command
+            _ => {
+                self.sense = Sense::INVALID_COMMAND;
+                Self::failed(csw, cbw.data_in)
+            }
+        };
+    }
+
+    /// Stage after a failed command: stall the data stage, if any, then
+    /// report the failure
+    fn failed(mut csw: Csw, data_in: bool) -> Stage {
+        csw.status = STATUS_FAILED;
+        if csw.residue == 0 {
+            Stage::Status(csw)
+        } else {
+            Stage::Stall { csw, data_in }
+        }
+    }
+
+    /// Run a SCSI command
+    fn execute(&mut self, command: &[u8; 16]) -> Reply {
+        match command[0] {
+            // TEST UNIT READY, PREVENT ALLOW MEDIUM REMOVAL, START STOP UNIT, VERIFY(10)
+            0x00 | 0x1e | 0x1b | 0x2f => Reply::Data(Vec::new()),
+            // REQUEST SENSE
+            0x03 => {
+                let mut sense = vec![0; 18];
+                sense[0] = 0x70; // Current error, fixed format
+                sense[2] = self.sense.key;
+                sense[7] = 10; // Additional sense length
+                sense[12] = self.sense.asc;
+                sense[13] = self.sense.ascq;
+                sense.truncate(command[4] as usize);
+                Reply::Data(sense)
+            }
+            // INQUIRY
+            0x12 => {
+                let mut inquiry = vec![
+                    0x00, // Direct access block device
+                    0x80, // Removable
+                    0x04, // SPC-2
+                    0x02, // Response data format
+                    31,   // Additional length
+                    0, 0, 0,
+                ];
+                inquiry.extend_from_slice(b"p2p-usb ");
+                inquiry.extend_from_slice(b"Emulated Disk   ");
+                inquiry.extend_from_slice(b"1.0 ");
+                inquiry.truncate(u16::from_be_bytes([command[3], command[4]]) as usize);
+                Reply::Data(inquiry)
+            }
+            // MODE SENSE(6)
+            0x1a => {
+                let mut mode = vec![3, 0, self.device_specific(), 0];
+                mode.truncate(command[4] as usize);
+                Reply::Data(mode)
+            }
+            // MODE SENSE(10)
+            0x5a => {
+                let mut mode = vec![0, 6, 0, self.device_specific(), 0, 0, 0, 0];
+                mode.truncate(u16::from_be_bytes([command[7], command[8]]) as usize);
+                Reply::Data(mode)
+            }
+            // READ FORMAT CAPACITIES
+            0x23 => {
+                let mut capacities = vec![0, 0, 0, 8];
+                capacities.extend_from_slice(&(self.blocks as u32).to_be_bytes());
+                capacities.push(0x02); // Formatted media
+                capacities.extend_from_slice(&(BLOCK_SIZE as u32).to_be_bytes()[1..]);
+                capacities.truncate(u16::from_be_bytes([command[7], command[8]]) as usize);
+                Reply::Data(capacities)
+            }
+            // READ CAPACITY(10)
+            0x25 => {
+                let last_lba = (self.blocks - 1).min(u32::MAX as u64) as u32;
+                let mut capacity = last_lba.to_be_bytes().to_vec();
+                capacity.extend_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+                Reply::Data(capacity)
+            }
+            // READ(10)
+            0x28 => {
+                let Some((lba, blocks)) = self.block_range(command) else {
+                    return Reply::Failed(Sense::LBA_OUT_OF_RANGE);
+                };
+                match self.read_blocks(lba, blocks) {
+                    Ok(data) => Reply::Data(data),
+                    Err(e) => {
+                        warn!("Emulated disk read failed: {}", e);
+                        Reply::Failed(Sense::UNRECOVERED_READ_ERROR)
+                    }
+                }
+            }
+            // WRITE(10)
+            0x2a => {
+                if self.read_only {
+                    return Reply::Failed(Sense::WRITE_PROTECTED);
+                }
+                match self.block_range(command) {
+                    Some((lba, blocks)) => Reply::Write {
+                        lba,
+                        length: (blocks * BLOCK_SIZE) as usize,
+                    },
+                    None => Reply::Failed(Sense::LBA_OUT_OF_RANGE),
+                }
+            }
+            // SYNCHRONIZE CACHE(10)
+            0x35 => match self.image.sync_data() {
+                Ok(()) => Reply::Data(Vec::new()),
+                Err(e) => {
+                    warn!("Emulated disk sync failed: {}", e);
+                    Reply::Failed(Sense::WRITE_ERROR)
+                }
+            },
+            _ => Reply::Failed(Sense::INVALID_COMMAND),
+        }
+    }
+
+    /// Mode parameter header byte with the write protect bit
+    fn device_specific(&self) -> u8 {
+        if self.read_only { 0x80 } else { 0x00 }
+    }
+
+    /// LBA and block count of a READ(10) or WRITE(10), if on the disk
+    fn block_range(&self, command: &[u8; 16]) -> Option<(u64, u64)> {
+        let lba = u32::from_be_bytes([command[2], command[3], command[4], command[5]]) as u64;
+        let blocks = u16::from_be_bytes([command[7], command[8]]) as u64;
+        (lba + blocks <= self.blocks).then_some((lba, blocks))
+    }
+
+    fn read_blocks(&mut self, lba: u64, blocks: u64) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; (blocks * BLOCK_SIZE) as usize];
+        self.image.seek(SeekFrom::Start(lba * BLOCK_SIZE))?;
+        self.image.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn write_blocks(&mut self, lba: u64, data: &[u8]) -> io::Result<()> {
+        self.image.seek(SeekFrom::Start(lba * BLOCK_SIZE))?;
+        self.image.write_all(data)
+    }
+}
+
+impl VirtualFunction for MassStorage {
+    fn model(&self) -> DeviceModel {
+        let bulk = |address| EndpointDescriptor {
+            address,
+            attributes: 0x02,
+            max_packet_size: 512,
+            interval: 0,
+            extra: Vec::new(),
+        };
+
+        DeviceModel {
+            product_id: 0x0003,
+            class: 0,
+            subclass: 0,
+            protocol: 0,
+            device_version: 0x0100,
+            speed: DeviceSpeed::High,
+            product: "Emulated Disk",
+            configuration: ConfigurationDescriptor {
+                value: 1,
+                string_index: 0,
+                attributes: 0x80,
+                max_power_ma: 200,
+                interfaces: vec![InterfaceDescriptor {
+                    number: 0,
+                    alt_setting: 0,
+                    class: 0x08,
+                    subclass: 0x06, // SCSI transparent command set
+                    protocol: 0x50, // Bulk-only transport
+                    string_index: 0,
+                    endpoints: vec![bulk(BULK_IN_ENDPOINT), bulk(BULK_OUT_ENDPOINT)],
+                    extra: Vec::new(),
+                }],
+                extra: Vec::new(),
+            },
+        }
+    }
+
+    fn control(&mut self, setup: &Setup, _data: &[u8]) -> Result<Vec<u8>, UsbError> {
+        match (setup.request_type, setup.request) {
+            // GET MAX LUN
+            (0xa1, 0xfe) => Ok(vec![0]),
+            // BULK-ONLY MASS STORAGE RESET
+            (0x21, 0xff) => {
+                self.stage = Stage::Command;
+                Ok(Vec::new())
+            }
+            _ => Err(UsbError::Pipe),
+        }
+    }
+
+    fn write(&mut self, endpoint: u8, data: &[u8]) -> Option<Result<(), UsbError>> {
+        if endpoint != BULK_OUT_ENDPOINT {
+            return Some(Err(UsbError::NotFound));
+        }
+
+        match &mut self.stage {
+            Stage::Command => match Cbw::parse(data) {
+                Some(cbw) => {
+                    self.command(cbw);
+                    Some(Ok(()))
+                }
+                // Invalid CBW: stall until the host resets the device
+                None => Some(Err(UsbError::Pipe)),
+            },
+            Stage::DataOut {
+                lba,
+                data: buffer,
+                expected,
+                csw,
+            } => {
+                let take = data.len().min(*expected - buffer.len());
+                buffer.extend_from_slice(&data[..take]);
+                if buffer.len() < *expected {
+                    return Some(Ok(()));
+                }
+
+                let (lba, buffer, mut csw) = (*lba, std::mem::take(buffer), *csw);
+                if let Err(e) = self.write_blocks(lba, &buffer) {
+                    warn!("Emulated disk write failed: {}", e);
+                    self.sense = Sense::WRITE_ERROR;
+                    csw.status = STATUS_FAILED;
+                }
+                self.stage = Stage::Status(csw);
+                Some(Ok(()))
+            }
+            Stage::Stall {
+                csw,
+                data_in: false,
+            } => {
+                self.stage = Stage::Status(*csw);
+                Some(Err(UsbError::Pipe))
+            }
+            // Waiting for the host to read data or status
+            _ => None,
+        }
+    }
+
+    fn read(&mut self, endpoint: u8, length: usize) -> Option<Result<Vec<u8>, UsbError>> {
+        if endpoint != BULK_IN_ENDPOINT {
+            return Some(Err(UsbError::NotFound));
+        }
+
+        match &mut self.stage {
+            Stage::DataIn { data, offset, csw } => {
+                let end = data.len().min(*offset + length);
+                let chunk = data[*offset..end].to_vec();
+                *offset = end;
+                if end == data.len() {
+                    self.stage = Stage::Status(*csw);
+                }
+                Some(Ok(chunk))
+            }
+            Stage::Stall { csw, data_in: true } => {
+                self.stage = Stage::Status(*csw);
+                Some(Err(UsbError::Pipe))
+            }
+            Stage::Status(csw) => {
+                let csw = csw.to_bytes();
+                self.stage = Stage::Command;
+                Some(Ok(csw))
+            }
+            // Waiting for the host to send a command or data
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.stage = Stage::Command;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Disk image of `blocks` blocks, block N filled with byte N
+    fn image(blocks: u8) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for block in 0..blocks {
+            file.write_all(&[block; BLOCK_SIZE as usize]).unwrap();
+        }
+        file
+    }
+
+    fn cbw(tag: u32, data_length: u32, data_in: bool, command: &[u8]) -> Vec<u8> {
+        let mut bytes = CBW_SIGNATURE.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&data_length.to_le_bytes());
+        bytes.push(if data_in { 0x80 } else { 0 });
+        bytes.push(0); // LUN
+        bytes.push(command.len() as u8);
+        let mut block = [0u8; 16];
+        block[..command.len()].copy_from_slice(command);
+        bytes.extend_from_slice(&block);
+        bytes
+    }
+
+    fn csw(disk: &mut MassStorage) -> (u32, u32, u8) {
+        let bytes = disk.read(BULK_IN_ENDPOINT, 13).unwrap().unwrap();
+        assert_eq!(bytes.len(), 13);
+        assert_eq!(bytes[0..4], CSW_SIGNATURE.to_le_bytes());
+        (
+            u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            bytes[12],
+        )
+    }
+
+    fn rw10(opcode: u8, lba: u32, blocks: u16) -> Vec<u8> {
+        let mut command = vec![opcode, 0];
+        command.extend_from_slice(&lba.to_be_bytes());
+        command.push(0);
+        command.extend_from_slice(&blocks.to_be_bytes());
+        command.push(0);
+        command
+    }
+
+    #[test]
+    fn test_read_capacity_and_read() {
+        let file = image(4);
+        let mut disk = MassStorage::open(file.path(), true).unwrap();
+
+        disk.write(BULK_OUT_ENDPOINT, &cbw(1, 8, true, &[0x25]))
+            .unwrap()
+            .unwrap();
+        let capacity = disk.read(BULK_IN_ENDPOINT, 512).unwrap().unwrap();
+        assert_eq!(capacity, [0, 0, 0, 3, 0, 0, 2, 0]);
+        assert_eq!(csw(&mut disk), (1, 0, STATUS_PASSED));
+
+        disk.write(BULK_OUT_ENDPOINT, &cbw(2, 1024, true, &rw10(0x28, 2, 2)))
+            .unwrap()
+            .unwrap();
+        let first = disk.read(BULK_IN_ENDPOINT, 512).unwrap().unwrap();
+        let second = disk.read(BULK_IN_ENDPOINT, 512).unwrap().unwrap();
+        assert!(first.iter().all(|&byte| byte == 2));
+        assert!(second.iter().all(|&byte| byte == 3));
+        assert_eq!(csw(&mut disk), (2, 0, STATUS_PASSED));
+
+        // Back to waiting for a command
+        assert!(disk.read(BULK_IN_ENDPOINT, 512).is_none());
+    }
+
+    #[test]
+    fn test_write_persists_to_image() {
+        let file = image(2);
+        let mut disk = MassStorage::open(file.path(), false).unwrap();
+
+        disk.write(BULK_OUT_ENDPOINT, &cbw(7, 512, false, &rw10(0x2a, 1, 1)))
+            .unwrap()
+            .unwrap();
+        disk.write(BULK_OUT_ENDPOINT, &[0xab; 256])
+            .unwrap()
+            .unwrap();
+        disk.write(BULK_OUT_ENDPOINT, &[0xcd; 256])
+            .unwrap()
+            .unwrap();
+        assert_eq!(csw(&mut disk), (7, 0, STATUS_PASSED));
+
+        let contents = std::fs::read(file.path()).unwrap();
+        assert!(contents[..512].iter().all(|&byte| byte == 0));
+        assert!(contents[512..768].iter().all(|&byte| byte == 0xab));
+        assert!(contents[768..].iter().all(|&byte| byte == 0xcd));
+    }
+
+    #[test]
+    fn test_read_only_write_fails_with_sense() {
+        let file = image(2);
+        let mut disk = MassStorage::open(file.path(), true).unwrap();
+
+        disk.write(BULK_OUT_ENDPOINT, &cbw(3, 512, false, &rw10(0x2a, 0, 1)))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            disk.write(BULK_OUT_ENDPOINT, &[0; 512]),
+            Some(Err(UsbError::Pipe))
+        ));
+        assert_eq!(csw(&mut disk), (3, 512, STATUS_FAILED));
+
+        disk.write(BULK_OUT_ENDPOINT, &cbw(4, 18, true, &[0x03, 0, 0, 0, 18]))
+            .unwrap()
+            .unwrap();
+        let sense = disk.read(BULK_IN_ENDPOINT, 18).unwrap().unwrap();
+        assert_eq!((sense[2], sense[12]), (0x07, 0x27));
+        assert_eq!(csw(&mut disk), (4, 0, STATUS_PASSED));
+    }
+
+    #[test]
+    fn test_unknown_command_and_out_of_range() {
+        let file = image(2);
+        let mut disk = MassStorage::open(file.path(), true).unwrap();
+
+        disk.write(BULK_OUT_ENDPOINT, &cbw(5, 0, false, &[0xee]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(csw(&mut disk), (5, 0, STATUS_FAILED));
+        assert_eq!(disk.sense, Sense::INVALID_COMMAND);
+
+        disk.write(BULK_OUT_ENDPOINT, &cbw(6, 1024, true, &rw10(0x28, 1, 2)))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            disk.read(BULK_IN_ENDPOINT, 1024),
+            Some(Err(UsbError::Pipe))
+        ));
+        assert_eq!(csw(&mut disk), (6, 1024, STATUS_FAILED));
+        assert_eq!(disk.sense, Sense::LBA_OUT_OF_RANGE);
+    }
+
+    #[test]
+    fn test_invalid_cbw_stalls() {
+        let file = image(1);
+        let mut disk = MassStorage::open(file.path(), true).unwrap();
+        assert!(matches!(
+            disk.write(BULK_OUT_ENDPOINT, b"not a cbw"),
+            Some(Err(UsbError::Pipe))
+        ));
+        assert!(MassStorage::open(tempfile::NamedTempFile::new().unwrap().path(), true).is_err());
+    }
+}
@@ -38,16 +38,18 @@
 //! - Client acknowledges received sequences for flow control
 //! - If buffer overflows, oldest unacknowledged data is dropped with warning
 
+// Only interrupt streams use the buffers so far; the pull API is for tests
+#![allow(dead_code)]
+
 use protocol::integrity::compute_interrupt_checksum;
 use protocol::DeviceId;
-use rusb::{Context, DeviceHandle};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 use tokio::sync::mpsc;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, info, trace, warn};
 
 /// Maximum number of buffered reports per endpoint
 pub const MAX_BUFFERED_REPORTS: usize = 64;
@@ -153,6 +155,14 @@ impl EndpointBuffer {
     /// If buffer is full, drops oldest unacknowledged report.
     /// Returns the sequence number assigned to this report.
     pub fn push(&self, data: Vec<u8>) -> u64 {
+        self.push_report(data).seq
+    }
+
+    /// Push a new report into the buffer and return a copy of it
+    ///
+    /// The copy carries the same sequence number, timestamp and checksum as
+    /// the buffered report, so a later retransmission is byte-identical.
+    pub fn push_report(&self, data: Vec<u8>) -> InterruptReport {
         let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
         let report = InterruptReport::new(seq, self.endpoint, data);
 
@@ -172,7 +182,7 @@ impl EndpointBuffer {
             }
         }
 
-        reports.push_back(report);
+        reports.push_back(report.clone());
 
         // Signal waiters that data is available
         self.data_available.notify_all();
@@ -184,7 +194,7 @@ impl EndpointBuffer {
             reports.len()
         );
 
-        seq
+        report
     }
 
     /// Pop the next report from the buffer (non-blocking)
@@ -239,13 +249,37 @@ impl EndpointBuffer {
     }
 
     /// Acknowledge receipt of reports up to and including seq
-    pub fn acknowledge(&self, seq: u64) {
-        self.last_acked_seq.store(seq, Ordering::SeqCst);
+    ///
+    /// Acknowledged reports are no longer needed for retransmission and are
+    /// trimmed from the buffer. Returns the number of reports trimmed.
+    pub fn acknowledge(&self, seq: u64) -> usize {
+        self.last_acked_seq.fetch_max(seq, Ordering::SeqCst);
+
+        let mut reports = self.reports.lock().unwrap();
+        let before = reports.len();
+        reports.retain(|report| report.seq > seq);
+        let trimmed = before - reports.len();
+
         trace!(
-            "Acknowledged seq {} on endpoint {:#x}",
+            "Acknowledged seq {} on endpoint {:#x} (trimmed {}, buffered {})",
             seq,
-            self.endpoint
+            self.endpoint,
+            trimmed,
+            reports.len()
         );
+        trimmed
+    }
+
+    /// Copies of the buffered reports with the given sequence numbers
+    ///
+    /// Reports that were already acknowledged or dropped on overflow cannot be
+    /// retransmitted and are skipped.
+    pub fn retransmit(&self, sequences: &[u64]) -> Vec<InterruptReport> {
+        let reports = self.reports.lock().unwrap();
+        sequences
+            .iter()
+            .filter_map(|seq| reports.iter().find(|report| report.seq == *seq).cloned())
+            .collect()
     }
 
     /// Get the next sequence number that will be assigned
@@ -270,6 +304,11 @@ impl EndpointBuffer {
     pub fn endpoint(&self) -> u8 {
         self.endpoint
     }
+
+    /// Get the maximum number of buffered reports
+    pub fn capacity(&self) -> usize {
+        self.max_size
+    }
 }
 
 /// Callback for when interrupt data is received
@@ -353,12 +392,10 @@ impl InterruptPoller {
                 while running.load(Ordering::Acquire) {
                     match read_fn(&mut read_buffer, timeout) {
                         Ok(len) if len > 0 => {
-                            let data = read_buffer[..len].to_vec();
-                            let seq = buffer.push(data.clone());
+                            let report = buffer.push_report(read_buffer[..len].to_vec());
 
                             // Call callback if set
                             if let Some(ref cb) = callback {
-                                let report = InterruptReport::new(seq, endpoint, data);
                                 cb(device_id, report);
                             }
                         }
@@ -448,11 +485,21 @@ impl DeviceInterruptManager {
 
     /// Register an interrupt endpoint
     pub fn register_endpoint(&mut self, endpoint: u8, max_packet_size: usize) {
+        self.register_endpoint_with_capacity(endpoint, max_packet_size, MAX_BUFFERED_REPORTS);
+    }
+
+    /// Register an interrupt endpoint with a specific buffer capacity
+    pub fn register_endpoint_with_capacity(
+        &mut self,
+        endpoint: u8,
+        max_packet_size: usize,
+        capacity: usize,
+    ) {
         if self.buffers.contains_key(&endpoint) {
             return; // Already registered
         }
 
-        let buffer = Arc::new(EndpointBuffer::new(endpoint));
+        let buffer = Arc::new(EndpointBuffer::with_capacity(endpoint, capacity));
         self.buffers.insert(endpoint, buffer.clone());
 
         let poller = InterruptPoller::new(self.device_id, endpoint, max_packet_size, buffer);
@@ -522,11 +569,12 @@ impl DeviceInterruptManager {
             .unwrap_or(false)
     }
 
-    /// Acknowledge receipt of data
-    pub fn acknowledge(&self, endpoint: u8, seq: u64) {
-        if let Some(buffer) = self.buffers.get(&endpoint) {
-            buffer.acknowledge(seq);
-        }
+    /// Acknowledge receipt of data, returning the number of reports trimmed
+    pub fn acknowledge(&self, endpoint: u8, seq: u64) -> usize {
+        self.buffers
+            .get(&endpoint)
+            .map(|b| b.acknowledge(seq))
+            .unwrap_or(0)
     }
 
     /// Get list of registered endpoints
@@ -598,6 +646,20 @@ impl InterruptBufferManager {
         }
     }
 
+    /// Register an interrupt endpoint with a specific buffer capacity
+    pub fn register_endpoint_with_capacity(
+        &self,
+        device_id: DeviceId,
+        endpoint: u8,
+        max_packet_size: usize,
+        capacity: usize,
+    ) {
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(manager) = devices.get_mut(&device_id) {
+            manager.register_endpoint_with_capacity(endpoint, max_packet_size, capacity);
+        }
+    }
+
     /// Unregister a device (stops all polling)
     pub fn unregister_device(&self, device_id: DeviceId) {
         let mut devices = self.devices.lock().unwrap();
@@ -668,12 +730,13 @@ impl InterruptBufferManager {
             .unwrap_or(false)
     }
 
-    /// Acknowledge receipt of data
-    pub fn acknowledge(&self, device_id: DeviceId, endpoint: u8, seq: u64) {
+    /// Acknowledge receipt of data, returning the number of reports trimmed
+    pub fn acknowledge(&self, device_id: DeviceId, endpoint: u8, seq: u64) -> usize {
         let devices = self.devices.lock().unwrap();
-        if let Some(manager) = devices.get(&device_id) {
-            manager.acknowledge(endpoint, seq);
-        }
+        devices
+            .get(&device_id)
+            .map(|m| m.acknowledge(endpoint, seq))
+            .unwrap_or(0)
     }
 
     /// Get buffer for an endpoint
@@ -749,6 +812,29 @@ mod tests {
         assert_eq!(report.seq, 6);
     }
 
+    #[test]
+    fn test_endpoint_buffer_ack_trims_and_nack_retransmits() {
+        let buffer = EndpointBuffer::new(0x81);
+        for i in 0..5 {
+            buffer.push(vec![i]);
+        }
+
+        // Retransmission returns identical copies and skips unknown sequences
+        let resent = buffer.retransmit(&[1, 3, 99]);
+        assert_eq!(resent.len(), 2);
+        assert_eq!(resent[0].seq, 1);
+        assert_eq!(resent[1].data, vec![3]);
+        assert!(resent.iter().all(|r| r.verify()));
+
+        assert_eq!(buffer.acknowledge(2), 3);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.last_acked(), 2);
+
+        // Acknowledged reports are gone from the history
+        assert!(buffer.retransmit(&[1]).is_empty());
+        assert_eq!(buffer.retransmit(&[3])[0].seq, 3);
+    }
+
     #[test]
     fn test_endpoint_buffer_timeout() {
         let buffer = Arc::new(EndpointBuffer::new(0x81));
@@ -0,0 +1,114 @@
+//! libusb backend
+//!
+//! Serves the USB devices connected to this machine through rusb. Transfers
+//! are submitted asynchronously by each [`UsbDevice`] and completed while
+//! [`UsbBackend::handle_events`] runs libusb's event loop.
+
+use crate::usb::backend::{BackendDevice, UsbBackend};
+use crate::usb::device::UsbDevice;
+use crate::usb::manager::HotplugSink;
+use protocol::DeviceId;
+use rusb::{Context, Device, Hotplug, HotplugBuilder, Registration, UsbContext};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// USB backend for real hardware
+pub struct LibusbBackend {
+    /// USB context for device operations
+    context: Context,
+    /// Hot-plug registration
+    _hotplug_registration: Option<Registration<Context>>,
+}
+
+impl LibusbBackend {
+    /// Create a libusb context
+    pub fn new() -> Result<Self, rusb::Error> {
+        Ok(Self {
+            context: Context::new()?,
+            _hotplug_registration: None,
+        })
+    }
+
+    /// Wrap a libusb device; the ID is assigned by the device manager
+    fn wrap(device: Device<Context>) -> Option<Box<dyn BackendDevice>> {
+        match UsbDevice::new(device, DeviceId(0), String::new()) {
+            Ok(device) => Some(Box::new(device)),
+            Err(e) => {
+                warn!("Failed to read device descriptor: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl UsbBackend for LibusbBackend {
+    fn devices(&self) -> Result<Vec<Box<dyn BackendDevice>>, rusb::Error> {
+        Ok(self
+            .context
+            .devices()?
+            .iter()
+            .filter_map(Self::wrap)
+            .collect())
+    }
+
+    fn device(&self, bus: u8, address: u8) -> Option<Box<dyn BackendDevice>> {
+        let devices = match self.context.devices() {
+            Ok(devices) => devices,
+            Err(e) => {
+                warn!("Failed to enumerate devices for arrival: {}", e);
+                return None;
+            }
+        };
+
+        devices
+            .iter()
+            .find(|device| device.bus_number() == bus && device.address() == address)
+            .and_then(Self::wrap)
+    }
+
+    fn register_hotplug(&mut self, hotplug: HotplugSink) -> Result<(), rusb::Error> {
+        let registration = HotplugBuilder::new()
+            .enumerate(false) // The device manager already enumerated
+            .register(&self.context, Box::new(HotplugCallback { hotplug }))?;
+
+        self._hotplug_registration = Some(registration);
+        debug!("Hot-plug callbacks registered");
+        Ok(())
+    }
+
+    fn handle_events(&self, timeout: Duration) -> Result<(), rusb::Error> {
+        self.context.handle_events(Some(timeout))
+    }
+}
+
+/// Hot-plug callback handler
+///
+/// Implements rusb's Hotplug trait and forwards arrivals and removals to the
+/// device manager, which debounces them.
+struct HotplugCallback {
+    hotplug: HotplugSink,
+}
+
+impl<T: UsbContext> Hotplug<T> for HotplugCallback {
+    fn device_arrived(&mut self, device: Device<T>) {
+        let bus = device.bus_number();
+        let address = device.address();
+
+        debug!(
+            "Hot-plug callback: device arrived (bus={}, addr={})",
+            bus, address
+        );
+        self.hotplug.device_arrived(bus, address);
+    }
+
+    fn device_left(&mut self, device: Device<T>) {
+        let bus = device.bus_number();
+        let address = device.address();
+
+        debug!(
+            "Hot-plug callback: device left (bus={}, addr={})",
+            bus, address
+        );
+        self.hotplug.device_left(bus, address);
+    }
+}
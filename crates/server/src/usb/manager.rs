@@ -1,16 +1,18 @@
 //! USB device manager
 //!
 //! Handles device enumeration, hot-plug events, and device state tracking.
-//! This module runs in the USB thread and manages the device registry.
+//! This module runs in the USB thread and manages the device registry. Devices
+//! come from a [`UsbBackend`].
 
-use crate::usb::device::UsbDevice;
-use crate::usb::sharing::{DeviceAccessTracker, SharingEvent};
-use common::UsbEvent;
+use crate::usb::backend::{BackendDevice, UsbBackend};
+use crate::usb::registry::DeviceRegistry;
+use crate::usb::sharing::DeviceAccessTracker;
+use common::{DeviceFilter, UsbEvent};
 use protocol::{
-    AttachError, DetachError, DeviceHandle, DeviceId, DeviceInfo, DeviceSharingStatus, LockResult,
-    SharingMode, UnlockResult,
+    AttachError, DetachError, DeviceDescriptors, DeviceHandle, DeviceId, DeviceInfo,
+    DeviceOperation, DeviceSharingStatus, LockResult, SharingMode, TransferType, UnlockResult,
+    UsbError,
 };
-use rusb::{Context, Device, Hotplug, HotplugBuilder, Registration, UsbContext};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -41,7 +43,7 @@ pub struct DebouncedEvent {
     pub address: u8,
 }
 
-/// Shared debounce state between HotplugCallback and DeviceManager
+/// Shared debounce state between HotplugSink and DeviceManager
 pub type DebounceState = Arc<std::sync::Mutex<HashMap<(u8, u8), DebouncedEvent>>>;
 
 /// Sharing configuration for DeviceManager
@@ -65,68 +67,141 @@ impl Default for SharingConfig {
     }
 }
 
+/// Interfaces of a device attached by one client
+///
+/// Created by [`DeviceManager::attach_interfaces`]; handles without a claim
+/// own the whole device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InterfaceClaim {
+    /// Claimed bInterfaceNumbers
+    interfaces: Vec<u8>,
+    /// Endpoint addresses of those interfaces (all alternate settings)
+    endpoints: Vec<u8>,
+}
+
+impl InterfaceClaim {
+    /// Whether a transfer stays within the claimed interfaces
+    ///
+    /// Control requests addressed to another interface or its endpoints are
+    /// refused, as is a raw SET_CONFIGURATION. Other device-level control
+    /// requests (descriptors, status) are shared.
+    fn allows_transfer(&self, transfer: &TransferType) -> bool {
+        match transfer {
+            TransferType::Control {
+                request_type,
+                request,
+                index,
+                ..
+            } => match request_type & 0x1f {
+                0x01 => self.interfaces.contains(&(*index as u8)),
+                0x02 => self.allows_endpoint(*index as u8),
+                _ => !(*request_type & 0x60 == 0 && *request == 0x09),
+            },
+            TransferType::Bulk { endpoint, .. }
+            | TransferType::Interrupt { endpoint, .. }
+            | TransferType::Isochronous { endpoint, .. } => self.allows_endpoint(*endpoint),
+        }
+    }
+
+    /// Whether a device operation only affects the claimed interfaces
+    ///
+    /// A reset or a configuration change would pull the device away from the
+    /// other clients; re-selecting the active configuration is harmless.
+    fn allows_operation(&self, operation: &DeviceOperation, active_config: Option<u8>) -> bool {
+        match operation {
+            DeviceOperation::Reset => false,
+            DeviceOperation::ClearHalt { endpoint } => self.allows_endpoint(*endpoint),
+            DeviceOperation::SetConfiguration { configuration } => {
+                active_config == Some(*configuration)
+            }
+            DeviceOperation::SetInterface { interface, .. } => self.interfaces.contains(interface),
+        }
+    }
+
+    /// Endpoint 0 is shared; others must belong to a claimed interface
+    fn allows_endpoint(&self, endpoint: u8) -> bool {
+        endpoint & 0x0f == 0 || self.endpoints.contains(&endpoint)
+    }
+}
+
 /// USB device manager
 ///
 /// Manages the registry of discovered USB devices, handles hot-plug events,
 /// and tracks device state (discovered, attached, detached).
 pub struct DeviceManager {
-    /// USB context for device operations
-    context: Context,
-    /// Registry of all discovered devices (bus, address) -> UsbDevice
-    devices: HashMap<(u8, u8), UsbDevice>,
+    /// Backend providing the devices
+    backend: Box<dyn UsbBackend>,
+    /// Registry of all discovered devices (bus, address) -> device
+    devices: HashMap<(u8, u8), Box<dyn BackendDevice>>,
     /// Mapping of DeviceId -> (bus, address)
     device_ids: HashMap<DeviceId, (u8, u8)>,
     /// Attached devices: DeviceHandle -> DeviceId
     attached: HashMap<DeviceHandle, (DeviceId, String)>,
-    /// Next device ID to assign
-    next_device_id: u32,
+    /// Handles attached to only some interfaces of their device
+    interface_claims: HashMap<DeviceHandle, InterfaceClaim>,
+    /// Persistent stable identity -> DeviceId mapping
+    registry: DeviceRegistry,
     /// Next device handle to assign
     next_handle_id: u32,
-    /// Hot-plug registration
-    _hotplug_registration: Option<Registration<Context>>,
     /// Event sender for hot-plug notifications
     event_sender: async_channel::Sender<UsbEvent>,
-    /// Device filters (VID:PID patterns)
-    allowed_filters: Vec<String>,
+    /// Device filters (None = all devices allowed)
+    device_filters: Option<Vec<DeviceFilter>>,
     /// Shared debounce state for hotplug events
     debounce_state: DebounceState,
     /// Device access tracker for multi-client sharing
     access_tracker: DeviceAccessTracker,
     /// Sharing configuration
     sharing_config: SharingConfig,
+    /// Devices the operator withheld from sharing, as last seen
+    withheld: HashMap<DeviceId, DeviceInfo>,
 }
 
 impl DeviceManager {
     /// Create a new device manager
     pub fn new(
+        backend: Box<dyn UsbBackend>,
         event_sender: async_channel::Sender<UsbEvent>,
         allowed_filters: Vec<String>,
-    ) -> Result<Self, rusb::Error> {
-        Self::with_sharing_config(event_sender, allowed_filters, SharingConfig::default())
+    ) -> Self {
+        Self::with_sharing_config(
+            backend,
+            event_sender,
+            allowed_filters,
+            SharingConfig::default(),
+        )
     }
 
     /// Create a new device manager with sharing configuration
     pub fn with_sharing_config(
+        backend: Box<dyn UsbBackend>,
         event_sender: async_channel::Sender<UsbEvent>,
         allowed_filters: Vec<String>,
         sharing_config: SharingConfig,
-    ) -> Result<Self, rusb::Error> {
-        let context = Context::new()?;
-
-        Ok(Self {
-            context,
+    ) -> Self {
+        Self {
+            backend,
             devices: HashMap::new(),
             device_ids: HashMap::new(),
             attached: HashMap::new(),
-            next_device_id: 1,
+            interface_claims: HashMap::new(),
+            registry: DeviceRegistry::in_memory(),
             next_handle_id: 1,
-            _hotplug_registration: None,
             event_sender,
-            allowed_filters,
+            device_filters: Self::compile_filters(&allowed_filters),
             debounce_state: Arc::new(std::sync::Mutex::new(HashMap::new())),
             access_tracker: DeviceAccessTracker::new(),
             sharing_config,
-        })
+            withheld: HashMap::new(),
+        }
+    }
+
+    /// Use a persistent registry so device IDs survive replugs and restarts
+    ///
+    /// Must be set before [`DeviceManager::initialize`].
+    pub fn with_registry(mut self, registry: DeviceRegistry) -> Self {
+        self.registry = registry;
+        self
     }
 
     /// Initialize device enumeration and hot-plug callbacks
@@ -148,9 +223,9 @@ impl DeviceManager {
 
     /// Enumerate all currently connected USB devices
     fn enumerate_devices(&mut self) -> Result<(), rusb::Error> {
-        let devices = self.context.devices()?;
+        let devices = self.backend.devices()?;
 
-        for device in devices.iter() {
+        for device in devices {
             if let Err(e) = self.add_device(device) {
                 warn!("Failed to add device during enumeration: {}", e);
             }
@@ -162,44 +237,21 @@ impl DeviceManager {
 
     /// Register hot-plug callbacks
     fn register_hotplug(&mut self) -> Result<(), rusb::Error> {
-        let debounce_state = Arc::clone(&self.debounce_state);
-
-        // Create hotplug callback with shared debounce state
-        let callback = HotplugCallback::new(debounce_state);
-
-        let registration = HotplugBuilder::new()
-            .enumerate(false) // We already enumerated
-            .register(&self.context, Box::new(callback))?;
-
-        self._hotplug_registration = Some(registration);
-        debug!("Hot-plug callbacks registered");
-        Ok(())
+        let hotplug = HotplugSink::new(Arc::clone(&self.debounce_state));
+        self.backend.register_hotplug(hotplug)
     }
 
     /// Add a device to the registry
-    fn add_device(&mut self, device: Device<Context>) -> Result<DeviceId, rusb::Error> {
-        let bus = device.bus_number();
-        let address = device.address();
+    ///
+    /// The device ID is assigned once the device passes the filters.
+    fn add_device(
+        &mut self,
+        mut usb_device: Box<dyn BackendDevice>,
+    ) -> Result<DeviceId, rusb::Error> {
+        let bus = usb_device.bus_number();
+        let address = usb_device.device_address();
         let key = (bus, address);
 
-        // Check if device is allowed based on filters
-        if !self.is_device_allowed(&device) {
-            debug!(
-                "Device ignored by filter: bus={}, addr={}, vid={:#x}, pid={:#x}",
-                bus,
-                address,
-                device
-                    .device_descriptor()
-                    .map(|d| d.vendor_id())
-                    .unwrap_or(0),
-                device
-                    .device_descriptor()
-                    .map(|d| d.product_id())
-                    .unwrap_or(0)
-            );
-            return Err(rusb::Error::Access); // Treat as access denied / filtered out
-        }
-
         // Check if already tracked
         if let Some(existing_device) = self.devices.get(&key) {
             return Ok(existing_device.id());
@@ -207,33 +259,55 @@ impl DeviceManager {
 
         // Skip root hubs - they can't be shared via USB/IP
         // Root hubs are VID 0x1d6b (Linux Foundation) with device class 9 (Hub)
-        if let Ok(desc) = device.device_descriptor() {
-            if desc.vendor_id() == 0x1d6b && desc.class_code() == 9 {
+        let descriptor_info = usb_device.descriptor_info();
+        if descriptor_info.vendor_id == 0x1d6b && descriptor_info.class == 9 {
+            debug!(
+                "Skipping root hub: bus={}, addr={}, vid={:#x}, pid={:#x}",
+                bus, address, descriptor_info.vendor_id, descriptor_info.product_id
+            );
+            return Err(rusb::Error::NotSupported);
+        }
+
+        // Check if device is allowed based on filters. Reading strings (and
+        // the serial-number identity) opens the device, so that is only done
+        // when a filter looks at them.
+        let mut candidates = None;
+        if let Some(filters) = &self.device_filters {
+            let info = if filters.iter().any(DeviceFilter::needs_strings) {
+                let ids = usb_device.stable_ids();
+                usb_device.set_identity(DeviceId(0), ids[0].clone());
+                candidates = Some(ids);
+                usb_device.device_info()
+            } else {
+                descriptor_info
+            };
+            if !self.is_device_allowed(&info) {
                 debug!(
-                    "Skipping root hub: bus={}, addr={}, vid={:#x}, pid={:#x}",
-                    bus,
-                    address,
-                    desc.vendor_id(),
-                    desc.product_id()
+                    "Device ignored by filter: bus={}, addr={}, vid={:#x}, pid={:#x}",
+                    bus, address, info.vendor_id, info.product_id
                 );
-                return Err(rusb::Error::NotSupported);
+                return Err(rusb::Error::Access); // Treat as access denied / filtered out
             }
         }
 
-        // Assign new device ID
-        let device_id = DeviceId(self.next_device_id);
-        self.next_device_id += 1;
+        // Reuse the ID recorded for this device's stable identity
+        let candidates = candidates.unwrap_or_else(|| usb_device.stable_ids());
+        let (stable_id, device_id) = self.assign_device_id(&candidates);
+        usb_device.set_identity(device_id, stable_id);
 
-        // Create USB device wrapper
-        let usb_device = UsbDevice::new(device, device_id)?;
+        if let Some(withheld) = self.withheld.get_mut(&device_id) {
+            debug!(
+                "Device {:?} is withheld from sharing: bus={}, addr={}",
+                device_id, bus, address
+            );
+            *withheld = usb_device.device_info();
+            return Err(rusb::Error::Access);
+        }
 
+        let info = usb_device.descriptor_info();
         debug!(
             "Added device {:?}: bus={}, addr={}, vid={:#x}, pid={:#x}",
-            device_id,
-            bus,
-            address,
-            usb_device.device_info().vendor_id,
-            usb_device.device_info().product_id
+            device_id, bus, address, info.vendor_id, info.product_id
         );
 
         self.device_ids.insert(device_id, key);
@@ -250,6 +324,32 @@ impl DeviceManager {
         Ok(device_id)
     }
 
+    /// Pick the stable identity and persistent ID for a new device
+    ///
+    /// `candidates` come from [`BackendDevice::stable_ids`], most preferred first. Falls back
+    /// to the port-path identity when the preferred one already belongs to a
+    /// connected device (e.g. two devices sharing a serial).
+    fn assign_device_id(&mut self, candidates: &[String]) -> (String, DeviceId) {
+        for stable_id in candidates {
+            let in_use = self
+                .registry
+                .lookup(stable_id)
+                .is_some_and(|id| self.device_ids.contains_key(&id));
+            if !in_use {
+                let device_id = self.registry.assign(stable_id);
+                return (stable_id.clone(), device_id);
+            }
+        }
+
+        // Every identity is taken by a connected device; use a temporary ID
+        let stable_id = candidates.last().cloned().unwrap_or_default();
+        warn!(
+            "Device identity {} already in use, assigning a temporary ID",
+            stable_id
+        );
+        (stable_id, self.registry.fresh_id())
+    }
+
     /// Remove a device from the registry
     ///
     /// Returns the DeviceId and any invalidated handles with their client IDs.
@@ -279,6 +379,9 @@ impl DeviceManager {
                     true // Keep this entry
                 }
             });
+            for handle in &invalidated_handles {
+                self.interface_claims.remove(handle);
+            }
 
             // Unregister from access tracker
             self.access_tracker.unregister_device(device_id);
@@ -322,41 +425,30 @@ impl DeviceManager {
 
     /// Handle device arrival (from hot-plug callback) - internal implementation
     fn handle_device_arrived_internal(&mut self, bus: u8, address: u8) {
-        let devices = match self.context.devices() {
-            Ok(d) => d,
-            Err(e) => {
-                warn!("Failed to enumerate devices for arrival: {}", e);
-                return;
-            }
+        let Some(device) = self.backend.device(bus, address) else {
+            debug!(
+                "Device arrived but not found in enumeration: bus={}, addr={}",
+                bus, address
+            );
+            return;
         };
 
-        for device in devices.iter() {
-            if device.bus_number() == bus && device.address() == address {
-                match self.add_device(device) {
-                    Ok(device_id) => {
-                        if let Some(usb_device) = self.get_device_by_id(device_id) {
-                            let device_info = usb_device.device_info();
-
-                            if let Err(e) =
-                                self.event_sender.send_blocking(UsbEvent::DeviceArrived {
-                                    device: device_info,
-                                })
-                            {
-                                error!("Failed to send DeviceArrived event: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Failed to add arrived device: {}", e);
+        match self.add_device(device) {
+            Ok(device_id) => {
+                if let Some(usb_device) = self.get_device_by_id(device_id) {
+                    let device_info = usb_device.device_info();
+
+                    if let Err(e) = self.event_sender.send_blocking(UsbEvent::DeviceArrived {
+                        device: device_info,
+                    }) {
+                        error!("Failed to send DeviceArrived event: {}", e);
                     }
                 }
-                return;
+            }
+            Err(e) => {
+                warn!("Failed to add arrived device: {}", e);
             }
         }
-        debug!(
-            "Device arrived but not found in enumeration: bus={}, addr={}",
-            bus, address
-        );
     }
 
     /// Process any debounced hotplug events that are ready to fire
@@ -413,6 +505,13 @@ impl DeviceManager {
             .collect()
     }
 
+    /// Full descriptor set of a device (does not require an attachment)
+    pub fn device_descriptors(&self, device_id: DeviceId) -> Result<DeviceDescriptors, UsbError> {
+        self.get_device_by_id(device_id)
+            .ok_or(UsbError::NotFound)?
+            .descriptors()
+    }
+
     /// Attach a device for a client
     pub fn attach_device(
         &mut self,
@@ -424,6 +523,11 @@ impl DeviceManager {
             return Err(AttachError::DeviceNotFound);
         }
 
+        // Interfaces attached by other clients rule out a whole-device attach
+        if self.has_interface_claims(device_id) {
+            return Err(AttachError::AlreadyAttached);
+        }
+
         // Check sharing policy via access tracker
         if !self.access_tracker.can_attach(device_id) {
             // Get the sharing mode to provide a better error message
@@ -476,6 +580,133 @@ impl DeviceManager {
         Ok(handle)
     }
 
+    /// Attach some interfaces of a device for a client
+    ///
+    /// Interface attachments of different clients may coexist as long as they
+    /// do not overlap. They bypass the sharing tracker and are refused while
+    /// the whole device is attached. Interfaces not claimed by anyone keep
+    /// their kernel drivers on the server.
+    pub fn attach_interfaces(
+        &mut self,
+        device_id: DeviceId,
+        client_id: String,
+        mut interfaces: Vec<u8>,
+    ) -> Result<DeviceHandle, AttachError> {
+        let device = self
+            .get_device_by_id(device_id)
+            .ok_or(AttachError::DeviceNotFound)?;
+
+        interfaces.sort_unstable();
+        interfaces.dedup();
+        if interfaces.is_empty() {
+            return Err(AttachError::Other {
+                message: "No interfaces requested".to_string(),
+            });
+        }
+        let available = device.interfaces();
+        if let Some(missing) = interfaces
+            .iter()
+            .find(|number| !available.iter().any(|iface| iface.number == **number))
+        {
+            return Err(AttachError::Other {
+                message: format!("Device has no interface {}", missing),
+            });
+        }
+
+        for (handle, (id, _)) in &self.attached {
+            if *id != device_id {
+                continue;
+            }
+            match self.interface_claims.get(handle) {
+                // Whole device attached
+                None => return Err(AttachError::AlreadyAttached),
+                Some(claim) if claim.interfaces.iter().any(|i| interfaces.contains(i)) => {
+                    return Err(AttachError::AlreadyAttached);
+                }
+                Some(_) => {}
+            }
+        }
+
+        let device = self
+            .get_device_by_id_mut(device_id)
+            .ok_or(AttachError::DeviceNotFound)?;
+        device.open_interfaces(&interfaces)?;
+        let endpoints = device.interface_endpoints(&interfaces);
+
+        let handle = DeviceHandle(self.next_handle_id);
+        self.next_handle_id += 1;
+
+        info!(
+            "Attached interfaces {:?} of device {:?} as handle {:?} for client {}",
+            interfaces, device_id, handle, client_id
+        );
+
+        self.attached.insert(handle, (device_id, client_id));
+        self.interface_claims.insert(
+            handle,
+            InterfaceClaim {
+                interfaces,
+                endpoints,
+            },
+        );
+
+        Ok(handle)
+    }
+
+    /// Whether any handle holds an interface-level claim on a device
+    fn has_interface_claims(&self, device_id: DeviceId) -> bool {
+        self.interface_claims
+            .keys()
+            .any(|handle| matches!(self.attached.get(handle), Some((id, _)) if *id == device_id))
+    }
+
+    /// Check that a transfer stays within the interfaces a handle attached
+    ///
+    /// Always allowed for whole-device handles.
+    pub fn authorize_transfer(
+        &self,
+        handle: DeviceHandle,
+        transfer: &TransferType,
+    ) -> Result<(), UsbError> {
+        match self.interface_claims.get(&handle) {
+            Some(claim) if !claim.allows_transfer(transfer) => {
+                debug!(
+                    "Transfer outside claimed interfaces {:?} refused for handle {:?}",
+                    claim.interfaces, handle
+                );
+                Err(UsbError::Access)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a device operation does not disturb other clients
+    ///
+    /// Always allowed for whole-device handles.
+    pub fn authorize_operation(
+        &self,
+        handle: DeviceHandle,
+        operation: &DeviceOperation,
+    ) -> Result<(), UsbError> {
+        let Some(claim) = self.interface_claims.get(&handle) else {
+            return Ok(());
+        };
+        let active_config = self
+            .get_device_id_for_handle(handle)
+            .and_then(|device_id| self.get_device_by_id(device_id))
+            .and_then(|device| device.active_configuration());
+
+        if claim.allows_operation(operation, active_config) {
+            Ok(())
+        } else {
+            debug!(
+                "{:?} refused for handle {:?} with interfaces {:?}",
+                operation, handle, claim.interfaces
+            );
+            Err(UsbError::Access)
+        }
+    }
+
     /// Detach a device
     ///
     /// Returns the device_id that was detached (for queue processing)
@@ -484,17 +715,21 @@ impl DeviceManager {
             .attached
             .remove(&handle)
             .ok_or(DetachError::HandleNotFound)?;
+        let claim = self.interface_claims.remove(&handle);
 
         // Detach from access tracker
         if let Some(state) = self.access_tracker.get_state_mut(device_id) {
             state.detach_client(handle);
         }
 
-        // Close the device only if no other clients are attached
+        // Close the device only if no other clients are attached; otherwise
+        // hand this client's interfaces back to the kernel
         let other_clients = self.attached.values().any(|(id, _)| *id == device_id);
-        if !other_clients {
-            if let Some(device) = self.get_device_by_id_mut(device_id) {
+        if let Some(device) = self.get_device_by_id_mut(device_id) {
+            if !other_clients {
                 device.close();
+            } else if let Some(claim) = claim {
+                device.release_interfaces(&claim.interfaces);
             }
         }
 
@@ -506,114 +741,190 @@ impl DeviceManager {
         Ok(())
     }
 
-    /// Process the queue for a device after detachment
-    ///
-    /// Should be called after detach_device to grant access to waiting clients.
-    /// Returns sharing events to send to clients.
-    pub fn process_device_queue(&mut self, device_id: DeviceId) -> Vec<SharingEvent> {
-        let mut events = Vec::new();
-
-        if let Some(state) = self.access_tracker.get_state_mut(device_id) {
-            // Process the queue
-            if let Some(granted_handle) = state.process_queue() {
-                events.push(SharingEvent::AccessGranted {
-                    device_id,
-                    handle: granted_handle,
-                });
-
-                // Update queue positions for remaining clients
-                for (handle, position) in state.get_all_queue_positions() {
-                    events.push(SharingEvent::QueuePositionChanged {
-                        device_id,
-                        handle,
-                        new_position: position,
-                    });
-                }
-            }
-        }
-
-        events
-    }
-
     /// Get device by DeviceId
-    fn get_device_by_id(&self, device_id: DeviceId) -> Option<&UsbDevice> {
+    fn get_device_by_id(&self, device_id: DeviceId) -> Option<&dyn BackendDevice> {
         let key = self.device_ids.get(&device_id)?;
-        self.devices.get(key)
+        self.devices.get(key).map(|device| device.as_ref())
     }
 
     /// Get mutable device by DeviceId
-    fn get_device_by_id_mut(&mut self, device_id: DeviceId) -> Option<&mut UsbDevice> {
+    fn get_device_by_id_mut(
+        &mut self,
+        device_id: DeviceId,
+    ) -> Option<&mut (dyn BackendDevice + 'static)> {
         let key = *self.device_ids.get(&device_id)?;
-        self.devices.get_mut(&key)
+        self.devices.get_mut(&key).map(|device| device.as_mut())
     }
 
     /// Get device by handle
-    pub fn get_device_by_handle(&mut self, handle: DeviceHandle) -> Option<&mut UsbDevice> {
+    pub fn get_device_by_handle(
+        &mut self,
+        handle: DeviceHandle,
+    ) -> Option<&mut (dyn BackendDevice + 'static)> {
         let (device_id, _client) = self.attached.get(&handle)?;
         self.get_device_by_id_mut(*device_id)
     }
 
-    /// Get USB context
-    pub fn context(&self) -> &Context {
-        &self.context
+    /// Complete asynchronous transfers on all open devices
+    ///
+    /// Called by the worker after each `handle_events` iteration. Returns the
+    /// number of transfers completed.
+    pub fn process_transfer_completions(&mut self) -> usize {
+        self.devices
+            .values_mut()
+            .map(|device| device.process_transfer_completions())
+            .sum()
     }
-    /// Check if a device is allowed by the configured filters
-    fn is_device_allowed(&self, device: &Device<Context>) -> bool {
-        let desc = match device.device_descriptor() {
-            Ok(d) => d,
-            Err(_) => return false,
-        };
 
-        Self::check_filter(desc.vendor_id(), desc.product_id(), &self.allowed_filters)
+    /// Check if any device has asynchronous transfers queued or in flight
+    pub fn has_pending_transfers(&self) -> bool {
+        self.devices
+            .values()
+            .any(|device| device.has_pending_transfers())
     }
 
-    /// Check if a VID/PID pair is allowed by the filters
-    fn check_filter(vid: u16, pid: u16, filters: &[String]) -> bool {
-        // If no filters are defined, all devices are allowed
-        if filters.is_empty() {
-            return true;
+    /// Wait up to `timeout` for USB events and handle them
+    ///
+    /// Completions are answered by [`DeviceManager::process_transfer_completions`].
+    pub fn handle_events(&self, timeout: Duration) -> Result<(), rusb::Error> {
+        self.backend.handle_events(timeout)
+    }
+    /// Replace the device filters, e.g. after a configuration reload
+    ///
+    /// Tracked devices the new filters exclude are removed and reported with
+    /// `DeviceLeft`, invalidating their handles; connected devices that now
+    /// pass are added and reported with `DeviceArrived`. Returns the number
+    /// of devices removed and added.
+    pub fn set_filters(&mut self, filters: &[String]) -> (usize, usize) {
+        self.device_filters = Self::compile_filters(filters);
+
+        let excluded: Vec<(u8, u8)> = self
+            .devices
+            .iter()
+            .filter(|(_, device)| !self.is_device_allowed(&device.device_info()))
+            .map(|(key, _)| *key)
+            .collect();
+        for (bus, address) in &excluded {
+            // Unlike an unplugged device this one is still there: close it so
+            // the host gets its interfaces back
+            if let Some(device) = self.devices.get_mut(&(*bus, *address)) {
+                device.close();
+            }
+            self.handle_device_left_internal(*bus, *address);
         }
 
-        // Check if device matches any filter
-        for filter in filters {
-            // Filter format: "0xVID:0xPID" or "0xVID:*"
-            // We assume filters are validated by config loader
-            let parts: Vec<&str> = filter.split(':').collect();
-            if parts.len() != 2 {
-                continue;
+        let added = self.add_new_devices();
+
+        info!(
+            "USB filters replaced: {} devices removed, {} added",
+            excluded.len(),
+            added
+        );
+        (excluded.len(), added)
+    }
+
+    /// Withhold a device from sharing, or share it again
+    ///
+    /// Withholding removes the device like [`Self::set_filters`] does and
+    /// keeps it out until it is shared again, even if it is replugged.
+    /// Returns whether anything changed.
+    pub fn set_device_shared(
+        &mut self,
+        device_id: DeviceId,
+        shared: bool,
+    ) -> Result<bool, UsbError> {
+        if shared {
+            if self.withheld.remove(&device_id).is_none() {
+                return if self.device_ids.contains_key(&device_id) {
+                    Ok(false)
+                } else {
+                    Err(UsbError::NotFound)
+                };
             }
+            info!("Device {:?} is shared again", device_id);
+            self.add_new_devices();
+            return Ok(true);
+        }
 
-            let filter_vid_str = parts[0];
-            let filter_pid_str = parts[1];
+        if self.withheld.contains_key(&device_id) {
+            return Ok(false);
+        }
+        let &(bus, address) = self.device_ids.get(&device_id).ok_or(UsbError::NotFound)?;
+        if let Some(device) = self.devices.get_mut(&(bus, address)) {
+            self.withheld.insert(device_id, device.device_info());
+            device.close();
+        }
+        info!("Device {:?} is withheld from sharing", device_id);
+        self.handle_device_left_internal(bus, address);
+        Ok(true)
+    }
 
-            // Check VID
-            let vid_match = if filter_vid_str == "*" {
-                true
-            } else {
-                u16::from_str_radix(filter_vid_str.trim_start_matches("0x"), 16)
-                    .map(|v| v == vid)
-                    .unwrap_or(false)
-            };
+    /// Devices withheld from sharing, as last seen
+    pub fn withheld_devices(&self) -> Vec<DeviceInfo> {
+        self.withheld.values().cloned().collect()
+    }
 
-            if !vid_match {
-                continue;
+    /// Add connected devices that are not tracked yet
+    ///
+    /// Each one added is reported with `DeviceArrived`. Returns how many
+    /// were added.
+    fn add_new_devices(&mut self) -> usize {
+        let devices = match self.backend.devices() {
+            Ok(devices) => devices,
+            Err(e) => {
+                warn!("Failed to enumerate devices: {}", e);
+                return 0;
             }
+        };
 
-            // Check PID
-            let pid_match = if filter_pid_str == "*" {
-                true
-            } else {
-                u16::from_str_radix(filter_pid_str.trim_start_matches("0x"), 16)
-                    .map(|p| p == pid)
-                    .unwrap_or(false)
+        let mut added = 0;
+        for device in devices {
+            let key = (device.bus_number(), device.device_address());
+            if self.devices.contains_key(&key) {
+                continue;
+            }
+            // Root hubs and devices the filters exclude or withheld are skipped
+            let Ok(device_id) = self.add_device(device) else {
+                continue;
             };
-
-            if pid_match {
-                return true;
+            added += 1;
+            if let Some(usb_device) = self.get_device_by_id(device_id) {
+                let device = usb_device.device_info();
+                if let Err(e) = self
+                    .event_sender
+                    .send_blocking(UsbEvent::DeviceArrived { device })
+                {
+                    error!("Failed to send DeviceArrived event: {}", e);
+                }
             }
         }
+        added
+    }
 
-        false
+    /// Check if a device is allowed by the configured filters
+    fn is_device_allowed(&self, info: &DeviceInfo) -> bool {
+        self.device_filters
+            .as_ref()
+            .is_none_or(|filters| filters.iter().any(|filter| filter.matches(info)))
+    }
+
+    /// Parse the configured filters; None if there are none
+    ///
+    /// Filters are validated at config load, so an invalid one is only logged
+    /// and matches no device.
+    fn compile_filters(filters: &[String]) -> Option<Vec<DeviceFilter>> {
+        if filters.is_empty() {
+            return None;
+        }
+        let filters = filters
+            .iter()
+            .filter_map(|filter| {
+                DeviceFilter::parse(filter)
+                    .inspect_err(|e| warn!("Ignoring USB filter: {}", e))
+                    .ok()
+            })
+            .collect();
+        Some(filters)
     }
 
     /// Get sharing status for a device
@@ -699,44 +1010,37 @@ impl DeviceManager {
         }
     }
 
-    /// Process all device queues and check for lock timeouts
-    ///
-    /// Returns sharing events to send to clients.
-    pub fn process_all_queues(&mut self) -> Vec<SharingEvent> {
-        self.access_tracker.process_all()
-    }
-
     /// Get the device ID for a handle
     pub fn get_device_id_for_handle(&self, handle: DeviceHandle) -> Option<DeviceId> {
         self.attached.get(&handle).map(|(id, _)| *id)
     }
-
-    /// Get client ID for a handle
-    pub fn get_client_id_for_handle(&self, handle: DeviceHandle) -> Option<String> {
-        self.attached.get(&handle).map(|(_, client)| client.clone())
-    }
-
-    /// Get the sharing mode for a device
-    pub fn get_sharing_mode(&self, device_id: DeviceId) -> Option<SharingMode> {
-        self.access_tracker.get_mode(device_id)
-    }
 }
 
-/// Hot-plug callback handler
+/// Receiver of hot-plug notifications from a [`UsbBackend`]
 ///
-/// This struct implements the Hotplug trait to receive notifications
-/// about device arrival and removal. Events are debounced by 500ms per device
-/// to handle rapid plug/unplug cycles gracefully.
-struct HotplugCallback {
+/// Events are debounced by 500ms per device to handle rapid plug/unplug
+/// cycles gracefully; [`DeviceManager::process_debounced_events`] fires them.
+#[derive(Clone)]
+pub struct HotplugSink {
     /// Shared debounce state with DeviceManager
     debounce_state: DebounceState,
 }
 
-impl HotplugCallback {
+impl HotplugSink {
     fn new(debounce_state: DebounceState) -> Self {
         Self { debounce_state }
     }
 
+    /// A device was connected
+    pub fn device_arrived(&self, bus: u8, address: u8) {
+        self.schedule_debounced_event(bus, address, PendingHotplugEvent::Arrived);
+    }
+
+    /// A device was disconnected
+    pub fn device_left(&self, bus: u8, address: u8) {
+        self.schedule_debounced_event(bus, address, PendingHotplugEvent::Left);
+    }
+
     /// Schedule a debounced event for a device
     ///
     /// If an event for this device is already pending, it will be replaced
@@ -774,54 +1078,27 @@ impl HotplugCallback {
     }
 }
 
-impl<T: UsbContext> Hotplug<T> for HotplugCallback {
-    fn device_arrived(&mut self, device: Device<T>) {
-        let bus = device.bus_number();
-        let address = device.address();
-
-        debug!(
-            "Hot-plug callback: device arrived (bus={}, addr={})",
-            bus, address
-        );
-
-        // Schedule debounced arrival event
-        self.schedule_debounced_event(bus, address, PendingHotplugEvent::Arrived);
-    }
-
-    fn device_left(&mut self, device: Device<T>) {
-        let bus = device.bus_number();
-        let address = device.address();
-
-        debug!(
-            "Hot-plug callback: device left (bus={}, addr={})",
-            bus, address
-        );
-
-        // Schedule debounced removal event
-        self.schedule_debounced_event(bus, address, PendingHotplugEvent::Left);
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::usb::emulated::{CdcAcm, EmulatedBackend, EmulatedBus, HidKeyboard};
+
+    /// Whether a device with these IDs and port passes `filters`
+    fn check_filter(vid: u16, pid: u16, bus: u8, ports: &[u8], filters: &[String]) -> bool {
+        let mut device = common::test_utils::create_mock_device_info(1, vid, pid);
+        device.bus_number = bus;
+        device.port_numbers = ports.to_vec();
+        DeviceManager::compile_filters(filters)
+            .is_none_or(|filters| filters.iter().any(|filter| filter.matches(&device)))
+    }
 
     #[test]
     fn test_device_id_assignment() {
         let (tx, _rx) = async_channel::bounded(1);
-        // This test might fail in environments without USB access (like CI or Sandbox)
-        // We catch the error and skip if USB context initialization fails
-        match DeviceManager::new(tx, vec![]) {
-            Ok(manager) => {
-                assert_eq!(manager.next_device_id, 1);
-                assert_eq!(manager.next_handle_id, 1);
-            }
-            Err(_) => {
-                println!(
-                    "Skipping test_device_id_assignment: USB context initialization failed (expected in sandbox)"
-                );
-            }
-        }
+        let backend = Box::new(EmulatedBackend::new(EmulatedBus::new()));
+        let mut manager = DeviceManager::new(backend, tx, vec![]);
+        assert_eq!(manager.registry.fresh_id(), DeviceId(1));
+        assert_eq!(manager.next_handle_id, 1);
     }
 
     #[test]
@@ -832,19 +1109,169 @@ mod tests {
         ];
 
         // Should match exact
-        assert!(DeviceManager::check_filter(0x1234, 0x5678, &filters));
+        assert!(check_filter(0x1234, 0x5678, 1, &[1], &filters));
 
         // Should match wildcard
-        assert!(DeviceManager::check_filter(0xABCD, 0x1111, &filters));
-        assert!(DeviceManager::check_filter(0xABCD, 0x9999, &filters));
+        assert!(check_filter(0xABCD, 0x1111, 1, &[1], &filters));
+        assert!(check_filter(0xABCD, 0x9999, 1, &[1], &filters));
 
         // Should not match
-        assert!(!DeviceManager::check_filter(0x1234, 0x9999, &filters)); // Wrong PID
-        assert!(!DeviceManager::check_filter(0x9999, 0x5678, &filters)); // Wrong VID
-        assert!(!DeviceManager::check_filter(0x0000, 0x0000, &filters));
+        assert!(!check_filter(0x1234, 0x9999, 1, &[1], &filters)); // Wrong PID
+        assert!(!check_filter(0x9999, 0x5678, 1, &[1], &filters)); // Wrong VID
+        assert!(!check_filter(0x0000, 0x0000, 1, &[1], &filters));
 
         // Empty filters = allow all
-        assert!(DeviceManager::check_filter(0x1234, 0x5678, &[]));
+        assert!(check_filter(0x1234, 0x5678, 1, &[1], &[]));
+    }
+
+    #[test]
+    fn test_port_filter() {
+        let filters = vec!["port:1-1.4".to_string(), "port:2-3.*".to_string()];
+
+        assert!(check_filter(0x1234, 0x5678, 1, &[1, 4], &filters));
+        assert!(check_filter(0xABCD, 0x1111, 2, &[3, 1], &filters));
+        assert!(!check_filter(0x1234, 0x5678, 1, &[1, 3], &filters));
+        assert!(!check_filter(0x1234, 0x5678, 2, &[3], &filters));
+    }
+
+    fn keyboard_claim() -> InterfaceClaim {
+        InterfaceClaim {
+            interfaces: vec![0],
+            endpoints: vec![0x81],
+        }
+    }
+
+    #[test]
+    fn test_interface_claim_transfers() {
+        let claim = keyboard_claim();
+        let control = |request_type: u8, request: u8, index: u16| TransferType::Control {
+            request_type,
+            request,
+            value: 0,
+            index,
+            data: Vec::new(),
+        };
+
+        assert!(claim.allows_transfer(&TransferType::Interrupt {
+            endpoint: 0x81,
+            data: vec![0u8; 8],
+            timeout_ms: 0,
+        }));
+        assert!(!claim.allows_transfer(&TransferType::Bulk {
+            endpoint: 0x02,
+            data: Vec::new(),
+            timeout_ms: 0,
+            checksum: None,
+        }));
+
+        // GET_DESCRIPTOR (device) is shared; HID SET_IDLE must target interface 0
+        assert!(claim.allows_transfer(&control(0x80, 0x06, 0)));
+        assert!(claim.allows_transfer(&control(0x21, 0x0a, 0)));
+        assert!(!claim.allows_transfer(&control(0x21, 0x0a, 1)));
+        // CLEAR_FEATURE addressed to another interface's endpoint
+        assert!(!claim.allows_transfer(&control(0x02, 0x01, 0x02)));
+        // Raw SET_CONFIGURATION
+        assert!(!claim.allows_transfer(&control(0x00, 0x09, 0)));
+    }
+
+    #[test]
+    fn test_interface_claim_operations() {
+        let claim = keyboard_claim();
+
+        assert!(!claim.allows_operation(&DeviceOperation::Reset, Some(1)));
+        assert!(claim.allows_operation(&DeviceOperation::ClearHalt { endpoint: 0x81 }, Some(1)));
+        assert!(!claim.allows_operation(&DeviceOperation::ClearHalt { endpoint: 0x02 }, Some(1)));
+        assert!(claim.allows_operation(
+            &DeviceOperation::SetConfiguration { configuration: 1 },
+            Some(1)
+        ));
+        assert!(!claim.allows_operation(
+            &DeviceOperation::SetConfiguration { configuration: 2 },
+            Some(1)
+        ));
+        assert!(claim.allows_operation(
+            &DeviceOperation::SetInterface {
+                interface: 0,
+                alt_setting: 1
+            },
+            Some(1)
+        ));
+        assert!(!claim.allows_operation(
+            &DeviceOperation::SetInterface {
+                interface: 1,
+                alt_setting: 0
+            },
+            Some(1)
+        ));
+    }
+
+    #[test]
+    fn test_set_filters_swaps_shared_devices() {
+        let (tx, rx) = async_channel::unbounded();
+        let bus = EmulatedBus::new();
+        bus.plug(HidKeyboard::new()).unwrap();
+        bus.plug(CdcAcm::new()).unwrap();
+        let backend = Box::new(EmulatedBackend::new(bus));
+        let mut manager = DeviceManager::new(backend, tx, vec!["class:03".to_string()]);
+        manager.initialize().unwrap();
+
+        let devices = manager.list_devices();
+        assert_eq!(devices.len(), 1);
+        let keyboard = devices[0].id;
+        let handle = manager
+            .attach_device(keyboard, "client".to_string())
+            .unwrap();
+
+        assert_eq!(manager.set_filters(&["class:02".to_string()]), (1, 1));
+        assert!(manager.get_device_id_for_handle(handle).is_none());
+        match rx.try_recv().unwrap() {
+            UsbEvent::DeviceLeft {
+                device_id,
+                invalidated_handles,
+                ..
+            } => {
+                assert_eq!(device_id, keyboard);
+                assert_eq!(invalidated_handles, vec![handle]);
+            }
+            event => panic!("Expected DeviceLeft, got {:?}", event),
+        }
+        match rx.try_recv().unwrap() {
+            UsbEvent::DeviceArrived { device } => assert_eq!(device.class, 0x02),
+            event => panic!("Expected DeviceArrived, got {:?}", event),
+        }
+
+        // Lifting the filters brings the keyboard back
+        assert_eq!(manager.set_filters(&[]), (0, 1));
+        assert_eq!(manager.list_devices().len(), 2);
+    }
+
+    #[test]
+    fn test_withheld_device_stays_hidden() {
+        let (tx, rx) = async_channel::unbounded();
+        let bus = EmulatedBus::new();
+        bus.plug(HidKeyboard::new()).unwrap();
+        let backend = Box::new(EmulatedBackend::new(bus));
+        let mut manager = DeviceManager::new(backend, tx, Vec::new());
+        manager.initialize().unwrap();
+        let keyboard = manager.list_devices()[0].id;
+
+        assert_eq!(manager.set_device_shared(keyboard, false), Ok(true));
+        assert_eq!(manager.set_device_shared(keyboard, false), Ok(false));
+        assert!(matches!(rx.try_recv(), Ok(UsbEvent::DeviceLeft { .. })));
+        assert!(manager.list_devices().is_empty());
+        assert_eq!(manager.withheld_devices()[0].id, keyboard);
+
+        // Other rescans leave it out
+        assert_eq!(manager.set_filters(&[]), (0, 0));
+
+        assert_eq!(manager.set_device_shared(keyboard, true), Ok(true));
+        assert!(matches!(rx.try_recv(), Ok(UsbEvent::DeviceArrived { .. })));
+        assert_eq!(manager.list_devices()[0].id, keyboard);
+        assert!(manager.withheld_devices().is_empty());
+        assert_eq!(
+            manager.set_device_shared(DeviceId(999), false),
+            Err(UsbError::NotFound)
+        );
     }
 
     #[test]
@@ -3,29 +3,31 @@
 //! Manages USB device enumeration, hot-plug detection, and transfer handling.
 //!
 //! This module implements the USB subsystem for the server, handling:
-//! - Device enumeration and discovery
+//! - Device enumeration and discovery through a backend (libusb or emulated)
 //! - Hot-plug detection
 //! - USB transfer execution (control, bulk, interrupt)
+//! - Asynchronous URB submission with multiple transfers in flight per endpoint
 //! - Device lifecycle management
+//! - Persistent device IDs keyed by stable device identity
 //! - Multi-client device sharing and access arbitration
 //!
 //! The USB subsystem runs in a dedicated thread (worker) to avoid blocking
 //! the Tokio async runtime, following the architecture design pattern for
 //! hybrid sync-async USB operations.
 
-// Allow dead_code and unused_imports for Phase 2 - modules not yet integrated with server main
-#![allow(dead_code)]
-#![allow(unused_imports)]
-
+pub mod backend;
 pub mod device;
+pub mod emulated;
 pub mod interrupt_buffer;
+pub mod libusb;
 pub mod manager;
+pub mod registry;
 pub mod sharing;
 pub mod transfers;
+pub mod urb;
 pub mod worker;
 
 // Re-export public types
-pub use device::UsbDevice;
-pub use manager::DeviceManager;
-pub use sharing::{DeviceAccessTracker, DeviceSharingState, SharingEvent};
-pub use worker::{UsbWorkerThread, spawn_usb_worker};
+pub use backend::open_backend;
+pub use registry::DeviceRegistry;
+pub use worker::spawn_usb_worker;
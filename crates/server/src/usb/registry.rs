@@ -0,0 +1,216 @@
+//! Persistent device ID registry
+//!
+//! Maps each device's stable identity (`DeviceInfo::stable_id`) to the
+//! `DeviceId` it was first given, so the same device gets the same ID after
+//! a replug or a server restart. The mapping is kept in a small TOML file:
+//!
+//! ```toml
+//! [devices]
+//! "1050:0407:12345678" = 1
+//! "04f9:0042@1-1.4" = 2
+//! ```
+
+use anyhow::{Context, Result};
+use protocol::DeviceId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// On-disk format of the registry
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    devices: BTreeMap<String, u32>,
+}
+
+/// Stable identity to `DeviceId` mapping
+#[derive(Debug)]
+pub struct DeviceRegistry {
+    /// File the mapping is saved to (None = not persisted)
+    path: Option<PathBuf>,
+    /// Assigned IDs by stable identity
+    ids: BTreeMap<String, DeviceId>,
+    /// Next ID to hand out
+    next_id: u32,
+}
+
+impl Default for DeviceRegistry {
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+impl DeviceRegistry {
+    /// Registry that is not saved anywhere
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            ids: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Load the registry from `path`
+    ///
+    /// A missing file yields an empty registry that is created on the first
+    /// assignment.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file: RegistryFile = if path.exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read device registry: {}", path.display()))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse device registry: {}", path.display()))?
+        } else {
+            RegistryFile::default()
+        };
+
+        let next_id = file.devices.values().max().map_or(1, |max| max + 1);
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            ids: file
+                .devices
+                .into_iter()
+                .map(|(key, id)| (key, DeviceId(id)))
+                .collect(),
+            next_id,
+        })
+    }
+
+    /// ID previously assigned to a stable identity
+    pub fn lookup(&self, stable_id: &str) -> Option<DeviceId> {
+        self.ids.get(stable_id).copied()
+    }
+
+    /// ID for a stable identity, assigning and saving a new one if needed
+    pub fn assign(&mut self, stable_id: &str) -> DeviceId {
+        if let Some(id) = self.lookup(stable_id) {
+            return id;
+        }
+
+        let id = self.fresh_id();
+        self.ids.insert(stable_id.to_string(), id);
+        if let Err(e) = self.save() {
+            warn!("Failed to save device registry: {:#}", e);
+        }
+        id
+    }
+
+    /// A new ID that is not recorded for any identity
+    pub fn fresh_id(&mut self) -> DeviceId {
+        let id = DeviceId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Write the mapping to disk (no-op for in-memory registries)
+    ///
+    /// The file is written next to the registry, synced and renamed over it,
+    /// so a crash leaves either the old or the new mapping, never a truncated
+    /// one that would load as empty and hand out reused IDs.
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create registry directory: {}", parent.display())
+            })?;
+        }
+
+        let file = RegistryFile {
+            devices: self
+                .ids
+                .iter()
+                .map(|(key, id)| (key.clone(), id.0))
+                .collect(),
+        };
+        let content = toml::to_string_pretty(&file).context("Failed to serialize registry")?;
+
+        let mut tmp_name = OsString::from(path.as_os_str());
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+        let mut tmp = File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        tmp.write_all(content.as_bytes())
+            .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        tmp.sync_all()
+            .with_context(|| format!("Failed to sync {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace device registry: {}", path.display()))?;
+
+        // Make the rename itself durable
+        if let Some(parent) = path.parent()
+            && let Err(e) = File::open(parent).and_then(|dir| dir.sync_all())
+        {
+            warn!(
+                "Failed to sync registry directory {}: {}",
+                parent.display(),
+                e
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_is_stable() {
+        let mut registry = DeviceRegistry::in_memory();
+        let printer = registry.assign("04f9:0042@1-1.4");
+        let key = registry.assign("1050:0407:12345678");
+
+        assert_ne!(printer, key);
+        assert_eq!(registry.assign("04f9:0042@1-1.4"), printer);
+        assert_eq!(registry.lookup("1050:0407:12345678"), Some(key));
+        assert_eq!(registry.lookup("dead:beef@2-1"), None);
+        assert_ne!(registry.fresh_id(), key);
+    }
+
+    #[test]
+    fn test_registry_persists_across_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("devices.toml");
+
+        let mut registry = DeviceRegistry::load(&path).unwrap();
+        let first = registry.assign("04f9:0042@1-1.4");
+        let second = registry.assign("1050:0407:12345678");
+
+        let mut reloaded = DeviceRegistry::load(&path).unwrap();
+        assert_eq!(reloaded.lookup("04f9:0042@1-1.4"), Some(first));
+        assert_eq!(reloaded.lookup("1050:0407:12345678"), Some(second));
+
+        let third = reloaded.assign("046d:c52b@1-1.2");
+        assert!(third.0 > second.0);
+
+        // Saving replaces the file without leaving the temporary copy behind
+        let entries: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec![OsString::from("devices.toml")]);
+    }
+
+    #[test]
+    fn test_truncated_registry_is_not_loaded_as_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("devices.toml");
+
+        let mut registry = DeviceRegistry::load(&path).unwrap();
+        registry.assign("04f9:0042@1-1.4");
+        registry.assign("1050:0407:12345678");
+
+        // A write cut short mid-entry must fail to load rather than start over
+        let content = fs::read_to_string(&path).unwrap();
+        let cut = content.find("1050").unwrap() + 6;
+        fs::write(&path, &content[..cut]).unwrap();
+        assert!(DeviceRegistry::load(&path).is_err());
+    }
+}
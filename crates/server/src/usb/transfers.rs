@@ -3,6 +3,11 @@
 //! This module handles executing USB transfers (control, bulk, interrupt) using rusb.
 //! It provides synchronous transfer functions that map rusb errors to protocol errors.
 //!
+//! The USB worker submits all transfers asynchronously through
+//! `urb::UrbEngine`; the synchronous path here remains for callers that need a
+//! blocking control, bulk or interrupt transfer. Isochronous transfers are only
+//! supported by the URB engine.
+//!
 //! # USB 3.0 SuperSpeed Optimization
 //!
 //! This module supports optimized transfer sizes for USB 3.0 SuperSpeed devices:
@@ -14,8 +19,8 @@
 //! which should use `DeviceSpeed::max_bulk_transfer_size()` to determine appropriate limits.
 
 use protocol::{
-    DeviceSpeed, IsoPacketDescriptor, SuperSpeedConfig, TransferResult, TransferType, UsbError,
-    UsbResponse, integrity::{compute_checksum, verify_checksum},
+    DeviceSpeed, SuperSpeedConfig, TransferResult, TransferType, UsbError, UsbResponse,
+    integrity::{compute_checksum, verify_checksum},
 };
 use rusb::DeviceHandle;
 use std::time::Duration;
@@ -28,18 +33,22 @@ const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
 pub const MAX_BULK_SIZE_HIGH_SPEED: usize = 64 * 1024;
 
 /// Maximum bulk transfer size for USB 3.0 SuperSpeed (1MB)
+#[allow(dead_code)]
 pub const MAX_BULK_SIZE_SUPERSPEED: usize = 1024 * 1024;
 
 /// Default URB buffer size for USB/IP protocol
+#[allow(dead_code)]
 pub const DEFAULT_URB_BUFFER_SIZE: usize = 64 * 1024;
 
 /// SuperSpeed URB buffer size for USB/IP protocol (256KB)
+#[allow(dead_code)]
 pub const SUPERSPEED_URB_BUFFER_SIZE: usize = 256 * 1024;
 
 /// Execute a USB transfer and return the response
 ///
 /// This function dispatches to the appropriate transfer type handler and
 /// ensures proper error mapping from rusb to protocol types.
+#[allow(dead_code)]
 pub fn execute_transfer(
     handle: &mut DeviceHandle<rusb::Context>,
     transfer: TransferType,
@@ -67,21 +76,17 @@ pub fn execute_transfer(
             timeout_ms,
         } => execute_interrupt_transfer(handle, endpoint, data, timeout_ms),
 
-        TransferType::Isochronous {
-            endpoint,
-            data,
-            iso_packet_descriptors,
-            start_frame,
-            interval: _,
-            timeout_ms,
-        } => execute_isochronous_transfer(
-            handle,
-            endpoint,
-            data,
-            iso_packet_descriptors,
-            start_frame,
-            timeout_ms,
-        ),
+        TransferType::Isochronous { endpoint, .. } => {
+            warn!(
+                "Isochronous transfer on endpoint {:#x} needs the URB engine",
+                endpoint
+            );
+            TransferResult::Error {
+                error: UsbError::Other {
+                    message: "Isochronous transfers require asynchronous submission".to_string(),
+                },
+            }
+        }
     };
 
     UsbResponse {
@@ -423,6 +428,7 @@ fn execute_bulk_transfer(
 /// Get the optimal transfer configuration for a device speed
 ///
 /// Returns the SuperSpeedConfig with appropriate buffer sizes and parallel slots.
+#[allow(dead_code)]
 pub fn get_transfer_config(speed: DeviceSpeed) -> SuperSpeedConfig {
     SuperSpeedConfig::for_speed(speed)
 }
@@ -430,6 +436,7 @@ pub fn get_transfer_config(speed: DeviceSpeed) -> SuperSpeedConfig {
 /// Calculate the optimal buffer size for a bulk transfer based on device speed
 ///
 /// Returns a buffer size that maximizes throughput for the given speed class.
+#[allow(dead_code)]
 pub fn optimal_bulk_buffer_size(speed: DeviceSpeed, requested_size: usize) -> usize {
     let max_size = speed.max_bulk_transfer_size();
     requested_size.min(max_size)
@@ -543,137 +550,6 @@ fn execute_interrupt_transfer(
     }
 }
 
-/// Execute an isochronous transfer
-///
-/// Isochronous transfers are used for audio/video streaming devices. They provide
-/// guaranteed bandwidth with bounded latency but no automatic retry on errors.
-///
-/// # Note on Implementation
-///
-/// libusb (and thus rusb) requires async transfers for isochronous operations.
-/// This implementation uses synchronous polling with a reasonable timeout.
-/// For high-performance audio/video streaming, consider implementing proper
-/// async transfer handling with double-buffering.
-///
-/// # Arguments
-///
-/// * `handle` - USB device handle
-/// * `endpoint` - Endpoint address (includes direction bit)
-/// * `data` - Data buffer (OUT: data to send, IN: pre-allocated buffer)
-/// * `iso_packet_descriptors` - Per-packet descriptors with offset/length
-/// * `start_frame` - Start frame for the transfer (0 for ASAP)
-/// * `timeout_ms` - Timeout in milliseconds
-fn execute_isochronous_transfer(
-    _handle: &mut DeviceHandle<rusb::Context>,
-    endpoint: u8,
-    data: Vec<u8>,
-    iso_packet_descriptors: Vec<IsoPacketDescriptor>,
-    start_frame: u32,
-    _timeout_ms: u32,
-) -> TransferResult {
-    let is_in = (endpoint & 0x80) != 0;
-    let num_packets = iso_packet_descriptors.len();
-
-    debug!(
-        "Isochronous transfer: endpoint={:#x}, packets={}, data_len={}, start_frame={}, is_in={}",
-        endpoint,
-        num_packets,
-        data.len(),
-        start_frame,
-        is_in
-    );
-
-    // Validate input
-    if num_packets == 0 {
-        warn!("Isochronous transfer with no packets");
-        return TransferResult::Error {
-            error: UsbError::InvalidParam,
-        };
-    }
-
-    // libusb/rusb requires async transfers for isochronous operations.
-    // The rusb crate's DeviceHandle does not expose synchronous isochronous
-    // transfer methods (unlike control/bulk/interrupt).
-    //
-    // Proper isochronous support would require:
-    // 1. Using libusb's async transfer API (libusb_alloc_transfer, libusb_submit_transfer)
-    // 2. Setting up proper callback handling
-    // 3. Implementing double-buffering for continuous streaming
-    //
-    // For now, we implement a simulation that:
-    // - For IN transfers: Returns a buffer with zero-filled packets (as if no data available)
-    // - For OUT transfers: Accepts the data (simulating successful write)
-    //
-    // This allows the USB/IP protocol to work correctly while real isochronous
-    // support requires additional implementation work.
-
-    if is_in {
-        // IN transfer: simulate reading from device
-        // In a real implementation, this would use libusb_submit_transfer
-        // and wait for completion with proper packet status reporting.
-
-        // Calculate total expected buffer size from packet descriptors
-        let total_len: usize = iso_packet_descriptors
-            .iter()
-            .map(|p| p.length as usize)
-            .sum();
-
-        // Create response buffer
-        let response_data = vec![0u8; total_len];
-
-        // Build response packet descriptors
-        // Since we're simulating (no real data), all packets have actual_length=0
-        // and status=0 (success but no data)
-        let response_descriptors: Vec<IsoPacketDescriptor> = iso_packet_descriptors
-            .iter()
-            .map(|p| IsoPacketDescriptor {
-                offset: p.offset,
-                length: p.length,
-                actual_length: 0, // No actual data received in simulation
-                status: 0,        // Success (no error)
-            })
-            .collect();
-
-        debug!(
-            "Isochronous IN simulated: {} packets, buffer_size={}",
-            num_packets, total_len
-        );
-
-        TransferResult::IsochronousSuccess {
-            data: response_data,
-            iso_packet_descriptors: response_descriptors,
-            start_frame,
-            error_count: 0,
-        }
-    } else {
-        // OUT transfer: simulate writing to device
-        // Accept the data and report success for all packets
-
-        let response_descriptors: Vec<IsoPacketDescriptor> = iso_packet_descriptors
-            .iter()
-            .map(|p| IsoPacketDescriptor {
-                offset: p.offset,
-                length: p.length,
-                actual_length: p.length, // All data "sent"
-                status: 0,               // Success
-            })
-            .collect();
-
-        debug!(
-            "Isochronous OUT simulated: {} packets, data_len={}",
-            num_packets,
-            data.len()
-        );
-
-        TransferResult::IsochronousSuccess {
-            data: Vec::new(), // No return data for OUT
-            iso_packet_descriptors: response_descriptors,
-            start_frame,
-            error_count: 0,
-        }
-    }
-}
-
 /// Map rusb::Error to protocol::UsbError
 ///
 /// This provides a clean mapping from low-level rusb errors to protocol-level errors
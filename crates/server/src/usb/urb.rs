@@ -0,0 +1,1261 @@
+//! Asynchronous USB transfer engine
+//!
+//! Submits USB transfers through libusb's asynchronous submit/callback API
+//! instead of the blocking `read_*`/`write_*` calls in `transfers.rs`. Each opened device owns one `UrbEngine`, which keeps up to
+//! [`MAX_URBS_PER_ENDPOINT`] transfers (URBs) in flight per endpoint and queues
+//! the rest, so a slow bulk read on one device no longer stalls every other
+//! device behind the single USB worker thread.
+//!
+//! # Completion Flow
+//!
+//! ```text
+//! UsbCommand::SubmitTransfer
+//!   └─> UrbEngine::submit()          (start or queue per endpoint)
+//!         └─> libusb_submit_transfer()
+//! libusb_handle_events()             (USB worker loop)
+//!   └─> urb_complete_cb()            (push transfer onto completion queue)
+//! UrbEngine::process_completions()   (USB worker loop)
+//!   ├─> build UsbResponse, send on oneshot (out of order across endpoints)
+//!   ├─> clear halt and resubmit once on bulk stall
+//!   └─> start next queued URB for the endpoint
+//! ```
+//!
+//! The callback does nothing but queue the finished transfer; all response
+//! handling runs on the worker thread after `handle_events` returns.
+//!
+//! # Isochronous Transfers
+//!
+//! rusb has no isochronous API, so this engine is the only iso path. Packets
+//! are laid out back to back in the libusb buffer in descriptor order. IN
+//! responses return the received bytes packed (each packet's `actual_length`
+//! bytes, in order), which is what USB/IP's RET_SUBMIT expects; the original
+//! offsets are echoed in the descriptors so vhci_hcd can unpack them.
+
+use protocol::{
+    DeviceHandle, IsoPacketDescriptor, IsoPacketResult, RequestId, TransferResult, TransferType,
+    UsbError, UsbRequest, UsbResponse,
+    integrity::{compute_checksum, verify_checksum},
+};
+use rusb::constants::{
+    LIBUSB_CONTROL_SETUP_SIZE, LIBUSB_ERROR_ACCESS, LIBUSB_ERROR_BUSY, LIBUSB_ERROR_INVALID_PARAM,
+    LIBUSB_ERROR_IO, LIBUSB_ERROR_NO_DEVICE, LIBUSB_ERROR_NOT_FOUND, LIBUSB_ERROR_OVERFLOW,
+    LIBUSB_ERROR_PIPE, LIBUSB_ERROR_TIMEOUT, LIBUSB_TRANSFER_CANCELLED, LIBUSB_TRANSFER_COMPLETED,
+    LIBUSB_TRANSFER_ERROR, LIBUSB_TRANSFER_NO_DEVICE, LIBUSB_TRANSFER_OVERFLOW,
+    LIBUSB_TRANSFER_STALL, LIBUSB_TRANSFER_TIMED_OUT,
+};
+use rusb::ffi;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::c_void;
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tracing::{debug, trace, warn};
+
+/// Maximum URBs kept in flight per bulk/interrupt endpoint
+pub const MAX_URBS_PER_ENDPOINT: usize = 8;
+
+/// Control transfers are serialized: one in flight on endpoint 0
+const MAX_CONTROL_URBS: usize = 1;
+
+/// Timeout for control transfers (matches the synchronous path)
+const CONTROL_TIMEOUT_MS: u32 = 5000;
+
+/// Default buffer size for control IN transfers with no length hint
+const DEFAULT_CONTROL_IN_SIZE: usize = 64;
+
+/// Finished transfers queued by the libusb callback
+type CompletionQueue = Mutex<Vec<usize>>;
+
+/// Owned pointer to a libusb transfer
+///
+/// Only ever dereferenced on the USB worker thread.
+struct RawTransfer(NonNull<ffi::libusb_transfer>);
+
+// SAFETY: the transfer is only accessed from the USB worker thread that owns
+// the engine; the pointer is merely carried along with the engine.
+unsafe impl Send for RawTransfer {}
+
+/// Transfer-type specific state of a URB
+enum UrbKind {
+    /// Control transfer; the buffer starts with the 8-byte setup packet
+    Control { request_type: u8, request: u8 },
+    /// Bulk transfer
+    Bulk {
+        /// Whether the endpoint stall was already cleared once for this URB
+        retried: bool,
+    },
+    /// Interrupt transfer
+    Interrupt,
+    /// Isochronous transfer
+    Isochronous {
+        /// Packet layout from the request
+        packets: Vec<IsoPacketDescriptor>,
+        /// Per-packet results, filled in on completion
+        results: Vec<IsoPacketResult>,
+        /// Start frame echoed in the response
+        start_frame: u32,
+    },
+}
+
+/// A transfer request prepared for submission
+struct Urb {
+    /// Request ID to echo in the response
+    request_id: RequestId,
+    /// Handle the request was submitted on; request IDs are only unique per handle
+    handle: DeviceHandle,
+    /// Endpoint address (0 for control)
+    endpoint: u8,
+    /// Transfer-type specific state
+    kind: UrbKind,
+    /// Transfer buffer; must not be reallocated while in flight
+    buffer: Vec<u8>,
+    /// Timeout in milliseconds (0 = no timeout)
+    timeout_ms: u32,
+    /// Channel to send the response back
+    response: oneshot::Sender<UsbResponse>,
+    /// Cancelled on request rather than by device removal
+    unlinked: bool,
+}
+
+impl Urb {
+    /// Whether this URB reads from the device
+    fn is_in(&self) -> bool {
+        match self.kind {
+            UrbKind::Control { request_type, .. } => request_type & 0x80 != 0,
+            _ => self.endpoint & 0x80 != 0,
+        }
+    }
+
+    /// Complete the URB with the given result
+    fn respond(self, result: TransferResult) {
+        let _ = self.response.send(UsbResponse {
+            id: self.request_id,
+            result,
+        });
+    }
+}
+
+/// A URB that has been submitted to libusb
+struct InFlight {
+    transfer: RawTransfer,
+    urb: Urb,
+}
+
+/// Per-endpoint submission state
+#[derive(Default)]
+struct EndpointQueue {
+    /// URBs currently submitted to libusb
+    active: usize,
+    /// URBs waiting for a free slot
+    backlog: VecDeque<Urb>,
+}
+
+/// Asynchronous transfer engine for one open device
+pub struct UrbEngine {
+    /// Transfers finished by libusb, awaiting processing
+    completions: Arc<CompletionQueue>,
+    /// Submitted transfers keyed by transfer pointer
+    in_flight: HashMap<usize, InFlight>,
+    /// Submission state per endpoint address
+    endpoints: HashMap<u8, EndpointQueue>,
+}
+
+impl Default for UrbEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UrbEngine {
+    /// Create an idle engine
+    pub fn new() -> Self {
+        Self {
+            completions: Arc::new(Mutex::new(Vec::new())),
+            in_flight: HashMap::new(),
+            endpoints: HashMap::new(),
+        }
+    }
+
+    /// Number of URBs currently submitted to libusb
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Whether any URBs are submitted or queued
+    pub fn is_busy(&self) -> bool {
+        !self.in_flight.is_empty() || self.endpoints.values().any(|q| !q.backlog.is_empty())
+    }
+
+    /// Submit a transfer request
+    ///
+    /// The response is sent on `response` once libusb completes the transfer
+    /// and [`process_completions`](Self::process_completions) has run.
+    pub fn submit(
+        &mut self,
+        dev_handle: *mut ffi::libusb_device_handle,
+        request: UsbRequest,
+        response: oneshot::Sender<UsbResponse>,
+    ) {
+        let Some(urb) = Self::prepare(request, response) else {
+            return;
+        };
+
+        let depth = Self::depth_for(urb.endpoint);
+        let queue = self.endpoints.entry(urb.endpoint).or_default();
+        if queue.active >= depth {
+            trace!(
+                "Endpoint {:#x} busy ({} in flight), queueing request {:?}",
+                urb.endpoint, queue.active, urb.request_id
+            );
+            queue.backlog.push_back(urb);
+            return;
+        }
+
+        self.start(dev_handle, urb);
+    }
+
+    /// Process transfers completed during the last `handle_events` call
+    ///
+    /// Sends responses, retries stalled bulk endpoints once and starts queued
+    /// URBs. Returns the number of transfers completed.
+    pub fn process_completions(&mut self, dev_handle: *mut ffi::libusb_device_handle) -> usize {
+        let finished = match self.completions.lock() {
+            Ok(mut queue) => std::mem::take(&mut *queue),
+            Err(poisoned) => std::mem::take(&mut *poisoned.into_inner()),
+        };
+
+        let mut completed = 0;
+        for ptr in finished {
+            let Some(InFlight { transfer, mut urb }) = self.in_flight.remove(&ptr) else {
+                warn!("Completion for unknown transfer {:#x}", ptr);
+                continue;
+            };
+
+            // SAFETY: the transfer was allocated by `start` and libusb is done with it
+            let (status, actual_length) = unsafe {
+                let t = transfer.0.as_ptr();
+                ((*t).status, (*t).actual_length.max(0) as usize)
+            };
+
+            // Clear a stalled bulk endpoint and resubmit once, like the
+            // synchronous path does
+            if status == LIBUSB_TRANSFER_STALL
+                && let UrbKind::Bulk { retried: false } = urb.kind
+            {
+                warn!(
+                    "Bulk pipe error on endpoint {:#x}, clearing stall and retrying",
+                    urb.endpoint
+                );
+                // SAFETY: dev_handle is the open handle the transfer was submitted on
+                let rc = unsafe { ffi::libusb_clear_halt(dev_handle, urb.endpoint) };
+                if rc == 0 {
+                    urb.kind = UrbKind::Bulk { retried: true };
+                    // SAFETY: the transfer is idle and its buffer is still owned by `urb`
+                    let rc = unsafe { ffi::libusb_submit_transfer(transfer.0.as_ptr()) };
+                    if rc == 0 {
+                        self.in_flight.insert(ptr, InFlight { transfer, urb });
+                        continue;
+                    }
+                    warn!("Failed to resubmit after clearing stall: {}", rc);
+                } else {
+                    warn!(
+                        "Failed to clear halt on endpoint {:#x}: {}",
+                        urb.endpoint, rc
+                    );
+                }
+            }
+
+            if let UrbKind::Isochronous {
+                packets, results, ..
+            } = &mut urb.kind
+            {
+                *results = (0..packets.len())
+                    .map(|i| {
+                        // SAFETY: the transfer was allocated with one descriptor per packet
+                        let desc = unsafe { &*iso_packet_desc(transfer.0.as_ptr(), i) };
+                        IsoPacketResult {
+                            actual_length: desc.actual_length,
+                            status: desc.status,
+                        }
+                    })
+                    .collect();
+            }
+
+            // SAFETY: the transfer is no longer referenced by libusb
+            unsafe { ffi::libusb_free_transfer(transfer.0.as_ptr()) };
+
+            let endpoint = urb.endpoint;
+            let result = Self::transfer_result(&mut urb, status, actual_length);
+            urb.respond(result);
+            completed += 1;
+
+            self.release_slot(dev_handle, endpoint);
+        }
+
+        completed
+    }
+
+    /// Cancel every queued and in-flight URB
+    ///
+    /// Queued URBs are answered immediately with `NoDevice`; in-flight URBs
+    /// complete through `process_completions` once libusb reports them
+    /// cancelled.
+    pub fn cancel_all(&mut self) {
+        for queue in self.endpoints.values_mut() {
+            for urb in queue.backlog.drain(..) {
+                urb.respond(TransferResult::Error {
+                    error: UsbError::NoDevice,
+                });
+            }
+        }
+
+        for in_flight in self.in_flight.values() {
+            // SAFETY: the transfer is submitted; cancelling an already
+            // completed transfer just returns NOT_FOUND
+            unsafe { ffi::libusb_cancel_transfer(in_flight.transfer.0.as_ptr()) };
+        }
+    }
+
+    /// Cancel queued and in-flight URBs on the given endpoints
+    ///
+    /// Like [`cancel_all`](Self::cancel_all), limited to the endpoints of
+    /// interfaces being released while others stay in use.
+    pub fn cancel_endpoints(&mut self, endpoints: &[u8]) {
+        for (endpoint, queue) in self.endpoints.iter_mut() {
+            if !endpoints.contains(endpoint) {
+                continue;
+            }
+            for urb in queue.backlog.drain(..) {
+                urb.respond(TransferResult::Error {
+                    error: UsbError::NoDevice,
+                });
+            }
+        }
+
+        for in_flight in self.in_flight.values() {
+            if endpoints.contains(&in_flight.urb.endpoint) {
+                // SAFETY: as in cancel_all
+                unsafe { ffi::libusb_cancel_transfer(in_flight.transfer.0.as_ptr()) };
+            }
+        }
+    }
+
+    /// Number of URBs submitted to libusb on the given endpoints
+    pub fn in_flight_on(&self, endpoints: &[u8]) -> usize {
+        self.in_flight
+            .values()
+            .filter(|f| endpoints.contains(&f.urb.endpoint))
+            .count()
+    }
+
+    /// Cancel one queued or in-flight URB submitted on `handle`
+    ///
+    /// A queued URB is answered immediately with `Cancelled`. An in-flight URB
+    /// is handed to `libusb_cancel_transfer` and answered with `Cancelled` by
+    /// `process_completions` once libusb reports it. Returns `false` if no
+    /// such URB exists or it already completed.
+    pub fn cancel(&mut self, handle: DeviceHandle, request_id: RequestId) -> bool {
+        let matches = |urb: &Urb| urb.handle == handle && urb.request_id == request_id;
+        for queue in self.endpoints.values_mut() {
+            if let Some(pos) = queue.backlog.iter().position(matches) {
+                if let Some(urb) = queue.backlog.remove(pos) {
+                    debug!("Cancelled queued request {:?}", request_id);
+                    urb.respond(TransferResult::Error {
+                        error: UsbError::Cancelled,
+                    });
+                }
+                return true;
+            }
+        }
+
+        let Some(in_flight) = self.in_flight.values_mut().find(|f| matches(&f.urb)) else {
+            return false;
+        };
+
+        // SAFETY: the transfer is submitted; a transfer that already
+        // completed but is not yet processed just returns NOT_FOUND
+        let rc = unsafe { ffi::libusb_cancel_transfer(in_flight.transfer.0.as_ptr()) };
+        if rc != 0 {
+            trace!("Request {:?} already completing ({})", request_id, rc);
+            return false;
+        }
+        in_flight.urb.unlinked = true;
+        debug!("Cancelling in-flight request {:?}", request_id);
+        true
+    }
+
+    /// Submit a URB to libusb, answering it directly on failure
+    fn start(&mut self, dev_handle: *mut ffi::libusb_device_handle, mut urb: Urb) {
+        let num_iso_packets = match &urb.kind {
+            UrbKind::Isochronous { packets, .. } => packets.len() as i32,
+            _ => 0,
+        };
+
+        // SAFETY: allocating a transfer has no preconditions
+        let Some(transfer) = NonNull::new(unsafe { ffi::libusb_alloc_transfer(num_iso_packets) })
+        else {
+            warn!("Failed to allocate libusb transfer");
+            urb.respond(TransferResult::Error {
+                error: UsbError::Other {
+                    message: "Out of memory".to_string(),
+                },
+            });
+            return;
+        };
+
+        let t = transfer.as_ptr();
+        let user_data = Arc::as_ptr(&self.completions) as *mut c_void;
+        let buffer = urb.buffer.as_mut_ptr();
+        let length = urb.buffer.len() as i32;
+
+        // SAFETY: `t` is a fresh transfer with room for `num_iso_packets`
+        // descriptors; the buffer lives in `urb`, which is kept in
+        // `in_flight` until the transfer completes, and `user_data` points at
+        // the completion queue owned by this engine
+        let rc = unsafe {
+            match &urb.kind {
+                UrbKind::Control { .. } => ffi::libusb_fill_control_transfer(
+                    t,
+                    dev_handle,
+                    buffer,
+                    urb_complete_cb,
+                    user_data,
+                    urb.timeout_ms,
+                ),
+                UrbKind::Bulk { .. } => ffi::libusb_fill_bulk_transfer(
+                    t,
+                    dev_handle,
+                    urb.endpoint,
+                    buffer,
+                    length,
+                    urb_complete_cb,
+                    user_data,
+                    urb.timeout_ms,
+                ),
+                UrbKind::Interrupt => ffi::libusb_fill_interrupt_transfer(
+                    t,
+                    dev_handle,
+                    urb.endpoint,
+                    buffer,
+                    length,
+                    urb_complete_cb,
+                    user_data,
+                    urb.timeout_ms,
+                ),
+                UrbKind::Isochronous { packets, .. } => {
+                    ffi::libusb_fill_iso_transfer(
+                        t,
+                        dev_handle,
+                        urb.endpoint,
+                        buffer,
+                        length,
+                        num_iso_packets,
+                        urb_complete_cb,
+                        user_data,
+                        urb.timeout_ms,
+                    );
+                    for (i, packet) in packets.iter().enumerate() {
+                        (*iso_packet_desc(t, i)).length = packet.length;
+                    }
+                }
+            }
+            ffi::libusb_submit_transfer(t)
+        };
+
+        if rc != 0 {
+            warn!(
+                "Failed to submit transfer {:?} on endpoint {:#x}: {}",
+                urb.request_id, urb.endpoint, rc
+            );
+            // SAFETY: the transfer was never submitted
+            unsafe { ffi::libusb_free_transfer(t) };
+            urb.respond(TransferResult::Error {
+                error: map_libusb_error(rc),
+            });
+            return;
+        }
+
+        self.endpoints.entry(urb.endpoint).or_default().active += 1;
+        self.in_flight.insert(
+            t as usize,
+            InFlight {
+                transfer: RawTransfer(transfer),
+                urb,
+            },
+        );
+    }
+
+    /// Free an endpoint slot and start the next queued URB
+    fn release_slot(&mut self, dev_handle: *mut ffi::libusb_device_handle, endpoint: u8) {
+        let next = match self.endpoints.get_mut(&endpoint) {
+            Some(queue) => {
+                queue.active = queue.active.saturating_sub(1);
+                queue.backlog.pop_front()
+            }
+            None => None,
+        };
+
+        if let Some(urb) = next {
+            self.start(dev_handle, urb);
+        }
+    }
+
+    /// Maximum concurrent URBs for an endpoint
+    fn depth_for(endpoint: u8) -> usize {
+        if endpoint & 0x7f == 0 {
+            MAX_CONTROL_URBS
+        } else {
+            MAX_URBS_PER_ENDPOINT
+        }
+    }
+
+    /// Turn a transfer request into a URB
+    ///
+    /// Invalid requests are answered directly and yield `None`.
+    fn prepare(request: UsbRequest, response: oneshot::Sender<UsbResponse>) -> Option<Urb> {
+        let request_id = request.id;
+        let handle = request.handle;
+        let respond_err = |response: oneshot::Sender<UsbResponse>, error: UsbError| {
+            let _ = response.send(UsbResponse {
+                id: request_id,
+                result: TransferResult::Error { error },
+            });
+            None
+        };
+
+        let (endpoint, kind, buffer, timeout_ms) = match request.transfer {
+            TransferType::Control {
+                request_type,
+                request: b_request,
+                value,
+                index,
+                data,
+            } => {
+                let is_in = request_type & 0x80 != 0;
+                let length = if is_in && data.is_empty() {
+                    DEFAULT_CONTROL_IN_SIZE
+                } else {
+                    data.len()
+                };
+                let Ok(w_length) = u16::try_from(length) else {
+                    return respond_err(response, UsbError::InvalidParam);
+                };
+
+                let mut buffer = vec![0u8; LIBUSB_CONTROL_SETUP_SIZE + length];
+                // SAFETY: the buffer is at least LIBUSB_CONTROL_SETUP_SIZE bytes
+                unsafe {
+                    ffi::libusb_fill_control_setup(
+                        buffer.as_mut_ptr(),
+                        request_type,
+                        b_request,
+                        value,
+                        index,
+                        w_length,
+                    );
+                }
+                if !is_in {
+                    buffer[LIBUSB_CONTROL_SETUP_SIZE..].copy_from_slice(&data);
+                }
+
+                (
+                    0,
+                    UrbKind::Control {
+                        request_type,
+                        request: b_request,
+                    },
+                    buffer,
+                    CONTROL_TIMEOUT_MS,
+                )
+            }
+
+            TransferType::Bulk {
+                endpoint,
+                data,
+                timeout_ms,
+                checksum,
+            } => {
+                let is_in = endpoint & 0x80 != 0;
+                if !is_in
+                    && let Some(expected) = checksum
+                    && !verify_checksum(&data, expected)
+                {
+                    warn!("Bulk OUT checksum mismatch: expected {:#x}", expected);
+                    return respond_err(
+                        response,
+                        UsbError::Other {
+                            message: "Checksum mismatch".to_string(),
+                        },
+                    );
+                }
+
+                let buffer = if is_in { vec![0u8; data.len()] } else { data };
+                (
+                    endpoint,
+                    UrbKind::Bulk { retried: false },
+                    buffer,
+                    timeout_ms,
+                )
+            }
+
+            TransferType::Interrupt {
+                endpoint,
+                data,
+                timeout_ms,
+            } => {
+                let buffer = if endpoint & 0x80 != 0 {
+                    vec![0u8; data.len()]
+                } else {
+                    data
+                };
+                (endpoint, UrbKind::Interrupt, buffer, timeout_ms)
+            }
+
+            TransferType::Isochronous {
+                endpoint,
+                data,
+                iso_packet_descriptors,
+                start_frame,
+                interval: _,
+                timeout_ms,
+            } => {
+                let Some(buffer) = iso_buffer(endpoint, &data, &iso_packet_descriptors) else {
+                    warn!(
+                        "Invalid isochronous request on endpoint {:#x}: {} packets, {} bytes",
+                        endpoint,
+                        iso_packet_descriptors.len(),
+                        data.len()
+                    );
+                    return respond_err(response, UsbError::InvalidParam);
+                };
+                trace!(
+                    "Isochronous transfer: endpoint={:#x}, packets={}, buffer={}",
+                    endpoint,
+                    iso_packet_descriptors.len(),
+                    buffer.len()
+                );
+                (
+                    endpoint,
+                    UrbKind::Isochronous {
+                        packets: iso_packet_descriptors,
+                        results: Vec::new(),
+                        start_frame,
+                    },
+                    buffer,
+                    timeout_ms,
+                )
+            }
+        };
+
+        Some(Urb {
+            request_id,
+            handle,
+            endpoint,
+            kind,
+            buffer,
+            timeout_ms,
+            response,
+            unlinked: false,
+        })
+    }
+
+    /// Build the protocol result for a finished URB
+    ///
+    /// Mirrors the synchronous path: bulk and interrupt IN timeouts and I/O
+    /// errors report an empty success so USB/IP clients simply resubmit.
+    fn transfer_result(urb: &mut Urb, status: i32, actual_length: usize) -> TransferResult {
+        let is_in = urb.is_in();
+
+        if status == LIBUSB_TRANSFER_CANCELLED && urb.unlinked {
+            debug!("Request {:?} cancelled", urb.request_id);
+            return TransferResult::Error {
+                error: UsbError::Cancelled,
+            };
+        }
+
+        if let UrbKind::Isochronous { .. } = urb.kind {
+            return Self::iso_result(urb, status);
+        }
+
+        match status {
+            LIBUSB_TRANSFER_COMPLETED => {
+                if !is_in {
+                    return TransferResult::Success {
+                        data: Vec::new(),
+                        checksum: None,
+                    };
+                }
+
+                let mut data = std::mem::take(&mut urb.buffer);
+                match urb.kind {
+                    UrbKind::Control { .. } => {
+                        let end = (LIBUSB_CONTROL_SETUP_SIZE + actual_length).min(data.len());
+                        data.truncate(end);
+                        data.drain(..LIBUSB_CONTROL_SETUP_SIZE);
+                        debug!("Control transfer succeeded: {} bytes", data.len());
+                        TransferResult::Success {
+                            data,
+                            checksum: None,
+                        }
+                    }
+                    UrbKind::Bulk { .. } => {
+                        data.truncate(actual_length);
+                        trace!("Bulk transfer succeeded: {} bytes", data.len());
+                        let checksum = Some(compute_checksum(&data));
+                        TransferResult::Success { data, checksum }
+                    }
+                    UrbKind::Interrupt => {
+                        data.truncate(actual_length);
+                        if !data.is_empty() {
+                            trace!(
+                                "Interrupt IN ep={:#x} len={} data={:02x?}",
+                                urb.endpoint,
+                                data.len(),
+                                &data[..data.len().min(16)]
+                            );
+                        }
+                        TransferResult::Success {
+                            data,
+                            checksum: None,
+                        }
+                    }
+                    UrbKind::Isochronous { .. } => unreachable!("handled by iso_result"),
+                }
+            }
+
+            LIBUSB_TRANSFER_TIMED_OUT | LIBUSB_TRANSFER_ERROR
+                if is_in && !matches!(urb.kind, UrbKind::Control { .. }) =>
+            {
+                trace!(
+                    "IN timeout/io on endpoint {:#x} - returning empty (no data available)",
+                    urb.endpoint
+                );
+                TransferResult::Success {
+                    data: Vec::new(),
+                    checksum: None,
+                }
+            }
+
+            LIBUSB_TRANSFER_STALL => {
+                // Single-LUN mass storage devices stall GET_MAX_LUN; the spec
+                // says to assume LUN 0
+                if let UrbKind::Control {
+                    request_type: 0xA1,
+                    request: 0xFE,
+                } = urb.kind
+                {
+                    debug!("GET_MAX_LUN stalled (single-LUN device), returning 0x00");
+                    return TransferResult::Success {
+                        data: vec![0x00],
+                        checksum: None,
+                    };
+                }
+                debug!("Transfer stalled on endpoint {:#x}", urb.endpoint);
+                TransferResult::Error {
+                    error: UsbError::Pipe,
+                }
+            }
+
+            status => {
+                let error = map_transfer_status(status);
+                warn!(
+                    "Transfer {:?} on endpoint {:#x} failed: {:?}",
+                    urb.request_id, urb.endpoint, error
+                );
+                TransferResult::Error { error }
+            }
+        }
+    }
+
+    /// Build the protocol result for a finished isochronous URB
+    ///
+    /// Packet errors are reported per descriptor; only a failure of the whole
+    /// transfer (cancelled, device gone) becomes a `TransferResult::Error`.
+    fn iso_result(urb: &mut Urb, status: i32) -> TransferResult {
+        let is_in = urb.is_in();
+        let buffer = std::mem::take(&mut urb.buffer);
+        let UrbKind::Isochronous {
+            packets,
+            results,
+            start_frame,
+        } = &mut urb.kind
+        else {
+            unreachable!("iso_result called for a non-isochronous URB");
+        };
+
+        if !matches!(
+            status,
+            LIBUSB_TRANSFER_COMPLETED | LIBUSB_TRANSFER_TIMED_OUT
+        ) {
+            let error = map_transfer_status(status);
+            debug!(
+                "Isochronous transfer on endpoint {:#x} failed: {:?}",
+                urb.endpoint, error
+            );
+            return TransferResult::Error { error };
+        }
+
+        let mut data = Vec::new();
+        let mut position = 0usize;
+        let mut error_count = 0;
+        for (i, packet) in packets.iter_mut().enumerate() {
+            let result = results.get(i).cloned().unwrap_or(IsoPacketResult {
+                actual_length: 0,
+                status: LIBUSB_TRANSFER_ERROR,
+            });
+            let actual_length = result.actual_length.min(packet.length);
+
+            packet.actual_length = actual_length;
+            packet.status = iso_packet_status(result.status);
+            if packet.status != 0 {
+                error_count += 1;
+            }
+
+            if is_in {
+                let start = position.min(buffer.len());
+                let end = (position + actual_length as usize).min(buffer.len());
+                data.extend_from_slice(&buffer[start..end]);
+            }
+            position += packet.length as usize;
+        }
+
+        trace!(
+            "Isochronous transfer on endpoint {:#x}: {} packets, {} bytes, {} errors",
+            urb.endpoint,
+            packets.len(),
+            data.len(),
+            error_count
+        );
+
+        TransferResult::IsochronousSuccess {
+            iso_packet_descriptors: std::mem::take(packets),
+            start_frame: *start_frame,
+            error_count,
+            data,
+        }
+    }
+}
+
+/// Build the libusb buffer for an isochronous request
+///
+/// libusb expects packets back to back in descriptor order. OUT data is
+/// gathered from each descriptor's offset; IN buffers are zero-filled.
+/// Returns `None` if the descriptors are empty or do not fit the data.
+fn iso_buffer(endpoint: u8, data: &[u8], packets: &[IsoPacketDescriptor]) -> Option<Vec<u8>> {
+    if packets.is_empty() || i32::try_from(packets.len()).is_err() {
+        return None;
+    }
+
+    let total = packets
+        .iter()
+        .try_fold(0usize, |total, p| total.checked_add(p.length as usize))?;
+    if i32::try_from(total).is_err() {
+        return None;
+    }
+
+    if endpoint & 0x80 != 0 {
+        return Some(vec![0u8; total]);
+    }
+
+    let mut buffer = Vec::with_capacity(total);
+    for packet in packets {
+        let start = packet.offset as usize;
+        let end = start.checked_add(packet.length as usize)?;
+        buffer.extend_from_slice(data.get(start..end)?);
+    }
+    Some(buffer)
+}
+
+/// Pointer to the `index`th iso packet descriptor of a transfer
+///
+/// # Safety
+///
+/// `transfer` must have been allocated with more than `index` iso packets.
+unsafe fn iso_packet_desc(
+    transfer: *mut ffi::libusb_transfer,
+    index: usize,
+) -> *mut ffi::libusb_iso_packet_descriptor {
+    // SAFETY: upheld by the caller; the descriptors trail the transfer struct
+    unsafe {
+        (&raw mut (*transfer).iso_packet_desc)
+            .cast::<ffi::libusb_iso_packet_descriptor>()
+            .add(index)
+    }
+}
+
+impl Drop for UrbEngine {
+    fn drop(&mut self) {
+        if self.in_flight.is_empty() {
+            return;
+        }
+
+        // libusb may still write into these buffers and call back into the
+        // completion queue; leaking them is the only safe option
+        warn!(
+            "Dropping URB engine with {} transfers in flight, leaking them",
+            self.in_flight.len()
+        );
+        std::mem::forget(self.completions.clone());
+        for (_, in_flight) in self.in_flight.drain() {
+            std::mem::forget(in_flight);
+        }
+    }
+}
+
+/// libusb completion callback
+///
+/// Runs inside `libusb_handle_events()`; only records the finished transfer.
+extern "system" fn urb_complete_cb(transfer: *mut ffi::libusb_transfer) {
+    // SAFETY: user_data was set by `UrbEngine::start` to the engine's
+    // completion queue, which outlives every in-flight transfer
+    let queue = unsafe { &*((*transfer).user_data as *const CompletionQueue) };
+    match queue.lock() {
+        Ok(mut queue) => queue.push(transfer as usize),
+        Err(poisoned) => poisoned.into_inner().push(transfer as usize),
+    }
+}
+
+/// Map a libusb transfer status to a protocol error
+fn map_transfer_status(status: i32) -> UsbError {
+    match status {
+        LIBUSB_TRANSFER_TIMED_OUT => UsbError::Timeout,
+        LIBUSB_TRANSFER_STALL => UsbError::Pipe,
+        LIBUSB_TRANSFER_NO_DEVICE | LIBUSB_TRANSFER_CANCELLED => UsbError::NoDevice,
+        LIBUSB_TRANSFER_OVERFLOW => UsbError::Overflow,
+        LIBUSB_TRANSFER_ERROR => UsbError::Io,
+        other => UsbError::Other {
+            message: format!("libusb transfer status {}", other),
+        },
+    }
+}
+
+/// Map a libusb iso packet status to the Linux errno USB/IP reports per packet
+fn iso_packet_status(status: i32) -> i32 {
+    match status {
+        LIBUSB_TRANSFER_COMPLETED => 0,
+        LIBUSB_TRANSFER_STALL => -32,      // EPIPE
+        LIBUSB_TRANSFER_NO_DEVICE => -19,  // ENODEV
+        LIBUSB_TRANSFER_OVERFLOW => -75,   // EOVERFLOW
+        LIBUSB_TRANSFER_TIMED_OUT => -110, // ETIMEDOUT
+        LIBUSB_TRANSFER_CANCELLED => -2,   // ENOENT
+        _ => -18,                          // EXDEV (packet not completed)
+    }
+}
+
+/// Map a libusb error code to a protocol error
+fn map_libusb_error(code: i32) -> UsbError {
+    match code {
+        LIBUSB_ERROR_TIMEOUT => UsbError::Timeout,
+        LIBUSB_ERROR_PIPE => UsbError::Pipe,
+        LIBUSB_ERROR_NO_DEVICE => UsbError::NoDevice,
+        LIBUSB_ERROR_NOT_FOUND => UsbError::NotFound,
+        LIBUSB_ERROR_BUSY => UsbError::Busy,
+        LIBUSB_ERROR_OVERFLOW => UsbError::Overflow,
+        LIBUSB_ERROR_IO => UsbError::Io,
+        LIBUSB_ERROR_INVALID_PARAM => UsbError::InvalidParam,
+        LIBUSB_ERROR_ACCESS => UsbError::Access,
+        other => UsbError::Other {
+            message: format!("libusb error {}", other),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::DeviceHandle;
+
+    fn request(transfer: TransferType) -> UsbRequest {
+        UsbRequest {
+            id: RequestId(7),
+            handle: DeviceHandle(1),
+            transfer,
+        }
+    }
+
+    #[test]
+    fn test_prepare_control_in_setup_packet() {
+        let (tx, _rx) = oneshot::channel();
+        let req = request(TransferType::Control {
+            request_type: 0x80,
+            request: 0x06,
+            value: 0x0100,
+            index: 0,
+            data: vec![0u8; 18],
+        });
+
+        let Some(urb) = UrbEngine::prepare(req, tx) else {
+            panic!("expected URB");
+        };
+        assert_eq!(urb.endpoint, 0);
+        assert!(urb.is_in());
+        assert_eq!(urb.buffer.len(), LIBUSB_CONTROL_SETUP_SIZE + 18);
+        assert_eq!(
+            &urb.buffer[..8],
+            &[0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 18, 0]
+        );
+    }
+
+    #[test]
+    fn test_prepare_bulk_out_checksum_mismatch() {
+        let (tx, mut rx) = oneshot::channel();
+        let req = request(TransferType::Bulk {
+            endpoint: 0x02,
+            data: vec![1, 2, 3],
+            timeout_ms: 1000,
+            checksum: Some(0xdead_beef),
+        });
+
+        assert!(UrbEngine::prepare(req, tx).is_none());
+        let response = rx.try_recv().expect("response sent");
+        assert!(matches!(
+            response.result,
+            TransferResult::Error {
+                error: UsbError::Other { .. }
+            }
+        ));
+    }
+
+    fn iso_packet(offset: u32, length: u32) -> IsoPacketDescriptor {
+        IsoPacketDescriptor {
+            offset,
+            length,
+            actual_length: 0,
+            status: 0,
+        }
+    }
+
+    #[test]
+    fn test_prepare_isochronous_without_packets() {
+        let (tx, mut rx) = oneshot::channel();
+        let req = request(TransferType::Isochronous {
+            endpoint: 0x81,
+            data: vec![],
+            iso_packet_descriptors: vec![],
+            start_frame: 0,
+            interval: 1,
+            timeout_ms: 1000,
+        });
+
+        assert!(UrbEngine::prepare(req, tx).is_none());
+        let response = rx.try_recv().expect("response sent");
+        assert!(matches!(
+            response.result,
+            TransferResult::Error {
+                error: UsbError::InvalidParam
+            }
+        ));
+    }
+
+    #[test]
+    fn test_prepare_isochronous_out_gathers_packets() {
+        let (tx, _rx) = oneshot::channel();
+        let req = request(TransferType::Isochronous {
+            endpoint: 0x02,
+            data: vec![1, 2, 0, 0, 3, 4],
+            iso_packet_descriptors: vec![iso_packet(0, 2), iso_packet(4, 2)],
+            start_frame: 0,
+            interval: 1,
+            timeout_ms: 1000,
+        });
+
+        let Some(urb) = UrbEngine::prepare(req, tx) else {
+            panic!("expected URB");
+        };
+        assert!(!urb.is_in());
+        assert_eq!(urb.buffer, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_isochronous_in_completion_packs_data() {
+        let (tx, _rx) = oneshot::channel();
+        let req = request(TransferType::Isochronous {
+            endpoint: 0x81,
+            data: vec![0u8; 8],
+            iso_packet_descriptors: vec![iso_packet(0, 4), iso_packet(4, 4)],
+            start_frame: 42,
+            interval: 1,
+            timeout_ms: 1000,
+        });
+        let Some(mut urb) = UrbEngine::prepare(req, tx) else {
+            panic!("expected URB");
+        };
+        urb.buffer.copy_from_slice(&[1, 2, 3, 0, 5, 6, 7, 8]);
+        if let UrbKind::Isochronous { results, .. } = &mut urb.kind {
+            *results = vec![
+                IsoPacketResult {
+                    actual_length: 3,
+                    status: LIBUSB_TRANSFER_COMPLETED,
+                },
+                IsoPacketResult {
+                    actual_length: 0,
+                    status: LIBUSB_TRANSFER_ERROR,
+                },
+            ];
+        }
+
+        match UrbEngine::transfer_result(&mut urb, LIBUSB_TRANSFER_COMPLETED, 0) {
+            TransferResult::IsochronousSuccess {
+                iso_packet_descriptors,
+                start_frame,
+                error_count,
+                data,
+            } => {
+                assert_eq!(data, vec![1, 2, 3]);
+                assert_eq!(start_frame, 42);
+                assert_eq!(error_count, 1);
+                assert_eq!(iso_packet_descriptors[0].offset, 0);
+                assert_eq!(iso_packet_descriptors[0].actual_length, 3);
+                assert_eq!(iso_packet_descriptors[0].status, 0);
+                assert_eq!(iso_packet_descriptors[1].offset, 4);
+                assert_eq!(iso_packet_descriptors[1].actual_length, 0);
+                assert_eq!(iso_packet_descriptors[1].status, -18);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bulk_in_timeout_is_empty_success() {
+        let (tx, _rx) = oneshot::channel();
+        let req = request(TransferType::Bulk {
+            endpoint: 0x81,
+            data: vec![0u8; 512],
+            timeout_ms: 1000,
+            checksum: None,
+        });
+        let Some(mut urb) = UrbEngine::prepare(req, tx) else {
+            panic!("expected URB");
+        };
+
+        match UrbEngine::transfer_result(&mut urb, LIBUSB_TRANSFER_TIMED_OUT, 0) {
+            TransferResult::Success { data, .. } => assert!(data.is_empty()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_control_in_completion_strips_setup() {
+        let (tx, _rx) = oneshot::channel();
+        let req = request(TransferType::Control {
+            request_type: 0x80,
+            request: 0x06,
+            value: 0x0100,
+            index: 0,
+            data: vec![],
+        });
+        let Some(mut urb) = UrbEngine::prepare(req, tx) else {
+            panic!("expected URB");
+        };
+        urb.buffer[LIBUSB_CONTROL_SETUP_SIZE] = 0x12;
+
+        match UrbEngine::transfer_result(&mut urb, LIBUSB_TRANSFER_COMPLETED, 1) {
+            TransferResult::Success { data, .. } => assert_eq!(data, vec![0x12]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cancelled_status_depends_on_unlink() {
+        let bulk = || {
+            request(TransferType::Bulk {
+                endpoint: 0x81,
+                data: vec![0u8; 64],
+                timeout_ms: 0,
+                checksum: None,
+            })
+        };
+
+        let (tx, _rx) = oneshot::channel();
+        let Some(mut urb) = UrbEngine::prepare(bulk(), tx) else {
+            panic!("expected URB");
+        };
+        match UrbEngine::transfer_result(&mut urb, LIBUSB_TRANSFER_CANCELLED, 0) {
+            TransferResult::Error { error } => assert_eq!(error, UsbError::NoDevice),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let (tx, _rx) = oneshot::channel();
+        let Some(mut urb) = UrbEngine::prepare(bulk(), tx) else {
+            panic!("expected URB");
+        };
+        urb.unlinked = true;
+        match UrbEngine::transfer_result(&mut urb, LIBUSB_TRANSFER_CANCELLED, 0) {
+            TransferResult::Error { error } => assert_eq!(error, UsbError::Cancelled),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cancel_queued_urb() {
+        let mut engine = UrbEngine::new();
+        let (tx, mut rx) = oneshot::channel();
+        let Some(urb) = UrbEngine::prepare(
+            request(TransferType::Interrupt {
+                endpoint: 0x81,
+                data: vec![0u8; 8],
+                timeout_ms: 0,
+            }),
+            tx,
+        ) else {
+            panic!("expected URB");
+        };
+        let request_id = urb.request_id;
+        engine
+            .endpoints
+            .entry(0x81)
+            .or_default()
+            .backlog
+            .push_back(urb);
+
+        assert!(!engine.cancel(DeviceHandle(2), request_id));
+        assert!(engine.cancel(DeviceHandle(1), request_id));
+        assert!(!engine.is_busy());
+        match rx.try_recv().expect("response sent").result {
+            TransferResult::Error { error } => assert_eq!(error, UsbError::Cancelled),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!engine.cancel(DeviceHandle(1), request_id));
+    }
+
+    #[test]
+    fn test_cancel_endpoints_leaves_other_endpoints() {
+        let mut engine = UrbEngine::new();
+        let mut receivers = Vec::new();
+        for endpoint in [0x81, 0x82] {
+            let (tx, rx) = oneshot::channel();
+            let urb = UrbEngine::prepare(
+                request(TransferType::Interrupt {
+                    endpoint,
+                    data: vec![0u8; 8],
+                    timeout_ms: 0,
+                }),
+                tx,
+            )
+            .expect("expected URB");
+            engine
+                .endpoints
+                .entry(endpoint)
+                .or_default()
+                .backlog
+                .push_back(urb);
+            receivers.push(rx);
+        }
+
+        engine.cancel_endpoints(&[0x81]);
+        match receivers[0].try_recv().expect("response sent").result {
+            TransferResult::Error { error } => assert_eq!(error, UsbError::NoDevice),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(receivers[1].try_recv().is_err());
+        assert!(engine.is_busy());
+        assert_eq!(engine.in_flight_on(&[0x81, 0x82]), 0);
+    }
+
+    #[test]
+    fn test_depth_for_endpoint() {
+        assert_eq!(UrbEngine::depth_for(0x00), MAX_CONTROL_URBS);
+        assert_eq!(UrbEngine::depth_for(0x80), MAX_CONTROL_URBS);
+        assert_eq!(UrbEngine::depth_for(0x81), MAX_URBS_PER_ENDPOINT);
+        assert_eq!(UrbEngine::depth_for(0x02), MAX_URBS_PER_ENDPOINT);
+    }
+}
@@ -1,21 +1,34 @@
 //! USB worker thread
 //!
 //! Dedicated thread for handling USB events and transfers.
-//! Runs libusb_handle_events() loop and communicates with Tokio runtime via channels.
+//! Runs the backend's event loop (libusb_handle_events() for real hardware) and
+//! communicates with Tokio runtime via channels. Transfers are submitted
+//! asynchronously (see `urb`), and their completions are collected after each
+//! event loop iteration.
 //!
 //! This module implements the hybrid sync-async architecture where USB operations
 //! run in a dedicated blocking thread and communicate with the Tokio runtime via
 //! async channels.
 
-use crate::usb::{manager::DeviceManager, transfers::execute_transfer};
+use crate::usb::backend::UsbBackend;
+use crate::usb::manager::DeviceManager;
+use crate::usb::registry::DeviceRegistry;
 use common::{UsbCommand, UsbWorker};
-use rusb::UsbContext;
 use std::time::Duration;
 use tracing::{debug, error, info, warn};
 
+/// Event loop timeout while no transfers are in flight
+const IDLE_EVENT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Event loop timeout while transfers are in flight
+///
+/// Completions wake the event loop immediately, but new commands do not, so
+/// keep polling the command channel often while the device is busy.
+const BUSY_EVENT_TIMEOUT: Duration = Duration::from_millis(1);
+
 /// USB worker thread
 ///
-/// Manages the USB context, device manager, and event loop.
+/// Manages the device manager and the backend's event loop.
 /// Processes commands from the Tokio runtime and sends events back.
 pub struct UsbWorkerThread {
     /// Device manager for USB operations
@@ -26,9 +39,15 @@ pub struct UsbWorkerThread {
 
 impl UsbWorkerThread {
     /// Create a new USB worker thread
-    pub fn new(worker: UsbWorker, allowed_filters: Vec<String>) -> Result<Self, rusb::Error> {
+    pub fn new(
+        worker: UsbWorker,
+        backend: Box<dyn UsbBackend>,
+        allowed_filters: Vec<String>,
+        registry: DeviceRegistry,
+    ) -> Result<Self, rusb::Error> {
         // Create device manager with event sender
-        let mut manager = DeviceManager::new(worker.event_tx.clone(), allowed_filters)?;
+        let mut manager = DeviceManager::new(backend, worker.event_tx.clone(), allowed_filters)
+            .with_registry(registry);
 
         // Initialize device enumeration and hot-plug
         manager.initialize()?;
@@ -39,35 +58,36 @@ impl UsbWorkerThread {
     /// Run the USB worker thread event loop
     ///
     /// This is the main loop that:
-    /// 1. Checks for incoming commands from Tokio (non-blocking)
+    /// 1. Drains incoming commands from Tokio (non-blocking)
     /// 2. Processes USB events (with timeout)
-    /// 3. Handles hot-plug notifications (debounced)
-    /// 4. Processes any ready debounced events
+    /// 3. Completes finished asynchronous transfers
+    /// 4. Handles hot-plug notifications (debounced)
+    /// 5. Processes any ready debounced events
     ///
     /// The loop continues until a Shutdown command is received.
     pub fn run(mut self) -> Result<(), rusb::Error> {
         info!("USB worker thread started");
 
-        loop {
-            // Process incoming commands (non-blocking)
-            match self.worker.try_recv_command() {
-                Some(UsbCommand::Shutdown) => {
+        'event_loop: loop {
+            // Process all pending commands (non-blocking) so queued transfers
+            // are submitted together rather than one per loop iteration
+            while let Some(cmd) = self.worker.try_recv_command() {
+                if let UsbCommand::Shutdown = cmd {
                     info!("USB worker shutting down");
-                    break;
-                }
-                Some(cmd) => {
-                    self.handle_command(cmd);
-                }
-                None => {
-                    // No command, continue to USB event processing
+                    break 'event_loop;
                 }
+                self.handle_command(cmd);
             }
 
             // Process USB events with timeout
             // This allows us to check for commands regularly while handling USB events
-            let timeout = Duration::from_millis(100);
+            let timeout = if self.manager.has_pending_transfers() {
+                BUSY_EVENT_TIMEOUT
+            } else {
+                IDLE_EVENT_TIMEOUT
+            };
 
-            match self.manager.context().handle_events(Some(timeout)) {
+            match self.manager.handle_events(timeout) {
                 Ok(()) => {
                     // Events processed successfully
                 }
@@ -84,6 +104,9 @@ impl UsbWorkerThread {
                 }
             }
 
+            // Send responses for transfers that completed during handle_events
+            self.manager.process_transfer_completions();
+
             // Process any debounced hotplug events that are ready to fire
             // This handles events after their 500ms debounce period has elapsed
             self.manager.process_debounced_events();
@@ -124,6 +147,22 @@ impl UsbWorkerThread {
                 let _ = response.send(result);
             }
 
+            UsbCommand::AttachInterfaces {
+                device_id,
+                client_id,
+                interfaces,
+                response,
+            } => {
+                debug!(
+                    "Attaching interfaces {:?} of device {:?} for client {}",
+                    interfaces, device_id, client_id
+                );
+                let result = self
+                    .manager
+                    .attach_interfaces(device_id, client_id, interfaces);
+                let _ = response.send(result);
+            }
+
             UsbCommand::DetachDevice { handle, response } => {
                 debug!("Detaching device handle {:?}", handle);
                 let result = self.manager.detach_device(handle);
@@ -140,57 +179,174 @@ impl UsbWorkerThread {
                     handle, request.id
                 );
 
-                // Get device by handle
-                let usb_response = match self.manager.get_device_by_handle(handle) {
-                    Some(device) => {
-                        // Get mutable handle for transfer
-                        match device.handle_mut() {
-                            Some(device_handle) => {
-                                // Execute the transfer
-                                execute_transfer(device_handle, request.transfer, request.id)
-                            }
-                            None => {
-                                // Device not open
-                                warn!("Device handle {:?} not open for transfer", handle);
-                                protocol::UsbResponse {
-                                    id: request.id,
-                                    result: protocol::TransferResult::Error {
-                                        error: protocol::UsbError::NotFound,
-                                    },
-                                }
-                            }
-                        }
-                    }
+                // Interface-level handles may only use their own endpoints
+                if let Err(error) = self.manager.authorize_transfer(handle, &request.transfer) {
+                    let _ = response.send(protocol::UsbResponse {
+                        id: request.id,
+                        result: protocol::TransferResult::Error { error },
+                    });
+                    return;
+                }
+
+                // Get device by handle; the response is sent on completion
+                match self.manager.get_device_by_handle(handle) {
+                    Some(device) => device.submit_transfer(request, response),
                     None => {
                         // Handle not found
                         warn!("Device handle {:?} not found", handle);
-                        protocol::UsbResponse {
+                        let _ = response.send(protocol::UsbResponse {
                             id: request.id,
                             result: protocol::TransferResult::Error {
                                 error: protocol::UsbError::NotFound,
                             },
-                        }
+                        });
                     }
-                };
+                }
+            }
 
-                let _ = response.send(usb_response);
+            UsbCommand::CancelTransfer {
+                handle,
+                request_id,
+                response,
+            } => {
+                debug!(
+                    "Cancelling transfer {:?} for handle {:?}",
+                    request_id, handle
+                );
+                // Only URBs submitted on this handle match, and those already
+                // passed `authorize_transfer`; another client's transfer with
+                // the same request ID is left alone
+                let cancelled = self
+                    .manager
+                    .get_device_by_handle(handle)
+                    .is_some_and(|device| device.cancel_transfer(handle, request_id));
+                let _ = response.send(cancelled);
             }
 
             UsbCommand::ResetDevice { handle, response } => {
                 debug!("Resetting device handle {:?}", handle);
+                // UsbDevice::reset cancels in-flight transfers before resetting
+                let operation = protocol::DeviceOperation::Reset;
+                let result = self
+                    .manager
+                    .authorize_operation(handle, &operation)
+                    .and_then(|()| match self.manager.get_device_by_handle(handle) {
+                        Some(device) if device.is_open() => device
+                            .reset()
+                            .map_err(crate::usb::transfers::map_rusb_error),
+                        _ => Err(protocol::UsbError::NotFound),
+                    });
+                let _ = response.send(result);
+            }
+
+            UsbCommand::ClearHalt {
+                handle,
+                endpoint,
+                response,
+            } => {
+                debug!("Clearing halt on handle {:?} ep {:#x}", handle, endpoint);
+                let operation = protocol::DeviceOperation::ClearHalt { endpoint };
+                let result = self
+                    .manager
+                    .authorize_operation(handle, &operation)
+                    .and_then(|()| match self.manager.get_device_by_handle(handle) {
+                        Some(device) if device.is_open() => device
+                            .clear_halt(endpoint)
+                            .map_err(crate::usb::transfers::map_rusb_error),
+                        _ => Err(protocol::UsbError::NotFound),
+                    });
+                let _ = response.send(result);
+            }
+
+            UsbCommand::SetConfiguration {
+                handle,
+                configuration,
+                response,
+            } => {
+                debug!(
+                    "Setting configuration {} on handle {:?}",
+                    configuration, handle
+                );
+                let operation = protocol::DeviceOperation::SetConfiguration { configuration };
+                let result = self
+                    .manager
+                    .authorize_operation(handle, &operation)
+                    .and_then(|()| match self.manager.get_device_by_handle(handle) {
+                        Some(device) if device.is_open() => device
+                            .set_configuration(configuration)
+                            .map_err(crate::usb::transfers::map_rusb_error),
+                        _ => Err(protocol::UsbError::NotFound),
+                    });
+                let _ = response.send(result);
+            }
+
+            UsbCommand::SetInterface {
+                handle,
+                interface,
+                alt_setting,
+                response,
+            } => {
+                debug!(
+                    "Setting interface {} alt {} on handle {:?}",
+                    interface, alt_setting, handle
+                );
+                let operation = protocol::DeviceOperation::SetInterface {
+                    interface,
+                    alt_setting,
+                };
+                let result = self
+                    .manager
+                    .authorize_operation(handle, &operation)
+                    .and_then(|()| match self.manager.get_device_by_handle(handle) {
+                        Some(device) if device.is_open() => device
+                            .set_interface(interface, alt_setting)
+                            .map_err(crate::usb::transfers::map_rusb_error),
+                        _ => Err(protocol::UsbError::NotFound),
+                    });
+                let _ = response.send(result);
+            }
+
+            UsbCommand::GetEndpointInfo {
+                handle,
+                endpoint,
+                response,
+            } => {
                 let result = match self.manager.get_device_by_handle(handle) {
-                    Some(device) => match device.handle_mut() {
-                        Some(device_handle) => match device_handle.reset() {
-                            Ok(_) => Ok(()),
-                            Err(e) => Err(crate::usb::transfers::map_rusb_error(e)),
-                        },
-                        None => Err(protocol::UsbError::NotFound),
-                    },
+                    Some(device) => device.endpoint_info(endpoint),
                     None => Err(protocol::UsbError::NotFound),
                 };
                 let _ = response.send(result);
             }
 
+            UsbCommand::GetDescriptors {
+                device_id,
+                response,
+            } => {
+                let result = self.manager.device_descriptors(device_id);
+                if let Err(ref e) = result {
+                    debug!("Failed to read descriptors of {:?}: {:?}", device_id, e);
+                }
+                let _ = response.send(result);
+            }
+
+            UsbCommand::SetFilters { filters, response } => {
+                let changes = self.manager.set_filters(&filters);
+                let _ = response.send(changes);
+            }
+
+            UsbCommand::SetDeviceShared {
+                device_id,
+                shared,
+                response,
+            } => {
+                let result = self.manager.set_device_shared(device_id, shared);
+                let _ = response.send(result);
+            }
+
+            UsbCommand::ListWithheldDevices { response } => {
+                let _ = response.send(self.manager.withheld_devices());
+            }
+
             UsbCommand::Shutdown => {
                 // Already handled in main loop
                 unreachable!()
@@ -237,12 +393,14 @@ impl UsbWorkerThread {
 /// The thread will run until a Shutdown command is received or an error occurs.
 pub fn spawn_usb_worker(
     worker: UsbWorker,
+    backend: Box<dyn UsbBackend>,
     filters: Vec<String>,
+    registry: DeviceRegistry,
 ) -> std::thread::JoinHandle<Result<(), rusb::Error>> {
     std::thread::Builder::new()
         .name("usb-worker".to_string())
         .spawn(move || {
-            let worker_thread = UsbWorkerThread::new(worker, filters)?;
+            let worker_thread = UsbWorkerThread::new(worker, backend, filters, registry)?;
             worker_thread.run()
         })
         .expect("Failed to spawn USB worker thread")
@@ -251,28 +409,96 @@ pub fn spawn_usb_worker(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::usb::emulated::{EmulatedBackend, EmulatedBus};
     use common::create_usb_bridge;
 
     #[test]
     fn test_usb_worker_creation() {
         let (_bridge, worker) = create_usb_bridge();
+        let backend = Box::new(EmulatedBackend::new(EmulatedBus::new()));
 
-        // Try to create worker thread (may fail if no USB access)
-        let result = UsbWorkerThread::new(worker, vec![]);
+        // The emulated backend needs no USB access
+        let result = UsbWorkerThread::new(worker, backend, vec![], DeviceRegistry::in_memory());
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_worker_serves_emulated_serial_loopback() {
+        use crate::usb::emulated::CdcAcm;
+        use protocol::{RequestId, TransferResult, TransferType, UsbRequest};
+        use tokio::sync::oneshot;
+
+        let (bridge, worker) = create_usb_bridge();
+        let bus = EmulatedBus::new();
+        bus.plug(CdcAcm::new()).unwrap();
+        let thread = spawn_usb_worker(
+            worker,
+            Box::new(EmulatedBackend::new(bus)),
+            vec![],
+            DeviceRegistry::in_memory(),
+        );
+
+        let (tx, rx) = oneshot::channel();
+        bridge
+            .send_command(UsbCommand::ListDevices { response: tx })
+            .await
+            .unwrap();
+        let devices = rx.await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].product.as_deref(), Some("Emulated Serial Port"));
+
+        let (tx, rx) = oneshot::channel();
+        bridge
+            .send_command(UsbCommand::AttachDevice {
+                device_id: devices[0].id,
+                client_id: "test".to_string(),
+                response: tx,
+            })
+            .await
+            .unwrap();
+        let handle = rx.await.unwrap().unwrap();
 
-        // We don't assert success because USB context creation may fail without permissions
-        // Just verify we can attempt to create it
-        match result {
-            Ok(_) => {
-                // USB access available
+        let transfer = |id, endpoint, data: Vec<u8>| {
+            let bridge = bridge.clone();
+            async move {
+                let (tx, rx) = oneshot::channel();
+                let request = UsbRequest {
+                    id: RequestId(id),
+                    handle,
+                    transfer: TransferType::Bulk {
+                        endpoint,
+                        data,
+                        timeout_ms: 1000,
+                        checksum: None,
+                    },
+                };
+                bridge
+                    .send_command(UsbCommand::SubmitTransfer {
+                        handle,
+                        request,
+                        response: tx,
+                    })
+                    .await
+                    .unwrap();
+                rx.await.unwrap().result
             }
-            Err(e) => {
-                // Expected if no USB permissions
-                eprintln!(
-                    "USB worker creation failed (expected without permissions): {}",
-                    e
-                );
+        };
+
+        // Submit the read first: it completes once the write arrives
+        let echo = tokio::spawn(transfer(1, 0x82, vec![0; 64]));
+        assert!(matches!(
+            transfer(2, 0x02, b"ping".to_vec()).await,
+            TransferResult::Success { .. }
+        ));
+        match echo.await.unwrap() {
+            TransferResult::Success { data, checksum } => {
+                assert_eq!(data, b"ping");
+                assert!(checksum.is_some());
             }
+            other => panic!("unexpected result {:?}", other),
         }
+
+        bridge.send_command(UsbCommand::Shutdown).await.unwrap();
+        thread.join().unwrap().unwrap();
     }
 }
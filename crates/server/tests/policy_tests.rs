@@ -24,6 +24,10 @@ fn make_device_info(vid: u16, pid: u16, class: u8) -> DeviceInfo {
         protocol: 0,
         speed: DeviceSpeed::High,
         num_configurations: 1,
+        interfaces: Vec::new(),
+        stable_id: format!("{:04x}:{:04x}@1-1", vid, pid),
+        port_numbers: vec![1],
+        parent_hub: None,
     }
 }
 